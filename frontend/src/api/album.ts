@@ -1,4 +1,4 @@
-import type { Album } from "src/bindings/Album";
+import type { Album } from "@lib/bindings/bindings";
 import { fetchJson } from "../../utils/api/api";
 
 export async function getAlbums(): Promise<Album[]> {
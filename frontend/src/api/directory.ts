@@ -1,5 +1,9 @@
-import type { Directory } from "@lib/bindings/Directory";
-import type { NewDirectory } from "@lib/bindings/NewDirectory";
+import type {
+	Directory,
+	FolderEntry,
+	NewDirectory,
+	Page,
+} from "@lib/bindings/bindings";
 import { fetchJson, fetchText } from "@utils/api";
 
 /**
@@ -35,11 +39,28 @@ export async function deleteDirectory(name: string): Promise<void> {
 	});
 }
 
+/**
+ * Get a page of the entries in specific system directory, directories first.
+ * @param path The path of the directory.
+ * @param params Optional `limit`, `offset` and `filter` query parameters.
+ * @returns Promise resolving to a page of entries.
+ */
+export async function getServerDirectoryEntries(
+	path: string,
+	params: Record<string, string> = {},
+): Promise<Page<FolderEntry>> {
+	const query = new URLSearchParams(params).toString();
+	return await fetchJson<Page<FolderEntry>>(
+		`/api/directories/filesystem//${path}${query ? `?${query}` : ""}`,
+	);
+}
+
 /**
  * Get a list of folders in specific system directory.
- * @param name The name of the directory.
- * @returns Promise resolving to an array of files.
+ * @param path The path of the directory.
+ * @returns Promise resolving to an array of folder names.
  */
 export async function getServerDirectoryFolders(path: string): Promise<Array<string>> {
-	return await fetchJson<Array<string>>(`/api/directories/filesystem//${path}`);
+	const { items } = await getServerDirectoryEntries(path);
+	return items.filter((entry) => entry.isDir).map((entry) => entry.name);
 }
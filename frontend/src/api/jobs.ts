@@ -1,4 +1,9 @@
-import type { JobReportsResponse, JobStateResponse, RegistryJob } from "@lib/bindings/bindings";
+import type {
+	JobParameters,
+	JobReportsResponse,
+	JobStateResponse,
+	RegistryJob,
+} from "@lib/bindings/bindings";
 import { fetchJson, fetchText } from "@utils/api";
 
 export async function getJobs(): Promise<RegistryJob[]> {
@@ -17,9 +22,13 @@ export async function getJobQueueOrder(): Promise<string[]> {
 	return await fetchJson<string[]>("/api/jobs/order");
 }
 
-export async function queueJob(id: string): Promise<string> {
+export async function queueJob(
+	id: string,
+	parameters?: Partial<JobParameters>,
+): Promise<string> {
 	return await fetchJson<string>(`/api/jobs/${id}/queue`, {
 		method: "POST",
+		body: parameters && JSON.stringify(parameters),
 	});
 }
 
@@ -1,10 +1,14 @@
-import type { DatabaseSong } from "@lib/bindings/DatabaseSong";
-import type { SongFile } from "@lib/bindings/SongFile";
-import type { SongMetadata } from "@lib/bindings/SongMetadata";
+import type {
+	DatabaseSong,
+	Page,
+	SongFile,
+	SongMetadata,
+} from "@lib/bindings/bindings";
 import { fetchJson } from "src/utils/api";
 
 export async function getSongs(): Promise<Array<DatabaseSong>> {
-	return await fetchJson<Array<DatabaseSong>>("/api/songs/");
+	const page = await fetchJson<Page<DatabaseSong>>("/api/songs/");
+	return page.items;
 }
 
 export async function getSongFileInfo(id: string): Promise<SongFile> {
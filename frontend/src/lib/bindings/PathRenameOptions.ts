@@ -1,3 +0,0 @@
-// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-
-export type PathRenameOptions = { renameOriginalFiles: boolean, directoryId: string | null, };
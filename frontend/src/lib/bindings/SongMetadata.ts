@@ -1,3 +0,0 @@
-// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-
-export type SongMetadata = { unknown?: { [key in string]: string }, } & ({ [key in "album" | "albumArtist" | "albumSort" | "artist" | "artistSort" | "artists" | "barcode" | "bpm" | "catalogNumber" | "comment" | "composer" | "composerSortOrder" | "conductor" | "copyright" | "director" | "discNumber" | "discTotal" | "encodedBy" | "encoderSettings" | "engineer" | "genre" | "grouping" | "key" | "isrc" | "language" | "license" | "lyricist" | "lyrics" | "mood" | "movement" | "movementNumber" | "movementTotal" | "musicBrainzRecordingId" | "musicBrainzTrackId" | "musicBrainzReleaseId" | "musicBrainzReleaseGroupId" | "musicBrainzArtistId" | "musicBrainzReleaseArtistId" | "musicBrainzWorkId" | "originalAlbum" | "originalArtist" | "originalFileName" | "originalReleaseDate" | "performer" | "producer" | "label" | "releaseDate" | "recordingDate" | "title" | "titleSort" | "trackNumber" | "trackTotal" | "website" | "work" | "writer" | "year" | "setSubtitle" | "showName" | "trackSubtitle" | "originalLyricist" | "albumTitleSortOrder" | "showNameSortOrder" | "arranger" | "mixDj" | "mixEngineer" | "musicianCredits" | "publisher" | "internetRadioStationName" | "internetRadioStationOwner" | "remixer" | "popularimeter" | "parentalAdvisory" | "flagCompilation" | "flagPodcast" | "fileType" | "fileOwner" | "taggingTime" | "length" | "originalMediaType" | "encoderSoftware" | "encodingTime" | "replayGainAlbumGain" | "replayGainAlbumPeak" | "replayGainTrackGain" | "replayGainTrackPeak" | "audioFileUrl" | "audioSourceUrl" | "commercialInformationUrl" | "copyrightUrl" | "radioStationUrl" | "paymentUrl" | "publisherUrl" | "integerBpm" | "color" | "podcastDescription" | "podcastSeriesCategory" | "podcastUrl" | "podcastGlobalUniqueId" | "podcastKeywords" | "description" | "script" | "appleXid" | "appleId3v2ContentGroup" | "unknown"]?: string });
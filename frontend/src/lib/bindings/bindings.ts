@@ -1,21 +1,354 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type FileOperationManagerEvent = { timestamp: Date, } & ({ "kind": "failed", source: bigint, error: string, } | { "kind": "started", source: bigint, } | { "kind": "completed", source: bigint, } | { "kind": "cancelled", source: bigint, } | { "kind": "moved", source: bigint, from: string, to: string, } | { "kind": "renamed", source: bigint, from: string, to: string, } | { "kind": "copied", source: bigint, from: string, to: string, } | { "kind": "deleted", source: bigint, path: string, } | { "kind": "progress", source: bigint, copied_bytes: bigint, total_bytes: bigint, file_index: number, file_count: number, });
+export const SCHEMA_VERSION = 1;
 
-export type FileOperationState = { "kind": "move", paths: { [key in string]: string }, status: FileOperationStatus, } | { "kind": "copy", paths: { [key in string]: string }, status: FileOperationStatus, } | { "kind": "delete", paths: Array<string>, status: FileOperationStatus, };
+/**
+ * A collection of songs. Does not correlate to a table in the database.
+ */
+export type Album = { title: string, artist: string | null, 
+/**
+ * Directories the tracks are stored in, the album is split if there's more than one.
+ */
+directoryIds: Array<string>, 
+/**
+ * Total duration of the tracks with a known duration, left out while some tracks haven't had
+ * their audio properties read.
+ */
+durationMs: bigint | null, 
+/**
+ * Whether the duration is left out until the `backfill-audio-properties` job read the audio
+ * properties of every track.
+ */
+durationPending: boolean, 
+/**
+ * ReplayGain of the album in dB, if its tracks have been analyzed.
+ */
+gainDb: number | null, peak: number | null, tracks: Array<DatabaseSong>, };
+
+export type AppInfo = { 
+/**
+ * The version number of the application
+ */
+version: string, 
+/**
+ * The name of the application
+ */
+name: string, 
+/**
+ * Version of the API payloads, clients built against another one get a `409 Conflict` when
+ * sending it in the `X-Schema-Version` header
+ */
+schemaVersion: number, 
+/**
+ * The maintenance the server is in, requests that would change files are turned away with
+ * `503 Service Unavailable` while it is
+ */
+maintenance: MaintenanceMode | null, 
+/**
+ * System information that the application is running on
+ */
+system: SystemInfo, };
+
+export type DatabaseSong = { id: string, path: string, title: string | null, artist: string | null, album: string | null, albumArtist: string | null, genre: string | null, trackNumber: string | null, discNumber: string | null, year: string | null, mood: string | null, addedAt: Date, updatedAt: Date, fileCreatedAt: Date, directoryId: string, durationMs: number | null, bitrateKbps: number | null, sampleRate: number | null, channels: number | null, 
+/**
+ * Whether the audio properties were read from the file, the `backfill-audio-properties` job
+ * reads them for songs scanned without them.
+ */
+propertiesRead: boolean, 
+/**
+ * Cue sheet describing the track, tracks of a cue sheet share the same file.
+ */
+cuePath: string | null, 
+/**
+ * Where the track starts in the file, always `0` unless the track is part of a cue sheet.
+ */
+startMs: number, 
+/**
+ * Where the track ends in the file, the end of the file if not set.
+ */
+endMs: number | null, 
+/**
+ * ReplayGain of the track in dB, set by the `analyze-loudness` job.
+ */
+trackGainDb: number | null, 
+/**
+ * Largest sample of the track, `1.0` is full scale.
+ */
+trackPeak: number | null, 
+/**
+ * ReplayGain of the album the track was analyzed with in dB.
+ */
+albumGainDb: number | null, albumPeak: number | null, 
+/**
+ * When a scan first found the file missing, cleared once it's found again.
+ */
+missingSince: Date, 
+/**
+ * Times the song was reported as played through `/api/songs/{id}/played`.
+ */
+playCount: number, 
+/**
+ * Times the song was reported as stopped early, see [`songs::SKIP_THRESHOLD_MS`].
+ */
+skipCount: number, 
+/**
+ * When the song was last reported as played, skips leave it unchanged.
+ */
+lastPlayedAt: Date, 
+/**
+ * Leaves the song out of random picks, e.g. intros, skits and hidden tracks.
+ */
+excludeFromShuffle: boolean, 
+/**
+ * Where playback starts, from the start of the track, to skip leading silence.
+ */
+startOffsetMs: number | null, 
+/**
+ * Where playback ends, from the start of the track, to skip trailing silence.
+ */
+endOffsetMs: number | null, };
+
+export type Directory = { 
+/**
+ * The name of the directory.
+ */
+name: string, 
+/**
+ * The path of the directory.
+ */
+path: string, 
+/**
+ * The display name of the directory, only used in the UI.
+ */
+displayName: string | null, 
+/**
+ * The size of the directory takes up in bytes.
+ */
+pathSize: bigint | null, 
+/**
+ * The free space of the hard drive the directory is stored on.
+ */
+freeSpace: bigint | null, 
+/**
+ * The total space of the hard drive the directory is stored on.
+ */
+totalSpace: bigint | null, 
+/**
+ * Whether the hard drive the directory is stored on is below the free space thresholds of
+ * the `storage` settings.
+ */
+lowSpace: boolean, };
+
+export type FileOperationKind = "move" | "copy" | "delete" | "restore";
+
+export type FileOperationManagerEvent = { timestamp: Date, } & ({ "kind": "failed", source: bigint, error: string, } | { "kind": "started", source: bigint, operation: FileOperationKind, file_count: number, total_bytes: bigint, } | { "kind": "completed", source: bigint, } | { "kind": "cancelled", source: bigint, } | { "kind": "moved", source: bigint, from: string, to: string, } | { "kind": "renamed", source: bigint, from: string, to: string, } | { "kind": "copied", source: bigint, from: string, to: string, } | { "kind": "deleted", source: bigint, path: string, } | { "kind": "progress", source: bigint, copied_bytes: bigint, total_bytes: bigint, file_index: number, file_count: number, 
+/**
+ * Bytes done over the whole operation, see [`OperationEvent::Progress`].
+ */
+operation_copied_bytes: bigint, operation_total_bytes: bigint, eta_seconds: bigint | null, });
+
+export type FileOperationState = { "kind": "move", paths: { [key in string]: string }, status: FileOperationStatus, file_count: number, total_bytes: bigint, } | { "kind": "copy", paths: { [key in string]: string }, status: FileOperationStatus, file_count: number, total_bytes: bigint, } | { "kind": "delete", paths: Array<string>, 
+/**
+ * Whether the paths are deleted for good instead of moved to the trash.
+ */
+permanent: boolean, status: FileOperationStatus, file_count: number, total_bytes: bigint, } | { "kind": "restore", 
+/**
+ * The trash entry being restored.
+ */
+id: string, status: FileOperationStatus, file_count: number, total_bytes: bigint, };
 
 export type FileOperationStatus = "pending" | "inProgress";
 
-export type JobExecutionReport = { startedAt: Date, completedAt: Date, cancelledAt: Date, completedSuccessfully: boolean, };
+/**
+ * An entry of a folder on the server's file system.
+ */
+export type FolderEntry = { name: string, isDir: boolean, 
+/**
+ * The size of the file in bytes, not set for directories.
+ */
+size: bigint | null, modified: Date, 
+/**
+ * Whether the directory directly contains songs, subdirectories aren't looked at.
+ */
+containsAudio: boolean, };
 
-export type JobManagerEvent = { timestamp: Date, } & ({ "kind": "started", source: string, } | { "kind": "completed", source: string, } | { "kind": "cancelled", source: string, } | { "kind": "warning", source: string, message: string, } | { "kind": "failed", source: string, message: string, } | { "kind": "stepCompleted", source: string, step: number, value: string | null, } | { "kind": "progress", source: string, current: bigint, total: bigint, step: number, } | { "kind": "stateAdded", source: string, state: JobState, } | { "kind": "stateUpdated", source: string, state: JobState, } | { "kind": "stateRemoved", source: string, } | { "kind": "orderUpdated", queue: Array<string>, } | { "kind": "reportUpdated", jobId: string, report: JobExecutionReport, });
+export type JobExecutionReport = { startedAt: Date, completedAt: Date, cancelledAt: Date, completedSuccessfully: boolean, dryRun: boolean, directory: string | null, hasArtifact: boolean, };
+
+export type JobManagerEvent = { timestamp: Date, } & ({ "kind": "started", source: string, jobId: string, } | { "kind": "completed", source: string, jobId: string, } | { "kind": "cancelled", source: string, jobId: string, } | { "kind": "warning", source: string, message: string, } | { "kind": "failed", source: string, jobId: string, message: string, } | { "kind": "stepCompleted", source: string, step: number, value: string | null, } | { "kind": "progress", source: string, current: bigint, total: bigint, step: number, } | { "kind": "stateAdded", source: string, state: JobState, } | { "kind": "stateUpdated", source: string, state: JobState, } | { "kind": "stateRemoved", source: string, } | { "kind": "orderUpdated", queue: Array<string>, } | { "kind": "reportUpdated", jobId: string, report: JobExecutionReport, });
+
+export type JobParameters = { 
+/**
+ * Only plan the changes, without touching any files or the database.
+ *
+ * Dry runs return what they would have changed as the artifact of the report.
+ */
+dryRun: boolean, 
+/**
+ * Only run these steps of the job, every step runs when not set.
+ *
+ * Accepts a comma separated list in query strings, e.g. `?steps=1,3`.
+ */
+steps: Array<number> | null, 
+/**
+ * Only process the songs of this album.
+ */
+album: string | null, 
+/**
+ * Only process the songs of the directory with this name.
+ */
+directory: string | null, 
+/**
+ * Process the songs of the playlist with this id.
+ */
+playlist: string | null, };
+
+export type JobProgress = { current: bigint, total: bigint, step: number, };
 
 export type JobReportsResponse = { [key in string]: JobExecutionReport };
 
-export type JobState = { jobId: string, status: JobStatus, currentStep: number, values: { [key in number]: string }, };
+export type JobState = { jobId: string, status: JobStatus, currentStep: number, values: { [key in number]: string }, 
+/**
+ * The most recent progress reported by the job, if it reported any.
+ */
+progress: JobProgress | null, parameters: JobParameters, };
 
 export type JobStateResponse = { [key in string]: JobState };
 
 export type JobStatus = "pending" | "inProgress";
 
-export type RegistryJob = { id: string, name: string, description: string, steps: { [key in number]: string }, };
+/**
+ * Set while the server is in maintenance, see [`crate::state::Maintenance`].
+ */
+export type MaintenanceMode = { startedAt: Date, 
+/**
+ * Why the server is in maintenance, e.g. the name of the backup that's running.
+ */
+reason: string | null, };
+
+export type MaintenanceRequest = { 
+/**
+ * Whether to start or end maintenance.
+ */
+enabled: boolean, 
+/**
+ * Why the server is put in maintenance, shown to clients that are turned away.
+ */
+reason: string | null, };
+
+export type NewDatabaseSong = { path: string, title: string | null, artist: string | null, album: string | null, albumArtist: string | null, genre: string | null, trackNumber: string | null, discNumber: string | null, year: string | null, mood: string | null, lyrics: string | null, fileCreatedAt: Date, };
+
+export type NewDirectory = { 
+/**
+ * The path of the directory.
+ */
+path: string, 
+/**
+ * The display name of the directory, only used in the UI.
+ */
+displayName: string | null, 
+/**
+ * Add the directory even if it's a home directory, which usually contains far more than
+ * music.
+ */
+allowHomeDirectory: boolean, };
+
+/**
+ * A single page of rows along with the total amount of rows available.
+ */
+export type Page<T> = { items: Array<T>, total: number, };
+
+export type PathRenameOptions = { renameOriginalFiles: boolean, 
+/**
+ * Directory to organize every track into, albums split across multiple directories are
+ * organized within each track's own directory if not set.
+ */
+directoryId: string | null, 
+/**
+ * Convert non-ASCII characters in the rendered path to ASCII.
+ */
+transliterate: boolean, 
+/**
+ * Handlebars template to render the paths with instead of the default one.
+ */
+template: string | null, 
+/**
+ * Name of a template from the organize settings, can't be combined with `template`.
+ */
+templateName: string | null, };
+
+export type PathRenamePreviewResult = { previousPath: string, newPath: string, 
+/**
+ * The directory the song will be organized into.
+ */
+directoryId: string, 
+/**
+ * Whether another song in the album would be moved to the same path.
+ */
+collides: boolean, };
+
+export type RegistryJob = { id: string, name: string, description: string, steps: { [key in number]: string }, supportsDryRun: boolean, supportsStepSelection: boolean, supportsFilters: boolean, supportsPlaylist: boolean, exclusive: boolean, };
+
+export type SongFile = { 
+/**
+ * The path to the file
+ */
+path: string, 
+/**
+ * The type of metadata format used
+ */
+tagType: TagType, 
+/**
+ * The type of file
+ */
+fileType: SongFileType, 
+/**
+ * System time when the file was created
+ */
+created: Date, 
+/**
+ * System time when the file was last modified
+ */
+lastModified: Date, 
+/**
+ * The size of the file
+ */
+size: bigint, 
+/**
+ * Metadata contained in the file
+ */
+metadata: SongMetadata | null, };
+
+/**
+ * Similar to [`FileType`] from [lofty](https://crates.io/crates/lofty), except with [`Serialize`] and [`Deserialize`] traits implemented.
+ */
+export type SongFileType = "aac" | "aiff" | "ape" | "flac" | "mpeg" | "mp4" | "mpc" | "opus" | "vorbis" | "speex" | "wav" | "wavPack" | "unknown";
+
+export type SongMetadata = { 
+/**
+ * The values of each field as they're stored in the tag, values can contain the separator.
+ */
+values?: { [key in SongMetadataKey]?: Array<string> }, unknown?: { [key in string]: string }, } & ({ [key in "album" | "albumArtist" | "albumSort" | "artist" | "artistSort" | "artists" | "barcode" | "bpm" | "catalogNumber" | "comment" | "composer" | "composerSortOrder" | "conductor" | "copyright" | "director" | "discNumber" | "discTotal" | "encodedBy" | "encoderSettings" | "engineer" | "genre" | "grouping" | "key" | "isrc" | "language" | "license" | "lyricist" | "lyrics" | "mood" | "movement" | "movementNumber" | "movementTotal" | "musicBrainzRecordingId" | "musicBrainzTrackId" | "musicBrainzReleaseId" | "musicBrainzReleaseGroupId" | "musicBrainzArtistId" | "musicBrainzReleaseArtistId" | "musicBrainzWorkId" | "originalAlbum" | "originalArtist" | "originalFileName" | "originalReleaseDate" | "performer" | "producer" | "label" | "releaseDate" | "recordingDate" | "title" | "titleSort" | "trackNumber" | "trackTotal" | "website" | "work" | "writer" | "year" | "setSubtitle" | "showName" | "trackSubtitle" | "originalLyricist" | "albumTitleSortOrder" | "showNameSortOrder" | "arranger" | "mixDj" | "mixEngineer" | "musicianCredits" | "publisher" | "internetRadioStationName" | "internetRadioStationOwner" | "remixer" | "popularimeter" | "parentalAdvisory" | "flagCompilation" | "flagPodcast" | "fileType" | "fileOwner" | "taggingTime" | "length" | "originalMediaType" | "encoderSoftware" | "encodingTime" | "replayGainAlbumGain" | "replayGainAlbumPeak" | "replayGainTrackGain" | "replayGainTrackPeak" | "audioFileUrl" | "audioSourceUrl" | "commercialInformationUrl" | "copyrightUrl" | "radioStationUrl" | "paymentUrl" | "publisherUrl" | "integerBpm" | "color" | "podcastDescription" | "podcastSeriesCategory" | "podcastUrl" | "podcastGlobalUniqueId" | "podcastKeywords" | "description" | "script" | "appleXid" | "appleId3v2ContentGroup" | "unknown"]?: string });
+
+/**
+ * The Item Keys that a [`Tag`](lofty::tag::Tag) can have, essentially is the same as Lofty's [`ItemKey`](lofty::tag::ItemKey) but copies some names from [MusicBrainz Picard Tag Mapping](https://picard-docs.musicbrainz.org/downloads/MusicBrainz_Picard_Tag_Map.html) spec.
+ *
+ * Additionally, this enum has the [`Serialize`](serde::Serialize) and [`Deserialize`](serde::Deserialize) traits implemented and maps to and from Lofty [`ItemKey`](lofty::tag::ItemKey).
+ */
+export type SongMetadataKey = "album" | "albumArtist" | "albumSort" | "artist" | "artistSort" | "artists" | "barcode" | "bpm" | "catalogNumber" | "comment" | "composer" | "composerSortOrder" | "conductor" | "copyright" | "director" | "discNumber" | "discTotal" | "encodedBy" | "encoderSettings" | "engineer" | "genre" | "grouping" | "key" | "isrc" | "language" | "license" | "lyricist" | "lyrics" | "mood" | "movement" | "movementNumber" | "movementTotal" | "musicBrainzRecordingId" | "musicBrainzTrackId" | "musicBrainzReleaseId" | "musicBrainzReleaseGroupId" | "musicBrainzArtistId" | "musicBrainzReleaseArtistId" | "musicBrainzWorkId" | "originalAlbum" | "originalArtist" | "originalFileName" | "originalReleaseDate" | "performer" | "producer" | "label" | "releaseDate" | "recordingDate" | "title" | "titleSort" | "trackNumber" | "trackTotal" | "website" | "work" | "writer" | "year" | "setSubtitle" | "showName" | "trackSubtitle" | "originalLyricist" | "albumTitleSortOrder" | "showNameSortOrder" | "arranger" | "mixDj" | "mixEngineer" | "musicianCredits" | "publisher" | "internetRadioStationName" | "internetRadioStationOwner" | "remixer" | "popularimeter" | "parentalAdvisory" | "flagCompilation" | "flagPodcast" | "fileType" | "fileOwner" | "taggingTime" | "length" | "originalMediaType" | "encoderSoftware" | "encodingTime" | "replayGainAlbumGain" | "replayGainAlbumPeak" | "replayGainTrackGain" | "replayGainTrackPeak" | "audioFileUrl" | "audioSourceUrl" | "commercialInformationUrl" | "copyrightUrl" | "radioStationUrl" | "paymentUrl" | "publisherUrl" | "integerBpm" | "color" | "podcastDescription" | "podcastSeriesCategory" | "podcastUrl" | "podcastGlobalUniqueId" | "podcastKeywords" | "description" | "script" | "appleXid" | "appleId3v2ContentGroup" | "unknown";
+
+export type SystemInfo = { 
+/**
+ * The name of the operating system, e.g. "Windows", "Ubuntu", "Darwin"
+ */
+os: string, 
+/**
+ * The name of the computer
+ */
+name: string, };
+
+/**
+ * Duplicate of [`TagType`](lofty::tag::TagType) from [lofty](https://crates.io/crates/lofty), except with [`Serialize`](serde::Serialize) and [`Deserialize`](serde::Deserialize) traits implemented.
+ */
+export type TagType = "ape" | "id3v1" | "id3v2" | "mp4Ilst" | "vorbisComments" | "riffInfo" | "aiffText";
+
+export type UpdatedSong = { title: string | null, artist: string | null, album: string | null, album_artist: string | null, genre: string | null, track_number: string | null, disc_number: string | null, year: string | null, mood: string | null, lyrics: string | null, };
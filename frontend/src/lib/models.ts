@@ -1,4 +1,3 @@
-import type { DatabaseSong } from "@lib/bindings/DatabaseSong";
-import type { SongMetadata } from "@lib/bindings/SongMetadata";
+import type { DatabaseSong, SongMetadata } from "@lib/bindings/bindings";
 
 export type Song = DatabaseSong & SongMetadata;
@@ -1,4 +1,4 @@
-import type { DatabaseSong } from "@lib/bindings/DatabaseSong";
+import type { DatabaseSong } from "@lib/bindings/bindings";
 import type { Song } from "@lib/models";
 import { sendMessage } from "./utils";
 
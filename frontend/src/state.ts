@@ -1,4 +1,4 @@
-import type { DatabaseSong } from "@lib/bindings/DatabaseSong";
+import type { DatabaseSong } from "@lib/bindings/bindings";
 import type { Song } from "@lib/models";
 import type { ResolvedRoute, Route, Router } from "@lib/router";
 import { createContext } from "svelte";
@@ -1,3 +1,11 @@
+import { SCHEMA_VERSION } from "@lib/bindings/bindings";
+
+/**
+ * Header telling the server which schema version the bindings of the frontend were generated for,
+ * so a stale frontend gets a `409 Conflict` instead of misreading the responses.
+ */
+const SCHEMA_VERSION_HEADER = "X-Schema-Version";
+
 // Interface for fetch errors
 export interface FetchError extends Error {
 	status: number;
@@ -18,6 +26,7 @@ export async function fetchJson<T>(
 			method: "GET",
 			headers: {
 				"Content-Type": "application/json",
+				[SCHEMA_VERSION_HEADER]: String(SCHEMA_VERSION),
 			},
 			...options,
 		});
@@ -44,6 +53,9 @@ export async function fetchText(
 	try {
 		const response = await fetch(url, {
 			method: "GET",
+			headers: {
+				[SCHEMA_VERSION_HEADER]: String(SCHEMA_VERSION),
+			},
 			...options,
 		});
 
@@ -0,0 +1,2 @@
+DROP TABLE `playlist_songs`;
+DROP TABLE `playlists`;
@@ -0,0 +1,18 @@
+CREATE TABLE `playlists` (
+    `id` TEXT NOT NULL PRIMARY KEY,
+    `name` TEXT NOT NULL,
+    `created_at` DATETIME DEFAULT NULL,
+    `updated_at` DATETIME DEFAULT NULL
+);
+
+CREATE TABLE `playlist_songs` (
+    `playlist_id` TEXT NOT NULL,
+    `song_id` TEXT NOT NULL,
+    `position` INTEGER NOT NULL,
+    PRIMARY KEY (`playlist_id`, `song_id`),
+    FOREIGN KEY (`playlist_id`) REFERENCES `playlists` (`id`) ON DELETE CASCADE,
+    FOREIGN KEY (`song_id`) REFERENCES `songs` (`id`) ON DELETE CASCADE
+);
+
+CREATE INDEX `playlist_songs_position` ON `playlist_songs` (`playlist_id`, `position`);
+CREATE INDEX `playlist_songs_song_id` ON `playlist_songs` (`song_id`);
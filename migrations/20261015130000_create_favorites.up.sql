@@ -0,0 +1,5 @@
+CREATE TABLE `favorites` (
+    `song_id` TEXT NOT NULL PRIMARY KEY,
+    `added_at` DATETIME DEFAULT NULL,
+    FOREIGN KEY (`song_id`) REFERENCES `songs` (`id`) ON DELETE CASCADE
+);
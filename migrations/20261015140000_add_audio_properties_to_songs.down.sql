@@ -0,0 +1,4 @@
+ALTER TABLE songs DROP COLUMN duration_ms;
+ALTER TABLE songs DROP COLUMN bitrate_kbps;
+ALTER TABLE songs DROP COLUMN sample_rate;
+ALTER TABLE songs DROP COLUMN channels;
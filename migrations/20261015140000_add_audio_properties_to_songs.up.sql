@@ -0,0 +1,4 @@
+ALTER TABLE songs ADD COLUMN duration_ms INTEGER DEFAULT NULL;
+ALTER TABLE songs ADD COLUMN bitrate_kbps INTEGER DEFAULT NULL;
+ALTER TABLE songs ADD COLUMN sample_rate INTEGER DEFAULT NULL;
+ALTER TABLE songs ADD COLUMN channels INTEGER DEFAULT NULL;
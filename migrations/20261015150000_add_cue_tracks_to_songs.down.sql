@@ -0,0 +1,44 @@
+-- no-transaction
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+-- Only the first track of every cue sheet is kept.
+DELETE FROM `favorites` WHERE `song_id` IN (SELECT `id` FROM `songs` WHERE `start_ms` != 0);
+DELETE FROM `playlist_songs` WHERE `song_id` IN (SELECT `id` FROM `songs` WHERE `start_ms` != 0);
+
+CREATE TABLE `songs_new` (
+		`id` TEXT NOT NULL PRIMARY KEY,
+		`path` TEXT NOT NULL,
+		`title` TEXT,
+		`artist` TEXT,
+		`album` TEXT,
+		`album_artist` TEXT,
+		`genre` TEXT,
+		`year` TEXT,
+		`track_number` TEXT,
+		`disc_number` TEXT,
+		`mood` TEXT,
+		`added_at` DATETIME DEFAULT NULL,
+		`updated_at` DATETIME DEFAULT NULL,
+		`file_created_at` DATETIME DEFAULT NULL,
+		`directory_id` TEXT NOT NULL,
+		`duration_ms` INTEGER DEFAULT NULL,
+		`bitrate_kbps` INTEGER DEFAULT NULL,
+		`sample_rate` INTEGER DEFAULT NULL,
+		`channels` INTEGER DEFAULT NULL,
+		FOREIGN KEY (`directory_id`) REFERENCES `directories` (`name`),
+		UNIQUE (`path`) ON CONFLICT REPLACE
+);
+
+INSERT INTO `songs_new` (`id`, `path`, `title`, `artist`, `album`, `album_artist`, `genre`, `year`, `track_number`, `disc_number`, `mood`, `added_at`, `updated_at`, `file_created_at`, `directory_id`, `duration_ms`, `bitrate_kbps`, `sample_rate`, `channels`)
+SELECT `id`, `path`, `title`, `artist`, `album`, `album_artist`, `genre`, `year`, `track_number`, `disc_number`, `mood`, `added_at`, `updated_at`, `file_created_at`, `directory_id`, `duration_ms`, `bitrate_kbps`, `sample_rate`, `channels`
+FROM `songs`
+WHERE `start_ms` = 0;
+
+DROP TABLE `songs`;
+ALTER TABLE `songs_new` RENAME TO `songs`;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,45 @@
+-- no-transaction
+-- Tracks of a cue sheet share the same file, so paths are only unique together with the start of
+-- the track. Foreign keys are disabled while the table is rebuilt so dropping the old table doesn't
+-- cascade into favorites and playlists.
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE `songs_new` (
+		`id` TEXT NOT NULL PRIMARY KEY,
+		`path` TEXT NOT NULL,
+		`title` TEXT,
+		`artist` TEXT,
+		`album` TEXT,
+		`album_artist` TEXT,
+		`genre` TEXT,
+		`year` TEXT,
+		`track_number` TEXT,
+		`disc_number` TEXT,
+		`mood` TEXT,
+		`added_at` DATETIME DEFAULT NULL,
+		`updated_at` DATETIME DEFAULT NULL,
+		`file_created_at` DATETIME DEFAULT NULL,
+		`directory_id` TEXT NOT NULL,
+		`duration_ms` INTEGER DEFAULT NULL,
+		`bitrate_kbps` INTEGER DEFAULT NULL,
+		`sample_rate` INTEGER DEFAULT NULL,
+		`channels` INTEGER DEFAULT NULL,
+		`cue_path` TEXT DEFAULT NULL,
+		`start_ms` INTEGER NOT NULL DEFAULT 0,
+		`end_ms` INTEGER DEFAULT NULL,
+		FOREIGN KEY (`directory_id`) REFERENCES `directories` (`name`),
+		UNIQUE (`path`, `start_ms`) ON CONFLICT REPLACE
+);
+
+INSERT INTO `songs_new` (`id`, `path`, `title`, `artist`, `album`, `album_artist`, `genre`, `year`, `track_number`, `disc_number`, `mood`, `added_at`, `updated_at`, `file_created_at`, `directory_id`, `duration_ms`, `bitrate_kbps`, `sample_rate`, `channels`)
+SELECT `id`, `path`, `title`, `artist`, `album`, `album_artist`, `genre`, `year`, `track_number`, `disc_number`, `mood`, `added_at`, `updated_at`, `file_created_at`, `directory_id`, `duration_ms`, `bitrate_kbps`, `sample_rate`, `channels`
+FROM `songs`;
+
+DROP TABLE `songs`;
+ALTER TABLE `songs_new` RENAME TO `songs`;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1 @@
+DROP INDEX `songs_added_at`;
@@ -0,0 +1 @@
+CREATE INDEX `songs_added_at` ON `songs` (`added_at`);
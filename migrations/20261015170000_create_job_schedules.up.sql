@@ -0,0 +1,7 @@
+CREATE TABLE `job_schedules` (
+    `job_id` TEXT NOT NULL PRIMARY KEY,
+    `trigger` TEXT NOT NULL,
+    `enabled` BOOLEAN NOT NULL DEFAULT 1,
+    `last_run_at` DATETIME DEFAULT NULL,
+    `updated_at` DATETIME NOT NULL
+);
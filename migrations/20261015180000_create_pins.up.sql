@@ -0,0 +1,7 @@
+CREATE TABLE `pins` (
+    `kind` TEXT NOT NULL,
+    `entity_id` TEXT NOT NULL,
+    `position` INTEGER NOT NULL,
+    `pinned_at` DATETIME DEFAULT NULL,
+    PRIMARY KEY (`kind`, `entity_id`)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE `songs` DROP COLUMN `album_peak`;
+ALTER TABLE `songs` DROP COLUMN `album_gain_db`;
+ALTER TABLE `songs` DROP COLUMN `track_peak`;
+ALTER TABLE `songs` DROP COLUMN `track_gain_db`;
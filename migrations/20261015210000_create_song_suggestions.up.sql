@@ -0,0 +1,12 @@
+CREATE TABLE `song_suggestions` (
+    `song_id` TEXT NOT NULL,
+    `recording_id` TEXT NOT NULL,
+    `score` INTEGER NOT NULL,
+    `title` TEXT NOT NULL,
+    `artist` TEXT DEFAULT NULL,
+    `album` TEXT DEFAULT NULL,
+    `length_ms` INTEGER DEFAULT NULL,
+    `suggested_at` DATETIME DEFAULT NULL,
+    PRIMARY KEY (`song_id`, `recording_id`),
+    FOREIGN KEY (`song_id`) REFERENCES `songs` (`id`) ON DELETE CASCADE
+);
@@ -0,0 +1,9 @@
+CREATE TABLE `events` (
+    `id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `kind` TEXT NOT NULL,
+    `source` TEXT DEFAULT NULL,
+    `message` TEXT DEFAULT NULL,
+    `created_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `events_created_at` ON `events` (`created_at`);
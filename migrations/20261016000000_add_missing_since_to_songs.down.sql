@@ -0,0 +1,3 @@
+DROP INDEX `songs_missing_since`;
+
+ALTER TABLE `songs` DROP COLUMN `missing_since`;
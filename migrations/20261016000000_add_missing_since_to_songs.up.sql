@@ -0,0 +1,3 @@
+ALTER TABLE `songs` ADD COLUMN `missing_since` DATETIME DEFAULT NULL;
+
+CREATE INDEX `songs_missing_since` ON `songs` (`missing_since`);
@@ -0,0 +1,3 @@
+DROP INDEX `songs_properties_read`;
+
+ALTER TABLE `songs` DROP COLUMN `properties_read`;
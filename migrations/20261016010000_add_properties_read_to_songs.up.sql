@@ -0,0 +1,6 @@
+ALTER TABLE `songs` ADD COLUMN `properties_read` BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Songs scanned before the audio properties were read don't have a duration.
+UPDATE `songs` SET `properties_read` = TRUE WHERE `duration_ms` IS NOT NULL;
+
+CREATE INDEX `songs_properties_read` ON `songs` (`properties_read`);
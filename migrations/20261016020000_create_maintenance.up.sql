@@ -0,0 +1,6 @@
+-- Holds a single row while the server is in maintenance, so it's still in it after a restart.
+CREATE TABLE `maintenance` (
+    `id` INTEGER NOT NULL PRIMARY KEY CHECK (`id` = 1),
+    `started_at` DATETIME NOT NULL,
+    `reason` TEXT DEFAULT NULL
+);
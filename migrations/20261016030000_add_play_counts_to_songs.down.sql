@@ -0,0 +1,7 @@
+DROP TABLE `plays`;
+
+DROP INDEX `songs_last_played_at`;
+
+ALTER TABLE `songs` DROP COLUMN `last_played_at`;
+ALTER TABLE `songs` DROP COLUMN `skip_count`;
+ALTER TABLE `songs` DROP COLUMN `play_count`;
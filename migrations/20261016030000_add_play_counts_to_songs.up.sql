@@ -0,0 +1,15 @@
+ALTER TABLE `songs` ADD COLUMN `play_count` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `songs` ADD COLUMN `skip_count` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `songs` ADD COLUMN `last_played_at` DATETIME DEFAULT NULL;
+
+CREATE INDEX `songs_last_played_at` ON `songs` (`last_played_at`);
+
+CREATE TABLE `plays` (
+    `id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `song_id` TEXT NOT NULL,
+    `played_at` DATETIME NOT NULL,
+    `skipped` BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY (`song_id`) REFERENCES `songs` (`id`) ON DELETE CASCADE
+);
+
+CREATE INDEX `plays_played_at` ON `plays` (`played_at`);
@@ -0,0 +1,3 @@
+ALTER TABLE `songs` DROP COLUMN `end_offset_ms`;
+ALTER TABLE `songs` DROP COLUMN `start_offset_ms`;
+ALTER TABLE `songs` DROP COLUMN `exclude_from_shuffle`;
@@ -0,0 +1,3 @@
+ALTER TABLE `songs` ADD COLUMN `exclude_from_shuffle` BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE `songs` ADD COLUMN `start_offset_ms` INTEGER DEFAULT NULL;
+ALTER TABLE `songs` ADD COLUMN `end_offset_ms` INTEGER DEFAULT NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE `smart_playlists` (
+    `id` TEXT NOT NULL PRIMARY KEY,
+    `name` TEXT NOT NULL,
+    `filter` TEXT NOT NULL,
+    `created_at` DATETIME DEFAULT NULL,
+    `updated_at` DATETIME DEFAULT NULL
+);
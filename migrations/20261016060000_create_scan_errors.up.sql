@@ -0,0 +1,10 @@
+CREATE TABLE `scan_errors` (
+    `id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `path` TEXT NOT NULL UNIQUE,
+    `category` TEXT NOT NULL,
+    `error` TEXT NOT NULL,
+    `modified_at` DATETIME DEFAULT NULL,
+    `first_seen` DATETIME NOT NULL,
+    `last_seen` DATETIME NOT NULL,
+    `attempts` INTEGER NOT NULL DEFAULT 1
+);
@@ -0,0 +1,2 @@
+ALTER TABLE `songs` DROP COLUMN `comment`;
+ALTER TABLE `songs` DROP COLUMN `composer`;
@@ -0,0 +1,2 @@
+ALTER TABLE `songs` ADD COLUMN `composer` TEXT;
+ALTER TABLE `songs` ADD COLUMN `comment` TEXT;
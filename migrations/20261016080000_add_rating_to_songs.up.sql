@@ -0,0 +1 @@
+ALTER TABLE `songs` ADD COLUMN `rating` INTEGER CHECK (`rating` BETWEEN 0 AND 100);
@@ -0,0 +1,5 @@
+DROP TRIGGER `songs_search_update`;
+DROP TRIGGER `songs_search_delete`;
+DROP TRIGGER `songs_search_insert`;
+DROP TRIGGER `songs_search_replace`;
+DROP TABLE `songs_search`;
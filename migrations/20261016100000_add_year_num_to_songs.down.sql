@@ -0,0 +1,2 @@
+DROP INDEX `songs_year_num`;
+ALTER TABLE `songs` DROP COLUMN `year_num`;
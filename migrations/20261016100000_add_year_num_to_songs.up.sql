@@ -0,0 +1,4 @@
+-- Filled in from `year` after the migration, as the year is parsed the same way the app does.
+ALTER TABLE `songs` ADD COLUMN `year_num` INTEGER;
+
+CREATE INDEX `songs_year_num` ON `songs` (`year_num`);
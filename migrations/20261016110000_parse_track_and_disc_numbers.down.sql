@@ -0,0 +1,7 @@
+ALTER TABLE `songs` DROP COLUMN `disc_total`;
+ALTER TABLE `songs` DROP COLUMN `disc_number`;
+ALTER TABLE `songs` DROP COLUMN `track_total`;
+ALTER TABLE `songs` DROP COLUMN `track_number`;
+
+ALTER TABLE `songs` RENAME COLUMN `disc_number_tag` TO `disc_number`;
+ALTER TABLE `songs` RENAME COLUMN `track_number_tag` TO `track_number`;
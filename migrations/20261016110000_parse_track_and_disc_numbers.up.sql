@@ -0,0 +1,9 @@
+-- The tags are kept until the numbers are parsed from them after the migration, as they're parsed
+-- the same way the app does.
+ALTER TABLE `songs` RENAME COLUMN `track_number` TO `track_number_tag`;
+ALTER TABLE `songs` RENAME COLUMN `disc_number` TO `disc_number_tag`;
+
+ALTER TABLE `songs` ADD COLUMN `track_number` INTEGER;
+ALTER TABLE `songs` ADD COLUMN `track_total` INTEGER;
+ALTER TABLE `songs` ADD COLUMN `disc_number` INTEGER;
+ALTER TABLE `songs` ADD COLUMN `disc_total` INTEGER;
@@ -0,0 +1,6 @@
+ALTER TABLE `songs` ADD COLUMN `track_number_tag` TEXT;
+ALTER TABLE `songs` ADD COLUMN `disc_number_tag` TEXT;
+
+UPDATE `songs` SET
+	`track_number_tag` = `track_number` || coalesce('/' || `track_total`, ''),
+	`disc_number_tag` = `disc_number` || coalesce('/' || `disc_total`, '');
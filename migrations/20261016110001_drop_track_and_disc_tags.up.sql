@@ -0,0 +1,2 @@
+ALTER TABLE `songs` DROP COLUMN `track_number_tag`;
+ALTER TABLE `songs` DROP COLUMN `disc_number_tag`;
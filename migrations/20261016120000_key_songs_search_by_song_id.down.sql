@@ -0,0 +1,39 @@
+DROP TRIGGER `songs_search_update`;
+DROP TRIGGER `songs_search_delete`;
+DROP TRIGGER `songs_search_insert`;
+DROP TRIGGER `songs_search_replace_update`;
+DROP TRIGGER `songs_search_replace`;
+DROP TABLE `songs_search`;
+
+CREATE VIRTUAL TABLE `songs_search` USING fts5(
+		`title`,
+		`artist`,
+		`album`,
+		`album_artist`,
+		`genre`,
+		tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO `songs_search` (`rowid`, `title`, `artist`, `album`, `album_artist`, `genre`)
+SELECT `rowid`, `title`, `artist`, `album`, `album_artist`, `genre` FROM `songs`;
+
+-- Paths are unique with `ON CONFLICT REPLACE`, which deletes the replaced song without running the
+-- delete trigger unless recursive triggers are enabled.
+CREATE TRIGGER `songs_search_replace` BEFORE INSERT ON `songs` BEGIN
+	DELETE FROM `songs_search` WHERE `rowid` IN (SELECT `rowid` FROM `songs` WHERE `path` = new.`path` AND `start_ms` = new.`start_ms`);
+END;
+
+CREATE TRIGGER `songs_search_insert` AFTER INSERT ON `songs` BEGIN
+	INSERT INTO `songs_search` (`rowid`, `title`, `artist`, `album`, `album_artist`, `genre`)
+	VALUES (new.`rowid`, new.`title`, new.`artist`, new.`album`, new.`album_artist`, new.`genre`);
+END;
+
+CREATE TRIGGER `songs_search_delete` AFTER DELETE ON `songs` BEGIN
+	DELETE FROM `songs_search` WHERE `rowid` = old.`rowid`;
+END;
+
+CREATE TRIGGER `songs_search_update` AFTER UPDATE OF `title`, `artist`, `album`, `album_artist`, `genre` ON `songs` BEGIN
+	UPDATE `songs_search`
+	SET `title` = new.`title`, `artist` = new.`artist`, `album` = new.`album`, `album_artist` = new.`album_artist`, `genre` = new.`genre`
+	WHERE `rowid` = old.`rowid`;
+END;
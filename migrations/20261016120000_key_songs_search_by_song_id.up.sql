@@ -0,0 +1,50 @@
+-- `songs` has a text primary key, so its rowid isn't stable: VACUUM may renumber it. The index is
+-- keyed by the id of the song instead, the rows are looked up by it only when songs change.
+DROP TRIGGER `songs_search_update`;
+DROP TRIGGER `songs_search_delete`;
+DROP TRIGGER `songs_search_insert`;
+DROP TRIGGER `songs_search_replace`;
+DROP TABLE `songs_search`;
+
+CREATE VIRTUAL TABLE `songs_search` USING fts5(
+		`title`,
+		`artist`,
+		`album`,
+		`album_artist`,
+		`genre`,
+		`song_id` UNINDEXED,
+		tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO `songs_search` (`title`, `artist`, `album`, `album_artist`, `genre`, `song_id`)
+SELECT `title`, `artist`, `album`, `album_artist`, `genre`, `id` FROM `songs`;
+
+-- Paths are unique with `ON CONFLICT REPLACE`, which deletes the replaced song without running the
+-- delete trigger unless recursive triggers are enabled. That happens both when a song is inserted
+-- and when another one is moved to its path.
+CREATE TRIGGER `songs_search_replace` BEFORE INSERT ON `songs`
+WHEN EXISTS (SELECT 1 FROM `songs` WHERE `path` = new.`path` AND `start_ms` = new.`start_ms`)
+BEGIN
+	DELETE FROM `songs_search` WHERE `song_id` IN (SELECT `id` FROM `songs` WHERE `path` = new.`path` AND `start_ms` = new.`start_ms`);
+END;
+
+CREATE TRIGGER `songs_search_replace_update` BEFORE UPDATE OF `path`, `start_ms` ON `songs`
+WHEN EXISTS (SELECT 1 FROM `songs` WHERE `path` = new.`path` AND `start_ms` = new.`start_ms` AND `id` != old.`id`)
+BEGIN
+	DELETE FROM `songs_search` WHERE `song_id` IN (SELECT `id` FROM `songs` WHERE `path` = new.`path` AND `start_ms` = new.`start_ms` AND `id` != old.`id`);
+END;
+
+CREATE TRIGGER `songs_search_insert` AFTER INSERT ON `songs` BEGIN
+	INSERT INTO `songs_search` (`title`, `artist`, `album`, `album_artist`, `genre`, `song_id`)
+	VALUES (new.`title`, new.`artist`, new.`album`, new.`album_artist`, new.`genre`, new.`id`);
+END;
+
+CREATE TRIGGER `songs_search_delete` AFTER DELETE ON `songs` BEGIN
+	DELETE FROM `songs_search` WHERE `song_id` = old.`id`;
+END;
+
+CREATE TRIGGER `songs_search_update` AFTER UPDATE OF `id`, `title`, `artist`, `album`, `album_artist`, `genre` ON `songs` BEGIN
+	UPDATE `songs_search`
+	SET `title` = new.`title`, `artist` = new.`artist`, `album` = new.`album`, `album_artist` = new.`album_artist`, `genre` = new.`genre`, `song_id` = new.`id`
+	WHERE `song_id` = old.`id`;
+END;
@@ -0,0 +1 @@
+ALTER TABLE `songs` DROP COLUMN `cover_art_bytes`;
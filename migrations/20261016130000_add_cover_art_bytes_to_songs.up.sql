@@ -0,0 +1,5 @@
+-- Read along with the audio properties, which are read again for every song so the column is
+-- filled in by the `backfill-audio-properties` job.
+ALTER TABLE `songs` ADD COLUMN `cover_art_bytes` INTEGER;
+
+UPDATE `songs` SET `properties_read` = FALSE;
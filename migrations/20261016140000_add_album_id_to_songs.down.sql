@@ -0,0 +1,5 @@
+DROP TRIGGER `songs_album_id_update`;
+
+DROP INDEX `songs_album_id`;
+
+ALTER TABLE `songs` DROP COLUMN `album_id`;
@@ -0,0 +1,13 @@
+-- The id is a hash, so it's filled in by the server after this migration and stored along with
+-- the album whenever a song is written. Writes that change the album or album artist without it
+-- clear the id instead of leaving a stale one.
+ALTER TABLE `songs` ADD COLUMN `album_id` TEXT;
+
+CREATE INDEX `songs_album_id` ON `songs` (`album_id`) WHERE `album` IS NOT NULL;
+
+CREATE TRIGGER `songs_album_id_update` AFTER UPDATE OF `album`, `album_artist` ON `songs`
+WHEN (new.`album` IS NOT old.`album` OR new.`album_artist` IS NOT old.`album_artist`)
+	AND new.`album_id` IS old.`album_id`
+BEGIN
+	UPDATE `songs` SET `album_id` = NULL WHERE `id` = new.`id`;
+END;
@@ -4,21 +4,50 @@ use axum::{http::StatusCode, response::IntoResponse};
 
 use super::{
     Error,
-    db::{DatabaseError, songs::DatabaseSongError},
+    backup::BackupError,
+    db::{
+        DatabaseError, artists::DatabaseArtistError, pins::DatabasePinError,
+        playlists::DatabasePlaylistError, scan_errors::DatabaseScanError,
+        schedules::DatabaseScheduleError, smart_playlists::DatabaseSmartPlaylistError,
+        songs::DatabaseSongError, stats::DatabaseStatsError, suggestions::DatabaseSuggestionError,
+    },
+    inbox::InboxError,
+    logging::LoggingError,
+    metadata::FileAccessError,
     organize::OrganizeError,
+    providers::ProviderError,
     state::{
         OperationManagerError,
         job::{JobRegistryError, manager::JobManagerError},
     },
+    trash::TrashError,
 };
 
+pub mod admin;
 pub mod albums;
+pub mod artists;
+pub mod auth;
+pub mod client_ip;
 pub mod cover_art;
 pub mod directories;
+pub mod genres;
+pub mod home;
+pub mod inbox;
+pub mod include;
 pub mod info;
 pub mod jobs;
+pub mod library;
+pub mod maintenance;
 pub mod organize;
+pub mod playlists;
+pub mod providers;
+pub mod schema_version;
+pub mod search;
+pub mod settings;
+pub mod smart_playlists;
 pub mod songs;
+pub mod subsonic;
+pub mod trash;
 pub mod ui;
 
 /// Utility function for mapping any error into a `500 Internal Server Error`
@@ -34,6 +63,12 @@ pub fn bad_request(err: impl Display) -> (StatusCode, String) {
     (StatusCode::BAD_REQUEST, err.to_string())
 }
 
+/// Utility function for mapping any error into a `401 Unauthorized` response.
+pub fn unauthorized(err: impl Display) -> (StatusCode, String) {
+    tracing::error!("unauthorized: {}", err);
+    (StatusCode::UNAUTHORIZED, err.to_string())
+}
+
 /// Utility function for mapping any error into a `404 Not Found` response.
 pub fn not_found(err: impl Display) -> (StatusCode, String) {
     tracing::error!("not found: {}", err);
@@ -46,6 +81,24 @@ pub fn conflict(err: impl Display) -> (StatusCode, String) {
     (StatusCode::CONFLICT, err.to_string())
 }
 
+/// Utility function for mapping any error of an upstream service into a `502 Bad Gateway`
+/// response.
+pub fn bad_gateway(err: impl Display) -> (StatusCode, String) {
+    tracing::error!("bad gateway: {}", err);
+    (StatusCode::BAD_GATEWAY, err.to_string())
+}
+
+/// Returns a `Content-Disposition` value making clients save the response as `file_name`, with
+/// an ASCII fallback for clients that don't understand UTF-8 file names.
+pub fn attachment(file_name: &str) -> String {
+    let fallback = any_ascii::any_ascii(file_name).replace(['"', '\\'], "_");
+    let encoded = url::form_urlencoded::byte_serialize(file_name.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20");
+
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         match self {
@@ -68,7 +121,9 @@ impl IntoResponse for OrganizeError {
                 }
                 _ => internal_error(err).into_response(),
             },
+            Self::Template(_) => bad_request(self).into_response(),
             Self::NoFileName(err) => bad_request(err.display()).into_response(),
+            Self::CoverArt(_) | Self::Io(_) => internal_error(self).into_response(),
         }
     }
 }
@@ -84,6 +139,12 @@ impl IntoResponse for JobManagerError {
             JobManagerError::StateNotFound | JobManagerError::ReportNotFound => {
                 not_found(self).into_response()
             }
+            JobManagerError::DryRunUnsupported
+            | JobManagerError::StepSelectionUnsupported
+            | JobManagerError::UnknownStep(_)
+            | JobManagerError::FiltersUnsupported
+            | JobManagerError::PlaylistUnsupported
+            | JobManagerError::GenreMergeUnsupported => bad_request(self).into_response(),
         }
     }
 }
@@ -93,9 +154,10 @@ impl IntoResponse for DatabaseSongError {
         match self {
             DatabaseSongError::SongAlreadyExists => conflict(self).into_response(),
             DatabaseSongError::Metadata(err) => internal_error(err).into_response(),
-            DatabaseSongError::PathNotFound | Self::PathDoesntContainDirectory => {
-                bad_request(self).into_response()
-            }
+            DatabaseSongError::PathNotFound
+            | Self::PathDoesntContainDirectory
+            | Self::InvalidPlaybackRange
+            | Self::InvalidFilter(_) => bad_request(self).into_response(),
             DatabaseSongError::AlbumNotFound | Self::SongNotFound => {
                 not_found(self).into_response()
             }
@@ -103,11 +165,92 @@ impl IntoResponse for DatabaseSongError {
     }
 }
 
+impl IntoResponse for DatabaseArtistError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound => not_found(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for DatabaseStatsError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::InvalidYear(_) => bad_request(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for DatabasePlaylistError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound | Self::SongNotInPlaylist => not_found(self).into_response(),
+            Self::SongAlreadyAdded => conflict(self).into_response(),
+            Self::NameEmpty | Self::SongNotFound | Self::InvalidOrder => {
+                bad_request(self).into_response()
+            }
+        }
+    }
+}
+
+impl IntoResponse for DatabaseSmartPlaylistError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound => not_found(self).into_response(),
+            Self::NameEmpty => bad_request(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for DatabasePinError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound | Self::EntityNotFound => not_found(self).into_response(),
+            Self::AlreadyPinned => conflict(self).into_response(),
+            Self::InvalidOrder => bad_request(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for DatabaseScheduleError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound => not_found(self).into_response(),
+            Self::IntervalTooShort | Self::InvalidTime(_) => bad_request(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for DatabaseScanError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound => not_found(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for DatabaseSuggestionError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound => not_found(self).into_response(),
+        }
+    }
+}
+
 impl IntoResponse for DatabaseError {
     fn into_response(self) -> axum::response::Response {
         match self {
             DatabaseError::Song(err) => err.into_response(),
+            DatabaseError::Artist(err) => err.into_response(),
+            DatabaseError::Stats(err) => err.into_response(),
             DatabaseError::Directory(err) => err.into_response(),
+            DatabaseError::Playlist(err) => err.into_response(),
+            DatabaseError::Pin(err) => err.into_response(),
+            DatabaseError::Schedule(err) => err.into_response(),
+            DatabaseError::ScanError(err) => err.into_response(),
+            DatabaseError::SmartPlaylist(err) => err.into_response(),
+            DatabaseError::Suggestion(err) => err.into_response(),
+            DatabaseError::Library(err) => bad_request(err).into_response(),
             DatabaseError::Sqlx(err) => internal_error(err).into_response(),
         }
     }
@@ -121,3 +264,66 @@ impl IntoResponse for OperationManagerError {
         }
     }
 }
+
+impl IntoResponse for ProviderError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::UnknownProvider(_) | Self::NotFound => not_found(self).into_response(),
+            Self::RateLimited => {
+                tracing::warn!("{self}");
+                (StatusCode::SERVICE_UNAVAILABLE, self.to_string()).into_response()
+            }
+            Self::Request(_) | Self::InvalidResponse(_) => bad_gateway(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for InboxError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotConfigured
+            | Self::OutsideInbox(_)
+            | Self::NoTracks(_)
+            | Self::UnknownTrack(_) => bad_request(self).into_response(),
+            Self::Metadata(_) | Self::Io(_) => internal_error(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for BackupError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::InMemory => conflict(self).into_response(),
+            Self::Database(_) | Self::Io(_) => internal_error(self).into_response(),
+        }
+    }
+}
+
+impl IntoResponse for LoggingError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::InvalidFilter(_) => bad_request(self).into_response(),
+            Self::NoLogFile | Self::InvalidFile(_) => not_found(self).into_response(),
+            Self::Uninitialized | Self::Reload(_) | Self::Io(_) => {
+                internal_error(self).into_response()
+            }
+        }
+    }
+}
+
+impl IntoResponse for TrashError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::NotFound(_) => not_found(self).into_response(),
+            Self::Io(_) | Self::Manifest(_) => internal_error(self).into_response(),
+        }
+    }
+}
+
+/// Both are `403 Forbidden`, so clients can tell them apart from a failed write.
+impl IntoResponse for FileAccessError {
+    fn into_response(self) -> axum::response::Response {
+        tracing::warn!("{self}");
+        (StatusCode::FORBIDDEN, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    response::{IntoResponse, Result},
+    routing::post,
+};
+use serde::Deserialize;
+use ts_rs::TS;
+
+use crate::{
+    db::MaintenanceMode,
+    state::{
+        AppState, JobManager, SharedMaintenance,
+        job::{JobParameters, JobStateId},
+    },
+};
+
+#[derive(Debug, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceRequest {
+    /// Whether to start or end maintenance.
+    pub enabled: bool,
+    /// Why the server is put in maintenance, shown to clients that are turned away.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/admin/rebuild", post(rebuild))
+        .route("/api/admin/maintenance", post(set_maintenance))
+}
+
+/// Queues the rebuild of derived data, `?steps=1,3` only rebuilds the selected steps.
+async fn rebuild(
+    State(manager): State<JobManager>,
+    Query(parameters): Query<JobParameters>,
+) -> Result<Json<JobStateId>> {
+    Ok(Json(
+        manager
+            .queue("rebuild-indexes", parameters, true, true)
+            .await?
+            .id(),
+    ))
+}
+
+/// Starts or ends maintenance, returning the maintenance the server is in afterwards.
+///
+/// Starting pauses the job queue and file operations and turns away requests that would change
+/// files, ending it resumes them. Doing either twice changes nothing.
+async fn set_maintenance(
+    State(maintenance): State<SharedMaintenance>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<Json<Option<MaintenanceMode>>> {
+    if request.enabled {
+        let mode = maintenance
+            .start(request.reason)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(Json(Some(mode)))
+    } else {
+        maintenance
+            .end()
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(Json(None))
+    }
+}
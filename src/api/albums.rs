@@ -1,26 +1,97 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::{Path as FilePath, PathBuf},
+};
+
 use axum::{
     Json, Router,
-    extract::{Path, State},
-    response::{IntoResponse, Result},
+    body::{Body, Bytes},
+    extract::{FromRequestParts, RawPathParams, State},
+    http::{header, request::Parts},
+    response::{IntoResponse, Response, Result},
     routing::get,
 };
+use time::OffsetDateTime;
+use tokio::{sync::mpsc, task::spawn_blocking};
+use tokio_stream::wrappers::ReceiverStream;
 
 use crate::{
     AppState,
-    api::internal_error,
-    db::{Album, songs},
+    api::{attachment, internal_error, not_found},
+    db::{Album, DatabaseError, songs},
+    hygiene::{AlbumHygieneReport, LibraryHygieneReport, check_album},
+    paths::album_hygiene_report_path,
     state::Pool,
 };
 
+/// Bytes of the zip collected before they're sent to the client.
+const ZIP_CHUNK_SIZE: usize = 64 * 1024;
+
 pub fn router() -> Router<AppState> {
     Router::new()
+        .route("/api/albums/by-id/{album_id}", get(get_album))
+        .route(
+            "/api/albums/by-id/{album_id}/hygiene",
+            get(get_album_hygiene),
+        )
+        .route("/api/albums/by-id/{album_id}/download", get(download_album))
         .route("/api/albums/{title}", get(get_album))
+        .route("/api/albums/{title}/hygiene", get(get_album_hygiene))
+        .route("/api/albums/{title}/download", get(download_album))
         .route("/api/albums/", get(get_albums))
+        .route("/api/reports/album-hygiene", get(get_library_hygiene))
+}
+
+/// How a route addresses an album, extracted from its `album_id` parameter or, on the routes kept
+/// for older clients, its `title` or `album` parameter.
+///
+/// Titles with a `/`, `?` or `#` get mangled on their way to the server, and albums of different
+/// artists sharing a title are returned as one album by the title routes.
+#[derive(Debug, Clone)]
+pub enum AlbumKey {
+    Id(String),
+    Title(String),
+}
+
+impl AlbumKey {
+    pub async fn get(
+        self,
+        connection: &mut sqlx::SqliteConnection,
+    ) -> Result<Album, DatabaseError> {
+        match self {
+            Self::Id(id) => songs::get_album_by_id(connection, &id).await,
+            Self::Title(title) => songs::get_album(connection, title).await,
+        }
+    }
 }
 
-async fn get_album(State(pool): State<Pool>, Path(title): Path<String>) -> Result<Json<Album>> {
+impl<S> FromRequestParts<S> for AlbumKey
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        params
+            .iter()
+            .find_map(|(name, value)| match name {
+                "album_id" => Some(Self::Id(value.to_string())),
+                "title" | "album" => Some(Self::Title(value.to_string())),
+                _ => None,
+            })
+            .ok_or_else(|| internal_error("The route doesn't address an album").into_response())
+    }
+}
+
+async fn get_album(State(pool): State<Pool>, album: AlbumKey) -> Result<Json<Album>> {
     let mut connection = pool.acquire().await.map_err(internal_error)?;
-    let album = songs::get_album(&mut connection, title)
+    let album = album
+        .get(&mut connection)
         .await
         .map_err(IntoResponse::into_response)?;
 
@@ -35,3 +106,198 @@ async fn get_albums(State(pool): State<Pool>) -> Result<Json<Vec<Album>>> {
 
     Ok(Json(albums))
 }
+
+async fn get_album_hygiene(
+    State(pool): State<Pool>,
+    album: AlbumKey,
+) -> Result<Json<AlbumHygieneReport>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let album = album
+        .get(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(check_album(&album)))
+}
+
+/// Streams a zip of the files of the album's tracks, it's written while it's sent so nothing is
+/// kept on disk or in memory.
+///
+/// Files are named after their path relative to the folder the tracks share, so discs kept in
+/// separate folders stay apart. Cue sheets are included along with the file of their tracks, and
+/// tracks whose file is gone are marked missing and left out.
+async fn download_album(State(pool): State<Pool>, album: AlbumKey) -> Result<Response> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let album = album
+        .get(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let mut files = Vec::new();
+    let mut found = HashMap::new();
+    let mut missing = Vec::new();
+
+    for track in &album.tracks {
+        if !add_file(&mut files, &mut found, &track.path).await {
+            missing.push(track.id.clone());
+        }
+
+        if let Some(cue_path) = &track.cue_path {
+            add_file(&mut files, &mut found, cue_path).await;
+        }
+    }
+
+    if !missing.is_empty() {
+        songs::mark_songs_missing(&mut connection, &missing, OffsetDateTime::now_utc())
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    drop(connection);
+
+    if files.is_empty() {
+        return Err(not_found(format!("None of the files of \"{}\" exist", album.title)).into());
+    }
+
+    let (tx, rx) = mpsc::channel(1);
+    spawn_blocking(move || {
+        let mut writer = ChunkWriter::new(tx.clone());
+
+        // Failing the body aborts the response, so a broken zip can't pass for a complete one.
+        if let Err(err) = write_zip(&files, &mut writer).and_then(|()| Ok(writer.flush()?)) {
+            tracing::warn!("Failed to send the zip of the album: {err}");
+            let _ = tx.blocking_send(Err(io::Error::other(err)));
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, String::from("application/zip")),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment(&format!(
+                    "{}.zip",
+                    sanitize_filename::sanitize(&album.title)
+                )),
+            ),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response())
+}
+
+/// Adds the file to `files` the first time it's found to exist, tracks of a cue sheet share their
+/// file and the sheet. Returns whether it exists.
+async fn add_file(
+    files: &mut Vec<PathBuf>,
+    found: &mut HashMap<PathBuf, bool>,
+    path: &str,
+) -> bool {
+    let path = PathBuf::from(path);
+    if let Some(&exists) = found.get(&path) {
+        return exists;
+    }
+
+    let exists = tokio::fs::try_exists(&path).await.unwrap_or(false);
+    if exists {
+        files.push(path.clone());
+    }
+    found.insert(path, exists);
+
+    exists
+}
+
+/// Writes a zip of the files, named after their path relative to the folder they share.
+fn write_zip(files: &[PathBuf], writer: impl Write) -> zip::result::ZipResult<()> {
+    let base = common_parent(files);
+    let mut zip = zip::ZipWriter::new_stream(writer);
+
+    // Audio and images are compressed already, deflating them would only take longer.
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+
+    for path in files {
+        let name = path
+            .strip_prefix(&base)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        zip.start_file(name, options)?;
+        io::copy(&mut std::fs::File::open(path)?, &mut zip)?;
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+/// Returns the deepest directory containing every path.
+fn common_parent(paths: &[PathBuf]) -> PathBuf {
+    let mut parent = paths
+        .first()
+        .and_then(|path| path.parent())
+        .map(FilePath::to_path_buf)
+        .unwrap_or_default();
+
+    for path in paths {
+        while !path.starts_with(&parent) && parent.pop() {}
+    }
+
+    parent
+}
+
+/// Sends what's written to it to the response body in chunks.
+struct ChunkWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    chunk: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(tx: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        Self {
+            tx,
+            chunk: Vec::with_capacity(ZIP_CHUNK_SIZE),
+        }
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+
+        if self.chunk.len() >= ZIP_CHUNK_SIZE {
+            self.flush()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Sends the collected bytes, fails once the client is gone so the zip stops being written.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(ZIP_CHUNK_SIZE));
+        self.tx
+            .blocking_send(Ok(chunk.into()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "The client is gone"))
+    }
+}
+
+/// Returns the report saved by the last `album-hygiene` job run.
+async fn get_library_hygiene() -> Result<Json<LibraryHygieneReport>> {
+    let path = album_hygiene_report_path();
+
+    let report = match tokio::fs::read_to_string(&path).await {
+        Ok(report) => report,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(not_found("No album hygiene report has been generated yet").into());
+        }
+        Err(err) => return Err(internal_error(err).into()),
+    };
+
+    Ok(Json(serde_json::from_str(&report).map_err(internal_error)?))
+}
@@ -0,0 +1,41 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    response::{IntoResponse, Result},
+    routing::get,
+};
+
+use crate::{
+    AppState,
+    api::internal_error,
+    db::{Artist, ArtistDetail, artists},
+    state::Pool,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/artists/{name}", get(get_artist))
+        .route("/api/artists/", get(get_artists))
+}
+
+async fn get_artists(State(pool): State<Pool>) -> Result<Json<Vec<Artist>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let artists = artists::get_artists(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(artists))
+}
+
+/// Returns the albums and loose tracks of the artist, the name is matched case-insensitively.
+async fn get_artist(
+    State(pool): State<Pool>,
+    Path(name): Path<String>,
+) -> Result<Json<ArtistDetail>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let artist = artists::get_artist(&mut connection, &name)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(artist))
+}
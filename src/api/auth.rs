@@ -0,0 +1,338 @@
+//! Token authentication of the API, only enforced once tokens are configured in [`Auth::tokens`]
+//! so a server only reachable from the same machine works without any setup.
+//!
+//! Clients send a token as `Authorization: Bearer <token>`. The event stream and the WebSocket
+//! also accept it as the `token` query parameter, since browsers can't set headers on an
+//! `EventSource` or a `WebSocket`. The Subsonic API checks the credentials its clients send
+//! itself, see [`super::subsonic`].
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response, Result},
+    routing::post,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ts_rs::TS;
+
+use crate::{
+    config::{Auth, Settings},
+    state::AppState,
+};
+
+use super::{
+    client_ip::{ClientIp, resolve_client_ip},
+    subsonic, *,
+};
+
+/// Query parameter the token can be sent in on [`QUERY_TOKEN_ROUTES`].
+pub const TOKEN_QUERY_PARAMETER: &str = "token";
+
+/// Routes that can be reached without a token.
+const PUBLIC_ROUTES: [&str; 1] = ["/api/auth/login"];
+
+/// Routes that accept the token as a query parameter, as they're opened by an `EventSource` or a
+/// `WebSocket`.
+const QUERY_TOKEN_ROUTES: [&str; 2] = ["/api/events", "/api/ws"];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AuthError {
+    #[error("An API token is required")]
+    MissingToken,
+    #[error("Invalid API token")]
+    InvalidToken,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/api/auth/login", post(login))
+}
+
+#[derive(Deserialize, TS)]
+pub struct Login {
+    pub token: String,
+}
+
+#[derive(Serialize, TS)]
+pub struct LoginResponse {
+    /// Name the token is configured under, not set if authentication is disabled and every
+    /// token is accepted.
+    pub name: Option<String>,
+}
+
+/// Checks a token before a client stores it, so a mistyped one is caught right away.
+async fn login(
+    State(settings): State<Settings>,
+    Json(Login { token }): Json<Login>,
+) -> Result<Json<LoginResponse>> {
+    if settings.auth.tokens.is_empty() {
+        return Ok(Json(LoginResponse { name: None }));
+    }
+
+    let name = find_token(&settings.auth, &token).ok_or(AuthError::InvalidToken)?;
+
+    Ok(Json(LoginResponse {
+        name: Some(name.to_string()),
+    }))
+}
+
+/// Rejects requests to the API without a valid token, unless no tokens are configured or the
+/// client is exempt, see [`ClientIp::is_exempt`].
+pub async fn require_token(
+    State(settings): State<Settings>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| {
+            resolve_client_ip(peer.ip(), request.headers(), &settings.auth.trusted_proxies)
+        });
+
+    match authorize(
+        &settings.auth,
+        request.uri().path(),
+        request.uri().query(),
+        request.headers(),
+        client,
+    ) {
+        Ok(()) => next.run(request).await,
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Decides whether a request may reach the API, `client` is the address it came from if it's
+/// known.
+pub fn authorize(
+    auth: &Auth,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    client: Option<IpAddr>,
+) -> Result<(), AuthError> {
+    if auth.tokens.is_empty()
+        || PUBLIC_ROUTES.contains(&path)
+        || path.starts_with(subsonic::ROUTE_PREFIX)
+    {
+        return Ok(());
+    }
+
+    if client.is_some_and(|client| ClientIp(client).is_exempt(auth)) {
+        return Ok(());
+    }
+
+    let token = bearer_token(headers).or_else(|| {
+        QUERY_TOKEN_ROUTES
+            .contains(&path)
+            .then(|| query_token(query?))
+            .flatten()
+    });
+
+    match token {
+        Some(token) if find_token(auth, &token).is_some() => Ok(()),
+        Some(_) => Err(AuthError::InvalidToken),
+        None => Err(AuthError::MissingToken),
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_string())
+}
+
+fn query_token(query: &str) -> Option<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == TOKEN_QUERY_PARAMETER)
+        .map(|(_, token)| token.into_owned())
+}
+
+/// Returns the name of the configured token matching `token`.
+///
+/// Tokens are compared by their hashes in constant time, so how long a comparison takes doesn't
+/// give away how much of a token was right.
+pub(super) fn find_token<'a>(auth: &'a Auth, token: &str) -> Option<&'a str> {
+    let digest = Sha256::digest(token.as_bytes());
+
+    // Every token is compared so the time taken doesn't depend on which one matches either.
+    auth.tokens
+        .iter()
+        .filter(|(_, configured)| {
+            Sha256::digest(configured.as_bytes())
+                .iter()
+                .zip(digest.iter())
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0
+        })
+        .map(|(name, _)| name.as_str())
+        .last()
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let mut response = unauthorized(self).into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn auth() -> Auth {
+        Auth {
+            tokens: BTreeMap::from([
+                (String::from("phone"), String::from("phone-token")),
+                (String::from("laptop"), String::from("laptop-token")),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        headers
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert_eq!(
+            authorize(
+                &Auth::default(),
+                "/api/songs/",
+                None,
+                &HeaderMap::new(),
+                None
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_missing_token() {
+        assert_eq!(
+            authorize(&auth(), "/api/songs/", None, &HeaderMap::new(), None),
+            Err(AuthError::MissingToken)
+        );
+
+        // Only the event stream accepts the token in the query.
+        assert_eq!(
+            authorize(
+                &auth(),
+                "/api/songs/",
+                Some("token=phone-token"),
+                &HeaderMap::new(),
+                None
+            ),
+            Err(AuthError::MissingToken)
+        );
+
+        assert_eq!(
+            authorize(&auth(), "/api/auth/login", None, &HeaderMap::new(), None),
+            Ok(())
+        );
+        assert_eq!(
+            authorize(&auth(), "/rest/ping.view", None, &HeaderMap::new(), None),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_wrong_token() {
+        for headers in [
+            bearer("phone"),
+            bearer("phone-token2"),
+            bearer("PHONE-TOKEN"),
+        ] {
+            assert_eq!(
+                authorize(&auth(), "/api/songs/", None, &headers, None),
+                Err(AuthError::InvalidToken)
+            );
+        }
+
+        assert_eq!(
+            authorize(
+                &auth(),
+                "/api/events",
+                Some("token=laptop"),
+                &HeaderMap::new(),
+                None
+            ),
+            Err(AuthError::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn test_valid_token() {
+        for token in ["phone-token", "laptop-token"] {
+            assert_eq!(
+                authorize(&auth(), "/api/songs/", None, &bearer(token), None),
+                Ok(())
+            );
+        }
+
+        assert_eq!(
+            authorize(
+                &auth(),
+                "/api/events",
+                Some("lastEventId=3&token=laptop-token"),
+                &HeaderMap::new(),
+                None
+            ),
+            Ok(())
+        );
+        assert_eq!(find_token(&auth(), "laptop-token"), Some("laptop"));
+        assert_eq!(find_token(&auth(), "laptop"), None);
+    }
+
+    #[test]
+    fn test_localhost_exemption() {
+        let auth = Auth {
+            allow_localhost: true,
+            ..auth()
+        };
+
+        assert_eq!(
+            authorize(
+                &auth,
+                "/api/songs/",
+                None,
+                &HeaderMap::new(),
+                Some(ip("127.0.0.1"))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            authorize(
+                &auth,
+                "/api/songs/",
+                None,
+                &HeaderMap::new(),
+                Some(ip("192.168.1.2"))
+            ),
+            Err(AuthError::MissingToken)
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Resolves the address of the client behind a request, only trusting forwarded headers from
+//! configured proxies.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{HeaderMap, StatusCode, request::Parts},
+};
+use ipnet::IpNet;
+
+use crate::config::{Auth, Settings};
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The address of the client that sent the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// Whether the client may skip authentication.
+    pub fn is_exempt(&self, auth: &Auth) -> bool {
+        auth.allow_localhost && self.0.is_loopback()
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    Settings: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .ok_or_else(|| super::internal_error("Server was started without connection info"))?;
+
+        let settings = Settings::from_ref(state);
+
+        Ok(Self(resolve_client_ip(
+            peer.ip(),
+            &parts.headers,
+            &settings.auth.trusted_proxies,
+        )))
+    }
+}
+
+/// Returns the address of the client, using `X-Forwarded-For` only if the peer is a trusted proxy.
+///
+/// The header is read from right to left as every proxy appends the address it received the
+/// request from, the first address that isn't a trusted proxy is the client. Anything to the left
+/// of it was sent by the client and can't be trusted.
+pub fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+    let peer = peer.to_canonical();
+
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let forwarded = headers
+        .get_all(FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    let mut client = peer;
+    for address in forwarded.into_iter().rev() {
+        let Ok(ip) = address.parse::<IpAddr>().map(|ip| ip.to_canonical()) else {
+            break;
+        };
+
+        client = ip;
+
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use test_log::test;
+
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        HeaderMap::from_iter([(
+            FORWARDED_FOR.parse().unwrap(),
+            HeaderValue::from_str(value).unwrap(),
+        )])
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_header() {
+        let proxies = ["10.0.0.0/8".parse().unwrap()];
+
+        assert_eq!(
+            resolve_client_ip(ip("203.0.113.9"), &forwarded_for("127.0.0.1"), &proxies),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            resolve_client_ip(ip("203.0.113.9"), &forwarded_for("127.0.0.1"), &[]),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn test_trusted_proxy_uses_header() {
+        let proxies = ["10.0.0.0/8".parse().unwrap()];
+
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.2"), &forwarded_for("203.0.113.9"), &proxies),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.2"), &HeaderMap::new(), &proxies),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_spoofed_addresses_are_ignored() {
+        let proxies = [
+            "127.0.0.1/32".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+        ];
+
+        // The client prepended a loopback address, the proxy appended the real one.
+        assert_eq!(
+            resolve_client_ip(
+                ip("127.0.0.1"),
+                &forwarded_for("127.0.0.1, 203.0.113.9"),
+                &proxies
+            ),
+            ip("203.0.113.9")
+        );
+
+        // Chained proxies are skipped, but nothing left of the first untrusted address is used.
+        assert_eq!(
+            resolve_client_ip(
+                ip("127.0.0.1"),
+                &forwarded_for("::1, 198.51.100.4, 10.0.0.7"),
+                &proxies
+            ),
+            ip("198.51.100.4")
+        );
+
+        // Garbage stops the search at the last address a trusted proxy reported.
+        assert_eq!(
+            resolve_client_ip(
+                ip("10.0.0.2"),
+                &forwarded_for("127.0.0.1, garbage"),
+                &proxies
+            ),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_ipv4_mapped_addresses() {
+        let proxies = ["10.0.0.0/8".parse().unwrap()];
+
+        assert_eq!(
+            resolve_client_ip(
+                ip("::ffff:10.0.0.2"),
+                &forwarded_for("::ffff:127.0.0.1"),
+                &proxies
+            ),
+            ip("127.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_localhost_exemption() {
+        let auth = Auth {
+            allow_localhost: true,
+            ..Default::default()
+        };
+
+        assert!(ClientIp(ip("127.0.0.1")).is_exempt(&auth));
+        assert!(ClientIp(ip("::1")).is_exempt(&auth));
+        assert!(!ClientIp(ip("192.168.1.2")).is_exempt(&auth));
+        assert!(!ClientIp(ip("127.0.0.1")).is_exempt(&Auth::default()));
+    }
+}
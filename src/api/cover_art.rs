@@ -1,22 +1,174 @@
-use std::{io::Cursor, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::Cursor,
+    path::PathBuf,
+};
 
 use axum::{
     Json, Router,
-    body::Body,
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Path, Query, State},
     http::{self, StatusCode, Uri},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
 };
+use image::{DynamicImage, ImageFormat, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use serde::{Deserialize, Serialize};
 use sqlx::query_scalar;
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
 
 use crate::{
     AppState,
-    metadata::{CoverArt, CoverArtType, get_cover_art},
-    state::Pool,
+    config::Settings,
+    db::{
+        Album, DatabaseError,
+        songs::{self, DatabaseSongError},
+    },
+    metadata::{
+        self, CoverArt, CoverArtType, SongFile, check_cover_art_type, find_external_cover_art,
+        get_cover_art, placeholder_cover_art, remove_cover_art, set_cover_art,
+    },
+    palette::{Palette, extract_palette},
+    state::{Pool, SharedCoverArtCache},
+};
+
+use super::{
+    albums::AlbumKey,
+    maintenance::OutsideMaintenance,
+    songs::{invalidate_cover_art, new_history_snapshot},
+    *,
 };
 
-use super::*;
+/// The largest image that can be embedded into a song.
+const MAX_COVER_ART_SIZE: usize = 20 * 1024 * 1024;
+
+/// The largest width or height cover art can be resized to.
+const MAX_RESIZE_DIMENSION: i64 = 4096;
+
+/// Width and height of placeholders when no size is requested.
+const PLACEHOLDER_SIZE: u32 = 512;
+
+/// Entry of the album's palette in the cover art cache.
+const PALETTE_CACHE_VARIANT: &str = "palette.json";
+
+/// Name of the file embedded front covers are extracted to, next to the tracks of the album.
+const EXTRACTED_COVER_ART_NAME: &str = "cover.jpg";
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub(super) struct CoverArtQuery {
+    /// Largest width or height of the returned image, it's never upscaled.
+    pub(super) size: Option<i64>,
+    /// JPEG quality from 1 to 100, ignored for other formats.
+    pub(super) quality: Option<i64>,
+    /// Whether a missing front cover is replaced with a generated placeholder, defaults to the
+    /// `cover_art.placeholders` setting.
+    pub(super) placeholder: Option<bool>,
+}
+
+/// The format and dimensions cover art is converted to before being returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CoverArtTarget {
+    format: ImageFormat,
+    size: Option<u32>,
+    quality: Option<u8>,
+}
+
+impl CoverArtTarget {
+    fn new(extension: &str, query: &CoverArtQuery) -> Result<Self, (StatusCode, String)> {
+        let format = ImageFormat::from_extension(extension)
+            .filter(|format| format.writing_enabled())
+            .ok_or_else(|| bad_request(format!("Unsupported image format \"{extension}\"")))?;
+
+        let size = query
+            .size
+            .map(|size| match size {
+                1..=MAX_RESIZE_DIMENSION => Ok(size as u32),
+                _ => Err(bad_request(format!(
+                    "Size must be between 1 and {MAX_RESIZE_DIMENSION}"
+                ))),
+            })
+            .transpose()?;
+
+        let quality = query
+            .quality
+            .map(|quality| match quality {
+                1..=100 => Ok(quality as u8),
+                _ => Err(bad_request("Quality must be between 1 and 100")),
+            })
+            .transpose()?;
+
+        Ok(Self {
+            format,
+            size,
+            quality,
+        })
+    }
+
+    /// Name of the cache entry for the cover type converted to this target.
+    fn cache_variant(&self, cover_type: &str) -> String {
+        let mut variant = cover_type.to_string();
+
+        if let Some(size) = self.size {
+            variant.push_str(&format!("-{size}"));
+        }
+
+        if let Some(quality) = self.quality
+            && self.format == ImageFormat::Jpeg
+        {
+            variant.push_str(&format!("-q{quality}"));
+        }
+
+        format!("{variant}.{}", self.format.extensions_str()[0])
+    }
+}
+
+/// Edits applied to uploaded cover art before it's embedded, the crop is applied first.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct CoverArtEdit {
+    /// Left edge of the crop, `0` if not set.
+    x: Option<u32>,
+    /// Top edge of the crop, `0` if not set.
+    y: Option<u32>,
+    /// Width of the crop, required when cropping.
+    width: Option<u32>,
+    /// Height of the crop, required when cropping.
+    height: Option<u32>,
+    /// Largest width or height of the embedded image, it's never upscaled.
+    max_size: Option<i64>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct ExtractOptions {
+    /// Replace the cover art files that already exist.
+    overwrite: bool,
+}
+
+/// Dimensions of the cover art that was embedded.
+#[derive(Serialize, Debug, PartialEq, Eq, TS)]
+pub struct EmbeddedCoverArt {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Cover art files written next to the tracks of an album.
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractedCoverArt {
+    pub paths: Vec<String>,
+}
+
+/// A cover art file embedded into the tracks of an album.
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddedAlbumCoverArt {
+    /// Directory the embedded file was found in.
+    pub directory: String,
+    pub song_ids: Vec<String>,
+}
 
 #[derive(serde::Serialize)]
 struct CoverArtMetadata {
@@ -32,16 +184,55 @@ pub fn router() -> Router<AppState> {
         )
         .route(
             "/api/songs/{song_id}/cover-art/{cover_type}",
-            get(get_song_cover_art),
+            get(get_song_cover_art)
+                .put(set_song_cover_art)
+                .delete(remove_song_cover_art)
+                .layer(DefaultBodyLimit::max(MAX_COVER_ART_SIZE)),
         )
         .route(
             "/api/songs/{song_id}/cover-art/{cover_type}/{index}",
             get(get_song_cover_art),
         )
+        .route(
+            "/api/albums/by-id/{album_id}/cover-art",
+            get(get_album_cover_art_metadata),
+        )
+        .route(
+            "/api/albums/by-id/{album_id}/cover-art/extract",
+            post(extract_album_cover_art),
+        )
+        .route(
+            "/api/albums/by-id/{album_id}/cover-art/embed",
+            post(embed_album_cover_art),
+        )
+        .route(
+            "/api/albums/by-id/{album_id}/cover-art/palette",
+            get(get_album_cover_art_palette),
+        )
+        .route(
+            "/api/albums/by-id/{album_id}/cover-art/{cover_type}",
+            get(get_album_cover_art),
+        )
+        .route(
+            "/api/albums/by-id/{album_id}/cover-art/{cover_type}/{index}",
+            get(get_album_cover_art),
+        )
         .route(
             "/api/albums/{album}/cover-art",
             get(get_album_cover_art_metadata),
         )
+        .route(
+            "/api/albums/{album}/cover-art/extract",
+            post(extract_album_cover_art),
+        )
+        .route(
+            "/api/albums/{album}/cover-art/embed",
+            post(embed_album_cover_art),
+        )
+        .route(
+            "/api/albums/{album}/cover-art/palette",
+            get(get_album_cover_art_palette),
+        )
         .route(
             "/api/albums/{album}/cover-art/{cover_type}",
             get(get_album_cover_art),
@@ -52,9 +243,12 @@ pub fn router() -> Router<AppState> {
         )
 }
 
-async fn get_song_cover_art(
+pub(super) async fn get_song_cover_art(
     State(pool): State<Pool>,
+    State(cache): State<SharedCoverArtCache>,
+    State(settings): State<Settings>,
     Path((song_id, cover_type)): Path<(String, String)>,
+    Query(query): Query<CoverArtQuery>,
     uri: Uri,
 ) -> Result<Response, impl IntoResponse> {
     let cover_type = cover_type
@@ -84,6 +278,8 @@ async fn get_song_cover_art(
         }
     };
 
+    let target = CoverArtTarget::new(ext, &query)?;
+
     let path = match query_scalar!("SELECT path FROM songs WHERE id = ?", song_id)
         .fetch_one(&pool)
         .await
@@ -92,7 +288,8 @@ async fn get_song_cover_art(
         Err(err) => return Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
     };
 
-    let cover_art = get_cover_art(&PathBuf::from(path))
+    let path = PathBuf::from(path);
+    let cover_art = get_cover_art(&path)
         .map_err(internal_error)?
         .into_iter()
         .find(|cover_art| {
@@ -101,8 +298,13 @@ async fn get_song_cover_art(
             cover_type == Ok(cover_art.cover_type)
         });
 
+    let cover_art = match cover_art {
+        Some(cover_art) => Some(cover_art),
+        None => external_cover_art(&path, &cover_type, &settings)?,
+    };
+
     match cover_art {
-        Some(cover_art) => match convert_cover_art(cover_art, ext) {
+        Some(cover_art) => match convert_cover_art(&cover_art.data, &target) {
             Some(cover_art) => Ok(Response::builder()
                 .status(StatusCode::OK)
                 .header(http::header::CONTENT_TYPE, mime.essence_str())
@@ -113,10 +315,114 @@ async fn get_song_cover_art(
                 "Failed to convert cover art".into(),
             )),
         },
+        None if wants_placeholder(&query, &settings, &cover_type) => {
+            let mut connection = pool.acquire().await.map_err(internal_error)?;
+            let song = songs::get_song(&mut connection, &song_id)
+                .await
+                .map_err(internal_error)?;
+            let name = song.album.or(song.title).unwrap_or_default();
+
+            let placeholder = placeholder(&cache, &name, &target).await?;
+
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(http::header::CONTENT_TYPE, mime.essence_str())
+                .body(Body::from(placeholder))
+                .unwrap())
+        }
         None => Err((StatusCode::NOT_FOUND, "Cover art not found".into())),
     }
 }
 
+/// Embeds the uploaded image, optionally cropped and downscaled, see [`CoverArtEdit`].
+async fn set_song_cover_art(
+    _: OutsideMaintenance,
+    State(pool): State<Pool>,
+    State(cache): State<SharedCoverArtCache>,
+    Path((song_id, cover_type)): Path<(String, String)>,
+    Query(edit): Query<CoverArtEdit>,
+    body: Bytes,
+) -> axum::response::Result<Json<EmbeddedCoverArt>> {
+    let cover_type = CoverArtType::try_from(cover_type.as_str()).map_err(bad_request)?;
+
+    if body.is_empty() {
+        return Err(bad_request("No image given").into());
+    }
+
+    let (data, embedded) = spawn_blocking(move || prepare_cover_art(body.to_vec(), &edit))
+        .await
+        .map_err(internal_error)??;
+
+    write_cover_art(&pool, &cache, song_id, cover_type, Some(data)).await?;
+
+    Ok(Json(embedded))
+}
+
+async fn remove_song_cover_art(
+    _: OutsideMaintenance,
+    State(pool): State<Pool>,
+    State(cache): State<SharedCoverArtCache>,
+    Path((song_id, cover_type)): Path<(String, String)>,
+) -> axum::response::Result<StatusCode> {
+    let cover_type = CoverArtType::try_from(cover_type.as_str()).map_err(bad_request)?;
+
+    write_cover_art(&pool, &cache, song_id, cover_type, None).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Replaces or removes a picture of the song, saving the previous state to the metadata history.
+async fn write_cover_art(
+    pool: &Pool,
+    cache: &SharedCoverArtCache,
+    song_id: String,
+    cover_type: CoverArtType,
+    data: Option<Vec<u8>>,
+) -> axum::response::Result<()> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let path = PathBuf::from(song.path);
+
+    spawn_blocking(move || -> Result<(), metadata::Error> {
+        let song = SongFile::open(&path)?;
+        check_cover_art_type(song.tag_type().into(), cover_type)?;
+
+        let previous = get_cover_art(&path)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|cover_art| cover_art.cover_type == cover_type)
+            .map(|cover_art| cover_art.data)
+            .unwrap_or_default();
+
+        let snapshot = new_history_snapshot(&song_id)?;
+        std::fs::write(
+            snapshot.with_extension("json"),
+            serde_json::to_string_pretty(song.metadata()).map_err(std::io::Error::from)?,
+        )?;
+        std::fs::write(
+            snapshot.with_extension(format!("cover-{}", cover_type.as_str())),
+            previous,
+        )?;
+
+        match data {
+            Some(data) => set_cover_art(&path, cover_type, data),
+            None => remove_cover_art(&path, cover_type),
+        }
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(|err| match err {
+        metadata::Error::CoverArt(err) => bad_request(err).into_response(),
+        err => internal_error(err).into_response(),
+    })?;
+
+    invalidate_cover_art(cache, [song.album]).await;
+
+    Ok(())
+}
+
 async fn get_song_cover_art_metadata(
     State(pool): State<Pool>,
     Path(song_id): Path<String>,
@@ -143,9 +449,13 @@ async fn get_song_cover_art_metadata(
     Ok(Json(cover_art))
 }
 
-async fn get_album_cover_art(
+pub(super) async fn get_album_cover_art(
     State(pool): State<Pool>,
-    Path((album, cover_type)): Path<(String, String)>,
+    State(cache): State<SharedCoverArtCache>,
+    State(settings): State<Settings>,
+    album: AlbumKey,
+    Path((_, cover_type)): Path<(String, String)>,
+    Query(query): Query<CoverArtQuery>,
     uri: Uri,
 ) -> Result<Response, impl IntoResponse> {
     let cover_type = cover_type
@@ -156,7 +466,7 @@ async fn get_album_cover_art(
         .to_string();
 
     let ext = match uri.path().split('.').next_back() {
-        Some(ext) => ext,
+        Some(ext) => ext.to_lowercase(),
         None => {
             return Err((
                 StatusCode::BAD_REQUEST,
@@ -165,7 +475,7 @@ async fn get_album_cover_art(
         }
     };
 
-    let mime = match mime_guess::from_ext(ext).first() {
+    let mime = match mime_guess::from_ext(&ext).first() {
         Some(mime) => mime,
         None => {
             return Err((
@@ -175,63 +485,154 @@ async fn get_album_cover_art(
         }
     };
 
-    let paths = match query_scalar!("SELECT path FROM songs WHERE album = ?", album)
-        .fetch_all(&pool)
-        .await
-    {
-        Ok(song) => song,
-        Err(err) => return Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
-    };
+    let target = CoverArtTarget::new(&ext, &query)?;
+    let album = find_album(&pool, album).await?;
+
+    let cover_art = cache
+        .get_or_create(
+            &album.title,
+            &album_cache_variant(&album, &target.cache_variant(&cover_type)),
+            || async {
+                match find_album_cover_art(&settings, &album, &cover_type)? {
+                    Some(art) => match convert_cover_art(&art.data, &target) {
+                        Some(cover_art) => Ok(Some(cover_art)),
+                        None => Err((
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "Failed to convert cover art".to_string(),
+                        )),
+                    },
+                    None => Ok(None),
+                }
+            },
+        )
+        .await?;
+
+    match cover_art {
+        Some(cover_art) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, mime.essence_str())
+            .header(http::header::CACHE_CONTROL, "public, max-age=6000")
+            .body(Body::from(cover_art))
+            .unwrap()),
+        None if wants_placeholder(&query, &settings, &cover_type) => {
+            let placeholder = placeholder(&cache, &album.title, &target).await?;
+
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(http::header::CONTENT_TYPE, mime.essence_str())
+                .body(Body::from(placeholder))
+                .unwrap())
+        }
+        None => Err((StatusCode::NOT_FOUND, "Cover art not found".into())),
+    }
+}
+
+/// Returns the dominant colors of the album's front cover, computed once and cached along with
+/// the converted cover art.
+async fn get_album_cover_art_palette(
+    State(pool): State<Pool>,
+    State(cache): State<SharedCoverArtCache>,
+    State(settings): State<Settings>,
+    album: AlbumKey,
+) -> Result<Json<Palette>, (StatusCode, String)> {
+    let album = find_album(&pool, album).await?;
+
+    let palette = cache
+        .get_or_create(
+            &album.title,
+            &album_cache_variant(&album, PALETTE_CACHE_VARIANT),
+            || async {
+                let Some(art) = find_album_cover_art(&settings, &album, "front")? else {
+                    return Ok(None);
+                };
 
-    let mut cover_art = None;
+                let palette = spawn_blocking(move || {
+                    image::load_from_memory(&art.data)
+                        .ok()
+                        .and_then(|image| extract_palette(&image))
+                })
+                .await
+                .map_err(internal_error)?;
 
-    for path in paths {
-        let art = get_cover_art(&PathBuf::from(path))
+                palette
+                    .map(|palette| serde_json::to_vec(&palette))
+                    .transpose()
+                    .map_err(internal_error)
+            },
+        )
+        .await?;
+
+    match palette {
+        Some(palette) => Ok(Json(
+            serde_json::from_slice(&palette).map_err(internal_error)?,
+        )),
+        None => Err((StatusCode::NOT_FOUND, "Cover art not found".into())),
+    }
+}
+
+/// Looks up the album, `404 Not Found` if it has no tracks.
+async fn find_album(pool: &Pool, album: AlbumKey) -> Result<Album, (StatusCode, String)> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+
+    match album.get(&mut connection).await {
+        Ok(album) => Ok(album),
+        Err(DatabaseError::Song(DatabaseSongError::AlbumNotFound)) => {
+            Err(not_found("Album not found"))
+        }
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+/// Cached entries are stored by title so editing a song invalidates them, the id keeps albums of
+/// different artists sharing a title apart.
+fn album_cache_variant(album: &Album, variant: &str) -> String {
+    format!("{}-{variant}", album.id)
+}
+
+/// Finds the album's cover art of the type, embedded in any of its songs or, failing that, in an
+/// image next to them.
+fn find_album_cover_art(
+    settings: &Settings,
+    album: &Album,
+    cover_type: &str,
+) -> Result<Option<CoverArt>, (StatusCode, String)> {
+    let paths = album
+        .tracks
+        .iter()
+        .map(|track| PathBuf::from(&track.path))
+        .collect::<Vec<_>>();
+
+    for path in &paths {
+        let art = get_cover_art(path)
             .map_err(internal_error)?
             .into_iter()
-            .find(|cover_art| {
-                let cover_type = CoverArtType::try_from(cover_type.as_str());
+            .find(|cover_art| CoverArtType::try_from(cover_type) == Ok(cover_art.cover_type));
 
-                cover_type == Ok(cover_art.cover_type)
-            });
-
-        if let Some(art) = art {
-            cover_art = Some(art);
-            break;
+        if art.is_some() {
+            return Ok(art);
         }
     }
 
-    match cover_art {
-        Some(cover_art) => match convert_cover_art(cover_art, ext) {
-            Some(cover_art) => Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header(http::header::CONTENT_TYPE, mime.essence_str())
-                .header(http::header::CACHE_CONTROL, "public, max-age=6000")
-                .body(Body::from(cover_art))
-                .unwrap()),
-            None => Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to convert cover art".into(),
-            )),
-        },
-        None => Err((StatusCode::NOT_FOUND, "Cover art not found".into())),
+    // Files next to the tracks are only looked at once none of them has art embedded.
+    let mut directories = HashSet::new();
+    for path in &paths {
+        if directories.insert(path.parent())
+            && let Some(art) = external_cover_art(path, cover_type, settings)?
+        {
+            return Ok(Some(art));
+        }
     }
+
+    Ok(None)
 }
 
 async fn get_album_cover_art_metadata(
     State(pool): State<Pool>,
-    Path(album): Path<String>,
+    album: AlbumKey,
 ) -> Result<Json<Vec<CoverArtMetadata>>, impl IntoResponse> {
-    let path = match query_scalar!("SELECT path FROM songs WHERE album = ?", album)
-        .fetch_one(&pool)
-        .await
-        .map_err(internal_error)
-    {
-        Ok(song) => song,
-        Err(err) => return Err(err),
-    };
+    let album = find_album(&pool, album).await?;
 
-    let cover_art = get_cover_art(&PathBuf::from(path))
+    let cover_art = get_cover_art(&PathBuf::from(&album.tracks[0].path))
         .map_err(internal_error)?
         .into_iter()
         .enumerate()
@@ -244,26 +645,325 @@ async fn get_album_cover_art_metadata(
     Ok(Json(cover_art))
 }
 
-fn convert_cover_art(cover_art: CoverArt, extension: &str) -> Option<Vec<u8>> {
-    use image::{ImageFormat, load_from_memory};
+/// Writes the first embedded front cover of the album's tracks to `cover.jpg` in every directory
+/// the tracks are stored in, converting it to JPEG if needed.
+async fn extract_album_cover_art(
+    _: OutsideMaintenance,
+    State(pool): State<Pool>,
+    album: AlbumKey,
+    Query(options): Query<ExtractOptions>,
+) -> axum::response::Result<Json<ExtractedCoverArt>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let tracks = album
+        .get(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .tracks;
+    let paths = tracks
+        .into_iter()
+        .map(|track| PathBuf::from(track.path))
+        .collect();
+
+    let paths = spawn_blocking(move || extract_cover_art(paths, options.overwrite))
+        .await
+        .map_err(internal_error)??;
+
+    Ok(Json(ExtractedCoverArt { paths }))
+}
+
+fn extract_cover_art(
+    paths: Vec<PathBuf>,
+    overwrite: bool,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let cover = paths
+        .iter()
+        .find_map(|path| {
+            get_cover_art(path)
+                .ok()?
+                .into_iter()
+                .find(|cover_art| cover_art.cover_type == CoverArtType::Front)
+        })
+        .ok_or_else(|| not_found("None of the tracks have an embedded front cover"))?;
+
+    let data = match image::guess_format(&cover.data) {
+        Ok(ImageFormat::Jpeg) => cover.data,
+        _ => {
+            let image = image::load_from_memory(&cover.data).map_err(internal_error)?;
+            let target = CoverArtTarget {
+                format: ImageFormat::Jpeg,
+                size: None,
+                quality: None,
+            };
+
+            encode_cover_art(image, &target)
+                .ok_or_else(|| internal_error("Failed to encode cover art"))?
+        }
+    };
+
+    let targets = paths
+        .iter()
+        .filter_map(|path| path.parent())
+        .map(|directory| directory.join(EXTRACTED_COVER_ART_NAME))
+        .collect::<BTreeSet<_>>();
+
+    if !overwrite && let Some(existing) = targets.iter().find(|target| target.exists()) {
+        return Err(conflict(format!("{} already exists", existing.display())));
+    }
+
+    for target in &targets {
+        std::fs::write(target, &data).map_err(internal_error)?;
+    }
+
+    Ok(targets
+        .into_iter()
+        .map(|target| target.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// Embeds the front cover file next to the album's tracks into all of them, see the
+/// `cover_art.front_files` setting for the files looked for.
+async fn embed_album_cover_art(
+    _: OutsideMaintenance,
+    State(pool): State<Pool>,
+    State(cache): State<SharedCoverArtCache>,
+    State(settings): State<Settings>,
+    album: AlbumKey,
+) -> axum::response::Result<Json<EmbeddedAlbumCoverArt>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let tracks = album
+        .get(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .tracks;
+    drop(connection);
 
-    let format = ImageFormat::from_extension(extension)?;
-    match load_from_memory(&cover_art.data) {
-        Ok(cover) => {
-            let mut buffer: Vec<u8> = Vec::new();
+    let paths = tracks
+        .iter()
+        .map(|track| PathBuf::from(&track.path))
+        .collect::<Vec<_>>();
+    let names = settings.cover_art.front_files;
 
-            if cover
-                .to_rgb8()
-                .write_to(&mut Cursor::new(&mut buffer), format)
-                .is_ok()
-            {
-                Some(buffer)
-            } else {
-                None
+    let (directory, cover) = spawn_blocking(move || -> std::io::Result<_> {
+        let mut directories = HashSet::new();
+        for path in &paths {
+            if !directories.insert(path.parent()) {
+                continue;
             }
+
+            if let Some(cover) = find_external_cover_art(path, CoverArtType::Front, &names)? {
+                return Ok(Some((path.parent().map(PathBuf::from), cover)));
+            }
+        }
+
+        Ok(None)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?
+    .ok_or_else(|| not_found("No cover art file found next to the tracks"))?;
+
+    // Tracks of a cue sheet share their file, which only has to be written once.
+    let mut written = HashSet::new();
+    let mut song_ids = Vec::new();
+    for track in tracks {
+        if written.insert(track.path.clone()) {
+            write_cover_art(
+                &pool,
+                &cache,
+                track.id.clone(),
+                CoverArtType::Front,
+                Some(cover.data.clone()),
+            )
+            .await?;
         }
+
+        song_ids.push(track.id);
+    }
+
+    Ok(Json(EmbeddedAlbumCoverArt {
+        directory: directory
+            .map(|directory| directory.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        song_ids,
+    }))
+}
+
+/// Looks for an image next to the song standing in for its missing cover art of the type, see the
+/// `cover_art` settings.
+fn external_cover_art(
+    path: &std::path::Path,
+    cover_type: &str,
+    settings: &Settings,
+) -> Result<Option<CoverArt>, (StatusCode, String)> {
+    let (cover_type, names) = match CoverArtType::try_from(cover_type) {
+        Ok(CoverArtType::Front) => (CoverArtType::Front, &settings.cover_art.front_files),
+        Ok(CoverArtType::Back) => (CoverArtType::Back, &settings.cover_art.back_files),
+        _ => return Ok(None),
+    };
+
+    find_external_cover_art(path, cover_type, names).map_err(internal_error)
+}
+
+/// Placeholders only stand in for front covers, a missing back cover is expected.
+fn wants_placeholder(query: &CoverArtQuery, settings: &Settings, cover_type: &str) -> bool {
+    query.placeholder.unwrap_or(settings.cover_art.placeholders)
+        && CoverArtType::try_from(cover_type) == Ok(CoverArtType::Front)
+}
+
+/// Returns the placeholder of the album converted to the target, generating it on a cache miss.
+///
+/// Placeholders are cached next to the album's real cover art, so they're removed along with it
+/// once the album's art changes.
+async fn placeholder(
+    cache: &SharedCoverArtCache,
+    name: &str,
+    target: &CoverArtTarget,
+) -> Result<Vec<u8>, (StatusCode, String)> {
+    let placeholder = cache
+        .get_or_create(name, &target.cache_variant("placeholder"), || async {
+            let name = name.to_string();
+            let target = *target;
+
+            spawn_blocking(move || {
+                let size = target.size.unwrap_or(PLACEHOLDER_SIZE);
+                let placeholder = DynamicImage::ImageRgb8(placeholder_cover_art(&name, size));
+
+                encode_cover_art(placeholder, &target)
+            })
+            .await
+            .map_err(internal_error)
+        })
+        .await?;
+
+    placeholder.ok_or_else(|| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to generate placeholder".to_string(),
+        )
+    })
+}
+
+/// Crops and downscales cover art before it's embedded, returning the image to embed along with
+/// its dimensions.
+///
+/// Images that don't need to be edited are embedded as they were given instead of re-encoding
+/// them, edited ones keep their format if it can be written.
+fn prepare_cover_art(
+    data: Vec<u8>,
+    edit: &CoverArtEdit,
+) -> Result<(Vec<u8>, EmbeddedCoverArt), (StatusCode, String)> {
+    let max_size = edit
+        .max_size
+        .map(|size| match size {
+            1..=MAX_RESIZE_DIMENSION => Ok(size as u32),
+            _ => Err(bad_request(format!(
+                "Max size must be between 1 and {MAX_RESIZE_DIMENSION}"
+            ))),
+        })
+        .transpose()?;
+
+    let crop = match (edit.x, edit.y, edit.width, edit.height) {
+        (None, None, None, None) => None,
+        (x, y, Some(width @ 1..), Some(height @ 1..)) => {
+            Some((x.unwrap_or(0), y.unwrap_or(0), width, height))
+        }
+        _ => {
+            return Err(bad_request(
+                "Cropping needs a width and height larger than 0",
+            ));
+        }
+    };
+
+    let format = image::guess_format(&data).map_err(bad_request)?;
+    let mut cover = image::load_from_memory_with_format(&data, format).map_err(bad_request)?;
+    let mut edited = false;
+
+    if let Some((x, y, width, height)) = crop {
+        if u64::from(x) + u64::from(width) > u64::from(cover.width())
+            || u64::from(y) + u64::from(height) > u64::from(cover.height())
+        {
+            return Err(bad_request(format!(
+                "Crop is outside of the {}x{} image",
+                cover.width(),
+                cover.height()
+            )));
+        }
+
+        cover = cover.crop_imm(x, y, width, height);
+        edited = true;
+    }
+
+    if let Some(size) = max_size
+        && size < cover.width().max(cover.height())
+    {
+        cover = cover.resize(size, size, FilterType::Lanczos3);
+        edited = true;
+    }
+
+    let embedded = EmbeddedCoverArt {
+        width: cover.width(),
+        height: cover.height(),
+    };
+
+    if !edited {
+        return Ok((data, embedded));
+    }
+
+    let target = CoverArtTarget {
+        format: if format.writing_enabled() {
+            format
+        } else {
+            ImageFormat::Jpeg
+        },
+        size: None,
+        quality: None,
+    };
+
+    let data = encode_cover_art(cover, &target).ok_or_else(|| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to encode cover art".to_string(),
+        )
+    })?;
+
+    Ok((data, embedded))
+}
+
+/// Converts the image to the target, downscaling it if it's larger than the requested size.
+fn convert_cover_art(data: &[u8], target: &CoverArtTarget) -> Option<Vec<u8>> {
+    let cover = match image::load_from_memory(data) {
+        Ok(cover) => cover,
         Err(err) => {
             tracing::error!("{}", err);
+            return None;
+        }
+    };
+
+    let cover = match target.size {
+        Some(size) if size < cover.width().max(cover.height()) => {
+            cover.resize(size, size, FilterType::Lanczos3)
+        }
+        _ => cover,
+    };
+
+    encode_cover_art(cover, target)
+}
+
+fn encode_cover_art(cover: DynamicImage, target: &CoverArtTarget) -> Option<Vec<u8>> {
+    let cover = DynamicImage::ImageRgb8(cover.to_rgb8());
+    let mut buffer: Vec<u8> = Vec::new();
+
+    let result = match (target.format, target.quality) {
+        (ImageFormat::Jpeg, Some(quality)) => {
+            cover.write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality))
+        }
+        (format, _) => cover.write_to(&mut Cursor::new(&mut buffer), format),
+    };
+
+    match result {
+        Ok(()) => Some(buffer),
+        Err(err) => {
+            tracing::error!("Failed to encode cover art: {err}");
             None
         }
     }
@@ -276,3 +976,216 @@ fn map_type_to_path(cover_type: &CoverArtType, index: usize) -> String {
         CoverArtType::Other => format!("/other/{index}.jpg"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, RgbImage};
+    use test_log::test;
+
+    use super::*;
+
+    fn image(width: u32, height: u32) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+            .unwrap();
+
+        buffer
+    }
+
+    fn target(extension: &str, size: Option<i64>, quality: Option<i64>) -> CoverArtTarget {
+        CoverArtTarget::new(
+            extension,
+            &CoverArtQuery {
+                size,
+                quality,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resize_preserves_aspect_ratio() {
+        let data = image(400, 200);
+
+        let cover = convert_cover_art(&data, &target("jpg", Some(100), Some(80))).unwrap();
+        assert_eq!(
+            image::load_from_memory(&cover).unwrap().dimensions(),
+            (100, 50)
+        );
+
+        let cover = convert_cover_art(&data, &target("png", Some(50), None)).unwrap();
+        assert_eq!(
+            image::load_from_memory(&cover).unwrap().dimensions(),
+            (50, 25)
+        );
+    }
+
+    #[test]
+    fn test_resize_never_upscales() {
+        let data = image(400, 200);
+
+        for size in [Some(400), Some(1024), None] {
+            let cover = convert_cover_art(&data, &target("jpg", size, None)).unwrap();
+            assert_eq!(
+                image::load_from_memory(&cover).unwrap().dimensions(),
+                (400, 200)
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_targets_are_rejected() {
+        for (size, quality) in [
+            (Some(0), None),
+            (Some(-128), None),
+            (Some(1_000_000), None),
+            (None, Some(0)),
+            (None, Some(101)),
+        ] {
+            let result = CoverArtTarget::new(
+                "jpg",
+                &CoverArtQuery {
+                    size,
+                    quality,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+
+        let result = CoverArtTarget::new("txt", &CoverArtQuery::default());
+        assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_prepare_cover_art() {
+        let data = image(400, 300);
+        let edit = |x, y, width, height, max_size| CoverArtEdit {
+            x,
+            y,
+            width,
+            height,
+            max_size,
+        };
+
+        // Covers that don't need any edits are embedded as they are.
+        let (cover, embedded) = prepare_cover_art(data.clone(), &CoverArtEdit::default()).unwrap();
+        assert_eq!(cover, data);
+        assert_eq!(
+            embedded,
+            EmbeddedCoverArt {
+                width: 400,
+                height: 300
+            }
+        );
+
+        let (cover, embedded) = prepare_cover_art(
+            data.clone(),
+            &edit(Some(50), None, Some(300), Some(300), None),
+        )
+        .unwrap();
+        assert_eq!(image::guess_format(&cover).unwrap(), ImageFormat::Png);
+        assert_eq!(
+            image::load_from_memory(&cover).unwrap().dimensions(),
+            (300, 300)
+        );
+        assert_eq!((embedded.width, embedded.height), (300, 300));
+
+        let (cover, embedded) = prepare_cover_art(
+            data.clone(),
+            &edit(None, None, Some(200), Some(100), Some(50)),
+        )
+        .unwrap();
+        assert_eq!(
+            image::load_from_memory(&cover).unwrap().dimensions(),
+            (50, 25)
+        );
+        assert_eq!((embedded.width, embedded.height), (50, 25));
+    }
+
+    #[test]
+    fn test_invalid_cover_art_edits_are_rejected() {
+        let data = image(400, 300);
+
+        for edit in [
+            CoverArtEdit {
+                x: Some(200),
+                width: Some(201),
+                height: Some(100),
+                ..Default::default()
+            },
+            CoverArtEdit {
+                y: Some(1),
+                width: Some(400),
+                height: Some(300),
+                ..Default::default()
+            },
+            CoverArtEdit {
+                x: Some(10),
+                y: Some(10),
+                ..Default::default()
+            },
+            CoverArtEdit {
+                width: Some(0),
+                height: Some(100),
+                ..Default::default()
+            },
+            CoverArtEdit {
+                max_size: Some(0),
+                ..Default::default()
+            },
+        ] {
+            let result = prepare_cover_art(data.clone(), &edit);
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+
+        let result = prepare_cover_art(b"not an image".to_vec(), &CoverArtEdit::default());
+        assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_cache_variant() {
+        assert_eq!(
+            target("jpg", None, None).cache_variant("front"),
+            "front.jpg"
+        );
+        assert_eq!(
+            target("jpeg", Some(256), Some(80)).cache_variant("front"),
+            "front-256-q80.jpg"
+        );
+        assert_eq!(
+            target("png", Some(128), Some(80)).cache_variant("back"),
+            "back-128.png"
+        );
+    }
+
+    #[test]
+    fn test_placeholders_replace_missing_front_covers() {
+        let mut settings = Settings::default();
+        let query = |placeholder| CoverArtQuery {
+            placeholder,
+            ..Default::default()
+        };
+
+        assert!(!wants_placeholder(&query(None), &settings, "front"));
+        assert!(wants_placeholder(&query(Some(true)), &settings, "front"));
+        assert!(!wants_placeholder(&query(Some(true)), &settings, "back"));
+
+        settings.cover_art.placeholders = true;
+        assert!(wants_placeholder(&query(None), &settings, "front"));
+        assert!(!wants_placeholder(&query(Some(false)), &settings, "front"));
+    }
+
+    #[test]
+    fn test_placeholder_matches_target() {
+        let placeholder = DynamicImage::ImageRgb8(placeholder_cover_art("Fixture Album", 128));
+        let cover = encode_cover_art(placeholder, &target("png", Some(128), None)).unwrap();
+
+        assert_eq!(
+            image::load_from_memory(&cover).unwrap().dimensions(),
+            (128, 128)
+        );
+    }
+}
@@ -1,42 +1,100 @@
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, path::PathBuf};
 
-use fs_extra::dir::get_size;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use axum::{
     Json, Router,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Result},
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
 };
 
 use sysinfo::Disks;
+use time::OffsetDateTime;
+use tokio::task::spawn_blocking;
 use ts_rs::TS;
 
 use crate::{
-    db::{Directory as DirectoryDB, NewDirectory, directories},
-    state::{AppState, Pool},
+    config::Settings,
+    db::{Directory as DirectoryDB, NewDirectory, Page, UpdatedDirectory, directories},
+    events::{AppEvent, AppEventKind},
+    jobs::is_song_file,
+    state::{
+        AppState, DirectorySize, Pool, SharedDirectorySizes, SharedSongFileTypes,
+        disk_space::find_disk,
+        job::{JobParameters, JobStateId},
+    },
 };
 
 use super::*;
 
 #[derive(Serialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(rename = "Directory", export)]
-struct DirectoryResponse {
+#[ts(rename = "Directory")]
+pub struct DirectoryResponse {
     /// The name of the directory.
     name: String,
     /// The path of the directory.
     path: String,
     /// The display name of the directory, only used in the UI.
     display_name: Option<String>,
-    /// The size of the directory takes up in bytes.
+    /// The size of the directory takes up in bytes, as of `size_computed_at`. Not set until it was
+    /// computed for the first time.
     path_size: Option<u64>,
+    /// When the size was computed, outdated sizes are computed again in the background.
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[ts(type = "Date | null")]
+    size_computed_at: Option<OffsetDateTime>,
     /// The free space of the hard drive the directory is stored on.
     free_space: Option<u64>,
     /// The total space of the hard drive the directory is stored on.
     total_space: Option<u64>,
+    /// Whether the hard drive the directory is stored on is below the free space thresholds of
+    /// the `storage` settings.
+    low_space: bool,
+}
+
+/// An entry of a folder on the server's file system.
+#[derive(Serialize, TS, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderEntry {
+    name: String,
+    is_dir: bool,
+    /// The size of the file in bytes, not set for directories.
+    size: Option<u64>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[ts(type = "Date")]
+    modified: Option<OffsetDateTime>,
+    /// Whether the directory directly contains songs, subdirectories aren't looked at.
+    contains_audio: bool,
+}
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DirectoriesQuery {
+    /// Compute the sizes of the directories before responding, instead of returning the last
+    /// computed ones.
+    refresh: bool,
+}
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UpdateDirectoryQuery {
+    /// Check that a sample of the songs exists at the new path before moving the directory, so a
+    /// mistyped path can't orphan every song of it.
+    verify: bool,
+}
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FolderQuery {
+    /// Maximum amount of entries to return, returns every entry if not set.
+    limit: Option<u32>,
+    /// Amount of entries to skip.
+    offset: Option<u32>,
+    /// Only returns the entries with a name containing this, ignoring case.
+    filter: Option<String>,
 }
 
 pub fn router() -> Router<AppState> {
@@ -48,72 +106,250 @@ pub fn router() -> Router<AppState> {
         )
         .route("/api/directories/", post(add_directory))
         .route("/api/directories/{name}", delete(remove_directory))
+        .route("/api/directories/{name}", put(update_directory))
+        .route("/api/directories/{name}/scan", post(scan_directory))
 }
 
 async fn add_directory(
     State(app): State<AppState>,
+    State(settings): State<Settings>,
     Json(new_directory): Json<NewDirectory>,
 ) -> Result<Json<DirectoryResponse>> {
     let mut connection = app.pool.acquire().await.map_err(internal_error)?;
 
-    let DirectoryDB {
-        name,
-        path,
-        display_name,
-    } = directories::add_directory(&mut connection, new_directory)
+    let (
+        DirectoryDB {
+            name,
+            path,
+            display_name,
+        },
+        absorbed,
+    ) = directories::add_directory(&mut connection, new_directory)
         .await
         .map_err(IntoResponse::into_response)?;
 
+    // The new directory is watched instead, which covers the absorbed ones.
+    for directory in absorbed {
+        let absorbed_path = std::path::Path::new(&directory.path);
+        app.directory_sizes.remove(absorbed_path);
+
+        if let Err(err) = app.directory_watcher.unwatch(absorbed_path).await {
+            tracing::warn!("Failed to stop watching \"{}\": {err}", directory.path);
+        }
+    }
+
     let disks = Disks::new_with_refreshed_list();
-    let disk = disks
-        .iter()
-        .find(|disk| path.contains(&disk.mount_point().to_string_lossy().to_string()));
+    let disk = find_disk(&disks, std::path::Path::new(&path));
+
+    // Only computed in the background, walking a large library can take a while.
+    app.directory_sizes.get(std::path::Path::new(&path));
+
+    if let Err(err) = app
+        .directory_watcher
+        .watch(std::path::Path::new(&path))
+        .await
+    {
+        tracing::warn!("Failed to watch \"{path}\": {err}");
+    }
+
+    if let Err(err) = app.directory_cache.reload(&app.pool).await {
+        tracing::error!("Failed to reload the directory cache: {err}");
+    }
+
+    app.events
+        .publish(AppEvent::new(AppEventKind::DirectoryAdded, path.clone()));
 
-    app.job_manager.queue("scan-songs", false, false).await?;
+    // Only the new directory is scanned, the others haven't changed.
+    app.job_manager
+        .queue("scan-songs", scan_parameters(&name), false, false)
+        .await?;
     Ok(Json(DirectoryResponse {
         free_space: disk.map(|disk| disk.available_space()),
         total_space: disk.map(|disk| disk.total_space()),
-        path_size: get_size(&path).ok(),
+        low_space: disk.is_some_and(|disk| {
+            settings
+                .storage
+                .is_low_space(disk.available_space(), disk.total_space())
+        }),
+        path_size: None,
+        size_computed_at: None,
         display_name,
         path,
         name,
     }))
 }
 
+/// Renames the directory or moves it to another path, e.g. after the library was copied to a new
+/// drive. The paths of its songs are moved along with it, so they don't have to be scanned again.
+async fn update_directory(
+    State(app): State<AppState>,
+    State(settings): State<Settings>,
+    Path(name): Path<String>,
+    Query(query): Query<UpdateDirectoryQuery>,
+    Json(update): Json<UpdatedDirectory>,
+) -> Result<Json<DirectoryResponse>> {
+    let mut connection = app.pool.acquire().await.map_err(internal_error)?;
+    let previous = directories::get_directory(&mut *connection, &name)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let directory = directories::update_directory(&mut connection, &name, update, query.verify)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    drop(connection);
+
+    let path = std::path::Path::new(&directory.path);
+
+    if directory.path != previous.path {
+        let previous_path = std::path::Path::new(&previous.path);
+        app.directory_sizes.remove(previous_path);
+
+        if let Err(err) = app.directory_watcher.unwatch(previous_path).await {
+            tracing::warn!("Failed to stop watching \"{}\": {err}", previous.path);
+        }
+
+        if let Err(err) = app.directory_watcher.watch(path).await {
+            tracing::warn!("Failed to watch \"{}\": {err}", directory.path);
+        }
+
+        if let Err(err) = app.directory_cache.reload(&app.pool).await {
+            tracing::error!("Failed to reload the directory cache: {err}");
+        }
+    }
+
+    let disks = Disks::new_with_refreshed_list();
+    let disk = find_disk(&disks, path);
+    let size = app.directory_sizes.get(path);
+
+    app.events.publish(AppEvent::new(
+        AppEventKind::DirectoryUpdated,
+        directory.path.clone(),
+    ));
+
+    Ok(Json(DirectoryResponse {
+        free_space: disk.map(|disk| disk.available_space()),
+        total_space: disk.map(|disk| disk.total_space()),
+        low_space: disk.is_some_and(|disk| {
+            settings
+                .storage
+                .is_low_space(disk.available_space(), disk.total_space())
+        }),
+        path_size: size.map(|size| size.bytes),
+        size_computed_at: size.map(|size| size.computed_at),
+        display_name: directory.display_name,
+        path: directory.path,
+        name: directory.name,
+    }))
+}
+
 async fn remove_directory(
-    State(pool): State<Pool>,
+    State(app): State<AppState>,
     Path(name): Path<String>,
 ) -> Result<StatusCode> {
-    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let mut connection = app.pool.acquire().await.map_err(internal_error)?;
+    let directory = directories::get_directory(&mut *connection, &name)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
     directories::remove_directory(&mut connection, name)
         .await
         .map_err(IntoResponse::into_response)?;
 
+    app.directory_sizes
+        .remove(std::path::Path::new(&directory.path));
+
+    if let Err(err) = app
+        .directory_watcher
+        .unwatch(std::path::Path::new(&directory.path))
+        .await
+    {
+        tracing::warn!("Failed to stop watching \"{}\": {err}", directory.path);
+    }
+
+    if let Err(err) = app.directory_cache.reload(&app.pool).await {
+        tracing::error!("Failed to reload the directory cache: {err}");
+    }
+
+    app.events.publish(AppEvent::new(
+        AppEventKind::DirectoryRemoved,
+        directory.path,
+    ));
+
     Ok(StatusCode::OK)
 }
 
-async fn get_directories(State(pool): State<Pool>) -> Result<Json<Vec<DirectoryResponse>>> {
+/// Queues a scan of the songs of this directory only.
+async fn scan_directory(
+    State(app): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<JobStateId>> {
+    let mut connection = app.pool.acquire().await.map_err(internal_error)?;
+    directories::get_directory(&mut *connection, &name)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let handler = app
+        .job_manager
+        .queue("scan-songs", scan_parameters(&name), false, true)
+        .await?;
+
+    Ok(Json(handler.id()))
+}
+
+pub(super) fn scan_parameters(directory: &str) -> JobParameters {
+    JobParameters {
+        directory: Some(directory.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Lists the directories with the space they and their drive take up.
+///
+/// Sizes are returned as they were last computed and computed again in the background once
+/// they're outdated, unless `refresh` is set.
+async fn get_directories(
+    State(pool): State<Pool>,
+    State(settings): State<Settings>,
+    State(sizes): State<SharedDirectorySizes>,
+    Query(query): Query<DirectoriesQuery>,
+) -> Result<Json<Vec<DirectoryResponse>>> {
     let mut connection = pool.acquire().await.map_err(internal_error)?;
 
-    let disks = Disks::new_with_refreshed_list();
     let directories = directories::get_directories(&mut connection)
         .await
         .map_err(|err| err.into_response())?;
+    drop(connection);
+
+    let directory_sizes: Vec<Option<DirectorySize>> = if query.refresh {
+        futures::future::join_all(
+            directories
+                .iter()
+                .map(|directory| sizes.refresh(std::path::Path::new(&directory.path))),
+        )
+        .await
+    } else {
+        directories
+            .iter()
+            .map(|directory| sizes.get(std::path::Path::new(&directory.path)))
+            .collect()
+    };
 
+    let disks = Disks::new_with_refreshed_list();
     let directories_with_space: Vec<DirectoryResponse> = directories
         .into_iter()
-        .filter_map(|directory| {
-            let disk = disks.iter().find(|disk| {
-                directory
-                    .path
-                    .contains(&disk.mount_point().to_string_lossy().to_string())
-            });
+        .zip(directory_sizes)
+        .filter_map(|(directory, size)| {
+            let disk = find_disk(&disks, std::path::Path::new(&directory.path));
 
             disk.map(|disk| DirectoryResponse {
                 name: directory.name,
-                path_size: get_size(&directory.path).ok(),
+                path_size: size.map(|size| size.bytes),
+                size_computed_at: size.map(|size| size.computed_at),
                 free_space: Some(disk.available_space()),
                 total_space: Some(disk.total_space()),
+                low_space: settings
+                    .storage
+                    .is_low_space(disk.available_space(), disk.total_space()),
                 display_name: directory.display_name,
                 path: directory.path,
             })
@@ -123,12 +359,21 @@ async fn get_directories(State(pool): State<Pool>) -> Result<Json<Vec<DirectoryR
     Ok(Json(directories_with_space))
 }
 
-async fn get_directory_folders(path: Path<String>) -> Result<Json<BTreeSet<String>>> {
-    if path.to_string().trim().is_empty() {
+/// Lists the entries of a folder on the server, directories first and then by name.
+///
+/// Only the directories leading to an allowed path are listed for its ancestors, everything else
+/// outside of them is forbidden.
+async fn get_directory_folders(
+    State(settings): State<Settings>,
+    State(file_types): State<SharedSongFileTypes>,
+    Path(path): Path<String>,
+    Query(query): Query<FolderQuery>,
+) -> Result<Json<Page<FolderEntry>>> {
+    if path.trim().is_empty() {
         return Err(bad_request("Path cannot be empty").into());
     }
 
-    let path = std::path::PathBuf::from(&path.to_string());
+    let path = PathBuf::from(&path);
 
     if !path.exists() {
         return Err(bad_request("Path does not exist").into());
@@ -142,18 +387,118 @@ async fn get_directory_folders(path: Path<String>) -> Result<Json<BTreeSet<Strin
         return Err(bad_request("Path must be absolute").into());
     }
 
-    let directories = std::fs::read_dir(path)
+    let Some(scope) = browse_scope(&path, &settings.browse.allowed_paths) else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("Browsing \"{}\" is not allowed", path.display()),
+        )
+            .into());
+    };
+
+    let file_types = file_types.get();
+    let mut entries = spawn_blocking(move || list_folder(&path, &scope, &file_types))
+        .await
         .map_err(internal_error)?
-        .filter_map(|entry| {
-            if let Ok(entry) = entry
-                && let Ok(metadata) = std::fs::metadata(entry.path())
-                && metadata.is_dir()
-            {
-                return Some(entry.file_name().to_string_lossy().to_string());
+        .map_err(internal_error)?;
+
+    if let Some(filter) = query.filter.map(|filter| filter.to_lowercase()) {
+        entries.retain(|entry| entry.name.to_lowercase().contains(&filter));
+    }
+
+    let total = entries.len() as i64;
+    let items = entries
+        .into_iter()
+        .skip(query.offset.unwrap_or(0) as usize)
+        .take(query.limit.map_or(usize::MAX, |limit| limit as usize))
+        .collect();
+
+    Ok(Json(Page { items, total }))
+}
+
+/// Where a folder is relative to the directories that can be browsed.
+enum BrowseScope {
+    /// Inside an allowed directory, or browsing isn't restricted.
+    Allowed,
+    /// On the way to the allowed directories, only the entries leading to them are listed.
+    Ancestor(Vec<PathBuf>),
+}
+
+/// Returns how much of the folder can be listed, nothing if it can't be browsed at all.
+fn browse_scope(path: &std::path::Path, allowed_paths: &[PathBuf]) -> Option<BrowseScope> {
+    if allowed_paths.is_empty() {
+        return Some(BrowseScope::Allowed);
+    }
+
+    let path = canonicalize(path);
+    let allowed_paths = allowed_paths
+        .iter()
+        .map(|allowed| canonicalize(allowed))
+        .collect::<Vec<_>>();
+
+    if allowed_paths
+        .iter()
+        .any(|allowed| path.starts_with(allowed))
+    {
+        return Some(BrowseScope::Allowed);
+    }
+
+    let below = allowed_paths
+        .into_iter()
+        .filter(|allowed| allowed.starts_with(&path))
+        .collect::<Vec<_>>();
+
+    (!below.is_empty()).then_some(BrowseScope::Ancestor(below))
+}
+
+/// Reads the entries of the folder in the scope, sorted with directories first.
+///
+/// Only the immediate children of directories are looked at to tell whether they contain audio,
+/// so large trees don't slow the listing down.
+fn list_folder(
+    path: &std::path::Path,
+    scope: &BrowseScope,
+    file_types: &BTreeSet<String>,
+) -> std::io::Result<Vec<FolderEntry>> {
+    let mut entries = std::fs::read_dir(path)?
+        .filter_map(Result::ok)
+        .filter(|entry| match scope {
+            BrowseScope::Allowed => true,
+            BrowseScope::Ancestor(allowed_paths) => {
+                let entry = canonicalize(&entry.path());
+                allowed_paths
+                    .iter()
+                    .any(|allowed| allowed.starts_with(&entry))
             }
-            None
         })
-        .collect::<BTreeSet<String>>();
+        .filter_map(|entry| {
+            // Follows symlinks, so a linked directory can be browsed like any other.
+            let metadata = std::fs::metadata(entry.path()).ok()?;
+            let is_dir = metadata.is_dir();
+
+            Some(FolderEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                is_dir,
+                size: (!is_dir).then_some(metadata.len()),
+                modified: metadata.modified().ok().map(OffsetDateTime::from),
+                contains_audio: is_dir && contains_audio(&entry.path(), file_types),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(entries)
+}
+
+fn contains_audio(path: &std::path::Path, file_types: &BTreeSet<String>) -> bool {
+    std::fs::read_dir(path).is_ok_and(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .any(|entry| is_song_file(&entry.path(), file_types))
+    })
+}
 
-    Ok(Json(directories))
+/// Resolves symlinks of the path, falling back to the path itself if it doesn't exist.
+fn canonicalize(path: &std::path::Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
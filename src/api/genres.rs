@@ -0,0 +1,118 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    response::{IntoResponse, Result},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    AppState,
+    db::{Genre, genres},
+    genres::GenreMerge,
+    state::{
+        JobManager, Pool,
+        job::{JobParameters, JobStateId},
+    },
+};
+
+use super::{bad_request, internal_error, maintenance::OutsideMaintenance};
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GenresQuery {
+    /// Group ID3v1 genre numbers like `(17)` with the genre they stand for.
+    normalize: bool,
+}
+
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreMergeRequest {
+    #[serde(flatten)]
+    pub merge: GenreMerge,
+    /// Also write the merged genres to the tags of the files, in a `merge-genres` job.
+    #[serde(default)]
+    pub write_tags: bool,
+    /// Only count the songs the merge would change.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreMergeResult {
+    /// The amount of songs whose genre changes.
+    pub song_count: usize,
+    /// The job writing the tags, if they're written.
+    pub job: Option<JobStateId>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/genres/", get(get_genres))
+        .route("/api/genres/merge", post(merge_genres))
+}
+
+async fn get_genres(
+    State(pool): State<Pool>,
+    Query(query): Query<GenresQuery>,
+) -> Result<Json<Vec<Genre>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let genres = genres::get_genres(&mut connection, query.normalize)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(genres))
+}
+
+/// Replaces the source genres with the target, right away in the database or in a job when the
+/// tags are written as well. The returned count is taken before the job runs, files that can't be
+/// written are left out of the merge.
+async fn merge_genres(
+    _: OutsideMaintenance,
+    State(pool): State<Pool>,
+    State(manager): State<JobManager>,
+    Json(request): Json<GenreMergeRequest>,
+) -> Result<Json<GenreMergeResult>> {
+    request.merge.validate().map_err(bad_request)?;
+
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+
+    if !request.dry_run && !request.write_tags {
+        let song_count = genres::merge_genres(&mut connection, &request.merge)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        return Ok(Json(GenreMergeResult {
+            song_count,
+            job: None,
+        }));
+    }
+
+    let song_count = genres::get_merged_genres(&mut connection, &request.merge)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .len();
+
+    if request.dry_run {
+        return Ok(Json(GenreMergeResult {
+            song_count,
+            job: None,
+        }));
+    }
+
+    let parameters = JobParameters {
+        genre_merge: Some(request.merge),
+        ..Default::default()
+    };
+    let job = manager
+        .queue("merge-genres", parameters, false, true)
+        .await?
+        .id();
+
+    Ok(Json(GenreMergeResult {
+        song_count,
+        job: Some(job),
+    }))
+}
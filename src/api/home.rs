@@ -0,0 +1,111 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Result},
+    routing::{delete, get, put},
+};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    AppState,
+    api::internal_error,
+    db::{Pin, PinKind, PinTarget, PinnedItem, Song, pins, songs},
+    state::Pool,
+};
+
+/// The amount of recently added songs shown on the home page.
+const RECENTLY_ADDED_COUNT: u32 = 20;
+
+/// The amount of most played songs shown on the home page.
+const MOST_PLAYED_COUNT: u32 = 10;
+
+/// The amount of random songs shown on the home page.
+const RANDOM_COUNT: u32 = 10;
+
+/// Everything the home page shows, so it can be rendered with a single request.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Home {
+    pinned: Vec<PinnedItem>,
+    recently_added: Vec<Song>,
+    /// Songs that were played at least once, the most played first.
+    most_played: Vec<Song>,
+    random: Vec<Song>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/home", get(get_home))
+        .route("/api/pins", get(get_pins).post(pin))
+        .route("/api/pins/order", put(reorder_pins))
+        .route("/api/pins/{kind}/{id}", delete(unpin))
+}
+
+async fn get_home(State(pool): State<Pool>) -> Result<Json<Home>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+
+    let pinned = pins::get_pinned_items(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let recently_added = songs::get_recently_added(&mut connection, RECENTLY_ADDED_COUNT)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let most_played = songs::get_most_played(&mut connection, MOST_PLAYED_COUNT)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let random = songs::get_random_songs(&mut connection, RANDOM_COUNT)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(Home {
+        pinned,
+        recently_added,
+        most_played,
+        random,
+    }))
+}
+
+async fn get_pins(State(pool): State<Pool>) -> Result<Json<Vec<Pin>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let pins = pins::get_pins(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(pins))
+}
+
+async fn pin(State(pool): State<Pool>, Json(target): Json<PinTarget>) -> Result<Json<Pin>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let pin = pins::pin(&mut connection, &target)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(pin))
+}
+
+/// Replaces the order of the pins, the body must contain every pin exactly once.
+async fn reorder_pins(
+    State(pool): State<Pool>,
+    Json(targets): Json<Vec<PinTarget>>,
+) -> Result<Json<Vec<Pin>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let pins = pins::reorder_pins(&mut connection, &targets)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(pins))
+}
+
+async fn unpin(
+    State(pool): State<Pool>,
+    Path((kind, id)): Path<(PinKind, String)>,
+) -> Result<StatusCode> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    pins::unpin(&mut connection, kind, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::OK)
+}
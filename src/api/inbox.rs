@@ -0,0 +1,357 @@
+//! Tagging albums dropped into the inbox and moving them into the library, see [`crate::inbox`].
+
+use std::path::PathBuf;
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Result},
+    routing::post,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+
+use crate::{
+    config::Settings,
+    db::{Album, NewSong, Song, directories, parse_year, songs},
+    duplicates::{self, SongIdentity},
+    fs::{Operation, OperationError},
+    inbox::{self, InboxCandidate, InboxError, InboxTrack, TrackProposal},
+    metadata::SongFile,
+    organize,
+    state::{AppState, SharedProviderRegistry, SharedSongFileTypes},
+};
+
+use super::{
+    directories::scan_parameters,
+    maintenance::OutsideMaintenance,
+    organize::{PathRenameOptions, plan_album_moves},
+    *,
+};
+
+/// The provider used when a request doesn't name one.
+const DEFAULT_PROVIDER: &str = "musicbrainz";
+
+/// The most releases looked at for a folder, every one is another request to the provider.
+const RELEASE_LIMIT: usize = 5;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/inbox/identify", post(identify_folder))
+        .route("/api/inbox/apply", post(apply_proposal))
+}
+
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxIdentify {
+    /// Folder inside the inbox holding the album.
+    pub path: PathBuf,
+    /// Provider to look the album up with, MusicBrainz if not set.
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Look the album up, only its files are read otherwise so it can be tagged by hand.
+    #[serde(default = "default_lookup")]
+    pub lookup: bool,
+}
+
+fn default_lookup() -> bool {
+    true
+}
+
+/// The files of an inbox folder and the releases they might be.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxIdentification {
+    pub tracks: Vec<InboxTrack>,
+    /// Releases with as many tracks as the folder has files, best match first. Empty if the
+    /// provider isn't enabled or couldn't be reached.
+    pub candidates: Vec<InboxCandidate>,
+}
+
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxApply {
+    /// Folder inside the inbox holding the album.
+    pub path: PathBuf,
+    /// Tags to write to the files of the folder, files left out are moved as they are.
+    pub tracks: Vec<TrackProposal>,
+    /// How the album is organized into the library, `directoryId` has to be set.
+    #[serde(default)]
+    pub organize: PathRenameOptions,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ApplyOptions {
+    /// Import files even if they're already in the library.
+    allow_duplicate: bool,
+}
+
+/// Files of an inbox folder that are already in the library, nothing was imported.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxDuplicates {
+    pub duplicates: Vec<InboxDuplicate>,
+}
+
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxDuplicate {
+    /// Where the file was moved to, inside the duplicates folder of the inbox.
+    pub path: PathBuf,
+    /// Id of the song the file is a duplicate of.
+    pub duplicate_of: String,
+}
+
+/// Where the files of an inbox folder were moved to.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxRelease {
+    pub directory_id: String,
+    pub moved: Vec<PathBuf>,
+}
+
+/// Reads the files of the folder and looks for releases matching their count and durations.
+///
+/// Failing to reach the provider isn't an error, the folder can still be tagged by hand.
+async fn identify_folder(
+    State(settings): State<Settings>,
+    State(file_types): State<SharedSongFileTypes>,
+    State(providers): State<SharedProviderRegistry>,
+    Json(request): Json<InboxIdentify>,
+) -> Result<Json<InboxIdentification>> {
+    let inbox_path = settings.inbox.path.ok_or(InboxError::NotConfigured)?;
+    let file_types = file_types.get();
+
+    let (folder, tracks) = spawn_blocking(move || {
+        let folder = inbox::resolve_folder(&inbox_path, &request.path)?;
+        let tracks = inbox::read_tracks(&folder, &file_types)?;
+
+        Ok::<_, InboxError>((folder, tracks))
+    })
+    .await
+    .map_err(internal_error)??;
+
+    let provider_id = request.provider.as_deref().unwrap_or(DEFAULT_PROVIDER);
+    let candidates = match providers.get(provider_id) {
+        Ok(provider) if request.lookup => {
+            let query = inbox::search_query(&folder, &tracks);
+
+            let mut candidates = Vec::new();
+            match provider.search(&query).await {
+                Ok(releases) => {
+                    let releases = releases.into_iter().filter(|release| {
+                        release
+                            .track_count
+                            .is_none_or(|count| count as usize == tracks.len())
+                    });
+
+                    for summary in releases.take(RELEASE_LIMIT) {
+                        match provider.release(&summary.id).await {
+                            Ok(release) => {
+                                candidates.extend(inbox::match_release(&tracks, &release));
+                            }
+                            Err(err) => tracing::warn!(
+                                "Failed to look up release {} of \"{}\": {err}",
+                                summary.id,
+                                folder.display()
+                            ),
+                        }
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to look up \"{}\": {err}", folder.display());
+                }
+            }
+
+            candidates.sort_by(|a, b| b.score.cmp(&a.score));
+            candidates
+        }
+        Ok(_) => Vec::new(),
+        // A disabled provider leaves the folder to be tagged by hand, like an offline one.
+        Err(_) if request.provider.is_none() => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(Json(InboxIdentification { tracks, candidates }))
+}
+
+/// Writes the tags to the files of the folder and moves them into a directory of the library,
+/// where they're organized like any other album and scanned.
+///
+/// Files that are already in the library are moved into the duplicates folder of the inbox
+/// instead, and nothing is imported, unless `allow_duplicate` is set.
+async fn apply_proposal(
+    _: OutsideMaintenance,
+    State(app): State<AppState>,
+    State(settings): State<Settings>,
+    State(file_types): State<SharedSongFileTypes>,
+    Query(options): Query<ApplyOptions>,
+    Json(request): Json<InboxApply>,
+) -> Result<Json<InboxRelease>> {
+    let inbox_path = settings.inbox.path.ok_or(InboxError::NotConfigured)?;
+    let Some(directory_id) = request.organize.directory_id.clone() else {
+        return Err(bad_request("The directory to move the album into has to be given").into());
+    };
+
+    let mut connection = app.pool.acquire().await.map_err(internal_error)?;
+    let directories = directories::get_directories(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    drop(connection);
+
+    let file_types = file_types.get();
+    let folder_inbox_path = inbox_path.clone();
+    let (tracks, songs) = spawn_blocking(move || {
+        let folder = inbox::resolve_folder(&folder_inbox_path, &request.path)?;
+        let tracks = inbox::read_tracks(&folder, &file_types)?;
+
+        for proposal in &request.tracks {
+            let Some(track) = tracks.iter().find(|track| {
+                proposal
+                    .path
+                    .canonicalize()
+                    .is_ok_and(|path| path == track.path)
+            }) else {
+                return Err(InboxError::UnknownTrack(proposal.path.clone()));
+            };
+
+            inbox::write_proposal(&TrackProposal {
+                path: track.path.clone(),
+                ..proposal.clone()
+            })?;
+        }
+
+        // Read back so the album is organized by the tags the files ended up with.
+        let songs = tracks
+            .iter()
+            .map(|track| Ok(NewSong::from(SongFile::open(&track.path)?)))
+            .collect::<Result<Vec<_>, InboxError>>()?;
+
+        Ok::<_, InboxError>((tracks, songs))
+    })
+    .await
+    .map_err(internal_error)??;
+
+    if !options.allow_duplicate {
+        let mut connection = app.pool.acquire().await.map_err(internal_error)?;
+        let mut found = Vec::new();
+        for (track, song) in tracks.iter().zip(&songs) {
+            let Some(title) = song.title.as_deref() else {
+                continue;
+            };
+
+            let candidates = songs::get_songs_titled(&mut connection, title)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            let identity = SongIdentity {
+                title: Some(title),
+                artist: song.artist.as_deref(),
+                album: song.album.as_deref(),
+                duration_ms: track.duration_ms,
+            };
+            if let Some(existing) = duplicates::find_duplicate(&identity, &candidates) {
+                found.push((track.path.clone(), existing.id.clone()));
+            }
+        }
+        drop(connection);
+
+        if !found.is_empty() {
+            let duplicates = spawn_blocking(move || {
+                found
+                    .into_iter()
+                    .map(|(path, duplicate_of)| {
+                        Ok(InboxDuplicate {
+                            path: inbox::set_aside_duplicate(&inbox_path, &path)?,
+                            duplicate_of,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, InboxError>>()
+            })
+            .await
+            .map_err(internal_error)??;
+
+            return Err((StatusCode::CONFLICT, Json(InboxDuplicates { duplicates }))
+                .into_response()
+                .into());
+        }
+    }
+
+    let album = Album::from(
+        songs
+            .into_iter()
+            .map(|song| song_of(song, &directory_id))
+            .collect::<Vec<_>>(),
+    );
+    let moves = plan_album_moves(&album, &directories, &request.organize, &settings.organize)?
+        .into_iter()
+        .map(|planned| (planned.from, planned.to))
+        .collect::<Vec<_>>();
+
+    let collisions = organize::find_collisions(moves.iter().map(|(from, to)| (from, to)));
+    if !collisions.is_empty() {
+        return Err(conflict(format!(
+            "Multiple files would be moved to the same path: {}",
+            collisions
+                .iter()
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+        .into());
+    }
+
+    let mut operation_handle = app
+        .file_operation_manager
+        .queue_operation(Operation::Move {
+            paths: moves.iter().cloned().collect(),
+            overwrite: false,
+            delete_empty_directories_after: true,
+            verify: request.organize.verify,
+        })
+        .await?;
+
+    while operation_handle.events().recv().await.is_some() {}
+
+    match operation_handle.result().await.map_err(internal_error)? {
+        Ok(()) => {}
+        Err(err @ OperationError::FileAlreadyExists(_)) => return Err(conflict(err).into()),
+        Err(err) => return Err(internal_error(err).into()),
+    }
+
+    app.job_manager
+        .queue("scan-songs", scan_parameters(&directory_id), false, false)
+        .await?;
+
+    Ok(Json(InboxRelease {
+        directory_id,
+        moved: moves.into_iter().map(|(_, to)| to).collect(),
+    }))
+}
+
+/// Stands in for the song a file of the folder will become, so the album can be organized
+/// before it's in the library.
+fn song_of(song: NewSong, directory_id: &str) -> Song {
+    Song {
+        path: song.path,
+        title: song.title,
+        artist: song.artist,
+        album: song.album,
+        album_artist: song.album_artist,
+        genre: song.genre,
+        track_number: song.track_number,
+        track_total: song.track_total,
+        disc_number: song.disc_number,
+        disc_total: song.disc_total,
+        year_num: song.year.as_deref().and_then(parse_year),
+        year: song.year,
+        mood: song.mood,
+        composer: song.composer,
+        comment: song.comment,
+        rating: song.rating,
+        directory_id: directory_id.to_string(),
+        ..Default::default()
+    }
+}
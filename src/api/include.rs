@@ -0,0 +1,201 @@
+//! Related records responses can include inline with `?include=`, so clients don't need another
+//! request for them.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::db::{Directory, Song};
+
+/// A related record that can be included, requested as a comma separated list, e.g.
+/// `?include=directory,album`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum Include {
+    /// The library directory the song is stored in.
+    Directory,
+    /// The album the song is a track of.
+    Album,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct IncludeQuery {
+    #[serde(deserialize_with = "deserialize_includes")]
+    pub include: BTreeSet<Include>,
+}
+
+impl IncludeQuery {
+    pub fn contains(&self, include: Include) -> bool {
+        self.include.contains(&include)
+    }
+}
+
+fn deserialize_includes<'de, D>(deserializer: D) -> Result<BTreeSet<Include>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{Error, IntoDeserializer};
+
+    String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|include| !include.is_empty())
+        .map(|include| {
+            Include::deserialize(include.into_deserializer())
+                .map_err(|err: serde::de::value::Error| D::Error::custom(err))
+        })
+        .collect()
+}
+
+/// A record along with the related records that were asked for.
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Expanded<T: TS> {
+    #[serde(flatten)]
+    pub item: T,
+    /// Kept apart from the fields of the record, which may share their names. Left out if nothing
+    /// was asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub included: Option<Included>,
+}
+
+/// The related records of a record, the ones that weren't asked for or don't exist are left out.
+#[derive(Serialize, Debug, Default, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Included {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub directory: Option<IncludedDirectory>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub album: Option<IncludedAlbum>,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct IncludedDirectory {
+    pub name: String,
+    pub path: String,
+    pub display_name: Option<String>,
+}
+
+impl From<&Directory> for IncludedDirectory {
+    fn from(directory: &Directory) -> Self {
+        Self {
+            name: directory.name.clone(),
+            path: directory.path.clone(),
+            display_name: directory.display_name.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct IncludedAlbum {
+    /// Title the album is looked up by, see `/api/albums/{title}`.
+    pub title: String,
+    pub artist: Option<String>,
+    pub cover_art_url: String,
+}
+
+impl IncludedAlbum {
+    fn of(song: &Song) -> Option<Self> {
+        let title = song.album.clone()?;
+        let segment = url::form_urlencoded::byte_serialize(title.as_bytes())
+            .collect::<String>()
+            .replace('+', "%20");
+
+        Some(Self {
+            cover_art_url: format!("/api/albums/{segment}/cover-art"),
+            artist: song.album_artist.clone(),
+            title,
+        })
+    }
+}
+
+/// Attaches what's asked for in `query` to the song, `directories` are the cached directories by
+/// name.
+pub fn expand_song(
+    song: Song,
+    query: &IncludeQuery,
+    directories: &HashMap<String, Directory>,
+) -> Expanded<Song> {
+    let directory = directories
+        .get(&song.directory_id)
+        .filter(|_| query.contains(Include::Directory))
+        .map(IncludedDirectory::from);
+    let album = query
+        .contains(Include::Album)
+        .then(|| IncludedAlbum::of(&song))
+        .flatten();
+
+    Expanded {
+        included: (!query.include.is_empty()).then_some(Included { directory, album }),
+        item: song,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(include: &str) -> Result<IncludeQuery, serde::de::value::Error> {
+        use serde::de::IntoDeserializer;
+
+        IncludeQuery::deserialize(HashMap::from([("include", include)]).into_deserializer())
+    }
+
+    #[test]
+    fn test_parsing_includes() {
+        assert_eq!(
+            query("album, directory,album").unwrap().include,
+            BTreeSet::from([Include::Directory, Include::Album])
+        );
+        assert!(query("").unwrap().include.is_empty());
+        assert!(query("playlists").is_err());
+    }
+
+    #[test]
+    fn test_expanding_song() {
+        let directories = HashMap::from([(
+            String::from("nas"),
+            Directory {
+                name: String::from("nas"),
+                path: String::from("/mnt/nas/music"),
+                display_name: Some(String::from("NAS")),
+            },
+        )]);
+        let song = Song {
+            directory_id: String::from("nas"),
+            album: Some(String::from("Sound & Color")),
+            ..Default::default()
+        };
+
+        assert!(
+            expand_song(song.clone(), &query("").unwrap(), &directories)
+                .included
+                .is_none()
+        );
+
+        let included = expand_song(song.clone(), &query("album").unwrap(), &directories)
+            .included
+            .unwrap();
+        assert!(included.directory.is_none());
+        assert_eq!(
+            included.album.unwrap().cover_art_url,
+            "/api/albums/Sound%20%26%20Color/cover-art"
+        );
+
+        let included = expand_song(song, &query("directory").unwrap(), &directories)
+            .included
+            .unwrap();
+        assert_eq!(
+            included.directory.unwrap().display_name.as_deref(),
+            Some("NAS")
+        );
+        assert!(included.album.is_none());
+    }
+}
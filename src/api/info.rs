@@ -1,43 +1,81 @@
 use axum::{
     Json, Router,
-    response::{IntoResponse, Response},
-    routing::get,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response, Result},
+    routing::{get, put},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use ts_rs::TS;
 
-use crate::AppState;
+use crate::{
+    AppState,
+    api::internal_error,
+    bindings::SCHEMA_VERSION,
+    db::{MaintenanceMode, OnThisDay, YearInReview, stats},
+    logging::{self, LogLevel, LogTail},
+    state::{Pool, SharedMaintenance},
+};
 
 #[derive(Serialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
-struct AppInfo {
+pub struct AppInfo {
     /// The version number of the application
     version: String,
     /// The name of the application
     name: String,
+    /// Version of the API payloads, clients built against another one get a `409 Conflict` when
+    /// sending it in the `X-Schema-Version` header
+    schema_version: u32,
+    /// The maintenance the server is in, requests that would change files are turned away with
+    /// `503 Service Unavailable` while it is
+    maintenance: Option<MaintenanceMode>,
     /// System information that the application is running on
     system: SystemInfo,
 }
 
 #[derive(Serialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
-struct SystemInfo {
+pub struct SystemInfo {
     /// The name of the operating system, e.g. "Windows", "Ubuntu", "Darwin"
     os: String,
     /// The name of the computer
     name: String,
 }
 
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogsQuery {
+    /// Number of lines from the end of the file, at most 10000.
+    lines: usize,
+    /// Name of an older log file to read instead of the current one.
+    file: Option<String>,
+}
+
+impl Default for LogsQuery {
+    fn default() -> Self {
+        Self {
+            lines: 500,
+            file: None,
+        }
+    }
+}
+
 pub fn router() -> Router<AppState> {
-    Router::new().route("/api/info", get(get_app_info))
+    Router::new()
+        .route("/api/info", get(get_app_info))
+        .route("/api/info/on-this-day", get(get_on_this_day))
+        .route("/api/info/year/{year}", get(get_year_in_review))
+        .route("/api/info/logs", get(get_logs))
+        .route("/api/info/log-level", put(set_log_level))
 }
 
-async fn get_app_info() -> Response {
+async fn get_app_info(State(maintenance): State<SharedMaintenance>) -> Response {
     Json(AppInfo {
         name: env!("CARGO_PKG_NAME").to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: SCHEMA_VERSION,
+        maintenance: maintenance.mode().await,
         system: SystemInfo {
             os: sysinfo::System::name()
                 .expect("Failed to get system name")
@@ -49,3 +87,43 @@ async fn get_app_info() -> Response {
     })
     .into_response()
 }
+
+/// Returns the songs added on today's date in earlier years.
+async fn get_on_this_day(State(pool): State<Pool>) -> Result<Json<Vec<OnThisDay>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let days = stats::get_on_this_day(&mut connection, OffsetDateTime::now_utc().date())
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(days))
+}
+
+async fn get_year_in_review(
+    State(pool): State<Pool>,
+    Path(year): Path<i32>,
+) -> Result<Json<YearInReview>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let review = stats::get_year_in_review(&mut connection, year)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(review))
+}
+
+/// Returns the last lines of the current log file, or of an older one in the log directory.
+async fn get_logs(Query(query): Query<LogsQuery>) -> Result<Json<LogTail>> {
+    let tail =
+        tokio::task::spawn_blocking(move || logging::tail_log(query.file.as_deref(), query.lines))
+            .await
+            .map_err(internal_error)?
+            .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(tail))
+}
+
+/// Changes what's logged until the server restarts, `RUST_LOG` sets it again on startup.
+async fn set_log_level(Json(level): Json<LogLevel>) -> Result<Json<LogLevel>> {
+    let level = logging::set_filter(&level.filter).map_err(IntoResponse::into_response)?;
+
+    Ok(Json(level))
+}
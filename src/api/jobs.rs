@@ -2,36 +2,48 @@ use std::collections::BTreeMap;
 
 use axum::{
     Json, Router,
-    extract::{Path, State},
-    response::Result,
-    routing::{get, post},
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response, Result},
+    routing::{get, post, put},
 };
 use serde::Serialize;
 use ts_rs::TS;
 
-use crate::state::{
-    AppState, JobManager,
-    job::{
-        JobId, JobStateId,
-        manager::{JobReports, JobStates},
+use crate::{
+    db::{JobSchedule, NewJobSchedule, schedules},
+    paths::job_artifact_path,
+    state::{
+        AppState, JobManager, Pool,
+        job::{
+            JobId, JobParameters, JobStateId,
+            manager::{JobReports, JobStates},
+        },
     },
 };
 
+use super::*;
+
 #[derive(Debug, Serialize, TS)]
-#[ts(export, export_to = "bindings.ts")]
+#[serde(rename_all = "camelCase")]
 pub struct RegistryJob {
     pub id: String,
     pub name: String,
     pub description: String,
     pub steps: BTreeMap<u8, String>,
+    pub supports_dry_run: bool,
+    pub supports_step_selection: bool,
+    pub supports_filters: bool,
+    pub supports_playlist: bool,
+    pub supports_genre_merge: bool,
+    pub exclusive: bool,
 }
 
 #[derive(Debug, Serialize, TS)]
-#[ts(export, export_to = "bindings.ts")]
 pub struct JobStateResponse(JobStates);
 
 #[derive(Debug, Serialize, TS)]
-#[ts(export, export_to = "bindings.ts")]
 pub struct JobReportsResponse(JobReports);
 
 pub fn router() -> Router<AppState> {
@@ -40,15 +52,31 @@ pub fn router() -> Router<AppState> {
         .route("/api/jobs/state", get(state))
         .route("/api/jobs/state/{id}/cancel", post(cancel_job))
         .route("/api/jobs/reports", get(job_reports))
+        .route("/api/jobs/reports/{id}/artifact", get(job_artifact))
         .route("/api/jobs/order", get(job_order))
+        .route("/api/jobs/schedules", get(get_schedules))
+        .route(
+            "/api/jobs/schedules/{id}",
+            put(set_schedule).delete(delete_schedule),
+        )
         .route("/api/jobs", get(list_jobs))
 }
 
+/// Queues the job with the parameters of the query string, or the ones of the JSON body when it
+/// isn't empty, e.g. `{ "directory": "<name>" }`.
 async fn queue_job(
     State(manager): State<JobManager>,
     Path(id): Path<JobId>,
+    Query(parameters): Query<JobParameters>,
+    body: Bytes,
 ) -> Result<Json<JobStateId>> {
-    Ok(Json(manager.queue(id, true, true).await?.id()))
+    let parameters = if body.trim_ascii().is_empty() {
+        parameters
+    } else {
+        serde_json::from_slice(&body).map_err(bad_request)?
+    };
+
+    Ok(Json(manager.queue(id, parameters, true, true).await?.id()))
 }
 
 async fn job_order(State(manager): State<JobManager>) -> Result<Json<Vec<JobStateId>>> {
@@ -59,6 +87,69 @@ async fn job_reports(State(manager): State<JobManager>) -> Result<Json<JobReport
     Ok(Json(JobReportsResponse(manager.reports().await)))
 }
 
+/// Returns the artifact saved by the last run of the job, e.g. the changes a dry run planned.
+async fn job_artifact(
+    State(manager): State<JobManager>,
+    Path(id): Path<JobId>,
+) -> Result<Response> {
+    if !manager.registry().jobs().contains_key(&id) {
+        return Err(not_found("Job not found").into());
+    }
+
+    let artifact = match tokio::fs::read(job_artifact_path(&id)).await {
+        Ok(artifact) => artifact,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(not_found("The last run of this job didn't save an artifact").into());
+        }
+        Err(err) => return Err(internal_error(err).into()),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, String::from("application/json")),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{id}.json\""),
+            ),
+        ],
+        artifact,
+    )
+        .into_response())
+}
+
+async fn get_schedules(State(db): State<Pool>) -> Result<Json<Vec<JobSchedule>>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    Ok(Json(schedules::get_schedules(&mut connection).await?))
+}
+
+/// Creates or replaces the schedule the job is queued on.
+async fn set_schedule(
+    State(AppState {
+        job_manager: manager,
+        pool: db,
+        ..
+    }): State<AppState>,
+    Path(id): Path<JobId>,
+    Json(schedule): Json<NewJobSchedule>,
+) -> Result<Json<JobSchedule>> {
+    if !manager.registry().jobs().contains_key(&id) {
+        return Err(not_found("Job not found").into());
+    }
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    Ok(Json(
+        schedules::set_schedule(&mut connection, &id, &schedule).await?,
+    ))
+}
+
+async fn delete_schedule(State(db): State<Pool>, Path(id): Path<JobId>) -> Result<()> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    Ok(schedules::delete_schedule(&mut connection, &id).await?)
+}
+
 async fn state(State(manager): State<JobManager>) -> Result<Json<JobStateResponse>> {
     Ok(Json(JobStateResponse(manager.states().await)))
 }
@@ -80,6 +171,12 @@ async fn list_jobs(State(manager): State<JobManager>) -> Result<Json<Vec<Registr
                 name: info.name,
                 description: info.description,
                 steps: info.steps,
+                supports_dry_run: info.supports_dry_run,
+                supports_step_selection: info.supports_step_selection,
+                supports_filters: info.supports_filters,
+                supports_playlist: info.supports_playlist,
+                supports_genre_merge: info.supports_genre_merge,
+                exclusive: info.exclusive,
             }
         })
         .collect::<Vec<_>>();
@@ -0,0 +1,384 @@
+use std::io;
+
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response, Result},
+    routing::{get, post},
+};
+use csv_async::{AsyncDeserializer, AsyncWriterBuilder};
+use futures::{StreamExt, TryStreamExt};
+use serde::Deserialize;
+use tokio::{io::AsyncBufReadExt, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+use ts_rs::TS;
+
+use crate::{
+    AppState,
+    backup::{self, Backup},
+    config::Settings,
+    db::{
+        ImportConflict, IntegrityRepair, IntegrityReport, LibraryImportSummary, LibraryRecord,
+        PlayStats, PlayStatsQuery, ScanError, integrity,
+        library::{self, LibraryImport},
+        scan_errors, stats,
+    },
+    jobs::save_integrity_report,
+    paths::{backups_dir, integrity_report_path},
+    state::{Pool, SharedDirectoryCache},
+};
+
+use super::*;
+
+/// Rows read ahead of the client while exporting.
+const EXPORT_BUFFER: usize = 256;
+
+/// Format of an exported library, the rows are the same in both, see [`LibraryRecord`].
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryFormat {
+    /// JSON Lines, a JSON object per row.
+    #[default]
+    Json,
+    /// CSV with a header row.
+    Csv,
+}
+
+impl LibraryFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/jsonl",
+            Self::Csv => "text/csv",
+        }
+    }
+
+    /// Encodes a single row, CSV rows start with the header if `first` is set.
+    async fn encode(&self, record: &LibraryRecord, first: bool) -> io::Result<Bytes> {
+        match self {
+            Self::Json => {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                Ok(line.into())
+            }
+            Self::Csv => {
+                let mut serializer = AsyncWriterBuilder::new()
+                    .has_headers(first)
+                    .create_serializer(Vec::new());
+                serializer
+                    .serialize(record)
+                    .await
+                    .map_err(io::Error::other)?;
+
+                Ok(serializer
+                    .into_inner()
+                    .await
+                    .map_err(|err| io::Error::other(err.to_string()))?
+                    .into())
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ExportOptions {
+    format: LibraryFormat,
+}
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LibraryImportOptions {
+    pub format: LibraryFormat,
+    /// What to do with songs whose path is already in the library, and playlists with an id
+    /// that's already taken.
+    pub on_conflict: ImportConflict,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/library/export", get(export_library))
+        .route("/api/library/import", post(import_library))
+        .route("/api/library/stats", get(get_play_stats))
+        .route("/api/library/scan-errors", get(get_scan_errors))
+        .route(
+            "/api/library/scan-errors/{id}/retry",
+            post(retry_scan_error),
+        )
+        .route("/api/library/integrity", get(get_integrity_report))
+        .route("/api/library/integrity/repair", post(repair_integrity))
+        .route("/api/library/backup", post(create_backup))
+        .route("/api/library/backups", get(get_backups))
+}
+
+/// Writes a snapshot of the database into the backups directory, then removes the oldest backups
+/// past [`Backups::keep`](crate::config::Backups::keep).
+///
+/// The library stays usable while it's backed up, only a single connection is taken up by it.
+async fn create_backup(
+    State(pool): State<Pool>,
+    State(settings): State<Settings>,
+) -> Result<(StatusCode, Json<Backup>)> {
+    let directory = backups_dir();
+
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let created = backup::create_backup(&mut connection, &directory, None)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    drop(connection);
+
+    if let Err(err) = backup::prune_backups(&directory, settings.backups.keep).await {
+        tracing::warn!("Failed to remove old backups: {err}");
+    }
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// Lists the backups of the database, newest first.
+async fn get_backups() -> Result<Json<Vec<Backup>>> {
+    let backups = backup::list_backups(&backups_dir())
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(backups))
+}
+
+/// Returns the report saved by the last `check-integrity` job run, or repair since.
+async fn get_integrity_report() -> Result<Json<IntegrityReport>> {
+    match read_integrity_report().await.map_err(internal_error)? {
+        Some(report) => Ok(Json(report)),
+        None => Err(not_found("The library hasn't been checked for integrity yet").into()),
+    }
+}
+
+async fn read_integrity_report() -> io::Result<Option<IntegrityReport>> {
+    let report = match tokio::fs::read_to_string(integrity_report_path()).await {
+        Ok(report) => report,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    Ok(Some(serde_json::from_str(&report)?))
+}
+
+/// Applies the fixes that don't need a decision, see [`integrity::repair`].
+///
+/// The saved report is replaced by what's left afterwards, the files aren't checked again so it
+/// keeps the missing files of the last check that still have a song.
+async fn repair_integrity(State(pool): State<Pool>) -> Result<Json<IntegrityRepair>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let mut repair = integrity::repair(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if let Some(previous) = read_integrity_report().await.map_err(internal_error)? {
+        let mut report = integrity::check_database(&mut connection)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        report.missing_files = previous
+            .missing_files
+            .into_iter()
+            .filter(|song| !repair.deleted_songs.contains(&song.id))
+            .collect();
+
+        save_integrity_report(&report)
+            .await
+            .map_err(internal_error)?;
+        repair.report = Some(report);
+    }
+
+    Ok(Json(repair))
+}
+
+/// Files the last scans failed to read, they aren't read again until they're modified.
+async fn get_scan_errors(State(pool): State<Pool>) -> Result<Json<Vec<ScanError>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let errors = scan_errors::get_scan_errors(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(errors))
+}
+
+/// Forgets a failed file, so the next scan reads it again.
+async fn retry_scan_error(State(pool): State<Pool>, Path(id): Path<i64>) -> Result<StatusCode> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    scan_errors::delete_scan_error(&mut connection, id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Counts the plays reported between `from` and `to`, along with the most played artists and
+/// albums.
+async fn get_play_stats(
+    State(pool): State<Pool>,
+    Query(query): Query<PlayStatsQuery>,
+) -> Result<Json<PlayStats>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let stats = stats::get_play_stats(&mut connection, query)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(stats))
+}
+
+/// Streams every directory, song and playlist of the library, rows are read from the database
+/// as the client receives them.
+async fn export_library(
+    State(pool): State<Pool>,
+    Query(ExportOptions { format }): Query<ExportOptions>,
+) -> Result<Response> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let (body_tx, body_rx) = mpsc::channel::<io::Result<Bytes>>(1);
+
+    tokio::spawn(async move {
+        let (record_tx, mut record_rx) = mpsc::channel(EXPORT_BUFFER);
+
+        let export = async move { library::export_library(&mut connection, &record_tx).await };
+
+        // Dropping the receiver once the client is gone stops the export.
+        let body = &body_tx;
+        let encode = async move {
+            let mut first = true;
+
+            while let Some(record) = record_rx.recv().await {
+                let chunk = format.encode(&record, first).await;
+                first = false;
+
+                if body.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        };
+
+        let (result, ()) = tokio::join!(export, encode);
+
+        // Failing the body aborts the response, so a failed export can't pass for a complete
+        // one.
+        if let Err(err) = result {
+            tracing::error!("Failed to export the library: {err}");
+            let _ = body_tx.send(Err(io::Error::other(err))).await;
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"library.{}\"", format.extension()),
+            ),
+        ],
+        Body::from_stream(ReceiverStream::new(body_rx)),
+    )
+        .into_response())
+}
+
+/// Adds the rows of an exported library as they're received, within a single transaction so a
+/// failed import doesn't leave a partial library behind.
+async fn import_library(
+    State(pool): State<Pool>,
+    State(directory_cache): State<SharedDirectoryCache>,
+    Query(options): Query<LibraryImportOptions>,
+    body: Body,
+) -> Result<Json<LibraryImportSummary>> {
+    let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+
+    let mut transaction = pool.begin().await.map_err(internal_error)?;
+    let mut import = LibraryImport::new(&mut transaction, options.on_conflict);
+
+    match options.format {
+        LibraryFormat::Json => {
+            let mut lines = reader.lines();
+
+            while let Some(line) = lines.next_line().await.map_err(bad_request)? {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let record = serde_json::from_str(&line).map_err(bad_request)?;
+                import
+                    .add(record)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+            }
+        }
+        LibraryFormat::Csv => {
+            let mut deserializer = AsyncDeserializer::from_reader(reader);
+            let mut records = deserializer.deserialize::<LibraryRecord>();
+
+            while let Some(record) = records.next().await {
+                import
+                    .add(record.map_err(bad_request)?)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+            }
+        }
+    }
+
+    let summary = import.finish();
+    transaction.commit().await.map_err(internal_error)?;
+
+    if let Err(err) = directory_cache.reload(&pool).await {
+        tracing::error!("Failed to reload the directory cache: {err}");
+    }
+
+    Ok(Json(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::db::LibraryRecordKind;
+
+    #[test(tokio::test)]
+    async fn test_csv_round_trip() {
+        let records = [
+            LibraryRecord {
+                record: LibraryRecordKind::Directory,
+                id: Some(String::from("directory")),
+                path: Some(String::from("/music/")),
+                ..Default::default()
+            },
+            LibraryRecord {
+                record: LibraryRecordKind::Song,
+                id: Some(String::from("song")),
+                path: Some(String::from("/music/song.flac")),
+                directory_id: Some(String::from("directory")),
+                lyrics: Some(String::from("First line,\n\"second\" line")),
+                added_at: Some(OffsetDateTime::from_unix_timestamp(1_792_065_600).unwrap()),
+                start_ms: Some(0),
+                track_gain_db: Some(-6.5),
+                ..Default::default()
+            },
+        ];
+
+        let mut csv = Vec::new();
+        for (index, record) in records.iter().enumerate() {
+            csv.extend(LibraryFormat::Csv.encode(record, index == 0).await.unwrap());
+        }
+
+        let mut deserializer = AsyncDeserializer::from_reader(csv.as_slice());
+        let decoded = deserializer
+            .deserialize::<LibraryRecord>()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(decoded, records);
+    }
+}
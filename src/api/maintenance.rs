@@ -0,0 +1,45 @@
+//! Turns away requests that would change the library while the server is in maintenance, see
+//! [`crate::state::Maintenance`].
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+
+use crate::state::SharedMaintenance;
+
+/// Seconds clients are told to wait before retrying a request that was turned away.
+const RETRY_AFTER_SECONDS: u32 = 60;
+
+/// Extracted by handlers that change files, rejects the request with `503 Service Unavailable`
+/// and a `Retry-After` header while the server is in maintenance.
+#[derive(Debug, Clone, Copy)]
+pub struct OutsideMaintenance;
+
+impl<S> FromRequestParts<S> for OutsideMaintenance
+where
+    SharedMaintenance: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(mode) = SharedMaintenance::from_ref(state).mode().await else {
+            return Ok(Self);
+        };
+
+        let message = match mode.reason {
+            Some(reason) => format!("Server is in maintenance: {reason}"),
+            None => String::from("Server is in maintenance"),
+        };
+        tracing::warn!("service unavailable: {message}");
+
+        Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, RETRY_AFTER_SECONDS.to_string())],
+            message,
+        )
+            .into_response())
+    }
+}
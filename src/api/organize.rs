@@ -1,41 +1,71 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     path::PathBuf,
 };
 
-use super::not_found;
+use super::{bad_request, conflict, maintenance::OutsideMaintenance, not_found};
 use axum::{
     Json, Router,
-    extract::{Path, Query, State},
+    extract::{Query, State},
+    http::StatusCode,
     response::{IntoResponse, Response, Result},
     routing::{get, post},
 };
 
+use tokio::task::JoinHandle;
 use ts_rs::TS;
 
 use crate::{
-    api::internal_error,
-    db::{Song, directories, songs},
+    api::{albums::AlbumKey, internal_error},
+    config::{Organize, Settings},
+    db::{Album, DatabaseError, Directory, Song, directories, songs},
+    events::{AppEvent, AppEventKind, EventBus},
     fs::{Operation, OperationEvent},
     metadata::{Metadata, item::ItemKey},
-    organize,
-    state::{AppState},
+    organize::{self, OrganizeError},
+    state::{AppState, OperationHandle, OperationManager, Pool},
 };
 
 #[derive(serde::Serialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
 pub struct PathRenamePreviewResult {
     pub previous_path: PathBuf,
     pub new_path: PathBuf,
+    /// The directory the song will be organized into.
+    pub directory_id: String,
+    /// Whether another song in the album would be moved to the same path.
+    pub collides: bool,
+}
+
+/// What organizing an album did besides moving its files.
+#[derive(serde::Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizeSummary {
+    pub album: String,
+    /// Cover images written into the album's folders.
+    pub folder_art: Vec<PathBuf>,
+    /// Problems that didn't stop the album from being organized.
+    pub warnings: Vec<String>,
 }
 
 #[derive(serde::Deserialize, TS)]
 #[serde(rename_all = "camelCase", default)]
-#[ts(export)]
 pub struct PathRenameOptions {
     pub rename_original_files: bool,
+    /// Directory to organize every track into, albums split across multiple directories are
+    /// organized within each track's own directory if not set.
     pub directory_id: Option<String>,
+    /// Convert non-ASCII characters in the rendered path to ASCII.
+    pub transliterate: bool,
+    /// Handlebars template to render the paths with instead of the default one.
+    pub template: Option<String>,
+    /// Name of a template from the organize settings, can't be combined with `template`.
+    pub template_name: Option<String>,
+    /// Extract the album's front cover into every folder the tracks end up in that doesn't have
+    /// a cover image yet.
+    pub write_folder_art: bool,
+    /// Verify the checksum of every file copied to another disk, see [`Operation::Copy`].
+    pub verify: bool,
 }
 
 impl Default for PathRenameOptions {
@@ -43,42 +73,151 @@ impl Default for PathRenameOptions {
         Self {
             rename_original_files: true,
             directory_id: None,
+            transliterate: false,
+            template: None,
+            template_name: None,
+            write_folder_art: false,
+            verify: false,
         }
     }
 }
 
-// TODO: Add ability to use a custom templates for folder structure.
+/// A template to preview, rendered against a song of the library or inline metadata as well as
+/// the built-in [`organize::sample_songs`].
+#[derive(serde::Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplatePreviewRequest {
+    pub template: String,
+    /// Song of the library to render the template with, can't be combined with `metadata`.
+    pub song_id: Option<String>,
+    /// Tags to render the template with instead of a song of the library.
+    pub metadata: Option<Metadata>,
+    /// File name the inline metadata is rendered for, its extension ends up in the path.
+    pub file_name: Option<String>,
+    #[serde(default = "default_true")]
+    pub rename_original_files: bool,
+    #[serde(default)]
+    pub transliterate: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Where a template would put the song, or why it couldn't be rendered.
+#[derive(serde::Serialize, TS, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateRender {
+    /// The song's title, or the edge case a sample song covers.
+    pub name: String,
+    /// Path relative to the directory the song would be organized into.
+    pub path: Option<PathBuf>,
+    pub error: Option<TemplateRenderError>,
+}
+
+#[derive(serde::Serialize, TS, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateRenderError {
+    pub message: String,
+    /// Line of the template the error is on, starting at 1.
+    pub line: Option<usize>,
+    /// Column of the template the error is on, starting at 1.
+    pub column: Option<usize>,
+}
+
+#[derive(serde::Serialize, TS, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplatePreview {
+    /// Set when the template doesn't compile, nothing is rendered then.
+    pub error: Option<TemplateRenderError>,
+    /// The path of the requested song, if one was given.
+    pub song: Option<TemplateRender>,
+    /// The paths of the built-in sample songs.
+    pub samples: Vec<TemplateRender>,
+}
+
+impl From<&PathRenameOptions> for organize::RenderOptions {
+    fn from(options: &PathRenameOptions) -> Self {
+        Self {
+            rename_original_file: options.rename_original_files,
+            transliterate: options.transliterate,
+        }
+    }
+}
 
 pub fn router() -> Router<AppState> {
-    Router::new().route(
-        "/albums/{title}/organize",
-        get(preview_organize_album_tracks).post(organize_album_tracks),
-    )
+    Router::new()
+        .route(
+            "/albums/by-id/{album_id}/organize",
+            get(preview_organize_album_tracks).post(organize_album_tracks),
+        )
+        .route(
+            "/albums/{title}/organize",
+            get(preview_organize_album_tracks).post(organize_album_tracks),
+        )
+        .route("/organize/preview-template", post(preview_template))
 }
 
-async fn organize_album_tracks(
-    Path(title): Path<String>,
-    State(AppState {
-        file_operation_manager: manager,
-        pool: db,
-        ..
-    }): State<AppState>,
-    Query(options): Query<PathRenameOptions>,
-) -> Result<()> {
-    let mut connection = db.acquire().await.map_err(internal_error)?;
+/// A file of an album along with the path it will be moved to.
+pub(super) struct PlannedMove {
+    /// Songs stored in the file, empty if the file is a cue sheet.
+    song_ids: Vec<String>,
+    directory_id: String,
+    pub(super) from: PathBuf,
+    pub(super) to: PathBuf,
+}
 
-    let album = songs::get_album(&mut connection, title)
-        .await
-        .map_err(IntoResponse::into_response)?;
+/// Returns the template the options select, falling back to [`organize::DEFAULT_TEMPLATE`].
+///
+/// The template is validated here, so an invalid one is rejected before anything is moved.
+fn resolve_template<'a>(
+    options: &'a PathRenameOptions,
+    settings: &'a Organize,
+) -> Result<&'a str, Response> {
+    let template = match (&options.template, &options.template_name) {
+        (Some(_), Some(_)) => {
+            return Err(
+                bad_request("Only one of template and templateName can be given").into_response(),
+            );
+        }
+        (Some(template), None) => template.as_str(),
+        (None, Some(name)) => settings
+            .templates
+            .get(name)
+            .ok_or_else(|| not_found(format!("Template {name} not found")).into_response())?,
+        (None, None) => organize::DEFAULT_TEMPLATE,
+    };
 
-    let directories = directories::get_directories(&mut connection)
-        .await
-        .map_err(IntoResponse::into_response)?;
+    organize::validate_template(template).map_err(IntoResponse::into_response)?;
 
-    let tracks = album
+    Ok(template)
+}
+
+/// Renders the new path of every track in the album.
+///
+/// Both the preview and the organize endpoint plan their moves here, so the preview always
+/// matches what will be moved.
+///
+/// Tracks are organized within the directory they are already stored in unless a target
+/// directory is given, so albums split across multiple directories stay split.
+///
+/// Tracks of a cue sheet are moved as a unit, the file they share keeps its name and the cue
+/// sheet is moved next to it.
+pub(super) fn plan_album_moves(
+    album: &Album,
+    directories: &[Directory],
+    options: &PathRenameOptions,
+    settings: &Organize,
+) -> Result<Vec<PlannedMove>, Response> {
+    let template = resolve_template(options, settings)?;
+    let handlebars = handlebars::Handlebars::new();
+    let mut planned_files = HashSet::new();
+
+    let mut planned = album
         .tracks
         .iter()
-        .try_fold(HashMap::new(), |mut paths, song| {
+        .filter(|song| planned_files.insert(song.path.clone()))
+        .map(|song| {
             let directory_id = options
                 .directory_id
                 .as_deref()
@@ -88,28 +227,169 @@ async fn organize_album_tracks(
                 .iter()
                 .find(|dir| dir.name == directory_id)
                 .ok_or_else(|| {
-                    not_found(format!("Directory {} not found", song.directory_id)).into_response()
+                    not_found(format!("Directory {directory_id} not found")).into_response()
                 })?
                 .path
                 .clone()
                 .into();
 
-            let path = directory.join(
-                organize::render_song_path(
-                    &handlebars::Handlebars::new(),
-                    organize::DEFAULT_TEMPLATE,
-                    &map_organize(song),
-                    options.rename_original_files,
-                )
-                .map_err(IntoResponse::into_response)?,
-            );
+            let is_cue_track = song.cue_path.is_some();
+            let render_options = organize::RenderOptions {
+                rename_original_file: options.rename_original_files && !is_cue_track,
+                ..organize::RenderOptions::from(options)
+            };
 
-            paths.insert(song.path.clone().into(), (path.clone(), song.id.clone()));
+            Ok(PlannedMove {
+                song_ids: album
+                    .tracks
+                    .iter()
+                    .filter(|track| track.path == song.path)
+                    .map(|track| track.id.clone())
+                    .collect(),
+                directory_id: directory_id.to_string(),
+                from: PathBuf::from(&song.path),
+                to: directory.join(
+                    organize::render_song_path(
+                        &handlebars,
+                        template,
+                        &map_organize(song),
+                        render_options,
+                    )
+                    .map_err(IntoResponse::into_response)?,
+                ),
+            })
+        })
+        .collect::<Result<Vec<_>, Response>>()?;
 
-            Ok::<HashMap<PathBuf, (PathBuf, String)>, Response>(paths)
-        })?;
+    let cue_sheets = album
+        .tracks
+        .iter()
+        .filter_map(|song| Some((song.cue_path.as_ref()?, &song.path)))
+        .collect::<HashMap<_, _>>();
+
+    for (cue_path, song_path) in cue_sheets {
+        let Some(audio) = planned
+            .iter()
+            .find(|planned| planned.from == std::path::Path::new(song_path))
+        else {
+            continue;
+        };
+
+        let cue_path = PathBuf::from(cue_path);
+        let to = audio.to.with_file_name(
+            cue_path
+                .file_name()
+                .ok_or_else(|| OrganizeError::NoFileName(cue_path.clone()).into_response())?,
+        );
+        let directory_id = audio.directory_id.clone();
+
+        planned.push(PlannedMove {
+            song_ids: Vec::new(),
+            directory_id,
+            from: cue_path,
+            to,
+        });
+    }
+
+    Ok(planned)
+}
+
+/// Whether organizing an album waits for its files to be moved.
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct OrganizeMode {
+    /// Respond with the [`OrganizeSummary`] once the album is organized instead of right away.
+    wait: bool,
+}
+
+/// An album whose files are being moved, watch the file operation over the event stream.
+#[derive(serde::Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizeStarted {
+    pub album: String,
+    /// Id of the file operation moving the files, the source of its events.
+    pub operation_id: i128,
+}
+
+/// Queues moving the album's files and responds with `202 Accepted` right away, unless `wait`
+/// is set.
+async fn organize_album_tracks(
+    _: OutsideMaintenance,
+    album: AlbumKey,
+    State(AppState {
+        file_operation_manager: manager,
+        pool: db,
+        events,
+        ..
+    }): State<AppState>,
+    State(settings): State<Settings>,
+    Query(options): Query<PathRenameOptions>,
+    Query(OrganizeMode { wait }): Query<OrganizeMode>,
+) -> Result<Response> {
+    let (started, task) =
+        organize_album(db, events, &manager, &settings.organize, album, options).await?;
+
+    if !wait {
+        return Ok((StatusCode::ACCEPTED, Json(started)).into_response());
+    }
+
+    let summary = task
+        .await
+        .map_err(internal_error)?
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(summary).into_response())
+}
+
+/// Queues moving the album's files, the returned task resolves once they're moved.
+///
+/// The paths of the songs are updated by a task of its own as the files are moved, so a client
+/// disconnecting halfway doesn't leave the database pointing at files that were moved.
+pub(crate) async fn organize_album(
+    db: Pool,
+    events: EventBus,
+    manager: &OperationManager,
+    settings: &Organize,
+    album: AlbumKey,
+    options: PathRenameOptions,
+) -> Result<
+    (
+        OrganizeStarted,
+        JoinHandle<Result<OrganizeSummary, DatabaseError>>,
+    ),
+    Response,
+> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    let album = album
+        .get(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let directories = directories::get_directories(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    drop(connection);
+
+    let tracks = plan_album_moves(&album, &directories, &options, settings)?
+        .into_iter()
+        .map(|planned| (planned.from, (planned.to, planned.song_ids)))
+        .collect::<HashMap<PathBuf, (PathBuf, Vec<String>)>>();
 
-    let mut operation_handle = manager
+    let collisions = organize::find_collisions(tracks.iter().map(|(from, (to, _))| (from, to)));
+    if !collisions.is_empty() {
+        return Err(conflict(format!(
+            "Multiple songs would be moved to the same path: {}",
+            collisions
+                .iter()
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+        .into_response());
+    }
+
+    let operation_handle = manager
         .queue_operation(Operation::Move {
             paths: tracks
                 .iter()
@@ -117,8 +397,52 @@ async fn organize_album_tracks(
                 .collect(),
             overwrite: true,
             delete_empty_directories_after: true,
+            verify: options.verify,
         })
-        .await?;
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let started = OrganizeStarted {
+        album: album.title.clone(),
+        operation_id: operation_handle.id(),
+    };
+
+    let task = tokio::spawn(async move {
+        let title = album.title.clone();
+        let result = sync_moved_tracks(
+            db,
+            events.clone(),
+            album,
+            tracks,
+            options.write_folder_art,
+            operation_handle,
+        )
+        .await;
+
+        if let Err(err) = &result {
+            tracing::error!("Failed to update the paths of \"{title}\" after moving it: {err}");
+            events.publish(AppEvent::new(
+                AppEventKind::Error,
+                format!("Failed to update the paths of \"{title}\" after moving it: {err}"),
+            ));
+        }
+
+        result
+    });
+
+    Ok((started, task))
+}
+
+/// Updates the paths of the songs as the operation moves their files, then writes the folder art.
+async fn sync_moved_tracks(
+    db: Pool,
+    events: EventBus,
+    album: Album,
+    tracks: HashMap<PathBuf, (PathBuf, Vec<String>)>,
+    write_art: bool,
+    mut operation_handle: OperationHandle,
+) -> Result<OrganizeSummary, DatabaseError> {
+    let mut connection = db.acquire().await?;
 
     while let Some(item) = operation_handle.events().recv().await {
         match item {
@@ -126,43 +450,124 @@ async fn organize_album_tracks(
                 tracing::debug!("Completed");
                 break;
             }
-            OperationEvent::Renamed { from, to } => {
-                let (_, song_id) = tracks.get(&from).expect("Path not found");
-
-                songs::update_song_path(
-                    &mut connection,
-                    song_id,
-                    to.to_str().expect("Path is not valid UTF-8"),
-                )
-                .await
-                .map_err(IntoResponse::into_response)?;
-            }
-            OperationEvent::Moved { from, to } => {
-                let (_, song_id) = tracks.get(&from).expect("Path not found");
-
-                songs::update_song_path(
-                    &mut connection,
-                    song_id,
-                    to.to_str().expect("Path is not valid UTF-8"),
-                )
-                .await
-                .map_err(IntoResponse::into_response)?;
+            OperationEvent::Renamed { from, to } | OperationEvent::Moved { from, to } => {
+                let (_, song_ids) = tracks.get(&from).expect("Path not found");
+                let to = to.to_str().expect("Path is not valid UTF-8");
+
+                if song_ids.is_empty() {
+                    songs::update_cue_path(
+                        &mut connection,
+                        from.to_str().expect("Path is not valid UTF-8"),
+                        to,
+                    )
+                    .await?;
+                }
+
+                for song_id in song_ids {
+                    songs::update_song_path(&mut connection, song_id, to).await?;
+                }
             }
             _ => continue,
         }
     }
 
-    Ok(())
+    let mut summary = OrganizeSummary {
+        album: album.title.clone(),
+        folder_art: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    if write_art {
+        match write_folder_art(&album, &tracks).await {
+            Ok(results) => {
+                for (directory, result) in results {
+                    match result {
+                        Ok(Some(path)) => {
+                            events.publish(AppEvent::new(
+                                AppEventKind::FolderArtWritten,
+                                path.to_string_lossy(),
+                            ));
+                            summary.folder_art.push(path);
+                        }
+                        Ok(None) => {}
+                        Err(err) => summary.warnings.push(format!(
+                            "Failed to write cover art to \"{}\": {err}",
+                            directory.display()
+                        )),
+                    }
+                }
+            }
+            Err(err) => summary
+                .warnings
+                .push(format!("Failed to write cover art: {err}")),
+        }
+    }
+
+    events.publish(AppEvent::new(
+        AppEventKind::AlbumOrganized,
+        format!("Organized \"{}\"", album.title),
+    ));
+
+    Ok(summary)
+}
+
+/// Writes the album's front cover into every folder its tracks were moved to, taking it from the
+/// first track in album order that has one.
+async fn write_folder_art(
+    album: &Album,
+    moved: &HashMap<PathBuf, (PathBuf, Vec<String>)>,
+) -> Result<Vec<(PathBuf, organize::Result<Option<PathBuf>>)>, tokio::task::JoinError> {
+    let mut directories: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+
+    for track in &album.tracks {
+        let Some((to, _)) = moved.get(std::path::Path::new(&track.path)) else {
+            continue;
+        };
+        let Some(directory) = to.parent() else {
+            continue;
+        };
+
+        match directories.iter_mut().find(|(path, _)| path == directory) {
+            Some((_, tracks)) if !tracks.contains(to) => tracks.push(to.clone()),
+            Some(_) => {}
+            None => directories.push((directory.to_path_buf(), vec![to.clone()])),
+        }
+    }
+
+    tokio::task::spawn_blocking(move || {
+        directories
+            .into_iter()
+            .map(|(directory, tracks)| {
+                let result = organize::write_folder_art(&directory, &tracks);
+                (directory, result)
+            })
+            .collect()
+    })
+    .await
 }
 
 async fn preview_organize_album_tracks(
     State(pool): State<sqlx::Pool<sqlx::Sqlite>>,
-    Path(title): Path<String>,
+    State(settings): State<Settings>,
+    album: AlbumKey,
     Query(options): Query<PathRenameOptions>,
 ) -> Result<Json<Vec<PathRenamePreviewResult>>> {
+    let previews = preview_album(&pool, &settings.organize, album, &options).await?;
+
+    Ok(Json(previews))
+}
+
+/// Returns where organizing the album would move each of its files, without moving any.
+pub(crate) async fn preview_album(
+    pool: &Pool,
+    settings: &Organize,
+    album: AlbumKey,
+    options: &PathRenameOptions,
+) -> Result<Vec<PathRenamePreviewResult>, Response> {
     let mut connection = pool.acquire().await.map_err(internal_error)?;
 
-    let album = songs::get_album(&mut connection, title)
+    let album = album
+        .get(&mut connection)
         .await
         .map_err(IntoResponse::into_response)?;
 
@@ -170,44 +575,116 @@ async fn preview_organize_album_tracks(
         .await
         .map_err(IntoResponse::into_response)?;
 
-    let previews = album
-        .tracks
-        .iter()
-        .map(|song| {
-            let directory_id = options
-                .directory_id
-                .as_deref()
-                .unwrap_or(&song.directory_id);
-
-            let directory: PathBuf = directories
-                .iter()
-                .find(|dir| dir.name == directory_id)
-                .ok_or_else(|| {
-                    not_found(format!("Directory {} not found", song.directory_id)).into_response()
-                })?
-                .path
-                .clone()
-                .into();
+    let planned = plan_album_moves(&album, &directories, options, settings)?;
+    let collisions =
+        organize::find_collisions(planned.iter().map(|planned| (&planned.from, &planned.to)));
 
-            Ok(PathRenamePreviewResult {
-                previous_path: PathBuf::from(&song.path),
-                new_path: directory.join(
-                    organize::render_song_path(
-                        &handlebars::Handlebars::new(),
-                        organize::DEFAULT_TEMPLATE,
-                        &map_organize(song),
-                        options.rename_original_files,
-                    )
-                    .map_err(IntoResponse::into_response)?,
-                ),
-            })
+    Ok(planned
+        .into_iter()
+        .map(|planned| PathRenamePreviewResult {
+            collides: collisions.contains(&planned.to),
+            directory_id: planned.directory_id,
+            previous_path: planned.from,
+            new_path: planned.to,
         })
-        .collect::<Result<Vec<_>, Response>>()?;
+        .collect())
+}
 
-    Ok(Json(previews))
+/// Renders a template the same way organizing an album would, without touching any files.
+async fn preview_template(
+    State(pool): State<sqlx::Pool<sqlx::Sqlite>>,
+    Json(request): Json<TemplatePreviewRequest>,
+) -> Result<Json<TemplatePreview>> {
+    let song = match (&request.song_id, request.metadata) {
+        (Some(_), Some(_)) => {
+            return Err(bad_request("Only one of songId and metadata can be given").into());
+        }
+        (Some(id), None) => {
+            let mut connection = pool.acquire().await.map_err(internal_error)?;
+            let song = songs::get_song(&mut connection, id)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            Some((
+                song.title.clone().unwrap_or_else(|| song.path.clone()),
+                map_organize(&song),
+            ))
+        }
+        (None, Some(metadata)) => Some((
+            metadata.get(&ItemKey::Title).cloned().unwrap_or_default(),
+            organize::Song {
+                file_path: PathBuf::from(request.file_name.as_deref().unwrap_or("song.flac")),
+                metadata,
+            },
+        )),
+        (None, None) => None,
+    };
+
+    if let Err(err) = handlebars::Template::compile(&request.template) {
+        let (line, column) = err.pos().unzip();
+
+        return Ok(Json(TemplatePreview {
+            error: Some(TemplateRenderError {
+                message: err.reason().to_string(),
+                line,
+                column,
+            }),
+            song: None,
+            samples: Vec::new(),
+        }));
+    }
+
+    let handlebars = handlebars::Handlebars::new();
+    let options = organize::RenderOptions {
+        rename_original_file: request.rename_original_files,
+        transliterate: request.transliterate,
+    };
+    let render = |name: String, song: &organize::Song| match organize::render_song_path(
+        &handlebars,
+        &request.template,
+        song,
+        options,
+    ) {
+        Ok(path) => TemplateRender {
+            name,
+            path: Some(path),
+            error: None,
+        },
+        Err(err) => TemplateRender {
+            name,
+            path: None,
+            error: Some(render_error(&err)),
+        },
+    };
+
+    Ok(Json(TemplatePreview {
+        error: None,
+        song: song.map(|(name, song)| render(name, &song)),
+        samples: organize::sample_songs()
+            .into_iter()
+            .map(|(name, song)| render(name.to_string(), &song))
+            .collect(),
+    }))
+}
+
+fn render_error(err: &OrganizeError) -> TemplateRenderError {
+    match err {
+        OrganizeError::Handlebars(err) => TemplateRenderError {
+            message: err.reason().to_string(),
+            line: err.line_no,
+            column: err.column_no,
+        },
+        err => TemplateRenderError {
+            message: err.to_string(),
+            line: None,
+            column: None,
+        },
+    }
 }
 
 fn map_organize(song: &Song) -> organize::Song {
+    let number = |number: Option<u32>| number.map(|number| number.to_string()).unwrap_or_default();
+
     organize::Song {
         file_path: PathBuf::from(&song.path),
         metadata: Metadata::new(
@@ -216,19 +693,17 @@ fn map_organize(song: &Song) -> organize::Song {
                 (ItemKey::Album, song.album.clone().unwrap_or_default()),
                 (ItemKey::Genre, song.genre.clone().unwrap_or_default()),
                 (ItemKey::Mood, song.mood.clone().unwrap_or_default()),
+                (ItemKey::Composer, song.composer.clone().unwrap_or_default()),
+                (ItemKey::Comment, song.comment.clone().unwrap_or_default()),
                 (
                     ItemKey::AlbumArtist,
                     song.album_artist.clone().unwrap_or_default(),
                 ),
                 (ItemKey::Title, song.title.clone().unwrap_or_default()),
-                (
-                    ItemKey::TrackNumber,
-                    song.track_number.clone().unwrap_or_default().to_string(),
-                ),
-                (
-                    ItemKey::DiscNumber,
-                    song.disc_number.clone().unwrap_or_default().to_string(),
-                ),
+                (ItemKey::TrackNumber, number(song.track_number)),
+                (ItemKey::TrackTotal, number(song.track_total)),
+                (ItemKey::DiscNumber, number(song.disc_number)),
+                (ItemKey::DiscTotal, number(song.disc_total)),
                 (
                     ItemKey::Year,
                     song.year.clone().unwrap_or_default().to_string(),
@@ -238,3 +713,212 @@ fn map_organize(song: &Song) -> organize::Song {
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use test_log::test;
+
+    use super::*;
+
+    fn track(id: &str, directory: &Directory, disc: &str) -> Song {
+        Song {
+            id: id.to_string(),
+            path: PathBuf::from(&directory.path)
+                .join(format!("{id}.flac"))
+                .to_string_lossy()
+                .to_string(),
+            title: Some(format!("Track {id}")),
+            album: Some(String::from("Split Album")),
+            album_artist: Some(String::from("Artist")),
+            disc_number: disc.parse().ok(),
+            track_number: id.parse().ok(),
+            directory_id: directory.name.clone(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_split_album() {
+        let ssd = tempdir().expect("Failed to create temp dir");
+        let nas = tempdir().expect("Failed to create temp dir");
+
+        let directories = [ssd.path(), nas.path()]
+            .iter()
+            .enumerate()
+            .map(|(index, path)| Directory {
+                name: format!("directory-{index}"),
+                path: path.to_string_lossy().to_string(),
+                display_name: None,
+            })
+            .collect::<Vec<_>>();
+
+        let album = Album::from(vec![
+            track("1", &directories[0], "1"),
+            track("2", &directories[1], "2"),
+        ]);
+
+        assert!(album.is_split());
+
+        let planned = plan_album_moves(
+            &album,
+            &directories,
+            &PathRenameOptions::default(),
+            &Organize::default(),
+        )
+        .unwrap_or_else(|_| panic!("Failed to plan moves"));
+
+        for (planned, directory) in planned.iter().zip(directories.iter()) {
+            assert_eq!(planned.directory_id, directory.name);
+            assert!(planned.to.starts_with(&directory.path));
+        }
+
+        let planned = plan_album_moves(
+            &album,
+            &directories,
+            &PathRenameOptions {
+                directory_id: Some(directories[1].name.clone()),
+                ..Default::default()
+            },
+            &Organize::default(),
+        )
+        .unwrap_or_else(|_| panic!("Failed to plan moves"));
+
+        assert!(
+            planned
+                .iter()
+                .all(|planned| planned.to.starts_with(&directories[1].path))
+        );
+    }
+
+    #[test]
+    fn test_plan_cue_album() {
+        let music = tempdir().expect("Failed to create temp dir");
+        let directory = Directory {
+            name: String::from("directory"),
+            path: music.path().to_string_lossy().to_string(),
+            display_name: None,
+        };
+
+        let path = music.path().join("rip.flac").to_string_lossy().to_string();
+        let cue_path = music.path().join("rip.cue").to_string_lossy().to_string();
+        let tracks = ["1", "2"]
+            .map(|id| Song {
+                path: path.clone(),
+                cue_path: Some(cue_path.clone()),
+                ..track(id, &directory, "1")
+            })
+            .to_vec();
+
+        let planned = plan_album_moves(
+            &Album::from(tracks),
+            std::slice::from_ref(&directory),
+            &PathRenameOptions::default(),
+            &Organize::default(),
+        )
+        .unwrap_or_else(|_| panic!("Failed to plan moves"));
+
+        assert_eq!(planned.len(), 2);
+        assert_eq!(planned[0].song_ids, ["1", "2"]);
+        assert_eq!(planned[0].to.file_name().unwrap(), "rip.flac");
+        assert!(planned[1].song_ids.is_empty());
+        assert_eq!(planned[1].from, PathBuf::from(&cue_path));
+        assert_eq!(planned[1].to, planned[0].to.with_file_name("rip.cue"));
+    }
+
+    #[test]
+    fn test_resolve_template() {
+        let settings = Organize {
+            templates: BTreeMap::from([(
+                String::from("classical"),
+                String::from("{{composer}}/{{album}}/{{title}}"),
+            )]),
+        };
+
+        let resolve = |template: Option<&str>, template_name: Option<&str>| {
+            let options = PathRenameOptions {
+                template: template.map(String::from),
+                template_name: template_name.map(String::from),
+                ..Default::default()
+            };
+
+            resolve_template(&options, &settings)
+                .map(String::from)
+                .map_err(|response| response.status())
+        };
+
+        assert_eq!(
+            resolve(None, None),
+            Ok(String::from(organize::DEFAULT_TEMPLATE))
+        );
+        assert_eq!(
+            resolve(Some("{{title}}"), None),
+            Ok(String::from("{{title}}"))
+        );
+        assert_eq!(
+            resolve(None, Some("classical")),
+            Ok(String::from("{{composer}}/{{album}}/{{title}}"))
+        );
+        assert_eq!(
+            resolve(None, Some("missing")),
+            Err(axum::http::StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            resolve(Some("{{title}}"), Some("classical")),
+            Err(axum::http::StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            resolve(Some("{{#if album}}"), None),
+            Err(axum::http::StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[test]
+    fn test_plan_with_missing_field() {
+        let music = tempdir().expect("Failed to create temp dir");
+        let directory = Directory {
+            name: String::from("directory"),
+            path: music.path().to_string_lossy().to_string(),
+            display_name: None,
+        };
+
+        let planned = plan_album_moves(
+            &Album::from(vec![track("1", &directory, "1")]),
+            std::slice::from_ref(&directory),
+            &PathRenameOptions {
+                template: Some(String::from("{{composer}}/{{album}}/{{title}}")),
+                ..Default::default()
+            },
+            &Organize::default(),
+        )
+        .unwrap_or_else(|_| panic!("Failed to plan moves"));
+
+        assert_eq!(
+            planned[0].to,
+            music.path().join("Split Album").join("Track 1.flac")
+        );
+
+        let planned = plan_album_moves(
+            &Album::from(vec![Song {
+                composer: Some(String::from("Bach")),
+                ..track("1", &directory, "1")
+            }]),
+            std::slice::from_ref(&directory),
+            &PathRenameOptions {
+                template: Some(String::from("{{composer}}/{{album}}/{{title}}")),
+                ..Default::default()
+            },
+            &Organize::default(),
+        )
+        .unwrap_or_else(|_| panic!("Failed to plan moves"));
+
+        assert_eq!(
+            planned[0].to,
+            music
+                .path()
+                .join("Bach")
+                .join("Split Album")
+                .join("Track 1.flac")
+        );
+    }
+}
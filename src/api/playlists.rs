@@ -0,0 +1,322 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response, Result},
+    routing::{delete, get, post},
+};
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+use ts_rs::TS;
+
+use crate::{
+    AppState,
+    config::Settings,
+    db::{
+        BulkAddResult, NewPlaylist, Playlist, PlaylistImport, PlaylistWithTracks, UpdatedPlaylist,
+        directories, playlists,
+    },
+    jobs::{PlaylistBundle, bundle_path, get_bundle},
+    m3u::{self, PlaylistFormat},
+    paths::bundles_dir,
+    state::{
+        JobManager, Pool,
+        job::{JobParameters, JobStateId},
+    },
+};
+
+use super::{songs::BulkSongs, *};
+
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistSong {
+    pub song_id: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ExportOptions {
+    format: PlaylistFormat,
+}
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportOptions {
+    /// Name of the new playlist, defaults to the name in the file.
+    pub name: Option<String>,
+    /// Directory relative paths are resolved against, defaults to every library directory.
+    pub base_directory: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/playlists/", get(get_playlists).post(create_playlist))
+        .route(
+            "/api/playlists/{id}",
+            get(get_playlist)
+                .put(update_playlist)
+                .delete(delete_playlist),
+        )
+        .route("/api/playlists/import", post(import_playlist))
+        .route("/api/playlists/{id}/export", get(export_playlist))
+        .route(
+            "/api/playlists/{id}/bundle",
+            get(get_playlist_bundle).post(bundle_playlist),
+        )
+        .route(
+            "/api/playlists/{id}/bundle/download",
+            get(download_playlist_bundle),
+        )
+        .route("/api/playlists/{id}/songs", post(add_song))
+        .route("/api/playlists/{id}/tracks/bulk", post(add_songs))
+        .route("/api/playlists/{id}/songs/{song_id}", delete(remove_song))
+}
+
+async fn get_playlists(State(pool): State<Pool>) -> Result<Json<Vec<Playlist>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlists = playlists::get_playlists(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlists))
+}
+
+async fn get_playlist(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+) -> Result<Json<PlaylistWithTracks>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlist = playlists::get_playlist_with_tracks(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlist))
+}
+
+async fn create_playlist(
+    State(pool): State<Pool>,
+    Json(playlist): Json<NewPlaylist>,
+) -> Result<Json<Playlist>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlist = playlists::create_playlist(&mut connection, &playlist.name)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlist))
+}
+
+async fn update_playlist(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+    Json(update): Json<UpdatedPlaylist>,
+) -> Result<Json<PlaylistWithTracks>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+
+    if let Some(name) = &update.name {
+        playlists::rename_playlist(&mut connection, &id, name)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    if let Some(song_ids) = &update.song_ids {
+        playlists::reorder_songs(&mut connection, &id, song_ids)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    let playlist = playlists::get_playlist_with_tracks(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlist))
+}
+
+async fn delete_playlist(State(pool): State<Pool>, Path(id): Path<String>) -> Result<StatusCode> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    playlists::delete_playlist(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn add_song(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+    Json(PlaylistSong { song_id }): Json<PlaylistSong>,
+) -> Result<StatusCode> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    playlists::add_song(&mut connection, &id, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn add_songs(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+    Json(request): Json<BulkSongs>,
+) -> Result<Json<BulkAddResult>> {
+    request.validate()?;
+
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let result = playlists::add_songs(&mut connection, &id, &request.song_ids)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(result))
+}
+
+async fn remove_song(
+    State(pool): State<Pool>,
+    Path((id, song_id)): Path<(String, String)>,
+) -> Result<StatusCode> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    playlists::remove_song(&mut connection, &id, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn export_playlist(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+    Query(ExportOptions { format }): Query<ExportOptions>,
+) -> Result<Response> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let PlaylistWithTracks { playlist, tracks } =
+        playlists::get_playlist_with_tracks(&mut connection, &id)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+    let content = m3u::render(
+        &playlist.name,
+        &tracks.iter().map(m3u::Track::from).collect::<Vec<_>>(),
+    );
+
+    let file_name = format!(
+        "{}.{}",
+        playlist.name.replace(['"', '/', '\\'], "_"),
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        content,
+    )
+        .into_response())
+}
+
+/// Queues a bundle of the playlist for offline listening, replacing its previous bundle once
+/// written.
+async fn bundle_playlist(
+    State(pool): State<Pool>,
+    State(manager): State<JobManager>,
+    Path(id): Path<String>,
+) -> Result<Json<JobStateId>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    playlists::get_playlist(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let parameters = JobParameters {
+        playlist: Some(id),
+        ..Default::default()
+    };
+
+    Ok(Json(
+        manager
+            .queue("bundle-playlist", parameters, false, true)
+            .await?
+            .id(),
+    ))
+}
+
+async fn get_playlist_bundle(
+    State(settings): State<Settings>,
+    Path(id): Path<String>,
+) -> Result<Json<PlaylistBundle>> {
+    get_bundle(&bundles_dir(), &id, settings.bundles.retention())
+        .await
+        .map_err(internal_error)?
+        .map(Json)
+        .ok_or_else(|| not_found("The playlist has no bundle or it has expired").into())
+}
+
+async fn download_playlist_bundle(
+    State(pool): State<Pool>,
+    State(settings): State<Settings>,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlist = playlists::get_playlist(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let Some(bundle) = get_bundle(&bundles_dir(), &id, settings.bundles.retention())
+        .await
+        .map_err(internal_error)?
+    else {
+        return Err(not_found("The playlist has no bundle or it has expired").into());
+    };
+
+    let file = tokio::fs::File::open(bundle_path(&bundles_dir(), &id))
+        .await
+        .map_err(internal_error)?;
+
+    let file_name = format!("{}.zip", playlist.name.replace(['"', '/', '\\'], "_"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, String::from("application/zip")),
+            (header::CONTENT_LENGTH, bundle.size.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+async fn import_playlist(
+    State(pool): State<Pool>,
+    Query(options): Query<ImportOptions>,
+    content: String,
+) -> Result<Json<PlaylistImport>> {
+    let parsed = m3u::parse(&content);
+    let name = options
+        .name
+        .or(parsed.name)
+        .unwrap_or_else(|| String::from("Imported playlist"));
+
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let base_directories = match options.base_directory {
+        Some(directory) => vec![PathBuf::from(directory)],
+        None => directories::get_directories(&mut connection)
+            .await
+            .map_err(IntoResponse::into_response)?
+            .into_iter()
+            .map(|directory| PathBuf::from(directory.path))
+            .collect(),
+    };
+
+    let import =
+        playlists::import_playlist(&mut connection, &name, &parsed.entries, &base_directories)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(import))
+}
@@ -0,0 +1,100 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response, Result},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    providers::{ProviderError, Release, ReleaseSummary, SearchQuery},
+    state::{AppState, SharedProviderRegistry},
+};
+
+use super::*;
+
+/// The provider used when a request doesn't name one.
+const DEFAULT_PROVIDER: &str = "musicbrainz";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/providers", get(get_providers))
+        .route("/api/lookup/search", get(search))
+        .route("/api/lookup/releases/{id}", get(get_release))
+        .route("/api/lookup/releases/{id}/cover-art", get(get_cover_art))
+}
+
+#[derive(Debug, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderInfo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderSelection {
+    provider: Option<String>,
+}
+
+impl ProviderSelection {
+    fn id(&self) -> &str {
+        self.provider.as_deref().unwrap_or(DEFAULT_PROVIDER)
+    }
+}
+
+async fn get_providers(State(providers): State<SharedProviderRegistry>) -> Json<Vec<ProviderInfo>> {
+    Json(
+        providers
+            .providers()
+            .map(|provider| ProviderInfo {
+                id: provider.id().to_string(),
+                name: provider.name().to_string(),
+            })
+            .collect(),
+    )
+}
+
+async fn search(
+    State(providers): State<SharedProviderRegistry>,
+    Query(selection): Query<ProviderSelection>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<ReleaseSummary>>> {
+    if query.is_empty() {
+        return Err(bad_request("An artist, album or title has to be given").into());
+    }
+
+    let provider = providers.get(selection.id())?;
+
+    Ok(Json(provider.search(&query).await?))
+}
+
+async fn get_release(
+    State(providers): State<SharedProviderRegistry>,
+    Path(id): Path<String>,
+    Query(selection): Query<ProviderSelection>,
+) -> Result<Json<Release>> {
+    let provider = providers.get(selection.id())?;
+
+    Ok(Json(provider.release(&id).await?))
+}
+
+async fn get_cover_art(
+    State(providers): State<SharedProviderRegistry>,
+    Path(id): Path<String>,
+    Query(selection): Query<ProviderSelection>,
+) -> Result<Response> {
+    let provider = providers.get(selection.id())?;
+
+    let cover_art = provider
+        .cover_art(&id)
+        .await?
+        .ok_or(ProviderError::NotFound)?;
+
+    Ok((
+        [(header::CONTENT_TYPE, cover_art.mime_type)],
+        cover_art.data,
+    )
+        .into_response())
+}
@@ -0,0 +1,40 @@
+//! Rejects requests of clients built against another version of the API payloads, instead of
+//! letting them misread the responses.
+
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::bindings::SCHEMA_VERSION;
+
+use super::{bad_request, conflict};
+
+/// Header clients send the schema version they were built against in, requests without it aren't
+/// checked.
+pub const SCHEMA_VERSION_HEADER: &str = "x-schema-version";
+
+pub async fn check_schema_version(request: Request, next: Next) -> Response {
+    let Some(header) = request.headers().get(SCHEMA_VERSION_HEADER) else {
+        return next.run(request).await;
+    };
+
+    let version = header
+        .to_str()
+        .ok()
+        .and_then(|version| version.trim().parse::<u32>().ok());
+
+    match version {
+        Some(SCHEMA_VERSION) => next.run(request).await,
+        Some(version) => conflict(format!(
+            "Client expects schema version {version} but the server uses version {SCHEMA_VERSION}, \
+             reload the page or update the client"
+        ))
+        .into_response(),
+        None => bad_request(format!(
+            "Invalid {SCHEMA_VERSION_HEADER} header, expected a version number"
+        ))
+        .into_response(),
+    }
+}
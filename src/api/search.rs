@@ -0,0 +1,53 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    response::{IntoResponse, Result},
+    routing::get,
+};
+use serde::Deserialize;
+use ts_rs::TS;
+
+use crate::{
+    AppState,
+    api::{bad_request, internal_error},
+    db::{SearchResults, search},
+    state::Pool,
+};
+
+/// The amount of songs, albums and artists returned when no limit is given.
+const DEFAULT_LIMIT: u32 = 20;
+
+/// The most songs, albums and artists a search can return.
+const MAX_LIMIT: u32 = 100;
+
+#[derive(Deserialize, TS, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LibrarySearchQuery {
+    /// What to search for, every word has to match the start of a word of the title, artist,
+    /// album, album artist or genre.
+    q: String,
+    /// Maximum amount of songs, albums and artists each, up to 100.
+    limit: Option<u32>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/api/search", get(search_library))
+}
+
+async fn search_library(
+    State(pool): State<Pool>,
+    Query(query): Query<LibrarySearchQuery>,
+) -> Result<Json<SearchResults>> {
+    if query.q.trim().is_empty() {
+        return Err(bad_request("Search query is empty").into());
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let results = search::search(&mut connection, &query.q, limit)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(results))
+}
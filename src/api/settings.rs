@@ -0,0 +1,308 @@
+use std::collections::BTreeSet;
+
+use axum::{
+    Json, Router,
+    extract::{FromRef, State},
+    response::Result,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::Mutex, task::spawn_blocking};
+use ts_rs::TS;
+
+use crate::{
+    config::{Settings, parse_file_types, save_settings},
+    events::{AppEvent, AppEventKind},
+    state::AppState,
+};
+
+use super::*;
+
+/// Sections only read on startup, changes to them are saved but need a restart to take effect.
+const RESTART_SECTIONS: [&str; 6] = [
+    "server",
+    "providers",
+    "jobs",
+    "events",
+    "bundles",
+    "streaming",
+];
+
+/// Settings never sent to clients, as the keys leading to them.
+const SECRET_SETTINGS: [&[&str]; 3] = [
+    &["auth", "tokens"],
+    &["providers", "acoustid", "api_key"],
+    &["storage", "webhook_url"],
+];
+
+/// Held while the settings are updated, so concurrent updates can't undo each other's changes
+/// or write the config file at the same time.
+static UPDATE_LOCK: Mutex<()> = Mutex::const_new(());
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/settings", get(get_settings).patch(update_settings))
+        .route(
+            "/api/settings/file-types",
+            get(get_file_types).put(update_file_types),
+        )
+}
+
+/// The settings after an update, laid out like the config file.
+#[derive(Debug, Serialize)]
+pub struct SettingsUpdate {
+    pub settings: Value,
+    /// Dotted keys of the changed settings that only take effect after a restart, e.g.
+    /// `server.port`.
+    pub requires_restart: Vec<String>,
+}
+
+/// Extensions of the files scanned as songs, without the leading dot.
+#[derive(Debug, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SongFileTypeList {
+    pub file_types: Vec<String>,
+}
+
+async fn get_settings(State(settings): State<Settings>) -> Result<Json<Value>> {
+    Ok(Json(public_settings(&settings).map_err(internal_error)?))
+}
+
+/// Changes the settings with a JSON merge patch, where `null` resets a setting to its default.
+///
+/// Changes are saved to the config file, settings read on startup are still accepted but listed
+/// in `requires_restart`.
+async fn update_settings(
+    State(app): State<AppState>,
+    Json(patch): Json<Value>,
+) -> Result<Json<SettingsUpdate>> {
+    let _lock = UPDATE_LOCK.lock().await;
+    let previous = Settings::from_ref(&app);
+
+    let mut value = serde_json::to_value(&previous).map_err(internal_error)?;
+    merge_patch(&mut value, patch);
+    let settings = Settings {
+        path: previous.path.clone(),
+        ..serde_json::from_value(value).map_err(bad_request)?
+    };
+
+    let changed = apply_settings(&app, previous, settings).await?;
+
+    Ok(Json(SettingsUpdate {
+        settings: public_settings(&Settings::from_ref(&app)).map_err(internal_error)?,
+        requires_restart: changed
+            .into_iter()
+            .filter(|key| {
+                key.split('.')
+                    .next()
+                    .is_some_and(|section| RESTART_SECTIONS.contains(&section))
+            })
+            .collect(),
+    }))
+}
+
+async fn get_file_types(State(app): State<AppState>) -> Json<SongFileTypeList> {
+    Json(SongFileTypeList {
+        file_types: app.song_file_types.get().iter().cloned().collect(),
+    })
+}
+
+/// Replaces the extensions scanned as songs, saving them to the config file.
+///
+/// Songs that were already scanned are kept when their extension is removed, the next scan only
+/// stops picking up new files with it.
+async fn update_file_types(
+    State(app): State<AppState>,
+    Json(SongFileTypeList { file_types }): Json<SongFileTypeList>,
+) -> Result<Json<SongFileTypeList>> {
+    let _lock = UPDATE_LOCK.lock().await;
+    let previous = Settings::from_ref(&app);
+
+    let mut settings = previous.clone();
+    settings.scan.file_types = file_types;
+    apply_settings(&app, previous, settings).await?;
+
+    Ok(Json(SongFileTypeList {
+        file_types: app.song_file_types.get().iter().cloned().collect(),
+    }))
+}
+
+/// Validates and saves the settings, then applies them to the running server.
+///
+/// Returns the dotted keys of the settings that changed, nothing is saved if none did. Callers
+/// must hold [`UPDATE_LOCK`].
+async fn apply_settings(
+    app: &AppState,
+    previous: Settings,
+    mut settings: Settings,
+) -> Result<Vec<String>> {
+    let file_types = parse_file_types(&settings.scan.file_types).map_err(bad_request)?;
+    settings.scan.file_types = file_types.iter().cloned().collect();
+
+    let mut changed = Vec::new();
+    changed_keys(
+        &serde_json::to_value(&previous).map_err(internal_error)?,
+        &serde_json::to_value(&settings).map_err(internal_error)?,
+        "",
+        &mut changed,
+    );
+
+    if changed.is_empty() {
+        return Ok(changed);
+    }
+
+    if let Some(path) = settings.path.clone() {
+        let saved = settings.clone();
+        spawn_blocking(move || save_settings(&path, &previous, &saved))
+            .await
+            .map_err(internal_error)?
+            .map_err(internal_error)?;
+    }
+
+    app.song_file_types.set(file_types);
+    app.cover_art_cache
+        .set_max_size(settings.cache.cover_art_size_limit_mb * 1024 * 1024);
+    *app.settings
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = settings;
+
+    app.events.publish(AppEvent::new(
+        AppEventKind::SettingsChanged,
+        format!("Changed settings: {}", changed.join(", ")),
+    ));
+
+    Ok(changed)
+}
+
+/// The settings as laid out in the config file, without secrets.
+fn public_settings(settings: &Settings) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(settings)?;
+
+    for keys in SECRET_SETTINGS {
+        remove_setting(&mut value, keys);
+    }
+
+    Ok(value)
+}
+
+fn remove_setting(value: &mut Value, keys: &[&str]) {
+    match keys {
+        [key] => {
+            if let Some(table) = value.as_object_mut() {
+                table.remove(*key);
+            }
+        }
+        [key, rest @ ..] => {
+            if let Some(value) = value.get_mut(*key) {
+                remove_setting(value, rest);
+            }
+        }
+        [] => {}
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7396) to `target`.
+fn merge_patch(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge_patch(target.entry(key).or_insert(Value::Null), value);
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+/// Collects the dotted keys of the values that differ between `previous` and `current`.
+fn changed_keys(previous: &Value, current: &Value, prefix: &str, changed: &mut Vec<String>) {
+    match (previous, current) {
+        (Value::Object(previous), Value::Object(current)) => {
+            let keys = previous
+                .keys()
+                .chain(current.keys())
+                .collect::<BTreeSet<_>>();
+
+            for key in keys {
+                let key_path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+
+                match (previous.get(key), current.get(key)) {
+                    (Some(previous), Some(current)) => {
+                        changed_keys(previous, current, &key_path, changed)
+                    }
+                    _ => changed.push(key_path),
+                }
+            }
+        }
+        (previous, current) if previous != current => changed.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut value = json!({
+            "server": { "port": 8080, "host": "127.0.0.1" },
+            "organize": { "templates": { "flat": "{{title}}" } },
+        });
+
+        merge_patch(
+            &mut value,
+            json!({
+                "server": { "host": null },
+                "organize": { "templates": { "nested": "{{album}}/{{title}}" } },
+            }),
+        );
+
+        assert_eq!(
+            value,
+            json!({
+                "server": { "port": 8080 },
+                "organize": {
+                    "templates": { "flat": "{{title}}", "nested": "{{album}}/{{title}}" }
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let mut changed = Vec::new();
+        changed_keys(
+            &json!({ "server": { "port": 8080 }, "scan": { "file_types": ["mp3"] } }),
+            &json!({
+                "server": { "port": 9090, "host": "0.0.0.0" },
+                "scan": { "file_types": ["mp3"] },
+            }),
+            "",
+            &mut changed,
+        );
+
+        assert_eq!(changed, ["server.host", "server.port"]);
+    }
+
+    #[test]
+    fn test_public_settings_hide_secrets() {
+        let mut settings = Settings::default();
+        settings.providers.acoustid.api_key = Some(String::from("secret"));
+
+        let value = public_settings(&settings).unwrap();
+
+        assert!(value["providers"]["acoustid"].get("api_key").is_none());
+        assert!(value["providers"]["acoustid"].get("enabled").is_some());
+    }
+}
@@ -0,0 +1,117 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Result},
+    routing::get,
+};
+
+use crate::{
+    AppState,
+    db::{NewSmartPlaylist, Page, SmartPlaylist, Song, SongQuery, smart_playlists},
+    state::{Pool, SharedDirectoryCache},
+};
+
+use super::{
+    include::{Expanded, IncludeQuery, expand_song},
+    *,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/smart-playlists/",
+            get(get_smart_playlists).post(create_smart_playlist),
+        )
+        .route(
+            "/api/smart-playlists/{id}",
+            get(get_smart_playlist)
+                .put(update_smart_playlist)
+                .delete(delete_smart_playlist),
+        )
+        .route(
+            "/api/smart-playlists/{id}/songs",
+            get(get_smart_playlist_songs),
+        )
+}
+
+async fn get_smart_playlists(State(pool): State<Pool>) -> Result<Json<Vec<SmartPlaylist>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlists = smart_playlists::get_smart_playlists(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlists))
+}
+
+async fn get_smart_playlist(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+) -> Result<Json<SmartPlaylist>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlist = smart_playlists::get_smart_playlist(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlist))
+}
+
+async fn create_smart_playlist(
+    State(pool): State<Pool>,
+    Json(playlist): Json<NewSmartPlaylist>,
+) -> Result<Json<SmartPlaylist>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlist = smart_playlists::create_smart_playlist(&mut connection, playlist)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlist))
+}
+
+async fn update_smart_playlist(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+    Json(playlist): Json<NewSmartPlaylist>,
+) -> Result<Json<SmartPlaylist>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let playlist = smart_playlists::update_smart_playlist(&mut connection, &id, playlist)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(playlist))
+}
+
+async fn delete_smart_playlist(
+    State(pool): State<Pool>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    smart_playlists::delete_smart_playlist(&mut connection, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Evaluates the playlist against the library as it is now, sorted and paged like `/api/songs`.
+async fn get_smart_playlist_songs(
+    State(pool): State<Pool>,
+    State(directory_cache): State<SharedDirectoryCache>,
+    Path(id): Path<String>,
+    Query(query): Query<SongQuery>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<Page<Expanded<Song>>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let Page { items, total } =
+        smart_playlists::get_smart_playlist_songs(&mut connection, &id, query)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+    let directories = directory_cache.get();
+    let items = items
+        .into_iter()
+        .map(|song| expand_song(song, &include, &directories))
+        .collect();
+
+    Ok(Json(Page { items, total }))
+}
@@ -1,97 +1,1259 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    sync::PoisonError,
+};
 
 use axum::{
     Json, Router,
-    extract::{Path, State},
-    http::StatusCode,
-    response::{IntoResponse, Result},
-    routing::{get, post, put},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response, Result},
+    routing::{delete, get, post, put},
 };
+use futures::StreamExt;
 use time::{OffsetDateTime, UtcDateTime};
-use tokio::task::spawn_blocking;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    task::spawn_blocking,
+};
+use tokio_util::io::ReaderStream;
+use ts_rs::TS;
 
 use crate::{
     AppState,
-    db::{Song, UpdatedSong, songs},
-    metadata::{Metadata as SongMetadata, SongFile},
-    paths::metadata_history_dir,
+    config::Settings,
+    db::{
+        BulkAddResult, CueRange, Page, Song, SongQuery, UpdatedSong, UpdatedSongPreferences,
+        directories, songs, suggestions,
+    },
+    duplicates,
+    fs::{Operation, OperationEvent},
+    history::{self, FieldChange},
+    infer::{InferredMetadata, Pattern, infer},
+    jobs::{identify_song, is_song_file},
+    metadata::{
+        CoverArtType, FileHealth, Metadata as SongMetadata, MetadataSchema, SongFile, TagWarning,
+        item::ItemKey,
+        position::{Position, parse_count},
+        rating::{MAX_RATING, parse_rating, write_rating},
+        read_metadata_from_path, read_properties_from_path, remove_cover_art, set_cover_art,
+        validate_tags,
+    },
+    paths::{metadata_history_dir, trash_dir},
+    providers::IdentifyCandidate,
+    state::{
+        SharedCoverArtCache, SharedDirectoryCache, SharedProviderRegistry, SharedSongFileTypes,
+        TranscodePermits,
+    },
+    transcode::{BITRATES_KBPS, Transcode, TranscodeFormat},
 };
 
-use super::*;
+use super::{
+    include::{Expanded, IncludeQuery, expand_song},
+    maintenance::OutsideMaintenance,
+    trash::purge_expired_trash,
+    *,
+};
 
 type SongId = String;
 
+/// How a song is streamed, as it's stored unless a `format` is given.
+#[derive(serde::Deserialize, Debug, Default, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StreamOptions {
+    /// Format the song is transcoded to.
+    pub format: Option<TranscodeFormat>,
+    /// Bitrate in kbit/s, from 32 to 320. Defaults to the one in the streaming settings.
+    pub bitrate: Option<u32>,
+}
+
+/// A play reported by a client, once the song ended or playback moved on from it.
+#[derive(serde::Deserialize, Debug, Default, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlayReport {
+    /// How far into the song playback stopped, plays stopped early count as skips, see
+    /// [`songs::SKIP_THRESHOLD_MS`].
+    pub position_ms: Option<u32>,
+}
+
+/// Headers reporting where playback of a streamed song starts and ends, see
+/// [`Song::start_offset_ms`].
+const START_OFFSET_HEADER: &str = "x-start-offset-ms";
+const END_OFFSET_HEADER: &str = "x-end-offset-ms";
+
+/// The most songs that can be given to a single bulk request.
+pub const MAX_BULK_SONGS: usize = 5000;
+
+/// How far the duration of a file a song is relocated to can be off from the song's duration.
+const RELOCATE_DURATION_TOLERANCE_MS: u32 = 2000;
+
+#[derive(serde::Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkSongs {
+    pub song_ids: Vec<SongId>,
+}
+
+impl BulkSongs {
+    /// Checks the amount of songs is within [`MAX_BULK_SONGS`].
+    pub fn validate(&self) -> Result<(), (StatusCode, String)> {
+        validate_song_ids(&self.song_ids)
+    }
+}
+
+fn validate_song_ids(song_ids: &[SongId]) -> Result<(), (StatusCode, String)> {
+    if song_ids.is_empty() {
+        return Err(bad_request("No songs given"));
+    }
+
+    if song_ids.len() > MAX_BULK_SONGS {
+        return Err(bad_request(format!(
+            "Too many songs given, at most {MAX_BULK_SONGS} songs can be given at once"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Changes to apply to the metadata of every given song.
+#[derive(serde::Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkMetadataEdit {
+    pub song_ids: Vec<SongId>,
+    /// The fields to change, a `null` value removes the field. Fields that aren't given are left
+    /// untouched.
+    pub changes: BTreeMap<ItemKey, Option<String>>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkEditStatus {
+    Updated,
+    /// The song already had the requested metadata.
+    Unchanged,
+    SongNotFound,
+    FileNotFound,
+    WriteFailed,
+    /// The file was written but the library couldn't be updated, a scan picks up the new metadata.
+    UpdateFailed,
+}
+
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkEditResult {
+    pub song_id: SongId,
+    pub status: BulkEditStatus,
+    pub error: Option<String>,
+    /// Changed fields the tag format of the song can't hold, which were left out of its file.
+    pub dropped_fields: Vec<ItemKey>,
+}
+
+impl BulkEditResult {
+    fn new(song_id: SongId, status: BulkEditStatus) -> Self {
+        Self {
+            song_id,
+            status,
+            error: None,
+            dropped_fields: Vec::new(),
+        }
+    }
+}
+
+/// The outcome of writing the metadata of a song to its file.
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataWrite {
+    /// Fields the tag format of the song can't hold, which were left out of its file.
+    pub dropped_fields: Vec<ItemKey>,
+}
+
+#[derive(serde::Deserialize, TS)]
+pub struct RelocateSong {
+    /// The absolute path the file was moved to.
+    pub path: String,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct RelocateOptions {
+    /// Relocate the song even if the metadata of the file doesn't match.
+    force: bool,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct DeleteOptions {
+    /// Delete the file of the song as well, the song is only removed once its file is gone.
+    delete_file: bool,
+    /// Delete the file for good instead of moving it to the trash.
+    permanent: bool,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct DownloadOptions {
+    /// Name the download after the file on disk instead of the song's artist and title.
+    original_name: bool,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct PurgeOptions {
+    /// Only purge the songs that have been missing for at least this many days.
+    older_than_days: Option<u32>,
+}
+
+/// Songs deleted because their file went missing.
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgedSongs {
+    pub song_ids: Vec<SongId>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkDeleteStatus {
+    Deleted,
+    SongNotFound,
+    /// The file isn't inside any of the library directories, so neither it nor the song was
+    /// removed.
+    OutsideLibrary,
+    /// The file holds every track of a cue sheet, deleting it would remove the other tracks too.
+    CueTrack,
+    DeleteFailed,
+}
+
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkDeleteResult {
+    pub song_id: SongId,
+    pub status: BulkDeleteStatus,
+    pub error: Option<String>,
+}
+
+impl BulkDeleteResult {
+    fn new(song_id: SongId, status: BulkDeleteStatus) -> Self {
+        Self {
+            song_id,
+            status,
+            error: None,
+        }
+    }
+}
+
+/// A song's file along with whether it can be read and written right now, what its tag format
+/// can hold and any problems with its tags.
+#[derive(serde::Serialize, TS)]
+pub struct SongFileInfo {
+    #[serde(flatten)]
+    pub file: SongFile,
+    #[serde(flatten)]
+    pub health: FileHealth,
+    pub capabilities: MetadataSchema,
+    pub warnings: Vec<TagWarning>,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct FileInfoOptions {
+    /// Open the file for appending to check it's writable, see [`FileHealth::check`].
+    probe: bool,
+}
+
+/// Returned when the file at the new path doesn't look like the same song.
+#[derive(serde::Serialize, TS)]
+pub struct RelocateMismatch {
+    pub song: Song,
+    pub file: SongFile,
+}
+
+/// The lyrics embedded in a song's file.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SongLyrics {
+    /// Unsynchronized lyrics, synchronized lyrics are kept as their raw LRC text.
+    pub lyrics: Option<String>,
+    /// Whether the lyrics have LRC timestamps, ignored when editing.
+    #[serde(default)]
+    pub synchronized: bool,
+}
+
+impl SongLyrics {
+    fn new(lyrics: Option<String>) -> Self {
+        Self {
+            synchronized: lyrics.as_deref().is_some_and(is_synchronized),
+            lyrics,
+        }
+    }
+}
+
+/// Whether any line of the lyrics starts with an LRC timestamp like `[01:23.45]`.
+fn is_synchronized(lyrics: &str) -> bool {
+    lyrics.lines().any(|line| {
+        line.trim_start()
+            .strip_prefix('[')
+            .and_then(|line| line.split_once(']'))
+            .and_then(|(timestamp, _)| timestamp.split_once(':'))
+            .is_some_and(|(minutes, seconds)| {
+                !minutes.is_empty()
+                    && minutes.bytes().all(|byte| byte.is_ascii_digit())
+                    && seconds.parse::<f32>().is_ok()
+            })
+    })
+}
+
+/// The rating of a song, `null` removes it.
+#[derive(serde::Deserialize, Debug, TS)]
+pub struct SongRating {
+    /// From `0` to `100`, see [`rating`](crate::metadata::rating) for the scale.
+    pub rating: Option<u8>,
+}
+
+/// Checks a rating given as a metadata field is on the scale of
+/// [`rating`](crate::metadata::rating).
+fn validate_rating(rating: Option<&str>) -> Result<(), (StatusCode, String)> {
+    match rating {
+        Some(rating) if parse_rating(rating).is_none() => Err(bad_request(format!(
+            "Rating must be a number from 0 to {MAX_RATING}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[derive(serde::Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyIdentification {
+    /// Id of the suggested recording to tag the song as.
+    pub recording_id: String,
+}
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/songs/", get(get_songs))
-        .route("/api/songs/{id}", get(get_song))
-        .route("/api/songs/{id}/file-info", post(get_song_file))
+        .route("/api/songs/favorites", get(get_favorites))
+        .route("/api/songs/favorites/bulk", post(add_favorites))
+        .route("/api/songs/duplicates", get(get_duplicates))
+        .route("/api/songs/metadata/bulk", put(edit_songs))
+        .route("/api/songs/bulk", delete(delete_songs))
+        .route("/api/songs/missing", delete(purge_missing_songs))
+        .route("/api/songs/{id}", get(get_song).delete(delete_song))
+        .route("/api/songs/{id}/stream", get(stream_or_transcode_song))
+        .route("/api/songs/{id}/download", get(download_song))
+        .route(
+            "/api/songs/{id}/file-info",
+            get(get_song_file).post(get_song_file),
+        )
+        .route("/api/songs/{id}/played", post(record_play))
         .route("/api/songs/{id}/refresh", post(refresh_song_details))
-        .route("/api/songs/{id}", put(edit_song))
+        .route("/api/songs/{id}/relocate", post(relocate_song))
+        .route(
+            "/api/songs/{id}",
+            put(edit_song).patch(update_song_preferences),
+        )
         .route(
             "/api/songs/{id}/metadata/restore/{timestamp}",
             post(restore_metadata),
         )
+        .route("/api/songs/{id}/metadata/schema", get(get_metadata_schema))
+        .route("/api/songs/{id}/rating", put(edit_song_rating))
+        .route(
+            "/api/songs/{id}/lyrics",
+            get(get_song_lyrics).put(edit_song_lyrics),
+        )
         .route(
             "/api/songs/{id}/metadata/history",
-            post(get_song_metadata_history),
+            post(get_song_metadata_history).delete(clear_song_metadata_history),
+        )
+        .route(
+            "/api/songs/{id}/metadata/history/{timestamp}/diff",
+            get(get_metadata_history_diff),
+        )
+        .route("/api/songs/{id}/infer", get(preview_inferred_metadata))
+        .route("/api/songs/{id}/identify", get(get_song_identification))
+        .route(
+            "/api/songs/{id}/identify/apply",
+            post(apply_song_identification),
         )
 }
 
 async fn get_song(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(directory_cache): State<SharedDirectoryCache>,
+    Path(song_id): Path<SongId>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<Expanded<Song>>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(expand_song(song, &include, &directory_cache.get())))
+}
+
+/// Changes whether the song is shuffled and where its playback starts and ends.
+async fn update_song_preferences(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+    Json(preferences): Json<UpdatedSongPreferences>,
+) -> Result<Json<Song>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::update_song_preferences(&mut connection, &song_id, preferences)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(song))
+}
+
+/// Records a play of the song and returns it with its updated counters, a report without a
+/// position counts as a full play.
+async fn record_play(
     State(db): State<sqlx::Pool<sqlx::Sqlite>>,
     Path(song_id): Path<SongId>,
+    report: Option<Json<PlayReport>>,
 ) -> Result<Json<Song>> {
+    let position_ms = report.and_then(|Json(report)| report.position_ms);
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::record_play(
+        &mut connection,
+        &song_id,
+        position_ms,
+        OffsetDateTime::now_utc(),
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(song))
+}
+
+async fn get_songs(
+    State(pool): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(directory_cache): State<SharedDirectoryCache>,
+    Query(query): Query<SongQuery>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<Page<Expanded<Song>>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let Page { items, total } = songs::get_songs_paginated(&mut connection, query)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let directories = directory_cache.get();
+    let items = items
+        .into_iter()
+        .map(|song| expand_song(song, &include, &directories))
+        .collect();
+
+    Ok(Json(Page { items, total }))
+}
+
+async fn get_favorites(State(pool): State<sqlx::Pool<sqlx::Sqlite>>) -> Result<Json<Vec<Song>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let songs = songs::get_favorites(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(songs))
+}
+
+/// Lists the songs that are in the library more than once, each group starts with the song added
+/// first.
+async fn get_duplicates(
+    State(pool): State<sqlx::Pool<sqlx::Sqlite>>,
+) -> Result<Json<Vec<Vec<Song>>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let songs = songs::get_titled_songs(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(
+        duplicates::group_duplicates(&songs)
+            .into_iter()
+            .map(|group| group.into_iter().cloned().collect())
+            .collect(),
+    ))
+}
+
+async fn add_favorites(
+    State(pool): State<sqlx::Pool<sqlx::Sqlite>>,
+    Json(request): Json<BulkSongs>,
+) -> Result<Json<BulkAddResult>> {
+    request.validate()?;
+
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let result = songs::add_favorites(&mut connection, &request.song_ids)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(result))
+}
+
+pub(super) async fn stream_song(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+    headers: HeaderMap,
+) -> Result<Response> {
     let mut connection = db.acquire().await.map_err(internal_error)?;
     let song = songs::get_song(&mut connection, &song_id)
         .await
-        .map(Json)
         .map_err(IntoResponse::into_response)?;
 
-    Ok(song)
+    drop(connection);
+
+    let path = PathBuf::from(&song.path);
+    let mut response = match song.cue_range() {
+        Some(cue_range) => stream_cue_track(&path, &cue_range, headers.get(header::RANGE)).await,
+        None => stream_file(&path, headers.get(header::RANGE)).await,
+    }?;
+
+    // The audio isn't trimmed, clients skip the silence themselves.
+    for (name, offset) in [
+        (START_OFFSET_HEADER, song.start_offset_ms),
+        (END_OFFSET_HEADER, song.end_offset_ms),
+    ] {
+        if let Some(offset) = offset {
+            response
+                .headers_mut()
+                .insert(name, HeaderValue::from(offset));
+        }
+    }
+
+    Ok(response)
 }
 
-async fn get_songs(State(pool): State<sqlx::Pool<sqlx::Sqlite>>) -> Result<Json<Vec<Song>>> {
-    let songs = songs::get_songs(&pool)
+/// Streams the song as it's stored unless a `format` is requested, in which case it's transcoded
+/// with ffmpeg while it's sent. Transcoded streams can't be seeked and have their offsets trimmed.
+async fn stream_or_transcode_song(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(settings): State<Settings>,
+    State(transcodes): State<TranscodePermits>,
+    Path(song_id): Path<SongId>,
+    Query(options): Query<StreamOptions>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(format) = options.format else {
+        return stream_song(State(db), Path(song_id), headers).await;
+    };
+
+    let Some(ffmpeg) = settings.streaming.ffmpeg else {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "Transcoding needs the path to ffmpeg set in the streaming settings",
+        )
+            .into());
+    };
+
+    let bitrate_kbps = options.bitrate.unwrap_or(settings.streaming.bitrate_kbps);
+    if !BITRATES_KBPS.contains(&bitrate_kbps) {
+        return Err(bad_request(format!(
+            "Bitrate must be between {} and {} kbit/s",
+            BITRATES_KBPS.start(),
+            BITRATES_KBPS.end()
+        ))
+        .into());
+    }
+
+    let Ok(permit) = transcodes.try_acquire_owned() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many songs are being transcoded, try again later",
+        )
+            .into());
+    };
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
         .await
-        .map(Json)
         .map_err(IntoResponse::into_response)?;
 
-    Ok(songs)
+    drop(connection);
+
+    let path = PathBuf::from(&song.path);
+    drop(open_file(&path).await?);
+
+    let transcode = Transcode::spawn(
+        &ffmpeg,
+        &path,
+        song.playback_range(),
+        format,
+        bitrate_kbps,
+        permit,
+    )
+    .map_err(internal_error)?;
+
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type())],
+        Body::from_stream(transcode),
+    )
+        .into_response())
+}
+
+/// Sends the file of the song as an attachment, streamed from disk.
+///
+/// Tracks of a cue sheet download the whole file they're stored in, named after the file. A song
+/// whose file is gone is marked missing.
+async fn download_song(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+    Query(options): Query<DownloadOptions>,
+) -> Result<Response> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let file = match tokio::fs::File::open(&song.path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            songs::mark_songs_missing(
+                &mut connection,
+                std::slice::from_ref(&song.id),
+                OffsetDateTime::now_utc(),
+            )
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+            return Err(not_found(format!("File \"{}\" not found", song.path)).into());
+        }
+        Err(err) => return Err(internal_error(err).into()),
+    };
+
+    drop(connection);
+
+    let length = file.metadata().await.map_err(internal_error)?.len();
+    let mime = mime_guess::from_path(&song.path).first_or_octet_stream();
+    let file_name = download_file_name(&song, options.original_name || song.cue_path.is_some());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, mime.essence_str().to_string()),
+            (header::CONTENT_LENGTH, length.to_string()),
+            (header::CONTENT_DISPOSITION, attachment(&file_name)),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// Names a downloaded song `Artist - Title` with the extension of its file, or after the file if
+/// the song has no title or `original` is set.
+fn download_file_name(song: &Song, original: bool) -> String {
+    let path = std::path::Path::new(&song.path);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let Some(title) = song.title.as_ref().filter(|_| !original) else {
+        return file_name;
+    };
+
+    let name = sanitize_filename::sanitize(match &song.artist {
+        Some(artist) => format!("{artist} - {title}"),
+        None => title.clone(),
+    });
+
+    match path.extension() {
+        Some(extension) => format!("{name}.{}", extension.to_string_lossy()),
+        None => name,
+    }
+}
+
+async fn open_file(path: &std::path::Path) -> Result<tokio::fs::File> {
+    match tokio::fs::File::open(path).await {
+        Ok(file) => Ok(file),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Err(not_found(format!("File \"{}\" not found", path.display())).into())
+        }
+        Err(err) => Err(internal_error(err).into()),
+    }
+}
+
+/// Streams a file from disk, only sending the requested byte range if a `Range` header is given.
+async fn stream_file(path: &std::path::Path, range: Option<&HeaderValue>) -> Result<Response> {
+    let file = open_file(path).await?;
+    let length = file.metadata().await.map_err(internal_error)?.len();
+
+    stream_parts(path, Vec::new(), file, 0..length, range).await
+}
+
+/// Streams a track of a cue sheet out of the FLAC file it shares with the other tracks.
+///
+/// FLAC frames can't be located without decoding the file, so the bytes of the track are
+/// estimated from its position in the file. The metadata blocks of the file are sent in front of
+/// the audio, decoders skip ahead to the first frame after the estimated start.
+async fn stream_cue_track(
+    path: &std::path::Path,
+    cue_range: &CueRange,
+    range: Option<&HeaderValue>,
+) -> Result<Response> {
+    let mut file = open_file(path).await?;
+    let length = file.metadata().await.map_err(internal_error)?.len();
+
+    let audio_offset = flac_audio_offset(&mut file).await.map_err(internal_error)?;
+
+    let mut header = vec![0; audio_offset as usize];
+    file.seek(std::io::SeekFrom::Start(0))
+        .await
+        .map_err(internal_error)?;
+    file.read_exact(&mut header).await.map_err(internal_error)?;
+
+    let properties_path = path.to_path_buf();
+    let duration_ms = spawn_blocking(move || read_properties_from_path(&properties_path))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?
+        .duration_ms
+        .ok_or_else(|| internal_error(format!("Unknown duration of \"{}\"", path.display())))?;
+
+    let bytes = estimate_track_bytes(
+        audio_offset,
+        length,
+        duration_ms,
+        cue_range.start_ms,
+        cue_range.end_ms,
+    );
+
+    stream_parts(path, header, file, bytes, range).await
+}
+
+/// Returns where the audio frames of a FLAC file start, right after the last metadata block.
+async fn flac_audio_offset(file: &mut tokio::fs::File) -> std::io::Result<u64> {
+    let mut marker = [0; 4];
+    file.seek(std::io::SeekFrom::Start(0)).await?;
+    file.read_exact(&mut marker).await?;
+
+    if &marker != b"fLaC" {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "File is not a FLAC file",
+        ));
+    }
+
+    loop {
+        let mut block_header = [0; 4];
+        file.read_exact(&mut block_header).await?;
+
+        let length = u32::from_be_bytes([0, block_header[1], block_header[2], block_header[3]]);
+        let position = file
+            .seek(std::io::SeekFrom::Current(i64::from(length)))
+            .await?;
+
+        if block_header[0] & 0x80 != 0 {
+            return Ok(position);
+        }
+    }
+}
+
+/// Estimates which bytes of the file a track is stored in, assuming a constant bitrate.
+fn estimate_track_bytes(
+    audio_offset: u64,
+    length: u64,
+    duration_ms: u32,
+    start_ms: u32,
+    end_ms: Option<u32>,
+) -> std::ops::Range<u64> {
+    let audio_length = length.saturating_sub(audio_offset);
+    let offset = |ms: u32| {
+        audio_offset + audio_length * u64::from(ms.min(duration_ms)) / u64::from(duration_ms.max(1))
+    };
+
+    offset(start_ms)..end_ms.map(offset).unwrap_or(length)
+}
+
+/// Streams `prefix` followed by `bytes` of the file, only sending the requested byte range of
+/// the two if a `Range` header is given.
+async fn stream_parts(
+    path: &std::path::Path,
+    prefix: Vec<u8>,
+    mut file: tokio::fs::File,
+    bytes: std::ops::Range<u64>,
+    range: Option<&HeaderValue>,
+) -> Result<Response> {
+    let prefix_length = prefix.len() as u64;
+    let length = prefix_length + (bytes.end - bytes.start);
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, mime.essence_str())
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    // Both ends are exclusive from here on.
+    let (response, start, end) = match range {
+        None => (response.status(StatusCode::OK), 0, length),
+        Some(range) => {
+            let Some((start, end)) = range
+                .to_str()
+                .ok()
+                .and_then(|range| parse_range(range, length))
+            else {
+                return response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{length}"))
+                    .body(Body::empty())
+                    .map_err(|err| internal_error(err).into());
+            };
+
+            (
+                response.status(StatusCode::PARTIAL_CONTENT).header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {start}-{end}/{length}"),
+                ),
+                start,
+                end + 1,
+            )
+        }
+    };
+
+    let prefix =
+        prefix[start.min(prefix_length) as usize..end.min(prefix_length) as usize].to_vec();
+    let file_length = end.saturating_sub(start.max(prefix_length));
+
+    file.seek(std::io::SeekFrom::Start(
+        bytes.start + start.saturating_sub(prefix_length),
+    ))
+    .await
+    .map_err(internal_error)?;
+
+    let body = futures::stream::once(async move { Ok(Bytes::from(prefix)) })
+        .chain(ReaderStream::new(file.take(file_length)));
+
+    response
+        .header(header::CONTENT_LENGTH, end - start)
+        .body(Body::from_stream(body))
+        .map_err(|err| internal_error(err).into())
+}
+
+/// Parses a single `bytes=` range, returning the inclusive start and end offsets.
+///
+/// Returns `None` if the range is malformed or can't be satisfied.
+fn parse_range(range: &str, length: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let (start, end) = if start.is_empty() {
+        let suffix = end.parse::<u64>().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+
+        (length.saturating_sub(suffix), length.checked_sub(1)?)
+    } else {
+        let start = start.parse::<u64>().ok()?;
+        let end = match end {
+            "" => length.checked_sub(1)?,
+            end => end.parse::<u64>().ok()?.min(length.checked_sub(1)?),
+        };
+
+        (start, end)
+    };
+
+    (start <= end && start < length).then_some((start, end))
 }
 
 async fn get_song_file(
     State(db): State<sqlx::Pool<sqlx::Sqlite>>,
     Path(song_id): Path<SongId>,
-) -> Result<Json<SongFile>> {
+    Query(options): Query<FileInfoOptions>,
+) -> Result<Json<SongFileInfo>> {
     let mut connection = db.acquire().await.map_err(internal_error)?;
     let path = songs::get_song_path(&mut connection, &song_id)
         .await
         .map_err(IntoResponse::into_response)?;
 
-    let file = read_song_file(path).await?;
+    let health_path = path.clone();
+    let health = spawn_blocking(move || FileHealth::check(&health_path, options.probe))
+        .await
+        .map_err(internal_error)?;
+    let file = read_song_file(path.clone()).await?;
+
+    let warnings = spawn_blocking(move || validate_tags(&path))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    Ok(Json(SongFileInfo {
+        capabilities: MetadataSchema::new(file.tag_type()),
+        file,
+        health,
+        warnings,
+    }))
+}
+
+/// Rejects edits of files that can't be written, before lofty fails halfway with a generic error.
+async fn ensure_editable(path: PathBuf) -> Result<()> {
+    spawn_blocking(move || FileHealth::check(&path, true))
+        .await
+        .map_err(internal_error)?
+        .ensure_editable()
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(())
+}
+
+async fn refresh_song_details(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
+    Path(song_id): Path<SongId>,
+) -> Result<Json<Option<SongMetadata>>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let file = read_song_file(PathBuf::from(&song.path)).await?;
+    let metadata = file.metadata().clone();
+    let updated_song = UpdatedSong::from(file);
+    let albums = [song.album, updated_song.album.clone()];
+
+    songs::update_song(&mut connection, &song_id, updated_song)
+        .await
+        .map_err(internal_error)?;
+
+    invalidate_cover_art(&cover_art_cache, albums).await;
+
+    Ok(Json(metadata))
+}
+
+/// Points the song at a file that was moved outside of the app.
+async fn relocate_song(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(song_file_types): State<SharedSongFileTypes>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
+    Path(song_id): Path<SongId>,
+    Query(RelocateOptions { force }): Query<RelocateOptions>,
+    Json(RelocateSong { path }): Json<RelocateSong>,
+) -> Result<Json<Song>> {
+    let new_path = PathBuf::from(&path);
+
+    if !new_path.is_absolute() {
+        return Err(bad_request("Path must be absolute").into());
+    }
+
+    if !new_path.is_file() {
+        return Err(bad_request(format!("File \"{path}\" does not exist")).into());
+    }
+
+    if !is_song_file(&new_path, &song_file_types.get()) {
+        return Err(bad_request(format!("File \"{path}\" is not a supported song file")).into());
+    }
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // The tracks of a cue sheet share their file with the sheet, moving one would split them.
+    if let Some(cue_path) = &song.cue_path {
+        return Err(bad_request(format!(
+            "The song is a track of \"{cue_path}\", move the cue sheet and its file together and scan them instead"
+        ))
+        .into());
+    }
+
+    if !directories::get_directories(&mut connection)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .iter()
+        .any(|directory| new_path.starts_with(&directory.path))
+    {
+        return Err(
+            bad_request(format!("File \"{path}\" is not in a registered directory")).into(),
+        );
+    }
+
+    // The path is unique, pointing the song at another song's file would replace that song.
+    if let Some(other_id) = songs::get_song_id_by_path(&mut connection, &path)
+        .await
+        .map_err(IntoResponse::into_response)?
+        && other_id != song_id
+    {
+        return Err(conflict(format!("File \"{path}\" belongs to song {other_id}")).into());
+    }
+
+    let file = read_song_file(new_path).await?;
+
+    if !force && !is_same_song(&song, &file) {
+        return Err(
+            (StatusCode::CONFLICT, Json(RelocateMismatch { song, file }))
+                .into_response()
+                .into(),
+        );
+    }
+
+    songs::update_song_path(&mut connection, &song_id, &path)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    invalidate_cover_art(&cover_art_cache, [song.album.clone()]).await;
+
+    Ok(Json(song))
+}
+
+/// Whether the file roughly matches the song.
+///
+/// Durations within [`RELOCATE_DURATION_TOLERANCE_MS`] match and durations further apart don't,
+/// whatever the titles are. Without a duration on both sides, titles are compared, or file names
+/// if the song has no title.
+fn is_same_song(song: &Song, file: &SongFile) -> bool {
+    let file_duration = file
+        .properties()
+        .and_then(|properties| properties.duration_ms);
+    if let (Some(duration), Some(file_duration)) = (song.duration_ms, file_duration) {
+        return duration.abs_diff(file_duration) <= RELOCATE_DURATION_TOLERANCE_MS;
+    }
+
+    let normalize = |value: &str| value.trim().to_lowercase();
+
+    match &song.title {
+        Some(title) => UpdatedSong::from(file.clone())
+            .title
+            .is_some_and(|file_title| normalize(&file_title) == normalize(title)),
+        None => {
+            let stem = |path: &std::path::Path| {
+                path.file_stem()
+                    .map(|stem| normalize(&stem.to_string_lossy()))
+            };
+
+            stem(std::path::Path::new(&song.path)) == stem(file.path())
+        }
+    }
+}
+
+/// Lists which metadata fields can be edited, based on the tag format of the song's file.
+async fn get_metadata_schema(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+) -> Result<Json<MetadataSchema>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let path = songs::get_song_path(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    drop(connection);
+
+    let file = read_song_file(path).await?;
+
+    Ok(Json(MetadataSchema::new(file.tag_type())))
+}
+
+async fn get_song_lyrics(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+) -> Result<Json<SongLyrics>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(SongLyrics::new(song.lyrics)))
+}
+
+/// Writes the lyrics to the song's file, `null` or blank lyrics remove them.
+///
+/// The previous metadata is saved to the history like any other edit.
+async fn edit_song_lyrics(
+    _: OutsideMaintenance,
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+    Json(SongLyrics { lyrics, .. }): Json<SongLyrics>,
+) -> Result<Json<SongLyrics>> {
+    let lyrics = lyrics.filter(|lyrics| !lyrics.trim().is_empty());
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if song.cue_path.is_some() {
+        return Err(
+            bad_request("Tracks of a cue sheet share one file and can't have lyrics").into(),
+        );
+    }
+
+    let id = song_id.clone();
+    let path = PathBuf::from(&song.path);
+    ensure_editable(path.clone()).await?;
+
+    let changes = BTreeMap::from([(ItemKey::Lyrics, lyrics.clone())]);
+    let saved = spawn_blocking(move || {
+        apply_metadata_changes(&id, &path, &changes)?;
+
+        Ok::<_, color_eyre::Report>(
+            read_metadata_from_path(&path)?
+                .get(&ItemKey::Lyrics)
+                .cloned(),
+        )
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    // Formats without a lyrics field, like ID3v1, silently drop them on write.
+    if saved != lyrics {
+        return Err(bad_request("The tag format of the song can't hold lyrics").into());
+    }
+
+    songs::update_song_lyrics(&mut connection, &song_id, saved.as_deref())
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(SongLyrics::new(saved)))
+}
+
+/// Patterns to preview instead of the configured ones.
+#[derive(serde::Deserialize, Debug, Default, TS)]
+#[serde(default)]
+pub struct InferPreviewQuery {
+    /// A single pattern to try, the patterns of the settings are tried when not set.
+    pub pattern: Option<String>,
+}
+
+/// Returns what the `infer-metadata` job would fill from the song's path, without applying it.
+async fn preview_inferred_metadata(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(settings): State<Settings>,
+    Path(song_id): Path<SongId>,
+    Query(InferPreviewQuery { pattern }): Query<InferPreviewQuery>,
+) -> Result<Json<InferredMetadata>> {
+    let patterns = match pattern {
+        Some(pattern) => vec![pattern],
+        None => settings.infer.patterns,
+    };
+    let patterns = Pattern::parse_all(&patterns).map_err(bad_request)?;
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(infer(&song, &patterns)))
+}
+
+/// Returns the recordings the song might be, best matches first.
+///
+/// Suggestions saved by the `identify-songs` job are returned as they are, otherwise the song is
+/// looked up on AcoustID if it's enabled. A failed lookup returns no suggestions rather than an
+/// error, identifying songs is only ever a hint.
+async fn get_song_identification(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(providers): State<SharedProviderRegistry>,
+    Path(song_id): Path<SongId>,
+) -> Result<Json<Vec<IdentifyCandidate>>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let saved = suggestions::get_suggestions(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if !saved.is_empty() {
+        return Ok(Json(saved));
+    }
+
+    let Some(acoustid) = providers.acoustid() else {
+        return Ok(Json(Vec::new()));
+    };
+
+    if song.cue_path.is_some() {
+        return Ok(Json(Vec::new()));
+    }
+
+    drop(connection);
+
+    let candidates = match identify_song(&acoustid, PathBuf::from(&song.path)).await {
+        Ok(candidates) => candidates,
+        Err(err) => {
+            tracing::warn!("Failed to identify \"{}\": {err}", song.path);
+            return Ok(Json(Vec::new()));
+        }
+    };
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    suggestions::replace_suggestions(&mut connection, &song_id, &candidates)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(candidates))
+}
+
+/// Tags the song with the title, artist and album of one of its suggestions.
+///
+/// The previous metadata is saved to the history like any other edit, and the remaining
+/// suggestions are discarded.
+async fn apply_song_identification(
+    _: OutsideMaintenance,
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
+    Path(song_id): Path<SongId>,
+    Json(ApplyIdentification { recording_id }): Json<ApplyIdentification>,
+) -> Result<Json<Song>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if song.cue_path.is_some() {
+        return Err(bad_request("Tracks of a cue sheet share one file and can't be tagged").into());
+    }
+
+    let candidate = suggestions::get_suggestion(&mut connection, &song_id, &recording_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let path = PathBuf::from(&song.path);
+    ensure_editable(path.clone()).await?;
+
+    let id = song_id.clone();
+    let file = spawn_blocking(move || {
+        let mut metadata = SongFile::open(&path)?
+            .metadata()
+            .clone()
+            .unwrap_or_else(|| SongMetadata::new(BTreeMap::new(), BTreeMap::new()));
+
+        metadata.insert(ItemKey::Title, candidate.title);
+        for (key, value) in [
+            (ItemKey::Artist, candidate.artist),
+            (ItemKey::Album, candidate.album),
+        ] {
+            if let Some(value) = value {
+                metadata.insert(key, value);
+            }
+        }
 
-    Ok(Json(file))
-}
+        update_metadata(id, &path, &metadata)?;
 
-async fn refresh_song_details(
-    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
-    Path(song_id): Path<SongId>,
-) -> Result<Json<Option<SongMetadata>>> {
-    let mut connection = db.acquire().await.map_err(internal_error)?;
+        Ok::<_, color_eyre::Report>(SongFile::open(&path)?)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
 
-    let path = songs::get_song_path(&mut connection, &song_id)
+    let updated_song = UpdatedSong::from(file);
+    let albums = [song.album, updated_song.album.clone()];
+
+    songs::update_song(&mut connection, &song_id, updated_song)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    suggestions::clear_suggestions(&mut connection, &song_id)
         .await
         .map_err(IntoResponse::into_response)?;
 
-    let file = read_song_file(path).await?;
-    let metadata = file.metadata().clone();
+    invalidate_cover_art(&cover_art_cache, albums).await;
 
-    songs::update_song(&mut connection, &song_id, UpdatedSong::from(file))
+    let song = songs::get_song(&mut connection, &song_id)
         .await
-        .map_err(internal_error)?;
+        .map_err(IntoResponse::into_response)?;
 
-    Ok(Json(metadata))
+    Ok(Json(song))
 }
 
 async fn get_song_metadata_history(
@@ -140,8 +1302,65 @@ async fn get_song_metadata_history(
     Ok(Json(metadata))
 }
 
+/// Removes every metadata history snapshot of the song.
+async fn clear_song_metadata_history(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+) -> Result<StatusCode> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+
+    // The song has to exist, so the id can't point outside of the history directory.
+    songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let metadata_dir = metadata_history_dir().join(&song_id);
+    if !metadata_dir.exists() {
+        return Err(not_found("No metadata found").into());
+    }
+
+    spawn_blocking(move || std::fs::remove_dir_all(metadata_dir))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Returns the fields whose value in the snapshot differs from the tags of the file, which are
+/// the fields restoring it would change.
+async fn get_metadata_history_diff(
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path((song_id, timestamp)): Path<(SongId, UtcDateTime)>,
+) -> Result<Json<Vec<FieldChange>>> {
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let path = metadata_history_dir()
+        .join(&song_id)
+        .join(format!("{}.json", timestamp.unix_timestamp_nanos()));
+
+    if !path.exists() {
+        return Err(not_found(format!("No metadata found for timestamp \"{timestamp}\"")).into());
+    }
+
+    let snapshot: Option<SongMetadata> =
+        serde_json::from_str(&std::fs::read_to_string(&path).map_err(internal_error)?)
+            .map_err(internal_error)?;
+    let file = read_song_file(PathBuf::from(&song.path)).await?;
+
+    Ok(Json(history::diff(
+        snapshot.as_ref(),
+        file.metadata().as_ref(),
+    )))
+}
+
 async fn restore_metadata(
+    _: OutsideMaintenance,
     State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
     Path((song_id, timestamp)): Path<(SongId, UtcDateTime)>,
 ) -> Result<StatusCode> {
     let mut connection = db.acquire().await.map_err(internal_error)?;
@@ -157,17 +1376,55 @@ async fn restore_metadata(
         serde_json::from_str(&std::fs::read_to_string(&path).map_err(internal_error)?)
             .map_err(internal_error)?;
 
-    let path = songs::get_song_path(&mut connection, &song_id)
+    let song = songs::get_song(&mut connection, &song_id)
         .await
         .map_err(IntoResponse::into_response)?;
 
-    let _ = spawn_blocking(move || update_metadata(song_id, &path, &new_metadata))
-        .await
-        .map_err(internal_error)?;
+    let albums = [song.album, new_metadata.get(&ItemKey::Album).cloned()];
+    let path = PathBuf::from(song.path);
+
+    spawn_blocking(move || {
+        update_metadata(song_id, &path, &new_metadata)?;
+        restore_cover_art(&metadata_dir, timestamp, &path)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    invalidate_cover_art(&cover_art_cache, albums).await;
 
     Ok(StatusCode::OK)
 }
 
+/// Restores the pictures saved alongside the metadata history entry, an empty file means there
+/// was no picture of that type.
+fn restore_cover_art(
+    metadata_dir: &std::path::Path,
+    timestamp: UtcDateTime,
+    path: &std::path::Path,
+) -> color_eyre::Result<()> {
+    for cover_type in [CoverArtType::Front, CoverArtType::Back, CoverArtType::Other] {
+        let snapshot = metadata_dir.join(format!(
+            "{}.cover-{}",
+            timestamp.unix_timestamp_nanos(),
+            cover_type.as_str()
+        ));
+
+        if !snapshot.exists() {
+            continue;
+        }
+
+        let data = std::fs::read(&snapshot)?;
+        if data.is_empty() {
+            remove_cover_art(path, cover_type)?;
+        } else {
+            set_cover_art(path, cover_type, data)?;
+        }
+    }
+
+    Ok(())
+}
+
 async fn read_song_file(path: PathBuf) -> Result<SongFile> {
     let file = spawn_blocking(move || SongFile::open(&path))
         .await
@@ -178,45 +1435,551 @@ async fn read_song_file(path: PathBuf) -> Result<SongFile> {
 }
 
 async fn edit_song(
+    _: OutsideMaintenance,
     State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
     Path(song_id): Path<SongId>,
     Json(metadata): Json<SongMetadata>,
-) -> Result<StatusCode> {
+) -> Result<Json<MetadataWrite>> {
     let mut connection = db.acquire().await.map_err(internal_error)?;
-    let path = songs::get_song_path(&mut connection, &song_id)
+    let song = songs::get_song(&mut connection, &song_id)
         .await
-        .map_err(internal_error)?;
+        .map_err(IntoResponse::into_response)?;
+
+    validate_rating(metadata.get(&ItemKey::Rating).map(String::as_str))?;
+
+    let albums = [song.album, metadata.get(&ItemKey::Album).cloned()];
+    let path = PathBuf::from(song.path);
+    // Saved right away, the tag format might not be able to store it for the library to pick up.
+    let rating = metadata
+        .get(&ItemKey::Rating)
+        .is_some()
+        .then(|| metadata.rating());
+
+    ensure_editable(path.clone()).await?;
 
-    let _ = spawn_blocking(move || update_metadata(song_id, &path, &metadata))
+    let id = song_id.clone();
+    let dropped_fields = spawn_blocking(move || update_metadata(id, &path, &metadata))
         .await
+        .map_err(internal_error)?
         .map_err(internal_error)?;
 
-    Ok(StatusCode::OK)
+    if let Some(rating) = rating {
+        songs::update_song_rating(&mut connection, &song_id, rating)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    invalidate_cover_art(&cover_art_cache, albums).await;
+
+    Ok(Json(MetadataWrite { dropped_fields }))
+}
+
+/// Sets the rating of the song without rewriting the rest of its tag. Songs whose tag format can't
+/// store a rating only have it saved in the library, as do tracks of a cue sheet since they share
+/// one file.
+async fn edit_song_rating(
+    _: OutsideMaintenance,
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    Path(song_id): Path<SongId>,
+    Json(SongRating { rating }): Json<SongRating>,
+) -> Result<Json<Song>> {
+    if rating.is_some_and(|rating| rating > MAX_RATING) {
+        return Err(bad_request(format!("Rating must be from 0 to {MAX_RATING}")).into());
+    }
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let song = songs::get_song(&mut connection, &song_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if song.cue_path.is_none() {
+        let path = PathBuf::from(song.path);
+        ensure_editable(path.clone()).await?;
+
+        spawn_blocking(move || write_rating(&path, rating))
+            .await
+            .map_err(internal_error)?
+            .map_err(internal_error)?;
+    }
+
+    let song = songs::update_song_rating(&mut connection, &song_id, rating)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(song))
+}
+
+/// Applies the same metadata changes to every song, a song that fails doesn't stop the others.
+async fn edit_songs(
+    _: OutsideMaintenance,
+    State(db): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
+    Json(BulkMetadataEdit { song_ids, changes }): Json<BulkMetadataEdit>,
+) -> Result<Json<Vec<BulkEditResult>>> {
+    validate_song_ids(&song_ids)?;
+
+    if changes.is_empty() {
+        return Err(bad_request("No changes given").into());
+    }
+
+    validate_rating(changes.get(&ItemKey::Rating).and_then(Option::as_deref))?;
+
+    let mut connection = db.acquire().await.map_err(internal_error)?;
+    let mut results = Vec::with_capacity(song_ids.len());
+    let mut found = Vec::new();
+
+    for song_id in song_ids.iter().collect::<BTreeSet<_>>() {
+        match songs::get_song(&mut connection, song_id).await {
+            Ok(song) => found.push(song),
+            Err(DatabaseError::Song(DatabaseSongError::SongNotFound)) => {
+                results.push(BulkEditResult::new(
+                    song_id.clone(),
+                    BulkEditStatus::SongNotFound,
+                ));
+            }
+            Err(err) => return Err(err.into_response().into()),
+        }
+    }
+
+    drop(connection);
+
+    let file_changes = changes.clone();
+    let (written, file_results) = spawn_blocking(move || {
+        let mut written = Vec::new();
+        let mut results = Vec::new();
+
+        for song in found {
+            let path = PathBuf::from(&song.path);
+
+            if !path.is_file() {
+                results.push(BulkEditResult::new(song.id, BulkEditStatus::FileNotFound));
+                continue;
+            }
+
+            match apply_metadata_changes(&song.id, &path, &file_changes) {
+                Ok(Some(dropped_fields)) => written.push((song, dropped_fields)),
+                Ok(None) => {
+                    results.push(BulkEditResult::new(song.id, BulkEditStatus::Unchanged));
+                }
+                Err(err) => {
+                    tracing::error!("Failed to write metadata of \"{}\": {err}", song.path);
+
+                    results.push(BulkEditResult {
+                        error: Some(err.to_string()),
+                        ..BulkEditResult::new(song.id, BulkEditStatus::WriteFailed)
+                    });
+                }
+            }
+        }
+
+        (written, results)
+    })
+    .await
+    .map_err(internal_error)?;
+
+    results.extend(file_results);
+
+    let mut albums = Vec::new();
+    let updated_songs = written
+        .iter()
+        .map(|(song, _)| {
+            let updated_song = apply_changes_to_song(song, &changes);
+            albums.push(song.album.clone());
+            albums.push(updated_song.album.clone());
+            (song.id.as_str(), updated_song)
+        })
+        .collect::<Vec<_>>();
+
+    // The files are already written, so failing to update the library is reported for each of
+    // them rather than failing the request, which would hide that their files were changed.
+    let updated = async {
+        let mut transaction = db.begin().await?;
+        for (song_id, updated_song) in updated_songs {
+            songs::update_song(&mut transaction, song_id, updated_song).await?;
+        }
+        transaction.commit().await?;
+        Ok::<_, DatabaseError>(())
+    }
+    .await;
+
+    let (status, error) = match updated {
+        Ok(()) => (BulkEditStatus::Updated, None),
+        Err(err) => {
+            tracing::error!("Failed to update the edited songs: {err}");
+            (BulkEditStatus::UpdateFailed, Some(err.to_string()))
+        }
+    };
+
+    results.extend(
+        written
+            .into_iter()
+            .map(|(song, dropped_fields)| BulkEditResult {
+                error: error.clone(),
+                dropped_fields,
+                ..BulkEditResult::new(song.id, status)
+            }),
+    );
+
+    invalidate_cover_art(&cover_art_cache, albums).await;
+
+    let order = song_ids
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, song_id)| (song_id, index))
+        .collect::<HashMap<_, _>>();
+    results.sort_by_key(|result| order[&result.song_id]);
+
+    Ok(Json(results))
 }
 
+async fn delete_song(
+    _: OutsideMaintenance,
+    State(state): State<AppState>,
+    Path(song_id): Path<SongId>,
+    Query(options): Query<DeleteOptions>,
+) -> Result<()> {
+    let [result] = remove_songs(&state, vec![song_id], &options)
+        .await?
+        .try_into()
+        .expect("One result per song");
+
+    let error = match result.status {
+        BulkDeleteStatus::Deleted => return Ok(()),
+        BulkDeleteStatus::SongNotFound => DatabaseSongError::SongNotFound.into_response(),
+        BulkDeleteStatus::OutsideLibrary => {
+            bad_request("Song file isn't inside any library directory").into_response()
+        }
+        BulkDeleteStatus::CueTrack => {
+            conflict("Song is a track of a cue sheet, its file holds every track of the sheet")
+                .into_response()
+        }
+        BulkDeleteStatus::DeleteFailed => {
+            internal_error(result.error.unwrap_or_default()).into_response()
+        }
+    };
+
+    Err(error.into())
+}
+
+async fn delete_songs(
+    _: OutsideMaintenance,
+    State(state): State<AppState>,
+    Query(options): Query<DeleteOptions>,
+    Json(request): Json<BulkSongs>,
+) -> Result<Json<Vec<BulkDeleteResult>>> {
+    request.validate()?;
+
+    let song_ids = request.song_ids.into_iter().collect::<BTreeSet<_>>();
+
+    Ok(Json(
+        remove_songs(&state, song_ids.into_iter().collect(), &options).await?,
+    ))
+}
+
+/// Deletes the songs whose file went missing without waiting for the scan's grace period.
+async fn purge_missing_songs(
+    State(pool): State<sqlx::Pool<sqlx::Sqlite>>,
+    State(cover_art_cache): State<SharedCoverArtCache>,
+    Query(options): Query<PurgeOptions>,
+) -> Result<Json<PurgedSongs>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let before = options
+        .older_than_days
+        .map(|days| OffsetDateTime::now_utc() - time::Duration::days(days.into()));
+
+    let purged = songs::purge_missing_songs(&mut connection, before)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let albums = purged
+        .iter()
+        .filter_map(|song| song.album.as_deref())
+        .collect::<HashSet<_>>();
+    for album in albums {
+        cover_art_cache.invalidate_album(album).await;
+    }
+
+    Ok(Json(PurgedSongs {
+        song_ids: purged.into_iter().map(|song| song.id).collect(),
+    }))
+}
+
+/// Removes the songs from the database, deleting their files first when `delete_file` is set.
+///
+/// Files are deleted with a single [`Operation::Delete`], a song is only removed once its file
+/// is confirmed to be gone so a failed deletion leaves the library consistent with the disk.
+/// Unless the deletion is `permanent` the files are moved to the trash, which is then purged
+/// down to its limits.
+async fn remove_songs(
+    AppState {
+        pool,
+        settings,
+        file_operation_manager,
+        cover_art_cache,
+        ..
+    }: &AppState,
+    song_ids: Vec<SongId>,
+    &DeleteOptions {
+        delete_file,
+        permanent,
+    }: &DeleteOptions,
+) -> Result<Vec<BulkDeleteResult>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let mut results = Vec::with_capacity(song_ids.len());
+    let mut found = Vec::new();
+
+    for song_id in song_ids {
+        match songs::get_song(&mut connection, &song_id).await {
+            Ok(song) => found.push(song),
+            Err(DatabaseError::Song(DatabaseSongError::SongNotFound)) => {
+                results.push(BulkDeleteResult::new(
+                    song_id,
+                    BulkDeleteStatus::SongNotFound,
+                ));
+            }
+            Err(err) => return Err(err.into_response().into()),
+        }
+    }
+
+    let mut removed = Vec::new();
+    let mut files = HashMap::new();
+
+    if delete_file {
+        let mut library = Vec::new();
+        for directory in directories::get_directories(&mut connection)
+            .await
+            .map_err(IntoResponse::into_response)?
+        {
+            match tokio::fs::canonicalize(&directory.path).await {
+                Ok(path) => library.push(path),
+                Err(err) => tracing::warn!("Skipping directory \"{}\": {err}", directory.path),
+            }
+        }
+
+        for song in found {
+            if song.cue_path.is_some() {
+                results.push(BulkDeleteResult::new(song.id, BulkDeleteStatus::CueTrack));
+                continue;
+            }
+
+            match tokio::fs::canonicalize(&song.path).await {
+                Ok(path) if is_inside_library(&path, &library) => {
+                    files.insert(path, song);
+                }
+                Ok(_) => {
+                    results.push(BulkDeleteResult::new(
+                        song.id,
+                        BulkDeleteStatus::OutsideLibrary,
+                    ));
+                }
+                // The file is already gone, so only the song is left to remove.
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => removed.push(song),
+                Err(err) => results.push(BulkDeleteResult {
+                    song_id: song.id,
+                    status: BulkDeleteStatus::DeleteFailed,
+                    error: Some(err.to_string()),
+                }),
+            }
+        }
+    } else {
+        removed = found;
+    }
+
+    if !files.is_empty() {
+        let mut operation_handle = file_operation_manager
+            .queue_operation(Operation::Delete {
+                paths: files.keys().cloned().collect(),
+                recursive: false,
+                trash: (!permanent).then(trash_dir),
+            })
+            .await?;
+
+        let mut deleted = HashSet::new();
+        while let Some(event) = operation_handle.events().recv().await {
+            if let OperationEvent::Deleted { path } = event {
+                deleted.insert(path);
+            }
+        }
+
+        let error = match operation_handle.result().await {
+            Ok(Ok(())) => String::from("File deletion was cancelled"),
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        for (path, song) in files {
+            if deleted.contains(&path) {
+                removed.push(song);
+            } else {
+                tracing::error!("Failed to delete \"{}\": {error}", song.path);
+
+                results.push(BulkDeleteResult {
+                    song_id: song.id,
+                    status: BulkDeleteStatus::DeleteFailed,
+                    error: Some(error.clone()),
+                });
+            }
+        }
+
+        if !permanent {
+            let trash = settings
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .trash
+                .clone();
+            purge_expired_trash(trash).await;
+        }
+    }
+
+    let mut albums = Vec::new();
+    let mut transaction = pool.begin().await.map_err(internal_error)?;
+
+    for song in removed {
+        songs::delete_song(&mut transaction, &song.id)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        albums.push(song.album);
+        results.push(BulkDeleteResult::new(song.id, BulkDeleteStatus::Deleted));
+    }
+
+    transaction.commit().await.map_err(internal_error)?;
+
+    invalidate_cover_art(cover_art_cache, albums).await;
+
+    Ok(results)
+}
+
+/// Whether the canonical path is inside one of the canonical library directories.
+fn is_inside_library(path: &std::path::Path, library: &[PathBuf]) -> bool {
+    library.iter().any(|directory| path.starts_with(directory))
+}
+
+/// Applies the changes to the tags of the file, saving the previous metadata to the history.
+///
+/// Returns the fields the tag format can't hold when the file was written, `None` if it already
+/// has the requested metadata.
+fn apply_metadata_changes(
+    id: &str,
+    path: &std::path::Path,
+    changes: &BTreeMap<ItemKey, Option<String>>,
+) -> color_eyre::Result<Option<Vec<ItemKey>>> {
+    FileHealth::check(path, true).ensure_editable()?;
+
+    let mut song = SongFile::open(path)?;
+    let original_metadata = song.metadata().clone();
+
+    let mut metadata = original_metadata
+        .clone()
+        .unwrap_or_else(|| SongMetadata::new(BTreeMap::new(), BTreeMap::new()));
+
+    for (key, value) in changes {
+        match value {
+            Some(value) => metadata.insert(key.clone(), value.clone()),
+            None => {
+                metadata.remove(key);
+            }
+        }
+    }
+
+    if original_metadata.as_ref() == Some(&metadata) {
+        return Ok(None);
+    }
+
+    std::fs::write(
+        new_history_snapshot(id)?.with_extension("json"),
+        serde_json::to_string_pretty(&original_metadata)?,
+    )?;
+
+    song.set_metadata(metadata);
+
+    Ok(Some(song.write()?))
+}
+
+/// Returns the song's row with the changes applied, leaving every other column as it is.
+fn apply_changes_to_song(song: &Song, changes: &BTreeMap<ItemKey, Option<String>>) -> UpdatedSong {
+    let value = |key: ItemKey, current: &Option<String>| match changes.get(&key) {
+        Some(value) => value.clone(),
+        None => current.clone(),
+    };
+    // A number like `3/12` changes the total along with it, unless the total is changed as well.
+    let position = |key: ItemKey, total_key: ItemKey, number: Option<u32>, total: Option<u32>| {
+        let changed = changes
+            .get(&key)
+            .map(|value| Position::parse(value.as_deref().unwrap_or_default()));
+        let total = match changes.get(&total_key) {
+            Some(value) => value.as_deref().and_then(parse_count),
+            None => changed.and_then(|changed| changed.total).or(total),
+        };
+
+        Position {
+            number: changed.map_or(number, |changed| changed.number),
+            total,
+        }
+    };
+    let track = position(
+        ItemKey::TrackNumber,
+        ItemKey::TrackTotal,
+        song.track_number,
+        song.track_total,
+    );
+    let disc = position(
+        ItemKey::DiscNumber,
+        ItemKey::DiscTotal,
+        song.disc_number,
+        song.disc_total,
+    );
+
+    UpdatedSong {
+        title: value(ItemKey::Title, &song.title),
+        artist: value(ItemKey::Artist, &song.artist),
+        album: value(ItemKey::Album, &song.album),
+        album_artist: value(ItemKey::AlbumArtist, &song.album_artist),
+        genre: value(ItemKey::Genre, &song.genre),
+        track_number: track.number,
+        track_total: track.total,
+        disc_number: disc.number,
+        disc_total: disc.total,
+        year: value(ItemKey::Year, &song.year),
+        mood: value(ItemKey::Mood, &song.mood),
+        composer: value(ItemKey::Composer, &song.composer),
+        comment: value(ItemKey::Comment, &song.comment),
+        rating: changes
+            .get(&ItemKey::Rating)
+            .map(|rating| rating.as_deref().and_then(parse_rating)),
+        lyrics: value(ItemKey::Lyrics, &song.lyrics),
+    }
+}
+
+/// Clears the cached cover art of the albums a song belonged to before and after a change.
+pub(super) async fn invalidate_cover_art(
+    cache: &SharedCoverArtCache,
+    albums: impl IntoIterator<Item = Option<String>>,
+) {
+    for album in albums.into_iter().flatten().collect::<HashSet<_>>() {
+        cache.invalidate_album(&album).await;
+    }
+}
+
+/// Replaces the metadata of the file, saving the previous metadata to the history.
+///
+/// Returns the fields the tag format can't hold, see [`SongFile::write`].
 fn update_metadata(
     id: SongId,
     path: &std::path::Path,
     new_metadata: &SongMetadata,
-) -> color_eyre::Result<()> {
+) -> color_eyre::Result<Vec<ItemKey>> {
     let mut song = SongFile::open(path)?;
     let original_metadata = song.metadata_mut();
 
     if original_metadata.as_ref() == Some(new_metadata) {
-        return Ok(());
-    }
-
-    let metadata_dir = metadata_history_dir().join(&id);
-
-    if !metadata_dir.exists() {
-        std::fs::create_dir_all(&metadata_dir)?;
+        return Ok(Vec::new());
     }
 
     std::fs::write(
-        metadata_dir.join(format!(
-            "{}.json",
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
-        )),
+        new_history_snapshot(&id)?.with_extension("json"),
         serde_json::to_string_pretty(&original_metadata)?,
     )?;
 
@@ -224,7 +1987,270 @@ fn update_metadata(
     song.set_metadata(new_metadata.clone());
 
     tracing::info!("Updated metadata: {:#?}", song.metadata());
-    song.write()?;
 
-    Ok(())
+    Ok(song.write()?)
+}
+
+/// Returns the path of a new metadata history entry for the song, without an extension.
+///
+/// The metadata is stored as `json`, any other state needed to restore the song shares the same
+/// file stem.
+pub(super) fn new_history_snapshot(id: &str) -> std::io::Result<PathBuf> {
+    let metadata_dir = metadata_history_dir().join(id);
+
+    if !metadata_dir.exists() {
+        std::fs::create_dir_all(&metadata_dir)?;
+    }
+
+    Ok(metadata_dir.join(OffsetDateTime::now_utc().unix_timestamp_nanos().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use test_log::test;
+
+    use super::*;
+
+    const FIXTURE: &str = "data/flip.mp3";
+
+    #[test]
+    fn test_is_inside_library() {
+        let library = [PathBuf::from("/music"), PathBuf::from("/mnt/other music")];
+
+        assert!(is_inside_library(
+            std::path::Path::new("/music/album/song.mp3"),
+            &library
+        ));
+        assert!(is_inside_library(
+            std::path::Path::new("/mnt/other music/song.flac"),
+            &library
+        ));
+        assert!(!is_inside_library(
+            std::path::Path::new("/music-backup/song.mp3"),
+            &library
+        ));
+        assert!(!is_inside_library(
+            std::path::Path::new("/etc/passwd"),
+            &library
+        ));
+    }
+
+    #[test]
+    fn test_is_same_song() {
+        let file = SongFile::open(std::path::Path::new(FIXTURE)).unwrap();
+        let title = UpdatedSong::from(file.clone()).title;
+
+        let song = Song {
+            path: String::from("/music/old/flip.mp3"),
+            title: title.map(|title| title.to_uppercase()),
+            ..Default::default()
+        };
+        assert!(is_same_song(&song, &file));
+
+        let song = Song {
+            title: Some(String::from("Something Else")),
+            ..song
+        };
+        assert!(!is_same_song(&song, &file));
+
+        let song = Song {
+            title: None,
+            ..song
+        };
+        assert!(is_same_song(&song, &file));
+
+        // A known duration decides, a matching title doesn't make up for a different length.
+        let duration = file
+            .properties()
+            .and_then(|properties| properties.duration_ms)
+            .unwrap();
+        let song = Song {
+            title: Some(String::from("Something Else")),
+            duration_ms: Some(duration + RELOCATE_DURATION_TOLERANCE_MS / 2),
+            ..song
+        };
+        assert!(is_same_song(&song, &file));
+
+        let song = Song {
+            title: UpdatedSong::from(file.clone()).title,
+            duration_ms: Some(duration + RELOCATE_DURATION_TOLERANCE_MS * 5),
+            ..song
+        };
+        assert!(!is_same_song(&song, &file));
+    }
+
+    #[test]
+    fn test_apply_changes_to_song() {
+        let song = Song {
+            title: Some(String::from("Title")),
+            artist: Some(String::from("Artist")),
+            album: Some(String::from("Album")),
+            album_artist: Some(String::from("Wrong")),
+            genre: Some(String::from("Genre")),
+            ..Default::default()
+        };
+
+        let updated = apply_changes_to_song(
+            &song,
+            &BTreeMap::from([
+                (ItemKey::AlbumArtist, Some(String::from("Artist"))),
+                (ItemKey::Genre, None),
+            ]),
+        );
+
+        assert_eq!(updated.album_artist.as_deref(), Some("Artist"));
+        assert_eq!(updated.genre, None);
+        assert_eq!(updated.title, song.title);
+        assert_eq!(updated.artist, song.artist);
+        assert_eq!(updated.album, song.album);
+        assert_eq!(updated.year, None);
+    }
+
+    #[test]
+    fn test_detecting_synchronized_lyrics() {
+        assert!(is_synchronized(
+            "[ar:Artist]\n[00:12.00]First line\n[00:17.20]Second line"
+        ));
+        assert!(is_synchronized("  [1:02]Line"));
+        assert!(!is_synchronized("[Chorus]\nLa la la"));
+        assert!(!is_synchronized("[ar:Artist]\nFirst line; second line"));
+        assert_eq!(
+            SongLyrics::new(Some(String::from("[00:01.00]Hi"))),
+            SongLyrics {
+                lyrics: Some(String::from("[00:01.00]Hi")),
+                synchronized: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=50-10", 1000), None);
+        assert_eq!(parse_range("items=0-10", 1000), None);
+    }
+
+    #[test(tokio::test)]
+    async fn test_stream_full_file() {
+        let path = std::path::Path::new(FIXTURE);
+        let expected = std::fs::read(path).unwrap();
+
+        let response = stream_file(path, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/mpeg");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), expected.as_slice());
+    }
+
+    #[test(tokio::test)]
+    async fn test_stream_partial_file() {
+        let path = std::path::Path::new(FIXTURE);
+        let expected = std::fs::read(path).unwrap();
+        let length = expected.len();
+        let (start, end) = (length / 2, length / 2 + 99);
+
+        let range = HeaderValue::from_str(&format!("bytes={start}-{end}")).unwrap();
+        let response = stream_file(path, Some(&range)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            format!("bytes {start}-{end}/{length}").as_str()
+        );
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), &expected[start..=end]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_stream_missing_file() {
+        let response = stream_file(std::path::Path::new("data/missing.mp3"), None).await;
+
+        assert_eq!(
+            response.unwrap_err().into_response().status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_download_file_name() {
+        let song = Song {
+            path: String::from("/music/Album/01 track.flac"),
+            title: Some(String::from("What: Now?")),
+            artist: Some(String::from("Artist")),
+            ..Default::default()
+        };
+
+        assert_eq!(download_file_name(&song, false), "Artist - What Now.flac");
+        assert_eq!(download_file_name(&song, true), "01 track.flac");
+        assert_eq!(
+            download_file_name(
+                &Song {
+                    title: None,
+                    ..song
+                },
+                false
+            ),
+            "01 track.flac"
+        );
+    }
+
+    #[test]
+    fn test_estimate_track_bytes() {
+        assert_eq!(
+            estimate_track_bytes(100, 1100, 10_000, 0, Some(2500)),
+            100..350
+        );
+        assert_eq!(
+            estimate_track_bytes(100, 1100, 10_000, 2500, None),
+            350..1100
+        );
+        assert_eq!(
+            estimate_track_bytes(100, 1100, 10_000, 9000, Some(20_000)),
+            1000..1100
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_stream_cue_track() {
+        let path = std::path::Path::new("data/goose.flac");
+        let expected = std::fs::read(path).unwrap();
+
+        let mut file = tokio::fs::File::open(path).await.unwrap();
+        let audio_offset = flac_audio_offset(&mut file).await.unwrap() as usize;
+
+        // Audio frames start with a sync code.
+        assert_eq!(expected[audio_offset], 0xFF);
+        assert_eq!(expected[audio_offset + 1] & 0xFE, 0xF8);
+
+        let cue_range = CueRange {
+            cue_path: String::from("data/goose.cue"),
+            start_ms: 100,
+            end_ms: None,
+        };
+
+        let response = stream_cue_track(path, &cue_range, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..audio_offset], &expected[..audio_offset]);
+        assert!(body.len() < expected.len());
+        assert!(expected.ends_with(&body[audio_offset..]));
+
+        let range = HeaderValue::from_static("bytes=2-5");
+        let response = stream_cue_track(path, &cue_range, Some(&range))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), &expected[2..=5]);
+    }
 }
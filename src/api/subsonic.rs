@@ -0,0 +1,1140 @@
+//! A Subsonic compatible API under `/rest`, so apps made for Subsonic servers can browse and play
+//! the library.
+//!
+//! Only what clients need to browse and play is implemented. Songs are identified by their id
+//! prefixed with `tr-`, albums and artists, which don't have ids, by their encoded name prefixed
+//! with `al-` and `ar-`.
+
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, FromRef, FromRequestParts, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode, Uri, header, request::Parts},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{MethodFilter, on},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use time::OffsetDateTime;
+
+use crate::{
+    APP_NAME, APP_VERSION, AppState,
+    config::{Auth, Settings},
+    db::{
+        Album, Artist, DatabaseError, FilterField, FilterOperator, FilterValue, Page, Song,
+        SongFilter, SongQuery,
+        artists::{self, DatabaseArtistError},
+        songs::{self, DatabaseSongError},
+    },
+    state::{Pool, SharedCoverArtCache},
+};
+
+use super::{
+    albums::AlbumKey,
+    auth::find_token,
+    client_ip::{ClientIp, resolve_client_ip},
+    cover_art::{CoverArtQuery, get_album_cover_art, get_song_cover_art},
+    songs::stream_song,
+};
+
+/// Prefix of every route, they're left out of the token authentication of the rest of the API.
+pub const ROUTE_PREFIX: &str = "/rest/";
+
+/// Version of the Subsonic API that's implemented.
+const API_VERSION: &str = "1.16.1";
+
+const XML_NAMESPACE: &str = "http://subsonic.org/restapi";
+
+const SONG_PREFIX: &str = "tr-";
+const ALBUM_PREFIX: &str = "al-";
+const ARTIST_PREFIX: &str = "ar-";
+
+/// Id of the only music folder, the library isn't split by directory.
+const MUSIC_FOLDER_ID: u32 = 1;
+
+const DEFAULT_LIST_SIZE: usize = 10;
+const MAX_LIST_SIZE: usize = 500;
+const DEFAULT_SEARCH_COUNT: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubsonicError {
+    #[error("Required parameter is missing: {0}")]
+    MissingParameter(&'static str),
+    #[error("Wrong username or password")]
+    WrongCredentials,
+    #[error("The requested data was not found")]
+    NotFound,
+    #[error("Unknown list type \"{0}\"")]
+    UnknownListType(String),
+    #[error(transparent)]
+    Database(DatabaseError),
+}
+
+impl SubsonicError {
+    /// Error code the Subsonic API uses for the error.
+    fn code(&self) -> u32 {
+        match self {
+            Self::MissingParameter(_) => 10,
+            Self::WrongCredentials => 40,
+            Self::NotFound => 70,
+            Self::UnknownListType(_) | Self::Database(_) => 0,
+        }
+    }
+}
+
+impl From<DatabaseError> for SubsonicError {
+    fn from(err: DatabaseError) -> Self {
+        match err {
+            DatabaseError::Song(
+                DatabaseSongError::SongNotFound | DatabaseSongError::AlbumNotFound,
+            )
+            | DatabaseError::Artist(DatabaseArtistError::NotFound) => Self::NotFound,
+            err => Self::Database(err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for SubsonicError {
+    fn from(err: sqlx::Error) -> Self {
+        Self::Database(err.into())
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    // Clients use both methods, with or without the `.view` suffix.
+    let methods = MethodFilter::GET.or(MethodFilter::POST);
+
+    [
+        ("ping", on(methods, ping)),
+        ("getLicense", on(methods, get_license)),
+        ("getMusicFolders", on(methods, get_music_folders)),
+        ("getArtists", on(methods, get_artists)),
+        ("getArtist", on(methods, get_artist)),
+        ("getAlbumList2", on(methods, get_album_list)),
+        ("getAlbum", on(methods, get_album)),
+        ("getSong", on(methods, get_song)),
+        ("search3", on(methods, search)),
+        ("stream", on(methods, stream)),
+        ("getCoverArt", on(methods, get_cover_art)),
+        ("scrobble", on(methods, scrobble)),
+    ]
+    .into_iter()
+    .fold(Router::new(), |router, (endpoint, handler)| {
+        router
+            .route(&format!("{ROUTE_PREFIX}{endpoint}"), handler.clone())
+            .route(&format!("{ROUTE_PREFIX}{endpoint}.view"), handler)
+    })
+    .route_layer(middleware::from_fn(render))
+}
+
+/// Format responses are rendered in, set by the `f` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Xml,
+    Json,
+}
+
+impl Format {
+    fn of(uri: &Uri) -> Self {
+        let format = uri.query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "f")
+                .map(|(_, format)| format.into_owned())
+        });
+
+        match format.as_deref() {
+            Some("json") => Self::Json,
+            _ => Self::Xml,
+        }
+    }
+}
+
+/// Status and elements of a response, rendered in the requested [`Format`] by [`render`].
+#[derive(Debug, Clone)]
+struct Envelope {
+    status: &'static str,
+    elements: Map<String, Value>,
+}
+
+impl Envelope {
+    fn respond(self) -> Response {
+        // Failures are reported in the envelope, clients don't look at the status code.
+        let mut response = StatusCode::OK.into_response();
+        response.extensions_mut().insert(self);
+
+        response
+    }
+}
+
+/// Elements of a successful response, an object like `{ "album": { ... } }`.
+struct Reply(Value);
+
+impl IntoResponse for Reply {
+    fn into_response(self) -> Response {
+        let elements = match self.0 {
+            Value::Object(elements) => elements,
+            _ => Map::new(),
+        };
+
+        Envelope {
+            status: "ok",
+            elements,
+        }
+        .respond()
+    }
+}
+
+impl IntoResponse for SubsonicError {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::Database(err) => tracing::error!("subsonic: {err}"),
+            err => tracing::warn!("subsonic: {err}"),
+        }
+
+        let mut elements = Map::new();
+        elements.insert(
+            String::from("error"),
+            json!({ "code": self.code(), "message": self.to_string() }),
+        );
+
+        Envelope {
+            status: "failed",
+            elements,
+        }
+        .respond()
+    }
+}
+
+/// Renders the [`Envelope`] of a response in the format the client asked for, audio and images
+/// are passed through as they are.
+async fn render(request: Request, next: Next) -> Response {
+    let format = Format::of(request.uri());
+    let mut response = next.run(request).await;
+
+    let Some(Envelope { status, elements }) = response.extensions_mut().remove::<Envelope>() else {
+        return response;
+    };
+
+    let mut root = Map::new();
+    root.insert(String::from("status"), Value::from(status));
+    root.insert(String::from("version"), Value::from(API_VERSION));
+    root.insert(String::from("type"), Value::from(APP_NAME));
+    root.insert(String::from("serverVersion"), Value::from(APP_VERSION));
+    root.extend(elements);
+    remove_nulls(&mut root);
+
+    match format {
+        Format::Json => Json(json!({ "subsonic-response": root })).into_response(),
+        Format::Xml => {
+            root.insert(String::from("xmlns"), Value::from(XML_NAMESPACE));
+
+            let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            write_element(&mut xml, "subsonic-response", &root);
+
+            ([(header::CONTENT_TYPE, "text/xml; charset=utf-8")], xml).into_response()
+        }
+    }
+}
+
+/// Leaves out unset fields, clients expect them to be missing rather than `null`.
+fn remove_nulls(object: &mut Map<String, Value>) {
+    object.retain(|_, value| !value.is_null());
+
+    for value in object.values_mut() {
+        match value {
+            Value::Object(object) => remove_nulls(object),
+            Value::Array(items) => items
+                .iter_mut()
+                .filter_map(Value::as_object_mut)
+                .for_each(remove_nulls),
+            _ => {}
+        }
+    }
+}
+
+/// Writes the object as an XML element, its scalar fields become attributes, objects child
+/// elements and arrays a child element for each item.
+fn write_element(xml: &mut String, name: &str, object: &Map<String, Value>) {
+    let mut children = Vec::new();
+
+    let _ = write!(xml, "<{name}");
+    for (key, value) in object {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Number(value) => value.to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Object(child) => {
+                children.push((key, child));
+                continue;
+            }
+            Value::Array(items) => {
+                children.extend(
+                    items
+                        .iter()
+                        .filter_map(Value::as_object)
+                        .map(|item| (key, item)),
+                );
+                continue;
+            }
+            Value::Null => continue,
+        };
+
+        let _ = write!(xml, " {key}=\"{}\"", escape_xml(&value));
+    }
+
+    if children.is_empty() {
+        xml.push_str("/>");
+        return;
+    }
+
+    xml.push('>');
+    for (key, child) in children {
+        write_element(xml, key, child);
+    }
+    let _ = write!(xml, "</{name}>");
+}
+
+/// Escapes the value for an attribute, dropping control characters XML doesn't allow.
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for character in value.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(character),
+            character if character.is_control() => {}
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+/// Credentials of a request, clients log in with the name of a token as the username and the
+/// token as the password.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct Credentials {
+    /// Name of the token.
+    u: Option<String>,
+    /// The token, either as is or hex encoded and prefixed with `enc:`.
+    p: Option<String>,
+    /// MD5 hash of the token followed by the salt, sent instead of the token.
+    t: Option<String>,
+    /// Salt of the hash.
+    s: Option<String>,
+}
+
+/// Extracted by every endpoint, rejects requests without valid [`Credentials`] unless no tokens
+/// are configured or the client is exempt, like the rest of the API.
+struct Authenticated;
+
+impl<S> FromRequestParts<S> for Authenticated
+where
+    Settings: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = SubsonicError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let settings = Settings::from_ref(state);
+        let client = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| {
+                resolve_client_ip(peer.ip(), &parts.headers, &settings.auth.trusted_proxies)
+            });
+        let credentials = Query::<Credentials>::try_from_uri(&parts.uri)
+            .map(|Query(credentials)| credentials)
+            .unwrap_or_default();
+
+        authenticate(&settings.auth, &credentials, client).map(|()| Self)
+    }
+}
+
+fn authenticate(
+    auth: &Auth,
+    credentials: &Credentials,
+    client: Option<IpAddr>,
+) -> Result<(), SubsonicError> {
+    if auth.tokens.is_empty() || client.is_some_and(|client| ClientIp(client).is_exempt(auth)) {
+        return Ok(());
+    }
+
+    let name = credentials
+        .u
+        .as_deref()
+        .ok_or(SubsonicError::MissingParameter("u"))?;
+
+    let valid = match credentials {
+        Credentials {
+            t: Some(hash),
+            s: Some(salt),
+            ..
+        } => auth.tokens.get(name).is_some_and(|token| {
+            let expected = Md5::digest(format!("{token}{salt}"));
+
+            decode_hex(hash).is_some_and(|hash| hash == expected.as_slice())
+        }),
+        Credentials {
+            p: Some(password), ..
+        } => {
+            let password = match password.strip_prefix("enc:") {
+                Some(encoded) => {
+                    decode_hex(encoded).and_then(|bytes| String::from_utf8(bytes).ok())
+                }
+                None => Some(password.clone()),
+            };
+
+            password.is_some_and(|password| find_token(auth, &password) == Some(name))
+        }
+        _ => return Err(SubsonicError::MissingParameter("p")),
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(SubsonicError::WrongCredentials)
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// What an id sent by a client refers to.
+#[derive(Debug, PartialEq, Eq)]
+enum ItemId {
+    Song(String),
+    Album(String),
+    Artist(String),
+}
+
+impl ItemId {
+    fn parse(id: &str) -> Option<Self> {
+        let decode = |name: &str| String::from_utf8(URL_SAFE_NO_PAD.decode(name).ok()?).ok();
+
+        if let Some(id) = id.strip_prefix(SONG_PREFIX) {
+            Some(Self::Song(id.to_string()))
+        } else if let Some(name) = id.strip_prefix(ALBUM_PREFIX) {
+            decode(name).map(Self::Album)
+        } else {
+            id.strip_prefix(ARTIST_PREFIX)
+                .and_then(decode)
+                .map(Self::Artist)
+        }
+    }
+}
+
+fn song_id(id: &str) -> String {
+    format!("{SONG_PREFIX}{id}")
+}
+
+fn album_id(title: &str) -> String {
+    format!("{ALBUM_PREFIX}{}", URL_SAFE_NO_PAD.encode(title))
+}
+
+fn artist_id(name: &str) -> String {
+    format!("{ARTIST_PREFIX}{}", URL_SAFE_NO_PAD.encode(name))
+}
+
+/// Returns the id of the first artist in a tag, tags can credit multiple artists.
+fn first_artist_id(value: &str) -> Option<String> {
+    artists::split_artists(value).next().map(artist_id)
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct IdParameter {
+    id: Option<String>,
+}
+
+impl IdParameter {
+    fn parse(&self) -> Result<ItemId, SubsonicError> {
+        let id = self
+            .id
+            .as_deref()
+            .ok_or(SubsonicError::MissingParameter("id"))?;
+
+        ItemId::parse(id).ok_or(SubsonicError::NotFound)
+    }
+}
+
+/// A song, called a child in the Subsonic API.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Child {
+    id: String,
+    parent: Option<String>,
+    is_dir: bool,
+    title: String,
+    album: Option<String>,
+    artist: Option<String>,
+    track: Option<u32>,
+    year: Option<u32>,
+    genre: Option<String>,
+    cover_art: String,
+    content_type: String,
+    suffix: Option<String>,
+    /// Duration in seconds.
+    duration: Option<u32>,
+    bit_rate: Option<u32>,
+    disc_number: Option<u32>,
+    album_id: Option<String>,
+    artist_id: Option<String>,
+    #[serde(rename = "type")]
+    media_type: &'static str,
+    #[serde(with = "time::serde::rfc3339::option")]
+    created: Option<OffsetDateTime>,
+    play_count: u32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    played: Option<OffsetDateTime>,
+}
+
+impl From<Song> for Child {
+    fn from(song: Song) -> Self {
+        let path = std::path::Path::new(&song.path);
+        let album_id = song.album.as_deref().map(album_id);
+
+        Self {
+            id: song_id(&song.id),
+            parent: album_id.clone(),
+            is_dir: false,
+            title: song.title.unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            album: song.album,
+            artist_id: song.artist.as_deref().and_then(first_artist_id),
+            artist: song.artist,
+            track: song.track_number,
+            year: song.year_num.and_then(|year| u32::try_from(year).ok()),
+            genre: song.genre,
+            // Tracks of an album share its cover art, so clients only fetch it once.
+            cover_art: album_id.clone().unwrap_or_else(|| song_id(&song.id)),
+            content_type: mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .essence_str()
+                .to_string(),
+            suffix: path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase()),
+            duration: song.duration_ms.map(|duration| duration / 1000),
+            bit_rate: song.bitrate_kbps,
+            disc_number: song.disc_number,
+            album_id,
+            media_type: "music",
+            created: song.added_at,
+            play_count: song.play_count,
+            played: song.last_played_at,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AlbumId3 {
+    id: String,
+    name: String,
+    artist: Option<String>,
+    artist_id: Option<String>,
+    cover_art: String,
+    song_count: usize,
+    /// Duration in seconds.
+    duration: u64,
+    play_count: u64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    created: Option<OffsetDateTime>,
+    year: Option<u32>,
+    genre: Option<String>,
+    /// Only set when a single album is requested.
+    song: Option<Vec<Child>>,
+}
+
+impl AlbumId3 {
+    fn new(album: Album, with_songs: bool) -> Self {
+        let id = album_id(&album.title);
+        let year = album_year(&album);
+        let duration_ms = album.duration_ms.unwrap_or_else(|| {
+            album
+                .tracks
+                .iter()
+                .filter_map(|track| track.duration_ms)
+                .map(u64::from)
+                .sum()
+        });
+
+        Self {
+            cover_art: id.clone(),
+            id,
+            artist_id: album.artist.as_deref().and_then(first_artist_id),
+            artist: album.artist,
+            song_count: album.tracks.len(),
+            duration: duration_ms / 1000,
+            play_count: album
+                .tracks
+                .iter()
+                .map(|track| u64::from(track.play_count))
+                .sum(),
+            created: album.tracks.iter().filter_map(|track| track.added_at).min(),
+            year,
+            genre: album.tracks.iter().find_map(|track| track.genre.clone()),
+            song: with_songs.then(|| album.tracks.into_iter().map(Child::from).collect()),
+            name: album.title,
+        }
+    }
+}
+
+fn album_year(album: &Album) -> Option<u32> {
+    album.year.and_then(|year| u32::try_from(year).ok())
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ArtistId3 {
+    id: String,
+    name: String,
+    album_count: usize,
+    /// Only set when a single artist is requested.
+    album: Option<Vec<AlbumId3>>,
+}
+
+impl From<Artist> for ArtistId3 {
+    fn from(artist: Artist) -> Self {
+        Self {
+            id: artist_id(&artist.name),
+            name: artist.name,
+            album_count: artist.album_count,
+            album: None,
+        }
+    }
+}
+
+async fn ping(_: Authenticated) -> Reply {
+    Reply(json!({}))
+}
+
+/// Some clients refuse to work with a server whose license isn't valid.
+async fn get_license(_: Authenticated) -> Reply {
+    Reply(json!({ "license": { "valid": true } }))
+}
+
+async fn get_music_folders(_: Authenticated) -> Reply {
+    Reply(json!({
+        "musicFolders": { "musicFolder": [{ "id": MUSIC_FOLDER_ID, "name": "Library" }] }
+    }))
+}
+
+/// Returns the artists grouped by the first letter of their name.
+async fn get_artists(_: Authenticated, State(pool): State<Pool>) -> Result<Reply, SubsonicError> {
+    let mut connection = pool.acquire().await?;
+    let artists = artists::get_artists(&mut connection).await?;
+
+    let mut indexes: BTreeMap<String, Vec<ArtistId3>> = BTreeMap::new();
+    for artist in artists {
+        let index = match artist.name.chars().next() {
+            Some(letter) if letter.is_alphabetic() => letter.to_uppercase().collect(),
+            _ => String::from("#"),
+        };
+
+        indexes
+            .entry(index)
+            .or_default()
+            .push(ArtistId3::from(artist));
+    }
+
+    let index = indexes
+        .into_iter()
+        .map(|(name, artist)| json!({ "name": name, "artist": artist }))
+        .collect::<Vec<_>>();
+
+    Ok(Reply(json!({
+        "artists": { "ignoredArticles": "", "index": index }
+    })))
+}
+
+async fn get_artist(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(parameter): Query<IdParameter>,
+) -> Result<Reply, SubsonicError> {
+    let ItemId::Artist(name) = parameter.parse()? else {
+        return Err(SubsonicError::NotFound);
+    };
+
+    let mut connection = pool.acquire().await?;
+    let artist = artists::get_artist(&mut connection, &name).await?;
+
+    let albums = artist
+        .albums
+        .into_iter()
+        .map(|album| AlbumId3::new(album, false))
+        .collect::<Vec<_>>();
+
+    Ok(Reply(json!({
+        "artist": ArtistId3 {
+            id: artist_id(&artist.name),
+            name: artist.name,
+            album_count: albums.len(),
+            album: Some(albums),
+        }
+    })))
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "camelCase")]
+struct AlbumListQuery {
+    #[serde(rename = "type")]
+    list_type: Option<String>,
+    size: Option<usize>,
+    offset: usize,
+    from_year: Option<u32>,
+    to_year: Option<u32>,
+    genre: Option<String>,
+}
+
+/// Lists albums sorted or filtered by the requested list type.
+async fn get_album_list(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(query): Query<AlbumListQuery>,
+) -> Result<Reply, SubsonicError> {
+    let list_type = query
+        .list_type
+        .ok_or(SubsonicError::MissingParameter("type"))?;
+
+    let mut connection = pool.acquire().await?;
+    let mut albums = songs::get_albums(&mut connection).await?;
+
+    let latest = |album: &Album, at: fn(&Song) -> Option<OffsetDateTime>| {
+        album.tracks.iter().filter_map(at).max()
+    };
+    let play_count = |album: &Album| {
+        album
+            .tracks
+            .iter()
+            .map(|track| u64::from(track.play_count))
+            .sum::<u64>()
+    };
+
+    match list_type.as_str() {
+        "random" => albums.sort_by_cached_key(|_| uuid::Uuid::new_v4()),
+        "newest" => albums.sort_by_cached_key(|album| Reverse(latest(album, |song| song.added_at))),
+        "recent" => {
+            albums.retain(|album| latest(album, |song| song.last_played_at).is_some());
+            albums.sort_by_cached_key(|album| Reverse(latest(album, |song| song.last_played_at)));
+        }
+        "frequent" => {
+            albums.retain(|album| play_count(album) > 0);
+            albums.sort_by_cached_key(|album| Reverse(play_count(album)));
+        }
+        "alphabeticalByName" => albums.sort_by_cached_key(|album| album.title.to_lowercase()),
+        "alphabeticalByArtist" => albums.sort_by_cached_key(|album| {
+            (
+                album.artist.as_deref().map(str::to_lowercase),
+                album.title.to_lowercase(),
+            )
+        }),
+        "byYear" => {
+            let from = query
+                .from_year
+                .ok_or(SubsonicError::MissingParameter("fromYear"))?;
+            let to = query
+                .to_year
+                .ok_or(SubsonicError::MissingParameter("toYear"))?;
+
+            albums.retain(|album| {
+                album_year(album).is_some_and(|year| (from.min(to)..=from.max(to)).contains(&year))
+            });
+            albums.sort_by_cached_key(album_year);
+
+            // A range from a later to an earlier year lists the albums newest first.
+            if from > to {
+                albums.reverse();
+            }
+        }
+        "byGenre" => {
+            let genre = query
+                .genre
+                .ok_or(SubsonicError::MissingParameter("genre"))?;
+
+            albums.retain(|album| {
+                album.tracks.iter().any(|track| {
+                    track
+                        .genre
+                        .as_deref()
+                        .is_some_and(|value| value.eq_ignore_ascii_case(&genre))
+                })
+            });
+        }
+        // Albums can't be starred or rated, so these lists are always empty.
+        "starred" | "highest" => albums.clear(),
+        _ => return Err(SubsonicError::UnknownListType(list_type)),
+    }
+
+    let album = albums
+        .into_iter()
+        .skip(query.offset)
+        .take(query.size.unwrap_or(DEFAULT_LIST_SIZE).min(MAX_LIST_SIZE))
+        .map(|album| AlbumId3::new(album, false))
+        .collect::<Vec<_>>();
+
+    Ok(Reply(json!({ "albumList2": { "album": album } })))
+}
+
+async fn get_album(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(parameter): Query<IdParameter>,
+) -> Result<Reply, SubsonicError> {
+    let ItemId::Album(title) = parameter.parse()? else {
+        return Err(SubsonicError::NotFound);
+    };
+
+    let mut connection = pool.acquire().await?;
+    let album = songs::get_album(&mut connection, title).await?;
+
+    Ok(Reply(json!({ "album": AlbumId3::new(album, true) })))
+}
+
+async fn get_song(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(parameter): Query<IdParameter>,
+) -> Result<Reply, SubsonicError> {
+    let ItemId::Song(id) = parameter.parse()? else {
+        return Err(SubsonicError::NotFound);
+    };
+
+    let mut connection = pool.acquire().await?;
+    let song = songs::get_song(&mut connection, &id).await?;
+
+    Ok(Reply(json!({ "song": Child::from(song) })))
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "camelCase")]
+struct SearchQuery {
+    query: String,
+    artist_count: Option<usize>,
+    artist_offset: usize,
+    album_count: Option<usize>,
+    album_offset: usize,
+    song_count: Option<u32>,
+    song_offset: u32,
+}
+
+/// Searches artists by name, albums by title and artist and songs by title, artist and album.
+///
+/// An empty query matches everything, clients use it to page through the whole library.
+async fn search(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Reply, SubsonicError> {
+    // Some clients quote the query, `""` is how they ask for everything.
+    let text = query.query.trim().trim_matches('"').to_lowercase();
+    let matches = |value: &str| value.to_lowercase().contains(&text);
+
+    let mut connection = pool.acquire().await?;
+
+    let artist = artists::get_artists(&mut connection)
+        .await?
+        .into_iter()
+        .filter(|artist| matches(&artist.name))
+        .skip(query.artist_offset)
+        .take(query.artist_count.unwrap_or(DEFAULT_SEARCH_COUNT))
+        .map(ArtistId3::from)
+        .collect::<Vec<_>>();
+
+    let album = songs::get_albums(&mut connection)
+        .await?
+        .into_iter()
+        .filter(|album| matches(&album.title) || album.artist.as_deref().is_some_and(matches))
+        .skip(query.album_offset)
+        .take(query.album_count.unwrap_or(DEFAULT_SEARCH_COUNT))
+        .map(|album| AlbumId3::new(album, false))
+        .collect::<Vec<_>>();
+
+    let song_query = SongQuery {
+        limit: Some(query.song_count.unwrap_or(DEFAULT_SEARCH_COUNT as u32)),
+        offset: Some(query.song_offset),
+        ..Default::default()
+    };
+    let Page { items, .. } = if text.is_empty() {
+        songs::get_songs_paginated(&mut connection, song_query).await?
+    } else {
+        let filter = SongFilter::Any {
+            filters: [FilterField::Title, FilterField::Artist, FilterField::Album]
+                .into_iter()
+                .map(|field| SongFilter::Rule {
+                    field,
+                    op: FilterOperator::Contains,
+                    value: Some(FilterValue::Text(text.clone())),
+                })
+                .collect(),
+        };
+
+        songs::get_songs_matching(&mut connection, &filter, song_query).await?
+    };
+    let song = items.into_iter().map(Child::from).collect::<Vec<_>>();
+
+    Ok(Reply(json!({
+        "searchResult3": { "artist": artist, "album": album, "song": song }
+    })))
+}
+
+/// Streams the song as it's stored, nothing is transcoded so `maxBitRate` and `format` are
+/// ignored.
+async fn stream(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(parameter): Query<IdParameter>,
+    headers: HeaderMap,
+) -> Result<Response, SubsonicError> {
+    let ItemId::Song(id) = parameter.parse()? else {
+        return Err(SubsonicError::NotFound);
+    };
+
+    Ok(stream_song(State(pool), Path(id), headers)
+        .await
+        .into_response())
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct CoverArtParameters {
+    id: Option<String>,
+    size: Option<i64>,
+}
+
+/// Returns the front cover of an album or song as a JPEG, like `/api/albums/{album}/cover-art`.
+async fn get_cover_art(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    State(cache): State<SharedCoverArtCache>,
+    State(settings): State<Settings>,
+    Query(parameters): Query<CoverArtParameters>,
+) -> Result<Response, SubsonicError> {
+    let id = IdParameter { id: parameters.id }.parse()?;
+    let query = CoverArtQuery {
+        size: parameters.size,
+        ..Default::default()
+    };
+    let cover_type = String::from("front.jpg");
+    let uri = Uri::from_static("/front.jpg");
+
+    let response = match id {
+        ItemId::Song(id) => get_song_cover_art(
+            State(pool),
+            State(cache),
+            State(settings),
+            Path((id, cover_type)),
+            Query(query),
+            uri,
+        )
+        .await
+        .into_response(),
+        ItemId::Album(title) => get_album_cover_art(
+            State(pool),
+            State(cache),
+            State(settings),
+            AlbumKey::Title(title.clone()),
+            Path((title, cover_type)),
+            Query(query),
+            uri,
+        )
+        .await
+        .into_response(),
+        ItemId::Artist(_) => return Err(SubsonicError::NotFound),
+    };
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(SubsonicError::NotFound);
+    }
+
+    Ok(response)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+struct ScrobbleQuery {
+    id: Option<String>,
+    /// When the song was played, in milliseconds since the Unix epoch.
+    time: Option<i64>,
+    /// Whether the song was played, rather than just started.
+    submission: bool,
+}
+
+impl Default for ScrobbleQuery {
+    fn default() -> Self {
+        Self {
+            id: None,
+            time: None,
+            submission: true,
+        }
+    }
+}
+
+/// Records a play of the song, notifications that a song started playing are ignored.
+async fn scrobble(
+    _: Authenticated,
+    State(pool): State<Pool>,
+    Query(query): Query<ScrobbleQuery>,
+) -> Result<Reply, SubsonicError> {
+    let ItemId::Song(id) = IdParameter { id: query.id }.parse()? else {
+        return Err(SubsonicError::NotFound);
+    };
+
+    if query.submission {
+        let played_at = query
+            .time
+            .and_then(|time| {
+                OffsetDateTime::from_unix_timestamp_nanos(i128::from(time) * 1_000_000).ok()
+            })
+            .unwrap_or_else(OffsetDateTime::now_utc);
+
+        let mut connection = pool.acquire().await?;
+        songs::record_play(&mut connection, &id, None, played_at).await?;
+    }
+
+    Ok(Reply(json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> Auth {
+        Auth {
+            tokens: BTreeMap::from([(String::from("phone"), String::from("phone-token"))]),
+            ..Default::default()
+        }
+    }
+
+    fn credentials(u: &str, p: Option<&str>, t: Option<&str>, s: Option<&str>) -> Credentials {
+        Credentials {
+            u: Some(u.to_string()),
+            p: p.map(str::to_string),
+            t: t.map(str::to_string),
+            s: s.map(str::to_string),
+        }
+    }
+
+    fn failure(result: Result<(), SubsonicError>) -> Option<u32> {
+        result.err().map(|err| err.code())
+    }
+
+    #[test]
+    fn test_authentication() {
+        let auth = auth();
+        let hash = format!("{:x}", Md5::digest("phone-tokenc19b2d"));
+
+        assert_eq!(
+            failure(authenticate(
+                &Auth::default(),
+                &Credentials::default(),
+                None
+            )),
+            None
+        );
+        assert_eq!(
+            failure(authenticate(&auth, &Credentials::default(), None)),
+            Some(10)
+        );
+
+        for valid in [
+            credentials("phone", Some("phone-token"), None, None),
+            credentials("phone", Some("enc:70686f6e652d746f6b656e"), None, None),
+            credentials("phone", None, Some(&hash), Some("c19b2d")),
+        ] {
+            assert_eq!(failure(authenticate(&auth, &valid, None)), None);
+        }
+
+        for invalid in [
+            credentials("laptop", Some("phone-token"), None, None),
+            credentials("phone", Some("phone"), None, None),
+            credentials("phone", Some("enc:zz"), None, None),
+            credentials("phone", None, Some(&hash), Some("salt")),
+        ] {
+            assert_eq!(failure(authenticate(&auth, &invalid, None)), Some(40));
+        }
+
+        assert_eq!(
+            failure(authenticate(
+                &auth,
+                &credentials("phone", None, None, None),
+                None
+            )),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn test_ids() {
+        let id = album_id("Kind of Blue / Remastered");
+        assert!(id.starts_with(ALBUM_PREFIX));
+        assert_eq!(
+            ItemId::parse(&id),
+            Some(ItemId::Album(String::from("Kind of Blue / Remastered")))
+        );
+        assert_eq!(
+            ItemId::parse(&artist_id("Miles Davis")),
+            Some(ItemId::Artist(String::from("Miles Davis")))
+        );
+        assert_eq!(
+            ItemId::parse("tr-1234"),
+            Some(ItemId::Song(String::from("1234")))
+        );
+        assert_eq!(ItemId::parse("al-!!"), None);
+        assert_eq!(ItemId::parse("1234"), None);
+    }
+
+    #[test]
+    fn test_xml_rendering() {
+        let Value::Object(root) = json!({
+            "status": "ok",
+            "album": {
+                "name": "Tom & \"Jerry\" <Live>",
+                "songCount": 2,
+                "song": [{ "id": "tr-1" }, { "id": "tr-2" }],
+            },
+        }) else {
+            unreachable!();
+        };
+
+        let mut xml = String::new();
+        write_element(&mut xml, "subsonic-response", &root);
+
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<subsonic-response status="ok">"#,
+                r#"<album name="Tom &amp; &quot;Jerry&quot; &lt;Live&gt;" songCount="2">"#,
+                r#"<song id="tr-1"/><song id="tr-2"/>"#,
+                "</album></subsonic-response>"
+            )
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Result},
+    routing::{delete, get, post},
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::task::spawn_blocking;
+use ts_rs::TS;
+
+use crate::{
+    AppState,
+    config::Trash,
+    fs::{Operation, OperationError},
+    paths::trash_dir,
+    state::FileOperationManager,
+    trash::{self, TrashEntry},
+};
+
+use super::{maintenance::OutsideMaintenance, *};
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RestoreOptions {
+    /// Replace the files added at the original paths since they were deleted.
+    overwrite: bool,
+}
+
+/// Entries permanently deleted from the trash.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgedTrash {
+    pub ids: Vec<String>,
+    /// Bytes freed on the disk of the trash.
+    pub freed_bytes: u64,
+}
+
+impl From<Vec<TrashEntry>> for PurgedTrash {
+    fn from(entries: Vec<TrashEntry>) -> Self {
+        Self {
+            freed_bytes: entries.iter().map(|entry| entry.size).sum(),
+            ids: entries.into_iter().map(|entry| entry.id).collect(),
+        }
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/fs/trash", get(get_trash).delete(purge_trash))
+        .route("/api/fs/trash/{id}", delete(purge_trash_entry))
+        .route("/api/fs/trash/{id}/restore", post(restore_trash_entry))
+}
+
+/// Purges what's past the age and size limits of the trash, returning the purged entries.
+pub async fn purge_expired_trash(settings: Trash) -> Vec<TrashEntry> {
+    let result = spawn_blocking(move || {
+        trash::purge_expired(&trash_dir(), &settings, OffsetDateTime::now_utc())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(purged)) => purged,
+        Ok(Err(err)) => {
+            tracing::error!("Failed to purge the trash: {err}");
+            Vec::new()
+        }
+        Err(err) => {
+            tracing::error!("Failed to purge the trash: {err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Lists the deletions in the trash, oldest first.
+async fn get_trash() -> Result<Json<Vec<TrashEntry>>> {
+    let entries = spawn_blocking(|| trash::list_entries(&trash_dir()))
+        .await
+        .map_err(internal_error)?
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(entries))
+}
+
+/// Permanently deletes everything in the trash.
+async fn purge_trash(_: OutsideMaintenance) -> Result<Json<PurgedTrash>> {
+    let purged = spawn_blocking(|| {
+        let root = trash_dir();
+        trash::list_entries(&root)?
+            .into_iter()
+            .map(|entry| trash::purge_entry(&root, &entry.id))
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(PurgedTrash::from(purged)))
+}
+
+async fn purge_trash_entry(
+    _: OutsideMaintenance,
+    Path(id): Path<String>,
+) -> Result<Json<PurgedTrash>> {
+    let purged = spawn_blocking(move || trash::purge_entry(&trash_dir(), &id))
+        .await
+        .map_err(internal_error)?
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(PurgedTrash::from(vec![purged])))
+}
+
+/// Moves the files of the entry back to where they were deleted from, the songs come back once
+/// the directory watcher or the next scan picks them up.
+async fn restore_trash_entry(
+    _: OutsideMaintenance,
+    State(manager): State<FileOperationManager>,
+    Path(id): Path<String>,
+    Query(options): Query<RestoreOptions>,
+) -> Result<Json<TrashEntry>> {
+    let entry = spawn_blocking({
+        let id = id.clone();
+        move || trash::read_entry(&trash_dir(), &id)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(IntoResponse::into_response)?;
+
+    let mut operation_handle = manager
+        .queue_operation(Operation::Restore {
+            trash: trash_dir(),
+            id,
+            overwrite: options.overwrite,
+        })
+        .await?;
+
+    while operation_handle.events().recv().await.is_some() {}
+
+    match operation_handle.result().await.map_err(internal_error)? {
+        Ok(()) => Ok(Json(entry)),
+        Err(OperationError::Trash(err)) => Err(err.into_response().into()),
+        Err(err @ OperationError::FileAlreadyExists(_)) => Err(conflict(err).into()),
+        Err(err) => Err(internal_error(err).into()),
+    }
+}
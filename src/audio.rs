@@ -0,0 +1,145 @@
+//! Decoding songs to raw samples with symphonia, for analyses that need the audio itself rather
+//! than its tags.
+
+use std::{fs::File, ops::ControlFlow, path::Path};
+
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AudioError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to decode file: {0}")]
+    Decode(#[from] SymphoniaError),
+    #[error("File has no audio track")]
+    NoTrack,
+    #[error("File doesn't report its sample rate or channels")]
+    UnknownLayout,
+}
+
+/// The default audio track of a file, decoded packet by packet.
+pub struct AudioDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    channels: u32,
+    /// Length of the track in frames, if the container stores it.
+    frames: Option<u64>,
+}
+
+impl AudioDecoder {
+    pub fn open(path: &Path) -> Result<Self, AudioError> {
+        let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                source,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )?
+            .format;
+
+        let track = format.default_track().ok_or(AudioError::NoTrack)?;
+        let track_id = track.id;
+        let frames = track.codec_params.n_frames;
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or(AudioError::UnknownLayout)?;
+        let channels = track
+            .codec_params
+            .channels
+            .ok_or(AudioError::UnknownLayout)?
+            .count() as u32;
+
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            sample_rate,
+            channels,
+            frames,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// Length of the track as stored in the container, which may not match the decoded length.
+    pub fn duration_ms(&self) -> Option<u64> {
+        self.frames
+            .map(|frames| frames * 1000 / u64::from(self.sample_rate))
+    }
+
+    /// Passes the interleaved samples of every packet to `consume`, until the end of the file or
+    /// until `consume` breaks.
+    ///
+    /// Packets that fail to decode are skipped, like players do, as long as the rest of the file
+    /// can be read.
+    pub fn decode<E>(
+        mut self,
+        mut consume: impl FnMut(&[f32]) -> Result<ControlFlow<()>, E>,
+    ) -> Result<(), E>
+    where
+        E: From<AudioError>,
+    {
+        let mut samples: Option<SampleBuffer<f32>> = None;
+
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err))
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(());
+                }
+                Err(err) => return Err(AudioError::from(err).into()),
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(SymphoniaError::DecodeError(err)) => {
+                    tracing::debug!("Skipping undecodable packet: {err}");
+                    continue;
+                }
+                Err(err) => return Err(AudioError::from(err).into()),
+            };
+
+            let samples = samples.get_or_insert_with(|| {
+                SampleBuffer::new(decoded.capacity() as u64, *decoded.spec())
+            });
+            samples.copy_interleaved_ref(decoded);
+
+            if consume(samples.samples())?.is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
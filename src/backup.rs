@@ -0,0 +1,227 @@
+//! Snapshots of the database, made on request and before migrations are applied.
+//!
+//! Backups are complete SQLite databases written with `VACUUM INTO`, named after when they were
+//! made. Restoring one is a matter of stopping the server and copying it over the database file.
+
+use std::{io, path::Path};
+
+use serde::Serialize;
+use sqlx::SqliteConnection;
+use time::OffsetDateTime;
+use ts_rs::TS;
+
+const FILE_PREFIX: &str = "muusik-";
+const FILE_EXTENSION: &str = "db";
+
+#[derive(thiserror::Error, Debug)]
+pub enum BackupError {
+    #[error("The database is only kept in memory, there's no file to back up")]
+    InMemory,
+    #[error("Failed to back up the database: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to access the backups: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T, E = BackupError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    /// File name of the backup in the backups directory.
+    pub name: String,
+    /// Bytes the backup takes up.
+    pub size: u64,
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Writes a snapshot of the database into the directory, `label` ends up in its file name.
+///
+/// `VACUUM INTO` reads the database in a single read transaction, which doesn't keep other
+/// connections from writing as the database is in WAL mode. The snapshot is written under a
+/// temporary name first, so a backup that fails halfway is never listed.
+pub async fn create_backup(
+    connection: &mut SqliteConnection,
+    directory: &Path,
+    label: Option<&str>,
+) -> Result<Backup> {
+    if !is_file_database(connection).await? {
+        return Err(BackupError::InMemory);
+    }
+
+    tokio::fs::create_dir_all(directory).await?;
+
+    let now = OffsetDateTime::now_utc();
+    let name = backup_name(now, label);
+    let path = directory.join(&name);
+    let partial = directory.join(format!("{name}.partial"));
+
+    let result = sqlx::query("VACUUM INTO ?")
+        .bind(partial.to_string_lossy())
+        .execute(&mut *connection)
+        .await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(err.into());
+    }
+
+    tokio::fs::rename(&partial, &path).await?;
+
+    Ok(Backup {
+        size: tokio::fs::metadata(&path).await?.len(),
+        name,
+        created_at: now,
+    })
+}
+
+/// Returns the backups in the directory, newest first.
+pub async fn list_backups(directory: &Path) -> Result<Vec<Backup>> {
+    let mut backups = Vec::new();
+
+    let mut entries = match tokio::fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(backups),
+        Err(err) => return Err(err.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_backup_name(&name) {
+            continue;
+        }
+
+        let metadata = entry.metadata().await?;
+        backups.push(Backup {
+            name,
+            size: metadata.len(),
+            created_at: metadata
+                .modified()
+                .map(OffsetDateTime::from)
+                .unwrap_or(OffsetDateTime::UNIX_EPOCH),
+        });
+    }
+
+    // The names start with when the backup was made, so they sort the same as their dates.
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+
+    Ok(backups)
+}
+
+/// Removes every backup but the newest `keep` ones, `0` keeps all of them. Returns the removed
+/// backups.
+pub async fn prune_backups(directory: &Path, keep: usize) -> Result<Vec<Backup>> {
+    if keep == 0 {
+        return Ok(Vec::new());
+    }
+
+    let expired = list_backups(directory)
+        .await?
+        .into_iter()
+        .skip(keep)
+        .collect::<Vec<_>>();
+
+    for backup in &expired {
+        tokio::fs::remove_file(directory.join(&backup.name)).await?;
+    }
+
+    Ok(expired)
+}
+
+/// Whether the database is stored in a file, rather than only in memory.
+async fn is_file_database(connection: &mut SqliteConnection) -> Result<bool> {
+    let file = sqlx::query_scalar::<_, String>(
+        "SELECT file FROM pragma_database_list WHERE name = 'main'",
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    Ok(file.is_some_and(|file| !file.is_empty()))
+}
+
+/// Names a backup after when it was made, down to the millisecond so they don't collide.
+fn backup_name(created_at: OffsetDateTime, label: Option<&str>) -> String {
+    let timestamp = format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}.{:03}Z",
+        created_at.year(),
+        u8::from(created_at.month()),
+        created_at.day(),
+        created_at.hour(),
+        created_at.minute(),
+        created_at.second(),
+        created_at.millisecond(),
+    );
+
+    match label {
+        Some(label) => format!("{FILE_PREFIX}{timestamp}-{label}.{FILE_EXTENSION}"),
+        None => format!("{FILE_PREFIX}{timestamp}.{FILE_EXTENSION}"),
+    }
+}
+
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with(FILE_PREFIX)
+        && Path::new(name)
+            .extension()
+            .is_some_and(|extension| extension == FILE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Connection, query, query_scalar};
+    use test_log::test;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_backing_up_and_pruning() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backups = dir.path().join("backups");
+
+        let mut connection = SqliteConnection::connect(&format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("a.db").display()
+        ))
+        .await?;
+        query("CREATE TABLE songs (title TEXT)")
+            .execute(&mut connection)
+            .await?;
+        query("INSERT INTO songs (title) VALUES ('Goose')")
+            .execute(&mut connection)
+            .await?;
+
+        let first = create_backup(&mut connection, &backups, Some("pre-migration")).await?;
+        assert!(first.name.ends_with("-pre-migration.db"));
+        assert!(first.size > 0);
+
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let second = create_backup(&mut connection, &backups, None).await?;
+
+        let listed = list_backups(&backups).await?;
+        assert_eq!(
+            listed.iter().map(|backup| &backup.name).collect::<Vec<_>>(),
+            [&second.name, &first.name]
+        );
+
+        let mut restored =
+            SqliteConnection::connect(&format!("sqlite://{}", backups.join(&first.name).display()))
+                .await?;
+        let title = query_scalar::<_, String>("SELECT title FROM songs")
+            .fetch_one(&mut restored)
+            .await?;
+        assert_eq!(title, "Goose");
+
+        assert!(prune_backups(&backups, 0).await?.is_empty());
+        assert_eq!(prune_backups(&backups, 1).await?, [listed[1].clone()]);
+        assert_eq!(list_backups(&backups).await?, [listed[0].clone()]);
+
+        let mut memory = SqliteConnection::connect("sqlite::memory:").await?;
+        assert!(matches!(
+            create_backup(&mut memory, &backups, None).await,
+            Err(BackupError::InMemory)
+        ));
+
+        Ok(())
+    }
+}
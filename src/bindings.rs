@@ -0,0 +1,256 @@
+//! The types shared with the frontend, exported together into a single file by the
+//! `export_bindings` test, so the frontend is always built against one consistent set of them.
+
+/// Version of the payloads of the API, bumped whenever a change would break clients built
+/// against an earlier version, like a renamed, removed or retyped field.
+///
+/// The bindings export it as well, so the frontend can tell the server which version it expects.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        path::Path,
+    };
+
+    use ts_rs::{Config, TS, TypeVisitor};
+
+    use super::*;
+    use crate::{
+        api::{
+            admin::MaintenanceRequest,
+            auth::{Login, LoginResponse},
+            cover_art::{EmbeddedAlbumCoverArt, EmbeddedCoverArt, ExtractedCoverArt},
+            directories::{
+                DirectoriesQuery, DirectoryResponse, FolderEntry, FolderQuery, UpdateDirectoryQuery,
+            },
+            genres::{GenreMergeRequest, GenreMergeResult, GenresQuery},
+            home::Home,
+            inbox::{
+                InboxApply, InboxDuplicate, InboxDuplicates, InboxIdentification, InboxIdentify,
+                InboxRelease,
+            },
+            include::{Expanded, Include, Included, IncludedAlbum, IncludedDirectory},
+            info::{AppInfo, LogsQuery, SystemInfo},
+            jobs::{JobReportsResponse, JobStateResponse, RegistryJob},
+            library::{LibraryFormat, LibraryImportOptions},
+            organize::{
+                OrganizeStarted, OrganizeSummary, PathRenameOptions, PathRenamePreviewResult,
+                TemplatePreview, TemplatePreviewRequest, TemplateRender, TemplateRenderError,
+            },
+            playlists::{ImportOptions, PlaylistSong},
+            providers::ProviderInfo,
+            search::LibrarySearchQuery,
+            settings::SongFileTypeList,
+            songs::{
+                ApplyIdentification, BulkDeleteResult, BulkDeleteStatus, BulkEditResult,
+                BulkEditStatus, BulkMetadataEdit, BulkSongs, InferPreviewQuery, MetadataWrite,
+                PlayReport, PurgedSongs, RelocateMismatch, RelocateSong, SongFileInfo, SongLyrics,
+                SongRating, StreamOptions,
+            },
+            trash::PurgedTrash,
+        },
+        backup::Backup,
+        db::{
+            Album, AlbumDisc, AlbumMatch, AlbumPlays, Artist, ArtistDetail, ArtistMatch,
+            ArtistPlays, BulkAddResult, DuplicatePath, FilterField, FilterOperator, FilterValue,
+            Genre, HistoryEvent, HistoryEventKind, HistoryQuery, ImportConflict, IntegrityRepair,
+            IntegrityReport, IntegritySong, JobSchedule, LibraryImportSummary, MaintenanceMode,
+            NewDirectory, NewJobSchedule, NewPlaylist, NewSmartPlaylist, NewSong, OnThisDay, Page,
+            Pin, PinKind, PinTarget, PinnedItem, PlayStats, PlayStatsQuery, Playlist,
+            PlaylistImport, PlaylistWithTracks, ScanError, ScanErrorCategory, ScheduleTrigger,
+            SearchResults, SmartPlaylist, Song, SongFilter, SongMatch, SongPlays, SongQuery,
+            SongSortColumn, SortOrder, UnresolvedEntry, UpdatedDirectory, UpdatedPlaylist,
+            UpdatedSong, UpdatedSongPreferences, YearInReview,
+        },
+        events::{
+            DirectoryWatcherEvent, EventSubscription, FileOperationManagerEvent, JobManagerEvent,
+            WebSocketMessage,
+        },
+        fs::OperationKind,
+        genres::GenreMerge,
+        history::FieldChange,
+        hygiene::{AlbumHygieneReport, HygieneCheck, HygieneFinding, LibraryHygieneReport},
+        inbox::{InboxCandidate, InboxTrack, TrackProposal},
+        infer::InferredMetadata,
+        jobs::{PlannedSong, PlaylistBundle, ScanSongsPlan},
+        logging::{LogLevel, LogTail},
+        m3u::PlaylistFormat,
+        metadata::{
+            Album as AlbumMetadata, AudioProperties, FieldSchema, FileHealth, Metadata,
+            MetadataSchema, SongFile, SongFileType, TagWarning,
+            item::{ItemKey, TagType},
+        },
+        palette::Palette,
+        providers::{IdentifyCandidate, Release, ReleaseSummary, ReleaseTrack, SearchQuery},
+        state::{
+            OperationState, OperationStatus,
+            job::{JobExecutionReport, JobParameters, JobProgress, JobState, JobStatus},
+        },
+        transcode::TranscodeFormat,
+        trash::{TrashEntry, TrashedPath},
+    };
+
+    /// Where the frontend imports the bindings from, relative to the crate.
+    const BINDINGS_PATH: &str = "frontend/src/lib/bindings/bindings.ts";
+
+    const HEADER: &str = "// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.";
+
+    /// A type as it's declared in the bindings.
+    struct Declaration {
+        name: String,
+        /// Names of the other exported types the declaration refers to.
+        dependencies: BTreeSet<String>,
+        source: String,
+    }
+
+    impl Declaration {
+        fn of<T: TS + 'static>(cfg: &Config) -> Self {
+            let mut dependencies = Dependencies {
+                cfg,
+                names: BTreeSet::new(),
+            };
+            T::visit_dependencies(&mut dependencies);
+
+            Self {
+                name: T::ident(cfg),
+                dependencies: dependencies.names,
+                source: format!("{}export {}", T::docs().unwrap_or_default(), T::decl(cfg)),
+            }
+        }
+    }
+
+    struct Dependencies<'a> {
+        cfg: &'a Config,
+        names: BTreeSet<String>,
+    }
+
+    impl TypeVisitor for Dependencies<'_> {
+        fn visit<T: TS + 'static + ?Sized>(&mut self) {
+            // Only derived types have somewhere to be exported to, the built-in ones are inlined.
+            if T::output_path().is_some() {
+                self.names.insert(T::ident(self.cfg));
+            }
+        }
+    }
+
+    macro_rules! declarations {
+        ($cfg:expr; $($ty:ty),+ $(,)?) => {
+            vec![$(Declaration::of::<$ty>($cfg)),+]
+        };
+    }
+
+    /// Every type the frontend can receive or send, generic ones are declared with their type
+    /// parameters no matter what they're instantiated with here.
+    fn declarations(cfg: &Config) -> Vec<Declaration> {
+        declarations![cfg;
+            MaintenanceRequest, Login, LoginResponse, EmbeddedAlbumCoverArt, EmbeddedCoverArt,
+            ExtractedCoverArt, DirectoriesQuery, DirectoryResponse, FolderEntry, FolderQuery, Home,
+            InboxApply, InboxIdentification, InboxIdentify, InboxRelease, Expanded<()>, Include,
+            Included, IncludedAlbum, IncludedDirectory, AppInfo, SystemInfo, JobReportsResponse,
+            JobStateResponse, RegistryJob, LibraryFormat, LibraryImportOptions, OrganizeStarted,
+            OrganizeSummary, PathRenameOptions, PathRenamePreviewResult, TemplatePreview,
+            TemplatePreviewRequest, TemplateRender, TemplateRenderError, ImportOptions,
+            PlaylistSong, ProviderInfo, SongFileTypeList, ApplyIdentification, BulkDeleteResult,
+            BulkDeleteStatus, BulkEditResult, BulkEditStatus, BulkMetadataEdit, BulkSongs,
+            InferPreviewQuery, PlayReport, PurgedSongs, RelocateMismatch, RelocateSong,
+            SongFileInfo, SongLyrics, SongRating, StreamOptions, PurgedTrash, Album, AlbumDisc,
+            AlbumPlays, Artist, ArtistDetail, ArtistPlays, BulkAddResult, FilterField,
+            FilterOperator, FilterValue, HistoryEvent, HistoryEventKind, HistoryQuery,
+            ImportConflict, JobSchedule, LibraryImportSummary, MaintenanceMode, NewDirectory,
+            NewJobSchedule, NewPlaylist, NewSmartPlaylist, NewSong, OnThisDay, Page<()>, Pin,
+            PinKind, PinTarget, PinnedItem, PlayStats, PlayStatsQuery, Playlist, PlaylistImport,
+            PlaylistWithTracks, ScanError, ScanErrorCategory, ScheduleTrigger, SmartPlaylist, Song,
+            SongFilter, SongQuery, SongSortColumn, SortOrder, UnresolvedEntry, UpdatedPlaylist,
+            UpdatedSong, UpdatedSongPreferences, YearInReview, DirectoryWatcherEvent,
+            FileOperationManagerEvent, JobManagerEvent, OperationKind, FieldChange,
+            AlbumHygieneReport, HygieneCheck, HygieneFinding, LibraryHygieneReport,
+            InferredMetadata, InboxCandidate, InboxTrack, TrackProposal, PlannedSong,
+            PlaylistBundle, ScanSongsPlan, PlaylistFormat, AlbumMetadata, AudioProperties,
+            FieldSchema, FileHealth, Metadata, MetadataSchema, SongFile, SongFileType, ItemKey,
+            TagType, IdentifyCandidate, Release, ReleaseSummary, ReleaseTrack, SearchQuery,
+            OperationState, OperationStatus, JobExecutionReport, JobParameters, JobProgress,
+            JobState, JobStatus, TrashEntry, TrashedPath, UpdateDirectoryQuery, UpdatedDirectory,
+            DuplicatePath, IntegrityRepair, IntegrityReport, IntegritySong, EventSubscription,
+            WebSocketMessage, TranscodeFormat, LibrarySearchQuery, SearchResults, SongMatch,
+            AlbumMatch, ArtistMatch, GenreMergeRequest, GenreMergeResult, GenresQuery, Genre,
+            GenreMerge, Backup, LogsQuery, LogLevel, LogTail, Palette, MetadataWrite, TagWarning,
+            InboxDuplicate, InboxDuplicates, SongPlays,
+        ]
+    }
+
+    /// The bindings sorted by name, so exporting them again only changes the file when a type did.
+    fn render(cfg: &Config) -> String {
+        let declarations = declarations(cfg)
+            .into_iter()
+            .map(|declaration| (declaration.name, declaration.source))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut bindings = format!("{HEADER}\n\nexport const SCHEMA_VERSION = {SCHEMA_VERSION};\n");
+        for source in declarations.into_values() {
+            bindings.push('\n');
+            bindings.push_str(&source);
+            bindings.push('\n');
+        }
+
+        bindings
+    }
+
+    #[test]
+    fn export_bindings() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(BINDINGS_PATH);
+        std::fs::write(&path, render(&Config::from_env()))
+            .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+    }
+
+    #[test]
+    fn test_bindings_names_are_unique() {
+        let cfg = Config::from_env();
+        let mut names = BTreeSet::new();
+        let duplicates = declarations(&cfg)
+            .into_iter()
+            .filter(|declaration| !names.insert(declaration.name.clone()))
+            .map(|declaration| declaration.name)
+            .collect::<Vec<_>>();
+
+        assert!(
+            duplicates.is_empty(),
+            "Types exported under the same name: {duplicates:?}"
+        );
+    }
+
+    #[test]
+    fn test_bindings_dependencies_are_exported() {
+        let cfg = Config::from_env();
+        let declarations = declarations(&cfg);
+        let names = declarations
+            .iter()
+            .map(|declaration| declaration.name.as_str())
+            .collect::<BTreeSet<_>>();
+
+        for declaration in &declarations {
+            let missing = declaration
+                .dependencies
+                .iter()
+                .filter(|dependency| !names.contains(dependency.as_str()))
+                .collect::<Vec<_>>();
+
+            assert!(
+                missing.is_empty(),
+                "{} refers to types missing from the bindings: {missing:?}",
+                declaration.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_bindings_are_deterministic() {
+        let cfg = Config::from_env();
+        let bindings = render(&cfg);
+
+        assert_eq!(bindings, render(&cfg));
+        assert!(bindings.contains(&format!("export const SCHEMA_VERSION = {SCHEMA_VERSION};")));
+        assert!(bindings.find("export type Album ") < bindings.find("export type AlbumDisc "));
+    }
+}
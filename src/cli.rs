@@ -0,0 +1,242 @@
+//! Commands that run a single task against the library and exit, without starting the server.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use axum::response::Response;
+use clap::Subcommand;
+use color_eyre::eyre::{Report, Result, eyre};
+use tokio::{signal, sync::mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    api::{
+        albums::AlbumKey,
+        organize::{PathRenameOptions, organize_album, preview_album},
+    },
+    config::{Settings, parse_file_types},
+    events::EventBus,
+    jobs::{JobEvent, JobHandle, ScanSongs},
+    paths,
+    state::{
+        CoverArtCache, OperationManager, Pool, SongFileTypes,
+        job::{JobInfo, JobParameters},
+    },
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Start the server (default)
+    Serve,
+    /// Scan the directories for new, updated and missing songs, then exit
+    Scan {
+        /// Only scan the directory with this name
+        #[arg(long)]
+        directory: Option<String>,
+
+        /// Only print what the scan would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move the files of an album to the paths rendered from the organize template, then exit
+    Organize {
+        /// Title of the album to organize
+        #[arg(long)]
+        album: String,
+
+        /// Name of a template from the organize settings to use instead of the default one
+        #[arg(long)]
+        template_name: Option<String>,
+
+        /// Only print where the files would be moved
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Runs a command other than [`Command::Serve`] to completion.
+pub async fn run_command(command: Command, pool: Pool, settings: Settings) -> Result<()> {
+    match command {
+        Command::Serve => Err(eyre!("The server isn't started as a command")),
+        Command::Scan { directory, dry_run } => scan(pool, settings, directory, dry_run).await,
+        Command::Organize {
+            album,
+            template_name,
+            dry_run,
+        } => {
+            let options = PathRenameOptions {
+                template_name,
+                ..PathRenameOptions::default()
+            };
+
+            if dry_run {
+                preview_organize(&pool, &settings, album, &options).await
+            } else {
+                organize(pool, settings, album, options).await
+            }
+        }
+    }
+}
+
+async fn scan(
+    pool: Pool,
+    settings: Settings,
+    directory: Option<String>,
+    dry_run: bool,
+) -> Result<()> {
+    let cover_art_cache = Arc::new(CoverArtCache::new(
+        paths::cover_art_cache_dir(),
+        settings.cache.cover_art_size_limit_mb * 1024 * 1024,
+    ));
+    let song_file_types = Arc::new(SongFileTypes::new(parse_file_types(
+        &settings.scan.file_types,
+    )?));
+
+    let job = ScanSongs::new(
+        pool,
+        cover_art_cache,
+        song_file_types,
+        Arc::new(RwLock::new(settings)),
+    );
+    let parameters = JobParameters {
+        directory,
+        dry_run,
+        ..JobParameters::default()
+    };
+
+    run_job(ScanSongs::job_info(), job, parameters).await
+}
+
+/// Runs the job in place of the job manager, printing its events as they come in.
+///
+/// The job is cancelled on Ctrl+C, which fails the command.
+async fn run_job(info: JobInfo, job: impl JobHandle, parameters: JobParameters) -> Result<()> {
+    let (tx, mut rx) = mpsc::channel(64);
+    let token = CancellationToken::new();
+
+    let cancel = token.clone();
+    let name = info.name.clone();
+    tokio::spawn(async move {
+        if signal::ctrl_c().await.is_ok() {
+            println!("Cancelling {name}...");
+            cancel.cancel();
+        }
+    });
+
+    let steps = info.steps.clone();
+    let printer = tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            print_event(&steps, event);
+        }
+    });
+
+    println!("Running {}", info.name);
+    let artifact = job.execute(parameters, token.clone(), tx).await;
+    printer.await?;
+
+    if token.is_cancelled() {
+        return Err(eyre!("{} was cancelled", info.name));
+    }
+
+    if let Some(artifact) = artifact? {
+        println!("{}", serde_json::to_string_pretty(&artifact)?);
+    }
+
+    println!("{} completed", info.name);
+
+    Ok(())
+}
+
+fn print_event(steps: &BTreeMap<u8, String>, event: JobEvent) {
+    let step_name = |step: u8| {
+        steps
+            .get(&step)
+            .map_or_else(|| format!("Step {step}"), String::clone)
+    };
+
+    match event {
+        JobEvent::Progress {
+            current,
+            total,
+            step,
+        } => println!(
+            "[{step}/{}] {}: {current}/{total}",
+            steps.len(),
+            step_name(step)
+        ),
+        JobEvent::StepCompleted { step, value } => match value {
+            Some(value) => println!("[{step}/{}] {}: {value}", steps.len(), step_name(step)),
+            None => println!("[{step}/{}] {}: done", steps.len(), step_name(step)),
+        },
+        JobEvent::Warning { message } => println!("Warning: {message}"),
+    }
+}
+
+async fn preview_organize(
+    pool: &Pool,
+    settings: &Settings,
+    album: String,
+    options: &PathRenameOptions,
+) -> Result<()> {
+    let album = AlbumKey::Title(album);
+    let previews = match preview_album(pool, &settings.organize, album, options).await {
+        Ok(previews) => previews,
+        Err(response) => return Err(response_error(response).await),
+    };
+
+    for preview in &previews {
+        println!(
+            "{} -> {}{}",
+            preview.previous_path.display(),
+            preview.new_path.display(),
+            if preview.collides { " (collides)" } else { "" }
+        );
+    }
+
+    Ok(())
+}
+
+async fn organize(
+    pool: Pool,
+    settings: Settings,
+    album: String,
+    options: PathRenameOptions,
+) -> Result<()> {
+    let events = EventBus::new(pool.clone(), settings.events.retention_days);
+    let manager = OperationManager::new();
+    let album = AlbumKey::Title(album);
+
+    let (started, task) =
+        match organize_album(pool, events, &manager, &settings.organize, album, options).await {
+            Ok(organizing) => organizing,
+            Err(response) => return Err(response_error(response).await),
+        };
+
+    println!("Organizing {}", started.album);
+    let summary = task.await??;
+
+    for path in &summary.folder_art {
+        println!("Wrote folder art to {}", path.display());
+    }
+
+    for warning in &summary.warnings {
+        println!("Warning: {warning}");
+    }
+
+    println!("Organized {}", summary.album);
+
+    Ok(())
+}
+
+/// Turns the error response of a handler function shared with the API into an error, its body is
+/// the message.
+async fn response_error(response: Response) -> Report {
+    let status = response.status();
+
+    match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) if !body.is_empty() => eyre!("{status}: {}", String::from_utf8_lossy(&body)),
+        _ => eyre!("{status}"),
+    }
+}
@@ -3,11 +3,14 @@ use handlebars::Handlebars;
 use serde::{Deserialize, Serialize};
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs::{File, read_to_string},
     net::{IpAddr, Ipv4Addr},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
+use ipnet::IpNet;
+
 use crate::{Args, paths};
 
 type Result<T, E = ConfigError> = std::result::Result<T, E>;
@@ -18,8 +21,18 @@ pub enum ConfigError {
     Io(#[from] std::io::Error),
     #[error("Serialization error: {0}")]
     Serialization(#[from] toml::de::Error),
+    #[error("Failed to serialize settings: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("Failed to edit config file: {0}")]
+    Edit(#[from] toml_edit::TomlError),
+    #[error("Invalid file type \"{0}\", expected an extension without dots or path separators")]
+    InvalidFileType(String),
 }
 
+/// Extensions of the files scanned as songs unless the settings list others.
+pub const DEFAULT_SONG_FILE_TYPES: [&str; 8] =
+    ["mp3", "m4a", "flac", "wav", "ogg", "wma", "aac", "opus"];
+
 /// Server configuration.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Server {
@@ -36,28 +49,677 @@ pub struct Server {
     pub host: Option<IpAddr>,
 }
 
+/// Cache configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Cache {
+    /// Maximum size of the cover art cache in megabytes, least recently used entries are removed
+    /// first
+    pub cover_art_size_limit_mb: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            cover_art_size_limit_mb: 256,
+        }
+    }
+}
+
+/// Cover art configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CoverArt {
+    /// Whether albums without cover art get a generated placeholder instead of a `404`, unless
+    /// the request sets `placeholder` itself
+    pub placeholders: bool,
+
+    /// Names of the images in a song's directory used as its front cover when none is embedded,
+    /// without extension and preferring earlier ones, e.g. `cover` for `cover.jpg`
+    pub front_files: Vec<String>,
+
+    /// Names of the images in a song's directory used as its back cover when none is embedded
+    pub back_files: Vec<String>,
+}
+
+impl Default for CoverArt {
+    fn default() -> Self {
+        Self {
+            placeholders: false,
+            front_files: ["cover", "folder", "front", "album"]
+                .map(String::from)
+                .to_vec(),
+            back_files: ["back", "backcover"].map(String::from).to_vec(),
+        }
+    }
+}
+
+/// Job queue configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Jobs {
+    /// Maximum number of jobs running at the same time, exclusive jobs still run one at a time
+    pub workers: usize,
+
+    /// Whether the `check-integrity` job is queued whenever the server starts
+    pub check_integrity_on_startup: bool,
+
+    /// Seconds to wait on shutdown for running jobs and file operations to stop before exiting
+    /// anyway
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            check_integrity_on_startup: false,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+/// Scan configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Scan {
+    /// Extensions of the files the scan job and the directory watcher pick up as songs
+    pub file_types: Vec<String>,
+
+    /// Days songs whose file went missing are kept for before a scan deletes them, `0` keeps them
+    /// until they're purged
+    pub missing_grace_days: u32,
+
+    /// Whether scans read the duration, bitrate and other audio properties of songs, they're
+    /// read by the `backfill-audio-properties` job otherwise
+    pub read_audio_properties: bool,
+
+    /// Threads that read the files of songs during a scan, `0` uses one for each CPU
+    pub workers: usize,
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Self {
+            file_types: DEFAULT_SONG_FILE_TYPES.map(String::from).to_vec(),
+            missing_grace_days: 30,
+            read_audio_properties: true,
+            workers: 0,
+        }
+    }
+}
+
+impl Scan {
+    /// The number of threads that read files, at least one.
+    pub fn worker_count(&self) -> usize {
+        match self.workers {
+            0 => std::thread::available_parallelism().map_or(1, usize::from),
+            workers => workers,
+        }
+    }
+}
+
+/// Free space monitoring configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Storage {
+    /// Megabytes of free space under which a library disk counts as low on space, `0` disables
+    /// the check
+    pub low_space_mb: u64,
+
+    /// Percentage of free space under which a library disk counts as low on space, `0` disables
+    /// the check
+    pub low_space_percent: u8,
+
+    /// Minutes between checks of the free space of the library disks
+    pub check_interval_minutes: u32,
+
+    /// URL a JSON warning is posted to when a library disk runs low on space
+    pub webhook_url: Option<String>,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            low_space_mb: 10 * 1024,
+            low_space_percent: 5,
+            check_interval_minutes: 10,
+            webhook_url: None,
+        }
+    }
+}
+
+impl Storage {
+    /// Whether the free space is below either threshold.
+    pub fn is_low_space(&self, free_space: u64, total_space: u64) -> bool {
+        self.is_below(free_space, total_space, 1.0)
+    }
+
+    /// Whether the free space is below the thresholds scaled by `factor`, e.g. to leave a margin
+    /// before a disk that was low on space counts as recovered.
+    pub fn is_below(&self, free_space: u64, total_space: u64, factor: f64) -> bool {
+        let free_space = free_space as f64;
+        let below_size = self.low_space_mb > 0
+            && free_space < self.low_space_mb as f64 * 1024.0 * 1024.0 * factor;
+        let below_percent = self.low_space_percent > 0
+            && total_space > 0
+            && free_space / total_space as f64 * 100.0 < f64::from(self.low_space_percent) * factor;
+
+        below_size || below_percent
+    }
+}
+
+/// Trash configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Trash {
+    /// Megabytes the trash can take up before the oldest deletions are purged, `0` doesn't limit
+    /// it
+    pub max_size_mb: u64,
+
+    /// Days deleted files are kept in the trash for, `0` keeps them until they're purged
+    pub max_age_days: u32,
+}
+
+impl Default for Trash {
+    fn default() -> Self {
+        Self {
+            max_size_mb: 10 * 1024,
+            max_age_days: 30,
+        }
+    }
+}
+
+impl Trash {
+    /// The size the trash is limited to in bytes, `None` if it isn't limited.
+    pub fn max_size(&self) -> Option<u64> {
+        (self.max_size_mb > 0).then(|| self.max_size_mb.saturating_mul(1024 * 1024))
+    }
+
+    /// How long deleted files are kept for, `None` if they're kept until purged.
+    pub fn max_age(&self) -> Option<time::Duration> {
+        (self.max_age_days > 0).then(|| time::Duration::days(i64::from(self.max_age_days)))
+    }
+}
+
+/// Database backup configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Backups {
+    /// Number of backups kept, the oldest ones are removed whenever a backup is made, `0` keeps
+    /// every backup
+    pub keep: usize,
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        Self { keep: 10 }
+    }
+}
+
+/// Metadata history configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MetadataHistory {
+    /// Snapshots kept for each song, the oldest are removed first, `0` doesn't limit them
+    pub max_entries_per_song: u32,
+
+    /// Days snapshots are kept for, `0` keeps them forever
+    pub max_age_days: u32,
+}
+
+impl Default for MetadataHistory {
+    fn default() -> Self {
+        Self {
+            max_entries_per_song: 50,
+            max_age_days: 365,
+        }
+    }
+}
+
+impl MetadataHistory {
+    /// The number of snapshots kept for each song, `None` if it isn't limited.
+    pub fn max_entries(&self) -> Option<usize> {
+        (self.max_entries_per_song > 0).then(|| self.max_entries_per_song as usize)
+    }
+
+    /// How long snapshots are kept for, `None` if they're kept forever.
+    pub fn max_age(&self) -> Option<time::Duration> {
+        (self.max_age_days > 0).then(|| time::Duration::days(i64::from(self.max_age_days)))
+    }
+}
+
+/// Filename metadata inference configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Infer {
+    /// Patterns the end of a song's path is matched against, see [`crate::infer::Pattern`]. The
+    /// first one that matches is used
+    pub patterns: Vec<String>,
+
+    /// Whether the `infer-metadata` job also writes the inferred fields to the tags of the files,
+    /// they're only saved to the database otherwise
+    pub write_tags: bool,
+}
+
+impl Default for Infer {
+    fn default() -> Self {
+        Self {
+            patterns: [
+                "{artist}/{album}/{track} - {title}",
+                "{artist}/{album}/{track}. {title}",
+                "{artist} - {album}/{track} - {title}",
+                "{artist} - {title}",
+            ]
+            .map(String::from)
+            .to_vec(),
+            write_tags: false,
+        }
+    }
+}
+
+/// Normalizes the extensions to lowercase, rejecting the ones that contain dots or path
+/// separators since they could never match an extension.
+pub fn parse_file_types<S: AsRef<str>>(file_types: &[S]) -> Result<BTreeSet<String>> {
+    file_types
+        .iter()
+        .map(|file_type| {
+            let file_type = file_type.as_ref().trim();
+
+            if file_type.is_empty() || file_type.contains(['.', '/', '\\']) {
+                return Err(ConfigError::InvalidFileType(file_type.to_string()));
+            }
+
+            Ok(file_type.to_lowercase())
+        })
+        .collect()
+}
+
+/// Audio format tracks of a bundle are transcoded to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleFormat {
+    #[default]
+    Opus,
+    Mp3,
+    Aac,
+}
+
+impl BundleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Opus => "opus",
+            Self::Mp3 => "mp3",
+            Self::Aac => "m4a",
+        }
+    }
+
+    /// Name of the ffmpeg encoder writing the format.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            Self::Opus => "libopus",
+            Self::Mp3 => "libmp3lame",
+            Self::Aac => "aac",
+        }
+    }
+}
+
+/// Playlist bundle configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Bundles {
+    /// Whether tracks are transcoded with ffmpeg, they're copied as they are otherwise
+    pub transcode: bool,
+
+    /// Format tracks are transcoded to
+    pub format: BundleFormat,
+
+    /// Bitrate tracks are transcoded with in kbit/s
+    pub bitrate_kbps: u32,
+
+    /// The ffmpeg executable, looked up in `PATH` unless it's a path
+    pub ffmpeg: String,
+
+    /// Hours a finished bundle can be downloaded for, `0` keeps them until they're replaced
+    pub retention_hours: u32,
+}
+
+impl Default for Bundles {
+    fn default() -> Self {
+        Self {
+            transcode: false,
+            format: BundleFormat::default(),
+            bitrate_kbps: 128,
+            ffmpeg: String::from("ffmpeg"),
+            retention_hours: 24,
+        }
+    }
+}
+
+impl Bundles {
+    /// How long finished bundles are kept for, `None` if they're kept until replaced.
+    pub fn retention(&self) -> Option<time::Duration> {
+        (self.retention_hours > 0).then(|| time::Duration::hours(i64::from(self.retention_hours)))
+    }
+}
+
+/// Configuration of songs transcoded while they're streamed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Streaming {
+    /// The ffmpeg executable songs are transcoded with, looked up in `PATH` unless it's a path.
+    /// Streams can't be transcoded if not set
+    pub ffmpeg: Option<String>,
+
+    /// Maximum number of songs transcoded at the same time, streams past it are refused
+    pub max_transcodes: usize,
+
+    /// Bitrate in kbit/s songs are transcoded with when the client doesn't ask for one
+    pub bitrate_kbps: u32,
+}
+
+impl Default for Streaming {
+    fn default() -> Self {
+        Self {
+            ffmpeg: None,
+            max_transcodes: 2,
+            bitrate_kbps: 192,
+        }
+    }
+}
+
+/// Event history configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Events {
+    /// Days significant events are kept in the history for, `0` keeps them forever
+    pub retention_days: u32,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+/// Logging configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Logging {
+    /// Whether logs are also written to files in the `logs` directory of the data directory, a
+    /// new file is started every day
+    pub file: bool,
+
+    /// Format the log files are written in
+    pub format: LogFormat,
+
+    /// Number of log files kept, the oldest ones are removed when a new file is started, `0`
+    /// keeps every file
+    pub max_files: usize,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            file: false,
+            format: LogFormat::default(),
+            max_files: 14,
+        }
+    }
+}
+
+/// Format log files are written in.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// The same lines as the console, without colors
+    #[default]
+    Plain,
+    /// One JSON object per line
+    Json,
+}
+
+/// Authentication configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Auth {
+    /// Whether clients connecting from a loopback address skip authentication
+    pub allow_localhost: bool,
+
+    /// Proxies allowed to report the client address through `X-Forwarded-For`
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// API tokens by the name of the client they were handed out to, requests to the API need one
+    /// of them once any are set
+    pub tokens: BTreeMap<String, String>,
+}
+
+/// File system browsing configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Browse {
+    /// Directories the folder picker can list the contents of, along with everything inside
+    /// them. Every directory can be browsed if empty
+    pub allowed_paths: Vec<PathBuf>,
+}
+
+/// Inbox configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Inbox {
+    /// Folder new albums are dropped into, to be tagged before they're moved into the library.
+    /// The inbox is disabled if not set
+    pub path: Option<PathBuf>,
+}
+
+/// Organize configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Organize {
+    /// Named Handlebars templates albums can be organized with instead of the default one
+    pub templates: BTreeMap<String, String>,
+}
+
+/// MusicBrainz provider configuration.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MusicBrainz {
+    /// Whether releases can be looked up on MusicBrainz
+    pub enabled: bool,
+
+    /// Email address or URL sent along with requests so MusicBrainz can reach out about them
+    pub contact: Option<String>,
+}
+
+impl Default for MusicBrainz {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            contact: None,
+        }
+    }
+}
+
+/// AcoustID configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AcoustId {
+    /// Whether untagged songs can be identified by their acoustic fingerprint
+    pub enabled: bool,
+
+    /// Key of the application registered on AcoustID, lookups are disabled without one
+    pub api_key: Option<String>,
+}
+
+/// Metadata provider configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Providers {
+    pub musicbrainz: MusicBrainz,
+    pub acoustid: AcoustId,
+}
+
 /// Application settings.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Settings {
+    /// The file the settings were loaded from, changes made at runtime are saved to it
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
     pub server: Server,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub browse: Browse,
+    #[serde(default)]
+    pub cache: Cache,
+    #[serde(default)]
+    pub cover_art: CoverArt,
+    #[serde(default)]
+    pub inbox: Inbox,
+    #[serde(default)]
+    pub organize: Organize,
+    #[serde(default)]
+    pub providers: Providers,
+    #[serde(default)]
+    pub scan: Scan,
+    #[serde(default)]
+    pub storage: Storage,
+    #[serde(default)]
+    pub trash: Trash,
+    #[serde(default)]
+    pub backups: Backups,
+    #[serde(default)]
+    pub metadata_history: MetadataHistory,
+    #[serde(default)]
+    pub infer: Infer,
+    #[serde(default)]
+    pub bundles: Bundles,
+    #[serde(default)]
+    pub streaming: Streaming,
+    #[serde(default)]
+    pub jobs: Jobs,
+    #[serde(default)]
+    pub events: Events,
+    #[serde(default)]
+    pub logging: Logging,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            path: None,
             server: Server {
                 listen_on_all_interfaces: false,
                 port: 3000,
                 host: None,
                 database_url: None,
             },
+            auth: Auth::default(),
+            browse: Browse::default(),
+            cache: Cache::default(),
+            cover_art: CoverArt::default(),
+            inbox: Inbox::default(),
+            organize: Organize::default(),
+            providers: Providers::default(),
+            scan: Scan::default(),
+            storage: Storage::default(),
+            trash: Trash::default(),
+            backups: Backups::default(),
+            metadata_history: MetadataHistory::default(),
+            infer: Infer::default(),
+            bundles: Bundles::default(),
+            streaming: Streaming::default(),
+            jobs: Jobs::default(),
+            events: Events::default(),
+            logging: Logging::default(),
         }
     }
 }
 
 impl Settings {
     pub fn load(path: &Path) -> Result<Self, ConfigError> {
-        Ok(toml::from_str::<Self>(&read_to_string(path)?)?)
+        let settings = Self {
+            path: Some(path.to_path_buf()),
+            ..toml::from_str::<Self>(&read_to_string(path)?)?
+        };
+
+        parse_file_types(&settings.scan.file_types)?;
+
+        Ok(settings)
+    }
+}
+
+/// Edits the config file in place, keeping its comments and the formatting of untouched
+/// settings.
+///
+/// The edited file is written next to the original and renamed over it, so readers never see a
+/// partially written file.
+pub fn edit_config_file(path: &Path, edit: impl FnOnce(&mut toml_edit::DocumentMut)) -> Result<()> {
+    let mut document = read_to_string(path)?.parse::<toml_edit::DocumentMut>()?;
+    edit(&mut document);
+
+    let temp_path = path.with_extension("toml.tmp");
+    std::fs::write(&temp_path, document.to_string())?;
+    std::fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// Saves the settings to the config file, keeping the comments and formatting of the settings
+/// that didn't change since `previous`.
+pub fn save_settings(path: &Path, previous: &Settings, settings: &Settings) -> Result<()> {
+    let previous = toml::to_string(previous)?.parse::<toml_edit::DocumentMut>()?;
+    let updated = toml::to_string(settings)?.parse::<toml_edit::DocumentMut>()?;
+
+    edit_config_file(path, |document| {
+        merge_item(
+            document.as_item_mut(),
+            Some(previous.as_item()),
+            updated.as_item(),
+        );
+    })
+}
+
+/// Writes the values of `updated` into `item`, removing the keys that were in `previous` but are
+/// no longer set. Keys the settings don't know about are left alone.
+fn merge_item(
+    item: &mut toml_edit::Item,
+    previous: Option<&toml_edit::Item>,
+    updated: &toml_edit::Item,
+) {
+    if let (Some(table), Some(updated)) = (item.as_table_like_mut(), updated.as_table_like()) {
+        let previous = previous.and_then(toml_edit::Item::as_table_like);
+
+        for (key, _) in previous.iter().flat_map(|previous| previous.iter()) {
+            if !updated.contains_key(key) {
+                table.remove(key);
+            }
+        }
+
+        for (key, value) in updated.iter() {
+            let previous = previous.and_then(|previous| previous.get(key));
+
+            match table.get_mut(key) {
+                Some(item) => merge_item(item, previous, value),
+                None => {
+                    table.insert(key, value.clone());
+                }
+            }
+        }
+
+        return;
+    }
+
+    let undecorated = |value: &toml_edit::Value| value.clone().decorated("", "").to_string();
+
+    match (item.as_value_mut(), updated.as_value()) {
+        (Some(value), Some(updated)) if undecorated(value) == undecorated(updated) => {}
+        (Some(value), Some(updated)) => {
+            let decor = value.decor().clone();
+            *value = updated.clone();
+            *value.decor_mut() = decor;
+        }
+        _ => *item = updated.clone(),
     }
 }
 
@@ -85,7 +747,10 @@ pub fn load_config(args: &Args) -> Result<Settings> {
 
             tracing::info!("{info_msg}");
 
-            let settings = Settings::default();
+            let settings = Settings {
+                path: Some(path.clone()),
+                ..Settings::default()
+            };
 
             let file = File::create(&path).expect("Failed to create config file");
             let template = include_str!("../templates/config.toml");
@@ -126,3 +791,90 @@ fn override_config(settings: &mut Settings, args: &Args) {
         settings.server.port = port;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_types() {
+        assert_eq!(
+            parse_file_types(&["MP3", " flac ", "mp3"]).unwrap(),
+            BTreeSet::from([String::from("flac"), String::from("mp3")])
+        );
+
+        for invalid in ["", ".mp3", "tar.gz", "a/b", "a\\b"] {
+            assert!(matches!(
+                parse_file_types(&[invalid]),
+                Err(ConfigError::InvalidFileType(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_edit_config_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "# Scan configuration\n[scan]\nfile_types = [\"mp3\"]\n",
+        )
+        .unwrap();
+
+        edit_config_file(&path, |document| {
+            document["scan"]["file_types"] =
+                toml_edit::value(toml_edit::Array::from_iter(["mp3", "xm"]));
+        })
+        .unwrap();
+
+        let contents = read_to_string(&path).unwrap();
+        assert!(contents.starts_with("# Scan configuration"));
+        assert_eq!(
+            toml::from_str::<Settings>(&format!(
+                "[server]\nlisten_on_all_interfaces = false\nport = 3000\n{contents}"
+            ))
+            .unwrap()
+            .scan
+            .file_types,
+            ["mp3", "xm"]
+        );
+    }
+
+    #[test]
+    fn test_save_settings() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("config.toml");
+        let previous = Settings {
+            server: Server {
+                host: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                ..Settings::default().server
+            },
+            ..Settings::default()
+        };
+
+        std::fs::write(
+            &path,
+            "[server]\n# The port\nport = 3000 # inline\nlisten_on_all_interfaces = false\n\
+             host = \"127.0.0.1\"\nunknown = 1\n\n[jobs]\nworkers = 1\n",
+        )
+        .unwrap();
+
+        let mut settings = previous.clone();
+        settings.server.port = 4000;
+        settings.server.host = None;
+        settings.organize.templates =
+            BTreeMap::from([(String::from("flat"), String::from("{{title}}"))]);
+
+        save_settings(&path, &previous, &settings).unwrap();
+
+        let contents = read_to_string(&path).unwrap();
+        assert!(contents.contains("# The port\nport = 4000 # inline\n"));
+        assert!(contents.contains("unknown = 1"));
+        assert!(!contents.contains("host"));
+
+        let saved = Settings::load(&path).unwrap();
+        assert_eq!(saved.server.port, 4000);
+        assert_eq!(saved.jobs.workers, 1);
+        assert_eq!(saved.organize.templates, settings.organize.templates);
+    }
+}
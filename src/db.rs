@@ -1,12 +1,32 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+};
+
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::prelude::FromRow;
 use sqlx::types::time::OffsetDateTime;
 use ts_rs::TS;
 
-use crate::metadata::{SongFile, item::ItemKey};
+use crate::metadata::{Metadata, SongFile, item::ItemKey, rating::supports_rating};
 
+pub mod artists;
 pub mod directories;
+pub mod genres;
+pub mod history;
+pub mod integrity;
+pub mod library;
+pub mod maintenance;
+pub mod pins;
+pub mod playlists;
+pub mod scan_errors;
+pub mod schedules;
+pub mod search;
+pub mod smart_playlists;
 pub mod songs;
+pub mod stats;
+pub mod suggestions;
 
 type Result<T, E = DatabaseError> = std::result::Result<T, E>;
 type Connection = sqlx::SqliteConnection;
@@ -16,12 +36,30 @@ pub enum DatabaseError {
     #[error(transparent)]
     Song(#[from] songs::DatabaseSongError),
     #[error(transparent)]
+    Artist(#[from] artists::DatabaseArtistError),
+    #[error(transparent)]
+    Stats(#[from] stats::DatabaseStatsError),
+    #[error(transparent)]
     Directory(#[from] directories::DatabaseDirectoryError),
     #[error(transparent)]
+    Playlist(#[from] playlists::DatabasePlaylistError),
+    #[error(transparent)]
+    Pin(#[from] pins::DatabasePinError),
+    #[error(transparent)]
+    Schedule(#[from] schedules::DatabaseScheduleError),
+    #[error(transparent)]
+    ScanError(#[from] scan_errors::DatabaseScanError),
+    #[error(transparent)]
+    SmartPlaylist(#[from] smart_playlists::DatabaseSmartPlaylistError),
+    #[error(transparent)]
+    Suggestion(#[from] suggestions::DatabaseSuggestionError),
+    #[error(transparent)]
+    Library(#[from] library::DatabaseLibraryError),
+    #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
 }
 
-#[derive(Deserialize, Serialize, FromRow)]
+#[derive(Deserialize, Serialize, FromRow, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Directory {
     pub name: String,
@@ -31,17 +69,40 @@ pub struct Directory {
 
 #[derive(Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
 pub struct NewDirectory {
     /// The path of the directory.
     pub path: String,
     /// The display name of the directory, only used in the UI.
     pub display_name: Option<String>,
+    /// Add the directory even if it's a home directory, which usually contains far more than
+    /// music.
+    #[serde(default)]
+    pub allow_home_directory: bool,
+    /// Absorb the existing directories inside of this one, moving their songs to it and removing
+    /// them. Adding a directory that contains others fails otherwise.
+    #[serde(default)]
+    pub absorb_subdirectories: bool,
+}
+
+/// Changes to a directory, fields that are left out are kept as they are.
+#[derive(Deserialize, Debug, Default, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedDirectory {
+    /// The display name of the directory, `null` removes it.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[ts(optional, type = "string | null")]
+    pub display_name: Option<Option<String>>,
+    /// Where the directory was moved to, the paths of its songs are moved along with it.
+    #[serde(default)]
+    pub new_path: Option<String>,
+    /// Accept a home directory as the new path, see [`NewDirectory::allow_home_directory`].
+    #[serde(default)]
+    pub allow_home_directory: bool,
 }
 
 #[derive(Deserialize, Serialize, FromRow, Debug, Clone, TS, Default)]
 #[serde(rename_all = "camelCase")]
-#[ts(rename = "DatabaseSong", export)]
+#[ts(rename = "DatabaseSong")]
 pub struct Song {
     pub id: String,
     pub path: String,
@@ -50,10 +111,26 @@ pub struct Song {
     pub album: Option<String>,
     pub album_artist: Option<String>,
     pub genre: Option<String>,
-    pub track_number: Option<String>,
-    pub disc_number: Option<String>,
+    /// Parsed from tags like `3/12`, see [`position`](crate::metadata::position).
+    pub track_number: Option<u32>,
+    /// The amount of tracks on the disc.
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    /// The year as it's tagged, which can be a full date or something else entirely.
     pub year: Option<String>,
+    /// The year parsed from [`Song::year`] with [`parse_year`], what songs are sorted and filtered
+    /// by.
+    pub year_num: Option<i32>,
     pub mood: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    /// From `0` to `100`, see [`rating`](crate::metadata::rating) for the scale.
+    pub rating: Option<u8>,
+    /// Served through `/api/songs/{id}/lyrics` instead, they'd bloat every song listing.
+    #[serde(skip)]
+    #[ts(skip)]
+    pub lyrics: Option<String>,
     #[ts(type = "Date")]
     pub added_at: Option<OffsetDateTime>,
     #[ts(type = "Date")]
@@ -61,11 +138,103 @@ pub struct Song {
     #[ts(type = "Date")]
     pub file_created_at: Option<OffsetDateTime>,
     pub directory_id: String,
+    pub duration_ms: Option<u32>,
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+    /// Size of the largest picture embedded in the file, `0` if it doesn't have one.
+    pub cover_art_bytes: Option<u32>,
+    /// Whether the audio properties were read from the file, the `backfill-audio-properties` job
+    /// reads them for songs scanned without them.
+    pub properties_read: bool,
+    /// Cue sheet describing the track, tracks of a cue sheet share the same file.
+    pub cue_path: Option<String>,
+    /// Where the track starts in the file, always `0` unless the track is part of a cue sheet.
+    pub start_ms: u32,
+    /// Where the track ends in the file, the end of the file if not set.
+    pub end_ms: Option<u32>,
+    /// ReplayGain of the track in dB, set by the `analyze-loudness` job.
+    pub track_gain_db: Option<f64>,
+    /// Largest sample of the track, `1.0` is full scale.
+    pub track_peak: Option<f64>,
+    /// ReplayGain of the album the track was analyzed with in dB.
+    pub album_gain_db: Option<f64>,
+    pub album_peak: Option<f64>,
+    /// When a scan first found the file missing, cleared once it's found again.
+    #[ts(type = "Date")]
+    pub missing_since: Option<OffsetDateTime>,
+    /// Times the song was reported as played through `/api/songs/{id}/played`.
+    pub play_count: u32,
+    /// Times the song was reported as stopped early, see [`songs::SKIP_THRESHOLD_MS`].
+    pub skip_count: u32,
+    /// When the song was last reported as played, skips leave it unchanged.
+    #[ts(type = "Date")]
+    pub last_played_at: Option<OffsetDateTime>,
+    /// Leaves the song out of random picks, e.g. intros, skits and hidden tracks.
+    pub exclude_from_shuffle: bool,
+    /// Where playback starts, from the start of the track, to skip leading silence.
+    pub start_offset_ms: Option<u32>,
+    /// Where playback ends, from the start of the track, to skip trailing silence.
+    pub end_offset_ms: Option<u32>,
+}
+
+impl Song {
+    /// Returns where playback starts and ends in the file, taking the part of the file a track
+    /// of a cue sheet is stored in and the offsets into account. Playback lasts until the end of
+    /// the file if it has no end.
+    pub fn playback_range(&self) -> (u32, Option<u32>) {
+        let start_ms = self.start_ms + self.start_offset_ms.unwrap_or_default();
+        let end_ms = match (self.end_offset_ms, self.end_ms) {
+            (Some(offset), Some(end_ms)) => Some((self.start_ms + offset).min(end_ms)),
+            (Some(offset), None) => Some(self.start_ms + offset),
+            (None, end_ms) => end_ms,
+        };
+
+        (start_ms, end_ms)
+    }
+
+    /// Returns the part of the file the track is stored in, if it's part of a cue sheet.
+    pub fn cue_range(&self) -> Option<CueRange> {
+        self.cue_path.as_ref().map(|cue_path| CueRange {
+            cue_path: cue_path.clone(),
+            start_ms: self.start_ms,
+            end_ms: self.end_ms,
+        })
+    }
+
+    /// The disc of the album the track is on, tracks without one are on the first disc.
+    pub fn disc(&self) -> u32 {
+        self.disc_number.unwrap_or(1)
+    }
+
+    pub fn track(&self) -> Option<u32> {
+        self.track_number
+    }
+}
+
+/// Years outside of these are taken for garbage rather than a release date.
+pub const YEAR_RANGE: RangeInclusive<i32> = 1000..=2999;
+
+/// Reads the year out of a year tag, which can also be a full date like `2003-05-12` or
+/// `12/05/2003`. Takes the first group of exactly four digits within [`YEAR_RANGE`].
+pub fn parse_year(value: &str) -> Option<i32> {
+    value
+        .split(|character: char| !character.is_ascii_digit())
+        .filter(|digits| digits.len() == 4)
+        .find_map(|digits| digits.parse().ok().filter(|year| YEAR_RANGE.contains(year)))
+}
+
+/// The part of a file a track of a cue sheet is stored in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CueRange {
+    pub cue_path: String,
+    pub start_ms: u32,
+    pub end_ms: Option<u32>,
 }
 
 #[derive(Deserialize, Debug, Clone, TS, Default)]
 #[serde(rename_all = "camelCase")]
-#[ts(rename = "NewDatabaseSong", export)]
+#[ts(rename = "NewDatabaseSong")]
 pub struct NewSong {
     pub path: String,
     pub title: Option<String>,
@@ -73,10 +242,22 @@ pub struct NewSong {
     pub album: Option<String>,
     pub album_artist: Option<String>,
     pub genre: Option<String>,
-    pub track_number: Option<String>,
-    pub disc_number: Option<String>,
+    pub track_number: Option<u32>,
+    #[serde(default)]
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    #[serde(default)]
+    pub disc_total: Option<u32>,
     pub year: Option<String>,
     pub mood: Option<String>,
+    #[serde(default)]
+    pub composer: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub rating: Option<u8>,
+    #[serde(default)]
+    pub lyrics: Option<String>,
     #[ts(type = "Date")]
     pub file_created_at: Option<OffsetDateTime>,
 }
@@ -84,6 +265,8 @@ pub struct NewSong {
 impl From<SongFile> for NewSong {
     fn from(file: SongFile) -> Self {
         let metadata = file.metadata().as_ref();
+        let track = metadata.map(Metadata::track).unwrap_or_default();
+        let disc = metadata.map(Metadata::disc).unwrap_or_default();
         NewSong {
             path: file.path().to_string_lossy().to_string(),
             title: metadata.and_then(|m| m.get(&ItemKey::Title).cloned()),
@@ -91,63 +274,1133 @@ impl From<SongFile> for NewSong {
             album: metadata.and_then(|m| m.get(&ItemKey::Album).cloned()),
             album_artist: metadata.and_then(|m| m.get(&ItemKey::AlbumArtist).cloned()),
             genre: metadata.and_then(|m| m.get(&ItemKey::Genre).cloned()),
-            track_number: metadata.and_then(|m| m.get(&ItemKey::TrackNumber).cloned()),
-            disc_number: metadata.and_then(|m| m.get(&ItemKey::DiscNumber).cloned()),
+            track_number: track.number,
+            track_total: track.total,
+            disc_number: disc.number,
+            disc_total: disc.total,
             year: metadata.and_then(|m| m.get(&ItemKey::Year).cloned()),
             mood: metadata.and_then(|m| m.get(&ItemKey::Mood).cloned()),
+            composer: metadata.and_then(|m| m.get(&ItemKey::Composer).cloned()),
+            comment: metadata.and_then(|m| m.get(&ItemKey::Comment).cloned()),
+            rating: file.rating(),
+            lyrics: metadata.and_then(|m| m.get(&ItemKey::Lyrics).cloned()),
             file_created_at: Some(file.created()),
         }
     }
 }
 
+/// Returns the column of the `songs` table the metadata key is saved to, if it's saved at all.
+pub fn song_column(key: &ItemKey) -> Option<&'static str> {
+    match key {
+        ItemKey::Title => Some("title"),
+        ItemKey::Artist => Some("artist"),
+        ItemKey::Album => Some("album"),
+        ItemKey::AlbumArtist => Some("album_artist"),
+        ItemKey::Genre => Some("genre"),
+        ItemKey::TrackNumber => Some("track_number"),
+        ItemKey::TrackTotal => Some("track_total"),
+        ItemKey::DiscNumber => Some("disc_number"),
+        ItemKey::DiscTotal => Some("disc_total"),
+        ItemKey::Year => Some("year"),
+        ItemKey::Mood => Some("mood"),
+        ItemKey::Composer => Some("composer"),
+        ItemKey::Comment => Some("comment"),
+        ItemKey::Rating => Some("rating"),
+        ItemKey::Lyrics => Some("lyrics"),
+        _ => None,
+    }
+}
+
 #[derive(Deserialize, TS)]
-#[ts(export)]
 pub struct UpdatedSong {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
     pub album_artist: Option<String>,
     pub genre: Option<String>,
-    pub track_number: Option<String>,
-    pub disc_number: Option<String>,
+    pub track_number: Option<u32>,
+    #[serde(default)]
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    #[serde(default)]
+    pub disc_total: Option<u32>,
     pub year: Option<String>,
     pub mood: Option<String>,
+    #[serde(default)]
+    pub composer: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Left out to keep the rating, like songs whose tag format can't store one do.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[ts(optional, type = "number | null")]
+    pub rating: Option<Option<u8>>,
+    #[serde(default)]
+    pub lyrics: Option<String>,
 }
 
 impl From<SongFile> for UpdatedSong {
     fn from(file: SongFile) -> Self {
         let metadata = file.metadata().as_ref();
+        let track = metadata.map(Metadata::track).unwrap_or_default();
+        let disc = metadata.map(Metadata::disc).unwrap_or_default();
         UpdatedSong {
             title: metadata.and_then(|m| m.get(&ItemKey::Title).cloned()),
             artist: metadata.and_then(|m| m.get(&ItemKey::Artist).cloned()),
             album: metadata.and_then(|m| m.get(&ItemKey::Album).cloned()),
             album_artist: metadata.and_then(|m| m.get(&ItemKey::AlbumArtist).cloned()),
             genre: metadata.and_then(|m| m.get(&ItemKey::Genre).cloned()),
-            track_number: metadata.and_then(|m| m.get(&ItemKey::TrackNumber).cloned()),
-            disc_number: metadata.and_then(|m| m.get(&ItemKey::DiscNumber).cloned()),
+            track_number: track.number,
+            track_total: track.total,
+            disc_number: disc.number,
+            disc_total: disc.total,
             year: metadata.and_then(|m| m.get(&ItemKey::Year).cloned()),
             mood: metadata.and_then(|m| m.get(&ItemKey::Mood).cloned()),
+            composer: metadata.and_then(|m| m.get(&ItemKey::Composer).cloned()),
+            comment: metadata.and_then(|m| m.get(&ItemKey::Comment).cloned()),
+            rating: supports_rating(file.tag_type()).then(|| file.rating()),
+            lyrics: metadata.and_then(|m| m.get(&ItemKey::Lyrics).cloned()),
+        }
+    }
+}
+
+#[derive(Serialize, FromRow, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    #[ts(type = "Date")]
+    pub created_at: Option<OffsetDateTime>,
+    #[ts(type = "Date")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+/// A playlist along with its songs in order.
+#[derive(Serialize, Debug, TS)]
+pub struct PlaylistWithTracks {
+    #[serde(flatten)]
+    pub playlist: Playlist,
+    pub tracks: Vec<Song>,
+}
+
+#[derive(Deserialize, TS)]
+pub struct NewPlaylist {
+    pub name: String,
+}
+
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedPlaylist {
+    /// The new name of the playlist.
+    pub name: Option<String>,
+    /// Every song id of the playlist in their new order.
+    pub song_ids: Option<Vec<String>>,
+}
+
+/// Changes to how a song is played, fields that are left out are kept as they are.
+#[derive(Deserialize, Debug, Default, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedSongPreferences {
+    pub exclude_from_shuffle: Option<bool>,
+    /// Where playback starts, `null` plays the song from the start.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[ts(optional, type = "number | null")]
+    pub start_offset_ms: Option<Option<u32>>,
+    /// Where playback ends, `null` plays the song until the end.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[ts(optional, type = "number | null")]
+    pub end_offset_ms: Option<Option<u32>>,
+}
+
+/// Tells a field set to `null` apart from a field that was left out.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// A line of an imported playlist file that couldn't be matched to a song.
+#[derive(Serialize, Debug, PartialEq, Eq, TS)]
+pub struct UnresolvedEntry {
+    #[ts(type = "number")]
+    pub line: usize,
+    pub path: String,
+}
+
+#[derive(Serialize, Debug, TS)]
+pub struct PlaylistImport {
+    pub playlist: Playlist,
+    /// Songs that were added to the playlist.
+    pub added: u32,
+    pub unresolved: Vec<UnresolvedEntry>,
+}
+
+/// Summary of adding many songs at once.
+#[derive(Serialize, Debug, Default, PartialEq, Eq, TS)]
+pub struct BulkAddResult {
+    /// Songs that were added.
+    pub added: u32,
+    /// Songs that were already added, or given more than once.
+    pub duplicate: u32,
+    /// Song ids that don't exist in the database.
+    pub unknown: u32,
+}
+
+/// A playlist of every song matching a filter, evaluated whenever it's listed so it always
+/// reflects the current library.
+#[derive(Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartPlaylist {
+    pub id: String,
+    pub name: String,
+    pub filter: SongFilter,
+    #[ts(type = "Date")]
+    pub created_at: Option<OffsetDateTime>,
+    #[ts(type = "Date")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+/// A smart playlist to create, or to replace an existing one with.
+#[derive(Deserialize, Debug, TS)]
+pub struct NewSmartPlaylist {
+    pub name: String,
+    pub filter: SongFilter,
+}
+
+/// Which songs a smart playlist holds, either a single rule or a group of filters.
+///
+/// Only the fields and operators below are accepted, see [`songs::validate_filter`] for which
+/// operators work on which fields.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, TS)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SongFilter {
+    /// Matches songs matching every filter of the group.
+    All { filters: Vec<SongFilter> },
+    /// Matches songs matching at least one filter of the group.
+    Any { filters: Vec<SongFilter> },
+    Rule {
+        field: FilterField,
+        op: FilterOperator,
+        /// Compared against the field, left out for `isSet` and `isNotSet`.
+        #[serde(default)]
+        value: Option<FilterValue>,
+    },
+}
+
+/// Columns songs can be filtered by, anything else is rejected when deserializing.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Mood,
+    Composer,
+    Comment,
+    /// Compared as a number, so dates like `1990-05-01` count as 1990.
+    Year,
+    Rating,
+    DurationMs,
+    PlayCount,
+    SkipCount,
+    AddedAt,
+    LastPlayedAt,
+}
+
+/// What a field is compared with, which values an operator takes depends on the field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterFieldKind {
+    Text,
+    Number,
+    Date,
+}
+
+impl FilterField {
+    /// Returns the column expression the field is compared with.
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Artist => "artist",
+            Self::Album => "album",
+            Self::AlbumArtist => "album_artist",
+            Self::Genre => "genre",
+            Self::Mood => "mood",
+            Self::Composer => "composer",
+            Self::Comment => "comment",
+            Self::Year => "year_num",
+            Self::Rating => "rating",
+            Self::DurationMs => "duration_ms",
+            Self::PlayCount => "play_count",
+            Self::SkipCount => "skip_count",
+            Self::AddedAt => "added_at",
+            Self::LastPlayedAt => "last_played_at",
+        }
+    }
+
+    pub fn kind(&self) -> FilterFieldKind {
+        match self {
+            Self::Title
+            | Self::Artist
+            | Self::Album
+            | Self::AlbumArtist
+            | Self::Genre
+            | Self::Mood
+            | Self::Composer
+            | Self::Comment => FilterFieldKind::Text,
+            Self::Year | Self::Rating | Self::DurationMs | Self::PlayCount | Self::SkipCount => {
+                FilterFieldKind::Number
+            }
+            Self::AddedAt | Self::LastPlayedAt => FilterFieldKind::Date,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterOperator {
+    /// Equal to the value, text is compared ignoring case.
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    StartsWith,
+    GreaterThan,
+    GreaterOrEqual,
+    LessThan,
+    LessOrEqual,
+    /// Set at all, takes no value.
+    IsSet,
+    IsNotSet,
+    /// Within the last `value` days.
+    InLastDays,
+    /// Not within the last `value` days, including never.
+    NotInLastDays,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, TS)]
+#[serde(untagged)]
+pub enum FilterValue {
+    Number(u32),
+    Text(String),
+}
+
+/// A single page of rows along with the total amount of rows available.
+#[derive(Serialize, Debug, TS)]
+pub struct Page<T: TS> {
+    pub items: Vec<T>,
+    #[ts(type = "number")]
+    pub total: i64,
+}
+
+/// Columns songs can be sorted by, anything else is rejected when deserializing.
+#[derive(Deserialize, Debug, Clone, Copy, Default, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum SongSortColumn {
+    #[default]
+    Title,
+    Artist,
+    Album,
+    Year,
+    AddedAt,
+    FileCreatedAt,
+    Duration,
+    PlayCount,
+    SkipCount,
+    LastPlayedAt,
+    Rating,
+}
+
+impl SongSortColumn {
+    /// Returns the column expression used in the `ORDER BY` clause.
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Title => "title COLLATE NOCASE",
+            Self::Artist => "artist COLLATE NOCASE",
+            Self::Album => "album COLLATE NOCASE",
+            Self::Year => "year_num",
+            Self::AddedAt => "added_at",
+            Self::FileCreatedAt => "file_created_at",
+            Self::Duration => "duration_ms",
+            Self::PlayCount => "play_count",
+            Self::SkipCount => "skip_count",
+            Self::LastPlayedAt => "last_played_at",
+            Self::Rating => "rating",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
         }
     }
 }
 
+#[derive(Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SongQuery {
+    /// Maximum amount of songs to return, returns every song if not set.
+    pub limit: Option<u32>,
+    /// Amount of songs to skip.
+    pub offset: Option<u32>,
+    /// Column to sort by.
+    pub sort_by: SongSortColumn,
+    /// Direction to sort in.
+    pub order: SortOrder,
+    /// Include songs whose file went missing, they're left out otherwise.
+    #[serde(alias = "include_missing")]
+    pub include_missing: bool,
+    /// Only return songs played at least this many times.
+    pub min_play_count: Option<u32>,
+    /// Only return songs rated at least this high, songs without a rating are left out.
+    pub min_rating: Option<u8>,
+    /// Only return songs last played from this point on, formatted as RFC 3339.
+    #[ts(type = "Date | null")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub played_since: Option<OffsetDateTime>,
+    /// Only return songs that are, or aren't, left out of random picks.
+    pub shuffleable: Option<bool>,
+    /// Only return songs from this year on, songs without a year are left out.
+    pub year_min: Option<i32>,
+    /// Only return songs up to and including this year, songs without a year are left out.
+    pub year_max: Option<i32>,
+}
+
+/// Album artist of compilations that don't have a single album artist.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
 /// A collection of songs. Does not correlate to a table in the database.
 #[derive(serde::Serialize, TS)]
-#[ts(rename = "Album", export)]
+#[serde(rename_all = "camelCase")]
+#[ts(rename = "Album")]
 pub struct Album {
+    /// Stays the same as long as the title and album artist do, see [`album_id`].
+    pub id: String,
     pub title: String,
     pub artist: Option<String>,
+    /// The year most tracks are from, the earliest of them on a tie.
+    pub year: Option<i32>,
+    /// Whether the tracks are by different artists without a single album artist credited.
+    pub compilation: bool,
+    /// Directories the tracks are stored in, the album is split if there's more than one.
+    pub directory_ids: BTreeSet<String>,
+    /// Total duration of the tracks with a known duration, left out while some tracks haven't had
+    /// their audio properties read.
+    pub duration_ms: Option<u64>,
+    /// Whether the duration is left out until the `backfill-audio-properties` job read the audio
+    /// properties of every track.
+    pub duration_pending: bool,
+    /// ReplayGain of the album in dB, if its tracks have been analyzed.
+    pub gain_db: Option<f64>,
+    pub peak: Option<f64>,
+    pub disc_count: usize,
+    /// The tracks of every disc, in disc order.
+    pub discs: Vec<AlbumDisc>,
+    /// Every track of the album, sorted by disc and then track number.
     pub tracks: Vec<Song>,
 }
 
+/// A disc of an album.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumDisc {
+    pub number: u32,
+    /// Ids of the tracks on the disc, in the same order as the album's tracks.
+    pub track_ids: Vec<String>,
+}
+
+/// Id of the album with the title and album artist, a hash of both so it can be used in a URL
+/// whatever the title contains and albums of different artists sharing a title don't collide.
+pub fn album_id(title: &str, album_artist: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(title.as_bytes());
+    if let Some(album_artist) = album_artist {
+        hasher.update([0]);
+        hasher.update(album_artist.as_bytes());
+    }
+
+    hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The [`album_id`] stored with a song, so albums are looked up by their id with an index instead
+/// of hashing every album.
+pub fn song_album_id(album: Option<&str>, album_artist: Option<&str>) -> Option<String> {
+    album.map(|title| album_id(title, album_artist))
+}
+
+impl Album {
+    /// Whether the tracks of the album are spread across multiple directories.
+    pub fn is_split(&self) -> bool {
+        self.directory_ids.len() > 1
+    }
+}
+
 impl From<Vec<Song>> for Album {
-    fn from(tracks: Vec<Song>) -> Self {
+    fn from(mut tracks: Vec<Song>) -> Self {
         let title = tracks[0].album.clone().expect("Album not found");
-        let artist = tracks[0].album_artist.clone();
+        let artist = tracks.iter().find_map(|track| track.album_artist.clone());
+        let directory_ids = tracks
+            .iter()
+            .map(|track| track.directory_id.clone())
+            .collect();
+
+        let artists = tracks
+            .iter()
+            .filter_map(|track| track.artist.as_deref())
+            .collect::<BTreeSet<_>>();
+        let compilation = artists.len() > 1
+            && artist
+                .as_deref()
+                .is_none_or(|artist| artist.eq_ignore_ascii_case(VARIOUS_ARTISTS));
+
+        // Tracks without a track number go last, the path and start keep the order of the rest
+        // stable.
+        tracks.sort_by_key(|track| {
+            let number = track.track();
+            (
+                track.disc(),
+                number.is_none(),
+                number,
+                track.path.clone(),
+                track.start_ms,
+            )
+        });
+
+        let mut discs: Vec<AlbumDisc> = Vec::new();
+        for track in &tracks {
+            match discs.last_mut() {
+                Some(disc) if disc.number == track.disc() => disc.track_ids.push(track.id.clone()),
+                _ => discs.push(AlbumDisc {
+                    number: track.disc(),
+                    track_ids: vec![track.id.clone()],
+                }),
+            }
+        }
+
+        let (duration_ms, duration_pending) = total_duration(&tracks);
+        let year = most_common_year(&tracks);
+
+        // Tracks analyzed together share the same values, the album gain is only missing from
+        // tracks added since.
+        let analyzed = tracks.iter().find(|track| track.album_gain_db.is_some());
+        let gain_db = analyzed.and_then(|track| track.album_gain_db);
+        let peak = analyzed.and_then(|track| track.album_peak);
+
         Album {
+            id: album_id(&title, artist.as_deref()),
             title,
             artist,
+            year,
+            compilation,
+            directory_ids,
+            duration_ms,
+            duration_pending,
+            gain_db,
+            peak,
+            disc_count: discs.len(),
+            discs,
             tracks,
         }
     }
 }
+
+/// Returns the year most of the songs are from, the earliest one if several are as common.
+fn most_common_year(songs: &[Song]) -> Option<i32> {
+    let mut counts = BTreeMap::new();
+    for year in songs.iter().filter_map(|song| song.year_num) {
+        *counts.entry(year).or_insert(0) += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+        .map(|(year, _)| year)
+}
+
+/// Returns the total duration of the songs with a known duration, and whether it's left out
+/// because some songs haven't had their audio properties read yet.
+///
+/// A total missing the songs scanned without their properties would look complete while being
+/// off by an unknown amount.
+fn total_duration(songs: &[Song]) -> (Option<u64>, bool) {
+    if songs.iter().any(|song| !song.properties_read) {
+        return (None, true);
+    }
+
+    let durations = songs
+        .iter()
+        .filter_map(|song| song.duration_ms.map(u64::from))
+        .collect::<Vec<_>>();
+
+    let duration_ms = (!durations.is_empty()).then(|| durations.iter().sum());
+
+    (duration_ms, false)
+}
+
+/// An artist credited on a track, either as the artist or the album artist. Does not correlate to
+/// a table in the database.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Artist {
+    pub name: String,
+    pub album_count: usize,
+    pub track_count: usize,
+}
+
+#[derive(serde::Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistDetail {
+    pub name: String,
+    /// Albums the artist is credited on, only containing the tracks the artist is credited on.
+    pub albums: Vec<Album>,
+    /// Tracks of the artist that aren't part of an album.
+    pub tracks: Vec<Song>,
+}
+
+/// Songs added to the library on the same day of an earlier year, and the songs played the most
+/// in the week around it.
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct OnThisDay {
+    pub year: i32,
+    /// Titles of the albums the songs belong to.
+    pub albums: BTreeSet<String>,
+    pub songs: Vec<Song>,
+    /// Songs played the most from three days before the day to three days after it, most played
+    /// first.
+    pub most_played: Vec<SongPlays>,
+}
+
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct YearInReview {
+    pub year: i32,
+    pub songs_added: usize,
+    /// Albums with at least one song added during the year.
+    pub albums_added: usize,
+    /// Songs added in every month, starting with January.
+    pub songs_added_per_month: Vec<usize>,
+    /// Total duration of the songs added, left out while some haven't had their audio properties
+    /// read.
+    pub duration_added_ms: Option<u64>,
+    /// Whether the duration is left out until the `backfill-audio-properties` job read the audio
+    /// properties of every song added.
+    pub duration_pending: bool,
+    /// Artists credited on the most songs added during the year.
+    pub top_artists: Vec<Artist>,
+    /// Plays reported during the year, with the artists, albums and songs played the most.
+    pub plays: PlayStats,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlayStatsQuery {
+    /// Only count plays from this point on, formatted as RFC 3339.
+    #[ts(type = "Date | null")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    /// Only count plays before this point, formatted as RFC 3339.
+    #[ts(type = "Date | null")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+    /// Maximum amount of artists and albums to return, 10 if not set.
+    pub limit: Option<u32>,
+}
+
+/// Plays reported over a period of time.
+#[derive(serde::Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayStats {
+    pub total_plays: u64,
+    pub total_skips: u64,
+    /// Artists with the most plays, most played first.
+    pub top_artists: Vec<ArtistPlays>,
+    /// Albums with the most plays, most played first.
+    pub top_albums: Vec<AlbumPlays>,
+    /// Songs with the most plays, most played first.
+    pub top_songs: Vec<SongPlays>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistPlays {
+    pub name: String,
+    pub play_count: u32,
+}
+
+#[derive(serde::Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SongPlays {
+    #[serde(flatten)]
+    pub song: Song,
+    pub play_count: u32,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumPlays {
+    pub title: String,
+    /// Album artist of the album, or the artist of its tracks if it doesn't credit one.
+    pub artist: Option<String>,
+    pub play_count: u32,
+}
+
+#[derive(Deserialize, Serialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Hash, TS)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum PinKind {
+    Album,
+    Song,
+}
+
+/// An album or song pinned to the top of the home page.
+#[derive(Serialize, FromRow, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Pin {
+    pub kind: PinKind,
+    /// Title of the album, or id of the song.
+    pub entity_id: String,
+    pub position: u32,
+    #[ts(type = "Date")]
+    pub pinned_at: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PinTarget {
+    pub kind: PinKind,
+    /// Title of the album, or id of the song.
+    pub entity_id: String,
+}
+
+/// A pin along with the album or song it points to.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum PinnedItem {
+    Album(Album),
+    Song(Song),
+}
+
+/// When a scheduled job is queued, times are in UTC.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "kind"
+)]
+pub enum ScheduleTrigger {
+    /// Every `seconds` after the previous run.
+    Interval { seconds: u32 },
+    /// Every day at `time`, formatted as `HH:MM`.
+    Daily { time: String },
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSchedule {
+    pub job_id: String,
+    pub trigger: ScheduleTrigger,
+    pub enabled: bool,
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_run_at: Option<OffsetDateTime>,
+    /// When the job is queued next, not set while the schedule is disabled.
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub next_run_at: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct NewJobSchedule {
+    pub trigger: ScheduleTrigger,
+    pub enabled: bool,
+}
+
+/// Set while the server is in maintenance, see [`crate::state::Maintenance`].
+#[derive(Serialize, FromRow, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceMode {
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    /// Why the server is in maintenance, e.g. the name of the backup that's running.
+    pub reason: Option<String>,
+}
+
+/// Events significant enough to be kept in the history after they're broadcast.
+#[derive(Deserialize, Serialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Hash, TS)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(rename_all = "camelCase")]
+pub enum HistoryEventKind {
+    JobStarted,
+    JobCompleted,
+    JobFailed,
+    JobCancelled,
+    OperationFailed,
+    DirectoryAdded,
+    DirectoryRemoved,
+    DirectoryUpdated,
+    SettingsChanged,
+    MaintenanceStarted,
+    MaintenanceEnded,
+}
+
+#[derive(Serialize, FromRow, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEvent {
+    #[ts(type = "number")]
+    pub id: i64,
+    pub kind: HistoryEventKind,
+    /// What the event is about, e.g. the id of the job or the path of the directory.
+    pub source: Option<String>,
+    pub message: Option<String>,
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewHistoryEvent {
+    pub kind: HistoryEventKind,
+    pub source: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryQuery {
+    /// Only return events from this point on, formatted as RFC 3339.
+    #[ts(type = "Date | null")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub since: Option<OffsetDateTime>,
+    /// Only return events of these kinds.
+    ///
+    /// Accepts a comma separated list in query strings, e.g. `?kinds=jobFailed,jobCancelled`.
+    #[serde(deserialize_with = "deserialize_history_kinds")]
+    pub kinds: Option<Vec<HistoryEventKind>>,
+    /// Maximum amount of events to return, returns every event if not set.
+    pub limit: Option<u32>,
+    /// Amount of events to skip.
+    pub offset: Option<u32>,
+}
+
+fn deserialize_history_kinds<'de, D>(
+    deserializer: D,
+) -> Result<Option<Vec<HistoryEventKind>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{Error, IntoDeserializer};
+
+    let Some(text) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    text.split(',')
+        .map(|kind| {
+            HistoryEventKind::deserialize(kind.trim().into_deserializer())
+                .map_err(|err: serde::de::value::Error| D::Error::custom(err))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Kind of a row of an exported library, see [`LibraryRecord`].
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LibraryRecordKind {
+    #[default]
+    Directory,
+    Song,
+    Playlist,
+    PlaylistSong,
+}
+
+/// A row of an exported library, JSON and CSV exports share the same columns.
+///
+/// Which columns are set depends on the `record` kind:
+/// - `directory`: `id` (the name of the directory), `path` and `displayName`.
+/// - `song`: `id`, `path`, `directoryId`, the tags, `addedAt`, `updatedAt`, `fileCreatedAt`,
+///   the audio properties, the cue sheet range and the ReplayGain values.
+/// - `playlist`: `id`, `name`, `createdAt` and `updatedAt`.
+/// - `playlistSong`: `playlistId`, `songId` and `position`.
+///
+/// Directories are exported first, followed by the songs, the playlists and their songs, so a
+/// row only refers to rows before it. Timestamps are formatted as RFC 3339, track and disc
+/// numbers along with their total like `3/12`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryRecord {
+    pub record: LibraryRecordKind,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub path: Option<String>,
+    pub display_name: Option<String>,
+    pub directory_id: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub track_number: Option<String>,
+    pub disc_number: Option<String>,
+    pub year: Option<String>,
+    pub mood: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    pub rating: Option<u8>,
+    pub lyrics: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub added_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub file_created_at: Option<OffsetDateTime>,
+    pub duration_ms: Option<u32>,
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+    pub cue_path: Option<String>,
+    pub start_ms: Option<u32>,
+    pub end_ms: Option<u32>,
+    pub track_gain_db: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub album_gain_db: Option<f64>,
+    pub album_peak: Option<f64>,
+    pub playlist_id: Option<String>,
+    pub song_id: Option<String>,
+    pub position: Option<i64>,
+}
+
+/// What to do with imported songs and playlists that are already in the library.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportConflict {
+    /// Keep the existing row, imported playlists still refer to the existing song.
+    #[default]
+    Skip,
+    /// Replace the existing row with the imported one, keeping its id.
+    Overwrite,
+}
+
+/// What importing a library did, songs are matched by their path.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryImportSummary {
+    pub directories_added: u64,
+    /// Imported directories matched to a directory with the same path.
+    pub directories_matched: u64,
+    pub songs_added: u64,
+    pub songs_updated: u64,
+    pub songs_skipped: u64,
+    pub playlists_added: u64,
+    pub playlists_updated: u64,
+    pub playlists_skipped: u64,
+}
+
+/// Why the scanner couldn't read a file.
+#[derive(Deserialize, Serialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Hash, TS)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(rename_all = "camelCase")]
+pub enum ScanErrorCategory {
+    /// The file is corrupt or couldn't be opened.
+    Unreadable,
+    /// The file doesn't contain any tags, it's still added to the library.
+    NoTag,
+    /// The file isn't in a format the metadata reader supports.
+    Unsupported,
+}
+
+impl ScanErrorCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unreadable => "unreadable",
+            Self::NoTag => "noTag",
+            Self::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// A file the scanner failed to read, it's skipped by later scans until it's modified or the
+/// entry is removed.
+#[derive(Serialize, FromRow, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanError {
+    #[ts(type = "number")]
+    pub id: i64,
+    pub path: String,
+    pub category: ScanErrorCategory,
+    /// The error as the metadata reader reported it.
+    pub error: String,
+    /// When the file was modified as of the last failed read.
+    #[ts(type = "Date | null")]
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub modified_at: Option<OffsetDateTime>,
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub first_seen: OffsetDateTime,
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen: OffsetDateTime,
+    /// Scans that failed to read the file.
+    pub attempts: u32,
+}
+
+/// A song found by the `check-integrity` job, see [`IntegrityReport`].
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegritySong {
+    pub id: String,
+    pub path: String,
+    pub directory_id: String,
+}
+
+/// Songs that share the same path and start, only possible in libraries written by older
+/// versions. The oldest song comes first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatePath {
+    pub path: String,
+    #[ts(type = "number")]
+    pub start_ms: i64,
+    pub song_ids: Vec<String>,
+}
+
+/// Where the library drifted from the files and directories it's made of.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub generated_at: OffsetDateTime,
+    /// Songs whose directory doesn't exist anymore.
+    pub orphaned_songs: Vec<IntegritySong>,
+    pub duplicate_paths: Vec<DuplicatePath>,
+    /// Songs whose path isn't inside the path of their directory.
+    pub songs_outside_directory: Vec<IntegritySong>,
+    /// Songs with an empty id, which can't be linked to.
+    pub blank_ids: Vec<IntegritySong>,
+    /// Songs whose file doesn't exist, including the ones a scan already marked as missing.
+    pub missing_files: Vec<IntegritySong>,
+}
+
+/// What a repair of the library changed, along with what's left to look at.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityRepair {
+    /// Orphaned songs moved to the directory their path is in.
+    pub reassigned_songs: Vec<IntegritySong>,
+    /// Ids of the orphaned songs outside of every directory and of the newer duplicates.
+    pub deleted_songs: Vec<String>,
+    /// The report after the repair, `None` if the library was never checked.
+    pub report: Option<IntegrityReport>,
+}
+
+/// A genre of the library, grouping the spellings that only differ in case.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Genre {
+    /// The most common spelling.
+    pub name: String,
+    /// The amount of songs with the genre in any spelling.
+    pub song_count: usize,
+    /// Every spelling of the genre with the amount of songs using it.
+    pub spellings: BTreeMap<String, usize>,
+}
+
+/// What matches a search, each sorted by relevance.
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    pub songs: Vec<SongMatch>,
+    pub albums: Vec<AlbumMatch>,
+    pub artists: Vec<ArtistMatch>,
+}
+
+/// A song matching a search, the higher the score the more relevant it is.
+#[derive(Serialize, FromRow, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct SongMatch {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub song: Song,
+    pub score: f64,
+}
+
+/// An album whose title or album artist matches a search, scored by its best matching track.
+#[derive(Serialize, FromRow, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumMatch {
+    pub title: String,
+    /// The album artist credited on the tracks, if any.
+    pub artist: Option<String>,
+    /// The amount of tracks matching the search.
+    pub track_count: u32,
+    pub score: f64,
+}
+
+/// An artist whose name matches a search, scored by their best matching track.
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistMatch {
+    pub name: String,
+    /// The amount of tracks the artist is credited on that match the search.
+    pub track_count: usize,
+    pub score: f64,
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use sqlx::{query, sqlite::SqlitePoolOptions};
+
+    /// Creates an in-memory database containing a song for every title.
+    pub async fn pool_with_songs(titles: &[&str]) -> sqlx::SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        crate::migration::run_migrations(&pool, true, None)
+            .await
+            .unwrap();
+
+        query("INSERT INTO directories (name, path) VALUES ('directory', '/music/')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for title in titles {
+            query(
+                "INSERT INTO songs (id, path, title, directory_id) VALUES (?, ?, ?, 'directory')",
+            )
+            .bind(uuid::Uuid::new_v4().to_string())
+            .bind(format!("/music/{title}.mp3"))
+            .bind(title)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        pool
+    }
+}
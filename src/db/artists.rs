@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use sqlx::query_as;
+
+use super::{Album, Artist, ArtistDetail, Connection, Result, Song};
+
+/// Separator the metadata reader joins multiple values of a tag with.
+const ARTIST_SEPARATOR: char = ';';
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseArtistError {
+    #[error("Artist not found")]
+    NotFound,
+}
+
+/// Splits a tag value containing multiple artists into the individual artists.
+pub fn split_artists(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(ARTIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|artist| !artist.is_empty())
+}
+
+/// Returns the artists credited on the song, as the artist or the album artist.
+pub(super) fn song_artists(song: &Song) -> BTreeSet<&str> {
+    [&song.artist, &song.album_artist]
+        .into_iter()
+        .flatten()
+        .flat_map(|value| split_artists(value))
+        .collect()
+}
+
+async fn get_credited_songs(connection: &mut Connection) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE (artist IS NOT NULL OR album_artist IS NOT NULL) AND missing_since IS NULL ORDER BY path",
+    )
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+pub async fn get_artists(connection: &mut Connection) -> Result<Vec<Artist>> {
+    let songs = get_credited_songs(connection).await?;
+
+    Ok(aggregate_artists(&songs))
+}
+
+pub async fn get_artist(connection: &mut Connection, name: &str) -> Result<ArtistDetail> {
+    let songs = get_credited_songs(connection).await?;
+
+    artist_detail(songs, name).ok_or_else(|| DatabaseArtistError::NotFound.into())
+}
+
+/// Groups the songs by artist, matching names case-insensitively.
+///
+/// The spelling of the first song an artist was found on is used as their name.
+pub(super) fn aggregate_artists(songs: &[Song]) -> Vec<Artist> {
+    let mut artists: BTreeMap<String, (String, BTreeSet<&str>, usize)> = BTreeMap::new();
+
+    for song in songs {
+        for artist in song_artists(song) {
+            let (_, albums, track_count) = artists
+                .entry(artist.to_lowercase())
+                .or_insert_with(|| (artist.to_string(), BTreeSet::new(), 0));
+
+            if let Some(album) = &song.album {
+                albums.insert(album);
+            }
+
+            *track_count += 1;
+        }
+    }
+
+    artists
+        .into_values()
+        .map(|(name, albums, track_count)| Artist {
+            name,
+            album_count: albums.len(),
+            track_count,
+        })
+        .collect()
+}
+
+fn artist_detail(songs: Vec<Song>, name: &str) -> Option<ArtistDetail> {
+    let key = name.to_lowercase();
+    let mut display_name = None;
+    let mut albums: HashMap<String, Vec<Song>> = HashMap::new();
+    let mut tracks = Vec::new();
+
+    for song in songs {
+        let Some(artist) = song_artists(&song)
+            .into_iter()
+            .find(|artist| artist.to_lowercase() == key)
+            .map(str::to_string)
+        else {
+            continue;
+        };
+
+        display_name.get_or_insert(artist);
+
+        match song.album.clone() {
+            Some(album) => albums.entry(album).or_default().push(song),
+            None => tracks.push(song),
+        }
+    }
+
+    let mut albums = albums.into_values().map(Album::from).collect::<Vec<_>>();
+    albums.sort_by(|a, b| a.title.cmp(&b.title));
+
+    Some(ArtistDetail {
+        name: display_name?,
+        albums,
+        tracks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+    use test_log::test;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    async fn set_credits(
+        connection: &mut Connection,
+        title: &str,
+        artist: Option<&str>,
+        album_artist: Option<&str>,
+        album: Option<&str>,
+    ) {
+        query("UPDATE songs SET artist = ?, album_artist = ?, album = ? WHERE title = ?")
+            .bind(artist)
+            .bind(album_artist)
+            .bind(album)
+            .bind(title)
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_split_artists() {
+        assert_eq!(
+            split_artists("Artist A; Artist B;;  ").collect::<Vec<_>>(),
+            ["Artist A", "Artist B"]
+        );
+        assert_eq!(split_artists("Artist A").collect::<Vec<_>>(), ["Artist A"]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_artists() {
+        let pool = pool_with_songs(&["a", "b", "c", "d"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        set_credits(
+            &mut connection,
+            "a",
+            Some("Artist A; Artist B"),
+            Some("Artist A"),
+            Some("First"),
+        )
+        .await;
+        set_credits(&mut connection, "b", Some("artist a"), None, Some("Second")).await;
+        set_credits(&mut connection, "c", Some("Artist B"), None, None).await;
+
+        let artists = get_artists(&mut connection).await.unwrap();
+
+        assert_eq!(
+            artists,
+            [
+                Artist {
+                    name: String::from("Artist A"),
+                    album_count: 2,
+                    track_count: 2,
+                },
+                Artist {
+                    name: String::from("Artist B"),
+                    album_count: 1,
+                    track_count: 2,
+                },
+            ]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_artist() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        set_credits(
+            &mut connection,
+            "a",
+            Some("Artist A; Artist B"),
+            None,
+            Some("First"),
+        )
+        .await;
+        set_credits(&mut connection, "b", Some("Artist B"), None, None).await;
+        set_credits(&mut connection, "c", Some("Artist C"), None, Some("Second")).await;
+
+        let artist = get_artist(&mut connection, "artist b").await.unwrap();
+
+        assert_eq!(artist.name, "Artist B");
+        assert_eq!(artist.albums.len(), 1);
+        assert_eq!(artist.albums[0].title, "First");
+        assert_eq!(artist.tracks.len(), 1);
+        assert_eq!(artist.tracks[0].title.as_deref(), Some("b"));
+
+        assert!(matches!(
+            get_artist(&mut connection, "Artist D").await,
+            Err(crate::db::DatabaseError::Artist(
+                DatabaseArtistError::NotFound
+            ))
+        ));
+    }
+}
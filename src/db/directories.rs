@@ -1,7 +1,13 @@
+use std::path::{Path, PathBuf};
+
 use axum::response::IntoResponse;
 use hyper::StatusCode;
 
-use super::{Directory, NewDirectory, Result, Connection};
+use crate::paths::app_owned_dirs;
+
+use sqlx::Connection as _;
+
+use super::{Directory, NewDirectory, Result, Connection, UpdatedDirectory};
 
 #[derive(thiserror::Error, Debug)]
 pub enum DatabaseDirectoryError {
@@ -19,17 +25,29 @@ pub enum DatabaseDirectoryError {
     PathNotAbsolute(String),
     #[error("Path \"{0}\" is a subdirectory of an existing directory")]
     PathIsSubdirectory(String),
+    #[error("Path \"{0}\" contains existing directories, they have to be explicitly absorbed")]
+    PathContainsDirectories(String),
     #[error("Directory already exists")]
     PathAlreadyAdded,
     #[error("Path is not a valid UTF-8 string")]
     PathNotUtf8,
+    #[error("Path \"{0}\" is inside a directory the app stores its own files in")]
+    PathInsideAppDirectory(String),
+    #[error("Songs weren't found at the new path, e.g. \"{0}\"")]
+    SongsNotMoved(String),
+    #[error("Path \"{0}\" is the root of the file system")]
+    PathIsRoot(String),
+    #[error("Path \"{0}\" is a home directory, it has to be explicitly allowed")]
+    PathIsHomeDirectory(String),
 }
 
 impl IntoResponse for DatabaseDirectoryError {
     fn into_response(self) -> axum::response::Response {
         match self {
             Self::NotFound => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
-            Self::PathAlreadyAdded | Self::PathIsSubdirectory(_) => {
+            Self::PathAlreadyAdded
+            | Self::PathIsSubdirectory(_)
+            | Self::PathContainsDirectories(_) => {
                 (StatusCode::CONFLICT, self.to_string()).into_response()
             }
             _ => (StatusCode::BAD_REQUEST, self.to_string()).into_response(),
@@ -37,10 +55,14 @@ impl IntoResponse for DatabaseDirectoryError {
     }
 }
 
+/// Adds the directory, returning it along with the existing directories it absorbed.
+///
+/// Directories inside the new one are only absorbed with [`NewDirectory::absorb_subdirectories`],
+/// their songs are moved to the new directory and they're removed.
 pub async fn add_directory(
     connection: &mut Connection,
     directory: NewDirectory,
-) -> Result<Directory> {
+) -> Result<(Directory, Vec<Directory>)> {
     if directory.path.trim().is_empty() {
         return Err(DatabaseDirectoryError::PathEmpty.into());
     }
@@ -49,6 +71,11 @@ pub async fn add_directory(
         return Err(DatabaseDirectoryError::PathNotUtf8.into());
     }
 
+    let directory = NewDirectory {
+        path: normalize_path(&directory.path),
+        ..directory
+    };
+
     if sqlx::query_as!(
         Directory,
         "SELECT * FROM directories WHERE path = ?",
@@ -75,32 +102,274 @@ pub async fn add_directory(
         return Err(DatabaseDirectoryError::PathNotDirectory(directory.path).into());
     }
 
-    let directories = sqlx::query_scalar!("SELECT path FROM directories")
-        .fetch_all(&mut *connection)
+    let home = ::directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    check_library_path(
+        path,
+        &app_owned_dirs(),
+        home.as_deref(),
+        directory.allow_home_directory,
+    )?;
+
+    let existing = get_directories(&mut *connection).await?;
+
+    if existing
+        .iter()
+        .any(|entry| is_inside(path, Path::new(&entry.path)))
+    {
+        return Err(DatabaseDirectoryError::PathIsSubdirectory(directory.path).into());
+    }
+
+    let absorbed = existing
+        .into_iter()
+        .filter(|entry| is_inside(Path::new(&entry.path), path))
+        .collect::<Vec<_>>();
+
+    if !absorbed.is_empty() && !directory.absorb_subdirectories {
+        return Err(DatabaseDirectoryError::PathContainsDirectories(directory.path).into());
+    }
+
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let mut transaction = connection.begin().await?;
+
+    sqlx::query("INSERT INTO directories (name, path, display_name) VALUES (?, ?, ?)")
+        .bind(&uuid)
+        .bind(&directory.path)
+        .bind(&directory.display_name)
+        .execute(&mut *transaction)
         .await?;
 
-    for entry in directories {
-        if path.starts_with(entry) {
-            return Err(DatabaseDirectoryError::PathIsSubdirectory(directory.path).into());
+    for child in &absorbed {
+        sqlx::query("UPDATE songs SET directory_id = ? WHERE directory_id = ?")
+            .bind(&uuid)
+            .bind(&child.name)
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query("DELETE FROM directories WHERE name = ?")
+            .bind(&child.name)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok((
+        Directory {
+            name: uuid,
+            path: directory.path,
+            display_name: directory.display_name,
+        },
+        absorbed,
+    ))
+}
+
+/// Songs whose new path is checked when verifying a moved directory.
+const VERIFIED_SONGS: i64 = 20;
+
+/// Applies the changes to the directory, returning it as it is afterwards.
+///
+/// When the directory was moved its path and the paths of its songs are replaced in a single
+/// transaction. With `verify` a sample of the songs has to exist at their new path, nothing is
+/// changed otherwise.
+pub async fn update_directory(
+    connection: &mut Connection,
+    name: &str,
+    update: UpdatedDirectory,
+    verify: bool,
+) -> Result<Directory> {
+    let mut directory = get_directory(&mut *connection, name).await?;
+    let mut transaction = connection.begin().await?;
+
+    if let Some(display_name) = update.display_name {
+        directory.display_name = display_name;
+    }
+
+    if let Some(new_path) = update.new_path.as_deref().map(normalize_path)
+        && new_path != directory.path
+    {
+        let path = Path::new(&new_path);
+        validate_new_path(path, &new_path, update.allow_home_directory)?;
+
+        let others = get_directories(&mut *transaction)
+            .await?
+            .into_iter()
+            .filter(|other| other.name != directory.name)
+            .collect::<Vec<_>>();
+
+        if others
+            .iter()
+            .any(|other| is_inside(path, Path::new(&other.path)))
+        {
+            return Err(DatabaseDirectoryError::PathIsSubdirectory(new_path).into());
+        }
+
+        if others
+            .iter()
+            .any(|other| is_inside(Path::new(&other.path), path))
+        {
+            return Err(DatabaseDirectoryError::PathContainsDirectories(new_path).into());
+        }
+
+        let old_prefix = directory_prefix(&directory.path);
+        let new_prefix = directory_prefix(&new_path);
+
+        for column in ["path", "cue_path"] {
+            sqlx::query(&format!(
+                "UPDATE songs SET {column} = ? || substr({column}, length(?) + 1) WHERE directory_id = ? AND substr({column}, 1, length(?)) = ?"
+            ))
+            .bind(&new_prefix)
+            .bind(&old_prefix)
+            .bind(&directory.name)
+            .bind(&old_prefix)
+            .bind(&old_prefix)
+            .execute(&mut *transaction)
+            .await?;
         }
+
+        if verify {
+            let sample = sqlx::query_scalar::<_, String>(
+                "SELECT path FROM songs WHERE directory_id = ? ORDER BY RANDOM() LIMIT ?",
+            )
+            .bind(&directory.name)
+            .bind(VERIFIED_SONGS)
+            .fetch_all(&mut *transaction)
+            .await?;
+
+            if let Some(missing) = sample.into_iter().find(|path| !Path::new(path).exists()) {
+                return Err(DatabaseDirectoryError::SongsNotMoved(missing).into());
+            }
+        }
+
+        directory.path = new_path;
     }
 
-    let uuid = uuid::Uuid::new_v4().to_string();
+    sqlx::query("UPDATE directories SET path = ?, display_name = ? WHERE name = ?")
+        .bind(&directory.path)
+        .bind(&directory.display_name)
+        .bind(&directory.name)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(directory)
+}
+
+/// Rejects paths a directory can't be moved to, the same way [`add_directory`] does.
+fn validate_new_path(
+    path: &Path,
+    new_path: &str,
+    allow_home: bool,
+) -> Result<(), DatabaseDirectoryError> {
+    if new_path.trim().is_empty() {
+        return Err(DatabaseDirectoryError::PathEmpty);
+    }
+
+    if !path.is_absolute() {
+        return Err(DatabaseDirectoryError::PathNotAbsolute(
+            new_path.to_string(),
+        ));
+    }
 
-    let _ = sqlx::query!(
-        "INSERT INTO directories (name, path, display_name) VALUES (?, ?, ?)",
-        uuid,
-        directory.path,
-        directory.display_name
+    if !path.exists() {
+        return Err(DatabaseDirectoryError::PathDoesNotExist(
+            new_path.to_string(),
+        ));
+    }
+
+    if !path.is_dir() {
+        return Err(DatabaseDirectoryError::PathNotDirectory(
+            new_path.to_string(),
+        ));
+    }
+
+    let home = ::directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    check_library_path(path, &app_owned_dirs(), home.as_deref(), allow_home)
+}
+
+/// Normalizes a path the way directories are stored, with the separators of the platform and
+/// without `.` components or trailing separators, e.g. `C:/Music/` is stored as `C:\Music` on
+/// Windows. Song paths are joined onto directory paths, so they're stored the same way.
+pub fn normalize_path(path: &str) -> String {
+    Path::new(path)
+        .components()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The path with a single trailing separator, so only the paths inside of it start with it.
+pub fn directory_prefix(path: &str) -> String {
+    format!(
+        "{}{}",
+        path.trim_end_matches(std::path::is_separator),
+        std::path::MAIN_SEPARATOR
     )
-    .execute(&mut *connection)
-    .await?;
+}
+
+/// Whether `path` is `directory` or inside of it, comparing whole components so `/music2` isn't
+/// inside `/music`. Symlinks are resolved first where the paths exist.
+fn is_inside(path: &Path, directory: &Path) -> bool {
+    canonicalize(path).starts_with(canonicalize(directory))
+}
+
+/// Returns the directory the path is in, the most nested one when directories are inside each
+/// other.
+pub fn find_directory_from_sub_path<'d>(
+    directories: &'d [(String, String)],
+    path: &str,
+) -> Option<&'d (String, String)> {
+    directories
+        .iter()
+        .filter(|(_, directory)| Path::new(path).starts_with(directory))
+        .max_by_key(|(_, directory)| Path::new(directory).components().count())
+}
+
+/// Rejects paths that shouldn't be scanned and organized as part of the library.
+///
+/// Paths inside `app_directories` would have the app index its own cache and reports, and the
+/// file system root would include every other directory. Home directories, either `home` itself
+/// or a sibling of it like `/home/other`, are only accepted when `allow_home` is set.
+fn check_library_path(
+    path: &Path,
+    app_directories: &[PathBuf],
+    home: Option<&Path>,
+    allow_home: bool,
+) -> Result<(), DatabaseDirectoryError> {
+    let display = || path.to_string_lossy().to_string();
+    let path = canonicalize(path);
+
+    if path.parent().is_none() {
+        return Err(DatabaseDirectoryError::PathIsRoot(display()));
+    }
+
+    if app_directories
+        .iter()
+        .any(|directory| path.starts_with(canonicalize(directory)))
+    {
+        return Err(DatabaseDirectoryError::PathInsideAppDirectory(display()));
+    }
+
+    if let Some(home) = home.map(canonicalize)
+        && !allow_home
+        && (path == home || is_sibling_home(&path, &home))
+    {
+        return Err(DatabaseDirectoryError::PathIsHomeDirectory(display()));
+    }
 
-    Ok(Directory {
-        name: uuid,
-        path: directory.path,
-        display_name: directory.display_name,
-    })
+    Ok(())
+}
+
+/// Whether the path is next to the home directory, unless the home directory is at the root like
+/// `/root`.
+fn is_sibling_home(path: &Path, home: &Path) -> bool {
+    home.parent()
+        .is_some_and(|parent| parent.parent().is_some() && path.parent() == Some(parent))
+}
+
+/// Resolves symlinks of the path, falling back to the path itself if it doesn't exist.
+fn canonicalize(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
 pub async fn remove_directory(
@@ -145,5 +414,238 @@ pub async fn get_directory<'c>(
     sqlx::query_as!(Directory, "SELECT * FROM directories WHERE name = ?", name)
         .fetch_one(&mut *connection)
         .await
-        .map_err(Into::into)
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => DatabaseDirectoryError::NotFound.into(),
+            _ => err.into(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{DatabaseError, test_utils::pool_with_songs};
+
+    #[test]
+    fn test_rejects_app_directories() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let data = temp.path().join("data");
+        let nested = data.join("reports");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let app_directories = [data.clone(), temp.path().join("missing-cache")];
+
+        for path in [
+            &data,
+            &nested,
+            &temp.path().join("missing-cache").join("art"),
+        ] {
+            assert!(matches!(
+                check_library_path(path, &app_directories, None, false),
+                Err(DatabaseDirectoryError::PathInsideAppDirectory(_))
+            ));
+        }
+
+        assert!(check_library_path(temp.path(), &app_directories, None, false).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_root() {
+        assert!(matches!(
+            check_library_path(Path::new("/"), &[], None, true),
+            Err(DatabaseDirectoryError::PathIsRoot(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_home_directories_unless_allowed() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let home = temp.path().join("user");
+        let other_home = temp.path().join("other");
+        let music = home.join("Music");
+        std::fs::create_dir_all(&music).unwrap();
+        std::fs::create_dir_all(&other_home).unwrap();
+
+        for path in [&home, &other_home] {
+            assert!(matches!(
+                check_library_path(path, &[], Some(&home), false),
+                Err(DatabaseDirectoryError::PathIsHomeDirectory(_))
+            ));
+            assert!(check_library_path(path, &[], Some(&home), true).is_ok());
+        }
+
+        assert!(check_library_path(&music, &[], Some(&home), false).is_ok());
+        assert!(
+            check_library_path(Path::new("/music"), &[], Some(Path::new("/root")), false).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_find_directory_from_sub_path() {
+        let directories = [
+            (String::from("music"), String::from("/music")),
+            (String::from("classical"), String::from("/music/classical")),
+            (String::from("music2"), String::from("/music2")),
+        ];
+        let find =
+            |path| find_directory_from_sub_path(&directories, path).map(|(name, _)| name.as_str());
+
+        assert_eq!(find("/music/classical/bach.flac"), Some("classical"));
+        assert_eq!(find("/music/rock/song.flac"), Some("music"));
+        assert_eq!(find("/music2/song.flac"), Some("music2"));
+        assert_eq!(find("/musical/song.flac"), None);
+    }
+
+    #[test]
+    fn test_windows_paths() {
+        let directories = [(String::from("music"), String::from(r"C:\Music"))];
+        let find =
+            |path| find_directory_from_sub_path(&directories, path).map(|(name, _)| name.as_str());
+
+        // Backslashes only separate components on Windows, elsewhere they're part of a name.
+        assert_eq!(Path::new(r"C:\Music").is_absolute(), cfg!(windows));
+        assert_eq!(find(r"C:\Music\Album\song.flac").is_some(), cfg!(windows));
+        assert_eq!(find(r"C:\Music2\song.flac"), None);
+
+        if cfg!(windows) {
+            assert_eq!(normalize_path("C:/Music/./Album/"), r"C:\Music\Album");
+            assert_eq!(normalize_path(r"\\server\share\Music\"), r"\\server\share\Music");
+            assert_eq!(directory_prefix("C:/Music/"), r"C:/Music\");
+        } else {
+            assert_eq!(normalize_path("/music/./album//"), "/music/album");
+            assert_eq!(normalize_path(r"/music/AC\DC"), r"/music/AC\DC");
+            assert_eq!(directory_prefix("/music//"), "/music/");
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_absorbing_subdirectories() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let parent = temp.path().join("music");
+        let child = parent.join("classical");
+        let sibling = temp.path().join("music2");
+        std::fs::create_dir_all(&child).unwrap();
+        std::fs::create_dir_all(&sibling).unwrap();
+
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let new_directory = |path: &Path, absorb_subdirectories| NewDirectory {
+            path: path.to_string_lossy().to_string(),
+            display_name: None,
+            allow_home_directory: false,
+            absorb_subdirectories,
+        };
+
+        let (child, _) = add_directory(&mut connection, new_directory(&child, false))
+            .await
+            .unwrap();
+        add_directory(&mut connection, new_directory(&sibling, false))
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO songs (id, path, directory_id) VALUES ('bach', ?, ?)")
+            .bind(format!("{}/bach.flac", child.path))
+            .bind(&child.name)
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            add_directory(&mut connection, new_directory(&parent, false)).await,
+            Err(DatabaseError::Directory(
+                DatabaseDirectoryError::PathContainsDirectories(_)
+            ))
+        ));
+
+        let (parent, absorbed) = add_directory(&mut connection, new_directory(&parent, true))
+            .await
+            .unwrap();
+        assert_eq!(absorbed.len(), 1);
+        assert_eq!(absorbed[0].name, child.name);
+
+        let directory_id: String =
+            sqlx::query_scalar("SELECT directory_id FROM songs WHERE id = 'bach'")
+                .fetch_one(&mut *connection)
+                .await
+                .unwrap();
+        assert_eq!(directory_id, parent.name);
+        assert_eq!(get_directories(&mut connection).await.unwrap().len(), 3);
+    }
+
+    async fn song_path(connection: &mut Connection) -> String {
+        sqlx::query_scalar("SELECT path FROM songs WHERE id = 'bach'")
+            .fetch_one(connection)
+            .await
+            .unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn test_moving_directory() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let old = temp.path().join("old");
+        let new = temp.path().join("new");
+        std::fs::create_dir_all(&old).unwrap();
+        std::fs::create_dir_all(&new).unwrap();
+
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let (directory, _) = add_directory(
+            &mut connection,
+            NewDirectory {
+                path: old.to_string_lossy().to_string(),
+                display_name: None,
+                allow_home_directory: false,
+                absorb_subdirectories: false,
+            },
+        )
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO songs (id, path, directory_id) VALUES ('bach', ?, ?)")
+            .bind(format!("{}/bach.flac", directory.path))
+            .bind(&directory.name)
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let move_to = |path: &Path| UpdatedDirectory {
+            new_path: Some(path.to_string_lossy().to_string()),
+            ..UpdatedDirectory::default()
+        };
+
+        // The song wasn't copied to the new path yet.
+        assert!(matches!(
+            update_directory(&mut connection, &directory.name, move_to(&new), true).await,
+            Err(DatabaseError::Directory(
+                DatabaseDirectoryError::SongsNotMoved(_)
+            ))
+        ));
+        assert_eq!(
+            song_path(&mut connection).await,
+            format!("{}/bach.flac", old.display())
+        );
+
+        std::fs::write(new.join("bach.flac"), []).unwrap();
+        let update = UpdatedDirectory {
+            display_name: Some(Some(String::from("Classical"))),
+            ..move_to(&new)
+        };
+        let moved = update_directory(&mut connection, &directory.name, update, true)
+            .await
+            .unwrap();
+
+        assert_eq!(moved.path, new.to_string_lossy());
+        assert_eq!(moved.display_name.as_deref(), Some("Classical"));
+        assert_eq!(
+            song_path(&mut connection).await,
+            format!("{}/bach.flac", new.display())
+        );
+        assert_eq!(
+            get_directory(&mut *connection, &directory.name)
+                .await
+                .unwrap()
+                .path,
+            moved.path
+        );
+    }
 }
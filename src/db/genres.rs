@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use sqlx::{Connection as _, query, query_as, query_scalar};
+
+use crate::genres::{GenreMerge, id3v1_genre, split_genres};
+
+use super::{Connection, Genre, Result, Song};
+
+/// Returns the genres of the songs that aren't missing, sorted by name.
+///
+/// With `normalize` set, ID3v1 genre numbers are grouped with the genre they stand for.
+pub async fn get_genres(connection: &mut Connection, normalize: bool) -> Result<Vec<Genre>> {
+    let values = query_scalar::<_, String>(
+        "SELECT genre FROM songs WHERE genre IS NOT NULL AND missing_since IS NULL",
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(aggregate_genres(&values, normalize))
+}
+
+/// Groups the genres of every genre field case-insensitively, a song listing a genre twice is
+/// only counted once.
+fn aggregate_genres(values: &[String], normalize: bool) -> Vec<Genre> {
+    #[derive(Default)]
+    struct Group<'a> {
+        names: BTreeMap<&'a str, usize>,
+        spellings: BTreeMap<String, usize>,
+        song_count: usize,
+    }
+
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+
+    for value in values {
+        let mut song_genres = BTreeMap::new();
+        for spelling in split_genres(value) {
+            let name = if normalize {
+                id3v1_genre(spelling).unwrap_or(spelling)
+            } else {
+                spelling
+            };
+
+            song_genres
+                .entry(name.to_lowercase())
+                .or_insert((name, spelling));
+        }
+
+        for (key, (name, spelling)) in song_genres {
+            let group = groups.entry(key).or_default();
+            *group.names.entry(name).or_default() += 1;
+            *group.spellings.entry(spelling.to_string()).or_default() += 1;
+            group.song_count += 1;
+        }
+    }
+
+    groups
+        .into_values()
+        .map(|group| Genre {
+            name: group
+                .names
+                .iter()
+                .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_default(),
+            song_count: group.song_count,
+            spellings: group.spellings,
+        })
+        .collect()
+}
+
+/// Returns the songs the merge changes the genre of, along with their merged genre.
+pub async fn get_merged_genres(
+    connection: &mut Connection,
+    merge: &GenreMerge,
+) -> Result<Vec<(Song, String)>> {
+    let songs = query_as::<_, Song>("SELECT * FROM songs WHERE genre IS NOT NULL ORDER BY path")
+        .fetch_all(&mut *connection)
+        .await?;
+
+    Ok(songs
+        .into_iter()
+        .filter_map(|song| {
+            let genre = merge.apply(song.genre.as_deref()?)?;
+            Some((song, genre))
+        })
+        .collect())
+}
+
+pub async fn set_song_genre(connection: &mut Connection, id: &str, genre: &str) -> Result<()> {
+    query("UPDATE songs SET genre = ? WHERE id = ?")
+        .bind(genre)
+        .bind(id)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Merges the genres in the database only, in a single transaction. Returns the amount of songs
+/// whose genre changed.
+pub async fn merge_genres(connection: &mut Connection, merge: &GenreMerge) -> Result<usize> {
+    let mut transaction = connection.begin().await?;
+
+    let songs = get_merged_genres(&mut transaction, merge).await?;
+    for (song, genre) in &songs {
+        set_song_genre(&mut transaction, &song.id, genre).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(songs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_listing_and_merging_genres() {
+        let pool = pool_with_songs(&["A", "B", "C", "D"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        for (title, genre) in [
+            ("A", "Hip-Hop"),
+            ("B", "hip-hop; Rock"),
+            ("C", "HipHop; (7)"),
+            ("D", "Rock; rock"),
+        ] {
+            query("UPDATE songs SET genre = ? WHERE title = ?")
+                .bind(genre)
+                .bind(title)
+                .execute(&mut *connection)
+                .await
+                .unwrap();
+        }
+
+        let genres = get_genres(&mut connection, false).await.unwrap();
+        let names = genres
+            .iter()
+            .map(|genre| genre.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["(7)", "Hip-Hop", "HipHop", "Rock"]);
+        assert_eq!(genres[1].song_count, 2);
+        assert_eq!(
+            genres[1].spellings,
+            BTreeMap::from([(String::from("Hip-Hop"), 1), (String::from("hip-hop"), 1)])
+        );
+        assert_eq!(genres[3].song_count, 2);
+
+        let genres = get_genres(&mut connection, true).await.unwrap();
+        assert_eq!(genres[0].name, "Hip-Hop");
+        assert_eq!(genres[0].song_count, 3);
+        assert_eq!(genres[0].spellings.get("(7)"), Some(&1));
+
+        let merge = GenreMerge {
+            sources: vec![String::from("hiphop"), String::from("HIP-HOP")],
+            target: String::from("Hip Hop"),
+        };
+        assert_eq!(
+            get_merged_genres(&mut connection, &merge)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(merge_genres(&mut connection, &merge).await.unwrap(), 3);
+
+        let genres = get_genres(&mut connection, false).await.unwrap();
+        let names = genres
+            .iter()
+            .map(|genre| genre.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["(7)", "Hip Hop", "Rock"]);
+        assert_eq!(genres[1].song_count, 3);
+    }
+}
@@ -0,0 +1,183 @@
+//! Significant events kept after they're broadcast, see [`crate::events::EventBus`].
+
+use sqlx::{QueryBuilder, Sqlite, query};
+use time::OffsetDateTime;
+
+use super::{Connection, HistoryEvent, HistoryQuery, NewHistoryEvent, Page, Result};
+
+pub async fn add_event(
+    connection: &mut Connection,
+    event: &NewHistoryEvent,
+    created_at: OffsetDateTime,
+) -> Result<()> {
+    query("INSERT INTO events (kind, source, message, created_at) VALUES (?, ?, ?, ?)")
+        .bind(event.kind)
+        .bind(&event.source)
+        .bind(&event.message)
+        .bind(created_at)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Fetches a page of the events matching the query, newest first.
+pub async fn get_events(
+    connection: &mut Connection,
+    history_query: HistoryQuery,
+) -> Result<Page<HistoryEvent>> {
+    let kinds = history_query
+        .kinds
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    let filter = |builder: &mut QueryBuilder<'_, Sqlite>| {
+        builder.push(" WHERE 1 = 1");
+
+        if let Some(since) = history_query.since {
+            builder.push(" AND created_at >= ").push_bind(since);
+        }
+
+        if let Some(kinds) = &kinds {
+            builder
+                .push(" AND kind IN (SELECT value FROM json_each(")
+                .push_bind(kinds.clone())
+                .push("))");
+        }
+    };
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM events");
+    filter(&mut count);
+    let total = count
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *connection)
+        .await?;
+
+    let mut select = QueryBuilder::new("SELECT * FROM events");
+    filter(&mut select);
+    select
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(history_query.limit.map(i64::from).unwrap_or(-1))
+        .push(" OFFSET ")
+        .push_bind(history_query.offset.unwrap_or_default());
+
+    let items = select
+        .build_query_as::<HistoryEvent>()
+        .fetch_all(&mut *connection)
+        .await?;
+
+    Ok(Page { items, total })
+}
+
+/// Removes every event older than `before`, returning how many were removed.
+pub async fn delete_events_before(
+    connection: &mut Connection,
+    before: OffsetDateTime,
+) -> Result<u64> {
+    Ok(query("DELETE FROM events WHERE created_at < ?")
+        .bind(before)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use time::Duration;
+
+    use super::*;
+    use crate::db::{HistoryEventKind, test_utils::pool_with_songs};
+
+    fn event(kind: HistoryEventKind, source: &str) -> NewHistoryEvent {
+        NewHistoryEvent {
+            kind,
+            source: Some(source.to_string()),
+            message: None,
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_querying_history() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let now = OffsetDateTime::now_utc();
+
+        for (kind, source, age) in [
+            (HistoryEventKind::JobStarted, "scan-songs", 3),
+            (HistoryEventKind::JobFailed, "scan-songs", 2),
+            (HistoryEventKind::DirectoryAdded, "/music", 1),
+        ] {
+            add_event(
+                &mut connection,
+                &event(kind, source),
+                now - Duration::days(age),
+            )
+            .await
+            .unwrap();
+        }
+
+        let page = get_events(&mut connection, HistoryQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.items[0].kind, HistoryEventKind::DirectoryAdded);
+
+        let page = get_events(
+            &mut connection,
+            HistoryQuery {
+                since: Some(now - Duration::hours(60)),
+                kinds: Some(vec![
+                    HistoryEventKind::JobStarted,
+                    HistoryEventKind::JobFailed,
+                ]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].kind, HistoryEventKind::JobFailed);
+
+        let page = get_events(
+            &mut connection,
+            HistoryQuery {
+                kinds: Some(Vec::new()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 0);
+
+        let page = get_events(
+            &mut connection,
+            HistoryQuery {
+                limit: Some(1),
+                offset: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].kind, HistoryEventKind::JobFailed);
+
+        assert_eq!(
+            delete_events_before(&mut connection, now - Duration::hours(36))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            get_events(&mut connection, HistoryQuery::default())
+                .await
+                .unwrap()
+                .total,
+            1
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Checks for rows that drifted from what the rest of the app expects, e.g. songs left behind by a
+//! directory that was deleted by hand, and repairs for the ones that can be fixed without guessing.
+
+use std::path::Path;
+
+use sqlx::{Connection as _, query, query_as};
+use time::OffsetDateTime;
+
+use super::{
+    Connection, DuplicatePath, IntegrityRepair, IntegrityReport, IntegritySong, Result,
+    directories::find_directory_from_sub_path,
+};
+
+/// Runs every check that only needs the database, the report has no missing files.
+pub async fn check_database(connection: &mut Connection) -> Result<IntegrityReport> {
+    Ok(IntegrityReport {
+        generated_at: OffsetDateTime::now_utc(),
+        orphaned_songs: get_orphaned_songs(&mut *connection).await?,
+        duplicate_paths: get_duplicate_paths(&mut *connection).await?,
+        songs_outside_directory: get_songs_outside_directory(&mut *connection).await?,
+        blank_ids: query_as::<_, IntegritySong>(
+            "SELECT id, path, directory_id FROM songs WHERE trim(id) = '' ORDER BY path",
+        )
+        .fetch_all(&mut *connection)
+        .await?,
+        missing_files: Vec::new(),
+    })
+}
+
+/// Returns every song, for checking whether their files exist.
+pub async fn get_songs(connection: &mut Connection) -> Result<Vec<IntegritySong>> {
+    Ok(
+        query_as::<_, IntegritySong>("SELECT id, path, directory_id FROM songs ORDER BY path")
+            .fetch_all(&mut *connection)
+            .await?,
+    )
+}
+
+pub async fn get_orphaned_songs(connection: &mut Connection) -> Result<Vec<IntegritySong>> {
+    Ok(query_as::<_, IntegritySong>(
+        "SELECT id, path, directory_id FROM songs WHERE directory_id NOT IN (SELECT name FROM directories) ORDER BY path",
+    )
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+pub async fn get_duplicate_paths(connection: &mut Connection) -> Result<Vec<DuplicatePath>> {
+    let rows = query_as::<_, (String, i64, String)>(
+        "SELECT path, start_ms, id FROM songs WHERE (path, start_ms) IN (SELECT path, start_ms FROM songs GROUP BY path, start_ms HAVING COUNT(*) > 1) ORDER BY path, start_ms, added_at IS NULL, added_at, rowid",
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let mut duplicates = Vec::<DuplicatePath>::new();
+    for (path, start_ms, id) in rows {
+        match duplicates.last_mut() {
+            Some(last) if last.path == path && last.start_ms == start_ms => last.song_ids.push(id),
+            _ => duplicates.push(DuplicatePath {
+                path,
+                start_ms,
+                song_ids: vec![id],
+            }),
+        }
+    }
+
+    Ok(duplicates)
+}
+
+/// Compares whole components, so a song in `/music2` isn't inside `/music`.
+pub async fn get_songs_outside_directory(
+    connection: &mut Connection,
+) -> Result<Vec<IntegritySong>> {
+    let songs = query_as::<_, (String, String, String, String)>(
+        "SELECT songs.id, songs.path, songs.directory_id, directories.path FROM songs INNER JOIN directories ON directories.name = songs.directory_id ORDER BY songs.path",
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(songs
+        .into_iter()
+        .filter(|(_, path, _, directory)| !Path::new(path).starts_with(directory))
+        .map(|(id, path, directory_id, _)| IntegritySong {
+            id,
+            path,
+            directory_id,
+        })
+        .collect())
+}
+
+/// Fixes what can be fixed safely in a single transaction: orphaned songs are moved to the
+/// directory their path is in or deleted if there's none, and of songs sharing a path only the
+/// oldest is kept.
+///
+/// Songs outside their directory, blank ids and missing files are left alone, they need someone
+/// to look at them. The returned repair has no report, that's up to the caller.
+pub async fn repair(connection: &mut Connection) -> Result<IntegrityRepair> {
+    let mut transaction = connection.begin().await?;
+
+    let directories = query_as::<_, (String, String)>("SELECT name, path FROM directories")
+        .fetch_all(&mut *transaction)
+        .await?;
+
+    let mut reassigned_songs = Vec::new();
+    let mut deleted_songs = Vec::new();
+
+    for song in get_orphaned_songs(&mut *transaction).await? {
+        match find_directory_from_sub_path(&directories, &song.path) {
+            Some((name, _)) => {
+                query("UPDATE songs SET directory_id = ? WHERE id = ?")
+                    .bind(name)
+                    .bind(&song.id)
+                    .execute(&mut *transaction)
+                    .await?;
+
+                reassigned_songs.push(IntegritySong {
+                    directory_id: name.clone(),
+                    ..song
+                });
+            }
+            None => deleted_songs.push(song.id),
+        }
+    }
+
+    for duplicate in get_duplicate_paths(&mut *transaction).await? {
+        deleted_songs.extend(duplicate.song_ids.into_iter().skip(1));
+    }
+
+    let ids =
+        serde_json::to_string(&deleted_songs).map_err(|err| sqlx::Error::Encode(err.into()))?;
+    query("DELETE FROM songs WHERE id IN (SELECT value FROM json_each(?))")
+        .bind(ids)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(IntegrityRepair {
+        reassigned_songs,
+        deleted_songs,
+        report: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_repairing_orphaned_songs() {
+        let pool = pool_with_songs(&["Inside"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        // Like a directory that was deleted by hand.
+        query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        for (id, path) in [
+            ("moved", "/music/Artist/Moved.mp3"),
+            ("lost", "/elsewhere/Lost.mp3"),
+        ] {
+            query("INSERT INTO songs (id, path, directory_id) VALUES (?, ?, 'deleted')")
+                .bind(id)
+                .bind(path)
+                .execute(&mut *connection)
+                .await
+                .unwrap();
+        }
+        query("INSERT INTO directories (name, path) VALUES ('other', '/other')")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        query("INSERT INTO songs (id, path, directory_id) VALUES ('outside', '/other2/Song.mp3', 'other')")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let report = check_database(&mut connection).await.unwrap();
+        assert_eq!(report.orphaned_songs.len(), 2);
+        assert!(report.duplicate_paths.is_empty());
+        assert_eq!(report.songs_outside_directory.len(), 1);
+        assert_eq!(report.songs_outside_directory[0].id, "outside");
+
+        let repair = repair(&mut connection).await.unwrap();
+        assert_eq!(repair.reassigned_songs.len(), 1);
+        assert_eq!(repair.reassigned_songs[0].id, "moved");
+        assert_eq!(repair.reassigned_songs[0].directory_id, "directory");
+        assert_eq!(repair.deleted_songs, ["lost"]);
+
+        let report = check_database(&mut connection).await.unwrap();
+        assert!(report.orphaned_songs.is_empty());
+        assert_eq!(report.songs_outside_directory.len(), 1);
+        assert_eq!(get_songs(&mut connection).await.unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,658 @@
+//! Exporting the catalog of the library and importing it back, see [`LibraryRecord`] for the
+//! format of the rows.
+
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+use sqlx::{FromRow, query, query_as, query_scalar, sqlite::SqliteRow};
+use tokio::sync::mpsc::Sender;
+
+use crate::metadata::position::Position;
+
+use super::{
+    Connection, Directory, ImportConflict, LibraryImportSummary, LibraryRecord, LibraryRecordKind,
+    Playlist, Result, Song, parse_year, song_album_id,
+};
+
+/// Columns of a song that are imported, in the order they're bound.
+const SONG_COLUMNS: [&str; 33] = [
+    "path",
+    "directory_id",
+    "title",
+    "artist",
+    "album",
+    "album_artist",
+    "album_id",
+    "genre",
+    "track_number",
+    "track_total",
+    "disc_number",
+    "disc_total",
+    "year",
+    "year_num",
+    "mood",
+    "composer",
+    "comment",
+    "rating",
+    "lyrics",
+    "added_at",
+    "updated_at",
+    "file_created_at",
+    "duration_ms",
+    "bitrate_kbps",
+    "sample_rate",
+    "channels",
+    "cue_path",
+    "start_ms",
+    "end_ms",
+    "track_gain_db",
+    "track_peak",
+    "album_gain_db",
+    "album_peak",
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseLibraryError {
+    #[error("Row {row} is missing \"{column}\"")]
+    MissingColumn { row: u64, column: &'static str },
+    #[error("Row {row} refers to directory \"{id}\", which isn't in the import")]
+    UnknownDirectory { row: u64, id: String },
+    #[error("Row {row} refers to song \"{id}\", which isn't in the import")]
+    UnknownSong { row: u64, id: String },
+    #[error("Row {row} refers to playlist \"{id}\", which isn't in the import")]
+    UnknownPlaylist { row: u64, id: String },
+}
+
+/// A song of a playlist.
+#[derive(FromRow)]
+struct PlaylistEntry {
+    playlist_id: String,
+    song_id: String,
+    position: i64,
+}
+
+impl From<Directory> for LibraryRecord {
+    fn from(directory: Directory) -> Self {
+        Self {
+            record: LibraryRecordKind::Directory,
+            id: Some(directory.name),
+            path: Some(directory.path),
+            display_name: directory.display_name,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Song> for LibraryRecord {
+    fn from(song: Song) -> Self {
+        Self {
+            record: LibraryRecordKind::Song,
+            id: Some(song.id),
+            path: Some(song.path),
+            directory_id: Some(song.directory_id),
+            title: song.title,
+            artist: song.artist,
+            album: song.album,
+            album_artist: song.album_artist,
+            genre: song.genre,
+            track_number: Position {
+                number: song.track_number,
+                total: song.track_total,
+            }
+            .to_tag(),
+            disc_number: Position {
+                number: song.disc_number,
+                total: song.disc_total,
+            }
+            .to_tag(),
+            year: song.year,
+            mood: song.mood,
+            composer: song.composer,
+            comment: song.comment,
+            rating: song.rating,
+            lyrics: song.lyrics,
+            added_at: song.added_at,
+            updated_at: song.updated_at,
+            file_created_at: song.file_created_at,
+            duration_ms: song.duration_ms,
+            bitrate_kbps: song.bitrate_kbps,
+            sample_rate: song.sample_rate,
+            channels: song.channels,
+            cue_path: song.cue_path,
+            start_ms: Some(song.start_ms),
+            end_ms: song.end_ms,
+            track_gain_db: song.track_gain_db,
+            track_peak: song.track_peak,
+            album_gain_db: song.album_gain_db,
+            album_peak: song.album_peak,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Playlist> for LibraryRecord {
+    fn from(playlist: Playlist) -> Self {
+        Self {
+            record: LibraryRecordKind::Playlist,
+            id: Some(playlist.id),
+            name: Some(playlist.name),
+            created_at: playlist.created_at,
+            updated_at: playlist.updated_at,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<PlaylistEntry> for LibraryRecord {
+    fn from(entry: PlaylistEntry) -> Self {
+        Self {
+            record: LibraryRecordKind::PlaylistSong,
+            playlist_id: Some(entry.playlist_id),
+            song_id: Some(entry.song_id),
+            position: Some(entry.position),
+            ..Default::default()
+        }
+    }
+}
+
+/// Sends every row of the library to `tx` one at a time, in the order described on
+/// [`LibraryRecord`].
+///
+/// Stops early without an error once the receiver is dropped.
+pub async fn export_library(connection: &mut Connection, tx: &Sender<LibraryRecord>) -> Result<()> {
+    if !send_rows::<Directory>(connection, "SELECT * FROM directories ORDER BY path", tx).await? {
+        return Ok(());
+    }
+
+    if !send_rows::<Song>(
+        connection,
+        "SELECT * FROM songs ORDER BY path, start_ms",
+        tx,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    if !send_rows::<Playlist>(
+        connection,
+        "SELECT * FROM playlists ORDER BY created_at, id",
+        tx,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    send_rows::<PlaylistEntry>(
+        connection,
+        "SELECT * FROM playlist_songs ORDER BY playlist_id, position",
+        tx,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sends the rows the query returns as they're read, returns whether the receiver still listens.
+async fn send_rows<T>(
+    connection: &mut Connection,
+    sql: &'static str,
+    tx: &Sender<LibraryRecord>,
+) -> Result<bool>
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    LibraryRecord: From<T>,
+{
+    let mut rows = query_as::<_, T>(sql).fetch(&mut *connection);
+
+    while let Some(row) = rows.try_next().await? {
+        if tx.send(LibraryRecord::from(row)).await.is_err() {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Adds the rows of an exported library, one at a time so the export never has to be held in
+/// memory.
+///
+/// Directories are matched to the existing ones by path, the songs and playlists referring to
+/// them are moved to the matching directory. Nothing is committed here, callers import within a
+/// transaction so a failed import leaves the library untouched.
+pub struct LibraryImport<'c> {
+    connection: &'c mut Connection,
+    conflict: ImportConflict,
+    /// Ids of the imported rows mapped to the ids they have in the library.
+    directories: HashMap<String, String>,
+    songs: HashMap<String, String>,
+    /// Skipped playlists map to `None`, their songs are skipped as well.
+    playlists: HashMap<String, Option<String>>,
+    summary: LibraryImportSummary,
+    row: u64,
+}
+
+impl<'c> LibraryImport<'c> {
+    pub fn new(connection: &'c mut Connection, conflict: ImportConflict) -> Self {
+        Self {
+            connection,
+            conflict,
+            directories: HashMap::new(),
+            songs: HashMap::new(),
+            playlists: HashMap::new(),
+            summary: LibraryImportSummary::default(),
+            row: 0,
+        }
+    }
+
+    pub async fn add(&mut self, record: LibraryRecord) -> Result<()> {
+        self.row += 1;
+
+        match record.record {
+            LibraryRecordKind::Directory => self.add_directory(record).await,
+            LibraryRecordKind::Song => self.add_song(record).await,
+            LibraryRecordKind::Playlist => self.add_playlist(record).await,
+            LibraryRecordKind::PlaylistSong => self.add_playlist_song(record).await,
+        }
+    }
+
+    pub fn finish(self) -> LibraryImportSummary {
+        self.summary
+    }
+
+    fn required<T>(&self, value: Option<T>, column: &'static str) -> Result<T> {
+        value.ok_or_else(|| {
+            DatabaseLibraryError::MissingColumn {
+                row: self.row,
+                column,
+            }
+            .into()
+        })
+    }
+
+    async fn add_directory(&mut self, record: LibraryRecord) -> Result<()> {
+        let id = self.required(record.id, "id")?;
+        let path = self.required(record.path, "path")?;
+
+        let existing = query_scalar::<_, String>("SELECT name FROM directories WHERE path = ?")
+            .bind(&path)
+            .fetch_optional(&mut *self.connection)
+            .await?;
+
+        let name = match existing {
+            Some(name) => {
+                self.summary.directories_matched += 1;
+                name
+            }
+            None => {
+                let name = if self.is_taken("directories", "name", &id).await? {
+                    uuid::Uuid::new_v4().to_string()
+                } else {
+                    id.clone()
+                };
+
+                query("INSERT INTO directories (name, path, display_name) VALUES (?, ?, ?)")
+                    .bind(&name)
+                    .bind(&path)
+                    .bind(&record.display_name)
+                    .execute(&mut *self.connection)
+                    .await?;
+
+                self.summary.directories_added += 1;
+                name
+            }
+        };
+
+        self.directories.insert(id, name);
+
+        Ok(())
+    }
+
+    async fn add_song(&mut self, mut record: LibraryRecord) -> Result<()> {
+        let id = self.required(record.id.take(), "id")?;
+        let path = self.required(record.path.clone(), "path")?;
+        let directory_id = self.required(record.directory_id.take(), "directoryId")?;
+
+        let directory_id = self.directories.get(&directory_id).cloned().ok_or(
+            DatabaseLibraryError::UnknownDirectory {
+                row: self.row,
+                id: directory_id,
+            },
+        )?;
+        record.directory_id = Some(directory_id);
+        record.start_ms = Some(record.start_ms.unwrap_or_default());
+
+        let existing =
+            query_scalar::<_, String>("SELECT id FROM songs WHERE path = ? AND start_ms = ?")
+                .bind(&path)
+                .bind(record.start_ms)
+                .fetch_optional(&mut *self.connection)
+                .await?;
+
+        let song_id = match existing {
+            Some(existing) if self.conflict == ImportConflict::Skip => {
+                self.summary.songs_skipped += 1;
+                existing
+            }
+            Some(existing) => {
+                let sql = format!(
+                    "UPDATE songs SET {} WHERE id = ?",
+                    SONG_COLUMNS
+                        .map(|column| format!("{column} = ?"))
+                        .join(", ")
+                );
+                bind_song(query(&sql), &record)
+                    .bind(&existing)
+                    .execute(&mut *self.connection)
+                    .await?;
+
+                self.summary.songs_updated += 1;
+                existing
+            }
+            None => {
+                let song_id = if self.is_taken("songs", "id", &id).await? {
+                    uuid::Uuid::new_v4().to_string()
+                } else {
+                    id.clone()
+                };
+
+                let sql = format!(
+                    "INSERT INTO songs ({}, id) VALUES ({}, ?)",
+                    SONG_COLUMNS.join(", "),
+                    ["?"; SONG_COLUMNS.len()].join(", ")
+                );
+                bind_song(query(&sql), &record)
+                    .bind(&song_id)
+                    .execute(&mut *self.connection)
+                    .await?;
+
+                self.summary.songs_added += 1;
+                song_id
+            }
+        };
+
+        self.songs.insert(id, song_id);
+
+        Ok(())
+    }
+
+    async fn add_playlist(&mut self, record: LibraryRecord) -> Result<()> {
+        let id = self.required(record.id, "id")?;
+        let name = self.required(record.name, "name")?;
+
+        let exists = self.is_taken("playlists", "id", &id).await?;
+
+        let playlist_id = match (exists, self.conflict) {
+            (true, ImportConflict::Skip) => {
+                self.summary.playlists_skipped += 1;
+                None
+            }
+            (true, ImportConflict::Overwrite) => {
+                query("UPDATE playlists SET name = ?, created_at = ?, updated_at = ? WHERE id = ?")
+                    .bind(&name)
+                    .bind(record.created_at)
+                    .bind(record.updated_at)
+                    .bind(&id)
+                    .execute(&mut *self.connection)
+                    .await?;
+
+                query("DELETE FROM playlist_songs WHERE playlist_id = ?")
+                    .bind(&id)
+                    .execute(&mut *self.connection)
+                    .await?;
+
+                self.summary.playlists_updated += 1;
+                Some(id.clone())
+            }
+            (false, _) => {
+                query(
+                    "INSERT INTO playlists (id, name, created_at, updated_at) VALUES (?, ?, ?, ?)",
+                )
+                .bind(&id)
+                .bind(&name)
+                .bind(record.created_at)
+                .bind(record.updated_at)
+                .execute(&mut *self.connection)
+                .await?;
+
+                self.summary.playlists_added += 1;
+                Some(id.clone())
+            }
+        };
+
+        self.playlists.insert(id, playlist_id);
+
+        Ok(())
+    }
+
+    async fn add_playlist_song(&mut self, record: LibraryRecord) -> Result<()> {
+        let playlist_id = self.required(record.playlist_id, "playlistId")?;
+        let song_id = self.required(record.song_id, "songId")?;
+        let position = self.required(record.position, "position")?;
+
+        let Some(playlist_id) = self.playlists.get(&playlist_id).cloned().ok_or(
+            DatabaseLibraryError::UnknownPlaylist {
+                row: self.row,
+                id: playlist_id,
+            },
+        )?
+        else {
+            return Ok(());
+        };
+
+        let song_id =
+            self.songs
+                .get(&song_id)
+                .cloned()
+                .ok_or(DatabaseLibraryError::UnknownSong {
+                    row: self.row,
+                    id: song_id,
+                })?;
+
+        query(
+            "INSERT OR IGNORE INTO playlist_songs (playlist_id, song_id, position) VALUES (?, ?, ?)",
+        )
+        .bind(&playlist_id)
+        .bind(&song_id)
+        .bind(position)
+        .execute(&mut *self.connection)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn is_taken(&mut self, table: &str, column: &str, value: &str) -> Result<bool> {
+        Ok(
+            query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {table} WHERE {column} = ?"))
+                .bind(value)
+                .fetch_one(&mut *self.connection)
+                .await?
+                > 0,
+        )
+    }
+}
+
+/// Binds the values of [`SONG_COLUMNS`] in order.
+fn bind_song<'q>(
+    query: sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    record: &'q LibraryRecord,
+) -> sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+    let track = Position::parse(record.track_number.as_deref().unwrap_or_default());
+    let disc = Position::parse(record.disc_number.as_deref().unwrap_or_default());
+
+    query
+        .bind(&record.path)
+        .bind(&record.directory_id)
+        .bind(&record.title)
+        .bind(&record.artist)
+        .bind(&record.album)
+        .bind(&record.album_artist)
+        .bind(song_album_id(
+            record.album.as_deref(),
+            record.album_artist.as_deref(),
+        ))
+        .bind(&record.genre)
+        .bind(track.number)
+        .bind(track.total)
+        .bind(disc.number)
+        .bind(disc.total)
+        .bind(&record.year)
+        .bind(record.year.as_deref().and_then(parse_year))
+        .bind(&record.mood)
+        .bind(&record.composer)
+        .bind(&record.comment)
+        .bind(record.rating)
+        .bind(&record.lyrics)
+        .bind(record.added_at)
+        .bind(record.updated_at)
+        .bind(record.file_created_at)
+        .bind(record.duration_ms)
+        .bind(record.bitrate_kbps)
+        .bind(record.sample_rate)
+        .bind(record.channels)
+        .bind(&record.cue_path)
+        .bind(record.start_ms)
+        .bind(record.end_ms)
+        .bind(record.track_gain_db)
+        .bind(record.track_peak)
+        .bind(record.album_gain_db)
+        .bind(record.album_peak)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::db::{playlists, test_utils::pool_with_songs};
+
+    async fn export(connection: &mut Connection) -> Vec<LibraryRecord> {
+        let (tx, mut rx) = mpsc::channel(4);
+        let export = async move { export_library(connection, &tx).await.unwrap() };
+        let receive = async {
+            let mut records = Vec::new();
+            while let Some(record) = rx.recv().await {
+                records.push(record);
+            }
+            records
+        };
+
+        tokio::join!(export, receive).1
+    }
+
+    async fn import(
+        connection: &mut Connection,
+        records: &[LibraryRecord],
+        conflict: ImportConflict,
+    ) -> LibraryImportSummary {
+        let mut import = LibraryImport::new(connection, conflict);
+        for record in records {
+            import.add(record.clone()).await.unwrap();
+        }
+        import.finish()
+    }
+
+    #[test(tokio::test)]
+    async fn test_export_and_import_library() {
+        let source = pool_with_songs(&["a", "b"]).await;
+        let mut connection = source.acquire().await.unwrap();
+
+        let song_id = query_scalar::<_, String>("SELECT id FROM songs WHERE title = 'b'")
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+        let playlist = playlists::create_playlist(&mut connection, "Mix")
+            .await
+            .unwrap();
+        playlists::add_song(&mut connection, &playlist.id, &song_id)
+            .await
+            .unwrap();
+
+        let records = export(&mut connection).await;
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.record)
+                .collect::<Vec<_>>(),
+            [
+                LibraryRecordKind::Directory,
+                LibraryRecordKind::Song,
+                LibraryRecordKind::Song,
+                LibraryRecordKind::Playlist,
+                LibraryRecordKind::PlaylistSong,
+            ]
+        );
+
+        // The same path is registered under another name on the target.
+        let target = pool_with_songs(&[]).await;
+        let mut connection = target.acquire().await.unwrap();
+        query("UPDATE directories SET name = 'other'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let summary = import(&mut connection, &records, ImportConflict::Skip).await;
+        assert_eq!(
+            summary,
+            LibraryImportSummary {
+                directories_matched: 1,
+                songs_added: 2,
+                playlists_added: 1,
+                ..Default::default()
+            }
+        );
+
+        let directory_ids = query_scalar::<_, String>("SELECT DISTINCT directory_id FROM songs")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+        assert_eq!(directory_ids, ["other"]);
+
+        let playlist = playlists::get_playlist_with_tracks(&mut connection, &playlist.id)
+            .await
+            .unwrap();
+        assert_eq!(playlist.tracks.len(), 1);
+        assert_eq!(playlist.tracks[0].title.as_deref(), Some("b"));
+
+        let summary = import(&mut connection, &records, ImportConflict::Skip).await;
+        assert_eq!((summary.songs_skipped, summary.playlists_skipped), (2, 1));
+
+        let mut renamed = records.clone();
+        renamed[1].title = Some(String::from("renamed"));
+        let summary = import(&mut connection, &renamed, ImportConflict::Overwrite).await;
+        assert_eq!((summary.songs_updated, summary.playlists_updated), (2, 1));
+        assert_eq!(
+            query_scalar::<_, i64>("SELECT COUNT(*) FROM songs WHERE title = 'renamed'")
+                .fetch_one(&mut *connection)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_import_unknown_directory() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let mut import = LibraryImport::new(&mut connection, ImportConflict::Skip);
+        let result = import
+            .add(LibraryRecord {
+                record: LibraryRecordKind::Song,
+                id: Some(String::from("song")),
+                path: Some(String::from("/music/song.mp3")),
+                directory_id: Some(String::from("missing")),
+                ..Default::default()
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(crate::db::DatabaseError::Library(
+                DatabaseLibraryError::UnknownDirectory { row: 1, .. }
+            ))
+        ));
+    }
+}
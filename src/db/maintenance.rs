@@ -0,0 +1,62 @@
+//! Whether the server is in maintenance, see [`crate::state::Maintenance`].
+
+use sqlx::{query, query_as};
+
+use super::{Connection, MaintenanceMode, Result};
+
+pub async fn get_maintenance(connection: &mut Connection) -> Result<Option<MaintenanceMode>> {
+    Ok(
+        query_as::<_, MaintenanceMode>("SELECT started_at, reason FROM maintenance WHERE id = 1")
+            .fetch_optional(&mut *connection)
+            .await?,
+    )
+}
+
+pub async fn set_maintenance(connection: &mut Connection, mode: &MaintenanceMode) -> Result<()> {
+    query("INSERT OR REPLACE INTO maintenance (id, started_at, reason) VALUES (1, ?, ?)")
+        .bind(mode.started_at)
+        .bind(&mode.reason)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn clear_maintenance(connection: &mut Connection) -> Result<()> {
+    query("DELETE FROM maintenance")
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_setting_maintenance() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        assert_eq!(get_maintenance(&mut connection).await.unwrap(), None);
+
+        let mode = MaintenanceMode {
+            started_at: Date::from_calendar_date(2026, Month::October, 16)
+                .unwrap()
+                .with_hms(2, 0, 0)
+                .unwrap()
+                .assume_utc(),
+            reason: Some(String::from("Nightly backup")),
+        };
+        set_maintenance(&mut connection, &mode).await.unwrap();
+        set_maintenance(&mut connection, &mode).await.unwrap();
+        assert_eq!(get_maintenance(&mut connection).await.unwrap(), Some(mode));
+
+        clear_maintenance(&mut connection).await.unwrap();
+        assert_eq!(get_maintenance(&mut connection).await.unwrap(), None);
+    }
+}
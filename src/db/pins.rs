@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use sqlx::{Connection as _, query, query_as, query_scalar};
+use time::OffsetDateTime;
+
+use super::{Album, Connection, Pin, PinKind, PinTarget, PinnedItem, Result, Song};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabasePinError {
+    #[error("Pin not found")]
+    NotFound,
+    #[error("Album or song to pin not found")]
+    EntityNotFound,
+    #[error("Already pinned")]
+    AlreadyPinned,
+    #[error("New order must contain every pin exactly once")]
+    InvalidOrder,
+}
+
+pub async fn get_pins(connection: &mut Connection) -> Result<Vec<Pin>> {
+    Ok(query_as::<_, Pin>("SELECT * FROM pins ORDER BY position")
+        .fetch_all(&mut *connection)
+        .await?)
+}
+
+/// Returns the pinned albums and songs in order, leaving out pins whose album or song is gone.
+pub async fn get_pinned_items(connection: &mut Connection) -> Result<Vec<PinnedItem>> {
+    let mut items = Vec::new();
+
+    for pin in get_pins(connection).await? {
+        match pin.kind {
+            PinKind::Album => {
+                let tracks = query_as::<_, Song>("SELECT * FROM songs WHERE album = ?")
+                    .bind(&pin.entity_id)
+                    .fetch_all(&mut *connection)
+                    .await?;
+
+                if !tracks.is_empty() {
+                    items.push(PinnedItem::Album(Album::from(tracks)));
+                }
+            }
+            PinKind::Song => {
+                if let Some(song) = query_as::<_, Song>("SELECT * FROM songs WHERE id = ?")
+                    .bind(&pin.entity_id)
+                    .fetch_optional(&mut *connection)
+                    .await?
+                {
+                    items.push(PinnedItem::Song(song));
+                }
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+/// Pins the album or song after every other pin.
+pub async fn pin(connection: &mut Connection, target: &PinTarget) -> Result<Pin> {
+    let mut transaction = connection.begin_with("BEGIN IMMEDIATE").await?;
+
+    let entity_query = match target.kind {
+        PinKind::Album => "SELECT COUNT(*) FROM songs WHERE album = ?",
+        PinKind::Song => "SELECT COUNT(*) FROM songs WHERE id = ?",
+    };
+
+    let exists = query_scalar::<_, i64>(entity_query)
+        .bind(&target.entity_id)
+        .fetch_one(&mut *transaction)
+        .await?
+        > 0;
+
+    if !exists {
+        return Err(DatabasePinError::EntityNotFound.into());
+    }
+
+    let pinned =
+        query_scalar::<_, i64>("SELECT COUNT(*) FROM pins WHERE kind = ? AND entity_id = ?")
+            .bind(target.kind)
+            .bind(&target.entity_id)
+            .fetch_one(&mut *transaction)
+            .await?;
+
+    if pinned > 0 {
+        return Err(DatabasePinError::AlreadyPinned.into());
+    }
+
+    let position = query_scalar::<_, u32>("SELECT COALESCE(MAX(position) + 1, 0) FROM pins")
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    let pin = Pin {
+        kind: target.kind,
+        entity_id: target.entity_id.clone(),
+        position,
+        pinned_at: Some(OffsetDateTime::now_utc()),
+    };
+
+    query("INSERT INTO pins (kind, entity_id, position, pinned_at) VALUES (?, ?, ?, ?)")
+        .bind(pin.kind)
+        .bind(&pin.entity_id)
+        .bind(pin.position)
+        .bind(pin.pinned_at)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(pin)
+}
+
+pub async fn unpin(connection: &mut Connection, kind: PinKind, entity_id: &str) -> Result<()> {
+    let rows_affected = query("DELETE FROM pins WHERE kind = ? AND entity_id = ?")
+        .bind(kind)
+        .bind(entity_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        Err(DatabasePinError::NotFound.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Replaces the order of the pins, `targets` must contain every pin exactly once.
+pub async fn reorder_pins(connection: &mut Connection, targets: &[PinTarget]) -> Result<Vec<Pin>> {
+    let mut transaction = connection.begin_with("BEGIN IMMEDIATE").await?;
+
+    let current = get_pins(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|pin| PinTarget {
+            kind: pin.kind,
+            entity_id: pin.entity_id,
+        })
+        .collect::<HashSet<_>>();
+
+    let requested = targets.iter().cloned().collect::<HashSet<_>>();
+    if requested.len() != targets.len() || requested != current {
+        return Err(DatabasePinError::InvalidOrder.into());
+    }
+
+    for (position, target) in targets.iter().enumerate() {
+        query("UPDATE pins SET position = ? WHERE kind = ? AND entity_id = ?")
+            .bind(position as i64)
+            .bind(target.kind)
+            .bind(&target.entity_id)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    let pins = get_pins(&mut *transaction).await?;
+    transaction.commit().await?;
+
+    Ok(pins)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{DatabaseError, test_utils::pool_with_songs};
+
+    fn target(kind: PinKind, entity_id: &str) -> PinTarget {
+        PinTarget {
+            kind,
+            entity_id: entity_id.to_string(),
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_pinning() {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        query("UPDATE songs SET album = 'Album' WHERE title = 'a'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        let song_id = query_scalar::<_, String>("SELECT id FROM songs WHERE title = 'b'")
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+
+        let album = pin(&mut connection, &target(PinKind::Album, "Album"))
+            .await
+            .unwrap();
+        let song = pin(&mut connection, &target(PinKind::Song, &song_id))
+            .await
+            .unwrap();
+        assert_eq!((album.position, song.position), (0, 1));
+
+        assert!(matches!(
+            pin(&mut connection, &target(PinKind::Album, "Album")).await,
+            Err(DatabaseError::Pin(DatabasePinError::AlreadyPinned))
+        ));
+        assert!(matches!(
+            pin(&mut connection, &target(PinKind::Album, "Missing")).await,
+            Err(DatabaseError::Pin(DatabasePinError::EntityNotFound))
+        ));
+
+        let pins = reorder_pins(
+            &mut connection,
+            &[
+                target(PinKind::Song, &song_id),
+                target(PinKind::Album, "Album"),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(pins[0].kind, PinKind::Song);
+
+        assert!(matches!(
+            reorder_pins(&mut connection, &[target(PinKind::Song, &song_id)]).await,
+            Err(DatabaseError::Pin(DatabasePinError::InvalidOrder))
+        ));
+
+        query("DELETE FROM songs WHERE id = ?")
+            .bind(&song_id)
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        let items = get_pinned_items(&mut connection).await.unwrap();
+        assert!(matches!(items.as_slice(), [PinnedItem::Album(album)] if album.title == "Album"));
+
+        unpin(&mut connection, PinKind::Album, "Album")
+            .await
+            .unwrap();
+        assert!(matches!(
+            unpin(&mut connection, PinKind::Album, "Album").await,
+            Err(DatabaseError::Pin(DatabasePinError::NotFound))
+        ));
+    }
+}
@@ -0,0 +1,516 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use sqlx::{Connection as _, query, query_as, query_scalar};
+use time::OffsetDateTime;
+
+use super::{
+    BulkAddResult, Connection, DatabaseError, Playlist, PlaylistImport, PlaylistWithTracks, Result,
+    Song, UnresolvedEntry, songs,
+};
+use crate::m3u;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabasePlaylistError {
+    #[error("Playlist not found")]
+    NotFound,
+    #[error("Name is empty")]
+    NameEmpty,
+    #[error("Song not found")]
+    SongNotFound,
+    #[error("Song is already in the playlist")]
+    SongAlreadyAdded,
+    #[error("Song is not in the playlist")]
+    SongNotInPlaylist,
+    #[error("New order must contain every song of the playlist exactly once")]
+    InvalidOrder,
+}
+
+pub async fn get_playlists(connection: &mut Connection) -> Result<Vec<Playlist>> {
+    Ok(
+        query_as::<_, Playlist>("SELECT * FROM playlists ORDER BY name COLLATE NOCASE")
+            .fetch_all(&mut *connection)
+            .await?,
+    )
+}
+
+pub async fn get_playlist(connection: &mut Connection, id: &str) -> Result<Playlist> {
+    query_as::<_, Playlist>("SELECT * FROM playlists WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .ok_or(DatabasePlaylistError::NotFound.into())
+}
+
+/// Returns the playlist along with its songs in order.
+pub async fn get_playlist_with_tracks(
+    connection: &mut Connection,
+    id: &str,
+) -> Result<PlaylistWithTracks> {
+    let playlist = get_playlist(connection, id).await?;
+    let tracks = query_as::<_, Song>(
+        "SELECT songs.* FROM playlist_songs
+        JOIN songs ON songs.id = playlist_songs.song_id
+        WHERE playlist_songs.playlist_id = ?
+        ORDER BY playlist_songs.position",
+    )
+    .bind(id)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(PlaylistWithTracks { playlist, tracks })
+}
+
+pub async fn create_playlist(connection: &mut Connection, name: &str) -> Result<Playlist> {
+    if name.trim().is_empty() {
+        return Err(DatabasePlaylistError::NameEmpty.into());
+    }
+
+    let playlist = Playlist {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        created_at: Some(OffsetDateTime::now_utc()),
+        updated_at: None,
+    };
+
+    query("INSERT INTO playlists (id, name, created_at) VALUES (?, ?, ?)")
+        .bind(&playlist.id)
+        .bind(&playlist.name)
+        .bind(playlist.created_at)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(playlist)
+}
+
+pub async fn rename_playlist(connection: &mut Connection, id: &str, name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(DatabasePlaylistError::NameEmpty.into());
+    }
+
+    let rows_affected = query("UPDATE playlists SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(name.trim())
+        .bind(OffsetDateTime::now_utc())
+        .bind(id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        Err(DatabasePlaylistError::NotFound.into())
+    } else {
+        Ok(())
+    }
+}
+
+pub async fn delete_playlist(connection: &mut Connection, id: &str) -> Result<()> {
+    let rows_affected = query("DELETE FROM playlists WHERE id = ?")
+        .bind(id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        Err(DatabasePlaylistError::NotFound.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Appends the song to the end of the playlist.
+pub async fn add_song(connection: &mut Connection, id: &str, song_id: &str) -> Result<()> {
+    let mut transaction = connection.begin_with("BEGIN IMMEDIATE").await?;
+
+    get_playlist(&mut *transaction, id).await?;
+
+    if query_scalar::<_, String>("SELECT id FROM songs WHERE id = ?")
+        .bind(song_id)
+        .fetch_optional(&mut *transaction)
+        .await?
+        .is_none()
+    {
+        return Err(DatabasePlaylistError::SongNotFound.into());
+    }
+
+    query(
+        "INSERT INTO playlist_songs (playlist_id, song_id, position)
+        SELECT ?, ?, COALESCE(MAX(position) + 1, 0) FROM playlist_songs WHERE playlist_id = ?",
+    )
+    .bind(id)
+    .bind(song_id)
+    .bind(id)
+    .execute(&mut *transaction)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            DatabasePlaylistError::SongAlreadyAdded.into()
+        }
+        _ => DatabaseError::from(err),
+    })?;
+
+    touch_playlist(&mut *transaction, id).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Appends every song in `song_ids` to the end of the playlist in a single transaction.
+///
+/// Songs already in the playlist and unknown song ids are skipped.
+pub async fn add_songs(
+    connection: &mut Connection,
+    id: &str,
+    song_ids: &[String],
+) -> Result<BulkAddResult> {
+    let mut transaction = connection.begin_with("BEGIN IMMEDIATE").await?;
+
+    get_playlist(&mut *transaction, id).await?;
+
+    let existing = songs::get_existing_song_ids(&mut *transaction, song_ids).await?;
+    let mut in_playlist =
+        query_scalar::<_, String>("SELECT song_id FROM playlist_songs WHERE playlist_id = ?")
+            .bind(id)
+            .fetch_all(&mut *transaction)
+            .await?
+            .into_iter()
+            .collect::<HashSet<String>>();
+
+    let mut position = query_scalar::<_, i64>(
+        "SELECT COALESCE(MAX(position) + 1, 0) FROM playlist_songs WHERE playlist_id = ?",
+    )
+    .bind(id)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    let mut result = BulkAddResult::default();
+    for song_id in song_ids {
+        if !existing.contains(song_id) {
+            result.unknown += 1;
+            continue;
+        }
+
+        if !in_playlist.insert(song_id.clone()) {
+            result.duplicate += 1;
+            continue;
+        }
+
+        query("INSERT INTO playlist_songs (playlist_id, song_id, position) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(song_id)
+            .bind(position)
+            .execute(&mut *transaction)
+            .await?;
+
+        position += 1;
+        result.added += 1;
+    }
+
+    if result.added > 0 {
+        touch_playlist(&mut *transaction, id).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(result)
+}
+
+/// Creates a playlist from the entries of a playlist file, keeping every entry that matches a song.
+pub async fn import_playlist(
+    connection: &mut Connection,
+    name: &str,
+    entries: &[m3u::Entry],
+    base_directories: &[PathBuf],
+) -> Result<PlaylistImport> {
+    let mut song_ids = Vec::new();
+    let mut unresolved = Vec::new();
+
+    for entry in entries {
+        match songs::resolve_song_path(connection, &entry.path, base_directories).await? {
+            Some(id) => song_ids.push(id),
+            None => unresolved.push(UnresolvedEntry {
+                line: entry.line,
+                path: entry.path.clone(),
+            }),
+        }
+    }
+
+    let playlist = create_playlist(connection, name).await?;
+    let BulkAddResult { added, .. } = add_songs(connection, &playlist.id, &song_ids).await?;
+
+    Ok(PlaylistImport {
+        playlist,
+        added,
+        unresolved,
+    })
+}
+
+/// Removes the song from the playlist, shifting every song after it up by one.
+pub async fn remove_song(connection: &mut Connection, id: &str, song_id: &str) -> Result<()> {
+    let mut transaction = connection.begin_with("BEGIN IMMEDIATE").await?;
+
+    get_playlist(&mut *transaction, id).await?;
+
+    let position = query_scalar::<_, i64>(
+        "DELETE FROM playlist_songs WHERE playlist_id = ? AND song_id = ? RETURNING position",
+    )
+    .bind(id)
+    .bind(song_id)
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(DatabasePlaylistError::SongNotInPlaylist)?;
+
+    query(
+        "UPDATE playlist_songs SET position = position - 1 WHERE playlist_id = ? AND position > ?",
+    )
+    .bind(id)
+    .bind(position)
+    .execute(&mut *transaction)
+    .await?;
+
+    touch_playlist(&mut *transaction, id).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Replaces the order of the songs in the playlist.
+///
+/// `song_ids` must contain every song of the playlist exactly once. The write lock is taken before
+/// the current songs are read, so concurrent reorders are applied one after another.
+pub async fn reorder_songs(
+    connection: &mut Connection,
+    id: &str,
+    song_ids: &[String],
+) -> Result<()> {
+    let mut transaction = connection.begin_with("BEGIN IMMEDIATE").await?;
+
+    get_playlist(&mut *transaction, id).await?;
+
+    let current =
+        query_scalar::<_, String>("SELECT song_id FROM playlist_songs WHERE playlist_id = ?")
+            .bind(id)
+            .fetch_all(&mut *transaction)
+            .await?
+            .into_iter()
+            .collect::<HashSet<String>>();
+
+    let requested = song_ids.iter().cloned().collect::<HashSet<String>>();
+    if requested.len() != song_ids.len() || requested != current {
+        return Err(DatabasePlaylistError::InvalidOrder.into());
+    }
+
+    for (position, song_id) in song_ids.iter().enumerate() {
+        query("UPDATE playlist_songs SET position = ? WHERE playlist_id = ? AND song_id = ?")
+            .bind(position as i64)
+            .bind(id)
+            .bind(song_id)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    touch_playlist(&mut *transaction, id).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn touch_playlist(connection: &mut Connection, id: &str) -> Result<()> {
+    query("UPDATE playlists SET updated_at = ? WHERE id = ?")
+        .bind(OffsetDateTime::now_utc())
+        .bind(id)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{songs, test_utils::pool_with_songs};
+
+    async fn song_ids(connection: &mut Connection) -> Vec<String> {
+        query_scalar::<_, String>("SELECT id FROM songs ORDER BY title")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap()
+    }
+
+    async fn track_ids(connection: &mut Connection, id: &str) -> Vec<String> {
+        get_playlist_with_tracks(connection, id)
+            .await
+            .unwrap()
+            .tracks
+            .into_iter()
+            .map(|song| song.id)
+            .collect()
+    }
+
+    #[test(tokio::test)]
+    async fn test_playlist_order() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let ids = song_ids(&mut connection).await;
+
+        let playlist = create_playlist(&mut connection, "Playlist").await.unwrap();
+        for song_id in &ids {
+            add_song(&mut connection, &playlist.id, song_id)
+                .await
+                .unwrap();
+        }
+
+        assert!(matches!(
+            add_song(&mut connection, &playlist.id, &ids[0]).await,
+            Err(DatabaseError::Playlist(
+                DatabasePlaylistError::SongAlreadyAdded
+            ))
+        ));
+        assert_eq!(track_ids(&mut connection, &playlist.id).await, ids);
+
+        let reversed = ids.iter().rev().cloned().collect::<Vec<_>>();
+        reorder_songs(&mut connection, &playlist.id, &reversed)
+            .await
+            .unwrap();
+        assert_eq!(track_ids(&mut connection, &playlist.id).await, reversed);
+
+        assert!(matches!(
+            reorder_songs(&mut connection, &playlist.id, &reversed[1..]).await,
+            Err(DatabaseError::Playlist(DatabasePlaylistError::InvalidOrder))
+        ));
+
+        remove_song(&mut connection, &playlist.id, &ids[1])
+            .await
+            .unwrap();
+        add_song(&mut connection, &playlist.id, &ids[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            track_ids(&mut connection, &playlist.id).await,
+            [ids[2].clone(), ids[0].clone(), ids[1].clone()]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_deleted_song_is_removed_from_playlists() {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let ids = song_ids(&mut connection).await;
+
+        let playlist = create_playlist(&mut connection, "Playlist").await.unwrap();
+        for song_id in &ids {
+            add_song(&mut connection, &playlist.id, song_id)
+                .await
+                .unwrap();
+        }
+
+        songs::delete_song(&mut connection, &ids[0]).await.unwrap();
+
+        assert_eq!(
+            track_ids(&mut connection, &playlist.id).await,
+            [ids[1].clone()]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_add_songs() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let ids = song_ids(&mut connection).await;
+
+        let playlist = create_playlist(&mut connection, "Playlist").await.unwrap();
+        add_song(&mut connection, &playlist.id, &ids[1])
+            .await
+            .unwrap();
+
+        let result = add_songs(
+            &mut connection,
+            &playlist.id,
+            &[
+                ids[2].clone(),
+                ids[1].clone(),
+                String::from("unknown"),
+                ids[0].clone(),
+                ids[2].clone(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result,
+            BulkAddResult {
+                added: 2,
+                duplicate: 2,
+                unknown: 1,
+            }
+        );
+        assert_eq!(
+            track_ids(&mut connection, &playlist.id).await,
+            [ids[1].clone(), ids[2].clone(), ids[0].clone()]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_m3u_round_trip() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let ids = song_ids(&mut connection).await;
+        let order = [ids[2].clone(), ids[0].clone(), ids[1].clone()];
+
+        let playlist = create_playlist(&mut connection, "Playlist").await.unwrap();
+        add_songs(&mut connection, &playlist.id, &order)
+            .await
+            .unwrap();
+
+        let exported = get_playlist_with_tracks(&mut connection, &playlist.id)
+            .await
+            .unwrap();
+        let content = m3u::render(
+            &exported.playlist.name,
+            &exported
+                .tracks
+                .iter()
+                .map(m3u::Track::from)
+                .collect::<Vec<_>>(),
+        );
+
+        let parsed = m3u::parse(&content);
+        let import = import_playlist(&mut connection, "Imported", &parsed.entries, &[])
+            .await
+            .unwrap();
+
+        assert_eq!(import.added, 3);
+        assert!(import.unresolved.is_empty());
+        assert_eq!(track_ids(&mut connection, &import.playlist.id).await, order);
+    }
+
+    #[test(tokio::test)]
+    async fn test_import_resolves_relative_paths() {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let ids = song_ids(&mut connection).await;
+
+        let parsed = m3u::parse("b.mp3\n/elsewhere/a.mp3\nmissing.mp3\n");
+        let import = import_playlist(
+            &mut connection,
+            "Imported",
+            &parsed.entries,
+            &[PathBuf::from("/music")],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            import.unresolved,
+            [UnresolvedEntry {
+                line: 3,
+                path: String::from("missing.mp3"),
+            }]
+        );
+        assert_eq!(
+            track_ids(&mut connection, &import.playlist.id).await,
+            [ids[1].clone(), ids[0].clone()]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Files the `scan-songs` job failed to read, so a corrupt file is only read and reported again
+//! once it's modified.
+
+use std::collections::HashMap;
+
+use sqlx::{query, query_as};
+use time::OffsetDateTime;
+
+use super::{Connection, Result, ScanError, ScanErrorCategory};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseScanError {
+    #[error("Scan error not found")]
+    NotFound,
+}
+
+/// A failed read of a file, see [`record_scan_errors`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewScanError {
+    pub path: String,
+    pub category: ScanErrorCategory,
+    pub error: String,
+    pub modified_at: Option<OffsetDateTime>,
+}
+
+pub async fn get_scan_errors(connection: &mut Connection) -> Result<Vec<ScanError>> {
+    Ok(
+        query_as::<_, ScanError>("SELECT * FROM scan_errors ORDER BY path")
+            .fetch_all(&mut *connection)
+            .await?,
+    )
+}
+
+/// Returns when each file that failed to be read was modified as of its last failure, keyed by
+/// path.
+pub async fn get_failed_paths(
+    connection: &mut Connection,
+) -> Result<HashMap<String, Option<OffsetDateTime>>> {
+    Ok(
+        query_as::<_, (String, Option<OffsetDateTime>)>(
+            "SELECT path, modified_at FROM scan_errors",
+        )
+        .fetch_all(&mut *connection)
+        .await?
+        .into_iter()
+        .collect(),
+    )
+}
+
+/// Records the failed reads, counting another attempt for files that failed before.
+pub async fn record_scan_errors(
+    connection: &mut Connection,
+    errors: &[NewScanError],
+    seen_at: OffsetDateTime,
+) -> Result<()> {
+    for error in errors {
+        query(
+            "INSERT INTO scan_errors (path, category, error, modified_at, first_seen, last_seen) VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (path) DO UPDATE SET category = excluded.category, error = excluded.error, modified_at = excluded.modified_at, last_seen = excluded.last_seen, attempts = attempts + 1",
+        )
+        .bind(&error.path)
+        .bind(error.category)
+        .bind(&error.error)
+        .bind(error.modified_at)
+        .bind(seen_at)
+        .bind(seen_at)
+        .execute(&mut *connection)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Forgets the failures of files that could be read after all.
+pub async fn clear_scan_errors(connection: &mut Connection, paths: &[String]) -> Result<u64> {
+    let paths = serde_json::to_string(paths).map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    Ok(
+        query("DELETE FROM scan_errors WHERE path IN (SELECT value FROM json_each(?))")
+            .bind(paths)
+            .execute(&mut *connection)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Removes the entry, so the next scan reads the file again whether it was modified or not.
+pub async fn delete_scan_error(connection: &mut Connection, id: i64) -> Result<ScanError> {
+    query_as::<_, ScanError>("DELETE FROM scan_errors WHERE id = ? RETURNING *")
+        .bind(id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .ok_or_else(|| DatabaseScanError::NotFound.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{DatabaseError, test_utils::pool_with_songs};
+
+    fn unreadable(path: &str, modified_at: Option<OffsetDateTime>) -> NewScanError {
+        NewScanError {
+            path: path.to_string(),
+            category: ScanErrorCategory::Unreadable,
+            error: String::from("Corrupt stream"),
+            modified_at,
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_scan_errors() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let first_seen = OffsetDateTime::UNIX_EPOCH;
+        let modified_at = Some(OffsetDateTime::UNIX_EPOCH + time::Duration::days(1));
+        record_scan_errors(
+            &mut connection,
+            &[
+                unreadable("/music/b.flac", None),
+                unreadable("/music/a.flac", None),
+            ],
+            first_seen,
+        )
+        .await
+        .unwrap();
+
+        // A second failure keeps when the file first failed.
+        let last_seen = first_seen + time::Duration::days(2);
+        record_scan_errors(
+            &mut connection,
+            &[unreadable("/music/a.flac", modified_at)],
+            last_seen,
+        )
+        .await
+        .unwrap();
+
+        let errors = get_scan_errors(&mut connection).await.unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, "/music/a.flac");
+        assert_eq!(errors[0].attempts, 2);
+        assert_eq!(errors[0].first_seen, first_seen);
+        assert_eq!(errors[0].last_seen, last_seen);
+        assert_eq!(errors[1].attempts, 1);
+
+        let failed = get_failed_paths(&mut connection).await.unwrap();
+        assert_eq!(failed["/music/a.flac"], modified_at);
+        assert_eq!(failed["/music/b.flac"], None);
+
+        assert_eq!(
+            clear_scan_errors(&mut connection, &[String::from("/music/a.flac")])
+                .await
+                .unwrap(),
+            1
+        );
+
+        let retried = delete_scan_error(&mut connection, errors[1].id)
+            .await
+            .unwrap();
+        assert_eq!(retried.path, "/music/b.flac");
+        assert!(get_scan_errors(&mut connection).await.unwrap().is_empty());
+        assert!(matches!(
+            delete_scan_error(&mut connection, errors[1].id).await,
+            Err(DatabaseError::ScanError(DatabaseScanError::NotFound))
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+//! Schedules jobs are queued on by the [`crate::state::job::scheduler`].
+
+use sqlx::{FromRow, query, query_as, types::Json};
+use time::{Duration, OffsetDateTime, Time};
+
+use super::{Connection, JobSchedule, NewJobSchedule, Result, ScheduleTrigger};
+
+/// The shortest interval a job can be scheduled with.
+pub const MIN_INTERVAL_SECONDS: u32 = 60;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseScheduleError {
+    #[error("Schedule not found")]
+    NotFound,
+    #[error("Interval must be at least {MIN_INTERVAL_SECONDS} seconds")]
+    IntervalTooShort,
+    #[error("Invalid time \"{0}\", expected HH:MM")]
+    InvalidTime(String),
+}
+
+#[derive(FromRow)]
+struct ScheduleRow {
+    job_id: String,
+    trigger: Json<ScheduleTrigger>,
+    enabled: bool,
+    last_run_at: Option<OffsetDateTime>,
+    updated_at: OffsetDateTime,
+}
+
+impl From<ScheduleRow> for JobSchedule {
+    fn from(row: ScheduleRow) -> Self {
+        let trigger = row.trigger.0;
+
+        // Counting from the last change keeps a schedule that was just enabled or edited from
+        // queueing its job right away because of a run long ago.
+        let since = row.last_run_at.map_or(row.updated_at, |last_run_at| {
+            last_run_at.max(row.updated_at)
+        });
+
+        Self {
+            job_id: row.job_id,
+            next_run_at: row
+                .enabled
+                .then(|| trigger.next_run_after(since).ok())
+                .flatten(),
+            trigger,
+            enabled: row.enabled,
+            last_run_at: row.last_run_at,
+        }
+    }
+}
+
+impl ScheduleTrigger {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Self::Interval { seconds } if *seconds < MIN_INTERVAL_SECONDS => {
+                Err(DatabaseScheduleError::IntervalTooShort.into())
+            }
+            Self::Interval { .. } => Ok(()),
+            Self::Daily { time } => parse_time(time).map(|_| ()),
+        }
+    }
+
+    /// Returns the first time the trigger fires after `since`.
+    pub fn next_run_after(&self, since: OffsetDateTime) -> Result<OffsetDateTime> {
+        match self {
+            Self::Interval { seconds } => Ok(since + Duration::seconds(i64::from(*seconds))),
+            Self::Daily { time } => {
+                let run = since.replace_time(parse_time(time)?);
+
+                Ok(if run > since {
+                    run
+                } else {
+                    run + Duration::DAY
+                })
+            }
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Result<Time> {
+    let invalid = || DatabaseScheduleError::InvalidTime(time.to_string());
+    let parse = |value: &str| {
+        (value.len() == 2)
+            .then(|| value.parse::<u8>().ok())
+            .flatten()
+            .ok_or_else(invalid)
+    };
+
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+
+    Ok(Time::from_hms(parse(hour)?, parse(minute)?, 0).map_err(|_| invalid())?)
+}
+
+pub async fn get_schedules(connection: &mut Connection) -> Result<Vec<JobSchedule>> {
+    Ok(
+        query_as::<_, ScheduleRow>("SELECT * FROM job_schedules ORDER BY job_id")
+            .fetch_all(&mut *connection)
+            .await?
+            .into_iter()
+            .map(JobSchedule::from)
+            .collect(),
+    )
+}
+
+pub async fn get_schedule(connection: &mut Connection, job_id: &str) -> Result<JobSchedule> {
+    query_as::<_, ScheduleRow>("SELECT * FROM job_schedules WHERE job_id = ?")
+        .bind(job_id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .map(JobSchedule::from)
+        .ok_or(DatabaseScheduleError::NotFound.into())
+}
+
+/// Creates or replaces the schedule of a job, keeping when it last ran.
+pub async fn set_schedule(
+    connection: &mut Connection,
+    job_id: &str,
+    schedule: &NewJobSchedule,
+) -> Result<JobSchedule> {
+    schedule.trigger.validate()?;
+
+    query(
+        "INSERT INTO job_schedules (job_id, trigger, enabled, updated_at) VALUES (?, ?, ?, ?)
+        ON CONFLICT (job_id) DO UPDATE SET
+            trigger = excluded.trigger,
+            enabled = excluded.enabled,
+            updated_at = excluded.updated_at",
+    )
+    .bind(job_id)
+    .bind(Json(&schedule.trigger))
+    .bind(schedule.enabled)
+    .bind(OffsetDateTime::now_utc())
+    .execute(&mut *connection)
+    .await?;
+
+    get_schedule(connection, job_id).await
+}
+
+pub async fn delete_schedule(connection: &mut Connection, job_id: &str) -> Result<()> {
+    let result = query("DELETE FROM job_schedules WHERE job_id = ?")
+        .bind(job_id)
+        .execute(&mut *connection)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(DatabaseScheduleError::NotFound.into());
+    }
+
+    Ok(())
+}
+
+pub async fn mark_schedule_run(
+    connection: &mut Connection,
+    job_id: &str,
+    run_at: OffsetDateTime,
+) -> Result<()> {
+    query("UPDATE job_schedules SET last_run_at = ? WHERE job_id = ?")
+        .bind(run_at)
+        .bind(job_id)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::db::{DatabaseError, test_utils::pool_with_songs};
+
+    fn at(hour: u8, minute: u8) -> OffsetDateTime {
+        Date::from_calendar_date(2026, Month::October, 15)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    #[test]
+    fn test_next_run() {
+        let nightly = ScheduleTrigger::Daily {
+            time: String::from("03:30"),
+        };
+
+        assert_eq!(nightly.next_run_after(at(1, 0)).unwrap(), at(3, 30));
+        assert_eq!(
+            nightly.next_run_after(at(3, 30)).unwrap(),
+            at(3, 30) + Duration::DAY
+        );
+        assert_eq!(
+            ScheduleTrigger::Interval { seconds: 3600 }
+                .next_run_after(at(1, 0))
+                .unwrap(),
+            at(2, 0)
+        );
+    }
+
+    #[test]
+    fn test_validating_triggers() {
+        for time in ["3:30", "24:00", "03:60", "0330", "ab:cd"] {
+            assert!(matches!(
+                ScheduleTrigger::Daily {
+                    time: time.to_string()
+                }
+                .validate(),
+                Err(DatabaseError::Schedule(DatabaseScheduleError::InvalidTime(
+                    _
+                )))
+            ));
+        }
+
+        assert!(matches!(
+            ScheduleTrigger::Interval { seconds: 59 }.validate(),
+            Err(DatabaseError::Schedule(
+                DatabaseScheduleError::IntervalTooShort
+            ))
+        ));
+        assert!(
+            ScheduleTrigger::Daily {
+                time: String::from("23:59")
+            }
+            .validate()
+            .is_ok()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_setting_schedules() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let schedule = NewJobSchedule {
+            trigger: ScheduleTrigger::Interval { seconds: 3600 },
+            enabled: true,
+        };
+        let created = set_schedule(&mut connection, "scan-songs", &schedule)
+            .await
+            .unwrap();
+        assert!(created.next_run_at.is_some());
+        assert!(created.last_run_at.is_none());
+
+        mark_schedule_run(&mut connection, "scan-songs", at(1, 0))
+            .await
+            .unwrap();
+
+        let schedule = NewJobSchedule {
+            enabled: false,
+            ..schedule
+        };
+        let updated = set_schedule(&mut connection, "scan-songs", &schedule)
+            .await
+            .unwrap();
+        assert_eq!(updated.last_run_at, Some(at(1, 0)));
+        assert!(updated.next_run_at.is_none());
+
+        assert_eq!(get_schedules(&mut connection).await.unwrap().len(), 1);
+
+        delete_schedule(&mut connection, "scan-songs")
+            .await
+            .unwrap();
+        assert!(matches!(
+            delete_schedule(&mut connection, "scan-songs").await,
+            Err(DatabaseError::Schedule(DatabaseScheduleError::NotFound))
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+//! Searching the library through the `songs_search` full-text index, which matches every word as
+//! the start of a word so results show up while the query is still being typed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use sqlx::{query, query_as};
+
+use super::{
+    AlbumMatch, ArtistMatch, Connection, Result, SearchResults, SongMatch, artists::split_artists,
+};
+
+/// Searches songs, albums and artists, returning at most `limit` of each.
+///
+/// Scores are the BM25 rank of the matches, titles weighing more than artists and albums, which
+/// weigh more than genres. Songs whose file is missing are left out.
+pub async fn search(connection: &mut Connection, query: &str, limit: u32) -> Result<SearchResults> {
+    let Some(expression) = match_expression(query) else {
+        return Ok(SearchResults {
+            songs: Vec::new(),
+            albums: Vec::new(),
+            artists: Vec::new(),
+        });
+    };
+
+    let songs = query_as::<_, SongMatch>(
+        "SELECT songs.*, -bm25(songs_search, 10.0, 5.0, 5.0, 5.0, 1.0) AS score FROM songs_search INNER JOIN songs ON songs.id = songs_search.song_id WHERE songs_search MATCH ? AND songs.missing_since IS NULL ORDER BY score DESC LIMIT ?",
+    )
+    .bind(&expression)
+    .bind(limit)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    // Ranking functions can't be aggregated directly, the matches are materialized first.
+    let albums = query_as::<_, AlbumMatch>(
+        "WITH matches AS MATERIALIZED (SELECT songs.album, songs.album_artist, -bm25(songs_search) AS score FROM songs_search INNER JOIN songs ON songs.id = songs_search.song_id WHERE songs_search MATCH ? AND songs.album IS NOT NULL AND songs.missing_since IS NULL) SELECT album AS title, MAX(album_artist) AS artist, COUNT(*) AS track_count, MAX(score) AS score FROM matches GROUP BY album ORDER BY score DESC, title LIMIT ?",
+    )
+    .bind(format!("{{album album_artist}} : ({expression})"))
+    .bind(limit)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    let credits = query_as::<_, (Option<String>, Option<String>, f64)>(
+        "SELECT songs.artist, songs.album_artist, -bm25(songs_search) AS score FROM songs_search INNER JOIN songs ON songs.id = songs_search.song_id WHERE songs_search MATCH ? AND songs.missing_since IS NULL",
+    )
+    .bind(format!("{{artist album_artist}} : ({expression})"))
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(SearchResults {
+        songs,
+        albums,
+        artists: matching_artists(credits, query, limit as usize),
+    })
+}
+
+/// Fills the index from scratch with the songs as they are now, returning how many were indexed.
+pub async fn rebuild_index(connection: &mut Connection) -> Result<u64> {
+    query("DELETE FROM songs_search")
+        .execute(&mut *connection)
+        .await?;
+
+    let indexed = query("INSERT INTO songs_search (title, artist, album, album_artist, genre, song_id) SELECT title, artist, album, album_artist, genre, id FROM songs")
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+    // Merges the index into a single b-tree, it's written all at once so it's fragmented otherwise.
+    query("INSERT INTO songs_search (songs_search) VALUES ('optimize')")
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(indexed)
+}
+
+/// Turns a query into an FTS5 expression matching every word as a prefix, `None` if it has no
+/// words. Each word is quoted so characters FTS5 reads as syntax are searched for instead.
+pub fn match_expression(query: &str) -> Option<String> {
+    let words = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Groups the credits of the matching songs by artist, matching names case-insensitively.
+///
+/// Songs credit several artists in a single field, only the ones whose own name matches the query
+/// are kept, so an artist isn't found for sharing a song with the one searched for.
+fn matching_artists(
+    credits: Vec<(Option<String>, Option<String>, f64)>,
+    query: &str,
+    limit: usize,
+) -> Vec<ArtistMatch> {
+    let query = words(query);
+    let mut artists: BTreeMap<String, ArtistMatch> = BTreeMap::new();
+
+    for (artist, album_artist, score) in &credits {
+        let names = [artist, album_artist]
+            .into_iter()
+            .flatten()
+            .flat_map(|value| split_artists(value))
+            .collect::<BTreeSet<_>>();
+
+        for name in names {
+            let name_words = words(name);
+            if !query
+                .iter()
+                .all(|word| name_words.iter().any(|name| name.starts_with(word)))
+            {
+                continue;
+            }
+
+            let artist = artists
+                .entry(name.to_lowercase())
+                .or_insert_with(|| ArtistMatch {
+                    name: name.to_string(),
+                    track_count: 0,
+                    score: *score,
+                });
+
+            artist.track_count += 1;
+            artist.score = artist.score.max(*score);
+        }
+    }
+
+    let mut artists = artists.into_values().collect::<Vec<_>>();
+    artists.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    artists.truncate(limit);
+
+    artists
+}
+
+/// Splits text into lowercase words without diacritics, the way the index tokenizes it.
+fn words(text: &str) -> Vec<String> {
+    any_ascii::any_ascii(text)
+        .to_lowercase()
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use sqlx::query_scalar;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test]
+    fn test_match_expression() {
+        assert_eq!(match_expression("  "), None);
+        assert_eq!(
+            match_expression("ac/dc \"back"),
+            Some(String::from("\"ac/dc\"* \"\"\"back\"*"))
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_search() {
+        let pool = pool_with_songs(&["Hells Bells", "Back in Black", "Formation"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        for (title, artist, album) in [
+            ("Hells Bells", "AC/DC", "Back in Black"),
+            ("Back in Black", "AC/DC", "Back in Black"),
+            ("Formation", "Beyoncé; Back Street", "Lemonade"),
+        ] {
+            query("UPDATE songs SET artist = ?, album = ? WHERE title = ?")
+                .bind(artist)
+                .bind(album)
+                .bind(title)
+                .execute(&mut *connection)
+                .await
+                .unwrap();
+        }
+
+        let results = search(&mut connection, "bac", 10).await.unwrap();
+        assert_eq!(results.songs.len(), 3);
+        assert_eq!(
+            results.songs[0].song.title.as_deref(),
+            Some("Back in Black")
+        );
+        assert!(results.songs.iter().all(|song| song.score > 0.0));
+        assert_eq!(results.albums.len(), 1);
+        assert_eq!(results.albums[0].title, "Back in Black");
+        assert_eq!(results.albums[0].track_count, 2);
+        assert_eq!(results.artists.len(), 1);
+        assert_eq!(results.artists[0].name, "Back Street");
+
+        // Diacritics are ignored and every word has to match.
+        let results = search(&mut connection, "beyonce form", 10).await.unwrap();
+        assert_eq!(results.songs.len(), 1);
+        assert!(results.artists.is_empty());
+        let results = search(&mut connection, "BEYON", 10).await.unwrap();
+        assert_eq!(results.artists[0].name, "Beyoncé");
+
+        // Renamed and deleted songs are kept in sync by the triggers.
+        query("UPDATE songs SET title = 'Thunderstruck' WHERE title = 'Hells Bells'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        query("DELETE FROM songs WHERE title = 'Formation'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        assert!(
+            search(&mut connection, "hells", 10)
+                .await
+                .unwrap()
+                .songs
+                .is_empty()
+        );
+        assert_eq!(
+            search(&mut connection, "thunder", 10)
+                .await
+                .unwrap()
+                .songs
+                .len(),
+            1
+        );
+        assert!(
+            search(&mut connection, "beyon", 10)
+                .await
+                .unwrap()
+                .artists
+                .is_empty()
+        );
+    }
+
+    async fn indexed_ids(connection: &mut Connection) -> Vec<String> {
+        query_scalar::<_, String>("SELECT song_id FROM songs_search ORDER BY song_id")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn test_index_follows_song_ids() {
+        let pool = pool_with_songs(&["Hells Bells", "Formation"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        // Moving a song onto the path of another replaces it, its row is gone from the index too.
+        query("UPDATE songs SET path = '/music/Formation.mp3' WHERE title = 'Hells Bells'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let ids = query_scalar::<_, String>("SELECT id FROM songs")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(indexed_ids(&mut connection).await, ids);
+
+        query("VACUUM").execute(&mut *connection).await.unwrap();
+        let results = search(&mut connection, "hells", 10).await.unwrap();
+        assert_eq!(results.songs[0].song.id, ids[0]);
+
+        query("DELETE FROM songs_search")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        assert_eq!(rebuild_index(&mut connection).await.unwrap(), 1);
+        assert_eq!(indexed_ids(&mut connection).await, ids);
+    }
+}
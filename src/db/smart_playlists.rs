@@ -0,0 +1,312 @@
+//! Playlists defined by a [`SongFilter`] instead of a list of songs, evaluated whenever
+//! they're listed, see [`songs::get_songs_matching`].
+
+use sqlx::{FromRow, query, query_as, types::Json};
+use time::OffsetDateTime;
+
+use super::{
+    Connection, NewSmartPlaylist, Page, Result, SmartPlaylist, Song, SongFilter, SongQuery, songs,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseSmartPlaylistError {
+    #[error("Smart playlist not found")]
+    NotFound,
+    #[error("Name is empty")]
+    NameEmpty,
+}
+
+#[derive(FromRow)]
+struct SmartPlaylistRow {
+    id: String,
+    name: String,
+    filter: Json<SongFilter>,
+    created_at: Option<OffsetDateTime>,
+    updated_at: Option<OffsetDateTime>,
+}
+
+impl From<SmartPlaylistRow> for SmartPlaylist {
+    fn from(row: SmartPlaylistRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            filter: row.filter.0,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Rejects playlists that couldn't be evaluated, so saving one is the only place that can fail
+/// because of its filter.
+fn validate(playlist: &NewSmartPlaylist) -> Result<()> {
+    if playlist.name.trim().is_empty() {
+        return Err(DatabaseSmartPlaylistError::NameEmpty.into());
+    }
+
+    songs::validate_filter(&playlist.filter)
+}
+
+pub async fn get_smart_playlists(connection: &mut Connection) -> Result<Vec<SmartPlaylist>> {
+    Ok(query_as::<_, SmartPlaylistRow>(
+        "SELECT * FROM smart_playlists ORDER BY name COLLATE NOCASE",
+    )
+    .fetch_all(&mut *connection)
+    .await?
+    .into_iter()
+    .map(SmartPlaylist::from)
+    .collect())
+}
+
+pub async fn get_smart_playlist(connection: &mut Connection, id: &str) -> Result<SmartPlaylist> {
+    query_as::<_, SmartPlaylistRow>("SELECT * FROM smart_playlists WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .map(SmartPlaylist::from)
+        .ok_or(DatabaseSmartPlaylistError::NotFound.into())
+}
+
+pub async fn create_smart_playlist(
+    connection: &mut Connection,
+    playlist: NewSmartPlaylist,
+) -> Result<SmartPlaylist> {
+    validate(&playlist)?;
+
+    let playlist = SmartPlaylist {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: playlist.name.trim().to_string(),
+        filter: playlist.filter,
+        created_at: Some(OffsetDateTime::now_utc()),
+        updated_at: None,
+    };
+
+    query("INSERT INTO smart_playlists (id, name, filter, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&playlist.id)
+        .bind(&playlist.name)
+        .bind(Json(&playlist.filter))
+        .bind(playlist.created_at)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(playlist)
+}
+
+/// Replaces the name and filter of the playlist.
+pub async fn update_smart_playlist(
+    connection: &mut Connection,
+    id: &str,
+    playlist: NewSmartPlaylist,
+) -> Result<SmartPlaylist> {
+    validate(&playlist)?;
+
+    query_as::<_, SmartPlaylistRow>(
+        "UPDATE smart_playlists SET name = ?, filter = ?, updated_at = ? WHERE id = ? RETURNING *",
+    )
+    .bind(playlist.name.trim())
+    .bind(Json(&playlist.filter))
+    .bind(OffsetDateTime::now_utc())
+    .bind(id)
+    .fetch_optional(&mut *connection)
+    .await?
+    .map(SmartPlaylist::from)
+    .ok_or(DatabaseSmartPlaylistError::NotFound.into())
+}
+
+pub async fn delete_smart_playlist(connection: &mut Connection, id: &str) -> Result<()> {
+    let rows_affected = query("DELETE FROM smart_playlists WHERE id = ?")
+        .bind(id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+    if rows_affected == 0 {
+        Err(DatabaseSmartPlaylistError::NotFound.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the songs currently matching the filter of the playlist.
+pub async fn get_smart_playlist_songs(
+    connection: &mut Connection,
+    id: &str,
+    query: SongQuery,
+) -> Result<Page<Song>> {
+    let playlist = get_smart_playlist(connection, id).await?;
+
+    songs::get_songs_matching(connection, &playlist.filter, query).await
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{
+        DatabaseError, FilterField, FilterOperator, FilterValue, test_utils::pool_with_songs,
+    };
+
+    fn rule(field: FilterField, op: FilterOperator, value: Option<FilterValue>) -> SongFilter {
+        SongFilter::Rule { field, op, value }
+    }
+
+    #[test(tokio::test)]
+    async fn test_smart_playlist_songs() {
+        let pool = pool_with_songs(&["Blue in Green", "So What", "Freddie", "Noise"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        query("UPDATE songs SET genre = 'Jazz', year = ?, year_num = ? WHERE title != 'Noise'")
+            .bind("1959")
+            .bind(1959)
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        query("UPDATE songs SET year = '1995-04-01', year_num = 1995 WHERE title = 'Freddie'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        query("UPDATE songs SET play_count = 3, last_played_at = ? WHERE title = 'So What'")
+            .bind(OffsetDateTime::now_utc() - time::Duration::days(40))
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let playlist = create_smart_playlist(
+            &mut connection,
+            NewSmartPlaylist {
+                name: String::from(" Jazz "),
+                filter: SongFilter::All {
+                    filters: vec![
+                        rule(
+                            FilterField::Genre,
+                            FilterOperator::Is,
+                            Some(FilterValue::Text(String::from("jazz"))),
+                        ),
+                        SongFilter::Any {
+                            filters: vec![
+                                rule(
+                                    FilterField::Year,
+                                    FilterOperator::GreaterOrEqual,
+                                    Some(FilterValue::Number(1990)),
+                                ),
+                                rule(
+                                    FilterField::LastPlayedAt,
+                                    FilterOperator::NotInLastDays,
+                                    Some(FilterValue::Number(30)),
+                                ),
+                            ],
+                        },
+                    ],
+                },
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(playlist.name, "Jazz");
+
+        let titles = |page: Page<Song>| {
+            page.items
+                .into_iter()
+                .map(|song| song.title.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Never played counts as not played recently, only a recent play would leave it out.
+        let page = get_smart_playlist_songs(&mut connection, &playlist.id, SongQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(titles(page), ["Blue in Green", "Freddie", "So What"]);
+
+        let playlist = update_smart_playlist(
+            &mut connection,
+            &playlist.id,
+            NewSmartPlaylist {
+                name: String::from("Unplayed"),
+                filter: rule(
+                    FilterField::PlayCount,
+                    FilterOperator::Is,
+                    Some(FilterValue::Number(0)),
+                ),
+            },
+        )
+        .await
+        .unwrap();
+        let page = get_smart_playlist_songs(&mut connection, &playlist.id, SongQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+
+        delete_smart_playlist(&mut connection, &playlist.id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            get_smart_playlist(&mut connection, &playlist.id).await,
+            Err(DatabaseError::SmartPlaylist(
+                DatabaseSmartPlaylistError::NotFound
+            ))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_invalid_filters_are_rejected() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let mut nested = rule(FilterField::Title, FilterOperator::IsSet, None);
+        for _ in 0..songs::MAX_FILTER_DEPTH + 1 {
+            nested = SongFilter::All {
+                filters: vec![nested],
+            };
+        }
+
+        for filter in [
+            rule(
+                FilterField::PlayCount,
+                FilterOperator::Contains,
+                Some(FilterValue::Number(1)),
+            ),
+            rule(
+                FilterField::Year,
+                FilterOperator::GreaterThan,
+                Some(FilterValue::Text(String::from("1990"))),
+            ),
+            rule(FilterField::AddedAt, FilterOperator::InLastDays, None),
+            rule(
+                FilterField::Title,
+                FilterOperator::IsSet,
+                Some(FilterValue::Number(1)),
+            ),
+            SongFilter::Any {
+                filters: Vec::new(),
+            },
+            nested,
+        ] {
+            let result = create_smart_playlist(
+                &mut connection,
+                NewSmartPlaylist {
+                    name: String::from("Invalid"),
+                    filter,
+                },
+            )
+            .await;
+
+            assert!(
+                matches!(
+                    result,
+                    Err(DatabaseError::Song(
+                        songs::DatabaseSongError::InvalidFilter(_)
+                    ))
+                ),
+                "{result:?}"
+            );
+        }
+
+        assert!(
+            get_smart_playlists(&mut connection)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
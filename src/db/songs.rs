@@ -1,13 +1,25 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashSet},
     path::{MAIN_SEPARATOR, Path, PathBuf},
 };
 
-use sqlx::{query, query_as, query_scalar};
-use time::OffsetDateTime;
+use sqlx::{Connection as _, QueryBuilder, Sqlite, query, query_as, query_scalar};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    loudness::ReplayGain,
+    metadata::{
+        AudioProperties,
+        item::ItemKey,
+        position::{Position, parse_count},
+    },
+};
 
 use super::{
-    Album, Connection, DatabaseError, Directory, NewSong, Result, Song, UpdatedSong, directories,
+    Album, BulkAddResult, Connection, CueRange, DatabaseError, Directory, FilterField,
+    FilterFieldKind, FilterOperator, FilterValue, NewSong, Page, Result, Song, SongFilter,
+    SongQuery, UpdatedSong, UpdatedSongPreferences, directories, parse_year, song_album_id,
+    song_column,
 };
 
 #[non_exhaustive]
@@ -25,9 +37,48 @@ pub enum DatabaseSongError {
     PathNotFound,
     #[error("Song path doesn't contain directory")]
     PathDoesntContainDirectory,
+    #[error("Playback must start before it ends")]
+    InvalidPlaybackRange,
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+}
+
+/// Most variables SQLite binds in a single statement, batched queries are split to stay under it.
+const MAX_VARIABLES: usize = 32_766;
+
+/// A song added by [`add_songs`], with the range of its track if it's from a cue sheet and the
+/// audio properties read from its file.
+pub struct SongInsert {
+    pub song: NewSong,
+    pub range: Option<CueRange>,
+    pub properties: Option<AudioProperties>,
+}
+
+/// A song changed by [`update_songs`], properties that weren't read are left as they are.
+pub struct SongUpdate {
+    pub id: String,
+    pub song: UpdatedSong,
+    pub properties: Option<AudioProperties>,
 }
 
 pub async fn add_song(connection: &mut Connection, song: NewSong) -> Result<Song> {
+    insert_song(connection, song, None).await
+}
+
+/// Adds a track of a cue sheet, every track of the sheet shares the path of the file.
+pub async fn add_cue_song(
+    connection: &mut Connection,
+    song: NewSong,
+    range: CueRange,
+) -> Result<Song> {
+    insert_song(connection, song, Some(range)).await
+}
+
+async fn insert_song(
+    connection: &mut Connection,
+    song: NewSong,
+    range: Option<CueRange>,
+) -> Result<Song> {
     let uuid = uuid::Uuid::new_v4().to_string();
 
     let NewSong {
@@ -36,40 +87,61 @@ pub async fn add_song(connection: &mut Connection, song: NewSong) -> Result<Song
         album,
         album_artist,
         disc_number,
+        disc_total,
         artist,
         year,
         track_number,
+        track_total,
         genre,
         mood,
+        composer,
+        comment,
+        rating,
+        lyrics,
         file_created_at,
     } = song;
 
-    let (directory_id, _) =
-        sqlx::query_as::<_, (String, String)>("SELECT name, path FROM directories")
-            .fetch_all(&mut *connection)
-            .await?
-            .into_iter()
-            .find(|(_, directory)| path.starts_with(directory))
-            .ok_or(DatabaseSongError::PathNotFound)?;
+    let directories = sqlx::query_as::<_, (String, String)>("SELECT name, path FROM directories")
+        .fetch_all(&mut *connection)
+        .await?;
+    let directory_id = directories::find_directory_from_sub_path(&directories, &path)
+        .map(|(name, _)| name.clone())
+        .ok_or(DatabaseSongError::PathNotFound)?;
 
     let added_at = Some(OffsetDateTime::now_utc());
-    let _ = query!(
-        "INSERT INTO songs (id, path, title, album, album_artist, disc_number, artist, year, track_number, genre, mood, added_at, file_created_at, directory_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-        uuid,
-        path,
-        title,
-        album,
-        album_artist,
-        disc_number,
-        artist,
-        year,
-        track_number,
-        genre,
-        mood,
-        added_at,
-        file_created_at,
-        directory_id
+    let year_num = year.as_deref().and_then(parse_year);
+    let (cue_path, start_ms, end_ms) = range
+        .map(|range| (Some(range.cue_path), range.start_ms, range.end_ms))
+        .unwrap_or_default();
+
+    query(
+        "INSERT INTO songs (id, path, title, album, album_artist, album_id, disc_number, disc_total, artist, year, year_num, track_number, track_total, genre, mood, composer, comment, rating, lyrics, added_at, file_created_at, directory_id, cue_path, start_ms, end_ms) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
+    .bind(&uuid)
+    .bind(&path)
+    .bind(&title)
+    .bind(&album)
+    .bind(&album_artist)
+    .bind(song_album_id(album.as_deref(), album_artist.as_deref()))
+    .bind(disc_number)
+    .bind(disc_total)
+    .bind(&artist)
+    .bind(&year)
+    .bind(year_num)
+    .bind(track_number)
+    .bind(track_total)
+    .bind(&genre)
+    .bind(&mood)
+    .bind(&composer)
+    .bind(&comment)
+    .bind(rating)
+    .bind(&lyrics)
+    .bind(added_at)
+    .bind(file_created_at)
+    .bind(&directory_id)
+    .bind(&cue_path)
+    .bind(start_ms)
+    .bind(end_ms)
     .execute(&mut *connection)
     .await?;
 
@@ -80,20 +152,108 @@ pub async fn add_song(connection: &mut Connection, song: NewSong) -> Result<Song
         album,
         album_artist,
         disc_number,
+        disc_total,
         artist,
         year,
+        year_num,
         track_number,
+        track_total,
         genre,
         mood,
+        composer,
+        comment,
+        rating,
+        lyrics,
         added_at,
         file_created_at,
         directory_id,
+        cue_path,
+        start_ms,
+        end_ms,
         ..Default::default()
     })
 }
 
+/// Adds the songs with a single `INSERT` for as many of them as SQLite can bind at once.
+///
+/// Songs that aren't inside any of the directories are left out, returns the number of songs
+/// added.
+pub async fn add_songs(connection: &mut Connection, songs: &[SongInsert]) -> Result<u64> {
+    const COLUMNS: usize = 31;
+
+    let directories = query_as::<_, (String, String)>("SELECT name, path FROM directories")
+        .fetch_all(&mut *connection)
+        .await?;
+    let added_at = OffsetDateTime::now_utc();
+
+    let rows = songs
+        .iter()
+        .filter_map(|insert| {
+            directories::find_directory_from_sub_path(&directories, &insert.song.path)
+                .map(|(directory_id, _)| (directory_id, insert))
+        })
+        .collect::<Vec<_>>();
+
+    let mut added = 0;
+    for chunk in rows.chunks(MAX_VARIABLES / COLUMNS) {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "INSERT INTO songs (id, path, title, album, album_artist, album_id, disc_number, disc_total, artist, year, year_num, track_number, track_total, genre, mood, composer, comment, rating, lyrics, added_at, file_created_at, directory_id, cue_path, start_ms, end_ms, duration_ms, bitrate_kbps, sample_rate, channels, cover_art_bytes, properties_read) ",
+        );
+
+        builder.push_values(chunk, |mut row, (directory_id, insert)| {
+            let song = &insert.song;
+            let range = insert.range.as_ref();
+            let properties = insert.properties.unwrap_or_default();
+
+            row.push_bind(uuid::Uuid::new_v4().to_string())
+                .push_bind(&song.path)
+                .push_bind(&song.title)
+                .push_bind(&song.album)
+                .push_bind(&song.album_artist)
+                .push_bind(song_album_id(
+                    song.album.as_deref(),
+                    song.album_artist.as_deref(),
+                ))
+                .push_bind(song.disc_number)
+                .push_bind(song.disc_total)
+                .push_bind(&song.artist)
+                .push_bind(&song.year)
+                .push_bind(song.year.as_deref().and_then(parse_year))
+                .push_bind(song.track_number)
+                .push_bind(song.track_total)
+                .push_bind(&song.genre)
+                .push_bind(&song.mood)
+                .push_bind(&song.composer)
+                .push_bind(&song.comment)
+                .push_bind(song.rating)
+                .push_bind(&song.lyrics)
+                .push_bind(added_at)
+                .push_bind(song.file_created_at)
+                .push_bind(*directory_id)
+                .push_bind(range.map(|range| &range.cue_path))
+                .push_bind(range.map_or(0, |range| range.start_ms))
+                .push_bind(range.and_then(|range| range.end_ms))
+                .push_bind(properties.duration_ms)
+                .push_bind(properties.bitrate_kbps)
+                .push_bind(properties.sample_rate)
+                .push_bind(properties.channels)
+                .push_bind(properties.cover_art_bytes)
+                .push_bind(insert.properties.is_some());
+        });
+
+        added += builder
+            .build()
+            .execute(&mut *connection)
+            .await?
+            .rows_affected();
+    }
+
+    Ok(added)
+}
+
 pub async fn get_song(connection: &mut Connection, id: &str) -> Result<Song> {
-    query_as!(Song, "SELECT * FROM songs WHERE id = ?", id)
+    query_as::<_, Song>("SELECT * FROM songs WHERE id = ?")
+        .bind(id)
         .fetch_one(&mut *connection)
         .await
         .map_err(|err| match err {
@@ -107,14 +267,393 @@ pub async fn get_song_path(connection: &mut Connection, id: &str) -> Result<Path
         .fetch_one(&mut *connection)
         .await
         .map(PathBuf::from)
-        .map_err(DatabaseError::from)
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => DatabaseSongError::SongNotFound.into(),
+            _ => err.into(),
+        })
 }
 
-pub async fn get_songs(pool: &sqlx::Pool<sqlx::Sqlite>) -> Result<Vec<Song>> {
-    query_as!(Song, "SELECT * FROM songs")
-        .fetch_all(pool)
-        .await
-        .map_err(DatabaseError::from)
+/// Fetches a page of songs sorted by the requested column, leaving out missing songs unless
+/// they're requested.
+///
+/// Sorting falls back to the song id so rows are never skipped or repeated between pages.
+pub async fn get_songs_paginated(
+    connection: &mut Connection,
+    query: SongQuery,
+) -> Result<Page<Song>> {
+    query_songs(connection, query, None).await
+}
+
+/// Returns the songs matching the filter, evaluated against the library as it is now, so relative
+/// dates count back from the time of the call.
+pub async fn get_songs_matching(
+    connection: &mut Connection,
+    filter: &SongFilter,
+    query: SongQuery,
+) -> Result<Page<Song>> {
+    validate_filter(filter)?;
+
+    query_songs(connection, query, Some(filter)).await
+}
+
+async fn query_songs(
+    connection: &mut Connection,
+    query: SongQuery,
+    song_filter: Option<&SongFilter>,
+) -> Result<Page<Song>> {
+    let now = OffsetDateTime::now_utc();
+    let filter = |builder: &mut QueryBuilder<'_, Sqlite>| {
+        builder
+            .push(" WHERE (")
+            .push_bind(query.include_missing)
+            .push(" OR missing_since IS NULL)");
+
+        if let Some(song_filter) = song_filter {
+            builder.push(" AND ");
+            push_filter(builder, song_filter, now);
+        }
+
+        if let Some(min_play_count) = query.min_play_count {
+            builder
+                .push(" AND play_count >= ")
+                .push_bind(min_play_count);
+        }
+
+        if let Some(min_rating) = query.min_rating {
+            builder.push(" AND rating >= ").push_bind(min_rating);
+        }
+
+        if let Some(played_since) = query.played_since {
+            builder
+                .push(" AND last_played_at >= ")
+                .push_bind(played_since);
+        }
+
+        if let Some(shuffleable) = query.shuffleable {
+            builder
+                .push(" AND exclude_from_shuffle != ")
+                .push_bind(shuffleable);
+        }
+
+        if let Some(year_min) = query.year_min {
+            builder.push(" AND year_num >= ").push_bind(year_min);
+        }
+
+        if let Some(year_max) = query.year_max {
+            builder.push(" AND year_num <= ").push_bind(year_max);
+        }
+    };
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM songs");
+    filter(&mut count);
+    let total = count
+        .build_query_scalar::<i64>()
+        .fetch_one(&mut *connection)
+        .await?;
+
+    let order = query.order.as_sql();
+    let mut select = QueryBuilder::new("SELECT * FROM songs");
+    filter(&mut select);
+    select
+        .push(format!(
+            " ORDER BY {} {order}, id {order} LIMIT ",
+            query.sort_by.as_sql()
+        ))
+        .push_bind(query.limit.map(i64::from).unwrap_or(-1))
+        .push(" OFFSET ")
+        .push_bind(query.offset.unwrap_or_default());
+
+    let items = select
+        .build_query_as::<Song>()
+        .fetch_all(&mut *connection)
+        .await?;
+
+    Ok(Page { items, total })
+}
+
+/// Groups can't be nested deeper than this, which keeps the queries filters compile to small.
+pub const MAX_FILTER_DEPTH: usize = 8;
+
+/// Checks that every rule of the filter compares a field with an operator and value that fit it,
+/// so a filter that was saved can always be evaluated.
+pub fn validate_filter(filter: &SongFilter) -> Result<()> {
+    check_filter(filter, 1).map_err(|message| DatabaseSongError::InvalidFilter(message).into())
+}
+
+fn check_filter(filter: &SongFilter, depth: usize) -> Result<(), String> {
+    match filter {
+        SongFilter::All { filters } | SongFilter::Any { filters } => {
+            if depth > MAX_FILTER_DEPTH {
+                return Err(format!(
+                    "Groups can't be nested more than {MAX_FILTER_DEPTH} deep"
+                ));
+            }
+
+            if filters.is_empty() {
+                return Err(String::from("Groups need at least one filter"));
+            }
+
+            filters
+                .iter()
+                .try_for_each(|filter| check_filter(filter, depth + 1))
+        }
+        SongFilter::Rule { field, op, value } => check_rule(*field, *op, value.as_ref()),
+    }
+}
+
+fn check_rule(
+    field: FilterField,
+    op: FilterOperator,
+    value: Option<&FilterValue>,
+) -> Result<(), String> {
+    use FilterOperator::*;
+
+    // Dates are compared in days, so every operator takes either text, a number or nothing.
+    let takes = match (field.kind(), op) {
+        (_, IsSet | IsNotSet) => None,
+        (FilterFieldKind::Text, Is | IsNot | Contains | NotContains | StartsWith) => {
+            Some(FilterFieldKind::Text)
+        }
+        (
+            FilterFieldKind::Number,
+            Is | IsNot | GreaterThan | GreaterOrEqual | LessThan | LessOrEqual,
+        )
+        | (FilterFieldKind::Date, InLastDays | NotInLastDays) => Some(FilterFieldKind::Number),
+        _ => {
+            return Err(format!(
+                "\"{}\" can't be used on \"{}\"",
+                name_of(op),
+                name_of(field)
+            ));
+        }
+    };
+
+    match (takes, value) {
+        (None, None)
+        | (Some(FilterFieldKind::Text), Some(FilterValue::Text(_)))
+        | (Some(FilterFieldKind::Number), Some(FilterValue::Number(_))) => Ok(()),
+        (None, Some(_)) => Err(format!("\"{}\" doesn't take a value", name_of(op))),
+        (Some(FilterFieldKind::Text), _) => Err(format!(
+            "\"{}\" of \"{}\" needs text to compare with",
+            name_of(op),
+            name_of(field)
+        )),
+        (Some(_), _) => Err(format!(
+            "\"{}\" of \"{}\" needs a number to compare with",
+            name_of(op),
+            name_of(field)
+        )),
+    }
+}
+
+/// Returns the name a field or operator is written as in a filter.
+fn name_of(value: impl serde::Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Compiles the filter into a condition on the `songs` table, every value is bound as a
+/// parameter. The filter has to be validated first.
+fn push_filter(builder: &mut QueryBuilder<'_, Sqlite>, filter: &SongFilter, now: OffsetDateTime) {
+    let (filters, separator) = match filter {
+        SongFilter::All { filters } => (filters, " AND "),
+        SongFilter::Any { filters } => (filters, " OR "),
+        SongFilter::Rule { field, op, value } => {
+            push_rule(builder, *field, *op, value.clone(), now);
+            return;
+        }
+    };
+
+    builder.push("(");
+    for (index, filter) in filters.iter().enumerate() {
+        if index > 0 {
+            builder.push(separator);
+        }
+        push_filter(builder, filter, now);
+    }
+    builder.push(")");
+}
+
+fn push_rule(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    field: FilterField,
+    op: FilterOperator,
+    value: Option<FilterValue>,
+    now: OffsetDateTime,
+) {
+    use FilterOperator::*;
+
+    let column = field.as_sql();
+    let text = |value: Option<FilterValue>| match value {
+        Some(FilterValue::Text(text)) => text,
+        _ => unreachable!("Filter should be validated"),
+    };
+    let number = |value: Option<FilterValue>| match value {
+        Some(FilterValue::Number(number)) => number,
+        _ => unreachable!("Filter should be validated"),
+    };
+
+    match (field.kind(), op) {
+        (_, IsSet) => {
+            builder.push(format!("{column} IS NOT NULL"));
+        }
+        (_, IsNotSet) => {
+            builder.push(format!("{column} IS NULL"));
+        }
+        (FilterFieldKind::Text, Is) => {
+            builder
+                .push(format!("{column} = "))
+                .push_bind(text(value))
+                .push(" COLLATE NOCASE");
+        }
+        (FilterFieldKind::Text, IsNot) => {
+            builder
+                .push(format!("({column} IS NULL OR {column} != "))
+                .push_bind(text(value))
+                .push(" COLLATE NOCASE)");
+        }
+        (FilterFieldKind::Text, Contains) => {
+            builder
+                .push(format!("instr(lower({column}), lower("))
+                .push_bind(text(value))
+                .push(")) > 0");
+        }
+        (FilterFieldKind::Text, NotContains) => {
+            builder
+                .push(format!(
+                    "({column} IS NULL OR instr(lower({column}), lower("
+                ))
+                .push_bind(text(value))
+                .push(")) = 0)");
+        }
+        (FilterFieldKind::Text, StartsWith) => {
+            builder
+                .push(format!("instr(lower({column}), lower("))
+                .push_bind(text(value))
+                .push(")) = 1");
+        }
+        (_, IsNot) => {
+            builder
+                .push(format!("({column} IS NULL OR {column} != "))
+                .push_bind(number(value))
+                .push(")");
+        }
+        (_, InLastDays) => {
+            builder
+                .push(format!("{column} >= "))
+                .push_bind(now - Duration::days(i64::from(number(value))));
+        }
+        (_, NotInLastDays) => {
+            builder
+                .push(format!("({column} IS NULL OR {column} < "))
+                .push_bind(now - Duration::days(i64::from(number(value))))
+                .push(")");
+        }
+        (_, op) => {
+            let operator = match op {
+                GreaterThan => ">",
+                GreaterOrEqual => ">=",
+                LessThan => "<",
+                LessOrEqual => "<=",
+                _ => "=",
+            };
+
+            builder
+                .push(format!("{column} {operator} "))
+                .push_bind(number(value));
+        }
+    }
+}
+
+/// Changes how the song is played and returns it, rejecting offsets that would end playback
+/// before it starts.
+pub async fn update_song_preferences(
+    connection: &mut Connection,
+    id: &str,
+    preferences: UpdatedSongPreferences,
+) -> Result<Song> {
+    let song = get_song(connection, id).await?;
+
+    let exclude_from_shuffle = preferences
+        .exclude_from_shuffle
+        .unwrap_or(song.exclude_from_shuffle);
+    let start_offset_ms = preferences.start_offset_ms.unwrap_or(song.start_offset_ms);
+    let end_offset_ms = preferences.end_offset_ms.unwrap_or(song.end_offset_ms);
+
+    if let Some(end_offset_ms) = end_offset_ms
+        && end_offset_ms <= start_offset_ms.unwrap_or_default()
+    {
+        return Err(DatabaseSongError::InvalidPlaybackRange.into());
+    }
+
+    query_as::<_, Song>(
+        "UPDATE songs SET exclude_from_shuffle = ?, start_offset_ms = ?, end_offset_ms = ? WHERE id = ? RETURNING *",
+    )
+    .bind(exclude_from_shuffle)
+    .bind(start_offset_ms)
+    .bind(end_offset_ms)
+    .bind(id)
+    .fetch_optional(&mut *connection)
+    .await?
+    .ok_or_else(|| DatabaseSongError::SongNotFound.into())
+}
+
+/// Reports stopped before this far into a song count as skips, songs shorter than twice as long
+/// are skipped before their halfway point instead.
+pub const SKIP_THRESHOLD_MS: u32 = 30_000;
+
+/// Records that the song was played, as a skip if it was stopped at `position_ms` before
+/// [`SKIP_THRESHOLD_MS`], and returns it with its updated counters.
+///
+/// The counters are incremented by the database, so reports arriving at the same time are all
+/// counted.
+pub async fn record_play(
+    connection: &mut Connection,
+    id: &str,
+    position_ms: Option<u32>,
+    played_at: OffsetDateTime,
+) -> Result<Song> {
+    let duration_ms = query_scalar::<_, Option<u32>>("SELECT duration_ms FROM songs WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .ok_or(DatabaseSongError::SongNotFound)?;
+
+    let threshold_ms = duration_ms.map_or(SKIP_THRESHOLD_MS, |duration_ms| {
+        SKIP_THRESHOLD_MS.min(duration_ms / 2)
+    });
+    let skipped = position_ms.is_some_and(|position_ms| position_ms < threshold_ms);
+
+    let mut transaction = connection.begin().await?;
+
+    let song = if skipped {
+        query_as::<_, Song>("UPDATE songs SET skip_count = skip_count + 1 WHERE id = ? RETURNING *")
+            .bind(id)
+            .fetch_optional(&mut *transaction)
+            .await?
+    } else {
+        query_as::<_, Song>(
+            "UPDATE songs SET play_count = play_count + 1, last_played_at = ? WHERE id = ? RETURNING *",
+        )
+        .bind(played_at)
+        .bind(id)
+        .fetch_optional(&mut *transaction)
+        .await?
+    }
+    .ok_or(DatabaseSongError::SongNotFound)?;
+
+    query("INSERT INTO plays (song_id, played_at, skipped) VALUES (?, ?, ?)")
+        .bind(id)
+        .bind(played_at)
+        .bind(skipped)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(song)
 }
 
 pub async fn delete_song(connection: &mut Connection, id: &str) -> Result<()> {
@@ -130,20 +669,514 @@ pub async fn delete_song(connection: &mut Connection, id: &str) -> Result<()> {
     }
 }
 
+/// Deletes the songs, returns the number of songs deleted.
+pub async fn delete_songs(connection: &mut Connection, ids: &[String]) -> Result<u64> {
+    let ids = serde_json::to_string(ids).map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    Ok(
+        query("DELETE FROM songs WHERE id IN (SELECT value FROM json_each(?))")
+            .bind(ids)
+            .execute(&mut *connection)
+            .await?
+            .rows_affected(),
+    )
+}
+
+pub async fn get_song_id_by_path(
+    connection: &mut Connection,
+    path: &str,
+) -> Result<Option<String>> {
+    query_scalar::<_, String>("SELECT id FROM songs WHERE path = ?")
+        .bind(path)
+        .fetch_optional(&mut *connection)
+        .await
+        .map_err(DatabaseError::from)
+}
+
+/// Finds the song a path from a playlist file refers to.
+///
+/// Absolute paths are matched exactly and relative paths are tried against every base directory,
+/// falling back to the first song with the same file name.
+pub async fn resolve_song_path(
+    connection: &mut Connection,
+    path: &str,
+    base_directories: &[PathBuf],
+) -> Result<Option<String>> {
+    let path = Path::new(path);
+    let candidates = if path.is_absolute() {
+        vec![path.to_path_buf()]
+    } else {
+        base_directories
+            .iter()
+            .map(|directory| directory.join(path))
+            .collect()
+    };
+
+    for candidate in candidates {
+        if let Some(id) = get_song_id_by_path(connection, &candidate.to_string_lossy()).await? {
+            return Ok(Some(id));
+        }
+    }
+
+    let Some(file_name) = path.file_name() else {
+        return Ok(None);
+    };
+
+    let suffix = format!("{MAIN_SEPARATOR}{}", file_name.to_string_lossy());
+    query_scalar::<_, String>(
+        "SELECT id FROM songs WHERE substr(path, -length(?)) = ? ORDER BY path LIMIT 1",
+    )
+    .bind(&suffix)
+    .bind(&suffix)
+    .fetch_optional(&mut *connection)
+    .await
+    .map_err(DatabaseError::from)
+}
+
+/// Returns the albums of the song at `path`, or of every song inside of it if `path` is a
+/// directory.
+pub async fn get_albums_by_path(connection: &mut Connection, path: &str) -> Result<Vec<String>> {
+    let prefix = directories::directory_prefix(path);
+
+    Ok(query_scalar::<_, String>(
+        "SELECT DISTINCT album FROM songs WHERE album IS NOT NULL AND (path = ? OR substr(path, 1, length(?)) = ?)",
+    )
+    .bind(path)
+    .bind(&prefix)
+    .bind(&prefix)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Marks the song at `path` as missing since `at`, or every song inside of it if `path` was a
+/// directory. Songs that were already missing keep the time they went missing.
+///
+/// Returns the number of songs that went missing.
+pub async fn mark_songs_missing_by_path(
+    connection: &mut Connection,
+    path: &str,
+    at: OffsetDateTime,
+) -> Result<u64> {
+    let prefix = directories::directory_prefix(path);
+
+    Ok(query(
+        "UPDATE songs SET missing_since = ? WHERE missing_since IS NULL AND (path = ? OR substr(path, 1, length(?)) = ?)",
+    )
+    .bind(at)
+    .bind(path)
+    .bind(&prefix)
+    .bind(&prefix)
+    .execute(&mut *connection)
+    .await?
+    .rows_affected())
+}
+
+/// Marks the songs as missing since `at`, songs that were already missing keep the time they
+/// went missing.
+pub async fn mark_songs_missing(
+    connection: &mut Connection,
+    ids: &[String],
+    at: OffsetDateTime,
+) -> Result<u64> {
+    let ids = serde_json::to_string(ids).map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    Ok(query(
+        "UPDATE songs SET missing_since = ? WHERE missing_since IS NULL AND id IN (SELECT value FROM json_each(?))",
+    )
+    .bind(at)
+    .bind(ids)
+    .execute(&mut *connection)
+    .await?
+    .rows_affected())
+}
+
+/// Clears the missing marker of the songs, once their files are back.
+pub async fn restore_missing_songs(connection: &mut Connection, ids: &[String]) -> Result<u64> {
+    let ids = serde_json::to_string(ids).map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    Ok(
+        query("UPDATE songs SET missing_since = NULL WHERE id IN (SELECT value FROM json_each(?))")
+            .bind(ids)
+            .execute(&mut *connection)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Deletes the songs that went missing before `before`, or every missing song if not given.
+///
+/// Returns the deleted songs.
+pub async fn purge_missing_songs(
+    connection: &mut Connection,
+    before: Option<OffsetDateTime>,
+) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "DELETE FROM songs WHERE missing_since IS NOT NULL AND (?1 IS NULL OR missing_since < ?1) RETURNING *",
+    )
+    .bind(before)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Returns the ids in `ids` that exist in the database, using a single query.
+pub async fn get_existing_song_ids(
+    connection: &mut Connection,
+    ids: &[String],
+) -> Result<HashSet<String>> {
+    let ids = serde_json::to_string(ids).map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    Ok(query_scalar::<_, String>(
+        "SELECT id FROM songs WHERE id IN (SELECT value FROM json_each(?))",
+    )
+    .bind(ids)
+    .fetch_all(&mut *connection)
+    .await?
+    .into_iter()
+    .collect())
+}
+
+/// Returns the songs on disk titled `title`, ignoring case, which an incoming file with that title
+/// might be a duplicate of.
+pub async fn get_songs_titled(connection: &mut Connection, title: &str) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE title = trim(?) COLLATE NOCASE AND missing_since IS NULL ORDER BY added_at, id",
+    )
+    .bind(title)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Returns every titled song on disk, grouped by title so duplicates are next to each other.
+pub async fn get_titled_songs(connection: &mut Connection) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE title IS NOT NULL AND missing_since IS NULL ORDER BY title COLLATE NOCASE, added_at, id",
+    )
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+pub async fn get_favorites(connection: &mut Connection) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT songs.* FROM favorites JOIN songs ON songs.id = favorites.song_id ORDER BY favorites.added_at",
+    )
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Returns the most recently added songs, newest first.
+pub async fn get_recently_added(connection: &mut Connection, limit: u32) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE added_at IS NOT NULL AND missing_since IS NULL ORDER BY added_at DESC LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Returns the songs played the most, ties going to the one played last.
+pub async fn get_most_played(connection: &mut Connection, limit: u32) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE play_count > 0 AND missing_since IS NULL ORDER BY play_count DESC, last_played_at DESC, id LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+pub async fn get_random_songs(connection: &mut Connection, limit: u32) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE missing_since IS NULL AND NOT exclude_from_shuffle ORDER BY RANDOM() LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Marks every song in `ids` as a favorite in a single transaction.
+pub async fn add_favorites(connection: &mut Connection, ids: &[String]) -> Result<BulkAddResult> {
+    let mut transaction = sqlx::Connection::begin(&mut *connection).await?;
+
+    let existing = get_existing_song_ids(&mut *transaction, ids).await?;
+    let mut favorites = query_scalar::<_, String>("SELECT song_id FROM favorites")
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .collect::<HashSet<String>>();
+
+    let added_at = OffsetDateTime::now_utc();
+    let mut result = BulkAddResult::default();
+
+    for id in ids {
+        if !existing.contains(id) {
+            result.unknown += 1;
+            continue;
+        }
+
+        if !favorites.insert(id.clone()) {
+            result.duplicate += 1;
+            continue;
+        }
+
+        query("INSERT INTO favorites (song_id, added_at) VALUES (?, ?)")
+            .bind(id)
+            .bind(added_at)
+            .execute(&mut *transaction)
+            .await?;
+
+        result.added += 1;
+    }
+
+    transaction.commit().await?;
+
+    Ok(result)
+}
+
 pub async fn update_song(connection: &mut Connection, id: &str, song: UpdatedSong) -> Result<()> {
-    let _ = query!(
-        "UPDATE songs SET title = ?, album = ?, album_artist = ?, disc_number = ?, artist = ?, year = ?, track_number = ?, genre = ?, mood = ? WHERE id = ?",
-        song.title,
-        song.album,
-        song.album_artist,
-        song.disc_number,
-        song.artist,
-        song.year,
-        song.track_number,
-        song.genre,
-        song.mood,
-        id
+    let year_num = song.year.as_deref().and_then(parse_year);
+    let album_id = song_album_id(song.album.as_deref(), song.album_artist.as_deref());
+
+    query(
+        "UPDATE songs SET title = ?, album = ?, album_artist = ?, album_id = ?, disc_number = ?, disc_total = ?, artist = ?, year = ?, year_num = ?, track_number = ?, track_total = ?, genre = ?, mood = ?, composer = ?, comment = ?, rating = CASE WHEN ? THEN ? ELSE rating END, lyrics = ? WHERE id = ?",
     )
+    .bind(song.title)
+    .bind(song.album)
+    .bind(song.album_artist)
+    .bind(album_id)
+    .bind(song.disc_number)
+    .bind(song.disc_total)
+    .bind(song.artist)
+    .bind(song.year)
+    .bind(year_num)
+    .bind(song.track_number)
+    .bind(song.track_total)
+    .bind(song.genre)
+    .bind(song.mood)
+    .bind(song.composer)
+    .bind(song.comment)
+    .bind(song.rating.is_some())
+    .bind(song.rating.flatten())
+    .bind(song.lyrics)
+    .bind(id)
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
+
+/// Applies the changes with a single `UPDATE` for as many songs as SQLite can bind at once, the
+/// same way [`update_song`] and [`update_song_properties`] would one at a time.
+///
+/// Returns the number of songs updated.
+pub async fn update_songs(connection: &mut Connection, songs: &[SongUpdate]) -> Result<u64> {
+    const COLUMNS: usize = 25;
+
+    let mut updated = 0;
+    for chunk in songs.chunks(MAX_VARIABLES / COLUMNS) {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "WITH changes (id, title, album, album_artist, album_id, disc_number, disc_total, artist, year, year_num, track_number, track_total, genre, mood, composer, comment, set_rating, rating, lyrics, read_properties, duration_ms, bitrate_kbps, sample_rate, channels, cover_art_bytes) AS (",
+        );
+
+        builder.push_values(chunk, |mut row, update| {
+            let song = &update.song;
+            let properties = update.properties.unwrap_or_default();
+
+            row.push_bind(&update.id)
+                .push_bind(&song.title)
+                .push_bind(&song.album)
+                .push_bind(&song.album_artist)
+                .push_bind(song_album_id(
+                    song.album.as_deref(),
+                    song.album_artist.as_deref(),
+                ))
+                .push_bind(song.disc_number)
+                .push_bind(song.disc_total)
+                .push_bind(&song.artist)
+                .push_bind(&song.year)
+                .push_bind(song.year.as_deref().and_then(parse_year))
+                .push_bind(song.track_number)
+                .push_bind(song.track_total)
+                .push_bind(&song.genre)
+                .push_bind(&song.mood)
+                .push_bind(&song.composer)
+                .push_bind(&song.comment)
+                .push_bind(song.rating.is_some())
+                .push_bind(song.rating.flatten())
+                .push_bind(&song.lyrics)
+                .push_bind(update.properties.is_some())
+                .push_bind(properties.duration_ms)
+                .push_bind(properties.bitrate_kbps)
+                .push_bind(properties.sample_rate)
+                .push_bind(properties.channels)
+                .push_bind(properties.cover_art_bytes);
+        });
+
+        builder.push(
+            ") UPDATE songs SET title = changes.title, album = changes.album, album_artist = changes.album_artist, album_id = changes.album_id, disc_number = changes.disc_number, disc_total = changes.disc_total, artist = changes.artist, year = changes.year, year_num = changes.year_num, track_number = changes.track_number, track_total = changes.track_total, genre = changes.genre, mood = changes.mood, composer = changes.composer, comment = changes.comment, rating = CASE WHEN changes.set_rating THEN changes.rating ELSE songs.rating END, lyrics = changes.lyrics, duration_ms = CASE WHEN changes.read_properties THEN changes.duration_ms ELSE songs.duration_ms END, bitrate_kbps = CASE WHEN changes.read_properties THEN changes.bitrate_kbps ELSE songs.bitrate_kbps END, sample_rate = CASE WHEN changes.read_properties THEN changes.sample_rate ELSE songs.sample_rate END, channels = CASE WHEN changes.read_properties THEN changes.channels ELSE songs.channels END, cover_art_bytes = CASE WHEN changes.read_properties THEN changes.cover_art_bytes ELSE songs.cover_art_bytes END, properties_read = songs.properties_read OR changes.read_properties FROM changes WHERE songs.id = changes.id",
+        );
+
+        updated += builder
+            .build()
+            .execute(&mut *connection)
+            .await?
+            .rows_affected();
+    }
+
+    Ok(updated)
+}
+
+/// Sets the rating of the song, `None` removes it. See [`rating`](crate::metadata::rating) for
+/// its scale.
+pub async fn update_song_rating(
+    connection: &mut Connection,
+    id: &str,
+    rating: Option<u8>,
+) -> Result<Song> {
+    query_as::<_, Song>("UPDATE songs SET rating = ? WHERE id = ? RETURNING *")
+        .bind(rating)
+        .bind(id)
+        .fetch_optional(&mut *connection)
+        .await?
+        .ok_or_else(|| DatabaseSongError::SongNotFound.into())
+}
+
+/// Sets the fields of the song that aren't set yet, keeping the ones that are. Fields without a
+/// column are ignored, the parsed year is set along with the year and a track or disc number
+/// like `3/12` sets the total as well.
+pub async fn fill_song_fields(
+    connection: &mut Connection,
+    id: &str,
+    fields: &BTreeMap<ItemKey, String>,
+) -> Result<()> {
+    for (key, value) in fields {
+        let Some(column) = song_column(key) else {
+            continue;
+        };
+
+        match key {
+            ItemKey::Year => {
+                query("UPDATE songs SET year = ?, year_num = ? WHERE id = ? AND year IS NULL")
+                    .bind(value)
+                    .bind(parse_year(value))
+                    .bind(id)
+                    .execute(&mut *connection)
+                    .await?;
+            }
+            ItemKey::TrackNumber | ItemKey::DiscNumber => {
+                let total = if *key == ItemKey::TrackNumber {
+                    "track_total"
+                } else {
+                    "disc_total"
+                };
+                let position = Position::parse(value);
+
+                query(&format!(
+                    "UPDATE songs SET {column} = ?, {total} = coalesce({total}, ?) WHERE id = ? AND {column} IS NULL"
+                ))
+                .bind(position.number)
+                .bind(position.total)
+                .bind(id)
+                .execute(&mut *connection)
+                .await?;
+            }
+            ItemKey::TrackTotal | ItemKey::DiscTotal => {
+                query(&format!(
+                    "UPDATE songs SET {column} = ? WHERE id = ? AND {column} IS NULL"
+                ))
+                .bind(parse_count(value))
+                .bind(id)
+                .execute(&mut *connection)
+                .await?;
+            }
+            _ => {
+                query(&format!(
+                    "UPDATE songs SET {column} = ? WHERE id = ? AND {column} IS NULL"
+                ))
+                .bind(value)
+                .bind(id)
+                .execute(&mut *connection)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn update_song_properties(
+    connection: &mut Connection,
+    id: &str,
+    properties: &AudioProperties,
+) -> Result<()> {
+    query(
+        "UPDATE songs SET duration_ms = ?, bitrate_kbps = ?, sample_rate = ?, channels = ?, cover_art_bytes = ?, properties_read = TRUE WHERE id = ?",
+    )
+    .bind(properties.duration_ms)
+    .bind(properties.bitrate_kbps)
+    .bind(properties.sample_rate)
+    .bind(properties.channels)
+    .bind(properties.cover_art_bytes)
+    .bind(id)
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns up to `limit` songs whose audio properties haven't been read, ordered by id and
+/// starting after `after`. Missing songs are left out.
+pub async fn get_songs_without_properties(
+    connection: &mut Connection,
+    after: Option<&str>,
+    limit: u32,
+) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE properties_read = FALSE AND missing_since IS NULL AND (?1 IS NULL OR id > ?1) ORDER BY id LIMIT ?2",
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Returns the number of songs whose audio properties haven't been read, missing songs aren't
+/// counted.
+pub async fn count_songs_without_properties(connection: &mut Connection) -> Result<u64> {
+    let count = query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM songs WHERE properties_read = FALSE AND missing_since IS NULL",
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+
+    Ok(count as u64)
+}
+
+pub async fn update_song_lyrics(
+    connection: &mut Connection,
+    id: &str,
+    lyrics: Option<&str>,
+) -> Result<()> {
+    query("UPDATE songs SET lyrics = ? WHERE id = ?")
+        .bind(lyrics)
+        .bind(id)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn update_song_replay_gain(
+    connection: &mut Connection,
+    id: &str,
+    gain: &ReplayGain,
+) -> Result<()> {
+    query(
+        "UPDATE songs SET track_gain_db = ?, track_peak = ?, album_gain_db = ?, album_peak = ? WHERE id = ?",
+    )
+    .bind(gain.track_gain_db)
+    .bind(gain.track_peak)
+    .bind(gain.album_gain_db)
+    .bind(gain.album_peak)
+    .bind(id)
     .execute(&mut *connection)
     .await?;
 
@@ -155,47 +1188,91 @@ pub async fn update_song_path(
     song_id: &str,
     new_path: &str,
 ) -> Result<()> {
-    let previous_directory_id =
-        query_scalar!("SELECT directory_id FROM songs WHERE id = ?", song_id)
-            .fetch_one(&mut *connection)
-            .await
-            .map_err(|err| match err {
-                sqlx::Error::RowNotFound => DatabaseError::from(DatabaseSongError::SongNotFound),
-                _ => err.into(),
-            })?;
-
-    let directories = sqlx::query_as::<_, (String, String)>("SELECT path, name FROM directories")
+    let directories = sqlx::query_as::<_, (String, String)>("SELECT name, path FROM directories")
         .fetch_all(&mut *connection)
         .await?;
 
-    let new_directory_id = directories
-        .iter()
-        .find_map(|(path, id)| new_path.starts_with(path.as_str()).then_some(id))
+    let (new_directory_id, _) = directories::find_directory_from_sub_path(&directories, new_path)
         .ok_or(DatabaseSongError::PathDoesntContainDirectory)?;
 
-    if new_directory_id != &previous_directory_id {
-        let _ = query!(
-            "UPDATE songs SET directory_id = ?, path = ? WHERE id = ?",
-            new_directory_id,
-            new_path,
-            song_id
-        )
-        .execute(&mut *connection)
-        .await?;
-    } else {
-        let _ = query!("UPDATE songs SET path = ? WHERE id = ?", new_path, song_id)
+    // A song found at its new path is no longer missing.
+    let updated =
+        query("UPDATE songs SET directory_id = ?, path = ?, missing_since = NULL WHERE id = ?")
+            .bind(new_directory_id)
+            .bind(new_path)
+            .bind(song_id)
             .execute(&mut *connection)
-            .await?;
+            .await?
+            .rows_affected();
+
+    if updated == 0 {
+        return Err(DatabaseSongError::SongNotFound.into());
     }
 
     Ok(())
 }
 
-pub async fn get_album(connection: &mut Connection, title: String) -> Result<Album> {
-    let tracks = query_as!(Song, "SELECT * FROM songs WHERE album = ?", title)
+/// Points every song at the directory containing its path, preferring the most nested one.
+///
+/// Returns the amount of songs that were moved to another directory, songs outside of every
+/// directory are left as they are.
+pub async fn relink_directories(connection: &mut Connection) -> Result<u64> {
+    let directories = sqlx::query_as::<_, (String, String)>("SELECT name, path FROM directories")
         .fetch_all(&mut *connection)
         .await?;
 
+    let songs = query_as::<_, (String, String, String)>("SELECT id, path, directory_id FROM songs")
+        .fetch_all(&mut *connection)
+        .await?;
+
+    let mut updated = 0;
+
+    for (id, path, directory_id) in songs {
+        let Some((name, _)) = directories::find_directory_from_sub_path(&directories, &path) else {
+            continue;
+        };
+
+        if *name != directory_id {
+            updated += query("UPDATE songs SET directory_id = ? WHERE id = ?")
+                .bind(name)
+                .bind(&id)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+        }
+    }
+
+    Ok(updated)
+}
+
+/// Points every track of a cue sheet at the new location of the sheet.
+pub async fn update_cue_path(connection: &mut Connection, from: &str, to: &str) -> Result<()> {
+    query("UPDATE songs SET cue_path = ? WHERE cue_path = ?")
+        .bind(to)
+        .bind(from)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Whether the file at `path` is split into tracks by a cue sheet.
+pub async fn is_cue_backed(connection: &mut Connection, path: &str) -> Result<bool> {
+    Ok(query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM songs WHERE path = ? AND cue_path IS NOT NULL)",
+    )
+    .bind(path)
+    .fetch_one(&mut *connection)
+    .await?)
+}
+
+pub async fn get_album(connection: &mut Connection, title: String) -> Result<Album> {
+    let tracks =
+        query_as::<_, Song>("SELECT * FROM songs WHERE album = ? AND missing_since IS NULL")
+            .bind(&title)
+            .fetch_all(&mut *connection)
+            .await?;
+
     if tracks.is_empty() {
         return Err(DatabaseSongError::AlbumNotFound.into());
     }
@@ -205,19 +1282,889 @@ pub async fn get_album(connection: &mut Connection, title: String) -> Result<Alb
     Ok(album)
 }
 
+/// Returns the album with the [`Album::id`], the tracks are grouped like [`get_albums`] does so
+/// albums of different artists sharing a title are told apart.
+pub async fn get_album_by_id(connection: &mut Connection, id: &str) -> Result<Album> {
+    let titles = query_scalar::<_, String>(
+        "SELECT DISTINCT album FROM songs WHERE album_id = ? AND album IS NOT NULL AND missing_since IS NULL ORDER BY album",
+    )
+    .bind(id)
+    .fetch_all(&mut *connection)
+    .await?;
+
+    // Untagged tracks can have joined an album of another artist, so the id is checked again
+    // once the tracks are grouped.
+    for title in titles {
+        let tracks =
+            query_as::<_, Song>("SELECT * FROM songs WHERE album = ? AND missing_since IS NULL")
+                .bind(&title)
+                .fetch_all(&mut *connection)
+                .await?;
+
+        if let Some(album) = group_albums(tracks)
+            .into_iter()
+            .map(Album::from)
+            .find(|album| album.id == id)
+        {
+            return Ok(album);
+        }
+    }
+
+    Err(DatabaseSongError::AlbumNotFound.into())
+}
+
+/// Returns the songs of the album in the directory, any album or directory matches if not given.
+pub async fn get_filtered_songs(
+    connection: &mut Connection,
+    album: Option<&str>,
+    directory_id: Option<&str>,
+) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE (?1 IS NULL OR album = ?1) AND (?2 IS NULL OR directory_id = ?2) ORDER BY album, path",
+    )
+    .bind(album)
+    .bind(directory_id)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
 pub async fn get_albums(connection: &mut Connection) -> Result<Vec<Album>> {
-    let tracks = query_as!(Song, "SELECT * FROM songs WHERE album IS NOT NULL")
-        .fetch_all(&mut *connection)
-        .await?;
+    let tracks = query_as::<_, Song>(
+        "SELECT * FROM songs WHERE album IS NOT NULL AND missing_since IS NULL",
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+
+    Ok(group_albums(tracks).into_iter().map(Album::from).collect())
+}
 
-    let mut album_map: HashMap<String, Vec<Song>> = HashMap::new();
+/// Groups the tracks into albums by title and album artist, so albums of different artists
+/// sharing a title stay apart.
+///
+/// Tracks without an album artist join the album of the same title when there's only one with an
+/// album artist, or the one credited to the track's artist, so a few untagged tracks don't split
+/// an album. Otherwise they form an album of their own.
+pub(super) fn group_albums(tracks: Vec<Song>) -> Vec<Vec<Song>> {
+    let mut titles: BTreeMap<String, BTreeMap<Option<String>, Vec<Song>>> = BTreeMap::new();
 
     for track in tracks {
-        album_map
-            .entry(track.album.clone().expect("Album not found"))
+        let Some(title) = track.album.clone() else {
+            continue;
+        };
+
+        titles
+            .entry(title)
+            .or_default()
+            .entry(track.album_artist.clone())
             .or_default()
             .push(track);
     }
 
-    Ok(album_map.into_values().map(Album::from).collect())
+    titles
+        .into_values()
+        .flat_map(|mut albums| {
+            let untagged = albums.remove(&None).unwrap_or_default();
+            let tagged = albums.len();
+
+            for track in untagged {
+                let album_artist = if tagged == 1 {
+                    albums.keys().next().cloned().flatten()
+                } else {
+                    track
+                        .artist
+                        .clone()
+                        .filter(|artist| albums.contains_key(&Some(artist.clone())))
+                };
+
+                albums.entry(album_artist).or_default().push(track);
+            }
+
+            albums.into_values()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{SongSortColumn, SortOrder, album_id, test_utils::pool_with_songs};
+
+    #[test(tokio::test)]
+    async fn test_relink_directories() {
+        let pool = pool_with_songs(&["a"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        query("INSERT INTO directories (name, path) VALUES ('nested', '/music/nested')")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        query("INSERT INTO songs (id, path, directory_id) VALUES ('b', '/music/nested/b.mp3', 'directory')")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        assert_eq!(relink_directories(&mut connection).await.unwrap(), 1);
+        assert_eq!(relink_directories(&mut connection).await.unwrap(), 0);
+
+        let directory_id =
+            query_scalar::<_, String>("SELECT directory_id FROM songs WHERE id = 'b'")
+                .fetch_one(&mut *connection)
+                .await
+                .unwrap();
+
+        assert_eq!(directory_id, "nested");
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_songs_paginated() {
+        let pool = pool_with_songs(&["c", "a", "b", "e", "d"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                limit: Some(2),
+                offset: Some(1),
+                sort_by: SongSortColumn::Title,
+                order: SortOrder::Desc,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let titles = page
+            .items
+            .iter()
+            .map(|song| song.title.clone().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(page.total, 5);
+        assert_eq!(titles, ["d", "c"]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_songs_paginated_out_of_range() {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                offset: Some(10),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(page.total, 2);
+        assert!(page.items.is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_record_play() {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        query("UPDATE songs SET duration_ms = 40000 WHERE title = 'b'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        let ids = query_scalar::<_, String>("SELECT id FROM songs ORDER BY title")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+        let (a, b) = (&ids[0], &ids[1]);
+        let now = OffsetDateTime::now_utc();
+
+        record_play(&mut connection, a, None, now).await.unwrap();
+        record_play(&mut connection, a, Some(SKIP_THRESHOLD_MS - 1), now)
+            .await
+            .unwrap();
+        let song = record_play(&mut connection, a, Some(SKIP_THRESHOLD_MS), now)
+            .await
+            .unwrap();
+        assert_eq!((song.play_count, song.skip_count), (2, 1));
+        assert!(song.last_played_at.is_some());
+
+        // Shorter songs are played once they're halfway through.
+        let song = record_play(&mut connection, b, Some(20000), now)
+            .await
+            .unwrap();
+        assert_eq!((song.play_count, song.skip_count), (1, 0));
+        let song = record_play(&mut connection, b, Some(19999), now)
+            .await
+            .unwrap();
+        assert_eq!((song.play_count, song.skip_count), (1, 1));
+
+        assert!(matches!(
+            record_play(&mut connection, "unknown", None, now).await,
+            Err(DatabaseError::Song(DatabaseSongError::SongNotFound))
+        ));
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                sort_by: SongSortColumn::PlayCount,
+                order: SortOrder::Desc,
+                min_play_count: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].id, *a);
+
+        let most_played = get_most_played(&mut connection, 1).await.unwrap();
+        assert_eq!(most_played.len(), 1);
+        assert_eq!(most_played[0].id, *a);
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                played_since: Some(now + time::Duration::seconds(1)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_update_song_preferences() {
+        let pool = pool_with_songs(&["intro", "song"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let id = query_scalar::<_, String>("SELECT id FROM songs WHERE title = 'intro'")
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+
+        let song = update_song_preferences(
+            &mut connection,
+            &id,
+            UpdatedSongPreferences {
+                exclude_from_shuffle: Some(true),
+                start_offset_ms: Some(Some(1000)),
+                end_offset_ms: Some(Some(5000)),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(song.exclude_from_shuffle);
+        assert_eq!(song.playback_range(), (1000, Some(5000)));
+
+        // Fields that are left out are kept.
+        let song = update_song_preferences(
+            &mut connection,
+            &id,
+            UpdatedSongPreferences {
+                start_offset_ms: Some(None),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(song.exclude_from_shuffle);
+        assert_eq!(
+            (song.start_offset_ms, song.end_offset_ms),
+            (None, Some(5000))
+        );
+
+        assert!(matches!(
+            update_song_preferences(
+                &mut connection,
+                &id,
+                UpdatedSongPreferences {
+                    start_offset_ms: Some(Some(5000)),
+                    ..Default::default()
+                },
+            )
+            .await,
+            Err(DatabaseError::Song(DatabaseSongError::InvalidPlaybackRange))
+        ));
+
+        let random = get_random_songs(&mut connection, 10).await.unwrap();
+        assert_eq!(random.len(), 1);
+        assert_eq!(random[0].title.as_deref(), Some("song"));
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                shuffleable: Some(false),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].id, id);
+    }
+
+    #[test(tokio::test)]
+    async fn test_relocated_song_is_no_longer_missing() {
+        let pool = pool_with_songs(&["a"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let id = query_scalar::<_, String>("SELECT id FROM songs")
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+        mark_songs_missing(&mut connection, &[id.clone()], OffsetDateTime::now_utc())
+            .await
+            .unwrap();
+
+        update_song_path(&mut connection, &id, "/music/moved/a.mp3")
+            .await
+            .unwrap();
+
+        let song = get_song(&mut connection, &id).await.unwrap();
+        assert_eq!(song.path, "/music/moved/a.mp3");
+        assert_eq!(song.missing_since, None);
+
+        assert!(matches!(
+            update_song_path(&mut connection, "missing", "/music/b.mp3").await,
+            Err(DatabaseError::Song(DatabaseSongError::SongNotFound))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_missing_songs() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let ids = query_scalar::<_, String>("SELECT id FROM songs ORDER BY title")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+        let (a, b) = (ids[0].clone(), ids[1].clone());
+        let went_missing = OffsetDateTime::now_utc() - time::Duration::days(10);
+
+        let marked = mark_songs_missing(&mut connection, &[a.clone(), b.clone()], went_missing)
+            .await
+            .unwrap();
+        assert_eq!(marked, 2);
+
+        // Songs that were already missing keep the time they went missing.
+        mark_songs_missing(&mut connection, &[a.clone()], OffsetDateTime::now_utc())
+            .await
+            .unwrap();
+        assert!(
+            get_song(&mut connection, &a)
+                .await
+                .unwrap()
+                .missing_since
+                .is_some_and(|since| since < OffsetDateTime::now_utc() - time::Duration::days(5))
+        );
+
+        let page = get_songs_paginated(&mut connection, SongQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].title.as_deref(), Some("c"));
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                include_missing: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 3);
+
+        restore_missing_songs(&mut connection, &[b.clone()])
+            .await
+            .unwrap();
+        assert!(
+            get_song(&mut connection, &b)
+                .await
+                .unwrap()
+                .missing_since
+                .is_none()
+        );
+
+        let purged = purge_missing_songs(
+            &mut connection,
+            Some(OffsetDateTime::now_utc() - time::Duration::days(30)),
+        )
+        .await
+        .unwrap();
+        assert!(purged.is_empty());
+
+        let purged = purge_missing_songs(&mut connection, None).await.unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].id, a);
+        assert!(get_song(&mut connection, &a).await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_add_favorites() {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let mut ids = query_scalar::<_, String>("SELECT id FROM songs")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+
+        add_favorites(&mut connection, &ids[..1]).await.unwrap();
+
+        ids.extend([ids[1].clone(), String::from("unknown")]);
+        let result = add_favorites(&mut connection, &ids).await.unwrap();
+
+        assert_eq!(
+            result,
+            BulkAddResult {
+                added: 1,
+                duplicate: 2,
+                unknown: 1,
+            }
+        );
+        assert_eq!(get_favorites(&mut connection).await.unwrap().len(), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_filtered_songs() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        query("UPDATE songs SET album = 'Album' WHERE title != 'c'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let titles = |songs: Vec<Song>| {
+            songs
+                .into_iter()
+                .map(|song| song.title.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let songs = get_filtered_songs(&mut connection, None, None)
+            .await
+            .unwrap();
+        assert_eq!(songs.len(), 3);
+
+        let songs = get_filtered_songs(&mut connection, Some("Album"), Some("directory"))
+            .await
+            .unwrap();
+        assert_eq!(titles(songs), ["a", "b"]);
+
+        let songs = get_filtered_songs(&mut connection, None, Some("missing"))
+            .await
+            .unwrap();
+        assert!(songs.is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_album_duration() {
+        let pool = pool_with_songs(&["a", "b", "c"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        query("UPDATE songs SET album = 'Album'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+
+        let ids = query_scalar::<_, String>("SELECT id FROM songs ORDER BY title")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+
+        // Songs scanned without their audio properties leave the total out.
+        let album = get_album(&mut connection, String::from("Album"))
+            .await
+            .unwrap();
+        assert_eq!(album.duration_ms, None);
+        assert!(album.duration_pending);
+
+        for (id, duration_ms) in ids.iter().zip([Some(1500), Some(2500), None]) {
+            let properties = AudioProperties {
+                duration_ms,
+                ..Default::default()
+            };
+
+            update_song_properties(&mut connection, id, &properties)
+                .await
+                .unwrap();
+        }
+
+        let album = get_album(&mut connection, String::from("Album"))
+            .await
+            .unwrap();
+        assert_eq!(album.duration_ms, Some(4000));
+        assert!(!album.duration_pending);
+        assert_eq!(
+            album
+                .tracks
+                .iter()
+                .filter(|track| track.duration_ms.is_some())
+                .count(),
+            2
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_albums() {
+        let pool = pool_with_songs(&["a", "b", "c", "d", "e", "f", "g"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        for (title, album, album_artist, artist, disc, track) in [
+            ("a", "Hits", Some("First"), "First", None, Some(10)),
+            ("b", "Hits", Some("First"), "First", None, Some(2)),
+            ("c", "Hits", None, "First", None, Some(3)),
+            ("d", "Hits", Some("Second"), "Second", None, None),
+            ("e", "Mix", None, "First", Some(2), Some(1)),
+            ("f", "Mix", None, "Second", Some(1), Some(2)),
+            ("g", "Mix", None, "Second", Some(1), Some(1)),
+        ] {
+            query(
+                "UPDATE songs SET album = ?, album_artist = ?, album_id = ?, artist = ?, disc_number = ?, track_number = ? WHERE title = ?",
+            )
+            .bind(album)
+            .bind(album_artist)
+            .bind(album_id(album, album_artist))
+            .bind(artist)
+            .bind(disc)
+            .bind(track)
+            .bind(title)
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        }
+
+        let albums = get_albums(&mut connection).await.unwrap();
+        let tracks = |album: &Album| {
+            album
+                .tracks
+                .iter()
+                .map(|track| track.title.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Albums of different artists sharing a title stay apart, the untagged track joins the
+        // one of its artist.
+        assert_eq!(albums.len(), 3);
+        assert_eq!(albums[0].artist.as_deref(), Some("First"));
+        assert_eq!(tracks(&albums[0]), ["b", "c", "a"]);
+        assert!(!albums[0].compilation);
+        assert_eq!(albums[1].artist.as_deref(), Some("Second"));
+        assert_eq!(tracks(&albums[1]), ["d"]);
+
+        let mix = &albums[2];
+        assert!(mix.compilation);
+        assert_eq!(tracks(mix), ["g", "f", "e"]);
+        assert_eq!(mix.disc_count, 2);
+        assert_eq!(
+            mix.discs
+                .iter()
+                .map(|disc| (disc.number, disc.track_ids.len()))
+                .collect::<Vec<_>>(),
+            [(1, 2), (2, 1)]
+        );
+
+        for album in &albums {
+            let found = get_album_by_id(&mut connection, &album.id).await.unwrap();
+            assert_eq!(found.id, album.id);
+            assert_eq!(tracks(&found), tracks(album));
+        }
+        assert_eq!(albums[0].id, album_id("Hits", Some("First")));
+
+        // The untagged track joined the album of its artist, there's no album without one.
+        assert!(matches!(
+            get_album_by_id(&mut connection, &album_id("Hits", None)).await,
+            Err(DatabaseError::Song(DatabaseSongError::AlbumNotFound))
+        ));
+
+        // Updating a song stores the id of its new album.
+        let song = UpdatedSong {
+            title: Some("d".to_string()),
+            artist: Some("Second".to_string()),
+            album: Some("Hits / Remastered".to_string()),
+            album_artist: Some("Second".to_string()),
+            genre: None,
+            track_number: None,
+            track_total: None,
+            disc_number: None,
+            disc_total: None,
+            year: None,
+            mood: None,
+            composer: None,
+            comment: None,
+            rating: None,
+            lyrics: None,
+        };
+        update_song(&mut connection, &albums[1].tracks[0].id, song)
+            .await
+            .unwrap();
+        let renamed = album_id("Hits / Remastered", Some("Second"));
+        assert_eq!(
+            tracks(&get_album_by_id(&mut connection, &renamed).await.unwrap()),
+            ["d"]
+        );
+        assert!(matches!(
+            get_album_by_id(&mut connection, &albums[1].id).await,
+            Err(DatabaseError::Song(DatabaseSongError::AlbumNotFound))
+        ));
+
+        // Changes that don't store the id clear it rather than leaving the old one.
+        query("UPDATE songs SET album = 'Hits' WHERE title = 'd'")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        assert!(matches!(
+            get_album_by_id(&mut connection, &renamed).await,
+            Err(DatabaseError::Song(DatabaseSongError::AlbumNotFound))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_song_years() {
+        assert_eq!(parse_year("1999"), Some(1999));
+        assert_eq!(parse_year("2003-05-12"), Some(2003));
+        assert_eq!(parse_year("12/05/2003"), Some(2003));
+        assert_eq!(parse_year("0"), None);
+        assert_eq!(parse_year("20030512"), None);
+        assert_eq!(parse_year("Unknown"), None);
+
+        let pool = pool_with_songs(&["a", "b", "c", "d"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        for (title, year) in [
+            ("a", "1999"),
+            ("b", "2003-05-12"),
+            ("c", "Unknown"),
+            ("d", "2003"),
+        ] {
+            let id = query_scalar::<_, String>("SELECT id FROM songs WHERE title = ?")
+                .bind(title)
+                .fetch_one(&mut *connection)
+                .await
+                .unwrap();
+
+            let fields = BTreeMap::from([
+                (ItemKey::Album, String::from("Hits")),
+                (ItemKey::Year, String::from(year)),
+            ]);
+            fill_song_fields(&mut connection, &id, &fields)
+                .await
+                .unwrap();
+        }
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                sort_by: SongSortColumn::Year,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            page.items
+                .iter()
+                .map(|song| (song.year.as_deref().unwrap(), song.year_num))
+                .collect::<Vec<_>>()[..2],
+            [("Unknown", None), ("1999", Some(1999))]
+        );
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                year_min: Some(2000),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 2);
+
+        let page = get_songs_paginated(
+            &mut connection,
+            SongQuery {
+                year_min: Some(1990),
+                year_max: Some(1999),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].title.as_deref(), Some("a"));
+
+        let albums = get_albums(&mut connection).await.unwrap();
+        assert_eq!(albums[0].year, Some(2003));
+    }
+
+    #[test(tokio::test)]
+    async fn test_cue_songs_share_path() {
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        for (title, start_ms, end_ms) in [("First", 0, Some(1000)), ("Second", 1000, None)] {
+            add_cue_song(
+                &mut connection,
+                NewSong {
+                    path: String::from("/music/album.flac"),
+                    title: Some(String::from(title)),
+                    ..Default::default()
+                },
+                CueRange {
+                    cue_path: String::from("/music/album.cue"),
+                    start_ms,
+                    end_ms,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        assert!(
+            is_cue_backed(&mut connection, "/music/album.flac")
+                .await
+                .unwrap()
+        );
+
+        update_cue_path(&mut connection, "/music/album.cue", "/music/moved.cue")
+            .await
+            .unwrap();
+
+        let tracks = query_as::<_, Song>("SELECT * FROM songs ORDER BY start_ms")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(
+            tracks[1].cue_range(),
+            Some(CueRange {
+                cue_path: String::from("/music/moved.cue"),
+                start_ms: 1000,
+                end_ms: None,
+            })
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_batched_writes() {
+        const SONGS: usize = 5000;
+
+        let pool = pool_with_songs(&[]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        let properties = AudioProperties {
+            duration_ms: Some(1000),
+            ..Default::default()
+        };
+        let mut inserts = (0..SONGS)
+            .map(|index| SongInsert {
+                song: NewSong {
+                    path: format!("/music/{index}.mp3"),
+                    title: Some(format!("Song {index}")),
+                    year: Some(String::from("2024")),
+                    ..Default::default()
+                },
+                range: None,
+                properties: (index % 2 == 0).then_some(properties),
+            })
+            .collect::<Vec<_>>();
+        inserts.push(SongInsert {
+            song: NewSong {
+                path: String::from("/elsewhere/song.mp3"),
+                ..Default::default()
+            },
+            range: None,
+            properties: None,
+        });
+
+        assert_eq!(
+            add_songs(&mut connection, &inserts).await.unwrap(),
+            SONGS as u64
+        );
+
+        let songs = query_as::<_, Song>("SELECT * FROM songs ORDER BY path")
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+        assert_eq!(songs.len(), SONGS);
+        let first = songs
+            .iter()
+            .find(|song| song.path == "/music/0.mp3")
+            .unwrap();
+        assert_eq!(first.year_num, Some(2024));
+        assert!(first.properties_read);
+        assert_eq!(first.duration_ms, Some(1000));
+        let second = songs
+            .iter()
+            .find(|song| song.path == "/music/1.mp3")
+            .unwrap();
+        assert!(!second.properties_read);
+
+        let updates = songs
+            .iter()
+            .map(|song| SongUpdate {
+                id: song.id.clone(),
+                song: UpdatedSong {
+                    title: song
+                        .title
+                        .as_ref()
+                        .map(|title| format!("{title} (Remastered)")),
+                    artist: None,
+                    album: None,
+                    album_artist: None,
+                    genre: None,
+                    track_number: None,
+                    track_total: None,
+                    disc_number: None,
+                    disc_total: None,
+                    year: None,
+                    mood: None,
+                    composer: None,
+                    comment: None,
+                    rating: (song.path == "/music/1.mp3").then_some(Some(80)),
+                    lyrics: None,
+                },
+                properties: None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            update_songs(&mut connection, &updates).await.unwrap(),
+            SONGS as u64
+        );
+
+        let first = get_song(&mut connection, &first.id).await.unwrap();
+        assert_eq!(first.title.as_deref(), Some("Song 0 (Remastered)"));
+        assert_eq!(first.year_num, None);
+        assert_eq!(first.rating, None);
+        // Properties that weren't read are kept.
+        assert_eq!(first.duration_ms, Some(1000));
+        assert_eq!(
+            get_song(&mut connection, &second.id).await.unwrap().rating,
+            Some(80)
+        );
+
+        let ids = songs
+            .iter()
+            .step_by(2)
+            .map(|song| song.id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delete_songs(&mut connection, &ids).await.unwrap(),
+            (SONGS / 2) as u64
+        );
+        assert_eq!(
+            query_scalar::<_, i64>("SELECT COUNT(*) FROM songs")
+                .fetch_one(&mut *connection)
+                .await
+                .unwrap(),
+            (SONGS / 2) as i64
+        );
+    }
 }
@@ -0,0 +1,510 @@
+//! Read-only aggregations of the library over time.
+//!
+//! Songs are selected by comparing `added_at` against `YYYY-MM-DD` or `YYYY` bounds, which sort
+//! before every timestamp of that day or year, so the queries can use the `added_at` index.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use sqlx::{QueryBuilder, Sqlite, query_as, query_scalar};
+use time::{Date, Duration};
+
+use super::{
+    Album, AlbumPlays, ArtistPlays, Connection, OnThisDay, PlayStats, PlayStatsQuery, Result, Song,
+    SongPlays, YearInReview,
+    artists::{aggregate_artists, song_artists},
+    songs::group_albums,
+    total_duration,
+};
+
+/// The amount of artists returned in a [`YearInReview`], and of artists and albums in
+/// [`PlayStats`] unless asked otherwise.
+const TOP_ARTIST_COUNT: usize = 10;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseStatsError {
+    #[error("Invalid year: {0}")]
+    InvalidYear(i32),
+}
+
+async fn get_songs_added_between(
+    connection: &mut Connection,
+    start: &str,
+    end: &str,
+) -> Result<Vec<Song>> {
+    Ok(query_as::<_, Song>(
+        "SELECT * FROM songs WHERE added_at >= ? AND added_at < ? ORDER BY added_at, path",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+/// Returns the songs added on the same day as `today` in every earlier year, and the songs played
+/// the most in the week around it, newest year first.
+///
+/// Years without any additions or plays are left out, as is February 29th in years that don't
+/// have it.
+pub async fn get_on_this_day(connection: &mut Connection, today: Date) -> Result<Vec<OnThisDay>> {
+    let first = query_scalar::<_, Option<String>>(
+        "SELECT MIN(first) FROM (SELECT MIN(added_at) AS first FROM songs UNION ALL SELECT MIN(played_at) FROM plays)",
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+
+    let Some(first_year) = first.and_then(|first| first.get(..4)?.parse().ok()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut days = Vec::new();
+
+    for year in (first_year..today.year()).rev() {
+        let Ok(day) = today.replace_year(year) else {
+            continue;
+        };
+
+        let Some(next_day) = day.next_day() else {
+            continue;
+        };
+
+        let songs =
+            get_songs_added_between(connection, &day.to_string(), &next_day.to_string()).await?;
+
+        let midnight = day.midnight().assume_utc();
+        let most_played = get_play_stats(
+            connection,
+            PlayStatsQuery {
+                from: Some(midnight - Duration::days(3)),
+                to: Some(midnight + Duration::days(4)),
+                limit: None,
+            },
+        )
+        .await?
+        .top_songs;
+
+        if songs.is_empty() && most_played.is_empty() {
+            continue;
+        }
+
+        days.push(OnThisDay {
+            year,
+            albums: songs.iter().filter_map(|song| song.album.clone()).collect(),
+            songs,
+            most_played,
+        });
+    }
+
+    Ok(days)
+}
+
+pub async fn get_year_in_review(connection: &mut Connection, year: i32) -> Result<YearInReview> {
+    if !(0..9999).contains(&year) {
+        return Err(DatabaseStatsError::InvalidYear(year).into());
+    }
+
+    let songs = get_songs_added_between(
+        connection,
+        &format!("{year:04}"),
+        &format!("{:04}", year + 1),
+    )
+    .await?;
+
+    let mut songs_added_per_month = vec![0; 12];
+    for added_at in songs.iter().filter_map(|song| song.added_at) {
+        songs_added_per_month[usize::from(u8::from(added_at.month())) - 1] += 1;
+    }
+
+    let (duration_added_ms, duration_pending) = total_duration(&songs);
+
+    let mut top_artists = aggregate_artists(&songs);
+    top_artists.sort_by(|a, b| b.track_count.cmp(&a.track_count));
+    top_artists.truncate(TOP_ARTIST_COUNT);
+
+    let start_of = |year| Date::from_ordinal_date(year, 1).map(|day| day.midnight().assume_utc());
+    let plays = get_play_stats(
+        connection,
+        PlayStatsQuery {
+            from: start_of(year).ok(),
+            to: start_of(year + 1).ok(),
+            limit: None,
+        },
+    )
+    .await?;
+
+    Ok(YearInReview {
+        year,
+        songs_added: songs.len(),
+        albums_added: songs
+            .iter()
+            .filter_map(|song| song.album.as_deref())
+            .collect::<BTreeSet<_>>()
+            .len(),
+        songs_added_per_month,
+        duration_added_ms,
+        duration_pending,
+        top_artists,
+        plays,
+    })
+}
+
+/// Counts the plays reported in the period of the query, along with the artists and albums
+/// played the most. Skips are only counted in the total.
+pub async fn get_play_stats(
+    connection: &mut Connection,
+    query: PlayStatsQuery,
+) -> Result<PlayStats> {
+    let filter = |builder: &mut QueryBuilder<'_, Sqlite>| {
+        builder.push(" WHERE 1 = 1");
+
+        if let Some(from) = query.from {
+            builder.push(" AND played_at >= ").push_bind(from);
+        }
+
+        if let Some(to) = query.to {
+            builder.push(" AND played_at < ").push_bind(to);
+        }
+    };
+
+    let mut totals = QueryBuilder::new(
+        "SELECT COUNT(*) FILTER (WHERE NOT skipped), COUNT(*) FILTER (WHERE skipped) FROM plays",
+    );
+    filter(&mut totals);
+    let (total_plays, total_skips) = totals
+        .build_query_as::<(i64, i64)>()
+        .fetch_one(&mut *connection)
+        .await?;
+
+    let mut counts = QueryBuilder::new("SELECT song_id, COUNT(*) FROM plays");
+    filter(&mut counts);
+    counts.push(" AND NOT skipped GROUP BY song_id");
+    let play_counts = counts
+        .build_query_as::<(String, u32)>()
+        .fetch_all(&mut *connection)
+        .await?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut played =
+        QueryBuilder::new("SELECT * FROM songs WHERE id IN (SELECT song_id FROM plays");
+    filter(&mut played);
+    played.push(" AND NOT skipped) ORDER BY path");
+    let songs = played
+        .build_query_as::<Song>()
+        .fetch_all(&mut *connection)
+        .await?;
+
+    let limit = query.limit.map_or(TOP_ARTIST_COUNT, |limit| limit as usize);
+    let plays_of = |song: &Song| play_counts.get(&song.id).copied().unwrap_or_default();
+
+    let mut artists: BTreeMap<String, ArtistPlays> = BTreeMap::new();
+    for song in &songs {
+        for artist in song_artists(song) {
+            artists
+                .entry(artist.to_lowercase())
+                .or_insert_with(|| ArtistPlays {
+                    name: artist.to_string(),
+                    play_count: 0,
+                })
+                .play_count += plays_of(song);
+        }
+    }
+
+    let mut top_artists = artists.into_values().collect::<Vec<_>>();
+    top_artists.sort_by(|a, b| b.play_count.cmp(&a.play_count));
+    top_artists.truncate(limit);
+
+    let mut top_songs = songs
+        .iter()
+        .map(|song| SongPlays {
+            song: song.clone(),
+            play_count: plays_of(song),
+        })
+        .collect::<Vec<_>>();
+    top_songs.sort_by(|a, b| b.play_count.cmp(&a.play_count));
+    top_songs.truncate(limit);
+
+    let mut top_albums = group_albums(songs)
+        .into_iter()
+        .map(|tracks| {
+            let play_count = tracks.iter().map(plays_of).sum();
+            let album = Album::from(tracks);
+
+            AlbumPlays {
+                title: album.title,
+                artist: album.artist,
+                play_count,
+            }
+        })
+        .collect::<Vec<_>>();
+    top_albums.sort_by(|a, b| b.play_count.cmp(&a.play_count));
+    top_albums.truncate(limit);
+
+    Ok(PlayStats {
+        total_plays: total_plays as u64,
+        total_skips: total_skips as u64,
+        top_artists,
+        top_albums,
+        top_songs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+    use test_log::test;
+    use time::{Month, OffsetDateTime, format_description::well_known::Rfc3339};
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    /// Creates a database with songs added at the given timestamps, titled by their index.
+    async fn pool_with_additions(
+        additions: &[(&str, Option<&str>, Option<&str>)],
+    ) -> sqlx::SqlitePool {
+        let pool = pool_with_songs(&[]).await;
+
+        for (index, (added_at, album, artist)) in additions.iter().enumerate() {
+            query(
+                "INSERT INTO songs (id, path, title, album, artist, added_at, directory_id) VALUES (?, ?, ?, ?, ?, ?, 'directory')",
+            )
+            .bind(index.to_string())
+            .bind(format!("/music/{index}.mp3"))
+            .bind(index.to_string())
+            .bind(album)
+            .bind(artist)
+            .bind(added_at)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        pool
+    }
+
+    async fn insert_plays(connection: &mut Connection, plays: &[(&str, &str, bool)]) {
+        for (song_id, played_at, skipped) in plays {
+            query("INSERT INTO plays (song_id, played_at, skipped) VALUES (?, ?, ?)")
+                .bind(song_id)
+                .bind(OffsetDateTime::parse(played_at, &Rfc3339).unwrap())
+                .bind(skipped)
+                .execute(&mut *connection)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_on_this_day() {
+        let pool = pool_with_additions(&[
+            ("2022-10-15T08:00:00Z", Some("Old Album"), None),
+            ("2022-10-16T00:00:00Z", Some("Next Day"), None),
+            ("2024-10-15T23:59:59.5Z", Some("Recent Album"), None),
+            ("2024-10-15T12:00:00Z", Some("Recent Album"), None),
+            ("2026-10-15T12:00:00Z", Some("Today"), None),
+        ])
+        .await;
+        let mut connection = pool.acquire().await.unwrap();
+        insert_plays(
+            &mut connection,
+            &[
+                ("0", "2024-10-12T00:00:00Z", false),
+                ("1", "2024-10-18T23:59:59Z", false),
+                ("1", "2024-10-16T10:00:00Z", false),
+                ("2", "2024-10-19T00:00:00Z", false),
+                ("3", "2024-10-15T10:00:00Z", true),
+                ("0", "2023-10-15T10:00:00Z", false),
+            ],
+        )
+        .await;
+
+        let days = get_on_this_day(
+            &mut connection,
+            Date::from_calendar_date(2026, Month::October, 15).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            days.iter()
+                .map(|day| (day.year, day.songs.len()))
+                .collect::<Vec<_>>(),
+            [(2024, 2), (2023, 0), (2022, 1)]
+        );
+        assert_eq!(
+            days[0]
+                .most_played
+                .iter()
+                .map(|song| (song.song.id.as_str(), song.play_count))
+                .collect::<Vec<_>>(),
+            [("1", 2), ("0", 1)]
+        );
+        assert_eq!(days[1].most_played[0].song.id, "0");
+        assert!(days[2].most_played.is_empty());
+        assert_eq!(
+            days[0].albums,
+            BTreeSet::from([String::from("Recent Album")])
+        );
+        assert_eq!(days[0].songs[0].title.as_deref(), Some("3"));
+    }
+
+    #[test(tokio::test)]
+    async fn test_on_this_day_without_additions() {
+        let pool = pool_with_songs(&["a"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        assert!(
+            get_on_this_day(
+                &mut connection,
+                Date::from_calendar_date(2024, Month::February, 29).unwrap()
+            )
+            .await
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_year_in_review() {
+        let pool = pool_with_additions(&[
+            ("2024-12-31T23:59:59Z", Some("Before"), Some("Someone")),
+            ("2025-01-01T00:00:00Z", Some("First"), Some("Band; Guest")),
+            ("2025-01-20T10:00:00Z", Some("First"), Some("Band")),
+            ("2025-06-01T10:00:00Z", None, Some("Guest")),
+            ("2025-12-31T23:59:59.999Z", Some("Last"), Some("band")),
+            ("2026-01-01T00:00:00Z", Some("After"), Some("Someone")),
+        ])
+        .await;
+        let mut connection = pool.acquire().await.unwrap();
+        insert_plays(
+            &mut connection,
+            &[
+                ("0", "2025-01-01T00:00:00Z", false),
+                ("0", "2025-12-31T23:59:59Z", false),
+                ("3", "2025-03-01T10:00:00Z", false),
+                ("3", "2025-03-01T11:00:00Z", true),
+                ("5", "2026-01-01T00:00:00Z", false),
+            ],
+        )
+        .await;
+
+        let review = get_year_in_review(&mut connection, 2025).await.unwrap();
+
+        assert_eq!(review.year, 2025);
+        assert_eq!(review.songs_added, 4);
+        assert_eq!(review.albums_added, 2);
+        assert_eq!(
+            review.songs_added_per_month,
+            [2, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            review
+                .top_artists
+                .iter()
+                .map(|artist| (artist.name.as_str(), artist.track_count))
+                .collect::<Vec<_>>(),
+            [("Band", 3), ("Guest", 2)]
+        );
+        assert_eq!((review.plays.total_plays, review.plays.total_skips), (3, 1));
+        assert_eq!(
+            review
+                .plays
+                .top_artists
+                .iter()
+                .map(|artist| (artist.name.as_str(), artist.play_count))
+                .collect::<Vec<_>>(),
+            [("Someone", 2), ("Guest", 1)]
+        );
+        assert_eq!(review.plays.top_songs[0].song.id, "0");
+        assert!(review.duration_pending);
+        assert_eq!(review.duration_added_ms, None);
+
+        query("UPDATE songs SET duration_ms = 1000, properties_read = TRUE")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        let review = get_year_in_review(&mut connection, 2025).await.unwrap();
+        assert!(!review.duration_pending);
+        assert_eq!(review.duration_added_ms, Some(4000));
+
+        assert!(matches!(
+            get_year_in_review(&mut connection, -1).await,
+            Err(crate::db::DatabaseError::Stats(
+                DatabaseStatsError::InvalidYear(-1)
+            ))
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn test_play_stats() {
+        let pool = pool_with_additions(&[
+            ("2025-01-01T00:00:00Z", Some("First"), Some("Band; Guest")),
+            ("2025-01-01T00:00:00Z", Some("First"), Some("band")),
+            ("2025-01-01T00:00:00Z", Some("Second"), Some("Someone")),
+        ])
+        .await;
+        let mut connection = pool.acquire().await.unwrap();
+
+        insert_plays(
+            &mut connection,
+            &[
+                ("0", "2026-01-01T10:00:00Z", false),
+                ("1", "2026-01-02T10:00:00Z", false),
+                ("1", "2026-01-03T10:00:00Z", false),
+                ("2", "2026-01-03T10:00:00Z", false),
+                ("2", "2026-01-04T10:00:00Z", true),
+                ("2", "2025-12-31T10:00:00Z", false),
+            ],
+        )
+        .await;
+
+        let stats = get_play_stats(
+            &mut connection,
+            PlayStatsQuery {
+                from: Some(OffsetDateTime::parse("2026-01-01T00:00:00Z", &Rfc3339).unwrap()),
+                to: Some(OffsetDateTime::parse("2026-02-01T00:00:00Z", &Rfc3339).unwrap()),
+                limit: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((stats.total_plays, stats.total_skips), (4, 1));
+        assert_eq!(
+            stats
+                .top_artists
+                .iter()
+                .map(|artist| (artist.name.as_str(), artist.play_count))
+                .collect::<Vec<_>>(),
+            [("Band", 3), ("Guest", 1), ("Someone", 1)]
+        );
+        assert_eq!(
+            stats
+                .top_albums
+                .iter()
+                .map(|album| (album.title.as_str(), album.play_count))
+                .collect::<Vec<_>>(),
+            [("First", 3), ("Second", 1)]
+        );
+        assert_eq!(
+            stats
+                .top_songs
+                .iter()
+                .map(|song| (song.song.id.as_str(), song.play_count))
+                .collect::<Vec<_>>(),
+            [("1", 2), ("0", 1), ("2", 1)]
+        );
+
+        let stats = get_play_stats(
+            &mut connection,
+            PlayStatsQuery {
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(stats.total_plays, 5);
+        assert_eq!(stats.top_albums.len(), 1);
+        assert_eq!(stats.top_albums[0].play_count, 3);
+    }
+}
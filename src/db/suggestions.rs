@@ -0,0 +1,153 @@
+//! Recordings a song might be, found by the `identify-songs` job or the identify endpoint.
+
+use sqlx::{Connection as _, query, query_as};
+use time::OffsetDateTime;
+
+use crate::providers::IdentifyCandidate;
+
+use super::{Connection, Result};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseSuggestionError {
+    #[error("Suggestion not found")]
+    NotFound,
+}
+
+/// Returns the suggestions of the song, best matches first.
+pub async fn get_suggestions(
+    connection: &mut Connection,
+    song_id: &str,
+) -> Result<Vec<IdentifyCandidate>> {
+    Ok(query_as::<_, IdentifyCandidate>(
+        "SELECT recording_id, score, title, artist, album, length_ms FROM song_suggestions WHERE song_id = ? ORDER BY score DESC, recording_id",
+    )
+    .bind(song_id)
+    .fetch_all(&mut *connection)
+    .await?)
+}
+
+pub async fn get_suggestion(
+    connection: &mut Connection,
+    song_id: &str,
+    recording_id: &str,
+) -> Result<IdentifyCandidate> {
+    query_as::<_, IdentifyCandidate>(
+        "SELECT recording_id, score, title, artist, album, length_ms FROM song_suggestions WHERE song_id = ? AND recording_id = ?",
+    )
+    .bind(song_id)
+    .bind(recording_id)
+    .fetch_optional(&mut *connection)
+    .await?
+    .ok_or_else(|| DatabaseSuggestionError::NotFound.into())
+}
+
+/// Replaces every suggestion of the song with `candidates`.
+pub async fn replace_suggestions(
+    connection: &mut Connection,
+    song_id: &str,
+    candidates: &[IdentifyCandidate],
+) -> Result<()> {
+    let mut transaction = connection.begin().await?;
+
+    query("DELETE FROM song_suggestions WHERE song_id = ?")
+        .bind(song_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    let suggested_at = OffsetDateTime::now_utc();
+    for candidate in candidates {
+        query(
+            "INSERT INTO song_suggestions (song_id, recording_id, score, title, artist, album, length_ms, suggested_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(song_id)
+        .bind(&candidate.recording_id)
+        .bind(candidate.score)
+        .bind(&candidate.title)
+        .bind(&candidate.artist)
+        .bind(&candidate.album)
+        .bind(candidate.length_ms)
+        .bind(suggested_at)
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+pub async fn clear_suggestions(connection: &mut Connection, song_id: &str) -> Result<()> {
+    query("DELETE FROM song_suggestions WHERE song_id = ?")
+        .bind(song_id)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query_scalar;
+    use test_log::test;
+
+    use super::*;
+    use crate::db::{DatabaseError, test_utils::pool_with_songs};
+
+    fn candidate(recording_id: &str, score: u8) -> IdentifyCandidate {
+        IdentifyCandidate {
+            recording_id: recording_id.to_string(),
+            score,
+            title: String::from("Song"),
+            artist: Some(String::from("Artist")),
+            album: None,
+            length_ms: Some(180_000),
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_suggestions() {
+        let pool = pool_with_songs(&["a"]).await;
+        let mut connection = pool.acquire().await.unwrap();
+        let song_id = query_scalar::<_, String>("SELECT id FROM songs")
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap();
+
+        replace_suggestions(
+            &mut connection,
+            &song_id,
+            &[candidate("old", 100), candidate("other", 10)],
+        )
+        .await
+        .unwrap();
+        replace_suggestions(
+            &mut connection,
+            &song_id,
+            &[candidate("b", 40), candidate("a", 90)],
+        )
+        .await
+        .unwrap();
+
+        let suggestions = get_suggestions(&mut connection, &song_id).await.unwrap();
+        assert_eq!(suggestions, [candidate("a", 90), candidate("b", 40)]);
+
+        assert_eq!(
+            get_suggestion(&mut connection, &song_id, "b")
+                .await
+                .unwrap(),
+            candidate("b", 40)
+        );
+        assert!(matches!(
+            get_suggestion(&mut connection, &song_id, "old").await,
+            Err(DatabaseError::Suggestion(DatabaseSuggestionError::NotFound))
+        ));
+
+        clear_suggestions(&mut connection, &song_id).await.unwrap();
+        assert!(
+            get_suggestions(&mut connection, &song_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
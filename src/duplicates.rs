@@ -0,0 +1,196 @@
+//! Telling whether two songs are the same recording, used to keep files from the inbox that are
+//! already in the library out of it and to report the duplicates the library already has.
+//!
+//! Files aren't hashed, so songs are compared by their title, artist and album, ignoring case,
+//! and by their duration when both are known.
+
+use std::collections::HashMap;
+
+use crate::db::Song;
+
+/// Difference between the durations of two songs up to which they're the same recording,
+/// encodes of a track rarely differ by more than a few hundred milliseconds.
+pub const DURATION_TOLERANCE_MS: u32 = 2000;
+
+/// The parts of a song it's compared by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SongIdentity<'a> {
+    pub title: Option<&'a str>,
+    pub artist: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub duration_ms: Option<u32>,
+}
+
+impl<'a> SongIdentity<'a> {
+    pub fn of(song: &'a Song) -> Self {
+        Self {
+            title: song.title.as_deref(),
+            artist: song.artist.as_deref(),
+            album: song.album.as_deref(),
+            duration_ms: song.duration_ms,
+        }
+    }
+
+    /// Whether both are the same recording.
+    ///
+    /// Songs without a title don't match anything, there's too little to go on.
+    pub fn matches(&self, other: &SongIdentity<'_>) -> bool {
+        let Some(title) = normalize(self.title) else {
+            return false;
+        };
+
+        Some(title) == normalize(other.title)
+            && normalize(self.artist) == normalize(other.artist)
+            && normalize(self.album) == normalize(other.album)
+            && match (self.duration_ms, other.duration_ms) {
+                (Some(a), Some(b)) => a.abs_diff(b) <= DURATION_TOLERANCE_MS,
+                _ => true,
+            }
+    }
+}
+
+/// Tags are compared trimmed and ignoring case, empty ones are the same as missing ones.
+fn normalize(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Returns the first song `identity` is a duplicate of.
+pub fn find_duplicate<'s>(identity: &SongIdentity<'_>, songs: &'s [Song]) -> Option<&'s Song> {
+    songs
+        .iter()
+        .find(|song| identity.matches(&SongIdentity::of(song)))
+}
+
+/// Groups the songs that are the same recording, songs without a duplicate are left out.
+///
+/// Each song is compared with the first song of every group with the same title, in order, so the
+/// first song of a group is the one the others are duplicates of.
+pub fn group_duplicates(songs: &[Song]) -> Vec<Vec<&Song>> {
+    let mut groups: Vec<Vec<&Song>> = Vec::new();
+    let mut groups_by_title: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for song in songs {
+        let Some(title) = normalize(song.title.as_deref()) else {
+            continue;
+        };
+
+        let identity = SongIdentity::of(song);
+        let titled = groups_by_title.entry(title).or_default();
+        match titled
+            .iter()
+            .find(|&&index| identity.matches(&SongIdentity::of(groups[index][0])))
+        {
+            Some(&index) => groups[index].push(song),
+            None => {
+                titled.push(groups.len());
+                groups.push(vec![song]);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    fn song(id: &str, title: Option<&str>, artist: Option<&str>, duration_ms: Option<u32>) -> Song {
+        Song {
+            id: id.to_string(),
+            title: title.map(String::from),
+            artist: artist.map(String::from),
+            album: Some("Album".to_string()),
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let original = song("a", Some("Title"), Some("Artist"), Some(180_000));
+        let identity = SongIdentity::of(&original);
+
+        for (other, expected) in [
+            (
+                song("b", Some("Title"), Some("Artist"), Some(180_000)),
+                true,
+            ),
+            (
+                song("b", Some(" title "), Some("ARTIST"), Some(181_500)),
+                true,
+            ),
+            (song("b", Some("Title"), Some("Artist"), None), true),
+            (
+                song("b", Some("Title"), Some("Artist"), Some(185_000)),
+                false,
+            ),
+            (
+                song("b", Some("Other"), Some("Artist"), Some(180_000)),
+                false,
+            ),
+            (
+                song("b", Some("Title"), Some("Other"), Some(180_000)),
+                false,
+            ),
+            (song("b", Some("Title"), None, Some(180_000)), false),
+            (song("b", None, Some("Artist"), Some(180_000)), false),
+        ] {
+            assert_eq!(
+                identity.matches(&SongIdentity::of(&other)),
+                expected,
+                "{other:?}"
+            );
+            assert_eq!(SongIdentity::of(&other).matches(&identity), expected);
+        }
+
+        let untitled = song("a", None, Some("Artist"), Some(180_000));
+        assert!(!SongIdentity::of(&untitled).matches(&SongIdentity::of(&untitled)));
+
+        let empty_artist = song("a", Some("Title"), Some(""), None);
+        let no_artist = song("b", Some("Title"), None, None);
+        assert!(SongIdentity::of(&empty_artist).matches(&SongIdentity::of(&no_artist)));
+    }
+
+    #[test]
+    fn test_find_duplicate() {
+        let songs = [
+            song("a", Some("Title"), Some("Artist"), Some(100_000)),
+            song("b", Some("Title"), Some("Artist"), Some(200_000)),
+        ];
+
+        let incoming = song("c", Some("Title"), Some("Artist"), Some(199_000));
+        assert_eq!(
+            find_duplicate(&SongIdentity::of(&incoming), &songs).map(|song| song.id.as_str()),
+            Some("b")
+        );
+
+        let incoming = song("c", Some("Title"), Some("Artist"), Some(150_000));
+        assert!(find_duplicate(&SongIdentity::of(&incoming), &songs).is_none());
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let songs = [
+            song("a", Some("Title"), Some("Artist"), Some(100_000)),
+            song("b", Some("Other"), Some("Artist"), Some(100_000)),
+            song("c", Some("title"), Some("Artist"), Some(101_000)),
+            song("d", Some("Title"), Some("Artist"), Some(300_000)),
+            song("e", None, Some("Artist"), Some(100_000)),
+            song("f", None, Some("Artist"), Some(100_000)),
+        ];
+
+        let groups = group_duplicates(&songs)
+            .into_iter()
+            .map(|group| group.into_iter().map(|song| song.id.as_str()).collect())
+            .collect::<Vec<Vec<_>>>();
+
+        assert_eq!(groups, vec![vec!["a", "c"]]);
+    }
+}
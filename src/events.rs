@@ -1,25 +1,56 @@
-use std::convert::Infallible;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
 
 use axum::{
-    Router,
-    extract::State,
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
     response::{
-        Sse,
+        IntoResponse, Result, Sse,
         sse::{Event as SseEvent, KeepAlive},
     },
     routing::get,
 };
 use futures::Stream;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
-use tokio::sync::broadcast::Sender;
+use tokio::sync::{
+    Semaphore,
+    broadcast::{self, Sender, error::RecvError},
+    mpsc,
+};
 use tokio_stream::{StreamExt, wrappers::BroadcastStream};
 use ts_rs::TS;
 
-use crate::AppState;
+use crate::{
+    AppState,
+    api::internal_error,
+    db::{HistoryEvent, HistoryEventKind, HistoryQuery, NewHistoryEvent, Page, history},
+    state::{OperationManagerEvent, Pool, job::manager::JobManagerEvent as JobEvent},
+};
+
+mod websocket;
+
+pub use websocket::{EventSubscription, WebSocketMessage};
+
+/// How often events older than the retention period are removed from the history.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How many of the latest events of each kind are kept for clients catching up on what they
+/// missed, per kind so a busy job can't push the rarer events out.
+const REPLAY_CAPACITY: usize = 256;
+
+/// How many long-poll requests can wait for events at once.
+const MAX_POLLERS: usize = 256;
+
+/// Longest a long-poll request waits for events, in seconds.
+const MAX_POLL_TIMEOUT: u64 = 60;
 
 #[derive(Debug, Clone, serde::Serialize, TS)]
-#[ts(export, export_to = "bindings.ts")]
 pub struct FileOperationManagerEvent {
     #[serde(flatten)]
     pub inner: super::state::OperationManagerEvent,
@@ -37,18 +68,12 @@ impl From<super::state::OperationManagerEvent> for FileOperationManagerEvent {
     }
 }
 
-impl From<FileOperationManagerEvent> for SseEvent {
-    fn from(event: FileOperationManagerEvent) -> Self {
-        SseEvent::default()
-            .event("fs-event")
-            .json_data(event)
-            .expect("Failed to serialize event")
-    }
+impl PublishableEvent for FileOperationManagerEvent {
+    const NAME: &str = "fs-event";
 }
 
 #[derive(Debug, Clone, serde::Serialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export, export_to = "bindings.ts")]
 pub struct JobManagerEvent {
     #[serde(flatten)]
     pub inner: super::state::job::manager::JobManagerEvent,
@@ -66,15 +91,33 @@ impl From<super::state::job::manager::JobManagerEvent> for JobManagerEvent {
     }
 }
 
-impl From<JobManagerEvent> for SseEvent {
-    fn from(event: JobManagerEvent) -> Self {
-        SseEvent::default()
-            .event("job-event")
-            .json_data(event)
-            .expect("Failed to serialize event")
+impl PublishableEvent for JobManagerEvent {
+    const NAME: &str = "job-event";
+}
+
+#[derive(Debug, Clone, serde::Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryWatcherEvent {
+    #[serde(flatten)]
+    pub inner: super::state::DirectoryWatcherEvent,
+    #[serde(with = "time::serde::rfc3339")]
+    #[ts(type = "Date")]
+    pub timestamp: OffsetDateTime,
+}
+
+impl From<super::state::DirectoryWatcherEvent> for DirectoryWatcherEvent {
+    fn from(event: super::state::DirectoryWatcherEvent) -> Self {
+        Self {
+            inner: event,
+            timestamp: OffsetDateTime::now_utc(),
+        }
     }
 }
 
+impl PublishableEvent for DirectoryWatcherEvent {
+    const NAME: &str = "watcher-event";
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[non_exhaustive]
@@ -82,6 +125,20 @@ pub enum AppEventKind {
     Initialized,
     Shutdown,
     Error,
+    DirectoryAdded,
+    DirectoryRemoved,
+    /// A directory was renamed or moved, the message is its path afterwards.
+    DirectoryUpdated,
+    SettingsChanged,
+    /// An album's cover was written into its folder while organizing it.
+    FolderArtWritten,
+    /// An album's files were moved and the paths of its songs updated.
+    AlbumOrganized,
+    /// Something needs attention, e.g. a library disk is running low on space.
+    Warning,
+    /// The server stopped touching the library, see [`crate::state::Maintenance`].
+    MaintenanceStarted,
+    MaintenanceEnded,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -94,6 +151,16 @@ pub struct AppEvent {
     pub timestamp: OffsetDateTime,
 }
 
+impl AppEvent {
+    pub fn new(kind: AppEventKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            timestamp: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
 impl Default for AppEvent {
     fn default() -> Self {
         Self {
@@ -104,24 +171,501 @@ impl Default for AppEvent {
     }
 }
 
-impl From<AppEvent> for SseEvent {
-    fn from(event: AppEvent) -> Self {
+impl PublishableEvent for AppEvent {
+    const NAME: &str = "app-event";
+}
+
+/// Events that can be published on the [`EventBus`].
+pub trait PublishableEvent: Serialize {
+    /// Name clients tell the kinds of events apart by, it's the event field of the SSE stream.
+    const NAME: &str;
+}
+
+/// An event as it's sent to clients, numbered in the order events were published so clients can
+/// ask for the ones they missed.
+#[derive(Debug, Clone, Serialize)]
+pub struct PublishedEvent {
+    pub id: u64,
+    pub event: &'static str,
+    pub data: serde_json::Value,
+}
+
+impl PublishedEvent {
+    /// The short name of the kind of event clients filter by, e.g. `job` for `job-event`.
+    fn category(&self) -> &'static str {
+        self.event.strip_suffix("-event").unwrap_or(self.event)
+    }
+
+    /// The id of the job or file operation the event is about, if it's about one.
+    fn source(&self) -> Option<String> {
+        match self.data.get("source")? {
+            serde_json::Value::String(source) => Some(source.clone()),
+            serde_json::Value::Number(source) => Some(source.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Which events a client wants, every event without any filter.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EventFilter {
+    /// Comma separated kinds of events, e.g. `job,fs`, out of `app`, `job`, `fs` and `watcher`.
+    types: Option<String>,
+    /// Only events about this job run or file operation.
+    source: Option<String>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &PublishedEvent) -> bool {
+        let types_match = self.types.as_ref().is_none_or(|types| {
+            types
+                .split(',')
+                .map(str::trim)
+                .any(|category| category == event.category())
+        });
+
+        types_match
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| event.source().as_ref() == Some(source))
+    }
+}
+
+impl From<PublishedEvent> for SseEvent {
+    fn from(event: PublishedEvent) -> Self {
         SseEvent::default()
-            .event("app-event")
-            .json_data(event)
+            .id(event.id.to_string())
+            .event(event.event)
+            .json_data(event.data)
             .expect("Failed to serialize event")
     }
 }
 
+/// The latest events, shared by SSE clients resuming with `Last-Event-ID` and long-polling
+/// clients.
+#[derive(Debug, Default)]
+struct ReplayBuffer {
+    /// The latest events keyed by [`PublishableEvent::NAME`].
+    events: HashMap<&'static str, VecDeque<PublishedEvent>>,
+    last_id: u64,
+}
+
+impl ReplayBuffer {
+    /// Numbers the event and buffers it, dropping the oldest event of its kind once it's full.
+    fn push(&mut self, event: &'static str, data: serde_json::Value) -> PublishedEvent {
+        self.last_id += 1;
+
+        let published = PublishedEvent {
+            id: self.last_id,
+            event,
+            data,
+        };
+
+        let events = self.events.entry(event).or_default();
+        if events.len() == REPLAY_CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(published.clone());
+
+        published
+    }
+
+    /// Returns the buffered events published after `since`, in the order they were published.
+    ///
+    /// Ids start over when the app restarts, so an id that hasn't been handed out yet is from a
+    /// previous run and everything buffered is new to the client.
+    fn since(&self, since: u64) -> Vec<PublishedEvent> {
+        let since = if since > self.last_id { 0 } else { since };
+
+        let mut events = self
+            .events
+            .values()
+            .flatten()
+            .filter(|event| event.id > since)
+            .cloned()
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| event.id);
+
+        events
+    }
+}
+
+/// Events that can be kept in the history once they're broadcast.
+pub trait RecordedEvent {
+    /// Returns the history entry of the event, if it's significant enough to be kept.
+    fn history_event(&self) -> Option<NewHistoryEvent>;
+}
+
+impl RecordedEvent for JobManagerEvent {
+    fn history_event(&self) -> Option<NewHistoryEvent> {
+        let (kind, job_id, message) = match &self.inner {
+            JobEvent::Started { job_id, .. } => (HistoryEventKind::JobStarted, job_id, None),
+            JobEvent::Completed { job_id, .. } => (HistoryEventKind::JobCompleted, job_id, None),
+            JobEvent::Cancelled { job_id, .. } => (HistoryEventKind::JobCancelled, job_id, None),
+            JobEvent::Failed {
+                job_id, message, ..
+            } => (HistoryEventKind::JobFailed, job_id, Some(message.clone())),
+            _ => return None,
+        };
+
+        Some(NewHistoryEvent {
+            kind,
+            source: Some(job_id.clone()),
+            message,
+        })
+    }
+}
+
+impl RecordedEvent for FileOperationManagerEvent {
+    fn history_event(&self) -> Option<NewHistoryEvent> {
+        match &self.inner {
+            OperationManagerEvent::Failed { source, error } => Some(NewHistoryEvent {
+                kind: HistoryEventKind::OperationFailed,
+                source: Some(source.to_string()),
+                message: Some(error.clone()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl RecordedEvent for DirectoryWatcherEvent {
+    fn history_event(&self) -> Option<NewHistoryEvent> {
+        None
+    }
+}
+
+impl RecordedEvent for AppEvent {
+    fn history_event(&self) -> Option<NewHistoryEvent> {
+        let kind = match self.kind {
+            AppEventKind::DirectoryAdded => HistoryEventKind::DirectoryAdded,
+            AppEventKind::DirectoryRemoved => HistoryEventKind::DirectoryRemoved,
+            AppEventKind::DirectoryUpdated => HistoryEventKind::DirectoryUpdated,
+            AppEventKind::SettingsChanged => HistoryEventKind::SettingsChanged,
+            AppEventKind::MaintenanceStarted => HistoryEventKind::MaintenanceStarted,
+            AppEventKind::MaintenanceEnded => HistoryEventKind::MaintenanceEnded,
+            _ => return None,
+        };
+
+        Some(NewHistoryEvent {
+            kind,
+            source: None,
+            message: Some(self.message.clone()),
+        })
+    }
+}
+
+/// The one tap every event of the app passes through, it's sent to clients and written to the
+/// history from here so the two can't disagree about what happened.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    live: Sender<PublishedEvent>,
+    recent: Arc<Mutex<ReplayBuffer>>,
+    pollers: Arc<Semaphore>,
+    history: mpsc::UnboundedSender<(NewHistoryEvent, OffsetDateTime)>,
+}
+
+impl EventBus {
+    /// Creates the bus and spawns the task writing the history, events are kept for
+    /// `retention_days` or forever if it's `0`.
+    pub fn new(pool: Pool, retention_days: u32) -> Self {
+        let (live, _) = broadcast::channel(1024);
+        let (history, rx) = mpsc::unbounded_channel();
+
+        let retention =
+            (retention_days > 0).then(|| time::Duration::days(i64::from(retention_days)));
+        tokio::spawn(write_history(pool, rx, retention));
+
+        Self {
+            live,
+            recent: Arc::default(),
+            pollers: Arc::new(Semaphore::new(MAX_POLLERS)),
+            history,
+        }
+    }
+
+    /// Subscribes to the events published from now on, along with the buffered ones published
+    /// after `since`. Every event ends up in exactly one of the two.
+    pub fn subscribe_since(
+        &self,
+        since: Option<u64>,
+    ) -> (broadcast::Receiver<PublishedEvent>, Vec<PublishedEvent>) {
+        let recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        let missed = since.map(|since| recent.since(since)).unwrap_or_default();
+
+        (self.live.subscribe(), missed)
+    }
+
+    /// Returns the buffered events published after `since`.
+    pub fn events_since(&self, since: u64) -> Vec<PublishedEvent> {
+        self.recent
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .since(since)
+    }
+
+    /// The id of the latest event, `0` if none were published yet.
+    pub fn last_id(&self) -> u64 {
+        self.recent
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .last_id
+    }
+
+    pub fn publish<E>(&self, event: E)
+    where
+        E: RecordedEvent + PublishableEvent,
+    {
+        if let Some(history_event) = event.history_event() {
+            let _ = self
+                .history
+                .send((history_event, OffsetDateTime::now_utc()));
+        }
+
+        let data = serde_json::to_value(&event).expect("Failed to serialize event");
+
+        // The buffer stays locked while the event is sent, so subscribing in between can't miss
+        // it or get it twice.
+        let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        let published = recent.push(E::NAME, data);
+
+        let _ = self.live.send(published);
+    }
+}
+
+async fn write_history(
+    pool: Pool,
+    mut rx: mpsc::UnboundedReceiver<(NewHistoryEvent, OffsetDateTime)>,
+    retention: Option<time::Duration>,
+) {
+    let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some((event, created_at)) = event else {
+                    break;
+                };
+
+                let result = match pool.acquire().await {
+                    Ok(mut connection) => {
+                        history::add_event(&mut connection, &event, created_at).await
+                    }
+                    Err(err) => Err(err.into()),
+                };
+
+                if let Err(err) = result {
+                    tracing::warn!("Failed to save {:?} event to the history: {err}", event.kind);
+                }
+            }
+            _ = prune.tick(), if retention.is_some() => {
+                let Some(retention) = retention else {
+                    continue;
+                };
+
+                let result = match pool.acquire().await {
+                    Ok(mut connection) => {
+                        history::delete_events_before(
+                            &mut connection,
+                            OffsetDateTime::now_utc() - retention,
+                        )
+                        .await
+                    }
+                    Err(err) => Err(err.into()),
+                };
+
+                match result {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::debug!("Removed {removed} events from the history"),
+                    Err(err) => tracing::warn!("Failed to prune the event history: {err}"),
+                }
+            }
+        }
+    }
+}
+
 pub fn router() -> Router<AppState> {
-    Router::new().route("/events", get(handler))
+    Router::new()
+        .route("/events", get(handler))
+        .route("/events/poll", get(poll_events))
+        .route("/events/history", get(get_history))
+        .route("/ws", get(websocket::handler))
 }
 
+/// Streams events as they're published, a client reconnecting with `Last-Event-ID` first gets the
+/// buffered events it missed. Only the events matching the filter are sent, e.g.
+/// `?types=job&source=<id>` for the events of a single job run.
 async fn handler(
-    State(tx): State<Sender<SseEvent>>,
+    State(events): State<EventBus>,
+    Query(filter): Query<EventFilter>,
+    headers: HeaderMap,
 ) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
-    let rx = tx.subscribe();
-    let stream = BroadcastStream::new(rx).filter_map(|event| event.ok().map(Ok));
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+
+    let (rx, missed) = events.subscribe_since(last_event_id);
+    let stream = event_stream(rx, missed, filter).map(|event| Ok(SseEvent::from(event)));
 
     Sse::new(stream).keep_alive(KeepAlive::default())
 }
+
+/// The missed events followed by the live ones, leaving out the ones the filter doesn't match.
+fn event_stream(
+    rx: broadcast::Receiver<PublishedEvent>,
+    missed: Vec<PublishedEvent>,
+    filter: EventFilter,
+) -> impl Stream<Item = PublishedEvent> {
+    tokio_stream::iter(missed)
+        .chain(BroadcastStream::new(rx).filter_map(Result::ok))
+        .filter(move |event| filter.matches(event))
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct PollQuery {
+    /// Id of the last event the client got, only events published after it are returned. Without
+    /// it, only events published from now on are.
+    since_id: Option<u64>,
+    /// Seconds to wait for an event before returning without any.
+    timeout: u64,
+}
+
+impl Default for PollQuery {
+    fn default() -> Self {
+        Self {
+            since_id: None,
+            timeout: 25,
+        }
+    }
+}
+
+/// Long-polls for events, for clients whose connection can't keep an SSE stream or a websocket
+/// open. Returns the buffered events newer than `since_id` right away, otherwise waits for the
+/// next ones until the timeout and returns an empty list.
+async fn poll_events(
+    State(events): State<EventBus>,
+    Query(query): Query<PollQuery>,
+) -> Result<Json<Vec<PublishedEvent>>> {
+    let Ok(_permit) = events.pollers.clone().try_acquire_owned() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many clients are polling for events, try again later",
+        )
+            .into());
+    };
+
+    let since = query.since_id.unwrap_or_else(|| events.last_id());
+    let (mut rx, missed) = events.subscribe_since(Some(since));
+
+    if !missed.is_empty() {
+        return Ok(Json(missed));
+    }
+
+    let timeout = Duration::from_secs(query.timeout.min(MAX_POLL_TIMEOUT));
+    let published = match tokio::time::timeout(timeout, rx.recv()).await {
+        // Events published along with the one that woke the request up are returned with it.
+        Ok(Ok(_) | Err(RecvError::Lagged(_))) => events.events_since(since),
+        Ok(Err(RecvError::Closed)) | Err(_) => Vec::new(),
+    };
+
+    Ok(Json(published))
+}
+
+/// Returns a page of the event history, newest first.
+async fn get_history(
+    State(pool): State<Pool>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Page<HistoryEvent>>> {
+    let mut connection = pool.acquire().await.map_err(internal_error)?;
+    let events = history::get_events(&mut connection, query)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(Json(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use test_log::test;
+
+    use super::*;
+
+    fn buffer_with(events: &[(&'static str, serde_json::Value)]) -> ReplayBuffer {
+        let mut buffer = ReplayBuffer::default();
+        for (event, data) in events {
+            buffer.push(*event, data.clone());
+        }
+
+        buffer
+    }
+
+    #[test]
+    fn test_replaying_missed_events() {
+        let mut buffer = buffer_with(&[
+            ("job-event", json!({ "source": "a" })),
+            ("app-event", json!({})),
+            ("job-event", json!({ "source": "b" })),
+        ]);
+
+        let ids =
+            |events: Vec<PublishedEvent>| events.iter().map(|event| event.id).collect::<Vec<_>>();
+        assert_eq!(ids(buffer.since(1)), [2, 3]);
+        // An id of a previous run of the app.
+        assert_eq!(ids(buffer.since(10)), [1, 2, 3]);
+
+        // A busy kind of event only pushes out its own kind.
+        for _ in 0..REPLAY_CAPACITY {
+            buffer.push("job-event", json!({}));
+        }
+        let replayed = buffer.since(0);
+        assert_eq!(replayed.len(), REPLAY_CAPACITY + 1);
+        assert_eq!(replayed[0].event, "app-event");
+        assert!(replayed.windows(2).all(|pair| pair[0].id < pair[1].id));
+    }
+
+    #[test(tokio::test)]
+    async fn test_filtering_events() {
+        let mut buffer = buffer_with(&[
+            ("job-event", json!({ "source": "a" })),
+            ("fs-event", json!({ "source": 7 })),
+            ("watcher-event", json!({})),
+        ]);
+        let (tx, rx) = broadcast::channel(16);
+
+        let filter = EventFilter {
+            types: Some(String::from("job,fs")),
+            source: None,
+        };
+        let stream = event_stream(rx, buffer.since(0), filter);
+
+        tx.send(buffer.push("app-event", json!({}))).unwrap();
+        tx.send(buffer.push("job-event", json!({ "source": "b" })))
+            .unwrap();
+        drop(tx);
+
+        let events = stream.collect::<Vec<_>>().await;
+        assert_eq!(
+            events.iter().map(|event| event.id).collect::<Vec<_>>(),
+            [1, 2, 5]
+        );
+
+        let by_source = EventFilter {
+            types: None,
+            source: Some(String::from("7")),
+        };
+        assert_eq!(
+            buffer
+                .since(0)
+                .into_iter()
+                .filter(|event| by_source.matches(event))
+                .map(|event| event.id)
+                .collect::<Vec<_>>(),
+            [2]
+        );
+    }
+}
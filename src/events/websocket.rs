@@ -0,0 +1,213 @@
+//! Events over a WebSocket, for clients behind proxies that buffer SSE and for views that only
+//! follow some of the jobs. It's fed by the same [`EventBus`] as the SSE stream, so both send the
+//! same payloads.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use axum::{
+    extract::{
+        State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    response::Response,
+};
+use serde::Deserialize;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{Instant, interval},
+};
+use ts_rs::TS;
+
+use crate::state::{JobManager, job::JobStateId};
+
+use super::{EventBus, PublishedEvent};
+
+/// How often the server pings the client.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Clients that haven't sent anything for this long, pongs included, are dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// A message from the client, e.g. `{"subscribe": {"jobs": ["scan-songs"], "fs": true}}`.
+///
+/// Messages that can't be read are answered with `{"error": "<reason>"}`.
+#[derive(Deserialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum WebSocketMessage {
+    /// Adds to the events the client is sent.
+    Subscribe(EventSubscription),
+    /// Removes from the events the client is sent.
+    Unsubscribe(EventSubscription),
+}
+
+/// Events a WebSocket client is sent, it starts out without any.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EventSubscription {
+    /// Ids of the jobs whose events are sent, e.g. `scan-songs`.
+    pub jobs: BTreeSet<String>,
+    /// Whether the events of file operations are sent.
+    pub fs: bool,
+}
+
+impl EventSubscription {
+    fn add(&mut self, other: EventSubscription) {
+        self.jobs.extend(other.jobs);
+        self.fs |= other.fs;
+    }
+
+    fn remove(&mut self, other: &EventSubscription) {
+        self.jobs.retain(|job| !other.jobs.contains(job));
+        self.fs &= !other.fs;
+    }
+}
+
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    State(events): State<EventBus>,
+    State(jobs): State<JobManager>,
+) -> Response {
+    ws.on_upgrade(move |socket| serve(socket, events, jobs))
+}
+
+/// Sends the subscribed events until the client leaves, stops answering pings or falls so far
+/// behind that events were dropped for it. Its subscription goes away with the connection.
+async fn serve(mut socket: WebSocket, events: EventBus, jobs: JobManager) {
+    let (mut rx, _) = events.subscribe_since(None);
+    let mut subscription = EventSubscription::default();
+    let mut job_ids = JobIds::new(jobs);
+
+    let mut ping = interval(PING_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let Some(Ok(message)) = message else {
+                    break;
+                };
+                last_seen = Instant::now();
+
+                match message {
+                    Message::Text(text) => match serde_json::from_str(&text) {
+                        Ok(WebSocketMessage::Subscribe(other)) => subscription.add(other),
+                        Ok(WebSocketMessage::Unsubscribe(other)) => subscription.remove(&other),
+                        Err(err) => {
+                            let error = serde_json::json!({ "error": err.to_string() });
+                            if socket.send(Message::Text(error.to_string().into())).await.is_err() {
+                                break;
+                            }
+                        }
+                    },
+                    Message::Close(_) => break,
+                    // Pings are answered by axum, pongs only show the client is still there.
+                    _ => {}
+                }
+            }
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if !wants(&subscription, &mut job_ids, &event).await {
+                        continue;
+                    }
+
+                    let text = serde_json::to_string(&event).expect("Failed to serialize event");
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("Dropping a WebSocket client that missed {missed} events");
+
+                    let close = CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: "Too slow to keep up with the events".into(),
+                    };
+                    let _ = socket.send(Message::Close(Some(close))).await;
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = ping.tick() => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    tracing::debug!("Dropping a WebSocket client that stopped answering pings");
+                    break;
+                }
+
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the client subscribed to the event.
+async fn wants(
+    subscription: &EventSubscription,
+    job_ids: &mut JobIds,
+    event: &PublishedEvent,
+) -> bool {
+    match event.category() {
+        "fs" => subscription.fs,
+        "job" if !subscription.jobs.is_empty() => {
+            // Events about no run in particular, like the order of the queue, concern every job.
+            if event.data.get("source").is_none() && event.data.get("jobId").is_none() {
+                return true;
+            }
+
+            job_ids
+                .job_id(&event.data)
+                .await
+                .is_some_and(|job_id| subscription.jobs.contains(&job_id))
+        }
+        _ => false,
+    }
+}
+
+/// Looks up the job of the runs events are about, most job events only carry the id of the run.
+struct JobIds {
+    manager: JobManager,
+    known: HashMap<JobStateId, String>,
+}
+
+impl JobIds {
+    fn new(manager: JobManager) -> Self {
+        Self {
+            manager,
+            known: HashMap::new(),
+        }
+    }
+
+    async fn job_id(&mut self, data: &serde_json::Value) -> Option<String> {
+        let source = data
+            .get("source")
+            .and_then(serde_json::Value::as_str)
+            .and_then(|source| source.parse::<JobStateId>().ok());
+
+        let job_id = data
+            .get("jobId")
+            .or_else(|| data.get("state")?.get("jobId"))
+            .and_then(serde_json::Value::as_str);
+
+        match (source, job_id) {
+            (Some(source), Some(job_id)) => {
+                self.known.insert(source, job_id.to_string());
+                Some(job_id.to_string())
+            }
+            (None, Some(job_id)) => Some(job_id.to_string()),
+            (Some(source), None) => {
+                if let Some(job_id) = self.known.get(&source) {
+                    return Some(job_id.clone());
+                }
+
+                let job_id = self.manager.states().await.get(&source)?.job_id.clone();
+                self.known.insert(source, job_id.clone());
+                Some(job_id)
+            }
+            (None, None) => None,
+        }
+    }
+}
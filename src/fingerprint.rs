@@ -0,0 +1,148 @@
+//! Chromaprint-compatible acoustic fingerprints, the kind AcoustID identifies recordings by.
+
+use std::{ops::ControlFlow, path::Path};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rusty_chromaprint::{Configuration, FingerprintCompressor, Fingerprinter, ResetError};
+
+use crate::audio::{AudioDecoder, AudioError};
+
+/// How much of the start of a song is fingerprinted, the same as `fpcalc` uses by default.
+const FINGERPRINT_LENGTH_SECS: u64 = 120;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FingerprintError {
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+    #[error("Failed to start fingerprinting: {0:?}")]
+    Start(ResetError),
+    #[error("File is too short to fingerprint")]
+    TooShort,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Compressed and base64 encoded, as printed by `fpcalc`.
+    pub fingerprint: String,
+    /// Length of the whole song.
+    pub duration_secs: u64,
+}
+
+impl Fingerprint {
+    pub fn compute(path: &Path) -> Result<Self, FingerprintError> {
+        let decoder = AudioDecoder::open(path)?;
+        let sample_rate = u64::from(decoder.sample_rate());
+        let channels = u64::from(decoder.channels());
+        let reported_duration_ms = decoder.duration_ms();
+
+        let config = Configuration::preset_test2();
+        let mut printer = Fingerprinter::new(&config);
+        printer
+            .start(decoder.sample_rate(), decoder.channels())
+            .map_err(FingerprintError::Start)?;
+
+        let limit = FINGERPRINT_LENGTH_SECS * sample_rate * channels;
+        let mut consumed = 0;
+
+        decoder.decode(|samples| {
+            let remaining = limit.saturating_sub(consumed) as usize;
+            consumed += samples.len() as u64;
+
+            if remaining > 0 {
+                let samples = samples
+                    .iter()
+                    .take(remaining)
+                    .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16)
+                    .collect::<Vec<_>>();
+
+                printer.consume(&samples);
+            }
+
+            // Without a reported duration, the rest of the file is decoded only to measure it.
+            if reported_duration_ms.is_some() && consumed >= limit {
+                Ok::<_, FingerprintError>(ControlFlow::Break(()))
+            } else {
+                Ok(ControlFlow::Continue(()))
+            }
+        })?;
+
+        printer.finish();
+
+        if printer.fingerprint().is_empty() {
+            return Err(FingerprintError::TooShort);
+        }
+
+        let compressed = FingerprintCompressor::from(&config).compress(printer.fingerprint());
+        let duration_secs = match reported_duration_ms {
+            Some(duration_ms) => duration_ms / 1000,
+            None => consumed / channels / sample_rate,
+        };
+
+        Ok(Self {
+            fingerprint: URL_SAFE_NO_PAD.encode(compressed),
+            duration_secs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    /// Writes a mono 16-bit WAV file of a rising tone.
+    fn write_tone(path: &Path, seconds: u32) {
+        const SAMPLE_RATE: u32 = 11025;
+
+        let samples = (0..SAMPLE_RATE * seconds)
+            .flat_map(|index| {
+                let time = index as f32 / SAMPLE_RATE as f32;
+                let frequency = 220.0 + 40.0 * time;
+                let sample = (time * frequency * std::f32::consts::TAU).sin() * 0.5;
+
+                ((sample * f32::from(i16::MAX)) as i16).to_le_bytes()
+            })
+            .collect::<Vec<_>>();
+
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + samples.len() as u32).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16_u32.to_le_bytes());
+        wav.extend(1_u16.to_le_bytes());
+        wav.extend(1_u16.to_le_bytes());
+        wav.extend(SAMPLE_RATE.to_le_bytes());
+        wav.extend((SAMPLE_RATE * 2).to_le_bytes());
+        wav.extend(2_u16.to_le_bytes());
+        wav.extend(16_u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend((samples.len() as u32).to_le_bytes());
+        wav.extend(samples);
+
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tone.wav");
+        write_tone(&path, 10);
+
+        let fingerprint = Fingerprint::compute(&path).unwrap();
+        assert_eq!(fingerprint.duration_secs, 10);
+        assert!(!fingerprint.fingerprint.is_empty());
+        assert!(fingerprint.fingerprint.chars().all(|character| {
+            character.is_ascii_alphanumeric() || character == '-' || character == '_'
+        }));
+        assert_eq!(Fingerprint::compute(&path).unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn test_fingerprinting_non_audio_file() {
+        assert!(matches!(
+            Fingerprint::compute(Path::new("Cargo.toml")),
+            Err(FingerprintError::Audio(_))
+        ));
+    }
+}
@@ -6,18 +6,22 @@ use std::{
     fs::{self, read_dir},
     io::{self, Read, Write},
     path::{Path, PathBuf},
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-        mpsc,
-    },
+    sync::{Arc, Condvar, Mutex, PoisonError, mpsc},
+    time::{Duration, Instant},
 };
 
 use serde::Serialize;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
 use ts_rs::TS;
 
+use crate::trash::{self, TrashEntry, TrashError, TrashedPath};
+
 const BUFFER_SIZE: usize = 64 * 1024;
-const ORDERING: Ordering = Ordering::SeqCst;
+
+/// How often a paused operation checks whether it got cancelled.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, thiserror::Error)]
 pub enum OperationError {
@@ -27,6 +31,12 @@ pub enum OperationError {
     FileNotFound(String),
     #[error("File already exists: {0}")]
     FileAlreadyExists(String),
+    #[error("Directory isn't empty: {0}")]
+    DirectoryNotEmpty(String),
+    #[error("Copy doesn't match the original, it was removed: {0}")]
+    ChecksumMismatch(String),
+    #[error(transparent)]
+    Trash(#[from] TrashError),
 }
 
 type Result<T, E = OperationError> = std::result::Result<T, E>;
@@ -42,8 +52,16 @@ pub enum OperationEvent {
     Progress {
         file_index: usize,
         file_count: usize,
+        /// Bytes copied of the current file.
         copied_bytes: u64,
+        /// Size of the current file.
         total_bytes: u64,
+        /// Bytes done over the whole operation, never more than `operation_total_bytes`.
+        operation_copied_bytes: u64,
+        /// Size of every file of the operation, counted before it started.
+        operation_total_bytes: u64,
+        /// Estimated seconds until the operation is done, once anything was copied.
+        eta_seconds: Option<u64>,
     },
     Renamed {
         from: PathBuf,
@@ -62,41 +80,214 @@ pub enum OperationEvent {
     },
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(rename = "FileOperationKind")]
+pub enum OperationKind {
+    Move,
+    Copy,
+    Delete,
+    Restore,
+}
+
 #[derive(Clone, Debug)]
 pub enum Operation {
     Move {
         paths: OperationPaths,
         overwrite: bool,
         delete_empty_directories_after: bool,
+        /// Read copied files back and compare their checksum to the original's, see
+        /// [`Operation::Copy::verify`]. Renamed files aren't copied, so they aren't verified.
+        verify: bool,
     },
     Copy {
         paths: OperationPaths,
         overwrite: bool,
+        /// Read every copy back and compare its checksum to the one of the original, which is
+        /// computed while copying. A copy that doesn't match is removed and fails the operation.
+        verify: bool,
     },
     Delete {
         paths: HashSet<PathBuf>,
+        /// Delete directories along with everything inside of them, only empty ones are deleted
+        /// otherwise.
+        recursive: bool,
+        /// The trash the paths are moved to, see [`crate::trash`]. They're deleted permanently
+        /// if not set.
+        trash: Option<PathBuf>,
+    },
+    /// Moves the files of a trash entry back to where they were deleted from.
+    Restore {
+        trash: PathBuf,
+        id: String,
+        /// Replace files that were added at the original paths since.
+        overwrite: bool,
     },
 }
 
+/// Holds operations between two files while it's paused, so the files they already touched are
+/// never left half copied or moved.
+#[derive(Debug, Clone, Default)]
+pub struct PauseGate {
+    paused: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl PauseGate {
+    pub fn pause(&self) {
+        *self.lock() = true;
+    }
+
+    pub fn resume(&self) {
+        *self.lock() = false;
+        self.paused.1.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.lock()
+    }
+
+    /// Blocks until the gate is resumed or `token` is cancelled.
+    fn wait(&self, token: &CancellationToken) {
+        let mut paused = self.lock();
+        while *paused && !token.is_cancelled() {
+            paused = self
+                .paused
+                .1
+                .wait_timeout(paused, PAUSE_POLL_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, bool> {
+        self.paused.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns whether the operation should stop, waiting first while it's paused.
+///
+/// Only called between files, which is where operations can be paused and stopped safely.
+fn should_stop(token: &CancellationToken, pause: &PauseGate) -> bool {
+    pause.wait(token);
+    token.is_cancelled()
+}
+
 impl Operation {
-    pub fn execute(self, tx: &mpsc::Sender<OperationEvent>, stop_flag: &AtomicBool) -> Result<()> {
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            Self::Move { .. } => OperationKind::Move,
+            Self::Copy { .. } => OperationKind::Copy,
+            Self::Delete { .. } => OperationKind::Delete,
+            Self::Restore { .. } => OperationKind::Restore,
+        }
+    }
+
+    /// Returns the number of files and bytes under the source paths of the operation.
+    ///
+    /// Paths that can't be read are skipped, the operation reports them once it runs.
+    pub fn totals(&self) -> (usize, u64) {
+        let sources: Vec<PathBuf> = match self {
+            Self::Move { paths, .. } | Self::Copy { paths, .. } => paths.keys().cloned().collect(),
+            Self::Delete { paths, .. } => paths.iter().cloned().collect(),
+            Self::Restore { trash, id, .. } => trash::read_entry(trash, id)
+                .map(|entry| {
+                    let dir = entry.dir(trash);
+                    entry
+                        .paths
+                        .iter()
+                        .map(|path| dir.join(&path.trashed))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        sources
+            .into_iter()
+            .filter_map(|path| Some((path_file_count(&path).ok()?, path_size(&path).ok()?)))
+            .fold((0, 0), |(files, bytes), (path_files, path_bytes)| {
+                (files + path_files, bytes + path_bytes)
+            })
+    }
+
+    /// Runs the operation until it's done or `token` is cancelled.
+    ///
+    /// The token is checked between files and between every buffer of a copy, a file that was
+    /// only partially copied when the operation got cancelled is removed.
+    pub fn execute(
+        self,
+        tx: &mpsc::Sender<OperationEvent>,
+        token: &CancellationToken,
+    ) -> Result<()> {
+        self.execute_pausable(tx, token, &PauseGate::default())
+    }
+
+    /// Like [`Operation::execute`], holding the operation between files while `pause` is paused.
+    pub fn execute_pausable(
+        self,
+        tx: &mpsc::Sender<OperationEvent>,
+        token: &CancellationToken,
+        pause: &PauseGate,
+    ) -> Result<()> {
+        send_event(tx, OperationEvent::Started);
+
+        let (file_count, total_bytes) = self.totals();
+
         match self {
             Self::Move {
                 paths,
                 overwrite,
                 delete_empty_directories_after,
-            } => Self::execute_move(
+                verify,
+            } => {
+                let mut progress =
+                    OperationProgress::new(tx, file_count, total_bytes).verifying(verify);
+                Self::execute_move(
+                    paths,
+                    overwrite,
+                    delete_empty_directories_after,
+                    &mut progress,
+                    token,
+                    pause,
+                )?
+            }
+            Self::Copy {
                 paths,
                 overwrite,
-                delete_empty_directories_after,
-                tx,
-                stop_flag,
-            )?,
-            Self::Copy { paths, overwrite } => Self::execute_copy(paths, overwrite, tx, stop_flag)?,
-            Self::Delete { paths } => Self::execute_delete(paths, tx, stop_flag)?,
+                verify,
+            } => {
+                let mut progress =
+                    OperationProgress::new(tx, file_count, total_bytes).verifying(verify);
+                Self::execute_copy(paths, overwrite, &mut progress, token, pause)?
+            }
+            Self::Delete {
+                paths,
+                recursive,
+                trash: Some(trash),
+            } => {
+                let mut progress = OperationProgress::new(tx, file_count, total_bytes);
+                Self::execute_trash(paths, recursive, &trash, &mut progress, token, pause)?
+            }
+            Self::Delete {
+                paths,
+                recursive,
+                trash: None,
+            } => Self::execute_delete(paths, recursive, tx, token, pause)?,
+            Self::Restore {
+                trash,
+                id,
+                overwrite,
+            } => {
+                let mut progress = OperationProgress::new(tx, file_count, total_bytes);
+                Self::execute_restore(&trash, &id, overwrite, &mut progress, token, pause)?
+            }
+        }
+
+        if token.is_cancelled() {
+            send_event(tx, OperationEvent::Cancelled);
+        } else {
+            send_event(tx, OperationEvent::Completed);
         }
 
-        send_event(tx, OperationEvent::Completed);
         Ok(())
     }
 
@@ -104,12 +295,13 @@ impl Operation {
         paths: OperationPaths,
         overwrite: bool,
         delete_empty_directories_after: bool,
-        tx: &mpsc::Sender<OperationEvent>,
-        stop_flag: &AtomicBool,
+        progress: &mut OperationProgress,
+        token: &CancellationToken,
+        pause: &PauseGate,
     ) -> Result<()> {
-        let count = paths.len();
-        for (index, (from, to)) in paths.iter().enumerate() {
-            if Self::is_stopped(stop_flag, tx) {
+        let tx = progress.tx;
+        for (from, to) in paths.iter() {
+            if should_stop(token, pause) {
                 return Ok(());
             }
 
@@ -119,11 +311,7 @@ impl Operation {
                 ));
             }
 
-            let to = if to.is_dir() {
-                to.join(from.file_name().expect("File name should exist"))
-            } else {
-                to.to_path_buf()
-            };
+            let to = target_path(from, to);
 
             if to.exists() && !overwrite {
                 return Err(OperationError::FileAlreadyExists(
@@ -133,8 +321,12 @@ impl Operation {
 
             log::trace!("Moving {from:?} to {to:?}");
 
+            let files = path_file_count(from).unwrap_or(1);
+            let size = progress.file_bytes(path_size(from).unwrap_or_default());
             match fs::rename(from, &to) {
                 Ok(_) => {
+                    progress.report(size, size);
+                    progress.finish(files, size);
                     send_event(
                         tx,
                         OperationEvent::Renamed {
@@ -144,29 +336,20 @@ impl Operation {
                     );
                 }
 
-                Err(err) => {
-                    if err.kind() != io::ErrorKind::CrossesDevices {
-                        return Err(OperationError::from(err));
+                // Directories can't be renamed onto a directory that isn't empty, so they're
+                // merged into it file by file like across devices.
+                Err(err)
+                    if err.kind() == io::ErrorKind::CrossesDevices
+                        || (overwrite
+                            && from.is_dir()
+                            && err.kind() == io::ErrorKind::DirectoryNotEmpty) =>
+                {
+                    if !transfer_tree(from, &to, overwrite, true, progress, token, pause)? {
+                        return Ok(());
                     }
-
-                    move_file(
-                        from,
-                        &to,
-                        overwrite,
-                        stop_flag,
-                        |copied_bytes, total_bytes| {
-                            handle_progress(copied_bytes, total_bytes, index, count, stop_flag, tx)
-                        },
-                    )?;
-
-                    send_event(
-                        tx,
-                        OperationEvent::Moved {
-                            from: from.to_path_buf(),
-                            to: to.to_path_buf(),
-                        },
-                    );
                 }
+
+                Err(err) => return Err(OperationError::from(err)),
             }
 
             if delete_empty_directories_after {
@@ -188,30 +371,26 @@ impl Operation {
     fn execute_copy(
         paths: OperationPaths,
         overwrite: bool,
-        tx: &mpsc::Sender<OperationEvent>,
-        stop_flag: &AtomicBool,
+        progress: &mut OperationProgress,
+        token: &CancellationToken,
+        pause: &PauseGate,
     ) -> Result<()> {
-        let count = paths.len();
-        for (index, (from, to)) in paths.iter().enumerate() {
-            if Self::is_stopped(stop_flag, tx) {
+        for (from, to) in paths.iter() {
+            if should_stop(token, pause) {
                 return Ok(());
             }
 
-            let to = if to.is_dir() {
-                to.join(from.file_name().expect("File name should exist"))
-            } else {
-                to.to_path_buf()
-            };
-
-            copy_file(
+            if !transfer_tree(
                 from,
-                to,
+                &target_path(from, to),
                 overwrite,
-                stop_flag,
-                |copied_bytes, total_bytes| {
-                    handle_progress(copied_bytes, total_bytes, index, count, stop_flag, tx);
-                },
-            )?;
+                false,
+                progress,
+                token,
+                pause,
+            )? {
+                return Ok(());
+            }
         }
 
         Ok(())
@@ -219,39 +398,311 @@ impl Operation {
 
     fn execute_delete(
         paths: HashSet<PathBuf>,
+        recursive: bool,
         tx: &mpsc::Sender<OperationEvent>,
-        stop_flag: &AtomicBool,
+        token: &CancellationToken,
+        pause: &PauseGate,
     ) -> Result<()> {
         for path in paths {
-            if Self::is_stopped(stop_flag, tx) {
+            if should_stop(token, pause) {
                 return Ok(());
             }
 
-            if path.is_dir() {
-                fs::remove_dir(&path)?;
-            } else {
-                fs::remove_file(&path)?;
+            if !recursive || !path.is_dir() {
+                if path.is_dir() {
+                    fs::remove_dir(&path)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+
+                send_event(tx, OperationEvent::Deleted { path });
+                continue;
             }
 
-            send_event(
-                tx,
-                OperationEvent::Deleted {
-                    path: path.to_path_buf(),
-                },
-            );
+            // Directories are removed deepest first once their files are gone, so a cancelled
+            // delete only leaves behind directories that still hold what wasn't deleted yet.
+            let entries = tree_entries(&path, &path)?;
+            let (directories, files): (Vec<_>, Vec<_>) =
+                entries.into_iter().partition(|entry| entry.is_dir);
+
+            for entry in files.into_iter().chain(directories.into_iter().rev()) {
+                if should_stop(token, pause) {
+                    return Ok(());
+                }
+
+                if entry.is_dir {
+                    fs::remove_dir(&entry.from)?;
+                } else {
+                    fs::remove_file(&entry.from)?;
+                }
+
+                send_event(tx, OperationEvent::Deleted { path: entry.from });
+            }
         }
 
         Ok(())
     }
 
-    fn is_stopped(stop_flag: &AtomicBool, tx: &mpsc::Sender<OperationEvent>) -> bool {
-        if stop_flag.load(ORDERING) {
-            send_event(tx, OperationEvent::Cancelled);
-            return true;
+    /// Moves the paths into a new entry of the trash, the manifest is saved after every path so
+    /// it always lists what's in the entry.
+    fn execute_trash(
+        paths: HashSet<PathBuf>,
+        recursive: bool,
+        trash: &Path,
+        progress: &mut OperationProgress,
+        token: &CancellationToken,
+        pause: &PauseGate,
+    ) -> Result<()> {
+        let mut entry = TrashEntry::new(OffsetDateTime::now_utc());
+        let dir = entry.dir(trash);
+
+        for path in paths {
+            if should_stop(token, pause) {
+                break;
+            }
+
+            if fs::symlink_metadata(&path).is_err() {
+                return Err(OperationError::FileNotFound(
+                    path.to_string_lossy().to_string(),
+                ));
+            }
+
+            if !recursive && path.is_dir() && read_dir(&path)?.next().is_some() {
+                return Err(OperationError::DirectoryNotEmpty(
+                    path.to_string_lossy().to_string(),
+                ));
+            }
+
+            let trashed = TrashEntry::trashed_path(&path);
+            let to = dir.join(&trashed);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let files = path_file_count(&path).unwrap_or(1);
+            let size = path_size(&path).unwrap_or_default();
+            let trashed_all = match fs::rename(&path, &to) {
+                Ok(_) => {
+                    progress.report(size, size);
+                    progress.finish(files, size);
+                    true
+                }
+                // The trash is on another disk, so the files are copied over one by one.
+                Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                    transfer_tree(&path, &to, false, true, progress, token, pause)?
+                }
+                Err(err) => return Err(OperationError::from(err)),
+            };
+
+            // Whatever made it into the trash before a cancellation is listed, so it can still
+            // be restored.
+            if to.exists() {
+                entry.size += path_size(&to).unwrap_or_default();
+                entry.paths.push(TrashedPath {
+                    original: path.clone(),
+                    trashed,
+                });
+                entry.save(trash)?;
+            }
+
+            if !trashed_all {
+                break;
+            }
+
+            send_event(progress.tx, OperationEvent::Deleted { path });
+        }
+
+        if entry.paths.is_empty() && dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the paths of a trash entry back, the entry is removed once they all are.
+    fn execute_restore(
+        trash: &Path,
+        id: &str,
+        overwrite: bool,
+        progress: &mut OperationProgress,
+        token: &CancellationToken,
+        pause: &PauseGate,
+    ) -> Result<()> {
+        let mut entry = trash::read_entry(trash, id)?;
+        let dir = entry.dir(trash);
+
+        while let Some(path) = entry.paths.first().cloned() {
+            if should_stop(token, pause) {
+                return Ok(());
+            }
+
+            let from = dir.join(&path.trashed);
+            let to = &path.original;
+            let size = path_size(&from).unwrap_or_default();
+
+            if fs::symlink_metadata(&from).is_ok() {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                // A directory that was created again since is merged with the restored one.
+                let merge = from.is_dir() && to.is_dir();
+                if to.exists() && !merge && !overwrite {
+                    return Err(OperationError::FileAlreadyExists(
+                        to.to_string_lossy().to_string(),
+                    ));
+                }
+
+                let renamed = !merge
+                    && match fs::rename(&from, to) {
+                        Ok(_) => true,
+                        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => false,
+                        Err(err) => return Err(OperationError::from(err)),
+                    };
+
+                if renamed {
+                    let files = path_file_count(to).unwrap_or(1);
+                    progress.report(size, size);
+                    progress.finish(files, size);
+                    send_event(
+                        progress.tx,
+                        OperationEvent::Renamed {
+                            from,
+                            to: to.clone(),
+                        },
+                    );
+                } else if !transfer_tree(&from, to, overwrite, true, progress, token, pause)? {
+                    entry.size = path_size(&dir).unwrap_or_default();
+                    entry.save(trash)?;
+                    return Ok(());
+                }
+            }
+
+            entry.paths.remove(0);
+            entry.size = entry.size.saturating_sub(size);
+
+            if entry.paths.is_empty() {
+                fs::remove_dir_all(&dir)?;
+            } else {
+                entry.save(trash)?;
+            }
         }
 
-        false
+        Ok(())
+    }
+}
+
+/// Returns where `from` ends up when moved or copied to `to`, which is inside of `to` if it's an
+/// existing directory.
+fn target_path(from: &Path, to: &Path) -> PathBuf {
+    if to.is_dir() {
+        to.join(from.file_name().expect("File name should exist"))
+    } else {
+        to.to_path_buf()
+    }
+}
+
+/// A file or directory found while walking a tree, along with where it ends up.
+#[derive(Debug)]
+struct TreeEntry {
+    from: PathBuf,
+    to: PathBuf,
+    is_dir: bool,
+}
+
+/// Returns `from` and everything inside of it, every directory comes before its contents.
+///
+/// Symbolic links aren't followed, they're treated like files.
+fn tree_entries(from: &Path, to: &Path) -> io::Result<Vec<TreeEntry>> {
+    let is_dir = fs::symlink_metadata(from)?.is_dir();
+    let mut entries = vec![TreeEntry {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        is_dir,
+    }];
+
+    if is_dir {
+        let mut children = read_dir(from)?.collect::<io::Result<Vec<_>>>()?;
+        children.sort_by_key(|child| child.file_name());
+
+        for child in children {
+            entries.extend(tree_entries(&child.path(), &to.join(child.file_name()))?);
+        }
     }
+
+    Ok(entries)
+}
+
+/// Copies or moves `from` to `to` one file at a time with the buffered copy, creating the
+/// directories of the tree along the way.
+///
+/// Returns whether everything was transferred before `token` got cancelled. Every file is either
+/// in its old place or completely in its new one when it does, the ones that are done were
+/// reported with a `Copied` or `Moved` event. Moved directories are removed once they're empty.
+fn transfer_tree(
+    from: &Path,
+    to: &Path,
+    overwrite: bool,
+    remove_source: bool,
+    progress: &mut OperationProgress,
+    token: &CancellationToken,
+    pause: &PauseGate,
+) -> Result<bool> {
+    if !from.exists() {
+        return Err(OperationError::FileNotFound(
+            from.to_string_lossy().to_string(),
+        ));
+    }
+
+    let entries = tree_entries(from, to)?;
+
+    for entry in &entries {
+        if should_stop(token, pause) {
+            return Ok(false);
+        }
+
+        if entry.is_dir {
+            fs::create_dir_all(&entry.to)?;
+            continue;
+        }
+
+        let size = progress.file_bytes(
+            fs::metadata(&entry.from)
+                .map(|metadata| metadata.len())
+                .unwrap_or_default(),
+        );
+        let verify = progress.verify;
+        let report = |copied_bytes, total_bytes| progress.report(copied_bytes, total_bytes);
+        let transferred = if remove_source {
+            move_file(&entry.from, &entry.to, overwrite, verify, token, report)?
+        } else {
+            copy_file(&entry.from, &entry.to, overwrite, verify, token, report)?
+        };
+
+        if !transferred {
+            return Ok(false);
+        }
+
+        progress.finish(1, size);
+
+        let (from, to) = (entry.from.clone(), entry.to.clone());
+        send_event(
+            progress.tx,
+            if remove_source {
+                OperationEvent::Moved { from, to }
+            } else {
+                OperationEvent::Copied { from, to }
+            },
+        );
+    }
+
+    if remove_source {
+        for entry in entries.iter().rev().filter(|entry| entry.is_dir) {
+            fs::remove_dir(&entry.from)?;
+        }
+    }
+
+    Ok(true)
 }
 
 /// Returns the size of the path in bytes
@@ -278,15 +729,44 @@ pub fn path_size<P: AsRef<Path>>(path: P) -> Result<u64> {
     Ok(bytes)
 }
 
+/// Returns the number of files in the path, a path to a file counts as one
+pub fn path_file_count<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let metadata = fs::symlink_metadata(path.as_ref())?;
+
+    if !metadata.is_dir() {
+        return Ok(1);
+    }
+
+    let mut count = 0;
+    for entry in read_dir(&path)? {
+        let entry = entry?;
+
+        if entry.metadata()?.is_dir() {
+            count += path_file_count(entry.path())?;
+        } else {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Copies `from` to `to`, returning whether the whole file was copied before `token` got
+/// cancelled.
+///
+/// When verifying, the original is hashed while it's copied and the copy is read back to compare
+/// their checksums, the progress covers both passes. A copy that's incomplete or didn't verify is
+/// removed.
 fn copy_file<P: AsRef<Path>, T: AsRef<Path>, F: FnMut(u64, u64)>(
     from: P,
     to: T,
     overwrite: bool,
-    stop_flag: &AtomicBool,
+    verify: bool,
+    token: &CancellationToken,
     mut handle_progress: F,
-) -> Result<()> {
-    if stop_flag.load(ORDERING) {
-        return Ok(());
+) -> Result<bool> {
+    if token.is_cancelled() {
+        return Ok(false);
     }
 
     if !from.as_ref().exists() {
@@ -301,45 +781,109 @@ fn copy_file<P: AsRef<Path>, T: AsRef<Path>, F: FnMut(u64, u64)>(
 
     let mut file_from = fs::File::open(from)?;
     let file_size = file_from.metadata()?.len();
+    let total_bytes = if verify { file_size * 2 } else { file_size };
     let mut buffer = vec![0; BUFFER_SIZE];
-    let mut copied_bytes: u64 = 0;
+    let mut source_hash = verify.then(Sha256::new);
 
     let mut file_to = fs::File::create(&to)?;
 
-    while !stop_flag.load(ORDERING) && !buffer.is_empty() {
-        match file_from.read(&mut buffer) {
+    let copied_bytes = read_buffered(&mut file_from, &mut buffer, token, |chunk, read_bytes| {
+        file_to.write_all(chunk)?;
+        if let Some(hasher) = &mut source_hash {
+            hasher.update(chunk);
+        }
+        handle_progress(read_bytes, total_bytes);
+        Ok(())
+    })?;
+
+    if token.is_cancelled() && copied_bytes != file_size {
+        drop(file_to);
+        remove_copy(to.as_ref(), "partially copied");
+        return Ok(false);
+    }
+
+    let Some(source_hash) = source_hash else {
+        return Ok(true);
+    };
+
+    // Makes sure the copy is written out before it's read back, so failed writes surface here.
+    file_to.sync_all()?;
+    drop(file_to);
+
+    let mut copy_hash = Sha256::new();
+    let verified_bytes = read_buffered(
+        &mut fs::File::open(&to)?,
+        &mut buffer,
+        token,
+        |chunk, read_bytes| {
+            copy_hash.update(chunk);
+            handle_progress(copied_bytes + read_bytes, total_bytes);
+            Ok(())
+        },
+    )?;
+
+    if token.is_cancelled() && verified_bytes != copied_bytes {
+        remove_copy(to.as_ref(), "partially verified");
+        return Ok(false);
+    }
+
+    if copy_hash.finalize() != source_hash.finalize() {
+        remove_copy(to.as_ref(), "mismatched");
+        return Err(OperationError::ChecksumMismatch(
+            to.as_ref().to_string_lossy().to_string(),
+        ));
+    }
+
+    Ok(true)
+}
+
+/// Reads `file` to its end one buffer at a time, handing every chunk to `handle_chunk` along with
+/// the bytes read so far. Stops early if `token` gets cancelled, returns the bytes read.
+fn read_buffered(
+    file: &mut fs::File,
+    buffer: &mut [u8],
+    token: &CancellationToken,
+    mut handle_chunk: impl FnMut(&[u8], u64) -> io::Result<()>,
+) -> Result<u64> {
+    let mut read_bytes: u64 = 0;
+
+    while !token.is_cancelled() {
+        match file.read(buffer) {
             Ok(0) => break,
             Ok(n) => {
-                file_to.write_all(&buffer[..n])?;
-                copied_bytes += n as u64;
-                handle_progress(copied_bytes, file_size);
+                read_bytes += n as u64;
+                handle_chunk(&buffer[..n], read_bytes)?;
             }
             Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
             Err(err) => return Err(OperationError::from(err)),
         }
     }
 
-    if stop_flag.load(ORDERING) && file_to.metadata()?.len() != file_size {
-        let _ = std::fs::remove_file(&to);
-    }
+    Ok(read_bytes)
+}
 
-    Ok(())
+/// Removes a copy that can't be kept, `reason` describes it in the log if that fails.
+fn remove_copy(path: &Path, reason: &str) {
+    if let Err(err) = fs::remove_file(path) {
+        log::warn!("Failed to remove {reason} {path:?}: {err}");
+    }
 }
 
 fn move_file<P: AsRef<Path>, T: AsRef<Path>>(
     from: P,
     to: T,
     overwrite: bool,
-    stop_flag: &AtomicBool,
+    verify: bool,
+    token: &CancellationToken,
     handle_progress: impl FnMut(u64, u64),
-) -> Result<()> {
-    copy_file(&from, to, overwrite, stop_flag, handle_progress)?;
+) -> Result<bool> {
+    let copied = copy_file(&from, to, overwrite, verify, token, handle_progress)?;
 
-    if !stop_flag.load(ORDERING) {
+    if copied {
         fs::remove_file(&from)?;
     }
 
-    Ok(())
+    Ok(copied)
 }
 
 fn send_event(tx: &mpsc::Sender<OperationEvent>, event: OperationEvent) {
@@ -348,27 +892,81 @@ fn send_event(tx: &mpsc::Sender<OperationEvent>, event: OperationEvent) {
     };
 }
 
-fn handle_progress(
-    copied_bytes: u64,
-    total_bytes: u64,
-    file_index: usize,
+/// Tracks the bytes done over a whole operation, so its progress doesn't reset with every file.
+struct OperationProgress<'t> {
+    tx: &'t mpsc::Sender<OperationEvent>,
     file_count: usize,
-    stop_flag: &AtomicBool,
-    tx: &mpsc::Sender<OperationEvent>,
-) {
-    if stop_flag.load(ORDERING) {
-        send_event(tx, OperationEvent::Cancelled);
-    }
+    /// Index of the file being transferred.
+    file_index: usize,
+    /// Bytes of the files that are done.
+    completed_bytes: u64,
+    /// Bytes of every file, counted before the operation started. Files can change size in the
+    /// meantime, so the bytes done are clamped to it.
+    total_bytes: u64,
+    started_at: Instant,
+    /// Whether copies are read back to verify them, which counts their bytes twice.
+    verify: bool,
+}
 
-    send_event(
-        tx,
-        OperationEvent::Progress {
-            file_index,
+impl<'t> OperationProgress<'t> {
+    fn new(tx: &'t mpsc::Sender<OperationEvent>, file_count: usize, total_bytes: u64) -> Self {
+        Self {
+            tx,
             file_count,
-            copied_bytes,
+            file_index: 0,
+            completed_bytes: 0,
             total_bytes,
-        },
-    );
+            started_at: Instant::now(),
+            verify: false,
+        }
+    }
+
+    /// Accounts for the verification pass of copies when `verify` is set.
+    fn verifying(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self.total_bytes = self.file_bytes(self.total_bytes);
+        self
+    }
+
+    /// Bytes the progress counts for a file of `size`, including its verification pass.
+    fn file_bytes(&self, size: u64) -> u64 {
+        if self.verify { size * 2 } else { size }
+    }
+
+    /// Bytes done over the whole operation, with `copied_bytes` of the current file.
+    fn operation_copied_bytes(&self, copied_bytes: u64) -> u64 {
+        self.completed_bytes
+            .saturating_add(copied_bytes)
+            .min(self.total_bytes)
+    }
+
+    fn report(&self, copied_bytes: u64, total_bytes: u64) {
+        let operation_copied_bytes = self.operation_copied_bytes(copied_bytes);
+        let eta_seconds = (operation_copied_bytes > 0).then(|| {
+            let elapsed = self.started_at.elapsed().as_secs_f64();
+            let remaining = self.total_bytes - operation_copied_bytes;
+            (elapsed / operation_copied_bytes as f64 * remaining as f64).round() as u64
+        });
+
+        send_event(
+            self.tx,
+            OperationEvent::Progress {
+                file_index: self.file_index,
+                file_count: self.file_count,
+                copied_bytes,
+                total_bytes,
+                operation_copied_bytes,
+                operation_total_bytes: self.total_bytes,
+                eta_seconds,
+            },
+        );
+    }
+
+    /// Counts the files as done, along with their size.
+    fn finish(&mut self, files: usize, size: u64) {
+        self.file_index += files;
+        self.completed_bytes = self.completed_bytes.saturating_add(size);
+    }
 }
 
 #[cfg(test)]
@@ -376,12 +974,14 @@ mod tests {
     use super::*;
     use std::collections::HashMap;
     use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
     use tempfile::tempdir;
     use test_log::test;
 
     #[test]
     fn test_move_operation() {
-        let stop_flag = AtomicBool::new(false);
+        let token = CancellationToken::new();
         let (junk_tx, _) = mpsc::channel();
 
         let temp = tempdir().expect("Failed to create temp dir");
@@ -401,10 +1001,10 @@ mod tests {
             paths,
             delete_empty_directories_after: true,
             overwrite: true,
+            verify: false,
         };
 
-        op.execute(&junk_tx, &stop_flag)
-            .expect("Failed to move files");
+        op.execute(&junk_tx, &token).expect("Failed to move files");
 
         let dst_file = dst_dir.join("file.txt");
         assert!(dst_file.exists(), "destination file should exist");
@@ -418,9 +1018,9 @@ mod tests {
 
     #[test]
     fn test_cancelled_move_operation() -> Result<()> {
-        let (junk_tx, _) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
 
-        let stop_flag = AtomicBool::new(false);
+        let token = CancellationToken::new();
         let temp = tempdir()?;
         let src_dir = temp.path().join("src");
         let dst_dir = temp.path().join("dst");
@@ -437,20 +1037,176 @@ mod tests {
             paths,
             delete_empty_directories_after: false,
             overwrite: true,
+            verify: false,
         };
 
-        stop_flag.store(true, ORDERING);
+        token.cancel();
+        op.execute(&tx, &token)?;
 
-        let result = op.execute(&junk_tx, &stop_flag);
+        assert!(src_file.exists(), "source file should be left in place");
+        assert!(matches!(
+            rx.try_iter().last(),
+            Some(OperationEvent::Cancelled)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_totals() -> Result<()> {
+        let temp = tempdir()?;
+        let album_dir = temp.path().join("album");
+        let nested_dir = album_dir.join("disc 2");
+
+        fs::create_dir_all(&nested_dir)?;
+        fs::write(album_dir.join("01.flac"), "hello")?;
+        fs::write(nested_dir.join("01.flac"), "hi")?;
+        fs::write(temp.path().join("single.mp3"), "abc")?;
+
+        let op = Operation::Delete {
+            paths: HashSet::from([
+                album_dir,
+                temp.path().join("single.mp3"),
+                temp.path().join("missing.mp3"),
+            ]),
+            recursive: false,
+            trash: None,
+        };
 
-        assert!(result.is_ok() || result.is_err());
+        assert_eq!(op.kind(), OperationKind::Delete);
+        assert_eq!(op.totals(), (3, 10));
 
         Ok(())
     }
 
+    #[test]
+    fn test_copy_reports_operation_progress() -> Result<()> {
+        let temp = tempdir()?;
+        let dst_dir = temp.path().join("dst");
+        fs::create_dir_all(&dst_dir)?;
+
+        let mut paths = HashMap::new();
+        for (name, size) in [("01.flac", 3 * BUFFER_SIZE), ("02.flac", 2 * BUFFER_SIZE)] {
+            let path = temp.path().join(name);
+            fs::write(&path, vec![0; size])?;
+            paths.insert(path, dst_dir.clone());
+        }
+
+        let op = Operation::Copy {
+            paths,
+            overwrite: false,
+            verify: false,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        op.execute(&tx, &CancellationToken::new())?;
+
+        let progress = rx
+            .try_iter()
+            .filter_map(|event| match event {
+                OperationEvent::Progress {
+                    operation_copied_bytes,
+                    operation_total_bytes,
+                    ..
+                } => Some((operation_copied_bytes, operation_total_bytes)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let total = 5 * BUFFER_SIZE as u64;
+        assert!(
+            progress
+                .iter()
+                .all(|(_, operation_total)| *operation_total == total)
+        );
+        assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(progress.last().map(|(copied, _)| *copied), Some(total));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verified_copy_reports_both_passes() -> Result<()> {
+        let temp = tempdir()?;
+        let src_file = temp.path().join("01.flac");
+        let dst_file = temp.path().join("copy.flac");
+        let contents = (0..3 * BUFFER_SIZE)
+            .map(|byte| (byte % 251) as u8)
+            .collect::<Vec<_>>();
+        fs::write(&src_file, &contents)?;
+
+        let op = Operation::Copy {
+            paths: HashMap::from([(src_file, dst_file.clone())]),
+            overwrite: false,
+            verify: true,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        op.execute(&tx, &CancellationToken::new())?;
+
+        assert_eq!(fs::read(&dst_file)?, contents);
+
+        let progress = rx
+            .try_iter()
+            .filter_map(|event| match event {
+                OperationEvent::Progress {
+                    copied_bytes,
+                    total_bytes,
+                    operation_copied_bytes,
+                    operation_total_bytes,
+                    ..
+                } => Some((
+                    copied_bytes,
+                    total_bytes,
+                    operation_copied_bytes,
+                    operation_total_bytes,
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // The copy and the verification pass each count for the size of the file.
+        let total = 2 * contents.len() as u64;
+        assert!(progress.iter().all(|(_, file_total, _, operation_total)| {
+            *file_total == total && *operation_total == total
+        }));
+        assert_eq!(progress.len(), 6);
+        assert_eq!(progress.last(), Some(&(total, total, total, total)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_progress_is_clamped() {
+        let (tx, rx) = mpsc::channel();
+        let mut progress = OperationProgress::new(&tx, 2, 100);
+
+        // The first file grew after the operation was counted.
+        progress.report(80, 120);
+        progress.finish(1, 120);
+        progress.report(10, 10);
+
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                OperationEvent::Progress {
+                    operation_copied_bytes: 80,
+                    eta_seconds: Some(_),
+                    ..
+                },
+                OperationEvent::Progress {
+                    operation_copied_bytes: 100,
+                    eta_seconds: Some(0),
+                    ..
+                },
+            ]
+        ));
+    }
+
     #[test]
     fn test_move_file() -> Result<()> {
-        let stop_flag = AtomicBool::new(false);
+        let token = CancellationToken::new();
 
         let temp = tempdir().expect("Failed to create temp dir");
         let src_dir = temp.path().join("src");
@@ -468,7 +1224,8 @@ mod tests {
             &src_file,
             &dst_file,
             true,
-            &stop_flag,
+            false,
+            &token,
             &mut |copied_bytes, total_bytes| {
                 tracing::info!("Copied {copied_bytes} bytes out of {total_bytes}");
             },
@@ -481,4 +1238,323 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_cancelling_large_copy() -> Result<()> {
+        const FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+        let temp = tempdir()?;
+        let src_file = temp.path().join("large.flac");
+        let dst_file = temp.path().join("copy.flac");
+        fs::File::create(&src_file)?.set_len(FILE_SIZE)?;
+
+        let op = Operation::Copy {
+            paths: HashMap::from([(src_file.clone(), dst_file.clone())]),
+            overwrite: false,
+            verify: false,
+        };
+
+        let token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn({
+            let token = token.clone();
+            move || op.execute(&tx, &token)
+        });
+
+        let copied_bytes = rx
+            .iter()
+            .find_map(|event| match event {
+                OperationEvent::Progress { copied_bytes, .. } => Some(copied_bytes),
+                _ => None,
+            })
+            .expect("Copy should report progress");
+        assert!(copied_bytes < FILE_SIZE);
+
+        let cancelled_at = Instant::now();
+        token.cancel();
+        handle.join().expect("Copy thread panicked")?;
+
+        assert!(cancelled_at.elapsed() < Duration::from_secs(1));
+        assert!(!dst_file.exists(), "partial copy should be removed");
+        assert!(src_file.exists());
+
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert!(matches!(events.last(), Some(OperationEvent::Cancelled)));
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, OperationEvent::Completed))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_directory() -> Result<()> {
+        let temp = tempdir()?;
+        let album_dir = temp.path().join("album");
+        fs::create_dir_all(album_dir.join("disc 2"))?;
+        fs::write(album_dir.join("01.flac"), "one")?;
+        fs::write(album_dir.join("disc 2").join("01.flac"), "two")?;
+
+        let dst_dir = temp.path().join("dst");
+        fs::create_dir_all(&dst_dir)?;
+
+        let op = Operation::Copy {
+            paths: HashMap::from([(album_dir.clone(), dst_dir.clone())]),
+            overwrite: false,
+            verify: false,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        op.execute(&tx, &CancellationToken::new())?;
+
+        let copied = dst_dir.join("album");
+        assert_eq!(fs::read_to_string(copied.join("01.flac"))?, "one");
+        assert_eq!(
+            fs::read_to_string(copied.join("disc 2").join("01.flac"))?,
+            "two"
+        );
+        assert!(album_dir.join("01.flac").exists());
+
+        let copied_files = rx
+            .try_iter()
+            .filter_map(|event| match event {
+                OperationEvent::Copied { to, .. } => Some(to),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            copied_files,
+            [
+                copied.join("01.flac"),
+                copied.join("disc 2").join("01.flac")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_directory_file_by_file() -> Result<()> {
+        let temp = tempdir()?;
+        let album_dir = temp.path().join("album");
+        fs::create_dir_all(album_dir.join("disc 2"))?;
+        fs::write(album_dir.join("01.flac"), "one")?;
+        fs::write(album_dir.join("disc 2").join("01.flac"), "two")?;
+
+        let dst_dir = temp.path().join("dst");
+        let (tx, rx) = mpsc::channel();
+        let mut progress = OperationProgress::new(&tx, 2, 6);
+
+        let moved = transfer_tree(
+            &album_dir,
+            &dst_dir,
+            false,
+            true,
+            &mut progress,
+            &CancellationToken::new(),
+        )?;
+
+        assert!(moved);
+        assert!(!album_dir.exists(), "moved directories should be removed");
+        assert_eq!(
+            fs::read_to_string(dst_dir.join("disc 2").join("01.flac"))?,
+            "two"
+        );
+        assert_eq!(
+            rx.try_iter()
+                .filter(|event| matches!(event, OperationEvent::Moved { .. }))
+                .count(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancelled_directory_move_is_consistent() -> Result<()> {
+        let temp = tempdir()?;
+        let album_dir = temp.path().join("album");
+        fs::create_dir_all(&album_dir)?;
+        fs::write(album_dir.join("01.flac"), "one")?;
+
+        let dst_dir = temp.path().join("dst");
+        let (tx, rx) = mpsc::channel();
+        let mut progress = OperationProgress::new(&tx, 1, 3);
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let moved = transfer_tree(
+            &album_dir,
+            &dst_dir,
+            false,
+            true,
+            &mut progress,
+            &token,
+            &PauseGate::default(),
+        )?;
+
+        assert!(!moved);
+        assert!(album_dir.join("01.flac").exists());
+        assert!(!dst_dir.join("01.flac").exists());
+        assert!(
+            !rx.try_iter()
+                .any(|event| matches!(event, OperationEvent::Moved { .. }))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paused_operation_waits_between_files() -> Result<()> {
+        let temp = tempdir()?;
+        let src_file = temp.path().join("file.txt");
+        let dst_file = temp.path().join("copy.txt");
+        fs::write(&src_file, "hello")?;
+
+        let op = Operation::Copy {
+            paths: HashMap::from([(src_file, dst_file.clone())]),
+            overwrite: false,
+            verify: false,
+        };
+        let pause = PauseGate::default();
+        pause.pause();
+
+        let (tx, _rx) = mpsc::channel();
+        let handle = thread::spawn({
+            let pause = pause.clone();
+            move || op.execute_pausable(&tx, &CancellationToken::new(), &pause)
+        });
+
+        thread::sleep(Duration::from_millis(300));
+        assert!(
+            !dst_file.exists(),
+            "paused operation shouldn't copy anything"
+        );
+
+        pause.resume();
+        handle.join().expect("Operation thread panicked")?;
+        assert!(dst_file.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_directory() -> Result<()> {
+        let temp = tempdir()?;
+        let album_dir = temp.path().join("album");
+        fs::create_dir_all(album_dir.join("disc 2"))?;
+        fs::write(album_dir.join("01.flac"), "one")?;
+        fs::write(album_dir.join("disc 2").join("01.flac"), "two")?;
+
+        let (tx, rx) = mpsc::channel();
+        let token = CancellationToken::new();
+
+        let op = Operation::Delete {
+            paths: HashSet::from([album_dir.clone()]),
+            recursive: false,
+            trash: None,
+        };
+        assert!(op.execute(&tx, &token).is_err());
+        assert!(album_dir.join("01.flac").exists());
+
+        let op = Operation::Delete {
+            paths: HashSet::from([album_dir.clone()]),
+            recursive: true,
+            trash: None,
+        };
+        op.execute(&tx, &token)?;
+        assert!(!album_dir.exists());
+
+        let deleted = rx
+            .try_iter()
+            .filter_map(|event| match event {
+                OperationEvent::Deleted { path } => Some(path),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            deleted,
+            [
+                album_dir.join("01.flac"),
+                album_dir.join("disc 2").join("01.flac"),
+                album_dir.join("disc 2"),
+                album_dir.clone(),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trash_and_restore() -> Result<()> {
+        let temp = tempdir()?;
+        let trash = temp.path().join("trash");
+        let album_dir = temp.path().join("album");
+        let single = temp.path().join("single.mp3");
+        fs::create_dir_all(&album_dir)?;
+        fs::write(album_dir.join("01.flac"), "one")?;
+        fs::write(&single, "two")?;
+
+        let (tx, rx) = mpsc::channel();
+        let token = CancellationToken::new();
+
+        let op = Operation::Delete {
+            paths: HashSet::from([album_dir.clone()]),
+            recursive: false,
+            trash: Some(trash.clone()),
+        };
+        assert!(matches!(
+            op.execute(&tx, &token),
+            Err(OperationError::DirectoryNotEmpty(_))
+        ));
+
+        let op = Operation::Delete {
+            paths: HashSet::from([album_dir.clone(), single.clone()]),
+            recursive: true,
+            trash: Some(trash.clone()),
+        };
+        op.execute(&tx, &token)?;
+
+        assert!(!album_dir.exists());
+        assert!(!single.exists());
+        assert_eq!(
+            rx.try_iter()
+                .filter(|event| matches!(event, OperationEvent::Deleted { .. }))
+                .count(),
+            2
+        );
+
+        let [entry] = trash::list_entries(&trash)?.try_into().unwrap();
+        assert_eq!(entry.size, 6);
+        assert_eq!(
+            fs::read_to_string(
+                entry
+                    .dir(&trash)
+                    .join(TrashEntry::trashed_path(&album_dir.join("01.flac")))
+            )?,
+            "one"
+        );
+
+        // Files added at a deleted path since are only replaced when asked to.
+        fs::write(&single, "new")?;
+        let restore = |overwrite| Operation::Restore {
+            trash: trash.clone(),
+            id: entry.id.clone(),
+            overwrite,
+        };
+        assert!(matches!(
+            restore(false).execute(&tx, &token),
+            Err(OperationError::FileAlreadyExists(_))
+        ));
+
+        restore(true).execute(&tx, &token)?;
+
+        assert_eq!(fs::read_to_string(album_dir.join("01.flac"))?, "one");
+        assert_eq!(fs::read_to_string(&single)?, "two");
+        assert!(trash::list_entries(&trash)?.is_empty());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,124 @@
+//! Genres as they're stored in the genre field of songs, which can hold several of them joined
+//! with [`VALUE_SEPARATOR`], and merging the different spellings of a genre into one.
+
+use lofty::id3::v1::GENRES;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::metadata::VALUE_SEPARATOR;
+
+/// Genres replaced by another genre, in the library and optionally the tags of the files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreMerge {
+    /// The genres to replace, matched case-insensitively.
+    pub sources: Vec<String>,
+    /// The genre they're replaced with.
+    pub target: String,
+}
+
+impl GenreMerge {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.target.trim().is_empty() {
+            return Err(String::from("The target genre is empty"));
+        }
+
+        if self.target.contains(VALUE_SEPARATOR.trim()) {
+            return Err(format!(
+                "The target genre can't contain \"{}\"",
+                VALUE_SEPARATOR.trim()
+            ));
+        }
+
+        if self.sources.iter().all(|source| source.trim().is_empty()) {
+            return Err(String::from("No genres to merge given"));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the genre field with the sources replaced by the target, `None` if that doesn't
+    /// change it. A genre that's listed twice after the merge is only kept once.
+    pub fn apply(&self, value: &str) -> Option<String> {
+        let sources = self
+            .sources
+            .iter()
+            .map(|source| source.trim().to_lowercase())
+            .collect::<Vec<_>>();
+        let is_source = |genre: &str| sources.contains(&genre.to_lowercase());
+
+        let genres = split_genres(value).collect::<Vec<_>>();
+        if !genres.iter().any(|genre| is_source(genre)) {
+            return None;
+        }
+
+        let target = self.target.trim();
+        let mut merged = Vec::<&str>::new();
+        for genre in genres {
+            let genre = if is_source(genre) { target } else { genre };
+
+            if !merged
+                .iter()
+                .any(|existing| existing.to_lowercase() == genre.to_lowercase())
+            {
+                merged.push(genre);
+            }
+        }
+
+        let merged = merged.join(VALUE_SEPARATOR);
+        (merged != value).then_some(merged)
+    }
+}
+
+/// Splits a genre field into the genres it holds.
+pub fn split_genres(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(VALUE_SEPARATOR.trim())
+        .map(str::trim)
+        .filter(|genre| !genre.is_empty())
+}
+
+/// Returns the name of an ID3v1 genre number like `17` or `(17)`, which some taggers write
+/// instead of the name.
+pub fn id3v1_genre(genre: &str) -> Option<&'static str> {
+    let number = genre.trim();
+    let number = number
+        .strip_prefix('(')
+        .and_then(|number| number.strip_suffix(')'))
+        .unwrap_or(number);
+
+    GENRES.get(number.parse::<usize>().ok()?).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_merging_genres() {
+        let merge = GenreMerge {
+            sources: vec![
+                String::from("hip-hop"),
+                String::from("HipHop "),
+                String::from("Hip Hop"),
+            ],
+            target: String::from("Hip Hop"),
+        };
+
+        assert_eq!(merge.apply("Rock"), None);
+        assert_eq!(merge.apply("Hip Hop"), None);
+        assert_eq!(merge.apply("Hip-Hop").as_deref(), Some("Hip Hop"));
+        assert_eq!(
+            merge.apply("HIPHOP; Pop;hip hop").as_deref(),
+            Some("Hip Hop; Pop")
+        );
+        assert!(merge.validate().is_ok());
+
+        assert_eq!(id3v1_genre("(17)"), Some("Rock"));
+        assert_eq!(id3v1_genre("7"), Some("Hip-Hop"));
+        assert_eq!(id3v1_genre("Rock"), None);
+        assert_eq!(id3v1_genre("1000"), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Snapshots of a song's tags, saved before every edit so the edit can be undone.
+//!
+//! Every song gets a directory of its own, named after its id. A snapshot is saved as
+//! `<timestamp>.json`, where the timestamp is when it was taken in nanoseconds, and the cover art
+//! it replaced is saved next to it as `<timestamp>.cover-<type>`.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use ts_rs::TS;
+
+use crate::{
+    config::MetadataHistory,
+    metadata::{Metadata, item::ItemKey},
+};
+
+/// A field whose value differs between a snapshot and the file.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub key: ItemKey,
+    /// The value in the snapshot, not set if the snapshot doesn't have the field.
+    pub snapshot: Option<String>,
+    /// The value in the file, not set if the file doesn't have the field.
+    pub current: Option<String>,
+}
+
+/// Returns when each snapshot in the directory of a song was taken in nanoseconds, oldest first.
+pub fn snapshots(song_dir: &Path) -> io::Result<Vec<i128>> {
+    if !song_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut timestamps = fs::read_dir(song_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some("json".as_ref()) && path.is_file())
+        .filter_map(|path| path.file_stem()?.to_str()?.parse::<i128>().ok())
+        .collect::<Vec<_>>();
+
+    timestamps.sort_unstable();
+
+    Ok(timestamps)
+}
+
+/// Removes the snapshot along with the cover art saved with it.
+pub fn remove_snapshot(song_dir: &Path, timestamp: i128) -> io::Result<()> {
+    let prefix = format!("{timestamp}.");
+
+    for entry in fs::read_dir(song_dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(&prefix))
+        {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the snapshots of a song past the age and count limits, returning how many were
+/// removed. The directory is removed once it's empty.
+pub fn prune_song(
+    song_dir: &Path,
+    settings: &MetadataHistory,
+    now: OffsetDateTime,
+) -> io::Result<u64> {
+    let timestamps = snapshots(song_dir)?;
+    let expires_before = settings
+        .max_age()
+        .map(|max_age| (now - max_age).unix_timestamp_nanos());
+    let excess = settings.max_entries().map_or(0, |max_entries| {
+        timestamps.len().saturating_sub(max_entries)
+    });
+
+    let mut removed = 0;
+    for (index, timestamp) in timestamps.into_iter().enumerate() {
+        if index < excess || expires_before.is_some_and(|before| timestamp < before) {
+            remove_snapshot(song_dir, timestamp)?;
+            removed += 1;
+        }
+    }
+
+    if song_dir.exists() && fs::read_dir(song_dir)?.next().is_none() {
+        fs::remove_dir(song_dir)?;
+    }
+
+    Ok(removed)
+}
+
+/// Removes the snapshots of every song past the age and count limits, returning how many were
+/// removed.
+pub fn prune(root: &Path, settings: &MetadataHistory, now: OffsetDateTime) -> io::Result<u64> {
+    if !root.exists() {
+        return Ok(0);
+    }
+
+    let song_dirs = fs::read_dir(root)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<PathBuf>>();
+
+    let mut removed = 0;
+    for song_dir in song_dirs {
+        removed += prune_song(&song_dir, settings, now)?;
+    }
+
+    Ok(removed)
+}
+
+/// Returns the fields that differ between a snapshot and the metadata of the file, in the order
+/// of their keys.
+pub fn diff(snapshot: Option<&Metadata>, current: Option<&Metadata>) -> Vec<FieldChange> {
+    let keys = snapshot
+        .into_iter()
+        .chain(current)
+        .flat_map(|metadata| metadata.fields().keys())
+        .collect::<BTreeSet<_>>();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let snapshot = snapshot.and_then(|metadata| metadata.get(key)).cloned();
+            let current = current.and_then(|metadata| metadata.get(key)).cloned();
+
+            (snapshot != current).then(|| FieldChange {
+                key: key.clone(),
+                snapshot,
+                current,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tempfile::tempdir;
+    use time::Duration;
+
+    use super::*;
+
+    fn metadata(fields: &[(ItemKey, &str)]) -> Metadata {
+        Metadata::new(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), value.to_string()))
+                .collect(),
+            BTreeMap::new(),
+        )
+    }
+
+    #[test]
+    fn test_prune() {
+        let root = tempdir().unwrap();
+        let song_dir = root.path().join("song");
+        fs::create_dir(&song_dir).unwrap();
+
+        let now = OffsetDateTime::now_utc();
+        let timestamps = [
+            Duration::days(40),
+            Duration::days(3),
+            Duration::days(2),
+            Duration::days(1),
+        ]
+        .map(|age| (now - age).unix_timestamp_nanos());
+        for timestamp in timestamps {
+            fs::write(song_dir.join(format!("{timestamp}.json")), "null").unwrap();
+        }
+        fs::write(song_dir.join(format!("{}.cover-front", timestamps[0])), "").unwrap();
+
+        let settings = MetadataHistory {
+            max_entries_per_song: 2,
+            max_age_days: 30,
+        };
+
+        assert_eq!(prune(root.path(), &settings, now).unwrap(), 2);
+        assert_eq!(snapshots(&song_dir).unwrap(), timestamps[2..]);
+        assert_eq!(fs::read_dir(&song_dir).unwrap().count(), 2);
+
+        let unlimited = MetadataHistory {
+            max_entries_per_song: 0,
+            max_age_days: 0,
+        };
+        assert_eq!(prune(root.path(), &unlimited, now).unwrap(), 0);
+
+        let expired = MetadataHistory {
+            max_entries_per_song: 0,
+            max_age_days: 1,
+        };
+        assert_eq!(
+            prune(root.path(), &expired, now + Duration::days(2)).unwrap(),
+            2
+        );
+        assert!(!song_dir.exists());
+    }
+
+    #[test]
+    fn test_diff() {
+        let snapshot = metadata(&[
+            (ItemKey::Title, "Old Title"),
+            (ItemKey::Artist, "Artist"),
+            (ItemKey::Composer, "Composer"),
+        ]);
+        let current = metadata(&[
+            (ItemKey::Title, "New Title"),
+            (ItemKey::Artist, "Artist"),
+            (ItemKey::Album, "Album"),
+        ]);
+
+        assert_eq!(
+            diff(Some(&snapshot), Some(&current)),
+            [
+                FieldChange {
+                    key: ItemKey::Album,
+                    snapshot: None,
+                    current: Some(String::from("Album")),
+                },
+                FieldChange {
+                    key: ItemKey::Composer,
+                    snapshot: Some(String::from("Composer")),
+                    current: None,
+                },
+                FieldChange {
+                    key: ItemKey::Title,
+                    snapshot: Some(String::from("Old Title")),
+                    current: Some(String::from("New Title")),
+                },
+            ]
+        );
+        assert!(diff(Some(&current), Some(&current)).is_empty());
+        assert_eq!(diff(None, Some(&current)).len(), 3);
+    }
+}
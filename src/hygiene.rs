@@ -0,0 +1,385 @@
+//! Consistency checks for albums, only uses data already stored in the database.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use ts_rs::TS;
+
+use crate::db::{Album, Song};
+
+/// How many times higher or lower than the median of the album a bitrate can be, lossy encodes
+/// of the same album only vary this much with a variable bitrate.
+const BITRATE_FACTOR: f64 = 1.5;
+
+/// How many times larger or smaller than the median of the album the embedded art of a track can
+/// be, tracks usually embed the very same picture.
+const COVER_ART_SIZE_FACTOR: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum HygieneCheck {
+    MixedFormats,
+    InconsistentAlbumArtist,
+    InconsistentYear,
+    InconsistentGenre,
+    MissingTrackNumbers,
+    VaryingBitrates,
+    CoverArtSizeOutliers,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct HygieneFinding {
+    /// The check that produced the finding.
+    pub check: HygieneCheck,
+    /// Human readable description of the finding.
+    pub message: String,
+    /// Songs that differ from the rest of the album.
+    pub song_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumHygieneReport {
+    pub album: String,
+    pub findings: Vec<HygieneFinding>,
+}
+
+/// Library-wide summary of every album that has findings.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryHygieneReport {
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub generated_at: OffsetDateTime,
+    pub album_count: usize,
+    pub counts: BTreeMap<HygieneCheck, usize>,
+    pub albums: Vec<AlbumHygieneReport>,
+}
+
+impl LibraryHygieneReport {
+    pub fn new(album_count: usize, albums: Vec<AlbumHygieneReport>) -> Self {
+        let counts = albums.iter().flat_map(|album| album.findings.iter()).fold(
+            BTreeMap::new(),
+            |mut counts, finding| {
+                *counts.entry(finding.check).or_default() += 1;
+                counts
+            },
+        );
+
+        Self {
+            generated_at: OffsetDateTime::now_utc(),
+            album_count,
+            counts,
+            albums: albums
+                .into_iter()
+                .filter(|album| !album.findings.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// Runs every check against the album.
+pub fn check_album(album: &Album) -> AlbumHygieneReport {
+    let mut findings = Vec::new();
+
+    findings.extend(check_consistency(
+        &album.tracks,
+        HygieneCheck::MixedFormats,
+        "file formats",
+        |song| {
+            Path::new(&song.path)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+        },
+    ));
+
+    findings.extend(check_consistency(
+        &album.tracks,
+        HygieneCheck::InconsistentAlbumArtist,
+        "album artists",
+        |song| song.album_artist.clone(),
+    ));
+
+    findings.extend(check_consistency(
+        &album.tracks,
+        HygieneCheck::InconsistentYear,
+        "years",
+        |song| song.year.clone(),
+    ));
+
+    findings.extend(check_consistency(
+        &album.tracks,
+        HygieneCheck::InconsistentGenre,
+        "genres",
+        |song| song.genre.clone(),
+    ));
+
+    let missing_track_numbers = album
+        .tracks
+        .iter()
+        .filter(|song| song.track_number.is_none())
+        .map(|song| song.id.clone())
+        .collect::<Vec<_>>();
+
+    if !missing_track_numbers.is_empty() {
+        findings.push(HygieneFinding {
+            check: HygieneCheck::MissingTrackNumbers,
+            message: format!(
+                "{} track(s) are missing a track number",
+                missing_track_numbers.len()
+            ),
+            song_ids: missing_track_numbers,
+        });
+    }
+
+    findings.extend(check_outliers(
+        &album.tracks,
+        HygieneCheck::VaryingBitrates,
+        BITRATE_FACTOR,
+        |song| song.bitrate_kbps,
+        |bitrates| format!("Album has widely varying bitrates: {bitrates} kbps"),
+    ));
+
+    findings.extend(check_outliers(
+        &album.tracks,
+        HygieneCheck::CoverArtSizeOutliers,
+        COVER_ART_SIZE_FACTOR,
+        |song| song.cover_art_bytes,
+        |sizes| format!("Album has embedded cover art of very different sizes: {sizes} bytes"),
+    ));
+
+    AlbumHygieneReport {
+        album: album.title.clone(),
+        findings,
+    }
+}
+
+/// Reports every track whose value is more than `factor` times higher or lower than the median
+/// of the album, tracks without a value are left out. A value of `0` is only close to another
+/// `0`, so tracks missing something most of the album has are reported as well.
+fn check_outliers(
+    tracks: &[Song],
+    check: HygieneCheck,
+    factor: f64,
+    value: impl Fn(&Song) -> Option<u32>,
+    message: impl Fn(&str) -> String,
+) -> Option<HygieneFinding> {
+    let mut values = tracks
+        .iter()
+        .filter_map(|song| Some((value(song)?, song)))
+        .collect::<Vec<_>>();
+
+    if values.len() < 2 {
+        return None;
+    }
+
+    values.sort_by_key(|(value, _)| *value);
+    let median = f64::from(values[values.len() / 2].0);
+    let (min, max) = (values[0].0, values[values.len() - 1].0);
+
+    let mut song_ids = values
+        .into_iter()
+        .filter(|(value, _)| {
+            let value = f64::from(*value);
+            value * factor < median || value > median * factor
+        })
+        .map(|(_, song)| song.id.clone())
+        .collect::<Vec<_>>();
+
+    if song_ids.is_empty() {
+        return None;
+    }
+
+    song_ids.sort();
+
+    Some(HygieneFinding {
+        check,
+        message: message(&format!("{min} to {max}")),
+        song_ids,
+    })
+}
+
+/// Groups the tracks by the value returned by `value`, reporting every track outside of the
+/// most common group when there's more than one group.
+fn check_consistency(
+    tracks: &[Song],
+    check: HygieneCheck,
+    name: &str,
+    value: impl Fn(&Song) -> Option<String>,
+) -> Option<HygieneFinding> {
+    let mut groups: HashMap<Option<String>, Vec<&Song>> = HashMap::new();
+    for song in tracks {
+        groups.entry(value(song)).or_default().push(song);
+    }
+
+    if groups.len() < 2 {
+        return None;
+    }
+
+    let (majority, _) = groups
+        .iter()
+        .max_by(|(a_value, a), (b_value, b)| a.len().cmp(&b.len()).then(b_value.cmp(a_value)))?;
+
+    let majority = majority.clone();
+    let mut values = groups
+        .keys()
+        .map(|value| value.clone().unwrap_or_else(|| String::from("none")))
+        .collect::<Vec<_>>();
+
+    values.sort();
+
+    let mut song_ids = groups
+        .into_iter()
+        .filter(|(value, _)| *value != majority)
+        .flat_map(|(_, songs)| songs.into_iter().map(|song| song.id.clone()))
+        .collect::<Vec<_>>();
+
+    song_ids.sort();
+
+    Some(HygieneFinding {
+        check,
+        message: format!("Album has mixed {name}: {}", values.join(", ")),
+        song_ids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    fn song(id: &str, path: &str, year: Option<&str>, track_number: Option<u32>) -> Song {
+        Song {
+            id: id.to_string(),
+            path: path.to_string(),
+            album: Some(String::from("album")),
+            album_artist: Some(String::from("artist")),
+            genre: Some(String::from("genre")),
+            year: year.map(String::from),
+            track_number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clean_album() {
+        let album = Album::from(vec![
+            song("1", "/music/1.flac", Some("2001"), Some(1)),
+            song("2", "/music/2.flac", Some("2001"), Some(2)),
+        ]);
+
+        assert!(check_album(&album).findings.is_empty());
+    }
+
+    #[test]
+    fn test_album_with_findings() {
+        let album = Album::from(vec![
+            song("1", "/music/1.flac", Some("2001"), Some(1)),
+            song("2", "/music/2.FLAC", Some("2001"), Some(2)),
+            song("3", "/music/3.mp3", Some("2002"), None),
+        ]);
+
+        let report = check_album(&album);
+        let finding = |check| {
+            report
+                .findings
+                .iter()
+                .find(|finding| finding.check == check)
+                .map(|finding| finding.song_ids.clone())
+        };
+
+        assert_eq!(report.findings.len(), 3);
+        assert_eq!(finding(HygieneCheck::MixedFormats), Some(vec!["3".into()]));
+        assert_eq!(
+            finding(HygieneCheck::InconsistentYear),
+            Some(vec!["3".into()])
+        );
+        assert_eq!(
+            finding(HygieneCheck::MissingTrackNumbers),
+            Some(vec!["3".into()])
+        );
+    }
+
+    #[test]
+    fn test_varying_bitrates() {
+        let album = |bitrates: &[u32]| {
+            Album::from(
+                bitrates
+                    .iter()
+                    .enumerate()
+                    .map(|(index, bitrate)| Song {
+                        bitrate_kbps: Some(*bitrate),
+                        ..song(
+                            &index.to_string(),
+                            &format!("/music/{index}.mp3"),
+                            None,
+                            Some(index as u32 + 1),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        // Variable bitrates of the same encoder are close enough.
+        assert!(
+            check_album(&album(&[245, 260, 231, 290]))
+                .findings
+                .is_empty()
+        );
+
+        let report = check_album(&album(&[320, 320, 128, 320, 1000]));
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].check, HygieneCheck::VaryingBitrates);
+        assert_eq!(report.findings[0].song_ids, ["2", "4"]);
+        assert_eq!(
+            report.findings[0].message,
+            "Album has widely varying bitrates: 128 to 1000 kbps"
+        );
+    }
+
+    #[test]
+    fn test_cover_art_size_outliers() {
+        let album = |sizes: &[Option<u32>]| {
+            Album::from(
+                sizes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, size)| Song {
+                        cover_art_bytes: *size,
+                        ..song(
+                            &index.to_string(),
+                            &format!("/music/{index}.mp3"),
+                            None,
+                            Some(index as u32 + 1),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        // Songs whose art wasn't read yet aren't compared.
+        assert!(
+            check_album(&album(&[Some(50_000), None, Some(60_000)]))
+                .findings
+                .is_empty()
+        );
+        assert!(check_album(&album(&[Some(0), Some(0)])).findings.is_empty());
+
+        let report = check_album(&album(&[
+            Some(50_000),
+            Some(0),
+            Some(50_000),
+            Some(4_000_000),
+            Some(50_000),
+        ]));
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].check, HygieneCheck::CoverArtSizeOutliers);
+        assert_eq!(report.findings[0].song_ids, ["1", "3"]);
+    }
+}
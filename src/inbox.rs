@@ -0,0 +1,456 @@
+//! Albums dropped into the inbox folder, tagged from a metadata provider before they're moved
+//! into the library, see [`crate::config::Inbox`].
+//!
+//! Releases are matched by their track count and the durations of their tracks, so folders of
+//! untagged files can be identified as well.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    jobs::{is_song_file, song_walker},
+    metadata::{self, Metadata, SongFile, item::ItemKey},
+    providers::{Release, ReleaseTrack, SearchQuery},
+};
+
+/// Difference between the duration of a file and of a track past which they don't match at all,
+/// rips are usually within a second or two of the release.
+const DURATION_TOLERANCE_MS: u32 = 5000;
+
+/// Folder inside the inbox files already in the library are moved to instead of importing them.
+pub const DUPLICATES_FOLDER: &str = "duplicates";
+
+#[derive(Debug, thiserror::Error)]
+pub enum InboxError {
+    #[error("No inbox is configured")]
+    NotConfigured,
+    #[error("\"{0}\" is not a folder inside the inbox")]
+    OutsideInbox(PathBuf),
+    #[error("\"{0}\" doesn't contain any songs")]
+    NoTracks(PathBuf),
+    #[error("\"{0}\" is not a song of the folder")]
+    UnknownTrack(PathBuf),
+    #[error("Failed to write tags: {0}")]
+    Metadata(#[from] metadata::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A song file in an inbox folder, with whatever tags it already has.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxTrack {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub disc_number: Option<u32>,
+    pub track_number: Option<u32>,
+    pub duration_ms: Option<u32>,
+}
+
+impl InboxTrack {
+    /// Files are ordered by disc and track number, or by path if they aren't tagged with one.
+    fn order(&self) -> (u32, u32, &Path) {
+        (
+            self.disc_number.unwrap_or(1),
+            self.track_number.unwrap_or(u32::MAX),
+            &self.path,
+        )
+    }
+}
+
+/// The tags proposed for a file of an inbox folder, applying it writes every tag that's set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackProposal {
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: String,
+    pub album_artist: Option<String>,
+    pub disc_number: u32,
+    pub track_number: u32,
+    pub year: Option<String>,
+}
+
+/// A release the folder might be, along with the tags it would give every file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxCandidate {
+    /// Id of the release within the provider.
+    pub release_id: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub date: Option<String>,
+    /// How well the durations of the files match the tracks from 0 to 100.
+    pub score: u8,
+    /// Tags for every file of the folder, in the order of the release.
+    pub tracks: Vec<TrackProposal>,
+}
+
+/// Returns the folder at `path` if it's inside the inbox, symlinks and `..` are resolved first
+/// so a path can't point outside of it.
+pub fn resolve_folder(inbox: &Path, path: &Path) -> Result<PathBuf, InboxError> {
+    let outside = || InboxError::OutsideInbox(path.to_path_buf());
+
+    let inbox = inbox.canonicalize()?;
+    let folder = path.canonicalize().map_err(|_| outside())?;
+
+    if folder == inbox || !folder.starts_with(&inbox) || !folder.is_dir() {
+        return Err(outside());
+    }
+
+    Ok(folder)
+}
+
+/// Moves a file of the inbox into its [duplicates folder](DUPLICATES_FOLDER), keeping the path
+/// it had inside the inbox, and returns where it ended up.
+///
+/// Files already in the duplicates folder are left where they are, and a file that's already at
+/// the new path is never overwritten.
+pub fn set_aside_duplicate(inbox: &Path, path: &Path) -> Result<PathBuf, InboxError> {
+    let inbox = inbox.canonicalize()?;
+    let duplicates = inbox.join(DUPLICATES_FOLDER);
+    if path.starts_with(&duplicates) {
+        return Ok(path.to_path_buf());
+    }
+
+    let relative = path
+        .strip_prefix(&inbox)
+        .map_err(|_| InboxError::OutsideInbox(path.to_path_buf()))?;
+    let target = duplicates.join(relative);
+    if target.try_exists()? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("\"{}\" already exists", target.display()),
+        )
+        .into());
+    }
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(path, &target)?;
+
+    Ok(target)
+}
+
+/// Reads the song files of the folder and its subfolders, sorted by disc and track number when
+/// they're tagged with one and by path otherwise.
+pub fn read_tracks(
+    folder: &Path,
+    file_types: &BTreeSet<String>,
+) -> Result<Vec<InboxTrack>, InboxError> {
+    let mut tracks = song_walker(folder)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+        })
+        .filter(|entry| is_song_file(entry.path(), file_types))
+        .map(|entry| read_track(entry.path()))
+        .collect::<Vec<_>>();
+
+    if tracks.is_empty() {
+        return Err(InboxError::NoTracks(folder.to_path_buf()));
+    }
+
+    tracks.sort_by(|a, b| a.order().cmp(&b.order()));
+
+    Ok(tracks)
+}
+
+/// Reads the tags and duration of the file, files that can't be read are listed without them.
+fn read_track(path: &Path) -> InboxTrack {
+    let file = SongFile::open(path)
+        .inspect_err(|err| tracing::warn!("Failed to read \"{}\": {err}", path.display()))
+        .ok();
+    let metadata = file.as_ref().and_then(|file| file.metadata().as_ref());
+    let tag = |key| metadata.and_then(|metadata| metadata.get(&key)).cloned();
+
+    InboxTrack {
+        path: path.to_path_buf(),
+        title: tag(ItemKey::Title),
+        artist: tag(ItemKey::Artist),
+        album: tag(ItemKey::Album),
+        disc_number: metadata.and_then(|metadata| metadata.disc().number),
+        track_number: metadata.and_then(|metadata| metadata.track().number),
+        duration_ms: file
+            .as_ref()
+            .and_then(|file| file.properties())
+            .and_then(|properties| properties.duration_ms),
+    }
+}
+
+/// Builds the search for the folder out of the album and artist most of its files are tagged
+/// with, falling back to the name of the folder for untagged albums.
+pub fn search_query(folder: &Path, tracks: &[InboxTrack]) -> SearchQuery {
+    let most_common = |values: Vec<&String>| {
+        let mut counts = BTreeMap::new();
+        for value in values {
+            *counts.entry(value).or_insert(0) += 1;
+        }
+
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(value, _)| value.clone())
+    };
+
+    let album = most_common(
+        tracks
+            .iter()
+            .filter_map(|track| track.album.as_ref())
+            .collect(),
+    )
+    .or_else(|| Some(folder.file_name()?.to_string_lossy().to_string()));
+    let artist = most_common(
+        tracks
+            .iter()
+            .filter_map(|track| track.artist.as_ref())
+            .collect(),
+    );
+
+    SearchQuery {
+        artist,
+        album,
+        title: None,
+    }
+}
+
+/// Matches the files of the folder to the tracks of the release in order, nothing if the release
+/// has a different amount of tracks.
+pub fn match_release(tracks: &[InboxTrack], release: &Release) -> Option<InboxCandidate> {
+    if tracks.len() != release.tracks.len() {
+        return None;
+    }
+
+    let mut release_tracks = release.tracks.iter().collect::<Vec<_>>();
+    release_tracks.sort_by_key(|track| (track.disc_number, track.track_number));
+
+    let similarity = tracks
+        .iter()
+        .zip(&release_tracks)
+        .map(|(track, release_track)| duration_similarity(track, release_track))
+        .sum::<f64>()
+        / tracks.len() as f64;
+
+    let year = release
+        .date
+        .as_ref()
+        .and_then(|date| date.get(..4))
+        .map(str::to_string);
+
+    Some(InboxCandidate {
+        release_id: release.id.clone(),
+        title: release.title.clone(),
+        artist: release.artist.clone(),
+        date: release.date.clone(),
+        score: (similarity * 100.0).round() as u8,
+        tracks: tracks
+            .iter()
+            .zip(release_tracks)
+            .map(|(track, release_track)| TrackProposal {
+                path: track.path.clone(),
+                title: release_track.title.clone(),
+                artist: release_track
+                    .artist
+                    .clone()
+                    .or_else(|| release.artist.clone()),
+                album: release.title.clone(),
+                album_artist: release.artist.clone(),
+                disc_number: release_track.disc_number,
+                track_number: release_track.track_number,
+                year: year.clone(),
+            })
+            .collect(),
+    })
+}
+
+/// How close the durations are from 0 to 1, tracks without a known duration count as half a
+/// match so they neither confirm nor rule out the release.
+fn duration_similarity(track: &InboxTrack, release_track: &ReleaseTrack) -> f64 {
+    match (track.duration_ms, release_track.length_ms) {
+        (Some(duration_ms), Some(length_ms)) => {
+            let difference = duration_ms.abs_diff(length_ms).min(DURATION_TOLERANCE_MS);
+            1.0 - f64::from(difference) / f64::from(DURATION_TOLERANCE_MS)
+        }
+        _ => 0.5,
+    }
+}
+
+/// Writes the proposed tags to the file, keeping its other tags.
+pub fn write_proposal(proposal: &TrackProposal) -> Result<(), InboxError> {
+    let mut file = SongFile::open(&proposal.path)?;
+    let mut metadata = file
+        .metadata()
+        .clone()
+        .unwrap_or_else(|| Metadata::new(BTreeMap::new(), BTreeMap::new()));
+
+    for (key, value) in [
+        (ItemKey::Title, Some(proposal.title.clone())),
+        (ItemKey::Artist, proposal.artist.clone()),
+        (ItemKey::Album, Some(proposal.album.clone())),
+        (ItemKey::AlbumArtist, proposal.album_artist.clone()),
+        (ItemKey::DiscNumber, Some(proposal.disc_number.to_string())),
+        (
+            ItemKey::TrackNumber,
+            Some(proposal.track_number.to_string()),
+        ),
+        (ItemKey::Year, proposal.year.clone()),
+    ] {
+        if let Some(value) = value {
+            metadata.insert(key, value);
+        }
+    }
+
+    file.set_metadata(metadata);
+    file.write()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    fn track(name: &str, duration_ms: Option<u32>) -> InboxTrack {
+        InboxTrack {
+            path: PathBuf::from(format!("/inbox/album/{name}.flac")),
+            title: None,
+            artist: None,
+            album: None,
+            disc_number: None,
+            track_number: None,
+            duration_ms,
+        }
+    }
+
+    fn release(lengths: &[Option<u32>]) -> Release {
+        Release {
+            id: String::from("release"),
+            title: String::from("Album"),
+            artist: Some(String::from("Band")),
+            date: Some(String::from("2024-05-01")),
+            tracks: lengths
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(index, length_ms)| ReleaseTrack {
+                    disc_number: 1,
+                    track_number: index as u32 + 1,
+                    title: format!("Track {}", index + 1),
+                    artist: None,
+                    length_ms: *length_ms,
+                    recording_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_match_release() {
+        let tracks = [track("a", Some(180_000)), track("b", Some(200_000))];
+
+        let candidate = match_release(&tracks, &release(&[Some(180_000), Some(202_500)])).unwrap();
+        assert_eq!(candidate.score, 75);
+        assert_eq!(candidate.tracks[0].path, tracks[0].path);
+        assert_eq!(candidate.tracks[0].title, "Track 1");
+        assert_eq!(candidate.tracks[1].track_number, 2);
+        assert_eq!(candidate.tracks[1].artist.as_deref(), Some("Band"));
+        assert_eq!(candidate.tracks[1].year.as_deref(), Some("2024"));
+
+        let candidate = match_release(&tracks, &release(&[None, Some(300_000)])).unwrap();
+        assert_eq!(candidate.score, 25);
+
+        assert!(match_release(&tracks, &release(&[Some(180_000)])).is_none());
+    }
+
+    #[test]
+    fn test_search_query() {
+        let tracks = [
+            InboxTrack {
+                album: Some(String::from("Album")),
+                artist: Some(String::from("Band")),
+                ..track("a", None)
+            },
+            InboxTrack {
+                album: Some(String::from("Album")),
+                ..track("b", None)
+            },
+            InboxTrack {
+                album: Some(String::from("Other")),
+                ..track("c", None)
+            },
+        ];
+
+        let query = search_query(Path::new("/inbox/rip"), &tracks);
+        assert_eq!(query.album.as_deref(), Some("Album"));
+        assert_eq!(query.artist.as_deref(), Some("Band"));
+
+        let query = search_query(Path::new("/inbox/rip"), &[track("a", None)]);
+        assert_eq!(query.album.as_deref(), Some("rip"));
+        assert_eq!(query.artist, None);
+    }
+
+    #[test]
+    fn test_resolve_folder() {
+        let inbox = tempfile::tempdir().unwrap();
+        let folder = inbox.path().join("album");
+        std::fs::create_dir(&folder).unwrap();
+
+        assert_eq!(
+            resolve_folder(inbox.path(), &folder).unwrap(),
+            folder.canonicalize().unwrap()
+        );
+
+        for path in [
+            inbox.path().to_path_buf(),
+            folder.join(".."),
+            folder.join("../.."),
+            inbox.path().join("missing"),
+        ] {
+            assert!(matches!(
+                resolve_folder(inbox.path(), &path),
+                Err(InboxError::OutsideInbox(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_set_aside_duplicate() {
+        let inbox = tempfile::tempdir().unwrap();
+        let inbox_path = inbox.path().canonicalize().unwrap();
+        let folder = inbox_path.join("album");
+        std::fs::create_dir(&folder).unwrap();
+        let file = folder.join("01.flac");
+        std::fs::write(&file, "song").unwrap();
+
+        let moved = set_aside_duplicate(inbox.path(), &file).unwrap();
+        assert_eq!(
+            moved,
+            inbox_path.join(DUPLICATES_FOLDER).join("album/01.flac")
+        );
+        assert!(!file.exists());
+        assert_eq!(std::fs::read_to_string(&moved).unwrap(), "song");
+
+        assert_eq!(set_aside_duplicate(inbox.path(), &moved).unwrap(), moved);
+
+        std::fs::write(&file, "other").unwrap();
+        assert!(matches!(
+            set_aside_duplicate(inbox.path(), &file),
+            Err(InboxError::Io(err)) if err.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+        assert_eq!(std::fs::read_to_string(&moved).unwrap(), "song");
+    }
+}
@@ -0,0 +1,457 @@
+//! Infers the tags of songs from their path, e.g. `Artist/Album/01 - Title.mp3`.
+//!
+//! A pattern like `{artist}/{album}/{track} - {title}` is matched against the end of the path
+//! without the extension, each `/` separating the directories. Only fields the song doesn't have
+//! yet are filled, and a path that matches a pattern in more than one way is skipped rather than
+//! guessed.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::{Component, Path},
+};
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{db::Song, metadata::item::ItemKey};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum PatternError {
+    #[error("Pattern \"{0}\" has an empty part")]
+    EmptyPart(String),
+    #[error("Pattern \"{0}\" has an unclosed `{{`")]
+    Unclosed(String),
+    #[error("Pattern \"{pattern}\" has an unknown field `{field}`")]
+    UnknownField { pattern: String, field: String },
+    #[error("Pattern \"{pattern}\" has the field `{field}` more than once")]
+    DuplicateField { pattern: String, field: String },
+    #[error("Pattern \"{0}\" has two fields without text between them")]
+    AdjacentFields(String),
+}
+
+/// A field a pattern can fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Track,
+    Disc,
+    Year,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "title" => Self::Title,
+            "artist" => Self::Artist,
+            "album" => Self::Album,
+            "albumArtist" => Self::AlbumArtist,
+            "genre" => Self::Genre,
+            "track" => Self::Track,
+            "disc" => Self::Disc,
+            "year" => Self::Year,
+            _ => return None,
+        })
+    }
+
+    fn key(self) -> ItemKey {
+        match self {
+            Self::Title => ItemKey::Title,
+            Self::Artist => ItemKey::Artist,
+            Self::Album => ItemKey::Album,
+            Self::AlbumArtist => ItemKey::AlbumArtist,
+            Self::Genre => ItemKey::Genre,
+            Self::Track => ItemKey::TrackNumber,
+            Self::Disc => ItemKey::DiscNumber,
+            Self::Year => ItemKey::Year,
+        }
+    }
+
+    /// Numbers only match digits, which keeps `{track} {title}` from splitting a title.
+    fn is_number(self) -> bool {
+        matches!(self, Self::Track | Self::Disc | Self::Year)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Text(String),
+    Field(Field),
+    /// `{_}`, matches a part of the path that's ignored.
+    Skip,
+}
+
+/// A parsed filename pattern, with a list of tokens for every part of the path it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    parts: Vec<Vec<Token>>,
+}
+
+impl Pattern {
+    pub fn parse(source: &str) -> Result<Self, PatternError> {
+        let mut fields = HashSet::new();
+        let parts = source
+            .split('/')
+            .map(|part| parse_part(source, part, &mut fields))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    /// Parses every pattern, failing on the first invalid one.
+    pub fn parse_all<S: AsRef<str>>(sources: &[S]) -> Result<Vec<Self>, PatternError> {
+        sources
+            .iter()
+            .map(|source| Self::parse(source.as_ref()))
+            .collect()
+    }
+
+    /// Returns every distinct way the end of the path matches the pattern, each as the values of
+    /// the fields.
+    fn matches(&self, path: &Path) -> Vec<BTreeMap<ItemKey, String>> {
+        let Some(components) = path_parts(path, self.parts.len()) else {
+            return Vec::new();
+        };
+
+        let mut matches = vec![BTreeMap::new()];
+        for (tokens, component) in self.parts.iter().zip(components) {
+            let mut part_matches = Vec::new();
+            match_tokens(tokens, component, &mut Vec::new(), &mut part_matches);
+
+            matches = matches
+                .iter()
+                .flat_map(|fields| {
+                    part_matches.iter().map(move |captures| {
+                        let mut fields = fields.clone();
+                        fields.extend(captures.iter().cloned());
+                        fields
+                    })
+                })
+                .collect();
+        }
+
+        matches.sort();
+        matches.dedup();
+        matches
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn parse_part(
+    source: &str,
+    part: &str,
+    fields: &mut HashSet<Field>,
+) -> Result<Vec<Token>, PatternError> {
+    if part.is_empty() {
+        return Err(PatternError::EmptyPart(source.to_string()));
+    }
+
+    let mut tokens = Vec::new();
+    let mut rest = part;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('{') else {
+            tokens.push(Token::Text(rest.to_string()));
+            break;
+        };
+
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+
+        let Some(end) = rest[start..].find('}') else {
+            return Err(PatternError::Unclosed(source.to_string()));
+        };
+
+        let name = &rest[start + 1..start + end];
+        let token = match name {
+            "_" => Token::Skip,
+            name => {
+                let field = Field::parse(name).ok_or_else(|| PatternError::UnknownField {
+                    pattern: source.to_string(),
+                    field: name.to_string(),
+                })?;
+
+                if !fields.insert(field) {
+                    return Err(PatternError::DuplicateField {
+                        pattern: source.to_string(),
+                        field: name.to_string(),
+                    });
+                }
+
+                Token::Field(field)
+            }
+        };
+
+        if matches!(tokens.last(), Some(Token::Field(_) | Token::Skip)) {
+            return Err(PatternError::AdjacentFields(source.to_string()));
+        }
+
+        tokens.push(token);
+        rest = &rest[start + end + 1..];
+    }
+
+    Ok(tokens)
+}
+
+/// Returns the last `count` components of the path, the file name without its extension last.
+fn path_parts(path: &Path, count: usize) -> Option<Vec<&str>> {
+    let file_name = path.file_stem()?.to_str()?;
+    let directories = path
+        .parent()?
+        .components()
+        .rev()
+        .take_while(|component| matches!(component, Component::Normal(_)))
+        .take(count - 1)
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+
+    if directories.len() != count - 1 {
+        return None;
+    }
+
+    Some(directories.into_iter().rev().chain([file_name]).collect())
+}
+
+/// Collects every way the text matches the tokens into `matches`.
+fn match_tokens(
+    tokens: &[Token],
+    text: &str,
+    captures: &mut Vec<(ItemKey, String)>,
+    matches: &mut Vec<Vec<(ItemKey, String)>>,
+) {
+    let Some((token, rest)) = tokens.split_first() else {
+        if text.is_empty() {
+            matches.push(captures.clone());
+        }
+        return;
+    };
+
+    match token {
+        Token::Text(expected) => {
+            if let Some(text) = text.strip_prefix(expected.as_str()) {
+                match_tokens(rest, text, captures, matches);
+            }
+        }
+        Token::Field(_) | Token::Skip => {
+            let ends = text
+                .char_indices()
+                .skip(1)
+                .map(|(index, _)| index)
+                .chain([text.len()]);
+
+            for end in ends {
+                let value = text[..end].trim();
+
+                if let Token::Field(field) = token {
+                    let Some(value) = field_value(*field, value) else {
+                        continue;
+                    };
+
+                    captures.push((field.key(), value));
+                    match_tokens(rest, &text[end..], captures, matches);
+                    captures.pop();
+                } else if !value.is_empty() {
+                    match_tokens(rest, &text[end..], captures, matches);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value of the field, with the leading zeros of numbers removed, if the text can be
+/// one.
+fn field_value(field: Field, text: &str) -> Option<String> {
+    if text.is_empty() {
+        return None;
+    }
+
+    if field.is_number() {
+        if !text.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        return text.parse::<u32>().ok().map(|number| number.to_string());
+    }
+
+    Some(text.to_string())
+}
+
+/// What the path of a song tells about its tags.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct InferredMetadata {
+    pub song_id: String,
+    pub path: String,
+    /// The pattern the path matched, not set if it matched none.
+    pub pattern: Option<String>,
+    /// Whether the path matched the pattern in more than one way, nothing is inferred then.
+    pub ambiguous: bool,
+    /// The inferred values of the fields the song doesn't have yet.
+    pub fields: BTreeMap<ItemKey, String>,
+}
+
+/// Infers the missing fields of the song from its path with the first pattern that matches it.
+///
+/// Tracks of a cue sheet share one file, so nothing is inferred for them.
+pub fn infer(song: &Song, patterns: &[Pattern]) -> InferredMetadata {
+    let mut inferred = InferredMetadata {
+        song_id: song.id.clone(),
+        path: song.path.clone(),
+        pattern: None,
+        ambiguous: false,
+        fields: BTreeMap::new(),
+    };
+
+    if song.cue_path.is_some() {
+        return inferred;
+    }
+
+    let path = Path::new(&song.path);
+    let Some((pattern, mut matches)) = patterns
+        .iter()
+        .map(|pattern| (pattern, pattern.matches(path)))
+        .find(|(_, matches)| !matches.is_empty())
+    else {
+        return inferred;
+    };
+
+    inferred.pattern = Some(pattern.to_string());
+
+    if matches.len() > 1 {
+        inferred.ambiguous = true;
+        return inferred;
+    }
+
+    inferred.fields = matches
+        .pop()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(key, _)| !has_value(song, key))
+        .collect();
+
+    inferred
+}
+
+fn has_value(song: &Song, key: &ItemKey) -> bool {
+    match key {
+        ItemKey::Title => song.title.is_some(),
+        ItemKey::Artist => song.artist.is_some(),
+        ItemKey::Album => song.album.is_some(),
+        ItemKey::AlbumArtist => song.album_artist.is_some(),
+        ItemKey::Genre => song.genre.is_some(),
+        ItemKey::TrackNumber => song.track_number.is_some(),
+        ItemKey::DiscNumber => song.disc_number.is_some(),
+        ItemKey::Year => song.year.is_some(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> Vec<BTreeMap<ItemKey, String>> {
+        Pattern::parse(pattern).unwrap().matches(Path::new(path))
+    }
+
+    fn fields(fields: &[(ItemKey, &str)]) -> BTreeMap<ItemKey, String> {
+        fields
+            .iter()
+            .map(|(key, value)| (key.clone(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        assert!(Pattern::parse("{artist}/{album}/{track} - {title}").is_ok());
+        assert!(Pattern::parse("{_}/{track}. {title}").is_ok());
+
+        assert!(matches!(
+            Pattern::parse("{artist}//{title}"),
+            Err(PatternError::EmptyPart(_))
+        ));
+        assert!(matches!(
+            Pattern::parse("{artist"),
+            Err(PatternError::Unclosed(_))
+        ));
+        assert!(matches!(
+            Pattern::parse("{track}{title}"),
+            Err(PatternError::AdjacentFields(_))
+        ));
+        assert!(matches!(
+            Pattern::parse("{composer} - {title}"),
+            Err(PatternError::UnknownField { field, .. }) if field == "composer"
+        ));
+        assert!(matches!(
+            Pattern::parse("{title}/{title}"),
+            Err(PatternError::DuplicateField { field, .. }) if field == "title"
+        ));
+    }
+
+    #[test]
+    fn test_match_pattern() {
+        assert_eq!(
+            matches(
+                "{artist}/{album}/{track} - {title}",
+                "/music/Artist/Album/01 - Title - Live.mp3"
+            ),
+            [fields(&[
+                (ItemKey::Artist, "Artist"),
+                (ItemKey::Album, "Album"),
+                (ItemKey::TrackNumber, "1"),
+                (ItemKey::Title, "Title - Live"),
+            ])]
+        );
+
+        assert_eq!(
+            matches("{_}/{track}. {title}", "/music/Album/3. Title.flac"),
+            [fields(&[
+                (ItemKey::TrackNumber, "3"),
+                (ItemKey::Title, "Title")
+            ])]
+        );
+
+        assert!(matches("{artist}/{album}/{title}", "Album/Title.mp3").is_empty());
+        assert!(matches("{track} - {title}", "/music/Title.mp3").is_empty());
+        assert_eq!(
+            matches("{artist} - {title}", "/music/A - B - C.mp3").len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_infer() {
+        let patterns = Pattern::parse_all(&["{track} - {title}", "{artist} - {title}"]).unwrap();
+        let song = |path: &str| Song {
+            path: path.to_string(),
+            artist: Some(String::from("Tagged Artist")),
+            ..Song::default()
+        };
+
+        let inferred = infer(&song("/music/Artist - Title.mp3"), &patterns);
+        assert_eq!(inferred.pattern.as_deref(), Some("{artist} - {title}"));
+        assert_eq!(inferred.fields, fields(&[(ItemKey::Title, "Title")]));
+
+        let inferred = infer(&song("/music/A - B - C.mp3"), &patterns);
+        assert!(inferred.ambiguous);
+        assert!(inferred.fields.is_empty());
+
+        let inferred = infer(&song("/music/Title.mp3"), &patterns);
+        assert_eq!(inferred.pattern, None);
+        assert!(inferred.fields.is_empty());
+    }
+}
@@ -6,26 +6,44 @@ use serde::Serialize;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
+use crate::state::job::JobParameters;
+
+mod album_hygiene;
+mod analyze_loudness;
+mod backfill_audio_properties;
+mod bundle_playlist;
+mod check_integrity;
+mod identify_songs;
+mod infer_metadata;
+mod merge_genres;
+mod prune_metadata_history;
+mod rebuild_indexes;
 mod scan_songs;
+mod workers;
+
+pub use album_hygiene::*;
+pub use analyze_loudness::*;
+pub use backfill_audio_properties::*;
+pub use bundle_playlist::*;
+pub use check_integrity::*;
+pub use identify_songs::*;
+pub use infer_metadata::*;
+pub use merge_genres::*;
+pub use prune_metadata_history::*;
+pub use rebuild_indexes::*;
 pub use scan_songs::*;
 
 type Sender = mpsc::Sender<JobEvent>;
 
+/// Structured output of a job run, saved alongside its report.
+pub type JobArtifact = serde_json::Value;
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase", tag = "kind")]
 pub enum JobEvent {
-    StepCompleted {
-        step: u8,
-        value: Option<String>,
-    },
-    Warning {
-        message: String,
-    },
-    Progress {
-        current: u64,
-        total: u64,
-        step: u8,
-    },
+    StepCompleted { step: u8, value: Option<String> },
+    Warning { message: String },
+    Progress { current: u64, total: u64, step: u8 },
 }
 
 /// Util function to send job events without freaking out if the channel is closed
@@ -44,5 +62,14 @@ fn emit_blocking_event(tx: &Sender, event: JobEvent) {
 
 #[async_trait]
 pub trait JobHandle: 'static + Send + Sync + Debug {
-    async fn execute(&self, cancel_token: CancellationToken, tx: Sender) -> Result<()>;
+    /// Runs the job, returning an artifact to save with the report.
+    ///
+    /// Jobs that support dry runs must not write to any files or the database when
+    /// [`JobParameters::dry_run`] is set, and should return the planned changes instead.
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        cancel_token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>>;
 }
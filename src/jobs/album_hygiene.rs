@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::Result;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db,
+    hygiene::{LibraryHygieneReport, check_album},
+    paths::album_hygiene_report_path,
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+#[derive(Debug)]
+pub struct AlbumHygiene {
+    db: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl AlbumHygiene {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        Self { db }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Album Hygiene",
+            "Checks every album for mixed formats and inconsistent tags",
+            BTreeMap::from([
+                (1, String::from("Checking albums")),
+                (2, String::from("Saving report")),
+            ]),
+        )
+    }
+}
+
+#[async_trait]
+impl JobHandle for AlbumHygiene {
+    async fn execute(
+        &self,
+        _parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let mut connection = self.db.acquire().await?;
+        let albums = db::songs::get_albums(&mut connection).await?;
+        drop(connection);
+
+        let total = albums.len() as u64;
+        let mut reports = Vec::with_capacity(albums.len());
+
+        for (index, album) in albums.iter().enumerate() {
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            reports.push(check_album(album));
+
+            emit_event(
+                &tx,
+                JobEvent::Progress {
+                    current: index as u64 + 1,
+                    total,
+                    step: 1,
+                },
+            )
+            .await;
+        }
+
+        let report = LibraryHygieneReport::new(albums.len(), reports);
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: report.albums.len().to_string().into(),
+            },
+        )
+        .await;
+
+        save_hygiene_report(&report).await?;
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 2,
+                value: None,
+            },
+        )
+        .await;
+
+        Ok(None)
+    }
+}
+
+/// Saves the report to [`album_hygiene_report_path`], replacing the previous one.
+pub(crate) async fn save_hygiene_report(report: &LibraryHygieneReport) -> Result<()> {
+    let path = album_hygiene_report_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    tokio::fs::write(&path, serde_json::to_string_pretty(report)?).await?;
+
+    tracing::info!(
+        "Saved album hygiene report to \"{}\"",
+        path.to_string_lossy()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Result;
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db::{self, Song},
+    loudness::{ReplayGain, TrackLoudness},
+    metadata::{self, Metadata, SongFile},
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+/// Computes the ReplayGain of songs, writing it to their tags and the database.
+///
+/// Songs are analyzed album by album, so the album gain only covers the tracks matching
+/// [`JobParameters::directory`] when an album is split across directories.
+#[derive(Debug)]
+pub struct AnalyzeLoudness {
+    db: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl AnalyzeLoudness {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        Self { db }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Analyze Loudness",
+            "Computes the ReplayGain of songs and writes it to their tags",
+            BTreeMap::from([(1, String::from("Analyzing songs"))]),
+        )
+        .with_filters()
+        .exclusive()
+    }
+}
+
+#[async_trait]
+impl JobHandle for AnalyzeLoudness {
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let mut connection = self.db.acquire().await?;
+        let songs = db::songs::get_filtered_songs(
+            &mut connection,
+            parameters.album.as_deref(),
+            parameters.directory.as_deref(),
+        )
+        .await?;
+        drop(connection);
+
+        // Tracks of a cue sheet share a file, which can only be measured as a whole.
+        let (songs, cue_tracks): (Vec<_>, Vec<_>) =
+            songs.into_iter().partition(|song| song.cue_path.is_none());
+
+        if !cue_tracks.is_empty() {
+            emit_event(
+                &tx,
+                JobEvent::Warning {
+                    message: format!("Skipped {} tracks of cue sheets", cue_tracks.len()),
+                },
+            )
+            .await;
+        }
+
+        let total = songs.len() as u64;
+        let mut current = 0;
+        let mut analyzed = 0;
+
+        for (album, songs) in group_by_album(songs) {
+            let mut tracks = Vec::with_capacity(songs.len());
+
+            for song in songs {
+                if token.is_cancelled() {
+                    return Ok(None);
+                }
+
+                let path = PathBuf::from(&song.path);
+                let loudness = spawn_blocking(move || TrackLoudness::analyze(&path))
+                    .await?
+                    .and_then(|loudness| loudness.gain().map(|_| loudness));
+
+                match loudness {
+                    Ok(loudness) => tracks.push((song, loudness)),
+                    Err(err) => {
+                        emit_event(
+                            &tx,
+                            JobEvent::Warning {
+                                message: format!("Skipped \"{}\": {err}", song.path),
+                            },
+                        )
+                        .await;
+                    }
+                }
+
+                current += 1;
+                emit_event(
+                    &tx,
+                    JobEvent::Progress {
+                        current,
+                        total,
+                        step: 1,
+                    },
+                )
+                .await;
+            }
+
+            if tracks.is_empty() {
+                continue;
+            }
+
+            let (songs, loudness): (Vec<_>, Vec<_>) = tracks.into_iter().unzip();
+            let gains = ReplayGain::for_album(&loudness, album.is_some())?;
+
+            let mut connection = self.db.acquire().await?;
+            for (song, gain) in songs.iter().zip(gains) {
+                let path = PathBuf::from(&song.path);
+                if let Err(err) = spawn_blocking(move || write_tags(&path, &gain)).await? {
+                    emit_event(
+                        &tx,
+                        JobEvent::Warning {
+                            message: format!("Failed to write tags of \"{}\": {err}", song.path),
+                        },
+                    )
+                    .await;
+                }
+
+                db::songs::update_song_replay_gain(&mut connection, &song.id, &gain).await?;
+                analyzed += 1;
+            }
+        }
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: analyzed.to_string().into(),
+            },
+        )
+        .await;
+
+        Ok(None)
+    }
+}
+
+/// Groups the songs by album, every song without an album is a group of its own.
+fn group_by_album(songs: Vec<Song>) -> Vec<(Option<String>, Vec<Song>)> {
+    let mut albums: BTreeMap<String, Vec<Song>> = BTreeMap::new();
+    let mut singles = Vec::new();
+
+    for song in songs {
+        match song.album.clone() {
+            Some(album) => albums.entry(album).or_default().push(song),
+            None => singles.push((None, vec![song])),
+        }
+    }
+
+    albums
+        .into_iter()
+        .map(|(album, songs)| (Some(album), songs))
+        .chain(singles)
+        .collect()
+}
+
+fn write_tags(path: &Path, gain: &ReplayGain) -> Result<(), metadata::Error> {
+    let mut song = SongFile::open(path)?;
+    let mut metadata = song
+        .metadata()
+        .clone()
+        .unwrap_or_else(|| Metadata::new(BTreeMap::new(), BTreeMap::new()));
+
+    gain.apply_to(&mut metadata);
+    song.set_metadata(metadata);
+    song.write()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        db::test_utils::pool_with_songs,
+        metadata::{item::ItemKey, read_metadata_from_path},
+    };
+
+    #[test(tokio::test)]
+    async fn test_analyzing_album() -> Result<()> {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("goose.flac");
+        std::fs::copy("data/goose.flac", &path)?;
+
+        query("UPDATE songs SET album = 'Album'")
+            .execute(&pool)
+            .await?;
+        query("UPDATE songs SET path = ? WHERE title = 'a'")
+            .bind(path.to_string_lossy())
+            .execute(&pool)
+            .await?;
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let parameters = JobParameters {
+            album: Some(String::from("Album")),
+            ..Default::default()
+        };
+
+        AnalyzeLoudness::new(pool.clone())
+            .execute(parameters, CancellationToken::new(), tx)
+            .await?;
+
+        let mut warnings = 0;
+        let mut completed = None;
+        while let Some(event) = rx.recv().await {
+            match event {
+                JobEvent::Warning { .. } => warnings += 1,
+                JobEvent::StepCompleted { value, .. } => completed = value,
+                JobEvent::Progress { .. } => {}
+            }
+        }
+
+        // The other song doesn't exist, so it's skipped without failing the job.
+        assert_eq!(warnings, 1);
+        assert_eq!(completed.as_deref(), Some("1"));
+
+        let album =
+            db::songs::get_album(&mut *pool.acquire().await?, String::from("Album")).await?;
+        assert!(album.gain_db.is_some());
+        assert!(album.peak.is_some());
+
+        let metadata = read_metadata_from_path(&path)?;
+        assert!(metadata.get(&ItemKey::ReplayGainTrackGain).is_some());
+        assert!(metadata.get(&ItemKey::ReplayGainAlbumGain).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouping_by_album() {
+        let song = |title: &str, album: Option<&str>| Song {
+            title: Some(title.to_string()),
+            album: album.map(String::from),
+            ..Default::default()
+        };
+
+        let groups = group_by_album(vec![
+            song("a", None),
+            song("b", Some("Album")),
+            song("c", None),
+            song("d", Some("Album")),
+        ]);
+
+        assert_eq!(
+            groups
+                .iter()
+                .map(|(album, songs)| (album.as_deref(), songs.len()))
+                .collect::<Vec<_>>(),
+            [(Some("Album"), 2), (None, 1), (None, 1)]
+        );
+    }
+}
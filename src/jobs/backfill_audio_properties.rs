@@ -0,0 +1,255 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use color_eyre::eyre::Result;
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db::{self, Song},
+    metadata::{AudioProperties, read_properties_from_path},
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+/// Songs read before they're saved, so a cancelled run only loses the batch it was reading.
+const BATCH_SIZE: u32 = 100;
+
+/// Reads the audio properties of the songs scanned without them, which are the songs added before
+/// they were read and the ones scanned with [`crate::config::Scan::read_audio_properties`] off.
+///
+/// Only the properties are read, tags are skipped. Every batch is saved as it's done and only
+/// songs that haven't been read are picked up, so a run that got cancelled or interrupted carries
+/// on where it stopped the next time.
+#[derive(Debug)]
+pub struct BackfillAudioProperties {
+    db: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl BackfillAudioProperties {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        Self { db }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Backfill Audio Properties",
+            "Reads the duration, bitrate and other audio properties of songs scanned without them",
+            BTreeMap::from([(1, String::from("Reading audio properties"))]),
+        )
+    }
+}
+
+#[async_trait]
+impl JobHandle for BackfillAudioProperties {
+    async fn execute(
+        &self,
+        _parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let mut connection = self.db.acquire().await?;
+        let total = db::songs::count_songs_without_properties(&mut connection).await?;
+        drop(connection);
+
+        let mut current = 0;
+        let mut read = 0;
+        // Songs that can't be read stay unread, the cursor keeps them from being picked up again.
+        let mut after = None;
+
+        while !token.is_cancelled() {
+            let mut connection = self.db.acquire().await?;
+            let songs = db::songs::get_songs_without_properties(
+                &mut connection,
+                after.as_deref(),
+                BATCH_SIZE,
+            )
+            .await?;
+            drop(connection);
+
+            let Some(last) = songs.last() else {
+                break;
+            };
+            after = Some(last.id.clone());
+
+            let mut batch = Vec::with_capacity(songs.len());
+            for song in songs {
+                if token.is_cancelled() {
+                    break;
+                }
+
+                current += 1;
+
+                let path = PathBuf::from(&song.path);
+                if !path.is_file() {
+                    emit_event(
+                        &tx,
+                        JobEvent::Warning {
+                            message: format!("Skipped \"{}\": file not found", song.path),
+                        },
+                    )
+                    .await;
+                    continue;
+                }
+
+                // Files that can't be parsed are saved without properties like the scan does, so
+                // they don't keep durations from being totalled.
+                let result = spawn_blocking(move || read_properties_from_path(&path)).await?;
+                let properties = match result {
+                    Ok(properties) => properties,
+                    Err(err) => {
+                        emit_event(
+                            &tx,
+                            JobEvent::Warning {
+                                message: format!(
+                                    "Failed to read the properties of \"{}\": {err}",
+                                    song.path
+                                ),
+                            },
+                        )
+                        .await;
+                        AudioProperties::default()
+                    }
+                };
+
+                batch.push((song.id.clone(), track_properties(&song, properties)));
+            }
+
+            let mut transaction = self.db.begin().await?;
+            for (id, properties) in &batch {
+                db::songs::update_song_properties(&mut transaction, id, properties).await?;
+            }
+            transaction.commit().await?;
+            read += batch.len();
+
+            emit_event(
+                &tx,
+                JobEvent::Progress {
+                    current,
+                    total: total.max(current),
+                    step: 1,
+                },
+            )
+            .await;
+        }
+
+        if token.is_cancelled() {
+            return Ok(None);
+        }
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: read.to_string().into(),
+            },
+        )
+        .await;
+
+        Ok(None)
+    }
+}
+
+/// Returns the properties of the part of the file the song is stored in, the duration of a track
+/// of a cue sheet only covers the track.
+fn track_properties(song: &Song, file: AudioProperties) -> AudioProperties {
+    match song.cue_range() {
+        Some(range) => AudioProperties {
+            duration_ms: range
+                .end_ms
+                .or(file.duration_ms)
+                .map(|end_ms| end_ms.saturating_sub(range.start_ms)),
+            ..file
+        },
+        None => file,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_backfilling_properties() -> Result<()> {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("goose.flac");
+        std::fs::copy("data/goose.flac", &path)?;
+
+        query("UPDATE songs SET path = ? WHERE title = 'a'")
+            .bind(path.to_string_lossy())
+            .execute(&pool)
+            .await?;
+
+        let (tx, mut rx) = mpsc::channel(16);
+        BackfillAudioProperties::new(pool.clone())
+            .execute(JobParameters::default(), CancellationToken::new(), tx)
+            .await?;
+
+        let mut warnings = 0;
+        let mut completed = None;
+        while let Some(event) = rx.recv().await {
+            match event {
+                JobEvent::Warning { .. } => warnings += 1,
+                JobEvent::StepCompleted { value, .. } => completed = value,
+                JobEvent::Progress { .. } => {}
+            }
+        }
+
+        // The other song doesn't exist, so it's left for a later run.
+        assert_eq!(warnings, 1);
+        assert_eq!(completed.as_deref(), Some("1"));
+
+        let mut connection = pool.acquire().await?;
+        let unread = db::songs::get_songs_without_properties(&mut connection, None, 10).await?;
+        assert_eq!(
+            unread
+                .iter()
+                .map(|song| song.title.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("b")]
+        );
+
+        let duration_ms = sqlx::query_scalar::<_, Option<u32>>(
+            "SELECT duration_ms FROM songs WHERE title = 'a' AND properties_read = TRUE",
+        )
+        .fetch_one(&mut *connection)
+        .await?;
+        assert!(duration_ms.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_track_properties() {
+        let file = AudioProperties {
+            duration_ms: Some(10_000),
+            sample_rate: Some(44_100),
+            ..Default::default()
+        };
+        let track = |start_ms, end_ms| Song {
+            cue_path: Some(String::from("/music/album.cue")),
+            start_ms,
+            end_ms,
+            ..Default::default()
+        };
+
+        assert_eq!(track_properties(&Song::default(), file), file);
+        assert_eq!(
+            track_properties(&track(2_000, Some(5_000)), file).duration_ms,
+            Some(3_000)
+        );
+        assert_eq!(
+            track_properties(&track(8_000, None), file),
+            AudioProperties {
+                duration_ms: Some(2_000),
+                ..file
+            }
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{Result, eyre};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use tokio::{process::Command, task::spawn_blocking};
+use tokio_util::sync::CancellationToken;
+use ts_rs::TS;
+
+use crate::{
+    config,
+    db::{self, PlaylistWithTracks, Song},
+    m3u,
+    metadata::{CoverArtType, get_cover_art, position::Position},
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+const PREPARE_TRACKS: u8 = 1;
+const WRITE_BUNDLE: u8 = 2;
+
+/// How long the files of an interrupted run are kept for when finished bundles never expire.
+const PARTIAL_RETENTION: Duration = Duration::DAY;
+
+/// A finished bundle of a playlist, which can be downloaded until it expires.
+#[derive(Debug, Clone, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistBundle {
+    pub playlist_id: String,
+    /// Size of the zip file in bytes.
+    pub size: u64,
+    #[ts(type = "Date")]
+    pub created_at: OffsetDateTime,
+    /// When the bundle is removed, `null` if it's kept until the playlist is bundled again.
+    #[ts(type = "Date | null")]
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+/// Returns the path the finished bundle of the playlist is saved to.
+pub fn bundle_path(directory: &Path, playlist_id: &str) -> PathBuf {
+    directory.join(format!("{playlist_id}.zip"))
+}
+
+/// Returns the finished bundle of the playlist, `None` if there's none or it has expired.
+pub async fn get_bundle(
+    directory: &Path,
+    playlist_id: &str,
+    retention: Option<Duration>,
+) -> std::io::Result<Option<PlaylistBundle>> {
+    let metadata = match tokio::fs::metadata(bundle_path(directory, playlist_id)).await {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let created_at = OffsetDateTime::from(metadata.modified()?);
+    let expires_at = retention.map(|retention| created_at + retention);
+
+    if expires_at.is_some_and(|expires_at| expires_at <= OffsetDateTime::now_utc()) {
+        return Ok(None);
+    }
+
+    Ok(Some(PlaylistBundle {
+        playlist_id: playlist_id.to_string(),
+        size: metadata.len(),
+        created_at,
+        expires_at,
+    }))
+}
+
+/// Removes the bundles older than `retention` along with the files of interrupted runs,
+/// returning how many were removed.
+pub async fn prune_bundles(directory: &Path, retention: Option<Duration>) -> std::io::Result<u64> {
+    let mut entries = match tokio::fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let now = SystemTime::now();
+    let mut removed = 0;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let partial = entry.file_name().to_string_lossy().ends_with(".partial");
+        let Some(retention) = retention.or(partial.then_some(PARTIAL_RETENTION)) else {
+            continue;
+        };
+
+        if now
+            .duration_since(metadata.modified()?)
+            .is_ok_and(|age| age < retention.unsigned_abs())
+        {
+            continue;
+        }
+
+        if metadata.is_dir() {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+        } else {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+
+        removed += 1;
+    }
+
+    Ok(removed)
+}
+
+/// The files of a bundle that's being written, removed once dropped so cancelled and failed runs
+/// don't leave anything behind.
+struct PartialBundle {
+    directory: PathBuf,
+    zip: PathBuf,
+}
+
+impl Drop for PartialBundle {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.directory)
+            && err.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove {:?}: {err}", self.directory);
+        }
+
+        if let Err(err) = std::fs::remove_file(&self.zip)
+            && err.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove {:?}: {err}", self.zip);
+        }
+    }
+}
+
+/// Writes the tracks of a playlist into a zip along with an M3U playlist and their cover art,
+/// so the playlist can be listened to offline.
+///
+/// Tracks are copied as they are unless [`config::Bundles::transcode`] is set, which also trims
+/// them to their playback offsets.
+#[derive(Debug)]
+pub struct BundlePlaylist {
+    db: sqlx::Pool<sqlx::Sqlite>,
+    settings: config::Bundles,
+    /// The directory finished bundles are saved to, see [`bundle_path`].
+    directory: PathBuf,
+}
+
+impl BundlePlaylist {
+    pub fn new(
+        db: sqlx::Pool<sqlx::Sqlite>,
+        settings: config::Bundles,
+        directory: PathBuf,
+    ) -> Self {
+        Self {
+            db,
+            settings,
+            directory,
+        }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Bundle Playlist",
+            "Writes the songs of a playlist into a zip file for offline listening",
+            BTreeMap::from([
+                (PREPARE_TRACKS, String::from("Preparing tracks")),
+                (WRITE_BUNDLE, String::from("Writing bundle")),
+            ]),
+        )
+        .with_playlist()
+    }
+
+    /// Writes the song into the bundle directory, returning the name of the file.
+    async fn add_track(
+        &self,
+        directory: &Path,
+        file_stem: &str,
+        song: &Song,
+        copied: &mut HashMap<String, String>,
+    ) -> Result<String> {
+        if !self.settings.transcode {
+            // Tracks of a cue sheet share their file, which only has to be copied once.
+            if let Some(name) = copied.get(&song.path) {
+                return Ok(name.clone());
+            }
+
+            let name = match Path::new(&song.path).extension() {
+                Some(extension) => {
+                    format!("{file_stem}.{}", extension.to_string_lossy().to_lowercase())
+                }
+                None => file_stem.to_string(),
+            };
+
+            tokio::fs::copy(&song.path, directory.join(&name)).await?;
+            copied.insert(song.path.clone(), name.clone());
+
+            return Ok(name);
+        }
+
+        let format = self.settings.format;
+        let name = format!("{file_stem}.{}", format.extension());
+        let mut command = Command::new(&self.settings.ffmpeg);
+        command.args(["-nostdin", "-hide_banner", "-loglevel", "error"]);
+
+        // Tracks of a cue sheet and trimmed songs only cover part of the file.
+        let (start_ms, end_ms) = song.playback_range();
+        if start_ms > 0 {
+            command.args(["-ss", &seconds(start_ms)]);
+        }
+
+        if let Some(end_ms) = end_ms {
+            command.args(["-t", &seconds(end_ms.saturating_sub(start_ms))]);
+        }
+
+        command
+            .arg("-i")
+            .arg(&song.path)
+            .args(["-map", "0:a", "-map_metadata", "0"]);
+
+        // Tracks of a cue sheet only have the tags of the whole file.
+        let track = Position {
+            number: song.track_number,
+            total: song.track_total,
+        }
+        .to_tag();
+        for (key, value) in [
+            ("title", &song.title),
+            ("artist", &song.artist),
+            ("album", &song.album),
+            ("track", &track),
+        ] {
+            if let Some(value) = value {
+                command.arg("-metadata").arg(format!("{key}={value}"));
+            }
+        }
+
+        let output = command
+            .args(["-c:a", format.encoder()])
+            .args(["-b:a", &format!("{}k", self.settings.bitrate_kbps)])
+            .arg(directory.join(&name))
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|err| eyre!("Failed to run \"{}\": {err}", self.settings.ffmpeg))?;
+
+        if !output.status.success() {
+            return Err(eyre!(
+                "ffmpeg failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(name)
+    }
+}
+
+/// Formats milliseconds as the seconds ffmpeg expects.
+fn seconds(ms: u32) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
+
+/// Names the file of a track so the files sort in playlist order, e.g. `01 - Artist - Title`.
+fn track_file_stem(position: usize, width: usize, song: &Song) -> String {
+    let name = match (&song.artist, &song.title) {
+        (Some(artist), Some(title)) => format!("{artist} - {title}"),
+        (None, Some(title)) => title.clone(),
+        _ => Path::new(&song.path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+
+    format!(
+        "{:0width$} - {}",
+        position + 1,
+        sanitize_filename::sanitize(name)
+    )
+}
+
+/// Writes the front cover embedded in the song to `covers/`, named after its album.
+async fn add_cover_art(directory: &Path, album: &str, song_path: &str) -> Result<()> {
+    let path = PathBuf::from(song_path);
+    let Some(cover) = spawn_blocking(move || get_cover_art(&path))
+        .await??
+        .into_iter()
+        .find(|cover| cover.cover_type == CoverArtType::Front)
+    else {
+        return Ok(());
+    };
+
+    let extension = match cover.mime_type.as_str() {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        _ => return Ok(()),
+    };
+
+    let covers = directory.join("covers");
+    tokio::fs::create_dir_all(&covers).await?;
+    tokio::fs::write(
+        covers.join(format!(
+            "{}.{extension}",
+            sanitize_filename::sanitize(album)
+        )),
+        cover.data,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Zips every file of the directory, returns `false` if cancelled before it was finished.
+fn write_zip(directory: &Path, path: &Path, token: &CancellationToken) -> Result<bool> {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+
+    // Audio and images are compressed already, deflating them would only take longer.
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+
+    for entry in walkdir::WalkDir::new(directory)
+        .min_depth(1)
+        .sort_by_file_name()
+    {
+        if token.is_cancelled() {
+            return Ok(false);
+        }
+
+        let entry = entry?;
+        let name = entry
+            .path()
+            .strip_prefix(directory)?
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if entry.file_type().is_dir() {
+            zip.add_directory(name, options)?;
+            continue;
+        }
+
+        zip.start_file(name, options)?;
+        std::io::copy(&mut std::fs::File::open(entry.path())?, &mut zip)?;
+    }
+
+    zip.finish()?;
+
+    Ok(true)
+}
+
+#[async_trait]
+impl JobHandle for BundlePlaylist {
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let Some(playlist_id) = parameters.playlist.as_deref() else {
+            return Err(eyre!("No playlist was selected to bundle"));
+        };
+
+        let mut connection = self.db.acquire().await?;
+        let PlaylistWithTracks { playlist, tracks } =
+            db::playlists::get_playlist_with_tracks(&mut connection, playlist_id).await?;
+        drop(connection);
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+
+        // Runs write files of their own, so bundling the same playlist twice can't mix them up.
+        let run_id = uuid::Uuid::new_v4();
+        let partial = PartialBundle {
+            directory: self
+                .directory
+                .join(format!("{playlist_id}.{run_id}.partial")),
+            zip: self
+                .directory
+                .join(format!("{playlist_id}.{run_id}.zip.partial")),
+        };
+        tokio::fs::create_dir(&partial.directory).await?;
+
+        let total = tracks.len() as u64;
+        let width = tracks.len().to_string().len().max(2);
+        let mut entries = Vec::with_capacity(tracks.len());
+        let mut copied = HashMap::new();
+        let mut albums = HashSet::new();
+
+        for (index, song) in tracks.iter().enumerate() {
+            let file_stem = track_file_stem(index, width, song);
+            let add_track = self.add_track(&partial.directory, &file_stem, song, &mut copied);
+            let result = tokio::select! {
+                result = add_track => result,
+                _ = token.cancelled() => return Ok(None),
+            };
+
+            match result {
+                Ok(name) => entries.push(m3u::Track {
+                    path: name,
+                    duration: song.duration_ms.map(|ms| u64::from(ms / 1000)),
+                    artist: song.artist.clone(),
+                    title: song.title.clone(),
+                }),
+                Err(err) => {
+                    emit_event(
+                        &tx,
+                        JobEvent::Warning {
+                            message: format!("Skipping \"{}\": {err}", song.path),
+                        },
+                    )
+                    .await;
+                }
+            }
+
+            if let Some(album) = &song.album
+                && albums.insert(album.clone())
+                && let Err(err) = add_cover_art(&partial.directory, album, &song.path).await
+            {
+                tracing::debug!("No cover art bundled for \"{album}\": {err}");
+            }
+
+            emit_event(
+                &tx,
+                JobEvent::Progress {
+                    current: index as u64 + 1,
+                    total,
+                    step: PREPARE_TRACKS,
+                },
+            )
+            .await;
+        }
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: PREPARE_TRACKS,
+                value: entries.len().to_string().into(),
+            },
+        )
+        .await;
+
+        tokio::fs::write(
+            partial.directory.join(format!(
+                "{}.m3u8",
+                sanitize_filename::sanitize(&playlist.name)
+            )),
+            m3u::render(&playlist.name, &entries),
+        )
+        .await?;
+
+        let (source, zip, zip_token) = (
+            partial.directory.clone(),
+            partial.zip.clone(),
+            token.clone(),
+        );
+        if !spawn_blocking(move || write_zip(&source, &zip, &zip_token)).await?? {
+            return Ok(None);
+        }
+
+        tokio::fs::rename(&partial.zip, bundle_path(&self.directory, playlist_id)).await?;
+        drop(partial);
+
+        let bundle = get_bundle(&self.directory, playlist_id, self.settings.retention())
+            .await?
+            .ok_or_else(|| eyre!("The written bundle is missing"))?;
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: WRITE_BUNDLE,
+                value: bundle.size.to_string().into(),
+            },
+        )
+        .await;
+
+        Ok(Some(serde_json::to_value(bundle)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query_scalar;
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_bundling_playlist() -> Result<()> {
+        let pool = pool_with_songs(&["a", "b"]).await;
+        let temp = tempfile::tempdir()?;
+        let library = temp.path().join("library");
+        let bundles = temp.path().join("bundles");
+        std::fs::create_dir(&library)?;
+
+        let mut connection = pool.acquire().await?;
+        let playlist = db::playlists::create_playlist(&mut connection, "Road Trip").await?;
+
+        for (index, id) in query_scalar::<_, String>("SELECT id FROM songs ORDER BY title")
+            .fetch_all(&mut *connection)
+            .await?
+            .into_iter()
+            .enumerate()
+        {
+            let path = library.join(format!("{index}.flac"));
+            std::fs::copy("data/goose.flac", &path)?;
+            sqlx::query("UPDATE songs SET path = ? WHERE id = ?")
+                .bind(path.to_string_lossy())
+                .bind(&id)
+                .execute(&mut *connection)
+                .await?;
+            db::playlists::add_song(&mut connection, &playlist.id, &id).await?;
+        }
+        drop(connection);
+
+        let (tx, _rx) = mpsc::channel(64);
+        let job = BundlePlaylist::new(pool, config::Bundles::default(), bundles.clone());
+
+        assert!(
+            job.execute(
+                JobParameters::default(),
+                CancellationToken::new(),
+                tx.clone()
+            )
+            .await
+            .is_err()
+        );
+
+        let parameters = JobParameters {
+            playlist: Some(playlist.id.clone()),
+            ..Default::default()
+        };
+        let artifact = job
+            .execute(parameters, CancellationToken::new(), tx)
+            .await?
+            .expect("A finished bundle should be returned");
+        assert_eq!(artifact["playlistId"], playlist.id);
+
+        let mut archive =
+            zip::ZipArchive::new(std::fs::File::open(bundle_path(&bundles, &playlist.id))?)?;
+        let mut names = archive.file_names().map(String::from).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[2], "Road Trip.m3u8");
+        assert!(names[0].starts_with("01 - ") && names[0].ends_with(".flac"));
+
+        let mut playlist_file = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("Road Trip.m3u8")?, &mut playlist_file)?;
+        assert!(playlist_file.contains(&format!("\n{}\n", names[0])));
+
+        // Only the finished bundle is left behind.
+        assert_eq!(std::fs::read_dir(&bundles)?.count(), 1);
+
+        assert_eq!(prune_bundles(&bundles, Some(Duration::HOUR)).await?, 0);
+        assert_eq!(prune_bundles(&bundles, Some(Duration::ZERO)).await?, 1);
+        assert!(get_bundle(&bundles, &playlist.id, None).await?.is_none());
+
+        Ok(())
+    }
+}
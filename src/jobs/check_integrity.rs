@@ -0,0 +1,138 @@
+use std::{collections::BTreeMap, path::Path};
+
+use color_eyre::eyre::Result;
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db::{self, IntegrityReport},
+    paths::integrity_report_path,
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+/// Looks for songs that drifted from their directories and files, saving what it finds to
+/// [`integrity_report_path`]. Nothing is changed, the safe fixes are applied by
+/// `POST /api/library/integrity/repair`.
+#[derive(Debug)]
+pub struct CheckIntegrity {
+    db: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl CheckIntegrity {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        Self { db }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Check Integrity",
+            "Checks the library for orphaned songs, duplicate paths and missing files",
+            BTreeMap::from([
+                (1, String::from("Checking the database")),
+                (2, String::from("Checking files")),
+                (3, String::from("Saving report")),
+            ]),
+        )
+    }
+}
+
+#[async_trait]
+impl JobHandle for CheckIntegrity {
+    async fn execute(
+        &self,
+        _parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let mut connection = self.db.acquire().await?;
+        let mut report = db::integrity::check_database(&mut connection).await?;
+        let songs = db::integrity::get_songs(&mut connection).await?;
+        drop(connection);
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: None,
+            },
+        )
+        .await;
+
+        // Checked in a single blocking task, large libraries have far too many songs for a task
+        // each.
+        let blocking_tx = tx.clone();
+        let blocking_token = token.clone();
+        let missing_files = spawn_blocking(move || {
+            let total = songs.len() as u64;
+            let mut missing_files = Vec::new();
+
+            for (index, song) in songs.into_iter().enumerate() {
+                if blocking_token.is_cancelled() {
+                    break;
+                }
+
+                if !Path::new(&song.path).exists() {
+                    missing_files.push(song);
+                }
+
+                emit_blocking_event(
+                    &blocking_tx,
+                    JobEvent::Progress {
+                        current: index as u64 + 1,
+                        total,
+                        step: 2,
+                    },
+                );
+            }
+
+            missing_files
+        })
+        .await?;
+
+        if token.is_cancelled() {
+            return Ok(None);
+        }
+        report.missing_files = missing_files;
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 2,
+                value: report.missing_files.len().to_string().into(),
+            },
+        )
+        .await;
+
+        save_integrity_report(&report).await?;
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 3,
+                value: None,
+            },
+        )
+        .await;
+
+        Ok(None)
+    }
+}
+
+/// Saves the report to [`integrity_report_path`], replacing the previous one.
+pub(crate) async fn save_integrity_report(report: &IntegrityReport) -> Result<()> {
+    let path = integrity_report_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    tokio::fs::write(&path, serde_json::to_string_pretty(report)?).await?;
+
+    tracing::info!(
+        "Saved library integrity report to \"{}\"",
+        path.to_string_lossy()
+    );
+
+    Ok(())
+}
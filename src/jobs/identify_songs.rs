@@ -0,0 +1,143 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use color_eyre::eyre::{Result, eyre};
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db,
+    fingerprint::Fingerprint,
+    providers::{AcoustId, IdentifyCandidate},
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+/// Looks up untagged songs on AcoustID by their fingerprint, saving the matching recordings as
+/// suggestions without touching the tags.
+#[derive(Debug)]
+pub struct IdentifySongs {
+    db: sqlx::Pool<sqlx::Sqlite>,
+    acoustid: Option<Arc<AcoustId>>,
+}
+
+impl IdentifySongs {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>, acoustid: Option<Arc<AcoustId>>) -> Self {
+        Self { db, acoustid }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Identify Songs",
+            "Finds matching recordings of songs without a title by their acoustic fingerprint",
+            BTreeMap::from([(1, String::from("Identifying songs"))]),
+        )
+        .with_filters()
+    }
+}
+
+/// Fingerprints the file and looks it up, returning the best matches first.
+pub async fn identify_song(acoustid: &AcoustId, path: PathBuf) -> Result<Vec<IdentifyCandidate>> {
+    let fingerprint = spawn_blocking(move || Fingerprint::compute(&path)).await??;
+
+    Ok(acoustid.lookup(&fingerprint).await?)
+}
+
+#[async_trait]
+impl JobHandle for IdentifySongs {
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let Some(acoustid) = &self.acoustid else {
+            return Err(eyre!("AcoustID lookups are disabled in the settings"));
+        };
+
+        let mut connection = self.db.acquire().await?;
+
+        // Tracks of a cue sheet share a file, which can only be fingerprinted as a whole.
+        let songs = db::songs::get_filtered_songs(
+            &mut connection,
+            parameters.album.as_deref(),
+            parameters.directory.as_deref(),
+        )
+        .await?
+        .into_iter()
+        .filter(|song| song.title.is_none() && song.cue_path.is_none())
+        .collect::<Vec<_>>();
+
+        let total = songs.len() as u64;
+        let mut identified = 0;
+
+        for (index, song) in songs.into_iter().enumerate() {
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            match identify_song(acoustid, PathBuf::from(&song.path)).await {
+                Ok(candidates) => {
+                    if !candidates.is_empty() {
+                        identified += 1;
+                    }
+
+                    db::suggestions::replace_suggestions(&mut connection, &song.id, &candidates)
+                        .await?;
+                }
+                Err(err) => {
+                    emit_event(
+                        &tx,
+                        JobEvent::Warning {
+                            message: format!("Failed to identify \"{}\": {err}", song.path),
+                        },
+                    )
+                    .await;
+                }
+            }
+
+            emit_event(
+                &tx,
+                JobEvent::Progress {
+                    current: index as u64 + 1,
+                    total,
+                    step: 1,
+                },
+            )
+            .await;
+        }
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: identified.to_string().into(),
+            },
+        )
+        .await;
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_identifying_without_acoustid() {
+        let pool = pool_with_songs(&["a"]).await;
+        let (tx, _rx) = mpsc::channel(16);
+
+        assert!(
+            IdentifySongs::new(pool, None)
+                .execute(JobParameters::default(), CancellationToken::new(), tx)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::PoisonError,
+};
+
+use color_eyre::eyre::Result;
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db,
+    infer::{InferredMetadata, Pattern, infer},
+    metadata::{self, Metadata, SongFile, item::ItemKey},
+    state::{
+        SharedSettings,
+        job::{JobInfo, JobParameters},
+    },
+};
+
+use super::*;
+
+/// Fills the missing fields of songs from their path with the patterns of
+/// [`crate::config::Infer`], which are read when the job runs so changes to them apply right away.
+///
+/// The inferred fields are returned as the artifact, or planned without being saved on dry runs.
+#[derive(Debug)]
+pub struct InferMetadata {
+    db: sqlx::Pool<sqlx::Sqlite>,
+    settings: SharedSettings,
+}
+
+impl InferMetadata {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>, settings: SharedSettings) -> Self {
+        Self { db, settings }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Infer Metadata",
+            "Fills the missing tags of songs from their file and directory names",
+            BTreeMap::from([(1, String::from("Inferring metadata"))]),
+        )
+        .with_dry_run()
+        .with_filters()
+        .exclusive()
+    }
+}
+
+#[async_trait]
+impl JobHandle for InferMetadata {
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let settings = self
+            .settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .infer
+            .clone();
+        let patterns = Pattern::parse_all(&settings.patterns)?;
+
+        let mut connection = self.db.acquire().await?;
+        let songs = db::songs::get_filtered_songs(
+            &mut connection,
+            parameters.album.as_deref(),
+            parameters.directory.as_deref(),
+        )
+        .await?;
+
+        let total = songs.len() as u64;
+        let mut inferred = Vec::new();
+
+        for (index, song) in songs.iter().enumerate() {
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            let inference = infer(song, &patterns);
+
+            if inference.ambiguous {
+                emit_event(
+                    &tx,
+                    JobEvent::Warning {
+                        message: format!(
+                            "Skipped \"{}\", it matches \"{}\" in more than one way",
+                            song.path,
+                            inference.pattern.as_deref().unwrap_or_default()
+                        ),
+                    },
+                )
+                .await;
+            }
+
+            if !inference.fields.is_empty() {
+                if !parameters.dry_run {
+                    apply(&mut connection, &inference, settings.write_tags, &tx).await?;
+                }
+
+                inferred.push(inference);
+            }
+
+            emit_event(
+                &tx,
+                JobEvent::Progress {
+                    current: index as u64 + 1,
+                    total,
+                    step: 1,
+                },
+            )
+            .await;
+        }
+
+        tracing::info!("Inferred the metadata of {} song(s)", inferred.len());
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: inferred.len().to_string().into(),
+            },
+        )
+        .await;
+
+        Ok(Some(serde_json::to_value(inferred)?))
+    }
+}
+
+/// Saves the inferred fields to the database, and to the tags of the file if `write_tags` is set.
+/// A file that can't be written is reported, its song is still updated.
+async fn apply(
+    connection: &mut sqlx::SqliteConnection,
+    inference: &InferredMetadata,
+    write_tags: bool,
+    tx: &Sender,
+) -> Result<()> {
+    if write_tags {
+        let path = PathBuf::from(&inference.path);
+        let fields = inference.fields.clone();
+
+        if let Err(err) = spawn_blocking(move || write_missing_tags(&path, &fields)).await? {
+            emit_event(
+                tx,
+                JobEvent::Warning {
+                    message: format!("Failed to write tags of \"{}\": {err}", inference.path),
+                },
+            )
+            .await;
+        }
+    }
+
+    db::songs::fill_song_fields(connection, &inference.song_id, &inference.fields).await?;
+
+    Ok(())
+}
+
+/// Writes the fields the tags of the file don't have yet.
+fn write_missing_tags(
+    path: &Path,
+    fields: &BTreeMap<ItemKey, String>,
+) -> Result<(), metadata::Error> {
+    let mut song = SongFile::open(path)?;
+    let mut metadata = song
+        .metadata()
+        .clone()
+        .unwrap_or_else(|| Metadata::new(BTreeMap::new(), BTreeMap::new()));
+
+    let original = metadata.clone();
+    for (key, value) in fields {
+        if metadata.get(key).is_none() {
+            metadata.insert(key.clone(), value.clone());
+        }
+    }
+
+    if metadata == original {
+        return Ok(());
+    }
+
+    song.set_metadata(metadata);
+    song.write()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        config::Settings,
+        db::{Song, test_utils::pool_with_songs},
+    };
+
+    async fn get_song(pool: &sqlx::SqlitePool, id: &str) -> Song {
+        let mut connection = pool.acquire().await.unwrap();
+        db::songs::get_song(&mut connection, id).await.unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn test_inferring_metadata() -> Result<()> {
+        let pool = pool_with_songs(&[]).await;
+        for (id, path) in [
+            ("a", "/music/Artist/Album/01 - Title.flac"),
+            ("b", "/music/Artist/Album/A - B - C.flac"),
+        ] {
+            sqlx::query("INSERT INTO songs (id, path, directory_id) VALUES (?, ?, 'directory')")
+                .bind(id)
+                .bind(path)
+                .execute(&pool)
+                .await?;
+        }
+
+        let mut settings = Settings::default();
+        settings.infer.patterns = vec![
+            String::from("{artist}/{album}/{track} - {title}"),
+            String::from("{album}/{artist} - {title}"),
+        ];
+        let job = InferMetadata::new(pool.clone(), Arc::new(RwLock::new(settings)));
+
+        let (tx, _rx) = mpsc::channel(64);
+        let dry_run = JobParameters {
+            dry_run: true,
+            ..JobParameters::default()
+        };
+        let plan = job.execute(dry_run, CancellationToken::new(), tx).await?;
+        assert_eq!(plan.unwrap().as_array().unwrap().len(), 1);
+        assert!(get_song(&pool, "a").await.title.is_none());
+
+        let (tx, _rx) = mpsc::channel(64);
+        job.execute(JobParameters::default(), CancellationToken::new(), tx)
+            .await?;
+
+        let song = get_song(&pool, "a").await;
+        assert_eq!(song.artist.as_deref(), Some("Artist"));
+        assert_eq!(song.album.as_deref(), Some("Album"));
+        assert_eq!(song.track_number, Some(1));
+        assert_eq!(song.title.as_deref(), Some("Title"));
+
+        // Both `A` and `A - B` could be the artist.
+        assert!(get_song(&pool, "b").await.title.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Result, eyre};
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db,
+    genres::split_genres,
+    metadata::{self, Metadata, SongFile, item::ItemKey},
+    state::job::{JobInfo, JobParameters},
+};
+
+use super::*;
+
+/// Merges the genres of [`JobParameters::genre_merge`] in the tags of the files as well as the
+/// database, song by song so a cancelled merge leaves every song consistent with its file.
+///
+/// A file that can't be written is reported and its song is left as it is. The ids of the merged
+/// songs are returned as the artifact.
+#[derive(Debug)]
+pub struct MergeGenres {
+    db: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl MergeGenres {
+    pub fn new(db: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        Self { db }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Merge Genres",
+            "Replaces genres with another one in the tags of the files and the library",
+            BTreeMap::from([(1, String::from("Merging genres"))]),
+        )
+        .with_genre_merge()
+        .exclusive()
+    }
+}
+
+#[async_trait]
+impl JobHandle for MergeGenres {
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let Some(merge) = parameters.genre_merge else {
+            return Err(eyre!("No genres were given to merge"));
+        };
+        merge.validate().map_err(|err| eyre!(err))?;
+
+        let mut connection = self.db.acquire().await?;
+        let songs = db::genres::get_merged_genres(&mut connection, &merge).await?;
+
+        let total = songs.len() as u64;
+        let mut merged = Vec::new();
+
+        for (index, (song, genre)) in songs.into_iter().enumerate() {
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            let path = PathBuf::from(&song.path);
+            let genres = split_genres(&genre).map(str::to_string).collect();
+            match spawn_blocking(move || write_genres(&path, genres)).await? {
+                Ok(()) => {
+                    db::genres::set_song_genre(&mut connection, &song.id, &genre).await?;
+                    merged.push(song.id);
+                }
+                Err(err) => {
+                    emit_event(
+                        &tx,
+                        JobEvent::Warning {
+                            message: format!("Failed to write tags of \"{}\": {err}", song.path),
+                        },
+                    )
+                    .await;
+                }
+            }
+
+            emit_event(
+                &tx,
+                JobEvent::Progress {
+                    current: index as u64 + 1,
+                    total,
+                    step: 1,
+                },
+            )
+            .await;
+        }
+
+        tracing::info!(
+            "Merged genres into \"{}\" for {} song(s)",
+            merge.target,
+            merged.len()
+        );
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: merged.len().to_string().into(),
+            },
+        )
+        .await;
+
+        Ok(Some(serde_json::to_value(merged)?))
+    }
+}
+
+/// Writes the genres as separate values, for the formats that can store more than one.
+fn write_genres(path: &Path, genres: Vec<String>) -> Result<(), metadata::Error> {
+    let mut song = SongFile::open(path)?;
+    let mut metadata = song
+        .metadata()
+        .clone()
+        .unwrap_or_else(|| Metadata::new(BTreeMap::new(), BTreeMap::new()));
+
+    metadata.insert_values(ItemKey::Genre, genres);
+    song.set_metadata(metadata);
+    song.write()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        db::test_utils::pool_with_songs, genres::GenreMerge, metadata::read_metadata_from_path,
+    };
+
+    #[test(tokio::test)]
+    async fn test_merging_genres() -> Result<()> {
+        let pool = pool_with_songs(&[]).await;
+        let dir = tempfile::tempdir()?;
+
+        for (id, file, genre) in [
+            ("flac", "goose.flac", "hip-hop; Rock"),
+            ("mp3", "flip.mp3", "Rock"),
+        ] {
+            let path = dir.path().join(file);
+            std::fs::copy(Path::new("data").join(file), &path)?;
+            query(
+                "INSERT INTO songs (id, path, genre, directory_id) VALUES (?, ?, ?, 'directory')",
+            )
+            .bind(id)
+            .bind(path.to_string_lossy())
+            .bind(genre)
+            .execute(&pool)
+            .await?;
+        }
+
+        let job = MergeGenres::new(pool.clone());
+        let parameters = JobParameters {
+            genre_merge: Some(GenreMerge {
+                sources: vec![String::from("Hip-Hop")],
+                target: String::from("Hip Hop"),
+            }),
+            ..JobParameters::default()
+        };
+
+        let (tx, _rx) = mpsc::channel(64);
+        let artifact = job
+            .execute(parameters, CancellationToken::new(), tx)
+            .await?;
+        assert_eq!(artifact, Some(serde_json::json!(["flac"])));
+
+        let mut connection = pool.acquire().await?;
+        let song = db::songs::get_song(&mut connection, "flac").await?;
+        assert_eq!(song.genre.as_deref(), Some("Hip Hop; Rock"));
+
+        let metadata = read_metadata_from_path(&dir.path().join("goose.flac"))?;
+        assert_eq!(
+            metadata.get(&ItemKey::Genre).map(String::as_str),
+            Some("Hip Hop; Rock")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::PoisonError};
+
+use color_eyre::eyre::Result;
+use time::OffsetDateTime;
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    history,
+    state::{
+        SharedSettings,
+        job::{JobInfo, JobParameters},
+    },
+};
+
+use super::*;
+
+/// Removes the metadata history snapshots past the limits of
+/// [`crate::config::MetadataHistory`], which are read when the job runs so changes to them apply
+/// right away.
+#[derive(Debug)]
+pub struct PruneMetadataHistory {
+    settings: SharedSettings,
+    /// The directory the snapshots are saved to, see [`history`].
+    directory: PathBuf,
+}
+
+impl PruneMetadataHistory {
+    pub fn new(settings: SharedSettings, directory: PathBuf) -> Self {
+        Self {
+            settings,
+            directory,
+        }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Prune Metadata History",
+            "Removes metadata history snapshots past the configured age and count per song",
+            BTreeMap::from([(1, String::from("Removing old snapshots"))]),
+        )
+    }
+}
+
+#[async_trait]
+impl JobHandle for PruneMetadataHistory {
+    async fn execute(
+        &self,
+        _parameters: JobParameters,
+        _token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        let settings = self
+            .settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .metadata_history
+            .clone();
+        let directory = self.directory.clone();
+
+        let removed = spawn_blocking(move || {
+            history::prune(&directory, &settings, OffsetDateTime::now_utc())
+        })
+        .await??;
+
+        tracing::info!("Removed {removed} metadata history snapshot(s)");
+
+        emit_event(
+            &tx,
+            JobEvent::StepCompleted {
+                step: 1,
+                value: Some(removed.to_string()),
+            },
+        )
+        .await;
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,234 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use color_eyre::eyre::Result;
+use time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db,
+    hygiene::{LibraryHygieneReport, check_album},
+    state::{
+        SharedCoverArtCache,
+        job::{JobInfo, JobParameters},
+    },
+};
+
+use super::*;
+
+const RELINK_DIRECTORIES: u8 = 1;
+const CLEAR_COVER_ART_CACHE: u8 = 2;
+const ALBUM_HYGIENE_REPORT: u8 = 3;
+const PRUNE_BUNDLES: u8 = 4;
+const SEARCH_INDEX: u8 = 5;
+
+/// Rebuilds the data derived from the songs already in the database, without reading any tags,
+/// and removes expired playlist bundles.
+///
+/// Every step can be selected on its own with [`JobParameters::steps`].
+#[derive(Debug)]
+pub struct RebuildIndexes {
+    db: sqlx::Pool<sqlx::Sqlite>,
+    cover_art_cache: SharedCoverArtCache,
+    /// The directory playlist bundles are saved to, see [`BundlePlaylist`].
+    bundles_directory: PathBuf,
+    bundle_retention: Option<Duration>,
+}
+
+impl RebuildIndexes {
+    pub fn new(
+        db: sqlx::Pool<sqlx::Sqlite>,
+        cover_art_cache: SharedCoverArtCache,
+        bundles_directory: PathBuf,
+        bundle_retention: Option<Duration>,
+    ) -> Self {
+        Self {
+            db,
+            cover_art_cache,
+            bundles_directory,
+            bundle_retention,
+        }
+    }
+
+    pub fn job_info() -> JobInfo {
+        JobInfo::new(
+            "Rebuild Indexes",
+            "Rebuilds data derived from the library without re-reading any files",
+            BTreeMap::from([
+                (
+                    RELINK_DIRECTORIES,
+                    String::from("Relinking song directories"),
+                ),
+                (
+                    CLEAR_COVER_ART_CACHE,
+                    String::from("Clearing cover art cache"),
+                ),
+                (
+                    ALBUM_HYGIENE_REPORT,
+                    String::from("Rebuilding album hygiene report"),
+                ),
+                (
+                    PRUNE_BUNDLES,
+                    String::from("Removing expired playlist bundles"),
+                ),
+                (SEARCH_INDEX, String::from("Rebuilding search index")),
+            ]),
+        )
+        .with_step_selection()
+        .exclusive()
+    }
+
+    async fn relink_directories(&self) -> Result<Option<String>> {
+        let mut transaction = self.db.begin().await?;
+        let updated = db::songs::relink_directories(&mut transaction).await?;
+        transaction.commit().await?;
+
+        Ok(Some(updated.to_string()))
+    }
+
+    async fn rebuild_search_index(&self) -> Result<Option<String>> {
+        let mut transaction = self.db.begin().await?;
+        let indexed = db::search::rebuild_index(&mut transaction).await?;
+        transaction.commit().await?;
+
+        Ok(Some(indexed.to_string()))
+    }
+
+    async fn clear_cover_art_cache(&self) -> Result<Option<String>> {
+        self.cover_art_cache.clear().await?;
+
+        Ok(None)
+    }
+
+    async fn prune_bundles(&self) -> Result<Option<String>> {
+        let removed = prune_bundles(&self.bundles_directory, self.bundle_retention).await?;
+
+        Ok(Some(removed.to_string()))
+    }
+
+    async fn rebuild_album_hygiene_report(
+        &self,
+        token: &CancellationToken,
+        tx: &Sender,
+    ) -> Result<Option<String>> {
+        let mut transaction = self.db.begin().await?;
+        let albums = db::songs::get_albums(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let total = albums.len() as u64;
+        let mut reports = Vec::with_capacity(albums.len());
+
+        for (index, album) in albums.iter().enumerate() {
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            reports.push(check_album(album));
+
+            emit_event(
+                tx,
+                JobEvent::Progress {
+                    current: index as u64 + 1,
+                    total,
+                    step: ALBUM_HYGIENE_REPORT,
+                },
+            )
+            .await;
+        }
+
+        let report = LibraryHygieneReport::new(albums.len(), reports);
+        save_hygiene_report(&report).await?;
+
+        Ok(Some(report.albums.len().to_string()))
+    }
+}
+
+#[async_trait]
+impl JobHandle for RebuildIndexes {
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        for step in [
+            RELINK_DIRECTORIES,
+            CLEAR_COVER_ART_CACHE,
+            ALBUM_HYGIENE_REPORT,
+            PRUNE_BUNDLES,
+            SEARCH_INDEX,
+        ] {
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            if !parameters.runs_step(step) {
+                continue;
+            }
+
+            let value = match step {
+                RELINK_DIRECTORIES => self.relink_directories().await?,
+                CLEAR_COVER_ART_CACHE => self.clear_cover_art_cache().await?,
+                PRUNE_BUNDLES => self.prune_bundles().await?,
+                SEARCH_INDEX => self.rebuild_search_index().await?,
+                _ => self.rebuild_album_hygiene_report(&token, &tx).await?,
+            };
+
+            if token.is_cancelled() {
+                return Ok(None);
+            }
+
+            tracing::info!("Rebuilt step {step} of indexes");
+            emit_event(&tx, JobEvent::StepCompleted { step, value }).await;
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use sqlx::query;
+    use test_log::test;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{db::test_utils::pool_with_songs, state::CoverArtCache};
+
+    #[test(tokio::test)]
+    async fn test_only_selected_steps_run() -> Result<()> {
+        let pool = pool_with_songs(&["a"]).await;
+        let directory = tempfile::tempdir()?;
+        let cache = Arc::new(CoverArtCache::new(directory.path().join("cache"), u64::MAX));
+
+        query("INSERT INTO directories (name, path) VALUES ('nested', '/music/nested')")
+            .execute(&pool)
+            .await?;
+        query("UPDATE songs SET path = '/music/nested/a.mp3'")
+            .execute(&pool)
+            .await?;
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let parameters = JobParameters {
+            steps: Some(BTreeSet::from([RELINK_DIRECTORIES])),
+            ..Default::default()
+        };
+
+        RebuildIndexes::new(pool.clone(), cache, directory.path().join("bundles"), None)
+            .execute(parameters, CancellationToken::new(), tx)
+            .await?;
+
+        let mut completed = Vec::new();
+        while let Some(event) = rx.recv().await {
+            if let JobEvent::StepCompleted { step, value } = event {
+                completed.push((step, value));
+            }
+        }
+
+        assert_eq!(completed, [(RELINK_DIRECTORIES, Some(String::from("1")))]);
+        assert!(!directory.path().join("cache").exists());
+
+        Ok(())
+    }
+}
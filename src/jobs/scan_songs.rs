@@ -1,57 +1,454 @@
 use std::{
-    collections::{BTreeMap, HashSet},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
-use color_eyre::eyre::Result;
-use futures::{StreamExt, stream};
+use color_eyre::eyre::{Result, eyre};
 use sqlx::query_as;
-use time::OffsetDateTime;
+use time::{Duration, OffsetDateTime};
 use tokio::task::spawn_blocking;
 use tokio_util::sync::CancellationToken;
+use ts_rs::TS;
 
 use crate::{
-    db::{self, Song},
-    metadata::{item::ItemKey, read_metadata_from_path},
-    state::job::JobInfo,
+    db::{
+        self, CueRange, NewSong, ScanErrorCategory, Song,
+        scan_errors::{NewScanError, clear_scan_errors, get_failed_paths, record_scan_errors},
+        songs::{SongInsert, SongUpdate},
+    },
+    metadata::{
+        AudioProperties, Error as MetadataError, Metadata, SongError, is_cue_file, item::ItemKey,
+        rating::file_rating, read_cue_sheet, read_metadata_from_path, read_properties_from_path,
+    },
+    state::{
+        SharedCoverArtCache, SharedSettings, SharedSongFileTypes,
+        job::{JobInfo, JobParameters},
+    },
 };
 
-use super::*;
+use super::{workers::run_in_workers, *};
 
-const SONG_FILE_TYPES: [&str; 8] = ["mp3", "m4a", "flac", "wav", "ogg", "wma", "aac", "opus"];
+/// File names that contain ignore rules for the scanner and directory watcher.
+pub const IGNORE_FILE_NAMES: [&str; 3] = [".muusik-ignore", ".muusik_ignore", ".muusikignore"];
+
+/// Songs checked between progress events, so scanning a large library doesn't flood the event
+/// stream.
+const PROGRESS_BATCH: u64 = 100;
+
+/// Changes saved with a single query, progress is reported once a batch is saved.
+const WRITE_BATCH: usize = 1000;
+
+/// Whether a progress event is due, every [`PROGRESS_BATCH`] items and for the last one.
+fn is_progress_due(current: u64, total: u64) -> bool {
+    current % PROGRESS_BATCH == 0 || current == total
+}
+
+/// Reports the progress of saving the changes, once a batch of them is saved.
+async fn emit_batch_progress(tx: &Sender, current: u64, total: u64) {
+    emit_event(
+        tx,
+        JobEvent::Progress {
+            current,
+            total,
+            step: 4,
+        },
+    )
+    .await;
+}
+
+/// Returns whether the path has one of the extensions, which are expected to be lowercase.
+pub fn is_song_file(path: &Path, file_types: &BTreeSet<String>) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| file_types.contains(&ext.to_lowercase()))
+}
+
+/// Creates a walker that follows the same ignore rules as the song scanner.
+pub fn song_walker(path: impl AsRef<Path>) -> ignore::WalkBuilder {
+    let mut builder = ignore::WalkBuilder::new(path);
+
+    for name in IGNORE_FILE_NAMES {
+        builder.add_custom_ignore_filename(name);
+    }
+
+    builder.hidden(false).follow_links(true);
+    builder
+}
+
+/// When the file was last modified, to the second as that's what the database keeps.
+fn modified_at(path: &Path) -> Option<OffsetDateTime> {
+    let modified = path
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    OffsetDateTime::from(modified).replace_nanosecond(0).ok()
+}
+
+/// Whether an earlier scan failed to read the file and it hasn't been modified since, so reading
+/// it again would fail the same way.
+fn is_quarantined(failed: &HashMap<String, Option<OffsetDateTime>>, path: &Path) -> bool {
+    failed
+        .get(path.to_string_lossy().as_ref())
+        .is_some_and(|failed_at| *failed_at == modified_at(path))
+}
+
+/// Sorts a failed read into a category that doesn't change with the wording of the reader's
+/// errors.
+fn categorize(err: &MetadataError) -> ScanErrorCategory {
+    match err {
+        MetadataError::Song(SongError::NoTag) => ScanErrorCategory::NoTag,
+        MetadataError::Lofty(err)
+            if matches!(err.kind(), lofty::error::ErrorKind::UnknownFormat) =>
+        {
+            ScanErrorCategory::Unsupported
+        }
+        _ => ScanErrorCategory::Unreadable,
+    }
+}
+
+fn scan_error(path: &Path, err: &MetadataError) -> NewScanError {
+    NewScanError {
+        path: path.to_string_lossy().to_string(),
+        category: categorize(err),
+        error: err.to_string(),
+        modified_at: modified_at(path),
+    }
+}
+
+fn scan_warning(error: &NewScanError) -> JobEvent {
+    let message = format!(
+        "Failed to read \"{}\": {}",
+        error.path,
+        error.category.as_str()
+    );
+    tracing::warn!("{message} ({})", error.error);
+
+    JobEvent::Warning { message }
+}
+
+/// Saves the files that failed to be read, and forgets the ones that could be read again.
+pub async fn save_read_outcomes(
+    connection: &mut sqlx::SqliteConnection,
+    failed: &[NewScanError],
+    recovered: &[String],
+    now: OffsetDateTime,
+) {
+    if let Err(err) = record_scan_errors(connection, failed, now).await {
+        tracing::error!("Failed to record scan errors: {err}");
+    }
+
+    if !recovered.is_empty()
+        && let Err(err) = clear_scan_errors(connection, recovered).await
+    {
+        tracing::error!("Failed to clear scan errors: {err}");
+    }
+}
+
+/// A song file read the way the scan reads it, which the directory watcher reads files with too.
+pub struct SongRead {
+    pub path: PathBuf,
+    /// Missing when the file couldn't be read, files without tags are still songs.
+    pub metadata: Option<Metadata>,
+    /// Only read with [`Scan::read_audio_properties`](crate::config::Scan::read_audio_properties).
+    pub properties: Option<AudioProperties>,
+    pub file_created_at: Option<OffsetDateTime>,
+    pub error: Option<NewScanError>,
+}
+
+impl SongRead {
+    pub fn read(path: PathBuf, read_audio_properties: bool) -> Self {
+        let (metadata, error) = match read_metadata_from_path(&path) {
+            Ok(metadata) => (Some(metadata), None),
+            Err(err) => (None, Some(scan_error(&path, &err))),
+        };
+        let properties =
+            read_audio_properties.then(|| read_properties_from_path(&path).unwrap_or_default());
+        let file_created_at = path
+            .metadata()
+            .and_then(|metadata| metadata.created())
+            .ok()
+            .map(OffsetDateTime::from);
+
+        Self {
+            path,
+            metadata,
+            properties,
+            file_created_at,
+            error,
+        }
+    }
+
+    /// Whether the file is a song, only files that can't be read at all aren't.
+    pub fn is_song(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_none_or(|error| error.category == ScanErrorCategory::NoTag)
+    }
+
+    pub fn new_song(&self) -> NewSong {
+        let metadata = self.metadata.as_ref();
+        let track = metadata.map(Metadata::track).unwrap_or_default();
+        let disc = metadata.map(Metadata::disc).unwrap_or_default();
+
+        NewSong {
+            path: self.path.to_string_lossy().to_string(),
+            title: metadata.and_then(|m| m.get(&ItemKey::Title)).cloned(),
+            artist: metadata.and_then(|m| m.get(&ItemKey::Artist)).cloned(),
+            album: metadata.and_then(|m| m.get(&ItemKey::Album)).cloned(),
+            album_artist: metadata.and_then(|m| m.get(&ItemKey::AlbumArtist)).cloned(),
+            genre: metadata.and_then(|m| m.get(&ItemKey::Genre)).cloned(),
+            track_number: track.number,
+            track_total: track.total,
+            disc_number: disc.number,
+            disc_total: disc.total,
+            year: metadata.and_then(|m| m.get(&ItemKey::Year)).cloned(),
+            mood: metadata.and_then(|m| m.get(&ItemKey::Mood)).cloned(),
+            composer: metadata.and_then(|m| m.get(&ItemKey::Composer)).cloned(),
+            comment: metadata.and_then(|m| m.get(&ItemKey::Comment)).cloned(),
+            rating: metadata.and_then(Metadata::rating),
+            lyrics: metadata.and_then(|m| m.get(&ItemKey::Lyrics)).cloned(),
+            file_created_at: self.file_created_at,
+        }
+    }
+
+    pub fn updated_song(&self) -> db::UpdatedSong {
+        updated_song(&self.path, self.metadata.as_ref())
+    }
+}
+
+/// The tags of the file as they're saved for a song that's already in the library.
+fn updated_song(path: &Path, metadata: Option<&Metadata>) -> db::UpdatedSong {
+    let track = metadata.map(Metadata::track).unwrap_or_default();
+    let disc = metadata.map(Metadata::disc).unwrap_or_default();
+
+    db::UpdatedSong {
+        title: metadata.and_then(|m| m.get(&ItemKey::Title)).cloned(),
+        album: metadata.and_then(|m| m.get(&ItemKey::Album)).cloned(),
+        album_artist: metadata.and_then(|m| m.get(&ItemKey::AlbumArtist)).cloned(),
+        disc_number: disc.number,
+        disc_total: disc.total,
+        artist: metadata.and_then(|m| m.get(&ItemKey::Artist)).cloned(),
+        year: metadata.and_then(|m| m.get(&ItemKey::Year)).cloned(),
+        track_number: track.number,
+        track_total: track.total,
+        genre: metadata.and_then(|m| m.get(&ItemKey::Genre)).cloned(),
+        mood: metadata.and_then(|m| m.get(&ItemKey::Mood)).cloned(),
+        composer: metadata.and_then(|m| m.get(&ItemKey::Composer)).cloned(),
+        comment: metadata.and_then(|m| m.get(&ItemKey::Comment)).cloned(),
+        rating: file_rating(path, metadata),
+        lyrics: metadata.and_then(|m| m.get(&ItemKey::Lyrics)).cloned(),
+    }
+}
+
+/// How reading a file went, if it's worth remembering.
+enum ReadOutcome {
+    Failed(NewScanError),
+    /// An earlier scan failed to read the file, but it could be read now.
+    Recovered(String),
+}
+
+/// Changes a dry run of [`ScanSongs`] would have saved.
+#[derive(Debug, Default, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanSongsPlan {
+    /// Paths of the songs that would be added.
+    pub added: Vec<String>,
+    pub updated: Vec<PlannedSong>,
+    /// Songs whose file went missing since the last scan, they're kept but hidden from listings.
+    pub missing: Vec<PlannedSong>,
+    /// Missing songs whose file is back.
+    pub restored: Vec<PlannedSong>,
+    /// Songs missing for longer than the grace period, or replaced by the tracks of a cue sheet.
+    pub deleted: Vec<PlannedSong>,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedSong {
+    pub id: String,
+    pub path: String,
+}
+
+/// The tracks of a cue sheet, all stored in the same FLAC file.
+#[derive(Debug)]
+struct CueAlbum {
+    audio_path: PathBuf,
+    tracks: Vec<(NewSong, CueRange, AudioProperties)>,
+}
+
+impl CueAlbum {
+    /// Reads the cue sheet, returns `None` if it doesn't describe a FLAC file that exists.
+    ///
+    /// Fields the cue sheet doesn't contain are taken from the tags of the FLAC file.
+    fn read(cue_path: &Path) -> Result<Option<Self>> {
+        let sheet = read_cue_sheet(cue_path)?;
+        let audio_path = sheet.audio_path(cue_path);
+
+        if !audio_path.is_file()
+            || !audio_path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+        {
+            return Ok(None);
+        }
+
+        let metadata = read_metadata_from_path(&audio_path).ok();
+        let properties = read_properties_from_path(&audio_path).unwrap_or_default();
+        let file_created_at = audio_path
+            .metadata()
+            .and_then(|metadata| metadata.created())
+            .ok()
+            .map(OffsetDateTime::from);
+
+        let tag = |key: &ItemKey| metadata.as_ref().and_then(|m| m.get(key)).cloned();
+        let disc = metadata.as_ref().map(Metadata::disc).unwrap_or_default();
+        let track_total = u32::try_from(sheet.tracks.len()).ok();
+        let path = audio_path.to_string_lossy().to_string();
+        let cue_path = cue_path.to_string_lossy().to_string();
+
+        let tracks = sheet
+            .tracks
+            .iter()
+            .zip(sheet.track_ranges(properties.duration_ms))
+            .map(|(track, (start_ms, end_ms))| {
+                let song = NewSong {
+                    path: path.clone(),
+                    title: track.title.clone(),
+                    artist: track.performer.clone().or_else(|| sheet.performer.clone()),
+                    album: sheet.title.clone().or_else(|| tag(&ItemKey::Album)),
+                    album_artist: sheet
+                        .performer
+                        .clone()
+                        .or_else(|| tag(&ItemKey::AlbumArtist)),
+                    genre: sheet.genre.clone().or_else(|| tag(&ItemKey::Genre)),
+                    track_number: Some(track.number),
+                    track_total,
+                    disc_number: disc.number,
+                    disc_total: disc.total,
+                    year: sheet.date.clone().or_else(|| tag(&ItemKey::Year)),
+                    mood: tag(&ItemKey::Mood),
+                    composer: tag(&ItemKey::Composer),
+                    comment: tag(&ItemKey::Comment),
+                    // The tracks of a cue sheet share one file, its lyrics and rating aren't any
+                    // track's.
+                    rating: None,
+                    lyrics: None,
+                    file_created_at,
+                };
+
+                let range = CueRange {
+                    cue_path: cue_path.clone(),
+                    start_ms,
+                    end_ms,
+                };
+
+                let properties = AudioProperties {
+                    duration_ms: end_ms.map(|end_ms| end_ms.saturating_sub(start_ms)),
+                    ..properties
+                };
+
+                (song, range, properties)
+            })
+            .collect();
+
+        Ok(Some(Self { audio_path, tracks }))
+    }
+
+    /// Whether the saved songs already match every track of the cue sheet.
+    fn matches(&self, songs: &[Song]) -> bool {
+        songs.len() == self.tracks.len()
+            && self.tracks.iter().all(|(track, range, properties)| {
+                songs.iter().any(|song| {
+                    song.cue_range().as_ref() == Some(range)
+                        && song.title == track.title
+                        && song.artist == track.artist
+                        && song.album == track.album
+                        && song.album_artist == track.album_artist
+                        && song.genre == track.genre
+                        && song.track_number == track.track_number
+                        && song.track_total == track.track_total
+                        && song.disc_number == track.disc_number
+                        && song.disc_total == track.disc_total
+                        && song.year == track.year
+                        && song.composer == track.composer
+                        && song.comment == track.comment
+                        && song.duration_ms == properties.duration_ms
+                })
+            })
+    }
+}
 
 #[derive(Debug)]
 pub struct ScanSongs {
     db: sqlx::Pool<sqlx::Sqlite>,
+    cover_art_cache: SharedCoverArtCache,
+    song_file_types: SharedSongFileTypes,
+    settings: SharedSettings,
 }
 
 impl ScanSongs {
-    pub fn new(db: sqlx::Pool<sqlx::Sqlite>) -> Self {
-        Self { db }
+    pub fn new(
+        db: sqlx::Pool<sqlx::Sqlite>,
+        cover_art_cache: SharedCoverArtCache,
+        song_file_types: SharedSongFileTypes,
+        settings: SharedSettings,
+    ) -> Self {
+        Self {
+            db,
+            cover_art_cache,
+            song_file_types,
+            settings,
+        }
     }
 
     pub fn job_info() -> JobInfo {
         JobInfo::new(
             "Scan Songs",
-            "Scans for new songs, updates existing ones, and deletes songs that no longer exist",
+            "Scans for new songs, updates existing ones, and marks songs that no longer exist as missing",
             BTreeMap::from([
-                (1, String::from("Scanning for songs to delete")),
+                (1, String::from("Scanning for missing songs")),
                 (2, String::from("Scanning for new songs")),
                 (3, String::from("Scanning for updated songs")),
                 (4, String::from("Applying and saving changes")),
             ]),
         )
+        .with_dry_run()
+        .with_filters()
+        .exclusive()
     }
 }
 
 #[async_trait]
 impl JobHandle for ScanSongs {
-    async fn execute(&self, token: CancellationToken, tx: Sender) -> Result<()> {
-        let directories =
-            sqlx::query_as::<_, (String, String)>("SELECT path, name FROM directories")
-                .fetch_all(&self.db)
-                .await
-                .unwrap_or_default();
+    async fn execute(
+        &self,
+        parameters: JobParameters,
+        token: CancellationToken,
+        tx: Sender,
+    ) -> Result<Option<JobArtifact>> {
+        if parameters.album.is_some() {
+            return Err(eyre!("Scans can only be limited to a directory"));
+        }
+
+        // Scans limited to a directory neither walk nor compare the songs of the other ones.
+        let directory = parameters.directory.as_deref();
+        let directories = sqlx::query_as::<_, (String, String)>(
+            "SELECT path, name FROM directories WHERE ?1 IS NULL OR name = ?1",
+        )
+        .bind(directory)
+        .fetch_all(&self.db)
+        .await
+        .unwrap_or_default();
+
+        if let Some(directory) = directory
+            && directories.is_empty()
+        {
+            return Err(eyre!("Directory \"{directory}\" not found"));
+        }
 
         if directories.is_empty() {
             let message = "No directories found, cancelling scan";
@@ -65,28 +462,68 @@ impl JobHandle for ScanSongs {
             )
             .await;
 
-            return Ok(());
+            return Ok(parameters
+                .dry_run
+                .then(|| serde_json::to_value(ScanSongsPlan::default()))
+                .transpose()?);
         }
 
         let message = format!("Found {} directory(s)", directories.len());
         tracing::info!(message);
 
-        let existing_songs = query_as!(Song, "SELECT * FROM songs")
-            .fetch_all(&self.db)
-            .await
-            .unwrap_or_default();
+        let existing_songs =
+            query_as::<_, Song>("SELECT * FROM songs WHERE ?1 IS NULL OR directory_id = ?1")
+                .bind(directory)
+                .fetch_all(&self.db)
+                .await
+                .unwrap_or_default();
 
-        let non_existing_song_ids = existing_songs
+        // Files that failed to be read are skipped until they're modified, so a corrupt file
+        // isn't reported by every scan.
+        let failed_paths = match self.db.acquire().await {
+            Ok(mut connection) => get_failed_paths(&mut connection).await.unwrap_or_default(),
+            Err(_) => HashMap::new(),
+        };
+
+        // Checking every file can take a while on network shares, so it's reported as progress.
+        let checked_songs = existing_songs
             .iter()
-            .cloned()
-            .filter_map(|song| (!PathBuf::from(&song.path).exists()).then_some(song.id))
-            .collect::<HashSet<_>>();
+            .map(|song| (song.id.clone(), song.path.clone(), song.cue_path.clone()))
+            .collect::<Vec<_>>();
+        let missing_tx = tx.clone();
+        let mut missing_song_ids = spawn_blocking(move || {
+            let total = checked_songs.len() as u64;
+
+            checked_songs
+                .into_iter()
+                .enumerate()
+                .filter_map(|(index, (id, path, cue_path))| {
+                    let current = index as u64 + 1;
+                    if is_progress_due(current, total) {
+                        emit_blocking_event(
+                            &missing_tx,
+                            JobEvent::Progress {
+                                current,
+                                total,
+                                step: 1,
+                            },
+                        );
+                    }
+
+                    let missing = !Path::new(&path).exists()
+                        || cue_path.is_some_and(|cue_path| !Path::new(&cue_path).exists());
+
+                    missing.then_some(id)
+                })
+                .collect::<HashSet<_>>()
+        })
+        .await?;
 
         emit_event(
             &tx,
             JobEvent::StepCompleted {
                 step: 1,
-                value: non_existing_song_ids.len().to_string().into(),
+                value: missing_song_ids.len().to_string().into(),
             },
         )
         .await;
@@ -96,6 +533,8 @@ impl JobHandle for ScanSongs {
             .map(|song| PathBuf::from(&song.path.clone()))
             .collect::<HashSet<_>>();
 
+        // Songs whose extension was disabled are left alone, only missing files are deleted.
+        let file_types = self.song_file_types.get();
         let tx_clone = tx.clone();
         let directories_clone = directories.clone();
         let block_token = token.child_token();
@@ -105,48 +544,37 @@ impl JobHandle for ScanSongs {
             while let Some((path, _)) = directories.next()
                 && !block_token.is_cancelled()
             {
-                ignore::WalkBuilder::new(path)
-                    .add_custom_ignore_filename(".muusik-ignore")
-                    .add_custom_ignore_filename(".muusik_ignore")
-                    .add_custom_ignore_filename(".muusikignore")
-                    .hidden(false)
-                    .follow_links(true)
-                    .build_parallel()
-                    .run(|| {
-                        let child_token = block_token.child_token();
-                        let existing_song_paths = existing_song_paths.clone();
-                        let event_channel = tx_clone.clone();
-
-                        let file_tx = tx.clone();
-                        Box::new(move |result| {
-                            use ignore::WalkState::*;
-                            if child_token.is_cancelled() {
-                                return Quit;
-                            }
-
-                            if let Ok(entry) = result.inspect_err(|err| {
-                                let message = format!("Skipping entry due to error: {err}");
-                                tracing::warn!(message);
-                                emit_blocking_event(&event_channel, JobEvent::Warning { message });
-                            }) && entry
-                                .file_type()
-                                .is_some_and(|file_type| file_type.is_file())
-                                && !existing_song_paths.contains(entry.path())
-                                && entry
-                                    .path()
-                                    .extension()
-                                    .and_then(|ext| ext.to_str())
-                                    .is_some_and(|ext| {
-                                        SONG_FILE_TYPES.contains(&ext.to_lowercase().as_str())
-                                    })
-                                && let Err(err) = file_tx.send(entry.path().to_path_buf())
-                            {
-                                tracing::error!("Failed to send file to channel: {err}");
-                            }
-
-                            Continue
-                        })
-                    });
+                song_walker(path).build_parallel().run(|| {
+                    let child_token = block_token.child_token();
+                    let existing_song_paths = existing_song_paths.clone();
+                    let file_types = file_types.clone();
+                    let event_channel = tx_clone.clone();
+
+                    let file_tx = tx.clone();
+                    Box::new(move |result| {
+                        use ignore::WalkState::*;
+                        if child_token.is_cancelled() {
+                            return Quit;
+                        }
+
+                        if let Ok(entry) = result.inspect_err(|err| {
+                            let message = format!("Skipping entry due to error: {err}");
+                            tracing::warn!(message);
+                            emit_blocking_event(&event_channel, JobEvent::Warning { message });
+                        }) && entry
+                            .file_type()
+                            .is_some_and(|file_type| file_type.is_file())
+                            && (is_cue_file(entry.path())
+                                || (!existing_song_paths.contains(entry.path())
+                                    && is_song_file(entry.path(), &file_types)))
+                            && let Err(err) = file_tx.send(entry.path().to_path_buf())
+                        {
+                            tracing::error!("Failed to send file to channel: {err}");
+                        }
+
+                        Continue
+                    })
+                });
             }
 
             drop(tx);
@@ -156,95 +584,267 @@ impl JobHandle for ScanSongs {
         .await
         .expect("Failed to join thread");
 
+        let (cue_paths, mut song_paths): (Vec<_>, Vec<_>) =
+            song_paths.into_iter().partition(|path| is_cue_file(path));
+
+        let cue_tx = tx.clone();
+        let cue_albums = spawn_blocking(move || {
+            cue_paths
+                .iter()
+                .filter_map(|cue_path| {
+                    CueAlbum::read(cue_path)
+                        .inspect_err(|err| {
+                            let message =
+                                format!("Skipping cue sheet \"{}\": {err}", cue_path.display());
+                            tracing::warn!(message);
+                            emit_blocking_event(&cue_tx, JobEvent::Warning { message });
+                        })
+                        .ok()
+                        .flatten()
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+
+        // Files split by a cue sheet are never added as a single song, the songs already saved
+        // for them are replaced if they don't match the cue sheet.
+        let cue_audio_paths = cue_albums
+            .iter()
+            .map(|album| album.audio_path.clone())
+            .collect::<HashSet<_>>();
+
+        song_paths
+            .retain(|path| !cue_audio_paths.contains(path) && !is_quarantined(&failed_paths, path));
+
+        let mut seen_audio_paths = HashSet::new();
+        let cue_changes = cue_albums
+            .into_iter()
+            .filter(|album| seen_audio_paths.insert(album.audio_path.clone()))
+            .filter_map(|album| {
+                let saved = existing_songs
+                    .iter()
+                    .filter(|song| Path::new(&song.path) == album.audio_path)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                (!album.matches(&saved)).then_some((album, saved))
+            })
+            .collect::<Vec<_>>();
+
+        for (_, replaced) in &cue_changes {
+            for song in replaced {
+                missing_song_ids.remove(&song.id);
+            }
+        }
+
+        // Songs whose file can't be found are only marked as missing, an unmounted drive would
+        // otherwise wipe them from the library. They're deleted once the grace period is over.
+        let (grace_days, read_audio_properties, workers) = {
+            let settings = self.settings.read().unwrap_or_else(PoisonError::into_inner);
+            (
+                settings.scan.missing_grace_days,
+                settings.scan.read_audio_properties,
+                settings.scan.worker_count(),
+            )
+        };
+        let now = OffsetDateTime::now_utc();
+        let expires_before = (grace_days > 0).then(|| now - Duration::days(grace_days.into()));
+
+        let mut newly_missing_ids = HashSet::new();
+        let mut expired_ids = HashSet::new();
+        let mut restored_ids = HashSet::new();
+
+        for song in &existing_songs {
+            match (missing_song_ids.contains(&song.id), song.missing_since) {
+                (true, None) => {
+                    newly_missing_ids.insert(song.id.clone());
+                }
+                (true, Some(since)) if expires_before.is_some_and(|before| since < before) => {
+                    expired_ids.insert(song.id.clone());
+                }
+                (false, Some(_)) => {
+                    restored_ids.insert(song.id.clone());
+                }
+                _ => {}
+            }
+        }
+
+        let planned = |ids: &HashSet<String>| {
+            existing_songs
+                .iter()
+                .filter(|song| ids.contains(&song.id))
+                .map(|song| PlannedSong {
+                    id: song.id.clone(),
+                    path: song.path.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let missing_songs = planned(&newly_missing_ids);
+        let restored_songs = planned(&restored_ids);
+        let deleted_songs = planned(&expired_ids)
+            .into_iter()
+            .chain(
+                cue_changes
+                    .iter()
+                    .flat_map(|(_, replaced)| replaced)
+                    .map(|song| PlannedSong {
+                        id: song.id.clone(),
+                        path: song.path.clone(),
+                    }),
+            )
+            .collect::<Vec<_>>();
+
+        // Albums whose cached cover art may be outdated once the changes are saved.
+        let mut changed_albums = existing_songs
+            .iter()
+            .filter(|song| {
+                newly_missing_ids.contains(&song.id)
+                    || expired_ids.contains(&song.id)
+                    || restored_ids.contains(&song.id)
+            })
+            .filter_map(|song| song.album.clone())
+            .collect::<HashSet<_>>();
+
         emit_event(
             &tx,
             JobEvent::StepCompleted {
                 step: 2,
-                value: song_paths.len().to_string().into(),
+                value: (song_paths.len() + cue_changes.len()).to_string().into(),
             },
         )
         .await;
 
         if token.is_cancelled() {
-            return Ok(());
+            return Ok(None);
         }
 
-        let existing_song_count = existing_songs.len();
+        let existing_song_count = existing_songs.len() as u64;
         let comparison_tx = tx.clone();
-        let child_token = token.child_token();
-        let comparison_tasks = existing_songs
+        let comparison_failed_paths = Arc::new(failed_paths.clone());
+        let compared_songs = Arc::new(AtomicU64::new(0));
+        let compared = existing_songs
             .into_iter()
-            .filter(|song| !non_existing_song_ids.contains(&song.id))
-            .enumerate()
-            .map(move |(index, song)| {
-                let tx = comparison_tx.clone();
-                let child_token_clone = child_token.clone();
-                spawn_blocking(move || {
-                    if child_token_clone.is_cancelled() {
-                        return None;
-                    }
+            .filter(|song| {
+                !missing_song_ids.contains(&song.id)
+                    && song.cue_path.is_none()
+                    && !cue_audio_paths.contains(Path::new(&song.path))
+            })
+            .collect::<Vec<_>>();
 
-                    emit_blocking_event(
-                        &tx,
-                        JobEvent::Progress {
-                            current: index as u64,
-                            total: existing_song_count as u64,
-                            step: 3,
-                        },
-                    );
-
-                    let path = PathBuf::from(&song.path);
-                    let metadata = match read_metadata_from_path(&path) {
-                        Ok(song) => Some(song),
-                        Err(err) => {
-                            emit_blocking_event(
-                                &tx,
-                                JobEvent::Warning {
-                                    message: format!("Failed to read metadata for song: {err}"),
-                                },
-                            );
-                            None
-                        }
-                    };
+        let comparisons = run_in_workers(compared, workers, &token, move |song: Song| {
+            let (tx, failed_paths) = (&comparison_tx, comparison_failed_paths.as_ref());
 
-                    let created_date = path
-                        .metadata()
-                        .and_then(|metadata| metadata.created())
-                        .ok()
-                        .map(OffsetDateTime::from);
-
-                    let metadata_ref = metadata.as_ref();
-                    if song.title.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Title))
-                        || song.album.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Album))
-                        || song.year.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Year))
-                        || song.genre.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Genre))
-                        || song.mood.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Mood))
-                        || song.file_created_at != created_date
-                        || song.album_artist.as_ref()
-                            != metadata_ref.and_then(|m| m.get(&ItemKey::AlbumArtist))
-                        || song.track_number.as_ref()
-                            != metadata_ref.and_then(|m| m.get(&ItemKey::TrackNumber))
-                        || song.artist.as_ref()
-                            != metadata_ref.and_then(|m| m.get(&ItemKey::Artist))
-                        || song.disc_number.as_ref()
-                            != metadata_ref.and_then(|m| m.get(&ItemKey::DiscNumber))
-                    {
-                        Some((song.id.to_string(), metadata))
-                    } else {
-                        None
-                    }
+            let current = compared_songs.fetch_add(1, Ordering::Relaxed) + 1;
+            if is_progress_due(current, existing_song_count) {
+                emit_blocking_event(
+                    tx,
+                    JobEvent::Progress {
+                        current,
+                        total: existing_song_count,
+                        step: 3,
+                    },
+                );
+            }
+
+            let path = PathBuf::from(&song.path);
+            if is_quarantined(failed_paths, &path) {
+                return (None, None);
+            }
+
+            let (metadata, outcome) = match read_metadata_from_path(&path) {
+                Ok(metadata) => (
+                    Some(metadata),
+                    failed_paths
+                        .contains_key(&song.path)
+                        .then(|| ReadOutcome::Recovered(song.path.clone())),
+                ),
+                Err(err) => {
+                    let error = scan_error(&path, &err);
+                    emit_blocking_event(tx, scan_warning(&error));
+
+                    (None, Some(ReadOutcome::Failed(error)))
+                }
+            };
+
+            // Songs without tags are updated to match, but the tags saved for a file
+            // that can't be read are kept.
+            if let Some(ReadOutcome::Failed(error)) = &outcome
+                && error.category != ScanErrorCategory::NoTag
+            {
+                return (None, outcome);
+            }
+
+            // Properties that aren't read are left as they are.
+            let properties =
+                read_audio_properties.then(|| read_properties_from_path(&path).unwrap_or_default());
+
+            let created_date = path
+                .metadata()
+                .and_then(|metadata| metadata.created())
+                .ok()
+                .map(OffsetDateTime::from);
+
+            // The columns hold the values of each field joined, the same way
+            // `Metadata::get` returns them. Track and disc numbers are compared once
+            // parsed, the way they're saved.
+            let metadata_ref = metadata.as_ref();
+            let track = metadata_ref.map(Metadata::track).unwrap_or_default();
+            let disc = metadata_ref.map(Metadata::disc).unwrap_or_default();
+            if song.title.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Title))
+                || song.album.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Album))
+                || song.year.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Year))
+                || song.genre.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Genre))
+                || song.mood.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Mood))
+                || song.composer.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Composer))
+                || song.comment.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Comment))
+                || song.lyrics.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Lyrics))
+                || file_rating(&path, metadata_ref).is_some_and(|rating| song.rating != rating)
+                || song.file_created_at != created_date
+                || song.album_artist.as_ref()
+                    != metadata_ref.and_then(|m| m.get(&ItemKey::AlbumArtist))
+                || (song.track_number, song.track_total) != (track.number, track.total)
+                || song.artist.as_ref() != metadata_ref.and_then(|m| m.get(&ItemKey::Artist))
+                || (song.disc_number, song.disc_total) != (disc.number, disc.total)
+                || properties.is_some_and(|properties| {
+                    !song.properties_read
+                        || song.duration_ms != properties.duration_ms
+                        || song.bitrate_kbps != properties.bitrate_kbps
+                        || song.sample_rate != properties.sample_rate
+                        || song.channels != properties.channels
+                        || song.cover_art_bytes != properties.cover_art_bytes
                 })
-            });
+            {
+                (
+                    Some((
+                        song.id.to_string(),
+                        song.path,
+                        song.album,
+                        metadata,
+                        properties,
+                    )),
+                    outcome,
+                )
+            } else {
+                (None, outcome)
+            }
+        })
+        .await;
 
-        let updated_songs = stream::iter(comparison_tasks)
-            .buffer_unordered(16)
-            .filter_map(|res| async move { res.ok() })
-            .flat_map(stream::iter)
-            .collect::<Vec<(_, _)>>()
-            .await;
+        let mut updated_songs = Vec::new();
+        let mut failed_reads = Vec::new();
+        let mut recovered_paths = Vec::new();
+        for (update, outcome) in comparisons {
+            updated_songs.extend(update);
+            match outcome {
+                Some(ReadOutcome::Failed(error)) => failed_reads.push(error),
+                Some(ReadOutcome::Recovered(path)) => recovered_paths.push(path),
+                None => {}
+            }
+        }
 
         if token.is_cancelled() {
-            return Ok(());
+            return Ok(None);
         }
 
         if !updated_songs.is_empty() {
@@ -253,10 +853,58 @@ impl JobHandle for ScanSongs {
             tracing::info!("No updated song(s) found...");
         }
 
-        if song_paths.is_empty() && non_existing_song_ids.is_empty() && updated_songs.is_empty() {
+        if parameters.dry_run {
+            emit_event(
+                &tx,
+                JobEvent::StepCompleted {
+                    step: 3,
+                    value: updated_songs.len().to_string().into(),
+                },
+            )
+            .await;
+
+            let plan = ScanSongsPlan {
+                added: song_paths
+                    .iter()
+                    .map(|path| path.to_string_lossy().to_string())
+                    .chain(cue_changes.iter().flat_map(|(album, _)| {
+                        album.tracks.iter().map(|(song, _, _)| song.path.clone())
+                    }))
+                    .collect(),
+                updated: updated_songs
+                    .into_iter()
+                    .map(|(id, path, _, _, _)| PlannedSong { id, path })
+                    .collect(),
+                missing: missing_songs,
+                restored: restored_songs,
+                deleted: deleted_songs,
+            };
+
+            tracing::info!(
+                "Dry run found {} new, {} updated, {} missing, {} restored and {} deleted song(s)",
+                plan.added.len(),
+                plan.updated.len(),
+                plan.missing.len(),
+                plan.restored.len(),
+                plan.deleted.len()
+            );
+
+            return Ok(Some(serde_json::to_value(plan)?));
+        }
+
+        if song_paths.is_empty()
+            && newly_missing_ids.is_empty()
+            && restored_ids.is_empty()
+            && expired_ids.is_empty()
+            && updated_songs.is_empty()
+            && cue_changes.is_empty()
+        {
             tracing::warn!("No changes found, stopping task...");
 
-            return Ok(());
+            let mut connection = self.db.acquire().await?;
+            save_read_outcomes(&mut connection, &failed_reads, &recovered_paths, now).await;
+
+            return Ok(None);
         }
 
         emit_event(
@@ -268,136 +916,180 @@ impl JobHandle for ScanSongs {
         )
         .await;
 
-        let change_count =
-            (song_paths.len() + updated_songs.len() + non_existing_song_ids.len()) as u64;
+        let cue_track_count = cue_changes
+            .iter()
+            .map(|(album, _)| album.tracks.len())
+            .sum::<usize>();
+        let change_count = (song_paths.len()
+            + cue_track_count
+            + updated_songs.len()
+            + newly_missing_ids.len()
+            + restored_ids.len()
+            + expired_ids.len()) as u64;
+
+        // The new files are read before the transaction starts, so it isn't kept open meanwhile.
+        let reads = run_in_workers(song_paths, workers, &token, move |path: PathBuf| {
+            SongRead::read(path, read_audio_properties)
+        })
+        .await;
 
-        let mut transaction = self.db.begin().await?;
         let mut current_change_index = 0;
+        let mut new_songs = Vec::with_capacity(reads.len());
 
-        for song in song_paths.iter() {
-            if token.is_cancelled() {
-                break;
+        for mut read in reads {
+            let is_song = read.is_song();
+            match read.error.take() {
+                Some(error) => {
+                    emit_event(&tx, scan_warning(&error)).await;
+                    failed_reads.push(error);
+                }
+                None => {
+                    let path = read.path.to_string_lossy();
+                    if failed_paths.contains_key(path.as_ref()) {
+                        recovered_paths.push(path.to_string());
+                    }
+                }
             }
 
-            let path_buf = song.to_path_buf();
-            let metadata = spawn_blocking(move || read_metadata_from_path(&path_buf).ok()).await?;
-
-            let file_created_at = tokio::fs::metadata(song)
-                .await?
-                .created()
-                .map(OffsetDateTime::from)
-                .ok();
-
-            let metadata = metadata.as_ref();
-            if let Err(err) = db::songs::add_song(
-                &mut transaction,
-                db::NewSong {
-                    path: song.to_string_lossy().to_string(),
-                    title: metadata.and_then(|m| m.get(&ItemKey::Title)).cloned(),
-                    artist: metadata.and_then(|m| m.get(&ItemKey::Artist)).cloned(),
-                    album: metadata.and_then(|m| m.get(&ItemKey::Album)).cloned(),
-                    album_artist: metadata.and_then(|m| m.get(&ItemKey::AlbumArtist)).cloned(),
-                    genre: metadata.and_then(|m| m.get(&ItemKey::Genre)).cloned(),
-                    track_number: metadata.and_then(|m| m.get(&ItemKey::TrackNumber)).cloned(),
-                    disc_number: metadata.and_then(|m| m.get(&ItemKey::DiscNumber)).cloned(),
-                    year: metadata.and_then(|m| m.get(&ItemKey::Year)).cloned(),
-                    mood: metadata.and_then(|m| m.get(&ItemKey::Mood)).cloned(),
-                    file_created_at,
-                },
-            )
-            .await
-            {
-                tracing::error!("Song scan error: {err}");
+            // Files without tags are still songs, only files that can't be read at all are left
+            // out.
+            if !is_song {
+                current_change_index += 1;
+                continue;
             }
 
-            current_change_index += 1;
-
-            emit_event(
-                &tx,
-                JobEvent::Progress {
-                    current: current_change_index,
-                    total: change_count,
-                    step: 4,
-                },
-            )
-            .await;
+            new_songs.push(SongInsert {
+                song: read.new_song(),
+                range: None,
+                properties: read.properties,
+            });
         }
 
-        if token.is_cancelled() {
-            return Ok(());
+        let mut cue_songs = Vec::new();
+        let mut replaced_ids = Vec::new();
+        for (album, replaced) in cue_changes {
+            for song in replaced {
+                changed_albums.extend(song.album);
+                replaced_ids.push(song.id);
+            }
+
+            for (song, range, properties) in album.tracks {
+                changed_albums.extend(song.album.clone());
+                cue_songs.push(SongInsert {
+                    song,
+                    range: Some(range),
+                    properties: Some(properties),
+                });
+            }
         }
 
-        for (song_id, metadata) in updated_songs {
+        let song_updates = updated_songs
+            .into_iter()
+            .map(|(id, path, previous_album, metadata, properties)| {
+                let song = updated_song(Path::new(&path), metadata.as_ref());
+                changed_albums.extend(previous_album);
+                changed_albums.extend(song.album.clone());
+
+                SongUpdate {
+                    song,
+                    id,
+                    properties,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Every change is saved in one transaction, so a failed or cancelled scan saves none of
+        // them. Cancellation is checked between batches.
+        let mut transaction = self.db.begin().await?;
+
+        let new_song_count = new_songs.len() + cue_songs.len();
+        let mut added_song_count = 0;
+        for batch in new_songs.chunks(WRITE_BATCH) {
             if token.is_cancelled() {
                 break;
             }
 
-            let metadata = metadata.as_ref();
-            if let Err(err) = db::songs::update_song(
-                &mut transaction,
-                &song_id,
-                db::UpdatedSong {
-                    title: metadata.and_then(|m| m.get(&ItemKey::Title)).cloned(),
-                    album: metadata.and_then(|m| m.get(&ItemKey::Album)).cloned(),
-                    album_artist: metadata.and_then(|m| m.get(&ItemKey::AlbumArtist)).cloned(),
-                    disc_number: metadata.and_then(|m| m.get(&ItemKey::DiscNumber)).cloned(),
-                    artist: metadata.and_then(|m| m.get(&ItemKey::Artist)).cloned(),
-                    year: metadata.and_then(|m| m.get(&ItemKey::Year)).cloned(),
-                    track_number: metadata.and_then(|m| m.get(&ItemKey::TrackNumber)).cloned(),
-                    genre: metadata.and_then(|m| m.get(&ItemKey::Genre)).cloned(),
-                    mood: metadata.and_then(|m| m.get(&ItemKey::Mood)).cloned(),
-                },
-            )
-            .await
-            {
-                tracing::error!("Song scan error: {err}");
+            added_song_count += db::songs::add_songs(&mut transaction, batch).await?;
+            current_change_index += batch.len() as u64;
+            emit_batch_progress(&tx, current_change_index, change_count).await;
+        }
+
+        if token.is_cancelled() {
+            transaction.rollback().await?;
+            return Ok(None);
+        }
+
+        save_read_outcomes(&mut transaction, &failed_reads, &recovered_paths, now).await;
+
+        db::songs::delete_songs(&mut transaction, &replaced_ids).await?;
+        for batch in cue_songs.chunks(WRITE_BATCH) {
+            if token.is_cancelled() {
+                break;
             }
 
-            current_change_index += 1;
+            added_song_count += db::songs::add_songs(&mut transaction, batch).await?;
+            current_change_index += batch.len() as u64;
+            emit_batch_progress(&tx, current_change_index, change_count).await;
+        }
 
-            emit_event(
-                &tx,
-                JobEvent::Progress {
-                    current: current_change_index,
-                    total: change_count,
-                    step: 4,
-                },
-            )
-            .await;
+        if added_song_count < new_song_count as u64 {
+            tracing::warn!(
+                "Skipped {} song(s) that aren't inside any directory",
+                new_song_count as u64 - added_song_count
+            );
         }
 
         if token.is_cancelled() {
-            return Ok(());
+            transaction.rollback().await?;
+            return Ok(None);
         }
 
-        for song_id in non_existing_song_ids {
+        for batch in song_updates.chunks(WRITE_BATCH) {
             if token.is_cancelled() {
                 break;
             }
 
-            if let Err(err) = db::songs::delete_song(&mut transaction, &song_id).await {
-                tracing::error!("Song scan error: {err}");
-            }
+            db::songs::update_songs(&mut transaction, batch).await?;
+            current_change_index += batch.len() as u64;
+            emit_batch_progress(&tx, current_change_index, change_count).await;
+        }
 
-            current_change_index += 1;
+        if token.is_cancelled() {
+            transaction.rollback().await?;
+            return Ok(None);
+        }
 
-            emit_event(
-                &tx,
-                JobEvent::Progress {
-                    current: current_change_index,
-                    total: change_count,
-                    step: 4,
-                },
-            )
-            .await;
+        let restored_ids = restored_ids.into_iter().collect::<Vec<_>>();
+        db::songs::restore_missing_songs(&mut transaction, &restored_ids).await?;
+
+        let newly_missing_ids = newly_missing_ids.into_iter().collect::<Vec<_>>();
+        db::songs::mark_songs_missing(&mut transaction, &newly_missing_ids, now).await?;
+
+        current_change_index += (restored_ids.len() + newly_missing_ids.len()) as u64;
+        emit_batch_progress(&tx, current_change_index, change_count).await;
+
+        let expired_ids = expired_ids.into_iter().collect::<Vec<_>>();
+        for batch in expired_ids.chunks(WRITE_BATCH) {
+            if token.is_cancelled() {
+                break;
+            }
+
+            db::songs::delete_songs(&mut transaction, batch).await?;
+            current_change_index += batch.len() as u64;
+            emit_batch_progress(&tx, current_change_index, change_count).await;
         }
 
         if token.is_cancelled() {
-            return Ok(());
+            transaction.rollback().await?;
+            return Ok(None);
         }
 
         transaction.commit().await?;
 
+        for album in changed_albums {
+            self.cover_art_cache.invalidate_album(&album).await;
+        }
+
         emit_event(
             &tx,
             JobEvent::StepCompleted {
@@ -409,6 +1101,86 @@ impl JobHandle for ScanSongs {
 
         tracing::info!("Finished song scans...");
 
-        Ok(())
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_is_progress_due() {
+        assert!(!is_progress_due(1, 250));
+        assert!(is_progress_due(100, 250));
+        assert!(!is_progress_due(101, 250));
+        assert!(is_progress_due(250, 250));
+        assert!(is_progress_due(3, 3));
+    }
+
+    #[test]
+    fn test_read_cue_album() {
+        let temp = tempdir().unwrap();
+        let audio_path = temp.path().join("album.flac");
+        let cue_path = temp.path().join("album.cue");
+
+        std::fs::copy("data/goose.flac", &audio_path).unwrap();
+        std::fs::write(
+            &cue_path,
+            "PERFORMER \"Band\"\nTITLE \"Album\"\nFILE \"album.flac\" WAVE\n\
+             TRACK 01 AUDIO\nTITLE \"One\"\nINDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\nTITLE \"Two\"\nINDEX 01 00:00:30\n",
+        )
+        .unwrap();
+
+        let album = CueAlbum::read(&cue_path).unwrap().unwrap();
+        assert_eq!(album.audio_path, audio_path);
+        assert_eq!(album.tracks.len(), 2);
+
+        let (song, range, properties) = &album.tracks[0];
+        assert_eq!(song.path, audio_path.to_string_lossy());
+        assert_eq!(song.title.as_deref(), Some("One"));
+        assert_eq!(song.album.as_deref(), Some("Album"));
+        assert_eq!(song.artist.as_deref(), Some("Band"));
+        assert_eq!(song.track_number, Some(1));
+        assert_eq!(song.track_total, Some(2));
+        assert_eq!(range.end_ms, Some(400));
+        assert_eq!(properties.duration_ms, Some(400));
+
+        let saved = album
+            .tracks
+            .iter()
+            .map(|(song, range, properties)| Song {
+                path: song.path.clone(),
+                title: song.title.clone(),
+                artist: song.artist.clone(),
+                album: song.album.clone(),
+                album_artist: song.album_artist.clone(),
+                genre: song.genre.clone(),
+                track_number: song.track_number,
+                track_total: song.track_total,
+                disc_number: song.disc_number,
+                disc_total: song.disc_total,
+                year: song.year.clone(),
+                duration_ms: properties.duration_ms,
+                cue_path: Some(range.cue_path.clone()),
+                start_ms: range.start_ms,
+                end_ms: range.end_ms,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        assert!(album.matches(&saved));
+        assert!(!album.matches(&saved[..1]));
+
+        std::fs::write(
+            &cue_path,
+            "FILE \"missing.flac\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00",
+        )
+        .unwrap();
+        assert!(CueAlbum::read(&cue_path).unwrap().is_none());
     }
 }
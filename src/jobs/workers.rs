@@ -0,0 +1,135 @@
+//! A fixed number of blocking threads working through a list, for jobs that read a file for every
+//! song. Spawning a blocking task per song would queue one for each song of the library at once.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tokio::{sync::mpsc, task::spawn_blocking};
+use tokio_util::sync::CancellationToken;
+
+/// Results sent back before the workers wait for them to be received.
+const RESULT_BUFFER: usize = 256;
+
+/// Runs `work` on every item with `workers` blocking threads, which pull the next item as soon as
+/// they're done with one. Returns the results in the order of the items.
+///
+/// Items that haven't been picked up when the token is cancelled are skipped, so a cancelled run
+/// returns fewer results.
+pub async fn run_in_workers<T, R, F>(
+    items: Vec<T>,
+    workers: usize,
+    token: &CancellationToken,
+    work: F,
+) -> Vec<R>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+{
+    let count = items.len();
+    let queue = Arc::new(Mutex::new(items.into_iter().enumerate()));
+    let work = Arc::new(work);
+    let (tx, mut rx) = mpsc::channel(RESULT_BUFFER);
+
+    for _ in 0..workers.clamp(1, count.max(1)) {
+        let queue = queue.clone();
+        let work = work.clone();
+        let token = token.clone();
+        let tx = tx.clone();
+
+        spawn_blocking(move || {
+            while !token.is_cancelled() {
+                let Some((index, item)) =
+                    queue.lock().unwrap_or_else(PoisonError::into_inner).next()
+                else {
+                    break;
+                };
+
+                if tx.blocking_send((index, work(item))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // The workers hold the only senders left, so the channel closes once they're all done.
+    drop(tx);
+
+    let mut results = Vec::with_capacity(count);
+    results.resize_with(count, || None);
+    while let Some((index, result)) = rx.recv().await {
+        results[index] = Some(result);
+    }
+
+    results.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread::{self, ThreadId},
+    };
+
+    use test_log::test;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_large_list_runs_on_a_fixed_number_of_threads() {
+        const ITEMS: usize = 200_000;
+        const WORKERS: usize = 4;
+
+        let active = Arc::new(AtomicUsize::new(0));
+        let most_active = Arc::new(AtomicUsize::new(0));
+        let threads = Arc::new(Mutex::new(HashSet::<ThreadId>::new()));
+
+        let work = {
+            let active = active.clone();
+            let most_active = most_active.clone();
+            let threads = threads.clone();
+
+            move |item: usize| {
+                let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                most_active.fetch_max(now_active, Ordering::SeqCst);
+                threads.lock().unwrap().insert(thread::current().id());
+                active.fetch_sub(1, Ordering::SeqCst);
+
+                item * 2
+            }
+        };
+
+        let started = std::time::Instant::now();
+        let results = run_in_workers(
+            (0..ITEMS).collect(),
+            WORKERS,
+            &CancellationToken::new(),
+            work,
+        )
+        .await;
+        tracing::info!("Processed {ITEMS} items in {:?}", started.elapsed());
+
+        assert_eq!(results.len(), ITEMS);
+        assert!(
+            results
+                .iter()
+                .enumerate()
+                .all(|(index, result)| *result == index * 2)
+        );
+        assert!(most_active.load(Ordering::SeqCst) <= WORKERS);
+        assert!(threads.lock().unwrap().len() <= WORKERS);
+    }
+
+    #[test(tokio::test)]
+    async fn test_cancelled_run_skips_the_rest() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let results = run_in_workers(vec![1, 2, 3], 2, &token, |item: i32| item).await;
+        assert!(results.is_empty());
+
+        let results =
+            run_in_workers(Vec::<i32>::new(), 0, &CancellationToken::new(), |item| item).await;
+        assert!(results.is_empty());
+    }
+}
@@ -7,27 +7,47 @@ use std::{
 use clap::Parser;
 use tower_http::trace::TraceLayer;
 use tracing::info_span;
-use tracing_subscriber::{prelude::*, util::SubscriberInitExt};
 
 use axum::{
     Router,
     extract::{MatchedPath, Request},
+    middleware,
 };
 
 mod metadata;
 
 mod api;
+mod audio;
+mod backup;
+mod bindings;
+mod cli;
 mod config;
 mod db;
+mod duplicates;
 mod events;
+mod fingerprint;
 mod fs;
+mod genres;
+mod history;
+mod hygiene;
+mod inbox;
+mod infer;
+mod jobs;
+mod logging;
+mod loudness;
+mod m3u;
 mod migration;
 mod organize;
+mod palette;
 mod paths;
+mod providers;
 mod state;
-mod jobs;
+mod transcode;
+mod trash;
 
+pub use cli::{Command, run_command};
 pub use config::load_config;
+pub use logging::{init as initialize_logging, init_log_file};
 pub use migration::run_migrations;
 pub use state::AppState;
 
@@ -38,29 +58,15 @@ pub static APP_DIRECTORIES: LazyLock<Vec<PathBuf>> = LazyLock::new(|| {
     vec![
         paths::app_config_dir(),
         paths::app_cache_dir(),
+        paths::cover_art_cache_dir(),
         paths::app_data_dir(),
         paths::metadata_history_dir(),
         paths::trash_dir(),
+        paths::backups_dir(),
+        paths::reports_dir(),
     ]
 });
 
-pub fn initialize_logging() {
-    color_eyre::install().expect("Failed to install color_eyre");
-
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                format!(
-                    "{}=debug,tower_http=debug,axum::rejection=trace",
-                    env!("CARGO_CRATE_NAME")
-                )
-                .into()
-            }),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-}
-
 /// Command-line arguments.
 #[derive(Parser, Debug)]
 #[command(name = "Music Manager", version, author)]
@@ -80,22 +86,47 @@ pub struct Args {
     /// Path to config file
     #[arg(short, long)]
     pub config: Option<PathBuf>,
+
+    /// What to run, the server unless given
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
 pub fn routes(state: AppState) -> Router {
     Router::new()
+        .merge(api::auth::router())
+        .merge(api::admin::router())
         .merge(api::jobs::router())
         .merge(api::songs::router())
         .merge(api::albums::router())
+        .merge(api::artists::router())
+        .merge(api::genres::router())
+        .merge(api::playlists::router())
+        .merge(api::smart_playlists::router())
+        .merge(api::library::router())
         .merge(api::directories::router())
         .merge(api::cover_art::router())
         .merge(api::info::router())
+        .merge(api::home::router())
+        .merge(api::search::router())
+        .merge(api::providers::router())
+        .merge(api::settings::router())
+        .merge(api::trash::router())
+        .merge(api::inbox::router())
+        .merge(api::subsonic::router())
         .nest(
             "/api",
             Router::new()
                 .merge(events::router())
                 .merge(api::organize::router()),
         )
+        .layer(middleware::from_fn(
+            api::schema_version::check_schema_version,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::auth::require_token,
+        ))
         .with_state(state)
         .merge(api::ui::router())
         .layer(
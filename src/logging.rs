@@ -0,0 +1,313 @@
+//! Logging to the console and, when enabled in the settings, to files in [`paths::logs_dir`].
+//!
+//! Logging starts before the settings are loaded, so the file layer is swapped in afterwards
+//! through a reload handle, the same way the filter of what's logged can be changed while the
+//! server runs.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{InitError, RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, filter::ParseError, layer::Layered, prelude::*, reload,
+};
+use ts_rs::TS;
+
+use crate::{
+    config::{LogFormat, Logging},
+    paths,
+};
+
+const FILE_PREFIX: &str = env!("CARGO_PKG_NAME");
+const FILE_SUFFIX: &str = "log";
+
+/// Most lines returned by [`tail_log`], so a request can't read a whole file into memory.
+pub const MAX_TAIL_LINES: usize = 10_000;
+
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type FileLayer = Box<dyn Layer<Filtered> + Send + Sync>;
+
+struct Handles {
+    filter: reload::Handle<EnvFilter, Registry>,
+    file: reload::Handle<Option<FileLayer>, Filtered>,
+}
+
+static HANDLES: OnceLock<Handles> = OnceLock::new();
+/// Directory the logs are written to, only set once they are.
+static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoggingError {
+    #[error("Logging hasn't been initialized")]
+    Uninitialized,
+    #[error("Invalid log filter: {0}")]
+    InvalidFilter(#[from] ParseError),
+    #[error("Failed to change the log filter: {0}")]
+    Reload(#[from] reload::Error),
+    #[error("Logs aren't written to a file")]
+    NoLogFile,
+    #[error("\"{0}\" isn't a log file")]
+    InvalidFile(String),
+    #[error("Failed to read the logs: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Result<T, E = LoggingError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct LogTail {
+    /// Name of the log file the lines were read from.
+    pub file: String,
+    /// The last lines of the file, oldest first.
+    pub lines: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevel {
+    /// Filter of what's logged, in the syntax of `RUST_LOG`, e.g. `muusik=info,tower_http=warn`.
+    pub filter: String,
+}
+
+/// Starts logging to stderr, filtered by `RUST_LOG` when it's set.
+pub fn init() {
+    color_eyre::install().expect("Failed to install color_eyre");
+
+    let (filter, filter_handle) =
+        reload::Layer::new(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            format!(
+                "{}=debug,tower_http=debug,axum::rejection=trace",
+                env!("CARGO_CRATE_NAME")
+            )
+            .into()
+        }));
+    let (file, file_handle) = reload::Layer::new(None::<FileLayer>);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(file)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let _ = HANDLES.set(Handles {
+        filter: filter_handle,
+        file: file_handle,
+    });
+}
+
+/// Also writes the logs to daily rotated files when the settings ask for it. The returned guard
+/// flushes the logs that are still buffered when it's dropped, so it has to be kept until exiting.
+///
+/// A log directory that can't be written to leaves logging on stderr only, with a warning.
+pub fn init_log_file(settings: &Logging) -> Option<WorkerGuard> {
+    if !settings.file {
+        return None;
+    }
+
+    let handles = HANDLES.get()?;
+    let directory = paths::logs_dir();
+
+    let appender = match file_appender(&directory, settings.max_files) {
+        Ok(appender) => appender,
+        Err(err) => {
+            tracing::warn!(
+                "Failed to write logs to \"{}\", only logging to stderr: {err}",
+                directory.display()
+            );
+            return None;
+        }
+    };
+
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false);
+    let layer: FileLayer = match settings.format {
+        LogFormat::Plain => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+
+    if let Err(err) = handles.file.reload(Some(layer)) {
+        tracing::warn!("Failed to start logging to a file, only logging to stderr: {err}");
+        return None;
+    }
+
+    tracing::info!("Writing logs to \"{}\"", directory.display());
+    let _ = DIRECTORY.set(directory);
+
+    Some(guard)
+}
+
+/// Replaces the filter of what's logged, returning the filter as it was parsed.
+pub fn set_filter(filter: &str) -> Result<LogLevel> {
+    let handles = HANDLES.get().ok_or(LoggingError::Uninitialized)?;
+    let filter = EnvFilter::try_new(filter)?;
+    let level = LogLevel {
+        filter: filter.to_string(),
+    };
+
+    handles.filter.reload(filter)?;
+    tracing::info!("Changed the log filter to \"{}\"", level.filter);
+
+    Ok(level)
+}
+
+/// Reads the last lines of the log file named `file`, or of the one currently written to.
+pub fn tail_log(file: Option<&str>, lines: usize) -> Result<LogTail> {
+    let directory = DIRECTORY.get().ok_or(LoggingError::NoLogFile)?;
+    let path = match file {
+        Some(file) => resolve_log_file(directory, file)?,
+        None => current_log_file(directory)?,
+    };
+
+    Ok(LogTail {
+        file: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        lines: tail_lines(&path, lines.min(MAX_TAIL_LINES))?,
+    })
+}
+
+fn file_appender(directory: &Path, max_files: usize) -> Result<RollingFileAppender, InitError> {
+    let builder = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX);
+
+    if max_files > 0 {
+        builder.max_log_files(max_files).build(directory)
+    } else {
+        builder.build(directory)
+    }
+}
+
+/// Log files are named like `muusik.2026-10-15.log`.
+fn is_log_name(name: &str) -> bool {
+    name.starts_with(&format!("{FILE_PREFIX}."))
+        && Path::new(name)
+            .extension()
+            .is_some_and(|extension| extension == FILE_SUFFIX)
+}
+
+/// Finds the named log file, refusing any name that isn't a log file right inside the directory.
+fn resolve_log_file(directory: &Path, name: &str) -> Result<PathBuf> {
+    let invalid = || LoggingError::InvalidFile(name.to_string());
+
+    if Path::new(name)
+        .file_name()
+        .is_none_or(|file_name| file_name != name)
+        || !is_log_name(name)
+    {
+        return Err(invalid());
+    }
+
+    let path = directory.join(name).canonicalize().map_err(|_| invalid())?;
+    if path.parent() != Some(directory.canonicalize()?.as_path()) || !path.is_file() {
+        return Err(invalid());
+    }
+
+    Ok(path)
+}
+
+/// The newest log file, their names sort the same as their dates.
+fn current_log_file(directory: &Path) -> Result<PathBuf> {
+    std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| is_log_name(name))
+        .max()
+        .map(|name| directory.join(name))
+        .ok_or(LoggingError::NoLogFile)
+}
+
+/// Reads the last lines of the file, backwards in chunks so the rest of it isn't read.
+fn tail_lines(path: &Path, count: usize) -> io::Result<Vec<String>> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    let mut file = File::open(path)?;
+    let mut position = file.seek(SeekFrom::End(0))?;
+    let mut buffer = Vec::new();
+
+    // The first line read is likely cut off, so one more line break than lines is needed.
+    while position > 0 && buffer.iter().filter(|byte| **byte == b'\n').count() <= count {
+        let size = CHUNK_SIZE.min(position);
+        position -= size;
+
+        let mut chunk = vec![0; size as usize];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut chunk)?;
+
+        chunk.extend_from_slice(&buffer);
+        buffer = chunk;
+    }
+
+    let text = String::from_utf8_lossy(&buffer);
+    let lines = text.lines().collect::<Vec<_>>();
+
+    Ok(lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_tailing_log_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let logs = dir.path().join("logs");
+        std::fs::create_dir(&logs)?;
+
+        let older = logs.join(format!("{FILE_PREFIX}.2026-10-14.{FILE_SUFFIX}"));
+        std::fs::write(&older, "old\n")?;
+
+        let current = logs.join(format!("{FILE_PREFIX}.2026-10-15.{FILE_SUFFIX}"));
+        let mut file = File::create(&current)?;
+        for line in 0..20_000 {
+            writeln!(file, "line {line}")?;
+        }
+
+        assert_eq!(current_log_file(&logs)?, current);
+        assert_eq!(tail_lines(&current, 2)?, ["line 19998", "line 19999"]);
+        assert_eq!(tail_lines(&current, 20_001)?.len(), 20_000);
+        assert_eq!(tail_lines(&older, 10)?, ["old"]);
+
+        let name = format!("{FILE_PREFIX}.2026-10-14.{FILE_SUFFIX}");
+        assert_eq!(resolve_log_file(&logs, &name)?, older.canonicalize()?);
+
+        std::fs::write(dir.path().join("secret.log"), "secret\n")?;
+        for name in [
+            "../secret.log",
+            "secret.log",
+            "/etc/passwd",
+            &format!("../logs/{name}"),
+            &format!("{FILE_PREFIX}.2026-10-16.{FILE_SUFFIX}"),
+        ] {
+            assert!(
+                matches!(
+                    resolve_log_file(&logs, name),
+                    Err(LoggingError::InvalidFile(_))
+                ),
+                "{name} was resolved"
+            );
+        }
+
+        Ok(())
+    }
+}
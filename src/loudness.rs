@@ -0,0 +1,220 @@
+//! ReplayGain 2.0 loudness analysis, measuring decoded songs with an EBU R128 meter.
+
+use std::{ops::ControlFlow, path::Path};
+
+use ebur128::{EbuR128, Mode};
+
+use crate::{
+    audio::{AudioDecoder, AudioError},
+    metadata::{Metadata, item::ItemKey},
+};
+
+/// Loudness ReplayGain 2.0 normalizes to, in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoudnessError {
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+    #[error("Failed to measure loudness: {0}")]
+    Meter(#[from] ebur128::Error),
+    #[error("File is silent")]
+    Silent,
+}
+
+type Result<T, E = LoudnessError> = std::result::Result<T, E>;
+
+/// The measured loudness of a single track, kept around to compute the gain of its album.
+pub struct TrackLoudness {
+    meter: EbuR128,
+    /// Largest absolute sample value across all channels, `1.0` is full scale.
+    peak: f64,
+}
+
+impl TrackLoudness {
+    /// Decodes the whole file and measures its loudness.
+    pub fn analyze(path: &Path) -> Result<Self> {
+        let decoder = AudioDecoder::open(path)?;
+        let channels = decoder.channels();
+
+        // The histogram keeps the memory used per track constant, no matter how long it is.
+        let mut meter = EbuR128::new(
+            channels,
+            decoder.sample_rate(),
+            Mode::I | Mode::SAMPLE_PEAK | Mode::HISTOGRAM,
+        )?;
+
+        decoder.decode(|samples| {
+            meter.add_frames_f32(samples)?;
+
+            Ok::<_, LoudnessError>(ControlFlow::Continue(()))
+        })?;
+
+        let peak = (0..channels)
+            .map(|channel| meter.sample_peak(channel))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .fold(0.0, f64::max);
+
+        Ok(Self { meter, peak })
+    }
+
+    /// The gain in dB that brings the track to the reference loudness.
+    pub fn gain(&self) -> Result<f64> {
+        gain(self.meter.loudness_global()?)
+    }
+
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+}
+
+/// Track and album gain of a song, as stored in its `REPLAYGAIN_*` tags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain_db: f64,
+    pub track_peak: f64,
+    /// Not set for songs without an album.
+    pub album_gain_db: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+impl ReplayGain {
+    /// Computes the gain of every track and of the album they make up together.
+    ///
+    /// Pass a single track for songs without an album, no album gain is computed for them.
+    pub fn for_album(tracks: &[TrackLoudness], album: bool) -> Result<Vec<Self>> {
+        let album_gain = album
+            .then(|| -> Result<_> {
+                let loudness =
+                    EbuR128::loudness_global_multiple(tracks.iter().map(|track| &track.meter))?;
+                let peak = tracks.iter().map(TrackLoudness::peak).fold(0.0, f64::max);
+
+                Ok((gain(loudness)?, peak))
+            })
+            .transpose()?;
+
+        tracks
+            .iter()
+            .map(|track| {
+                Ok(Self {
+                    track_gain_db: track.gain()?,
+                    track_peak: track.peak(),
+                    album_gain_db: album_gain.map(|(gain, _)| gain),
+                    album_peak: album_gain.map(|(_, peak)| peak),
+                })
+            })
+            .collect()
+    }
+
+    /// Writes the gain to the tags, removing album tags left over from a previous analysis if
+    /// there's no album gain.
+    pub fn apply_to(&self, metadata: &mut Metadata) {
+        metadata.insert(
+            ItemKey::ReplayGainTrackGain,
+            format_gain(self.track_gain_db),
+        );
+        metadata.insert(ItemKey::ReplayGainTrackPeak, format_peak(self.track_peak));
+
+        match (self.album_gain_db, self.album_peak) {
+            (Some(gain), Some(peak)) => {
+                metadata.insert(ItemKey::ReplayGainAlbumGain, format_gain(gain));
+                metadata.insert(ItemKey::ReplayGainAlbumPeak, format_peak(peak));
+            }
+            _ => {
+                metadata.remove(&ItemKey::ReplayGainAlbumGain);
+                metadata.remove(&ItemKey::ReplayGainAlbumPeak);
+            }
+        }
+    }
+}
+
+fn gain(loudness: f64) -> Result<f64> {
+    // Silence is measured as negative infinity, there's no gain that would make it audible.
+    if !loudness.is_finite() {
+        return Err(LoudnessError::Silent);
+    }
+
+    Ok(REFERENCE_LOUDNESS - loudness)
+}
+
+/// Formats the gain the way other taggers write it, e.g. `-6.52 dB`.
+fn format_gain(gain: f64) -> String {
+    format!("{gain:.2} dB")
+}
+
+fn format_peak(peak: f64) -> String {
+    format!("{peak:.6}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_analyzing_samples() {
+        let tracks = ["data/goose.flac", "data/flip.mp3"]
+            .iter()
+            .map(|path| TrackLoudness::analyze(Path::new(path)).unwrap())
+            .collect::<Vec<_>>();
+
+        let gains = ReplayGain::for_album(&tracks, true).unwrap();
+        assert_eq!(gains.len(), 2);
+
+        for gain in &gains {
+            assert!(gain.track_gain_db.is_finite());
+            assert!(gain.track_peak > 0.0);
+            assert!(gain.album_peak >= Some(gain.track_peak));
+        }
+
+        let single = ReplayGain::for_album(&tracks[..1], false).unwrap();
+        assert_eq!(single[0].track_gain_db, gains[0].track_gain_db);
+        assert_eq!(single[0].album_gain_db, None);
+    }
+
+    #[test]
+    fn test_analyzing_non_audio_file() {
+        assert!(TrackLoudness::analyze(Path::new("Cargo.toml")).is_err());
+    }
+
+    #[test]
+    fn test_writing_tags() {
+        let mut metadata = Metadata::new(BTreeMap::new(), BTreeMap::new());
+
+        ReplayGain {
+            track_gain_db: -6.519,
+            track_peak: 0.98855,
+            album_gain_db: Some(-7.0),
+            album_peak: Some(1.0),
+        }
+        .apply_to(&mut metadata);
+
+        assert_eq!(
+            metadata.get(&ItemKey::ReplayGainTrackGain).unwrap(),
+            "-6.52 dB"
+        );
+        assert_eq!(
+            metadata.get(&ItemKey::ReplayGainTrackPeak).unwrap(),
+            "0.988550"
+        );
+        assert_eq!(
+            metadata.get(&ItemKey::ReplayGainAlbumGain).unwrap(),
+            "-7.00 dB"
+        );
+
+        ReplayGain {
+            track_gain_db: 1.0,
+            track_peak: 0.5,
+            album_gain_db: None,
+            album_peak: None,
+        }
+        .apply_to(&mut metadata);
+
+        assert_eq!(metadata.get(&ItemKey::ReplayGainAlbumGain), None);
+        assert_eq!(metadata.get(&ItemKey::ReplayGainAlbumPeak), None);
+    }
+}
@@ -0,0 +1,200 @@
+//! Reading and writing of extended M3U playlist files.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use ts_rs::TS;
+
+use crate::db::Song;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistFormat {
+    M3u,
+    #[default]
+    M3u8,
+}
+
+impl PlaylistFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::M3u => "m3u",
+            Self::M3u8 => "m3u8",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::M3u => "audio/x-mpegurl",
+            Self::M3u8 => "application/vnd.apple.mpegurl",
+        }
+    }
+}
+
+/// A track to write into a playlist file.
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub path: String,
+    /// Duration in seconds, written as `-1` if unknown.
+    pub duration: Option<u64>,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+}
+
+impl From<&Song> for Track {
+    fn from(song: &Song) -> Self {
+        Self {
+            path: song.path.clone(),
+            duration: song
+                .duration_ms
+                .map(|duration_ms| u64::from(duration_ms / 1000)),
+            artist: song.artist.clone(),
+            title: song.title.clone(),
+        }
+    }
+}
+
+/// A path read from a playlist file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The line number the path was found on, starting at 1.
+    pub line: usize,
+    pub path: String,
+}
+
+/// The contents of a parsed playlist file.
+#[derive(Debug, Default)]
+pub struct Playlist {
+    /// The name given by a `#PLAYLIST:` directive.
+    pub name: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// Renders an extended M3U playlist.
+///
+/// Both formats are written as UTF-8, as that's what every modern player expects.
+pub fn render(name: &str, tracks: &[Track]) -> String {
+    let mut output = format!("#EXTM3U\n#PLAYLIST:{}\n", single_line(name));
+
+    for track in tracks {
+        let title = track.title.as_deref().map(single_line).unwrap_or_else(|| {
+            Path::new(&track.path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        let duration = track.duration.map(|duration| duration as i64).unwrap_or(-1);
+
+        match &track.artist {
+            Some(artist) => output.push_str(&format!(
+                "#EXTINF:{duration},{} - {title}\n",
+                single_line(artist)
+            )),
+            None => output.push_str(&format!("#EXTINF:{duration},{title}\n")),
+        }
+
+        output.push_str(&track.path);
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Parses a M3U or M3U8 playlist, ignoring comments and unknown directives.
+pub fn parse(content: &str) -> Playlist {
+    let mut playlist = Playlist::default();
+
+    for (index, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            playlist.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            continue;
+        }
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        playlist.entries.push(Entry {
+            line: index + 1,
+            path: line.strip_prefix("file://").unwrap_or(line).to_string(),
+        });
+    }
+
+    playlist
+}
+
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let output = render(
+            "Playlist",
+            &[
+                Track {
+                    path: String::from("/music/Artist/Song.flac"),
+                    duration: Some(215),
+                    artist: Some(String::from("Artist")),
+                    title: Some(String::from("Song")),
+                },
+                Track {
+                    path: String::from("/music/Untitled.mp3"),
+                    ..Default::default()
+                },
+            ],
+        );
+
+        assert_eq!(
+            output,
+            "#EXTM3U\n#PLAYLIST:Playlist\n#EXTINF:215,Artist - Song\n/music/Artist/Song.flac\n#EXTINF:-1,Untitled\n/music/Untitled.mp3\n"
+        );
+    }
+
+    #[test]
+    fn test_track_from_song() {
+        let song = Song {
+            path: String::from("/music/Song.flac"),
+            duration_ms: Some(215_900),
+            ..Default::default()
+        };
+
+        assert_eq!(Track::from(&song).duration, Some(215));
+        assert_eq!(Track::from(&Song::default()).duration, None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let playlist = parse(
+            "\u{feff}#EXTM3U\r\n#PLAYLIST:Road Trip\r\n#EXTINF:215,Artist - Song\r\n/music/Song.flac\r\n\r\n# comment\r\nrelative/Other.mp3\r\nfile:///music/Third.ogg\r\n",
+        );
+
+        assert_eq!(playlist.name.as_deref(), Some("Road Trip"));
+        assert_eq!(
+            playlist.entries,
+            [
+                Entry {
+                    line: 4,
+                    path: String::from("/music/Song.flac")
+                },
+                Entry {
+                    line: 7,
+                    path: String::from("relative/Other.mp3")
+                },
+                Entry {
+                    line: 8,
+                    path: String::from("/music/Third.ogg")
+                },
+            ]
+        );
+    }
+}
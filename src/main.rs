@@ -12,8 +12,8 @@ use sqlx::sqlite::SqlitePoolOptions;
 use tokio::signal;
 
 use muusik::{
-    APP_DIRECTORIES, AppState, Args, create_default_database, initialize_logging, load_config,
-    routes, run_migrations,
+    APP_DIRECTORIES, AppState, Args, Command, create_default_database, init_log_file,
+    initialize_logging, load_config, routes, run_command, run_migrations,
 };
 
 #[tokio::main]
@@ -31,6 +31,7 @@ async fn main() {
 
     let args = Args::parse();
     let settings = load_config(&args).expect("Failed to load settings");
+    let _log_guard = init_log_file(&settings.logging);
 
     let database_url = match &settings.server.database_url {
         Some(url) if !url.trim().is_empty() => url,
@@ -64,9 +65,22 @@ async fn main() {
         .await
         .expect("Failed to connect to database");
 
-    run_migrations(&pool, new_database)
-        .await
-        .expect("Failed to run migrations");
+    if let Err(err) = run_migrations(&pool, new_database, Some(&settings.backups)).await {
+        tracing::error!("Failed to run migrations: {err}");
+        std::process::exit(1);
+    }
+
+    match args.command {
+        None | Some(Command::Serve) => {}
+        Some(command) => {
+            if let Err(err) = run_command(command, pool, settings).await {
+                tracing::error!("{err}");
+                std::process::exit(1);
+            }
+
+            return;
+        }
+    }
 
     let host = settings.server.host.unwrap_or_else(|| {
         if settings.server.listen_on_all_interfaces {
@@ -83,6 +97,7 @@ async fn main() {
         .expect("Failed to bind to address");
 
     let state = AppState::new(pool, settings);
+    let shutdown = shutdown_signal(state.clone());
 
     tracing::info!(
         "Listening on {}{}",
@@ -90,13 +105,36 @@ async fn main() {
         addr.underline().blue()
     );
 
-    axum::serve(listener, routes(state))
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .expect("Failed to start server");
+    axum::serve(
+        listener,
+        routes(state).into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await
+    .expect("Failed to start server");
+}
+
+/// Waits for a signal to shut down, then stops the running jobs and file operations before the
+/// server stops. A second signal exits right away.
+async fn shutdown_signal(state: AppState) {
+    wait_for_signal().await;
+
+    tracing::info!(
+        "Shutting down, stopping jobs and file operations. Press Ctrl+C again to exit right away"
+    );
+
+    tokio::spawn(async {
+        wait_for_signal().await;
+        tracing::warn!("Exiting without waiting for jobs and file operations to stop");
+        std::process::exit(1);
+    });
+
+    if !state.shutdown().await {
+        tracing::warn!("Jobs or file operations didn't stop in time");
+    }
 }
 
-async fn shutdown_signal() {
+async fn wait_for_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -1,12 +1,23 @@
 mod album;
 mod cover_art;
+mod cue;
 mod file;
+mod placeholder;
+mod schema;
 mod song;
+mod validate;
+mod write;
 
 pub mod item;
-pub use {album::*, cover_art::*, song::*};
+pub mod position;
+pub mod rating;
+pub use {
+    album::*, cover_art::*, cue::*, file::*, placeholder::*, schema::*, song::*, validate::*,
+};
 
-pub const TAG_SEPARATOR: char = ';';
+/// Separator the values of a field are joined with where a field only has room for a single
+/// string. Values are never split on it, since a value can contain it.
+pub const VALUE_SEPARATOR: &str = "; ";
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -18,8 +29,17 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Parse error: {0}")]
     Parse(#[from] std::num::ParseIntError),
+    #[error(transparent)]
+    CoverArt(#[from] CoverArtError),
+    #[error("Cue sheet error: {0}")]
+    Cue(#[from] CueError),
     #[error("Lofty error: {0}")]
     Lofty(#[from] lofty::error::LoftyError),
+    #[error("Failed to write the tags of {}: {source}", path.display())]
+    Write {
+        path: std::path::PathBuf,
+        source: lofty::error::LoftyError,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
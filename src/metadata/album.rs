@@ -6,8 +6,7 @@ use super::{Error, Metadata as SongMetadata};
 
 #[derive(Debug, Clone, Default, TS, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
-#[ts(rename = "Album")]
-#[ts(export)]
+#[ts(rename = "AlbumMetadata")]
 pub struct Album {
     pub title: String,
     pub tracks: Vec<SongMetadata>,
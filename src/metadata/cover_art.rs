@@ -1,10 +1,23 @@
-use std::{fmt::Debug, path::Path};
+use std::{
+    ffi::OsStr,
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
 
+use lofty::config::WriteOptions;
+use lofty::id3::v2::Id3v2Tag;
 use lofty::probe::Probe;
-use lofty::tag::TagType;
-use lofty::{picture::PictureType, prelude::*};
+use lofty::tag::{Tag, TagType};
+use lofty::{
+    picture::{MimeType, Picture, PictureType},
+    prelude::*,
+};
 
-use super::{Result, SongError};
+use super::{Result, SongError, write::write_tag};
+
+/// Extensions of the images looked for next to songs, preferring earlier ones.
+const EXTERNAL_COVER_ART_EXTENSIONS: [&str; 7] =
+    ["jpg", "jpeg", "png", "webp", "gif", "bmp", "tiff"];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 #[non_exhaustive]
@@ -37,6 +50,36 @@ impl From<PictureType> for CoverArtType {
     }
 }
 
+impl From<CoverArtType> for PictureType {
+    fn from(value: CoverArtType) -> Self {
+        match value {
+            CoverArtType::Front => PictureType::CoverFront,
+            CoverArtType::Back => PictureType::CoverBack,
+            CoverArtType::Other => PictureType::Other,
+        }
+    }
+}
+
+impl CoverArtType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoverArtType::Front => "front",
+            CoverArtType::Back => "back",
+            CoverArtType::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CoverArtError {
+    #[error("Invalid image: {0}")]
+    InvalidImage(#[from] image::ImageError),
+    #[error("{0:?} tags don't support embedded pictures")]
+    Unsupported(TagType),
+    #[error("{0:?} tags don't store picture types, only the front cover can be changed")]
+    FrontCoverOnly(TagType),
+}
+
 #[derive(Clone, serde::Serialize)]
 pub struct CoverArt {
     pub cover_type: CoverArtType,
@@ -108,6 +151,161 @@ pub fn get_cover_art(path: &Path) -> Result<Vec<CoverArt>> {
     })
 }
 
+/// Returns an error if pictures of the type can't be told apart in the tag.
+///
+/// MP4 files don't store picture types, so their first picture is treated as the front cover and
+/// no other type can be changed.
+pub fn check_cover_art_type(tag_type: TagType, cover_type: CoverArtType) -> Result<()> {
+    if tag_type == TagType::Mp4Ilst && cover_type != CoverArtType::Front {
+        return Err(CoverArtError::FrontCoverOnly(tag_type).into());
+    }
+
+    Ok(())
+}
+
+/// Embeds an image into the file, replacing any existing picture of the same type.
+///
+/// See [`check_cover_art_type`] for MP4 files.
+pub fn set_cover_art(path: &Path, cover_type: CoverArtType, data: Vec<u8>) -> Result<()> {
+    let format = image::guess_format(&data).map_err(CoverArtError::from)?;
+    image::load_from_memory_with_format(&data, format).map_err(CoverArtError::from)?;
+
+    let picture = Picture::new_unchecked(
+        cover_type.into(),
+        Some(MimeType::from_str(format.to_mime_type())),
+        None,
+        data,
+    );
+
+    update_pictures(path, |tag| {
+        check_cover_art_type(tag.tag_type(), cover_type)?;
+
+        if tag.tag_type() == TagType::Mp4Ilst {
+            if tag.picture_count() > 0 {
+                tag.set_picture(0, picture);
+            } else {
+                tag.push_picture(picture);
+            }
+        } else {
+            tag.remove_picture_type(cover_type.into());
+            tag.push_picture(picture);
+        }
+
+        Ok(())
+    })
+}
+
+/// Removes every picture of the type from the file.
+///
+/// See [`check_cover_art_type`] for MP4 files.
+pub fn remove_cover_art(path: &Path, cover_type: CoverArtType) -> Result<()> {
+    update_pictures(path, |tag| {
+        check_cover_art_type(tag.tag_type(), cover_type)?;
+
+        if tag.tag_type() == TagType::Mp4Ilst {
+            if tag.picture_count() > 0 {
+                tag.remove_picture(0);
+            }
+        } else {
+            tag.remove_picture_type(cover_type.into());
+        }
+
+        Ok(())
+    })
+}
+
+fn update_pictures(path: &Path, update: impl FnOnce(&mut Tag) -> Result<()>) -> Result<()> {
+    let mut tagged_file = Probe::open(path)?.read()?;
+    let tag_type = tagged_file.primary_tag_type();
+
+    if matches!(
+        tag_type,
+        TagType::Id3v1 | TagType::RiffInfo | TagType::AiffText
+    ) {
+        return Err(CoverArtError::Unsupported(tag_type).into());
+    }
+
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+
+    let tag = tagged_file
+        .primary_tag_mut()
+        .expect("Primary tag should have been inserted");
+
+    update(tag)?;
+
+    write_tag(path, |path| match tag_type {
+        TagType::Id3v2 => Id3v2Tag::from(tag.clone()).save_to_path(path, WriteOptions::default()),
+        _ => tag.save_to_path(path, WriteOptions::default()),
+    })
+}
+
+/// Finds the image in the directory of the song named like one of `names` without its extension,
+/// e.g. `cover` for `cover.jpg`, preferring earlier names. Names are matched ignoring case.
+///
+/// Only files directly inside the song's directory are looked at and symlinks aren't followed,
+/// so no name can lead outside of it.
+pub fn find_external_cover_art(
+    song_path: &Path,
+    cover_type: CoverArtType,
+    names: &[String],
+) -> std::io::Result<Option<CoverArt>> {
+    let Some(directory) = song_path.parent() else {
+        return Ok(None);
+    };
+
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut found: Option<((usize, usize), PathBuf)> = None;
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let (Some(stem), Some(extension)) = (
+            path.file_stem().and_then(OsStr::to_str),
+            path.extension().and_then(OsStr::to_str),
+        ) else {
+            continue;
+        };
+
+        let stem = stem.to_lowercase();
+        let rank = names
+            .iter()
+            .position(|name| name.to_lowercase() == stem)
+            .zip(
+                EXTERNAL_COVER_ART_EXTENSIONS
+                    .iter()
+                    .position(|candidate| candidate.eq_ignore_ascii_case(extension)),
+            );
+
+        if let Some(rank) = rank
+            && found.as_ref().is_none_or(|(found, _)| rank < *found)
+        {
+            found = Some((rank, path));
+        }
+    }
+
+    found
+        .map(|(_, path)| {
+            Ok(CoverArt {
+                cover_type,
+                mime_type: mime_guess::from_path(&path)
+                    .first_or_octet_stream()
+                    .to_string(),
+                data: std::fs::read(&path)?,
+            })
+        })
+        .transpose()
+}
+
 pub fn get_external_cover_art(path: &Path) -> Result<Vec<CoverArt>> {
     let mut cover_art = Vec::new();
 
@@ -174,6 +372,116 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_set_and_remove_cover_art() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for file in ["data/goose.flac", "data/flip.mp3", "data/bumm.m4a"] {
+            let path = dir.path().join(Path::new(file).file_name().unwrap());
+            std::fs::copy(file, &path).unwrap();
+
+            let front = std::fs::read("data/cover.jpg").unwrap();
+            let back = std::fs::read("data/cover.png").unwrap();
+
+            // MP4 files don't store picture types, nothing but the front cover can be changed.
+            if file.ends_with(".m4a") {
+                for result in [
+                    set_cover_art(&path, CoverArtType::Back, back.clone()),
+                    remove_cover_art(&path, CoverArtType::Other),
+                ] {
+                    assert!(matches!(
+                        result,
+                        Err(crate::metadata::Error::CoverArt(
+                            CoverArtError::FrontCoverOnly(_)
+                        ))
+                    ));
+                }
+            } else {
+                set_cover_art(&path, CoverArtType::Back, back)
+                    .unwrap_or_else(|err| panic!("Failed to set cover art of {file}: {err}"));
+            }
+
+            let count = get_cover_art(&path).unwrap().len();
+            set_cover_art(&path, CoverArtType::Front, front.clone())
+                .unwrap_or_else(|err| panic!("Failed to set cover art of {file}: {err}"));
+            set_cover_art(&path, CoverArtType::Front, front.clone())
+                .unwrap_or_else(|err| panic!("Failed to set cover art of {file}: {err}"));
+
+            // Setting a picture again replaces it.
+            let cover_art = get_cover_art(&path).unwrap();
+            assert!(cover_art.len() <= count + 1, "{file}");
+            assert!(cover_art.iter().any(|cover_art| {
+                cover_art.cover_type == CoverArtType::Front && cover_art.data == front
+            }));
+
+            remove_cover_art(&path, CoverArtType::Front).unwrap();
+            assert!(
+                get_cover_art(&path)
+                    .unwrap()
+                    .iter()
+                    .all(|cover_art| cover_art.data != front)
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_invalid_cover_art() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("goose.flac");
+        std::fs::copy("data/goose.flac", &path).unwrap();
+
+        assert!(matches!(
+            set_cover_art(&path, CoverArtType::Front, b"not an image".to_vec()),
+            Err(crate::metadata::Error::CoverArt(
+                CoverArtError::InvalidImage(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_find_external_cover_art() {
+        let dir = tempfile::tempdir().unwrap();
+        let song = dir.path().join("goose.flac");
+        for name in [
+            "Folder.PNG",
+            "cover.webp",
+            "cover.txt",
+            "back.jpg",
+            "goose.flac",
+        ] {
+            std::fs::write(dir.path().join(name), name).unwrap();
+        }
+        std::fs::create_dir(dir.path().join("cover.jpg")).unwrap();
+
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let find = |names: &[String]| {
+            find_external_cover_art(&song, CoverArtType::Front, names)
+                .unwrap()
+                .map(|cover_art| String::from_utf8(cover_art.data).unwrap())
+        };
+
+        assert_eq!(
+            find(&names(&["cover", "folder"])).as_deref(),
+            Some("cover.webp")
+        );
+        assert_eq!(
+            find(&names(&["folder", "cover"])).as_deref(),
+            Some("Folder.PNG")
+        );
+        assert_eq!(find(&names(&["front"])), None);
+        assert_eq!(find(&names(&["../cover", "goose"])), None);
+        assert!(
+            find_external_cover_art(&dir.path().join("gone/goose.flac"), CoverArtType::Back, &[])
+                .unwrap()
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_get_external_cover_art() {
         let cover_art = get_external_cover_art(Path::new("data/")).unwrap();
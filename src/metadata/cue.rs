@@ -0,0 +1,350 @@
+//! Parses cue sheets describing the tracks of an album stored in a single file.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use super::Result;
+
+pub const CUE_FILE_EXTENSION: &str = "cue";
+
+/// Cue sheet index times are given in frames, 75 to a second.
+const FRAMES_PER_SECOND: u32 = 75;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CueError {
+    #[error("Cue sheet doesn't reference a file")]
+    MissingFile,
+    #[error("Cue sheets referencing multiple files are not supported")]
+    MultipleFiles,
+    #[error("Cue sheet references \"{0}\", which isn't a file next to it")]
+    InvalidFile(String),
+    #[error("Cue sheet has no tracks")]
+    NoTracks,
+    #[error("Track {0} has no start index")]
+    MissingIndex(u32),
+    #[error("Invalid track number: {0}")]
+    InvalidTrack(String),
+    #[error("Invalid index time: {0}")]
+    InvalidTime(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    /// Name of the file the tracks are stored in, in the directory of the cue sheet.
+    pub file: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Where the track starts in the file, taken from `INDEX 01`.
+    pub start_ms: u32,
+}
+
+impl CueSheet {
+    pub fn parse(content: &str) -> Result<Self, CueError> {
+        let mut sheet = CueSheet::default();
+        let mut file = None;
+        let mut track: Option<(CueTrack, bool)> = None;
+
+        for line in content.trim_start_matches('\u{feff}').lines() {
+            let (command, arguments) = line
+                .trim()
+                .split_once(char::is_whitespace)
+                .map(|(command, arguments)| (command, arguments.trim()))
+                .unwrap_or((line.trim(), ""));
+
+            match command.to_uppercase().as_str() {
+                "REM" => {
+                    let (field, value) = arguments.split_once(char::is_whitespace).unzip();
+                    let value = value.map(unquote);
+
+                    match field.map(str::to_uppercase).as_deref() {
+                        Some("GENRE") => sheet.genre = value,
+                        Some("DATE") => sheet.date = value,
+                        _ => {}
+                    }
+                }
+                "FILE" => {
+                    if file.is_some() {
+                        return Err(CueError::MultipleFiles);
+                    }
+
+                    let name = file_name(arguments);
+
+                    // Anything but a plain name could point outside of the cue sheet's directory.
+                    if Path::new(&name).file_name() != Some(OsStr::new(&name)) {
+                        return Err(CueError::InvalidFile(name));
+                    }
+
+                    file = Some(name);
+                }
+                "TITLE" => match &mut track {
+                    Some((track, _)) => track.title = Some(unquote(arguments)),
+                    None => sheet.title = Some(unquote(arguments)),
+                },
+                "PERFORMER" => match &mut track {
+                    Some((track, _)) => track.performer = Some(unquote(arguments)),
+                    None => sheet.performer = Some(unquote(arguments)),
+                },
+                "TRACK" => {
+                    if let Some(track) = track.take() {
+                        sheet.tracks.push(finish_track(track)?);
+                    }
+
+                    let number = arguments.split_whitespace().next().unwrap_or_default();
+                    let number = number
+                        .parse::<u32>()
+                        .map_err(|_| CueError::InvalidTrack(number.to_string()))?;
+
+                    track = Some((
+                        CueTrack {
+                            number,
+                            ..Default::default()
+                        },
+                        false,
+                    ));
+                }
+                "INDEX" => {
+                    let Some((track, has_index)) = &mut track else {
+                        continue;
+                    };
+
+                    if let Some(("01", time)) = arguments.split_once(char::is_whitespace) {
+                        track.start_ms = parse_time(time.trim())?;
+                        *has_index = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(track) = track {
+            sheet.tracks.push(finish_track(track)?);
+        }
+
+        sheet.file = file.ok_or(CueError::MissingFile)?;
+
+        if sheet.tracks.is_empty() {
+            return Err(CueError::NoTracks);
+        }
+
+        Ok(sheet)
+    }
+
+    /// Returns the path of the file the tracks are stored in.
+    pub fn audio_path(&self, cue_path: &Path) -> PathBuf {
+        cue_path
+            .parent()
+            .map(|directory| directory.join(&self.file))
+            .unwrap_or_else(|| PathBuf::from(&self.file))
+    }
+
+    /// Returns where every track starts and ends, each track ends where the next one starts and
+    /// the last one ends at `duration_ms`.
+    pub fn track_ranges(&self, duration_ms: Option<u32>) -> Vec<(u32, Option<u32>)> {
+        self.tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| {
+                let end_ms = self
+                    .tracks
+                    .get(index + 1)
+                    .map(|next| next.start_ms)
+                    .or(duration_ms);
+
+                (track.start_ms, end_ms)
+            })
+            .collect()
+    }
+}
+
+/// Returns whether the path has the [`CUE_FILE_EXTENSION`] extension.
+pub fn is_cue_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(CUE_FILE_EXTENSION))
+}
+
+/// Reads and parses the cue sheet at the path, invalid UTF-8 is replaced as cue sheets are often
+/// saved in legacy encodings.
+pub fn read_cue_sheet(path: &Path) -> Result<CueSheet> {
+    let content = std::fs::read(path)?;
+
+    Ok(CueSheet::parse(&String::from_utf8_lossy(&content))?)
+}
+
+fn finish_track((track, has_index): (CueTrack, bool)) -> Result<CueTrack, CueError> {
+    if has_index {
+        Ok(track)
+    } else {
+        Err(CueError::MissingIndex(track.number))
+    }
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.rsplit_once('"'))
+        .map(|(value, _)| value)
+        .unwrap_or(value)
+        .to_string()
+}
+
+/// Returns the file name of a `FILE` command, leaving out the file type after it.
+fn file_name(arguments: &str) -> String {
+    if arguments.starts_with('"') {
+        return unquote(arguments);
+    }
+
+    arguments
+        .rsplit_once(char::is_whitespace)
+        .map(|(name, _)| name)
+        .unwrap_or(arguments)
+        .to_string()
+}
+
+/// Parses a `mm:ss:ff` index time into milliseconds.
+fn parse_time(time: &str) -> Result<u32, CueError> {
+    let invalid = || CueError::InvalidTime(time.to_string());
+
+    let parts = time
+        .split(':')
+        .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [minutes, seconds, frames] = parts[..] else {
+        return Err(invalid());
+    };
+
+    if seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return Err(invalid());
+    }
+
+    minutes
+        .checked_mul(60)
+        .and_then(|total| total.checked_add(seconds))
+        .and_then(|total| total.checked_mul(1000))
+        .and_then(|total| total.checked_add(frames * 1000 / FRAMES_PER_SECOND))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    const SHEET: &str = r#"REM GENRE "Progressive Rock"
+REM DATE 1973
+PERFORMER "Some Band"
+TITLE "Some Album"
+FILE "Some Album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "First"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    PERFORMER "Guest"
+    INDEX 00 03:58:50
+    INDEX 01 04:00:15
+"#;
+
+    #[test]
+    fn test_parse_cue_sheet() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+
+        assert_eq!(sheet.title.as_deref(), Some("Some Album"));
+        assert_eq!(sheet.performer.as_deref(), Some("Some Band"));
+        assert_eq!(sheet.genre.as_deref(), Some("Progressive Rock"));
+        assert_eq!(sheet.date.as_deref(), Some("1973"));
+        assert_eq!(sheet.file, "Some Album.flac");
+        assert_eq!(
+            sheet.tracks,
+            [
+                CueTrack {
+                    number: 1,
+                    title: Some(String::from("First")),
+                    performer: None,
+                    start_ms: 0,
+                },
+                CueTrack {
+                    number: 2,
+                    title: Some(String::from("Second")),
+                    performer: Some(String::from("Guest")),
+                    start_ms: 240_200,
+                },
+            ]
+        );
+
+        assert_eq!(
+            sheet.audio_path(Path::new("/music/album/Some Album.cue")),
+            PathBuf::from("/music/album/Some Album.flac")
+        );
+        assert_eq!(
+            sheet.track_ranges(Some(500_000)),
+            [(0, Some(240_200)), (240_200, Some(500_000))]
+        );
+        assert_eq!(sheet.track_ranges(None)[1], (240_200, None));
+    }
+
+    #[test]
+    fn test_parse_invalid_cue_sheets() {
+        assert!(matches!(
+            CueSheet::parse("TRACK 01 AUDIO\nINDEX 01 00:00:00"),
+            Err(CueError::MissingFile)
+        ));
+        assert!(matches!(
+            CueSheet::parse("FILE a.flac WAVE\nFILE b.flac WAVE"),
+            Err(CueError::MultipleFiles)
+        ));
+        assert!(matches!(
+            CueSheet::parse("FILE a.flac WAVE"),
+            Err(CueError::NoTracks)
+        ));
+        assert!(matches!(
+            CueSheet::parse("FILE a.flac WAVE\nTRACK 01 AUDIO\nINDEX 00 00:00:00"),
+            Err(CueError::MissingIndex(1))
+        ));
+        assert!(matches!(
+            CueSheet::parse("FILE a.flac WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:75"),
+            Err(CueError::InvalidTime(_))
+        ));
+        assert!(matches!(
+            CueSheet::parse("FILE a.flac WAVE\nTRACK 01 AUDIO\nINDEX 01 99999999:00:00"),
+            Err(CueError::InvalidTime(_))
+        ));
+
+        for file in ["/etc/a.flac", "../a.flac", "disc 1/a.flac", ".."] {
+            assert!(
+                matches!(
+                    CueSheet::parse(&format!(
+                        "FILE \"{file}\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00"
+                    )),
+                    Err(CueError::InvalidFile(_))
+                ),
+                "{file} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unquoted_file_name() {
+        assert_eq!(file_name("Some Album.flac WAVE"), "Some Album.flac");
+        assert_eq!(
+            file_name("\"Some \"Album\".flac\" WAVE"),
+            "Some \"Album\".flac"
+        );
+    }
+}
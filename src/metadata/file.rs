@@ -1,12 +1,77 @@
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
 use lofty::file::FileType;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
+/// Files modified more recently than this may still be being written, e.g. by a rip in progress.
+pub const RECENTLY_MODIFIED: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileAccessError {
+    #[error("File can't be read")]
+    NotReadable,
+    #[error("File is read-only")]
+    ReadOnly,
+}
+
+/// Whether a song's file can be read and written right now.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct FileHealth {
+    pub readable: bool,
+    pub writable: bool,
+    /// Whether the file was modified in the last [`RECENTLY_MODIFIED`], suggesting it's still
+    /// being written.
+    pub recently_modified: bool,
+}
+
+impl FileHealth {
+    /// Checks the file, a missing file is neither readable nor writable.
+    ///
+    /// Permissions don't tell whether the file is on a read-only mount or owned by another user,
+    /// `probe_write` also opens the file for appending to find out, without writing anything.
+    pub fn check(path: &Path, probe_write: bool) -> Self {
+        let Ok(metadata) = path.metadata() else {
+            return Self::default();
+        };
+
+        let writable = !metadata.permissions().readonly()
+            && (!probe_write || OpenOptions::new().append(true).open(path).is_ok());
+
+        Self {
+            readable: File::open(path).is_ok(),
+            writable,
+            recently_modified: metadata
+                .modified()
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age < RECENTLY_MODIFIED),
+        }
+    }
+
+    /// Returns why the tags of the file can't be edited, if they can't.
+    ///
+    /// Recently modified files aren't rejected, the app's own edits would trip it.
+    pub fn ensure_editable(&self) -> Result<(), FileAccessError> {
+        if !self.readable {
+            Err(FileAccessError::NotReadable)
+        } else if !self.writable {
+            Err(FileAccessError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// Similar to [`FileType`] from [lofty](https://crates.io/crates/lofty), except with [`Serialize`] and [`Deserialize`] traits implemented.
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
 pub enum SongFileType {
     Aac,
     Aiff,
@@ -42,3 +107,40 @@ impl From<FileType> for SongFileType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_file_health() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.flac");
+        std::fs::write(&path, "fLaC").unwrap();
+
+        let health = FileHealth::check(&path, true);
+        assert!(health.readable && health.writable && health.recently_modified);
+        assert!(health.ensure_editable().is_ok());
+        assert_eq!(std::fs::read(&path).unwrap(), b"fLaC");
+
+        let mut permissions = path.metadata().unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+
+        let health = FileHealth::check(&path, false);
+        assert!(health.readable && !health.writable);
+        assert!(matches!(
+            health.ensure_editable(),
+            Err(FileAccessError::ReadOnly)
+        ));
+
+        let missing = FileHealth::check(&dir.path().join("missing.flac"), true);
+        assert_eq!(missing, FileHealth::default());
+        assert!(matches!(
+            missing.ensure_editable(),
+            Err(FileAccessError::NotReadable)
+        ));
+    }
+}
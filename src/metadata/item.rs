@@ -6,7 +6,6 @@ use ts_rs::TS;
 #[non_exhaustive]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
 pub enum TagType {
     Ape,
     Id3v1,
@@ -25,7 +24,7 @@ pub enum TagType {
     Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize, TS,
 )]
 #[serde(rename_all = "camelCase")]
-#[ts(export, rename = "SongMetadataKey")]
+#[ts(rename = "SongMetadataKey")]
 pub enum ItemKey {
     Album,
     AlbumArtist,
@@ -98,6 +97,8 @@ pub enum ItemKey {
     InternetRadioStationOwner,
     Remixer,
     Popularimeter,
+    /// See [`rating`](super::rating) for its scale and how each tag format stores it.
+    Rating,
     ParentalAdvisory,
     FlagCompilation,
     FlagPodcast,
@@ -134,6 +135,142 @@ pub enum ItemKey {
     Unknown,
 }
 
+impl ItemKey {
+    /// Every known key, in declaration order and without [`ItemKey::Unknown`].
+    pub const ALL: [ItemKey; 104] = [
+        ItemKey::Album,
+        ItemKey::AlbumArtist,
+        ItemKey::AlbumSort,
+        ItemKey::Artist,
+        ItemKey::ArtistSort,
+        ItemKey::Artists,
+        ItemKey::Barcode,
+        ItemKey::Bpm,
+        ItemKey::CatalogNumber,
+        ItemKey::Comment,
+        ItemKey::Composer,
+        ItemKey::ComposerSortOrder,
+        ItemKey::Conductor,
+        ItemKey::Copyright,
+        ItemKey::Director,
+        ItemKey::DiscNumber,
+        ItemKey::DiscTotal,
+        ItemKey::EncodedBy,
+        ItemKey::EncoderSettings,
+        ItemKey::Engineer,
+        ItemKey::Genre,
+        ItemKey::Grouping,
+        ItemKey::Key,
+        ItemKey::Isrc,
+        ItemKey::Language,
+        ItemKey::License,
+        ItemKey::Lyricist,
+        ItemKey::Lyrics,
+        ItemKey::Mood,
+        ItemKey::Movement,
+        ItemKey::MovementNumber,
+        ItemKey::MovementTotal,
+        ItemKey::MusicBrainzRecordingId,
+        ItemKey::MusicBrainzTrackId,
+        ItemKey::MusicBrainzReleaseId,
+        ItemKey::MusicBrainzReleaseGroupId,
+        ItemKey::MusicBrainzArtistId,
+        ItemKey::MusicBrainzReleaseArtistId,
+        ItemKey::MusicBrainzWorkId,
+        ItemKey::OriginalAlbum,
+        ItemKey::OriginalArtist,
+        ItemKey::OriginalFileName,
+        ItemKey::OriginalReleaseDate,
+        ItemKey::Performer,
+        ItemKey::Producer,
+        ItemKey::Label,
+        ItemKey::ReleaseDate,
+        ItemKey::RecordingDate,
+        ItemKey::Title,
+        ItemKey::TitleSort,
+        ItemKey::TrackNumber,
+        ItemKey::TrackTotal,
+        ItemKey::Website,
+        ItemKey::Work,
+        ItemKey::Writer,
+        ItemKey::Year,
+        ItemKey::SetSubtitle,
+        ItemKey::ShowName,
+        ItemKey::TrackSubtitle,
+        ItemKey::OriginalLyricist,
+        ItemKey::AlbumTitleSortOrder,
+        ItemKey::ShowNameSortOrder,
+        ItemKey::Arranger,
+        ItemKey::MixDj,
+        ItemKey::MixEngineer,
+        ItemKey::MusicianCredits,
+        ItemKey::Publisher,
+        ItemKey::InternetRadioStationName,
+        ItemKey::InternetRadioStationOwner,
+        ItemKey::Remixer,
+        ItemKey::Popularimeter,
+        ItemKey::Rating,
+        ItemKey::ParentalAdvisory,
+        ItemKey::FlagCompilation,
+        ItemKey::FlagPodcast,
+        ItemKey::FileType,
+        ItemKey::FileOwner,
+        ItemKey::TaggingTime,
+        ItemKey::Length,
+        ItemKey::OriginalMediaType,
+        ItemKey::EncoderSoftware,
+        ItemKey::EncodingTime,
+        ItemKey::ReplayGainAlbumGain,
+        ItemKey::ReplayGainAlbumPeak,
+        ItemKey::ReplayGainTrackGain,
+        ItemKey::ReplayGainTrackPeak,
+        ItemKey::AudioFileUrl,
+        ItemKey::AudioSourceUrl,
+        ItemKey::CommercialInformationUrl,
+        ItemKey::CopyrightUrl,
+        ItemKey::RadioStationUrl,
+        ItemKey::PaymentUrl,
+        ItemKey::PublisherUrl,
+        ItemKey::IntegerBpm,
+        ItemKey::Color,
+        ItemKey::PodcastDescription,
+        ItemKey::PodcastSeriesCategory,
+        ItemKey::PodcastUrl,
+        ItemKey::PodcastGlobalUniqueId,
+        ItemKey::PodcastKeywords,
+        ItemKey::Description,
+        ItemKey::Script,
+        ItemKey::AppleXid,
+        ItemKey::AppleId3v2ContentGroup,
+    ];
+
+    /// Whether the value of the key is a number or flag, which can never hold more than one
+    /// value.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            ItemKey::TrackNumber
+                | ItemKey::TrackTotal
+                | ItemKey::DiscNumber
+                | ItemKey::DiscTotal
+                | ItemKey::MovementNumber
+                | ItemKey::MovementTotal
+                | ItemKey::Year
+                | ItemKey::Bpm
+                | ItemKey::IntegerBpm
+                | ItemKey::Rating
+                | ItemKey::FlagCompilation
+                | ItemKey::FlagPodcast
+        )
+    }
+
+    /// Whether the value is free-form text that can span lines, so it's read as one value instead
+    /// of being split into its tag items or trimmed.
+    pub fn is_free_text(&self) -> bool {
+        matches!(self, ItemKey::Lyrics)
+    }
+}
+
 impl From<ItemKey> for lofty::tag::ItemKey {
     fn from(value: ItemKey) -> Self {
         match value {
@@ -208,6 +345,7 @@ impl From<ItemKey> for lofty::tag::ItemKey {
             ItemKey::InternetRadioStationOwner => Self::InternetRadioStationOwner,
             ItemKey::Remixer => Self::Remixer,
             ItemKey::Popularimeter => Self::Popularimeter,
+            ItemKey::Rating => Self::Popularimeter,
             ItemKey::ParentalAdvisory => Self::ParentalAdvisory,
             ItemKey::FlagCompilation => Self::FlagCompilation,
             ItemKey::FlagPodcast => Self::FlagPodcast,
@@ -318,7 +456,7 @@ impl From<lofty::tag::ItemKey> for ItemKey {
             lofty::tag::ItemKey::InternetRadioStationName => ItemKey::InternetRadioStationName,
             lofty::tag::ItemKey::InternetRadioStationOwner => ItemKey::InternetRadioStationOwner,
             lofty::tag::ItemKey::Remixer => ItemKey::Remixer,
-            lofty::tag::ItemKey::Popularimeter => ItemKey::Popularimeter,
+            lofty::tag::ItemKey::Popularimeter => ItemKey::Rating,
             lofty::tag::ItemKey::ParentalAdvisory => ItemKey::ParentalAdvisory,
             lofty::tag::ItemKey::FlagCompilation => ItemKey::FlagCompilation,
             lofty::tag::ItemKey::FlagPodcast => ItemKey::FlagPodcast,
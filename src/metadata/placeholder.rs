@@ -0,0 +1,184 @@
+//! Generated cover art for albums without any.
+
+use any_ascii::any_ascii;
+use image::{Rgb, RgbImage};
+
+/// Width and height of a glyph of [`FONT`] in pixels.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Most initials drawn onto a placeholder.
+const MAX_INITIALS: usize = 2;
+
+/// A 5×7 bitmap font covering the characters initials are drawn with, each row is a bitmask
+/// with the leftmost pixel as the highest of the 5 bits.
+const FONT: [(char, [u8; GLYPH_HEIGHT as usize]); 36] = [
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+];
+
+/// Draws a square placeholder for the album, filled with a color derived from its name and
+/// showing its initials.
+///
+/// The same name always gets the same color, so a placeholder looks the same across clients and
+/// restarts.
+pub fn placeholder_cover_art(name: &str, size: u32) -> RgbImage {
+    let background = background_color(name);
+    let mut image = RgbImage::from_pixel(size, size, background);
+
+    let glyphs = initials(name).chars().filter_map(glyph).collect::<Vec<_>>();
+
+    if glyphs.is_empty() {
+        return image;
+    }
+
+    // The initials take up about half of the width, with a column of spacing between glyphs.
+    let columns = glyphs.len() as u32 * (GLYPH_WIDTH + 1) - 1;
+    let scale = size / 2 / columns;
+
+    if scale == 0 {
+        return image;
+    }
+
+    let foreground = Rgb([255, 255, 255]);
+    let left = (size - columns * scale) / 2;
+    let top = (size - GLYPH_HEIGHT * scale) / 2;
+
+    for (index, rows) in glyphs.iter().enumerate() {
+        let glyph_left = left + index as u32 * (GLYPH_WIDTH + 1) * scale;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+
+                let x = glyph_left + column * scale;
+                let y = top + row as u32 * scale;
+
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        image.put_pixel(x + dx, y + dy, foreground);
+                    }
+                }
+            }
+        }
+    }
+
+    image
+}
+
+/// The first letter or digit of the first words of the name, transliterated to ASCII.
+fn initials(name: &str) -> String {
+    any_ascii(name)
+        .split_whitespace()
+        .filter_map(|word| word.chars().find(char::is_ascii_alphanumeric))
+        .take(MAX_INITIALS)
+        .map(|initial| initial.to_ascii_uppercase())
+        .collect()
+}
+
+fn glyph(character: char) -> Option<&'static [u8; GLYPH_HEIGHT as usize]> {
+    FONT.iter()
+        .find(|(glyph, _)| *glyph == character)
+        .map(|(_, rows)| rows)
+}
+
+/// A muted color with a hue picked by hashing the name.
+///
+/// FNV-1a is used instead of the standard library's hasher, whose output may change between Rust
+/// releases.
+fn background_color(name: &str) -> Rgb<u8> {
+    let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+
+    hsl_to_rgb((hash % 360) as f32, 0.45, 0.42)
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+
+    let (r, g, b) = match hue as u32 {
+        0..60 => (chroma, x, 0.0),
+        60..120 => (x, chroma, 0.0),
+        120..180 => (0.0, chroma, x),
+        180..240 => (0.0, x, chroma),
+        240..300 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let channel = |value: f32| ((value + m) * 255.0).round() as u8;
+
+    Rgb([channel(r), channel(g), channel(b)])
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_initials() {
+        assert_eq!(initials("Fixture Album"), "FA");
+        assert_eq!(initials("the (long) way home"), "TL");
+        assert_eq!(initials("Ænima"), "A");
+        assert_eq!(initials("  "), "");
+    }
+
+    #[test]
+    fn test_placeholder_is_stable() {
+        let first = placeholder_cover_art("Fixture Album", 64);
+        let second = placeholder_cover_art("Fixture Album", 64);
+        let other = placeholder_cover_art("Other Album", 64);
+
+        assert_eq!(first.dimensions(), (64, 64));
+        assert_eq!(first, second);
+        assert_ne!(first.get_pixel(0, 0), other.get_pixel(0, 0));
+        assert!(first.pixels().any(|pixel| *pixel == Rgb([255, 255, 255])));
+    }
+
+    #[test]
+    fn test_tiny_placeholder_has_no_initials() {
+        let image = placeholder_cover_art("Fixture Album", 8);
+
+        assert!(image.pixels().all(|pixel| pixel == image.get_pixel(0, 0)));
+    }
+}
@@ -0,0 +1,100 @@
+//! Track and disc numbers are kept as numbers along with the total they're out of, even though
+//! tags store them in their own way:
+//!
+//! - ID3v2 has a single `TRCK` and `TPOS` frame holding both, like `3/12`.
+//! - Vorbis comments, APE tags and MP4 have separate fields for the number and the total, which
+//!   some taggers still fill with `3/12`.
+//!
+//! Parts that aren't a number, and zeroes, are left out rather than guessed at.
+
+use super::{Metadata, item::ItemKey};
+
+/// A track or disc number along with the total it's out of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    pub number: Option<u32>,
+    pub total: Option<u32>,
+}
+
+impl Position {
+    /// Parses a number like `3`, `03` or `3/12`.
+    pub fn parse(value: &str) -> Self {
+        let (number, total) = value.split_once('/').unwrap_or((value, ""));
+
+        Self {
+            number: parse_count(number),
+            total: parse_count(total),
+        }
+    }
+
+    /// Reads the position of the number field from the metadata, the total field fills in the
+    /// total if the number doesn't include one.
+    pub fn from_metadata(metadata: &Metadata, number: &ItemKey, total: &ItemKey) -> Self {
+        let position = metadata
+            .get(number)
+            .map(|value| Self::parse(value))
+            .unwrap_or_default();
+
+        Self {
+            total: position
+                .total
+                .or_else(|| metadata.get(total).and_then(|value| parse_count(value))),
+            ..position
+        }
+    }
+
+    /// Formats the position the way ID3v2 stores it, `None` without a number.
+    pub fn to_tag(&self) -> Option<String> {
+        let number = self.number?;
+
+        Some(match self.total {
+            Some(total) => format!("{number}/{total}"),
+            None => number.to_string(),
+        })
+    }
+}
+
+/// Parses a number made of digits only, zero counts as no number.
+pub fn parse_count(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok().filter(|count| *count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_position() {
+        let position = |number, total| Position { number, total };
+
+        assert_eq!(Position::parse("3"), position(Some(3), None));
+        assert_eq!(Position::parse("03 / 12"), position(Some(3), Some(12)));
+        assert_eq!(Position::parse("/12"), position(None, Some(12)));
+        assert_eq!(Position::parse("A1"), position(None, None));
+        assert_eq!(Position::parse("0/0"), position(None, None));
+
+        let metadata = Metadata::new(
+            BTreeMap::from([
+                (ItemKey::TrackNumber, String::from("3")),
+                (ItemKey::TrackTotal, String::from("12")),
+                (ItemKey::DiscNumber, String::from("1/2")),
+                (ItemKey::DiscTotal, String::from("3")),
+            ]),
+            BTreeMap::new(),
+        );
+        assert_eq!(metadata.track(), position(Some(3), Some(12)));
+        assert_eq!(metadata.disc(), position(Some(1), Some(2)));
+
+        assert_eq!(metadata.track().to_tag().as_deref(), Some("3/12"));
+        assert_eq!(position(None, Some(12)).to_tag(), None);
+    }
+}
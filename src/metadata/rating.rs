@@ -0,0 +1,210 @@
+//! Ratings are kept on a scale from `0` to `100` everywhere in the app, where each star is worth
+//! [`STAR`], so five stars are `100`. Songs that were never rated have no rating instead of `0`.
+//!
+//! Tags store them in their own way, which is converted from and to that scale here:
+//!
+//! - ID3v2 has a byte from `0` to `255` in its `POPM` frame, written with the steps Windows Media
+//!   Player uses for each star since most players read those.
+//! - Vorbis comments and APE tags have a `RATING` field, which holds the rating as it is.
+//!
+//! Every other format has nowhere to store a rating, the ratings of their songs are only kept in
+//! the database.
+
+use std::{fs::File, path::Path};
+
+use lofty::{
+    file::FileType,
+    prelude::*,
+    read_from,
+    tag::{ItemKey as LoftyKey, ItemValue, Tag, TagItem},
+};
+
+use super::{Metadata, Result, item::TagType, write::save_tag};
+
+pub const MAX_RATING: u8 = 100;
+
+/// What a single star is worth.
+pub const STAR: u8 = 20;
+
+/// Field Vorbis comments and APE tags store the rating in.
+pub const RATING_FIELD: &str = "RATING";
+
+/// Players look for the `POPM` frame of their own email, this one is read by most of them.
+const POPULARIMETER_EMAIL: &str = "Windows Media Player 9 Series";
+
+/// The `POPM` byte written for each amount of stars, starting at none.
+const POPULARIMETER_STARS: [u8; 6] = [0, 1, 64, 128, 196, 255];
+
+/// Whether the tag format can store a rating.
+pub fn supports_rating(tag_type: TagType) -> bool {
+    matches!(
+        tag_type,
+        TagType::Id3v2 | TagType::VorbisComments | TagType::Ape
+    )
+}
+
+/// The rating of a song's metadata read from its file, `None` if the file can't store one, so the
+/// saved rating is kept. Goes by the extension of the file.
+pub fn file_rating(path: &Path, metadata: Option<&Metadata>) -> Option<Option<u8>> {
+    FileType::from_path(path)
+        .is_some_and(|file_type| supports_rating(file_type.primary_tag_type().into()))
+        .then(|| metadata.and_then(Metadata::rating))
+}
+
+/// Reads a rating written on the app's scale, `None` if it isn't one.
+pub fn parse_rating(value: &str) -> Option<u8> {
+    value
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|rating| *rating <= MAX_RATING)
+}
+
+/// The amount of whole stars the rating is closest to.
+pub fn stars(rating: u8) -> u8 {
+    (rating.min(MAX_RATING) + STAR / 2) / STAR
+}
+
+/// Converts the byte of a `POPM` frame, `0` means the song wasn't rated.
+pub fn from_popularimeter(value: u8) -> Option<u8> {
+    let stars = match value {
+        0 => return None,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        224..=255 => 5,
+    };
+
+    Some(stars * STAR)
+}
+
+pub fn to_popularimeter(rating: u8) -> u8 {
+    POPULARIMETER_STARS[stars(rating) as usize]
+}
+
+/// Reads the rating from the tag, whichever way its format stores it.
+pub fn read_rating(tag: &Tag) -> Option<u8> {
+    match tag.get(&LoftyKey::Popularimeter).map(TagItem::value) {
+        // The frame as it's stored: the email ending in a null byte, the rating and a play count.
+        Some(ItemValue::Binary(frame)) => {
+            let email_end = frame.iter().position(|byte| *byte == 0)?;
+            return from_popularimeter(*frame.get(email_end + 1)?);
+        }
+        Some(ItemValue::Text(value)) => return parse_rating(value),
+        _ => {}
+    }
+
+    tag.get_string(&LoftyKey::Unknown(RATING_FIELD.to_string()))
+        .and_then(parse_rating)
+}
+
+/// The item storing the rating in the tag format, `None` if it can't store one.
+pub fn rating_item(tag_type: TagType, rating: u8) -> Option<TagItem> {
+    match tag_type {
+        TagType::Id3v2 => {
+            let mut frame = POPULARIMETER_EMAIL.as_bytes().to_vec();
+            frame.extend([0, to_popularimeter(rating), 0, 0, 0, 0]);
+
+            Some(TagItem::new(
+                LoftyKey::Popularimeter,
+                ItemValue::Binary(frame),
+            ))
+        }
+        TagType::VorbisComments | TagType::Ape => Some(TagItem::new(
+            LoftyKey::Unknown(RATING_FIELD.to_string()),
+            ItemValue::Text(rating.to_string()),
+        )),
+        _ => None,
+    }
+}
+
+/// Sets the rating in the song's tag without touching any other field, `None` removes it.
+///
+/// Returns whether the tag format can store a rating, the file is left as it is if it can't.
+pub fn write_rating(path: &Path, rating: Option<u8>) -> Result<bool> {
+    let mut file = File::open(path)?;
+    let mut tagged_file = read_from(&mut file)?;
+    let tag_type = tagged_file.primary_tag_type();
+
+    if !supports_rating(tag_type.into()) {
+        return Ok(false);
+    }
+
+    let tag = match tagged_file.primary_tag_mut() {
+        Some(tag) => tag,
+        None => &mut Tag::new(tag_type),
+    };
+
+    tag.remove_key(&LoftyKey::Popularimeter);
+    tag.remove_key(&LoftyKey::Unknown(RATING_FIELD.to_string()));
+    if let Some(item) = rating.and_then(|rating| rating_item(tag_type.into(), rating)) {
+        tag.insert_unchecked(item);
+    }
+
+    save_tag(path, tag)?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::metadata::{SongFile, item::ItemKey, read_metadata_from_path};
+
+    #[test]
+    fn test_popularimeter_scale() {
+        for stars in 0..=5 {
+            let rating = stars * STAR;
+            let value = to_popularimeter(rating);
+            assert_eq!(from_popularimeter(value), (stars > 0).then_some(rating));
+        }
+
+        assert_eq!(to_popularimeter(50), 128);
+        assert_eq!(from_popularimeter(186), Some(80));
+        assert_eq!(parse_rating("101"), None);
+    }
+
+    #[test]
+    fn test_rating_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for file in ["data/goose.flac", "data/flip.mp3", "data/bumm.m4a"] {
+            let path = dir.path().join(Path::new(file).file_name().unwrap());
+            std::fs::copy(file, &path).unwrap();
+            let before = read_metadata_from_path(&path).unwrap();
+
+            let stored = write_rating(&path, Some(80)).unwrap();
+            let metadata = read_metadata_from_path(&path).unwrap();
+            assert_eq!(stored, file != "data/bumm.m4a", "{file}");
+            assert_eq!(
+                metadata.get(&ItemKey::Rating).map(String::as_str),
+                stored.then_some("80"),
+                "{file}"
+            );
+            assert_eq!(
+                metadata.get(&ItemKey::Title),
+                before.get(&ItemKey::Title),
+                "{file}"
+            );
+
+            // Rewriting every field keeps the rating.
+            let mut song = SongFile::open(&path).unwrap();
+            song.write().unwrap();
+            assert_eq!(song.rating(), stored.then_some(80), "{file}");
+            assert_eq!(
+                read_metadata_from_path(&path)
+                    .unwrap()
+                    .get(&ItemKey::Rating),
+                metadata.get(&ItemKey::Rating),
+                "{file}"
+            );
+
+            write_rating(&path, None).unwrap();
+            let metadata = read_metadata_from_path(&path).unwrap();
+            assert_eq!(metadata.get(&ItemKey::Rating), None, "{file}");
+        }
+    }
+}
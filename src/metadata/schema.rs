@@ -0,0 +1,166 @@
+//! Describes which metadata fields can be edited for each tag format.
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::db::song_column;
+
+use super::{
+    item::{ItemKey, TagType},
+    rating::supports_rating,
+};
+
+/// The editable fields of a song, based on the tag format of its file.
+#[derive(Debug, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataSchema {
+    pub tag_type: TagType,
+    /// Whether the tag format can embed pictures, like cover art.
+    pub pictures: bool,
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldSchema {
+    pub key: ItemKey,
+    /// Whether the field is read from the tag format.
+    pub readable: bool,
+    /// Whether writing the field keeps it, fields that aren't writable are silently dropped.
+    pub writable: bool,
+    /// The `songs` column the field is saved to, if any.
+    pub database_column: Option<String>,
+    /// Whether the field can hold several values, which are written as separate values.
+    pub multiple_values: bool,
+    /// The most characters the tag format can hold for the field.
+    pub max_length: Option<usize>,
+}
+
+impl MetadataSchema {
+    pub fn new(tag_type: TagType) -> Self {
+        Self {
+            tag_type,
+            pictures: supports_pictures(tag_type),
+            fields: ItemKey::ALL
+                .iter()
+                .map(|key| field_schema(tag_type, key))
+                .collect(),
+        }
+    }
+}
+
+fn field_schema(tag_type: TagType, key: &ItemKey) -> FieldSchema {
+    let supported = match tag_type {
+        // ID3v1 has a fixed set of fields, which lofty doesn't have a key map for.
+        TagType::Id3v1 => matches!(
+            key,
+            ItemKey::Title
+                | ItemKey::Artist
+                | ItemKey::Album
+                | ItemKey::Year
+                | ItemKey::Comment
+                | ItemKey::TrackNumber
+                | ItemKey::Genre
+        ),
+        _ if *key == ItemKey::Rating => supports_rating(tag_type),
+        _ => lofty::tag::ItemKey::from(key.clone())
+            .map_key(tag_type.into(), false)
+            .is_some(),
+    };
+
+    FieldSchema {
+        key: key.clone(),
+        readable: supported,
+        writable: supported,
+        database_column: song_column(key).map(String::from),
+        multiple_values: supported
+            && supports_multiple_values(tag_type)
+            && !key.is_numeric()
+            && !key.is_free_text(),
+        max_length: supported.then(|| max_length(tag_type, key)).flatten(),
+    }
+}
+
+fn supports_multiple_values(tag_type: TagType) -> bool {
+    !matches!(
+        tag_type,
+        TagType::Id3v1 | TagType::RiffInfo | TagType::AiffText
+    )
+}
+
+fn supports_pictures(tag_type: TagType) -> bool {
+    !matches!(
+        tag_type,
+        TagType::Id3v1 | TagType::RiffInfo | TagType::AiffText
+    )
+}
+
+/// ID3v1 tags have fixed size fields, every other format has no practical limit.
+fn max_length(tag_type: TagType, key: &ItemKey) -> Option<usize> {
+    if tag_type != TagType::Id3v1 {
+        return None;
+    }
+
+    match key {
+        ItemKey::Title | ItemKey::Artist | ItemKey::Album => Some(30),
+        ItemKey::Comment => Some(28),
+        ItemKey::Year => Some(4),
+        ItemKey::TrackNumber => Some(3),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    fn field(schema: &MetadataSchema, key: ItemKey) -> &FieldSchema {
+        schema
+            .fields
+            .iter()
+            .find(|field| field.key == key)
+            .expect("Key missing from schema")
+    }
+
+    #[test]
+    fn test_id3v1_schema() {
+        let schema = MetadataSchema::new(TagType::Id3v1);
+
+        assert!(!schema.pictures);
+
+        let title = field(&schema, ItemKey::Title);
+        assert!(title.writable);
+        assert!(!title.multiple_values);
+        assert_eq!(title.max_length, Some(30));
+        assert_eq!(title.database_column.as_deref(), Some("title"));
+
+        assert!(!field(&schema, ItemKey::AlbumArtist).writable);
+        assert!(!field(&schema, ItemKey::Lyrics).readable);
+    }
+
+    #[test]
+    fn test_vorbis_comments_schema() {
+        let schema = MetadataSchema::new(TagType::VorbisComments);
+
+        assert!(schema.pictures);
+
+        let artist = field(&schema, ItemKey::Artist);
+        assert!(artist.writable);
+        assert!(artist.multiple_values);
+        assert_eq!(artist.max_length, None);
+
+        let track_number = field(&schema, ItemKey::TrackNumber);
+        assert!(track_number.writable);
+        assert!(!track_number.multiple_values);
+
+        assert_eq!(field(&schema, ItemKey::Conductor).database_column, None);
+        assert!(!field(&schema, ItemKey::Lyrics).multiple_values);
+        assert!(field(&schema, ItemKey::Rating).writable);
+
+        let mp4 = MetadataSchema::new(TagType::Mp4Ilst);
+        assert!(!field(&mp4, ItemKey::Rating).writable);
+        assert_eq!(schema.fields.len(), ItemKey::ALL.len());
+    }
+}
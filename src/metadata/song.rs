@@ -9,45 +9,125 @@ use time::OffsetDateTime;
 use ts_rs::TS;
 
 use lofty::{
-    config::WriteOptions,
-    id3::v2::Id3v2Tag,
+    file::TaggedFile,
     prelude::*,
     probe::Probe,
+    properties::FileProperties,
     read_from,
     tag::Tag,
     tag::{ItemKey as LoftyKey, ItemValue, TagItem},
 };
 
 use super::{
-    Result, TAG_SEPARATOR,
+    Result, VALUE_SEPARATOR,
     file::SongFileType,
     item::{ItemKey, TagType},
+    position::Position,
+    rating::{RATING_FIELD, parse_rating, rating_item, read_rating},
+    write::save_tag,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, TS)]
+#[derive(Debug, Clone, Serialize, Eq, PartialEq, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(rename = "SongMetadata")]
-#[ts(export)]
 pub struct Metadata {
+    /// The values of each field joined with [`VALUE_SEPARATOR`], for everything that only has
+    /// room for a single string, like the columns of the database.
     #[serde(flatten)]
     fields: BTreeMap<ItemKey, String>,
+    /// The values of each field as they're stored in the tag, values can contain the separator.
+    #[serde(default = "BTreeMap::new")]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    values: BTreeMap<ItemKey, Vec<String>>,
     #[serde(default = "BTreeMap::new")]
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     unknown: BTreeMap<String, String>,
 }
 
+impl<'de> Deserialize<'de> for Metadata {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(flatten)]
+            fields: BTreeMap<ItemKey, String>,
+            #[serde(default)]
+            values: BTreeMap<ItemKey, Vec<String>>,
+            #[serde(default)]
+            unknown: BTreeMap<String, String>,
+        }
+
+        let Fields {
+            fields,
+            values,
+            unknown,
+        } = Fields::deserialize(deserializer)?;
+        let mut metadata = Self::new(fields, unknown);
+
+        // Values are only kept when they still match the joined string, otherwise the field was
+        // edited or removed as a string, which takes precedence.
+        for (key, values) in values {
+            if metadata.fields.get(&key) == Some(&values.join(VALUE_SEPARATOR)) {
+                metadata.insert_values(key, values);
+            }
+        }
+
+        Ok(metadata)
+    }
+}
+
 impl Metadata {
+    /// Creates metadata with a single value for each field, which is never split.
     pub fn new(items: BTreeMap<ItemKey, String>, unknown: BTreeMap<String, String>) -> Self {
         Self {
+            values: items
+                .iter()
+                .map(|(key, value)| (key.clone(), vec![value.clone()]))
+                .collect(),
             fields: items,
             unknown,
         }
     }
 
+    pub fn from_values(
+        values: BTreeMap<ItemKey, Vec<String>>,
+        unknown: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            fields: values
+                .iter()
+                .map(|(key, values)| (key.clone(), values.join(VALUE_SEPARATOR)))
+                .collect(),
+            values,
+            unknown,
+        }
+    }
+
+    /// Sets the field to a single value.
+    ///
+    /// The values are kept if they're already joined to the same string, so fields edited as
+    /// strings don't lose their values when they didn't change.
     pub fn insert(&mut self, key: ItemKey, value: String) {
-        self.fields.insert(key, value);
+        if self.fields.get(&key) != Some(&value) {
+            self.values.insert(key.clone(), vec![value.clone()]);
+            self.fields.insert(key, value);
+        }
     }
-    
+
+    pub fn insert_values(&mut self, key: ItemKey, values: Vec<String>) {
+        self.fields
+            .insert(key.clone(), values.join(VALUE_SEPARATOR));
+        self.values.insert(key, values);
+    }
+
+    pub fn remove(&mut self, key: &ItemKey) -> Option<String> {
+        self.values.remove(key);
+        self.fields.remove(key)
+    }
+
+    /// The fields with their values joined, see [`Metadata::values`] for the separate values.
     pub fn fields(&self) -> &BTreeMap<ItemKey, String> {
         &self.fields
     }
@@ -60,15 +140,89 @@ impl Metadata {
         self.fields.iter()
     }
 
+    pub fn iter_values(&self) -> impl Iterator<Item = (&ItemKey, &[String])> {
+        self.values
+            .iter()
+            .map(|(key, values)| (key, values.as_slice()))
+    }
+
+    /// The values of the field joined with [`VALUE_SEPARATOR`].
     pub fn get(&self, key: &ItemKey) -> Option<&String> {
         self.fields.get(key)
     }
 
+    pub fn values(&self, key: &ItemKey) -> Option<&[String]> {
+        self.values.get(key).map(Vec::as_slice)
+    }
+
+    /// The rating, see [`rating`](super::rating) for its scale.
+    pub fn rating(&self) -> Option<u8> {
+        self.get(&ItemKey::Rating)
+            .and_then(|rating| parse_rating(rating))
+    }
+
+    /// The track number and total, see [`position`](super::position) for how they're read.
+    pub fn track(&self) -> Position {
+        Position::from_metadata(self, &ItemKey::TrackNumber, &ItemKey::TrackTotal)
+    }
+
+    /// The disc number and total.
+    pub fn disc(&self) -> Position {
+        Position::from_metadata(self, &ItemKey::DiscNumber, &ItemKey::DiscTotal)
+    }
+
     pub fn get_unknown(&self, key: &String) -> Option<&String> {
         self.unknown.get(key)
     }
 }
 
+/// Properties of the audio stream, `None` for anything the file doesn't report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioProperties {
+    pub duration_ms: Option<u32>,
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+    /// Size of the largest picture embedded in the file, `0` if it doesn't have one. Not a
+    /// property of the audio, but read along with them so it's stored for every song.
+    pub cover_art_bytes: Option<u32>,
+}
+
+impl AudioProperties {
+    fn of(tagged_file: &TaggedFile) -> Self {
+        let cover_art_bytes = tagged_file
+            .tags()
+            .iter()
+            .flat_map(|tag| tag.pictures())
+            .map(|picture| picture.data().len())
+            .max()
+            .unwrap_or_default();
+
+        Self {
+            cover_art_bytes: Some(u32::try_from(cover_art_bytes).unwrap_or(u32::MAX)),
+            ..Self::from(tagged_file.properties())
+        }
+    }
+}
+
+impl From<&FileProperties> for AudioProperties {
+    fn from(properties: &FileProperties) -> Self {
+        let duration_ms = properties.duration().as_millis();
+
+        Self {
+            duration_ms: (duration_ms > 0).then(|| u32::try_from(duration_ms).unwrap_or(u32::MAX)),
+            bitrate_kbps: properties
+                .overall_bitrate()
+                .or(properties.audio_bitrate())
+                .filter(|bitrate| *bitrate > 0),
+            sample_rate: properties.sample_rate().filter(|rate| *rate > 0),
+            channels: properties.channels().filter(|channels| *channels > 0),
+            cover_art_bytes: None,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SongError {
     #[error("No Tag(s) found")]
@@ -77,7 +231,6 @@ pub enum SongError {
 
 #[derive(Deserialize, Serialize, Debug, Clone, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export)]
 pub struct SongFile {
     /// The path to the file
     path: PathBuf,
@@ -97,6 +250,9 @@ pub struct SongFile {
     size: u64,
     /// Metadata contained in the file
     metadata: Option<Metadata>,
+    /// Properties of the audio stream
+    #[serde(default)]
+    properties: Option<AudioProperties>,
 }
 
 impl SongFile {
@@ -121,6 +277,7 @@ impl SongFile {
             metadata: read_metadata_from_path(path)
                 .inspect_err(|err| log::warn!("Failed to read metadata from path: {err}"))
                 .ok(),
+            properties: Some(AudioProperties::of(&tagged_file)),
         })
     }
 
@@ -149,7 +306,11 @@ impl SongFile {
         Ok(())
     }
 
-    pub fn write(&mut self) -> Result<()> {
+    /// Writes the metadata to the file's tag, replacing what it held.
+    ///
+    /// Returns the fields the tag format can't hold, which are left out of the tag. The rating
+    /// isn't among them, since it's kept in the library when the format can't store it.
+    pub fn write(&mut self) -> Result<Vec<ItemKey>> {
         let mut file = File::open(&self.path)?;
         let mut tagged_file = read_from(&mut file)?;
         let tag_type = self.tag_type.into();
@@ -158,48 +319,53 @@ impl SongFile {
             None => &mut Tag::new(tag_type),
         };
 
+        let mut dropped = Vec::new();
+
         tag.clear();
         if let Some(metadata) = &self.metadata {
-            for (key, value) in metadata.iter() {
-                let key = key.clone().into();
-
-                let split: Vec<_> = value.split(TAG_SEPARATOR).map(|s| s.trim()).collect();
-
-                if split.len() == 1 {
-                    let item =
-                        TagItem::new_checked(tag_type, key, ItemValue::Text(value.to_string()));
+            for (item_key, values) in metadata.iter_values() {
+                // Stored differently by each format, see `rating`.
+                if *item_key == ItemKey::Rating {
+                    continue;
+                }
 
-                    if let Some(item) = item {
-                        tag.insert(item);
-                    }
-                } else {
-                    for value in split {
-                        let item = TagItem::new_checked(
+                let key: LoftyKey = item_key.clone().into();
+                let items = values
+                    .iter()
+                    .filter_map(|value| {
+                        TagItem::new_checked(
                             tag_type,
                             key.clone(),
                             ItemValue::Text(value.to_string()),
-                        );
+                        )
+                    })
+                    .collect::<Vec<_>>();
 
-                        if let Some(item) = item {
-                            tag.push(item);
-                        }
+                if items.is_empty() {
+                    dropped.push(item_key.clone());
+                }
+
+                for item in items {
+                    // A single value replaces the field, several are kept as separate items.
+                    if let [_] = values {
+                        tag.insert(item);
+                    } else {
+                        tag.push(item);
                     }
                 }
             }
-        }
-
-        match self.tag_type {
-            TagType::Id3v2 => {
-                let id3_tag: Id3v2Tag = tag.clone().into();
 
-                id3_tag.save_to_path(&self.path, WriteOptions::default())?
-            }
-            _ => {
-                tag.save_to_path(&self.path, WriteOptions::default())?;
+            if let Some(item) = self
+                .rating()
+                .and_then(|rating| rating_item(self.tag_type, rating))
+            {
+                tag.insert_unchecked(item);
             }
         }
 
-        Ok(())
+        save_tag(&self.path, tag)?;
+
+        Ok(dropped)
     }
 
     pub fn tag_type(&self) -> TagType {
@@ -225,6 +391,22 @@ impl SongFile {
     pub fn created(&self) -> OffsetDateTime {
         self.created
     }
+
+    pub fn properties(&self) -> Option<&AudioProperties> {
+        self.properties.as_ref()
+    }
+
+    pub fn rating(&self) -> Option<u8> {
+        self.metadata.as_ref()?.rating()
+    }
+}
+
+/// Reads the audio properties of the file, its tags are skipped.
+pub fn read_properties_from_path(path: &Path) -> Result<AudioProperties> {
+    // Tags are read for the size of the embedded cover art.
+    let tagged_file = Probe::open(path)?.read()?;
+
+    Ok(AudioProperties::of(&tagged_file))
 }
 
 pub fn read_metadata_from_path(path: &Path) -> Result<Metadata> {
@@ -240,35 +422,44 @@ pub fn read_metadata_from_path(path: &Path) -> Result<Metadata> {
         .map(|item| item.clone().into_key())
         .collect::<HashSet<lofty::tag::ItemKey>>();
 
-    let items = keys
+    let mut items = keys
         .iter()
         .filter_map(|key| match key {
-            LoftyKey::Unknown(_) => None,
+            // Read separately since each format stores the rating differently.
+            LoftyKey::Unknown(_) | LoftyKey::Popularimeter => None,
             _ => {
-                let value = (
-                    key.clone().into(),
+                let item_key = ItemKey::from(key.clone());
+                let values = if item_key.is_free_text() {
+                    // Lyrics keep their line breaks, synchronized (LRC) lyrics would be mangled
+                    // otherwise.
+                    vec![tag.get_string(key)?.replace("\0", "")]
+                } else {
                     tag.get_strings(key)
                         .map(|string| string.trim().replace("\0", "").to_string())
                         .collect::<Vec<String>>()
-                        .join("; "),
-                );
+                };
+                let values = (item_key, values);
 
-                log::trace!("{key:?}: {value:?}");
-                Some(value)
+                log::trace!("{key:?}: {values:?}");
+                Some(values)
             }
         })
-        .collect::<BTreeMap<ItemKey, String>>();
+        .collect::<BTreeMap<ItemKey, Vec<String>>>();
+
+    if let Some(rating) = read_rating(tag) {
+        items.insert(ItemKey::Rating, vec![rating.to_string()]);
+    }
 
     let unknown = keys
         .iter()
         .filter_map(|key| match key {
-            LoftyKey::Unknown(field_name) => {
+            LoftyKey::Unknown(field_name) if field_name.as_str() != RATING_FIELD => {
                 let value = (
                     field_name.to_string(),
                     tag.get_strings(key)
                         .map(|string| string.trim().replace("\0", "").to_string())
                         .collect::<Vec<String>>()
-                        .join("; "),
+                        .join(VALUE_SEPARATOR),
                 );
 
                 log::trace!("{key:?}: {value:?}");
@@ -278,5 +469,142 @@ pub fn read_metadata_from_path(path: &Path) -> Result<Metadata> {
         })
         .collect::<BTreeMap<String, String>>();
 
-    Ok(Metadata::new(items, unknown))
+    Ok(Metadata::from_values(items, unknown))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MetadataSchema;
+    use test_log::test;
+
+    #[test]
+    fn test_lyrics_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let lyrics =
+            "[00:12.00]First line; still the first line\n[00:17.20] Second line \n\nVerse two";
+
+        for file in ["data/goose.flac", "data/flip.mp3", "data/bumm.m4a"] {
+            let path = dir.path().join(Path::new(file).file_name().unwrap());
+            std::fs::copy(file, &path).unwrap();
+
+            let mut song = SongFile::open(&path).unwrap();
+            let mut metadata = song.metadata().clone().unwrap();
+            metadata.insert(ItemKey::Lyrics, lyrics.to_string());
+            song.set_metadata(metadata);
+            song.write().unwrap();
+
+            let metadata = read_metadata_from_path(&path).unwrap();
+            assert_eq!(
+                metadata.get(&ItemKey::Lyrics).map(String::as_str),
+                Some(lyrics),
+                "{file}"
+            );
+        }
+    }
+    #[test]
+    fn test_value_with_separator_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for file in ["data/goose.flac", "data/flip.mp3", "data/bumm.m4a"] {
+            let path = dir.path().join(Path::new(file).file_name().unwrap());
+            std::fs::copy(file, &path).unwrap();
+
+            let mut song = SongFile::open(&path).unwrap();
+            let mut metadata = song.metadata().clone().unwrap();
+            metadata.insert(ItemKey::Artist, "AC; DC".to_string());
+            song.set_metadata(metadata);
+            song.write().unwrap();
+
+            let metadata = read_metadata_from_path(&path).unwrap();
+            assert_eq!(
+                metadata.values(&ItemKey::Artist),
+                Some(["AC; DC".to_string()].as_slice()),
+                "{file}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_multiple_values_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("goose.flac");
+        std::fs::copy("data/goose.flac", &path).unwrap();
+        let artists = vec!["Simon; Garfunkel".to_string(), "Art".to_string()];
+
+        let mut song = SongFile::open(&path).unwrap();
+        let mut metadata = song.metadata().clone().unwrap();
+        metadata.insert_values(ItemKey::Artist, artists.clone());
+        song.set_metadata(metadata);
+        song.write().unwrap();
+
+        let metadata = read_metadata_from_path(&path).unwrap();
+        assert_eq!(metadata.values(&ItemKey::Artist), Some(artists.as_slice()));
+        assert_eq!(
+            metadata.get(&ItemKey::Artist).map(String::as_str),
+            Some("Simon; Garfunkel; Art")
+        );
+    }
+
+    #[test]
+    fn test_write_reports_dropped_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bumm.m4a");
+        std::fs::copy("data/bumm.m4a", &path).unwrap();
+
+        let mut song = SongFile::open(&path).unwrap();
+        let unsupported = MetadataSchema::new(song.tag_type())
+            .fields
+            .into_iter()
+            .find(|field| !field.writable && field.key != ItemKey::Rating)
+            .expect("Every field is writable")
+            .key;
+
+        let mut metadata = song.metadata().clone().unwrap();
+        metadata.insert(ItemKey::Title, "Bumm".to_string());
+        metadata.insert(unsupported.clone(), "Dropped".to_string());
+        song.set_metadata(metadata);
+
+        assert_eq!(song.write().unwrap(), [unsupported.clone()]);
+
+        let metadata = read_metadata_from_path(&path).unwrap();
+        assert_eq!(
+            metadata.get(&ItemKey::Title).map(String::as_str),
+            Some("Bumm")
+        );
+        assert_eq!(metadata.get(&unsupported), None);
+    }
+
+    #[test]
+    fn test_metadata_serde() {
+        let mut metadata = Metadata::new(BTreeMap::new(), BTreeMap::new());
+        metadata.insert_values(ItemKey::Artist, vec!["A; B".to_string(), "C".to_string()]);
+        metadata.insert(ItemKey::Title, "Title".to_string());
+
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["artist"], "A; B; C");
+        assert_eq!(json["values"]["artist"], serde_json::json!(["A; B", "C"]));
+        assert_eq!(serde_json::from_value::<Metadata>(json).unwrap(), metadata);
+
+        // Clients that only send the joined strings get a single value per field.
+        let metadata: Metadata = serde_json::from_value(serde_json::json!({
+            "artist": "A; B",
+        }))
+        .unwrap();
+        assert_eq!(
+            metadata.values(&ItemKey::Artist),
+            Some(["A; B".to_string()].as_slice())
+        );
+
+        // An edited string replaces the values it no longer matches.
+        let metadata: Metadata = serde_json::from_value(serde_json::json!({
+            "artist": "D",
+            "values": { "artist": ["A; B", "C"] },
+        }))
+        .unwrap();
+        assert_eq!(
+            metadata.values(&ItemKey::Artist),
+            Some(["D".to_string()].as_slice())
+        );
+    }
 }
@@ -0,0 +1,150 @@
+//! Checks a song's file for problems with its tags that reading them doesn't surface, but that
+//! other players or later edits trip over.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use lofty::{file::FileType, prelude::*, probe::Probe};
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::{Result, file::SongFileType, item::TagType};
+
+/// Embedded pictures larger than this are reported, cover art rarely needs more.
+pub const MAX_PICTURE_SIZE: usize = 2 * 1024 * 1024;
+
+/// A problem with the tags of a file, it can still be read and written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum TagWarning {
+    /// The ID3v2 tag is an older version, ID3v2.3 stores dates in `TYER` and `TDAT` frames which
+    /// only hold a year and a day. Writing the tag upgrades it to ID3v2.4.
+    OutdatedId3v2 { version: String },
+    /// An embedded picture is larger than [`MAX_PICTURE_SIZE`], which some players refuse or
+    /// take long to load.
+    LargePicture { picture_type: String, size: usize },
+    /// The extension names another format than the file's contents.
+    ExtensionMismatch {
+        extension: String,
+        file_type: SongFileType,
+    },
+    /// The file has tags besides the one that's edited, which can go out of sync with it.
+    ExtraTags { tag_types: Vec<TagType> },
+}
+
+/// Checks the tags of the file at `path`.
+pub fn validate_tags(path: &Path) -> Result<Vec<TagWarning>> {
+    let mut warnings = Vec::new();
+
+    if let Some(version) = id3v2_version(path)?
+        && version < 4
+    {
+        warnings.push(TagWarning::OutdatedId3v2 {
+            version: format!("2.{version}"),
+        });
+    }
+
+    let tagged_file = Probe::open(path)?.guess_file_type()?.read()?;
+    let file_type = tagged_file.file_type();
+
+    if let Some(extension) = path.extension()
+        && FileType::from_ext(extension).is_some_and(|from_ext| from_ext != file_type)
+    {
+        warnings.push(TagWarning::ExtensionMismatch {
+            extension: extension.to_string_lossy().to_string(),
+            file_type: file_type.into(),
+        });
+    }
+
+    let primary = tagged_file
+        .primary_tag()
+        .or(tagged_file.first_tag())
+        .map(|tag| tag.tag_type());
+
+    for tag in tagged_file.tags() {
+        for picture in tag.pictures() {
+            if picture.data().len() > MAX_PICTURE_SIZE {
+                warnings.push(TagWarning::LargePicture {
+                    picture_type: format!("{:?}", picture.pic_type()),
+                    size: picture.data().len(),
+                });
+            }
+        }
+    }
+
+    let extra = tagged_file
+        .tags()
+        .iter()
+        .map(|tag| tag.tag_type())
+        .filter(|tag_type| Some(*tag_type) != primary)
+        .map(TagType::from)
+        .collect::<Vec<_>>();
+
+    if !extra.is_empty() {
+        warnings.push(TagWarning::ExtraTags { tag_types: extra });
+    }
+
+    Ok(warnings)
+}
+
+/// The major version of the ID3v2 tag the file starts with, like `3` for ID3v2.3.
+fn id3v2_version(path: &Path) -> io::Result<Option<u8>> {
+    let mut header = [0; 4];
+
+    match File::open(path)?.read_exact(&mut header) {
+        Ok(()) => Ok((header[..3] == *b"ID3").then_some(header[3])),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let is_mismatch =
+            |warning: &TagWarning| matches!(warning, TagWarning::ExtensionMismatch { .. });
+
+        let flac = dir.path().join("goose.flac");
+        std::fs::copy("data/goose.flac", &flac).unwrap();
+        assert!(!validate_tags(&flac).unwrap().iter().any(is_mismatch));
+
+        let renamed = dir.path().join("goose.mp3");
+        std::fs::copy("data/goose.flac", &renamed).unwrap();
+        assert!(
+            validate_tags(&renamed)
+                .unwrap()
+                .contains(&TagWarning::ExtensionMismatch {
+                    extension: String::from("mp3"),
+                    file_type: SongFileType::Flac,
+                })
+        );
+    }
+
+    #[test]
+    fn test_id3v2_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.mp3");
+
+        std::fs::write(&path, b"ID3\x03\x00\x00").unwrap();
+        assert_eq!(id3v2_version(&path).unwrap(), Some(3));
+
+        std::fs::write(&path, b"fLaC\x00\x00").unwrap();
+        assert_eq!(id3v2_version(&path).unwrap(), None);
+
+        std::fs::write(&path, b"ID").unwrap();
+        assert_eq!(id3v2_version(&path).unwrap(), None);
+    }
+}
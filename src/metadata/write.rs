@@ -0,0 +1,140 @@
+//! Tags are written to a copy of the song that replaces it once it's on disk, so a crash or a full
+//! disk halfway through a write leaves the song as it was.
+
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use lofty::{config::WriteOptions, error::LoftyError, id3::v2::Id3v2Tag, prelude::*, tag::Tag};
+use uuid::Uuid;
+
+use super::{Error, Result};
+
+/// Copies the song next to itself, lets `save` write the tag to the copy and renames the copy over
+/// the song once it's synced to disk. When anything fails the copy is removed and the song is left
+/// untouched.
+///
+/// The copy is always made in the song's directory, so the rename never crosses file systems.
+pub(crate) fn write_tag(
+    path: &Path,
+    save: impl FnOnce(&Path) -> Result<(), LoftyError>,
+) -> Result<()> {
+    let copy = copy_path(path);
+
+    write_copy(path, &copy, save).map_err(|source| {
+        if let Err(err) = fs::remove_file(&copy)
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            log::warn!("Failed to remove unfinished tag write {copy:?}: {err}");
+        }
+
+        Error::Write {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+/// Writes the tag with [`write_tag`], ID3v2 tags are converted first so their frames are written
+/// as ID3v2 frames.
+pub(crate) fn save_tag(path: &Path, tag: &Tag) -> Result<()> {
+    write_tag(path, |path| match tag.tag_type() {
+        lofty::tag::TagType::Id3v2 => {
+            let id3_tag: Id3v2Tag = tag.clone().into();
+
+            id3_tag.save_to_path(path, WriteOptions::default())
+        }
+        _ => tag.save_to_path(path, WriteOptions::default()),
+    })
+}
+
+fn write_copy(
+    path: &Path,
+    copy: &Path,
+    save: impl FnOnce(&Path) -> Result<(), LoftyError>,
+) -> Result<(), LoftyError> {
+    fs::copy(path, copy)?;
+    save(copy)?;
+    OpenOptions::new().write(true).open(copy)?.sync_all()?;
+    fs::rename(copy, path)?;
+
+    // Makes sure the rename itself survives a crash.
+    #[cfg(unix)]
+    if let Some(directory) = path.parent() {
+        fs::File::open(directory)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// A hidden file next to the song, e.g. `.song.flac.<uuid>.partial` for `song.flac`.
+fn copy_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{}.partial", Uuid::new_v4()))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    fn checksum(path: &Path) -> Vec<u8> {
+        Sha256::digest(fs::read(path).unwrap()).to_vec()
+    }
+
+    fn file_names(directory: &Path) -> Vec<PathBuf> {
+        fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn test_failed_write_keeps_song() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("goose.flac");
+        fs::copy("data/goose.flac", &path).unwrap();
+        let before = checksum(&path);
+
+        // Fails halfway, after part of the copy got overwritten and it became read-only.
+        let result = write_tag(&path, |copy| {
+            let length = fs::metadata(copy)?.len();
+            fs::write(copy, vec![0; length as usize / 2])?;
+
+            let mut permissions = fs::metadata(copy)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(copy, permissions)?;
+
+            Err(io::Error::from(io::ErrorKind::PermissionDenied).into())
+        });
+
+        let Err(Error::Write { path: failed, .. }) = result else {
+            panic!("Expected a write error, got {result:?}");
+        };
+        assert_eq!(failed, path);
+        assert_eq!(checksum(&path), before);
+        assert_eq!(file_names(dir.path()), [path]);
+    }
+
+    #[test]
+    fn test_write_replaces_song() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("goose.flac");
+        fs::copy("data/goose.flac", &path).unwrap();
+        let before = checksum(&path);
+
+        write_tag(&path, |copy| {
+            let mut file = OpenOptions::new().append(true).open(copy)?;
+            io::Write::write_all(&mut file, b"tag")?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert_ne!(checksum(&path), before);
+        assert!(fs::read(&path).unwrap().ends_with(b"tag"));
+        assert_eq!(file_names(dir.path()), [path]);
+    }
+}
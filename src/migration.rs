@@ -8,13 +8,21 @@ use color_eyre::{
     owo_colors::OwoColorize,
 };
 use sqlx::{
-    SqlitePool,
+    SqliteConnection, SqlitePool,
     migrate::{AppliedMigration, Migrate, MigrateError, Migrator},
     query_as,
 };
 
 use futures::future::BoxFuture;
 
+use crate::{
+    backup::{self, BackupError},
+    config::Backups,
+    db::{album_id, parse_year},
+    metadata::position::Position,
+    paths,
+};
+
 use tracing::info;
 static MIGRATOR: Migrator = sqlx::migrate!();
 
@@ -33,17 +41,29 @@ macro_rules! custom_migrations {
     };
 }
 
+/// Creates the full-text index songs are searched with, which needs SQLite built with FTS5.
+const CREATE_SONGS_SEARCH: i64 = 20261016090000;
+
 static CUSTOM_MIGRATIONS: LazyLock<HashMap<i64, MigrationFn>> = custom_migrations! {
     20250905175005, add_uuid_to_songs;
     20250916122132, use_uuid_for_names_in_directories;
     20260115231518, add_reference_to_directory_in_songs;
+    20261016100000, add_year_num_to_songs;
+    20261016110000, parse_track_and_disc_numbers;
+    20261016140000, add_album_id_to_songs;
 };
 
 /// Runs the migrations
 ///
 /// If the database is new nothing will be printed, otherwise every migration that is applied will
 /// be printed to console.
-pub async fn run_migrations(pool: &SqlitePool, new_database: bool) -> Result<()> {
+///
+/// With `backups` set, an existing database is backed up before the first migration is applied.
+pub async fn run_migrations(
+    pool: &SqlitePool,
+    new_database: bool,
+    backups: Option<&Backups>,
+) -> Result<()> {
     let mut connection = pool.acquire().await?;
     let mut made_changes = false;
     connection.lock().await?;
@@ -83,6 +103,17 @@ pub async fn run_migrations(pool: &SqlitePool, new_database: bool) -> Result<()>
             continue;
         }
 
+        if !new_database
+            && !made_changes
+            && let Some(backups) = backups
+        {
+            back_up(&mut connection, backups).await?;
+        }
+
+        if migration.version == CREATE_SONGS_SEARCH {
+            ensure_fts5(&mut connection).await?;
+        }
+
         connection.apply(migration).await?;
 
         if !new_database {
@@ -124,6 +155,166 @@ pub async fn run_migrations(pool: &SqlitePool, new_database: bool) -> Result<()>
     Ok(())
 }
 
+/// Backs up the database before it's migrated, a database that's only kept in memory has nothing
+/// to lose so it isn't backed up.
+async fn back_up(connection: &mut SqliteConnection, settings: &Backups) -> Result<()> {
+    let directory = paths::backups_dir();
+
+    match backup::create_backup(connection, &directory, Some("pre-migration")).await {
+        Ok(backup) => info!(
+            "Backed up the database to \"{}\" before migrating",
+            directory.join(&backup.name).display()
+        ),
+        Err(BackupError::InMemory) => return Ok(()),
+        Err(err) => {
+            return Err(eyre!(
+                "Failed to back up the database, no migrations were applied: {err}"
+            ));
+        }
+    }
+
+    backup::prune_backups(&directory, settings.keep).await?;
+
+    Ok(())
+}
+
+/// Checks that SQLite has the FTS5 module, so a build without it stops with an explanation
+/// instead of failing halfway through the migration creating the search index.
+async fn ensure_fts5(connection: &mut SqliteConnection) -> Result<()> {
+    if let Err(err) = sqlx::query("CREATE VIRTUAL TABLE temp.fts5_check USING fts5(value)")
+        .execute(&mut *connection)
+        .await
+    {
+        return Err(eyre!(
+            "The search index needs SQLite with the FTS5 module, which the SQLite in use doesn't have: {err}"
+        ));
+    }
+
+    sqlx::query("DROP TABLE temp.fts5_check")
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Parses the years of the songs into `year_num`, tags the year can't be read from are left
+/// without one.
+async fn add_year_num_to_songs(pool: &SqlitePool) -> Result<()> {
+    let years: Vec<(String, String)> =
+        query_as("SELECT id, year FROM songs WHERE year IS NOT NULL")
+            .fetch_all(pool)
+            .await?;
+
+    if years.is_empty() {
+        info!("No songs with a year found, skipping parsing years");
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now();
+    let mut tx = pool.begin().await?;
+    let mut parsed = 0;
+
+    for (song_id, year) in &years {
+        let Some(year_num) = parse_year(year) else {
+            continue;
+        };
+
+        sqlx::query("UPDATE songs SET year_num = ? WHERE id = ?")
+            .bind(year_num)
+            .bind(song_id)
+            .execute(&mut *tx)
+            .await?;
+        parsed += 1;
+    }
+
+    tx.commit().await?;
+
+    info!(
+        "Parsed the years of {parsed} out of {} songs in {}ms",
+        years.len(),
+        now.elapsed()?.as_millis()
+    );
+
+    Ok(())
+}
+
+/// Stores the id of the album of every song with one, see [`album_id`].
+async fn add_album_id_to_songs(pool: &SqlitePool) -> Result<()> {
+    let albums: Vec<(String, Option<String>)> =
+        query_as("SELECT DISTINCT album, album_artist FROM songs WHERE album IS NOT NULL")
+            .fetch_all(pool)
+            .await?;
+
+    if albums.is_empty() {
+        info!("No songs with an album found, skipping adding album ids");
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now();
+    let mut tx = pool.begin().await?;
+
+    for (album, album_artist) in &albums {
+        sqlx::query("UPDATE songs SET album_id = ? WHERE album = ? AND album_artist IS ?")
+            .bind(album_id(album, album_artist.as_deref()))
+            .bind(album)
+            .bind(album_artist)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    info!(
+        "Added the ids of {} albums in {}ms",
+        albums.len(),
+        now.elapsed()?.as_millis()
+    );
+
+    Ok(())
+}
+
+/// Parses the track and disc tags into numbers and totals, the tags are dropped by the next
+/// migration.
+async fn parse_track_and_disc_numbers(pool: &SqlitePool) -> Result<()> {
+    let tags: Vec<(String, Option<String>, Option<String>)> = query_as(
+        "SELECT id, track_number_tag, disc_number_tag FROM songs WHERE track_number_tag IS NOT NULL OR disc_number_tag IS NOT NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if tags.is_empty() {
+        info!("No songs with track or disc numbers found, skipping parsing them");
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now();
+    let mut tx = pool.begin().await?;
+
+    for (song_id, track, disc) in &tags {
+        let track = Position::parse(track.as_deref().unwrap_or_default());
+        let disc = Position::parse(disc.as_deref().unwrap_or_default());
+
+        sqlx::query("UPDATE songs SET track_number = ?, track_total = ?, disc_number = ?, disc_total = ? WHERE id = ?")
+            .bind(track.number)
+            .bind(track.total)
+            .bind(disc.number)
+            .bind(disc.total)
+            .bind(song_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    info!(
+        "Parsed the track and disc numbers of {} songs in {}ms",
+        tags.len(),
+        now.elapsed()?.as_millis()
+    );
+
+    Ok(())
+}
+
 async fn add_reference_to_directory_in_songs(pool: &SqlitePool) -> Result<()> {
     let song_paths: Vec<(String, String)> = query_as("SELECT id, path FROM songs")
         .fetch_all(pool)
@@ -425,7 +616,7 @@ mod test {
         .await
         .unwrap();
 
-        run_migrations(&pool, false)
+        run_migrations(&pool, false, None)
             .await
             .expect("Failed to run migrations");
 
@@ -458,7 +649,7 @@ mod test {
             .await
             .unwrap();
 
-        run_migrations(&pool, false)
+        run_migrations(&pool, false, None)
             .await
             .expect("Failed to run migrations");
 
@@ -494,8 +685,108 @@ mod test {
             .await
             .unwrap();
 
-        run_migrations(&pool, false)
+        run_migrations(&pool, false, None)
+            .await
+            .expect("Failed to run migrations");
+    }
+
+    #[tokio::test]
+    #[test_log::test]
+    async fn test_add_year_num_to_songs() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        apply_migrations(&pool, CREATE_SONGS_SEARCH).await.unwrap();
+
+        query("INSERT INTO directories (name, path) VALUES ('directory', '/path/to/')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for (id, year) in [
+            ("plain", Some("1999")),
+            ("date", Some("2003-05-12")),
+            ("garbage", Some("Unknown")),
+            ("zero", Some("0")),
+            ("none", None),
+        ] {
+            query("INSERT INTO songs (id, path, year, directory_id) VALUES (?, ?, ?, 'directory')")
+                .bind(id)
+                .bind(format!("/path/to/{id}.mp3"))
+                .bind(year)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        run_migrations(&pool, false, None)
+            .await
+            .expect("Failed to run migrations");
+
+        let years: HashMap<_, _> =
+            sqlx::query_as::<_, (String, Option<i32>)>("SELECT id, year_num FROM songs")
+                .fetch_all(&pool)
+                .await
+                .unwrap()
+                .into_iter()
+                .collect();
+
+        assert_eq!(years["plain"], Some(1999));
+        assert_eq!(years["date"], Some(2003));
+        assert_eq!(years["garbage"], None);
+        assert_eq!(years["zero"], None);
+        assert_eq!(years["none"], None);
+    }
+
+    const ADD_YEAR_NUM_TO_SONGS: i64 = 20261016100000;
+
+    #[tokio::test]
+    #[test_log::test]
+    async fn test_parse_track_and_disc_numbers() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        apply_migrations(&pool, ADD_YEAR_NUM_TO_SONGS)
+            .await
+            .unwrap();
+
+        query("INSERT INTO directories (name, path) VALUES ('directory', '/path/to/')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for (id, track, disc) in [
+            ("total", Some("3/12"), Some("1/2")),
+            ("plain", Some("03"), None),
+            ("vinyl", Some("A1"), Some("B")),
+            ("none", None, None),
+        ] {
+            query("INSERT INTO songs (id, path, track_number, disc_number, directory_id) VALUES (?, ?, ?, ?, 'directory')")
+                .bind(id)
+                .bind(format!("/path/to/{id}.mp3"))
+                .bind(track)
+                .bind(disc)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        run_migrations(&pool, false, None)
             .await
             .expect("Failed to run migrations");
+
+        let numbers: HashMap<_, _> =
+            sqlx::query_as::<_, (String, Option<u32>, Option<u32>, Option<u32>, Option<u32>)>(
+                "SELECT id, track_number, track_total, disc_number, disc_total FROM songs",
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, track, track_total, disc, disc_total)| {
+                (id, (track, track_total, disc, disc_total))
+            })
+            .collect();
+
+        assert_eq!(numbers["total"], (Some(3), Some(12), Some(1), Some(2)));
+        assert_eq!(numbers["plain"], (Some(3), None, None, None));
+        assert_eq!(numbers["vinyl"], (None, None, None, None));
+        assert_eq!(numbers["none"], (None, None, None, None));
     }
 }
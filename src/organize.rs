@@ -1,13 +1,13 @@
 use std::{
-    collections::BTreeMap,
-    path::{MAIN_SEPARATOR_STR, PathBuf},
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{MAIN_SEPARATOR_STR, Path, PathBuf},
 };
 
 use handlebars::Handlebars;
 use serde::Serialize;
 
 use super::metadata;
-use metadata::Metadata;
+use metadata::{Metadata, item::ItemKey};
 
 pub const DEFAULT_TEMPLATE: &str = r#"
 {{#if albumArtist}}
@@ -28,12 +28,28 @@ pub const DEFAULT_TEMPLATE: &str = r#"
 pub enum OrganizeError {
     #[error(transparent)]
     Handlebars(#[from] handlebars::RenderError),
+    #[error("Invalid template: {0}")]
+    Template(#[from] handlebars::TemplateError),
     #[error("Original path has no file name: {0}")]
     NoFileName(PathBuf),
+    #[error("Failed to read cover art: {0}")]
+    CoverArt(#[from] metadata::Error),
+    #[error("Failed to write cover art: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 pub type Result<T, E = OrganizeError> = std::result::Result<T, E>;
 
+/// Names Windows reserves for devices, a file can't be named after them even with an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Names, without the extension, of the images file managers and players show as an album's
+/// cover.
+const FOLDER_ART_NAMES: [&str; 3] = ["cover", "folder", "front"];
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Song {
@@ -43,20 +59,52 @@ pub struct Song {
     pub metadata: Metadata,
 }
 
+/// Options that change how a song path is rendered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Use the rendered file name instead of keeping the original one.
+    pub rename_original_file: bool,
+    /// Convert non-ASCII characters to their closest ASCII representation.
+    pub transliterate: bool,
+}
+
+/// Checks the template compiles, so an invalid one is rejected before any song is rendered.
+pub fn validate_template(template: &str) -> Result<()> {
+    handlebars::Template::compile(template)?;
+
+    Ok(())
+}
+
+/// Renders the path of the song relative to its directory.
+///
+/// Empty segments, e.g. from a field the song doesn't have, are left out along with `.` and `..`
+/// so the path can't point outside of the directory.
 pub fn render_song_path(
     handlebar: &Handlebars,
     template: &str,
     song: &Song,
-    rename_original_file: bool,
+    options: RenderOptions,
 ) -> Result<PathBuf> {
+    let metadata = format_numbers(&song.metadata);
+    let metadata = if options.transliterate {
+        sanitize_metadata(&transliterate_metadata(&metadata))
+    } else {
+        sanitize_metadata(&metadata)
+    };
+
     let mut rendered_path = handlebar
-        .render_template(template, &sanitize_metadata(&song.metadata))?
+        .render_template(template, &metadata)?
         .lines()
         .map(str::trim)
         .filter(|line| !line.is_empty())
         .collect::<Vec<_>>()
         .join("")
-        .replace(['\\', '/'], MAIN_SEPARATOR_STR);
+        .replace(['\\', '/'], MAIN_SEPARATOR_STR)
+        .split(MAIN_SEPARATOR_STR)
+        .map(|segment| portable_segment(segment.trim()))
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join(MAIN_SEPARATOR_STR);
 
     rendered_path.push_str(
         &song
@@ -72,38 +120,226 @@ pub fn render_song_path(
     );
 
     let path = PathBuf::from(rendered_path);
-    if !rename_original_file {
+    if !options.rename_original_file {
         let original_file_name = song
             .file_path
             .file_name()
             .ok_or(OrganizeError::NoFileName(song.file_path.clone()))?;
 
-        Ok(path.with_file_name(original_file_name))
+        if options.transliterate {
+            Ok(path.with_file_name(any_ascii::any_ascii(&original_file_name.to_string_lossy())))
+        } else {
+            Ok(path.with_file_name(original_file_name))
+        }
     } else {
         Ok(path)
     }
 }
 
+/// Makes a segment of a rendered path valid on Windows as well, so a library organized on one
+/// system can be moved to another. Trailing dots and spaces are removed, which also empties `.`
+/// and `..`, and device names like `CON` get an underscore appended.
+fn portable_segment(segment: &str) -> String {
+    let segment = segment.trim_end_matches(['.', ' ']);
+    let stem = segment.split('.').next().unwrap_or_default();
+
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem))
+    {
+        format!("{stem}_{}", &segment[stem.len()..])
+    } else {
+        segment.to_string()
+    }
+}
+
+/// Returns every target path that more than one source path is mapped to.
+pub fn find_collisions<'p>(
+    paths: impl IntoIterator<Item = (&'p PathBuf, &'p PathBuf)>,
+) -> HashSet<PathBuf> {
+    paths
+        .into_iter()
+        .fold(HashMap::<&PathBuf, usize>::new(), |mut targets, (_, to)| {
+            *targets.entry(to).or_default() += 1;
+            targets
+        })
+        .into_iter()
+        .filter_map(|(to, count)| (count > 1).then(|| to.clone()))
+        .collect()
+}
+
+/// Writes the front cover embedded in the first of the tracks that has one into the directory as
+/// `cover.jpg` (or whatever format the image is in).
+///
+/// Nothing is written if the directory already has a cover image or none of the tracks have a
+/// front cover, the path of the written image is returned otherwise.
+pub fn write_folder_art(directory: &Path, tracks: &[PathBuf]) -> Result<Option<PathBuf>> {
+    let has_folder_art = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .any(|entry| {
+            let path = entry.path();
+            image::ImageFormat::from_path(&path).is_ok()
+                && path.file_stem().is_some_and(|stem| {
+                    FOLDER_ART_NAMES.contains(&stem.to_string_lossy().to_lowercase().as_str())
+                })
+        });
+
+    if has_folder_art {
+        return Ok(None);
+    }
+
+    for track in tracks {
+        let Some(cover_art) = metadata::get_cover_art(track)?
+            .into_iter()
+            .find(|cover_art| cover_art.cover_type == metadata::CoverArtType::Front)
+        else {
+            continue;
+        };
+
+        let extension = image::guess_format(&cover_art.data)
+            .ok()
+            .and_then(|format| format.extensions_str().first().copied())
+            .unwrap_or("jpg");
+
+        let path = directory.join(format!("cover.{extension}"));
+        std::fs::write(&path, cover_art.data)?;
+
+        return Ok(Some(path));
+    }
+
+    Ok(None)
+}
+
+/// Songs templates are previewed with, named after the edge case they cover: a song with only a
+/// title, one with every common tag and one whose tags are full of characters paths can't hold.
+pub fn sample_songs() -> Vec<(&'static str, Song)> {
+    use metadata::item::ItemKey;
+
+    let song = |file_name: &str, fields: &[(ItemKey, &str)]| Song {
+        file_path: PathBuf::from(file_name),
+        metadata: Metadata::new(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), value.to_string()))
+                .collect(),
+            BTreeMap::new(),
+        ),
+    };
+
+    vec![
+        (
+            "Minimal tags",
+            song("01 Untitled.mp3", &[(ItemKey::Title, "Untitled")]),
+        ),
+        (
+            "Full tags",
+            song(
+                "03 Goose.flac",
+                &[
+                    (ItemKey::Title, "Goose"),
+                    (ItemKey::Artist, "The Flock"),
+                    (ItemKey::AlbumArtist, "The Flock"),
+                    (ItemKey::Album, "Migration"),
+                    (ItemKey::Genre, "Ambient"),
+                    (ItemKey::Mood, "Calm"),
+                    (ItemKey::TrackNumber, "3"),
+                    (ItemKey::DiscNumber, "1"),
+                    (ItemKey::Year, "2024"),
+                ],
+            ),
+        ),
+        (
+            "Dirty characters",
+            song(
+                "track?.ogg",
+                &[
+                    (ItemKey::Title, "What/Why: A \"Question\"?"),
+                    (ItemKey::Artist, "AC/DC"),
+                    (ItemKey::AlbumArtist, " .. "),
+                    (ItemKey::Album, "Ünïcödé <Album> *|*"),
+                    (ItemKey::TrackNumber, "1/12"),
+                ],
+            ),
+        ),
+    ]
+}
+
+/// Writes the track and disc numbers without their totals, which are available on their own, so
+/// `3/12` doesn't add a separator to the path. Track numbers are zero-padded to the width of the
+/// total so files sort in order, numbers that can't be parsed are left as they are.
+fn format_numbers(metadata: &Metadata) -> Metadata {
+    let mut metadata = metadata.clone();
+
+    let track = metadata.track();
+    if let Some(number) = track.number {
+        let width = track
+            .total
+            .map_or(1, |total| total.to_string().len())
+            .max(2);
+        metadata.insert(ItemKey::TrackNumber, format!("{number:0width$}"));
+    }
+    if let Some(total) = track.total {
+        metadata.insert(ItemKey::TrackTotal, total.to_string());
+    }
+
+    let disc = metadata.disc();
+    if let Some(number) = disc.number {
+        metadata.insert(ItemKey::DiscNumber, number.to_string());
+    }
+    if let Some(total) = disc.total {
+        metadata.insert(ItemKey::DiscTotal, total.to_string());
+    }
+
+    metadata
+}
+
+/// Converts every metadata value into ASCII, the tags themselves are left untouched.
+pub fn transliterate_metadata(metadata: &Metadata) -> Metadata {
+    Metadata::new(
+        metadata
+            .fields()
+            .iter()
+            .map(|(key, value)| (key.clone(), any_ascii::any_ascii(value)))
+            .collect(),
+        metadata
+            .unknown_fields()
+            .iter()
+            .map(|(key, value)| (key.clone(), any_ascii::any_ascii(value)))
+            .collect(),
+    )
+}
+
 pub fn sanitize_metadata(metadata: &Metadata) -> Metadata {
     Metadata::new(
         metadata
             .fields()
             .iter()
             .fold(BTreeMap::new(), |mut sanitized_known, (key, value)| {
-                sanitized_known.insert(key.clone(), sanitize_filename::sanitize(value).to_string());
+                sanitized_known.insert(key.clone(), sanitize_value(value));
                 sanitized_known
             }),
         metadata.unknown_fields().iter().fold(
             BTreeMap::new(),
             |mut sanitized_unknown, (key, value)| {
-                sanitized_unknown
-                    .insert(key.clone(), sanitize_filename::sanitize(value).to_string());
+                sanitized_unknown.insert(key.clone(), sanitize_value(value));
                 sanitized_unknown
             },
         ),
     )
 }
 
+/// Removes the characters file names can't contain from a value. Windows device names and
+/// trailing dots are left to [`portable_segment`], so a value renders the same on every platform.
+fn sanitize_value(value: &str) -> String {
+    sanitize_filename::sanitize_with_options(
+        value,
+        sanitize_filename::Options {
+            windows: false,
+            ..Default::default()
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use test_log::test;
@@ -112,7 +348,25 @@ mod tests {
     use handlebars::Handlebars;
     use std::collections::BTreeMap;
 
-    use metadata::item::ItemKey;
+    #[test]
+    fn test_write_folder_art() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracks = ["data/flip.wav", "data/goose.flac"].map(|file| {
+            let path = dir.path().join(Path::new(file).file_name().unwrap());
+            std::fs::copy(file, &path).unwrap();
+            path
+        });
+
+        // The first track has no cover art, so the second one's is used.
+        let written = write_folder_art(dir.path(), &tracks).unwrap().unwrap();
+        assert_eq!(written.file_stem().unwrap(), "cover");
+        assert_eq!(
+            std::fs::read(&written).unwrap(),
+            metadata::get_cover_art(&tracks[1]).unwrap()[0].data
+        );
+
+        assert!(write_folder_art(dir.path(), &tracks).unwrap().is_none());
+    }
 
     #[test]
     fn test_render_song_with_simple_template() {
@@ -136,7 +390,15 @@ mod tests {
             metadata,
         };
 
-        let result = render_song_path(&handlebars, DEFAULT_TEMPLATE, &song, true);
+        let result = render_song_path(
+            &handlebars,
+            DEFAULT_TEMPLATE,
+            &song,
+            RenderOptions {
+                rename_original_file: true,
+                ..Default::default()
+            },
+        );
 
         log::debug!("Result: {:#?}", result);
 
@@ -147,6 +409,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_render_song_path_with_numbers() {
+        let handlebars = Handlebars::new();
+
+        let song = |track: &str, total: Option<&str>| Song {
+            file_path: PathBuf::from("test_file.mp3"),
+            metadata: Metadata::new(
+                [
+                    Some((ItemKey::Title, String::from("test_title"))),
+                    Some((ItemKey::TrackNumber, track.to_string())),
+                    total.map(|total| (ItemKey::TrackTotal, total.to_string())),
+                    Some((ItemKey::DiscNumber, String::from("1/2"))),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+                BTreeMap::new(),
+            ),
+        };
+        let render = |song: &Song| {
+            render_song_path(
+                &handlebars,
+                "{{discNumber}}-{{trackNumber}} of {{trackTotal}} - {{title}}",
+                song,
+                RenderOptions {
+                    rename_original_file: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            render(&song("1/12", None)),
+            PathBuf::from("1-01 of 12 - test_title.mp3")
+        );
+        assert_eq!(
+            render(&song("7", Some("120"))),
+            PathBuf::from("1-007 of 120 - test_title.mp3")
+        );
+        assert_eq!(
+            render(&song("A1", None)),
+            PathBuf::from("1-A1 of  - test_title.mp3")
+        );
+    }
+
+    #[test]
+    fn test_render_song_path_with_windows_names() {
+        let handlebars = Handlebars::new();
+
+        let song = Song {
+            file_path: PathBuf::from("test_file.mp3"),
+            metadata: Metadata::new(
+                BTreeMap::from([
+                    (ItemKey::Title, "nul".to_string()),
+                    (ItemKey::Artist, "Com1.Live".to_string()),
+                    (ItemKey::Album, "Greatest Hits Vol. ".to_string()),
+                    (ItemKey::AlbumArtist, "..".to_string()),
+                ]),
+                BTreeMap::new(),
+            ),
+        };
+
+        let path = render_song_path(
+            &handlebars,
+            "{{artist}}/{{album}}.../{{albumArtist}}/{{title}}",
+            &song,
+            RenderOptions {
+                rename_original_file: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            path,
+            Path::new("Com1_.Live")
+                .join("Greatest Hits Vol")
+                .join("nul_.mp3")
+        );
+        assert_eq!(portable_segment("CONCERT"), "CONCERT");
+        assert_eq!(portable_segment("aux. "), "aux_");
+    }
+
     #[test]
     fn test_render_song_path() {
         let handlebars = Handlebars::new();
@@ -169,7 +515,7 @@ mod tests {
             metadata,
         };
 
-        let result = render_song_path(&handlebars, DEFAULT_TEMPLATE, &song, false);
+        let result = render_song_path(&handlebars, DEFAULT_TEMPLATE, &song, Default::default());
 
         assert!(result.is_ok());
         assert_eq!(
@@ -204,7 +550,10 @@ mod tests {
             &handlebars,
             "{{albumArtist}}/{{album}}/{{title}}",
             &song,
-            true,
+            RenderOptions {
+                rename_original_file: true,
+                ..Default::default()
+            },
         );
         assert!(result.is_ok());
 
@@ -214,4 +563,153 @@ mod tests {
             PathBuf::from("albumartistname/albumname/titlewithillegalchars.wav")
         );
     }
+
+    #[test]
+    fn test_render_sample_songs() {
+        let handlebars = Handlebars::new();
+
+        for (name, song) in sample_songs() {
+            let path = render_song_path(
+                &handlebars,
+                DEFAULT_TEMPLATE,
+                &song,
+                RenderOptions::default(),
+            )
+            .unwrap();
+
+            assert!(
+                path.components()
+                    .all(|component| matches!(component, std::path::Component::Normal(_))),
+                "{name} rendered to {path:?}"
+            );
+            assert_eq!(path.file_name(), song.file_path.file_name());
+        }
+    }
+
+    #[test]
+    fn test_render_song_path_with_missing_field() {
+        let song = Song {
+            file_path: PathBuf::from("test_file.flac"),
+            metadata: Metadata::new(
+                BTreeMap::from([
+                    (ItemKey::Title, "title".to_string()),
+                    (ItemKey::Album, "album".to_string()),
+                ]),
+                BTreeMap::new(),
+            ),
+        };
+
+        let result = render_song_path(
+            &Handlebars::new(),
+            "{{composer}}/{{album}}/./../{{title}}",
+            &song,
+            RenderOptions {
+                rename_original_file: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(result.unwrap(), PathBuf::from("album/title.flac"));
+    }
+
+    #[test]
+    fn test_render_song_path_with_separator_in_field() {
+        let song = Song {
+            file_path: PathBuf::from("test_file.flac"),
+            metadata: Metadata::new(
+                BTreeMap::from([
+                    (ItemKey::Title, "Either/Or".to_string()),
+                    (ItemKey::AlbumArtist, "AC\\DC".to_string()),
+                ]),
+                BTreeMap::new(),
+            ),
+        };
+
+        let result = render_song_path(
+            &Handlebars::new(),
+            "{{albumArtist}}/{{title}}",
+            &song,
+            RenderOptions {
+                rename_original_file: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(result.unwrap(), PathBuf::from("ACDC/EitherOr.flac"));
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template(DEFAULT_TEMPLATE).is_ok());
+        assert!(matches!(
+            validate_template("{{#if album}}{{album}}"),
+            Err(OrganizeError::Template(_))
+        ));
+    }
+
+    fn render_transliterated(title: &str, artist: &str) -> PathBuf {
+        let metadata = Metadata::new(
+            BTreeMap::from([
+                (ItemKey::Title, title.to_string()),
+                (ItemKey::AlbumArtist, artist.to_string()),
+            ]),
+            BTreeMap::new(),
+        );
+
+        let song = Song {
+            file_path: PathBuf::from("test_file.flac"),
+            metadata,
+        };
+
+        render_song_path(
+            &Handlebars::new(),
+            "{{albumArtist}}/{{title}}",
+            &song,
+            RenderOptions {
+                rename_original_file: true,
+                transliterate: true,
+            },
+        )
+        .expect("Failed to render path")
+    }
+
+    #[test]
+    fn test_transliterate_japanese() {
+        assert_eq!(
+            render_transliterated("さくら", "ヨルシカ"),
+            PathBuf::from("yorushika/sakura.flac")
+        );
+    }
+
+    #[test]
+    fn test_transliterate_cyrillic() {
+        assert_eq!(
+            render_transliterated("Привет", "Кино"),
+            PathBuf::from("Kino/Privet.flac")
+        );
+    }
+
+    #[test]
+    fn test_transliterate_accented_latin() {
+        assert_eq!(
+            render_transliterated("Déjà Vu", "Beyoncé"),
+            PathBuf::from("Beyonce/Deja Vu.flac")
+        );
+    }
+
+    #[test]
+    fn test_transliteration_collisions() {
+        let from = [PathBuf::from("a.flac"), PathBuf::from("b.flac")];
+        let to = [
+            render_transliterated("Café", "Artist"),
+            render_transliterated("Cafe", "Artist"),
+        ];
+
+        let collisions = find_collisions(from.iter().zip(to.iter()));
+
+        assert_eq!(
+            collisions,
+            HashSet::from([PathBuf::from("Artist/Cafe.flac")])
+        );
+    }
 }
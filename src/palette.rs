@@ -0,0 +1,222 @@
+//! Dominant colors of cover art, for clients to theme album pages with.
+//!
+//! Colors are found with median cut over a downscaled copy of the image: the pixels are split
+//! into boxes along the channel they vary the most in, and every box is averaged into a color.
+
+use image::{DynamicImage, GenericImageView, Rgb, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Largest width or height of the copy the colors are taken from.
+const SAMPLE_SIZE: u32 = 64;
+
+/// Most colors in a palette, fewer are returned when the image doesn't have as many.
+const MAX_COLORS: usize = 6;
+
+/// Pixels more transparent than this are left out, they don't show the color they store.
+const MIN_ALPHA: u8 = 128;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Palette {
+    /// The dominant colors as hex like `#1a2b3c`, the most common first.
+    pub colors: Vec<String>,
+    /// Black or white, whichever is easier to read on the most common color.
+    pub foreground: String,
+}
+
+/// A box of pixels, split until there are as many boxes as colors.
+struct ColorBox {
+    pixels: Vec<Rgb<u8>>,
+}
+
+impl ColorBox {
+    /// The channel the pixels vary the most in, with how much they vary in it.
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let values = self.pixels.iter().map(|pixel| pixel[channel]);
+                let min = values.clone().min().unwrap_or_default();
+                let max = values.max().unwrap_or_default();
+
+                (channel, max - min)
+            })
+            .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+            .unwrap_or_default()
+    }
+
+    /// Splits the box at the median of its widest channel, keeping pixels with the same value in
+    /// the same box so a color isn't averaged with its neighbours.
+    fn split(self) -> (Self, Self) {
+        let (channel, _) = self.widest_channel();
+        let mut values = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel[channel])
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        let median = values[values.len() / 2];
+
+        // When the median is the lowest value, the pixels with it make up the lower box instead.
+        let (lower, upper) = if values[0] < median {
+            self.pixels
+                .into_iter()
+                .partition(|pixel| pixel[channel] < median)
+        } else {
+            self.pixels
+                .into_iter()
+                .partition(|pixel| pixel[channel] <= median)
+        };
+
+        (Self { pixels: lower }, Self { pixels: upper })
+    }
+
+    fn average(&self) -> Rgb<u8> {
+        let count = self.pixels.len().max(1) as u64;
+        let mut sums = [0u64; 3];
+        for pixel in &self.pixels {
+            for (sum, value) in sums.iter_mut().zip(pixel.0) {
+                *sum += u64::from(value);
+            }
+        }
+
+        Rgb(sums.map(|sum| ((sum + count / 2) / count) as u8))
+    }
+}
+
+/// Finds the dominant colors of the image, `None` if it has no visible pixels.
+pub fn extract_palette(image: &DynamicImage) -> Option<Palette> {
+    let image = if image.width() > SAMPLE_SIZE || image.height() > SAMPLE_SIZE {
+        image.resize(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+
+    let pixels = image
+        .pixels()
+        .filter(|(_, _, pixel)| pixel[3] >= MIN_ALPHA)
+        .map(|(_, _, pixel)| Rgb([pixel[0], pixel[1], pixel[2]]))
+        .collect::<Vec<_>>();
+
+    if pixels.is_empty() {
+        return None;
+    }
+
+    let mut boxes = vec![ColorBox { pixels }];
+    while boxes.len() < MAX_COLORS {
+        // The box with the widest range is split next, the larger one on a tie.
+        let Some((index, _)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, color_box)| (index, color_box.widest_channel().1))
+            .filter(|(_, range)| *range > 0)
+            .max_by_key(|(index, range)| (*range, boxes[*index].pixels.len()))
+        else {
+            break;
+        };
+
+        let (lower, upper) = boxes.swap_remove(index).split();
+        boxes.extend([lower, upper]);
+    }
+
+    // Boxes that split on a run of the same value can average to the same color.
+    let mut colors: Vec<(Rgb<u8>, usize)> = Vec::new();
+    for color_box in &boxes {
+        let color = color_box.average();
+        match colors.iter_mut().find(|(existing, _)| *existing == color) {
+            Some((_, count)) => *count += color_box.pixels.len(),
+            None => colors.push((color, color_box.pixels.len())),
+        }
+    }
+    colors.sort_by_key(|(color, count)| (std::cmp::Reverse(*count), color.0));
+
+    let foreground =
+        if contrast(colors[0].0, Rgb([0, 0, 0])) >= contrast(colors[0].0, Rgb([255, 255, 255])) {
+            "#000000"
+        } else {
+            "#ffffff"
+        };
+
+    Some(Palette {
+        colors: colors.into_iter().map(|(color, _)| hex(color)).collect(),
+        foreground: foreground.to_string(),
+    })
+}
+
+fn hex(color: Rgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// The contrast ratio of two colors as defined by WCAG, from 1 to 21.
+fn contrast(a: Rgb<u8>, b: Rgb<u8>) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The relative luminance of the color as defined by WCAG.
+fn luminance(color: Rgb<u8>) -> f64 {
+    let [r, g, b] = color.0.map(|value| {
+        let value = f64::from(value) / 255.0;
+        if value <= 0.03928 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_palette() {
+        // Bands of 40% navy, 30% orange, 20% white and 10% green, small enough not to be
+        // downscaled.
+        let bands = [
+            (24, Rgba([20, 30, 90, 255])),
+            (18, Rgba([240, 140, 20, 255])),
+            (12, Rgba([255, 255, 255, 255])),
+            (6, Rgba([30, 160, 60, 255])),
+        ];
+        let image = RgbaImage::from_fn(60, 60, |_, y| {
+            let mut end = 0;
+            bands
+                .iter()
+                .find(|(height, _)| {
+                    end += height;
+                    y < end
+                })
+                .map(|(_, color)| *color)
+                .unwrap()
+        });
+
+        assert_eq!(
+            extract_palette(&DynamicImage::ImageRgba8(image)),
+            Some(Palette {
+                colors: ["#141e5a", "#f08c14", "#ffffff", "#1ea03c"]
+                    .map(String::from)
+                    .to_vec(),
+                foreground: String::from("#ffffff"),
+            })
+        );
+
+        let light = RgbaImage::from_pixel(8, 8, Rgba([250, 240, 200, 255]));
+        assert_eq!(
+            extract_palette(&DynamicImage::ImageRgba8(light)).map(|palette| palette.foreground),
+            Some(String::from("#000000"))
+        );
+
+        let transparent = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 0]));
+        assert_eq!(
+            extract_palette(&DynamicImage::ImageRgba8(transparent)),
+            None
+        );
+    }
+}
@@ -40,6 +40,41 @@ pub fn metadata_history_dir() -> PathBuf {
     app_data_dir().join("metadata").join("history")
 }
 
+/// Get the path to the directory generated reports are saved to.
+pub fn reports_dir() -> PathBuf {
+    app_data_dir().join("reports")
+}
+
+/// Get the path to the latest library-wide album hygiene report.
+pub fn album_hygiene_report_path() -> PathBuf {
+    reports_dir().join("album-hygiene.json")
+}
+
+/// Get the path to the latest library integrity report.
+pub fn integrity_report_path() -> PathBuf {
+    reports_dir().join("integrity.json")
+}
+
+/// Get the path to the artifact saved by the last run of a job.
+pub fn job_artifact_path(job_id: &str) -> PathBuf {
+    reports_dir().join("jobs").join(format!("{job_id}.json"))
+}
+
+/// Get the path to the directory database backups are written to.
+pub fn backups_dir() -> PathBuf {
+    app_data_dir().join("backups")
+}
+
+/// Get the path to the directory log files are written to.
+pub fn logs_dir() -> PathBuf {
+    app_data_dir().join("logs")
+}
+
+/// Get the path to the directory playlist bundles are written to.
+pub fn bundles_dir() -> PathBuf {
+    app_data_dir().join("bundles")
+}
+
 /// Get the path to the app cache directory.
 pub fn app_cache_dir() -> PathBuf {
     if let Ok(cache_dir) = env::var(format!("{}_CACHE_DIR", APP_NAME.to_uppercase()).as_str()) {
@@ -53,6 +88,21 @@ pub fn app_cache_dir() -> PathBuf {
     env::current_dir().expect("Failed to get current directory")
 }
 
+/// Get the path to the converted cover art cache directory.
+pub fn cover_art_cache_dir() -> PathBuf {
+    app_cache_dir().join("cover-art")
+}
+
+/// Get the directories the app keeps its own files in, none of them can be part of the library.
+pub fn app_owned_dirs() -> [PathBuf; 4] {
+    [
+        app_data_dir(),
+        app_cache_dir(),
+        app_config_dir(),
+        metadata_history_dir(),
+    ]
+}
+
 fn project_dirs() -> Option<ProjectDirs> {
     ProjectDirs::from("org", "muusik", "Muusik")
 }
@@ -0,0 +1,258 @@
+//! Lookups of release metadata and cover art in external databases.
+//!
+//! Every provider implements [`MetadataProvider`] and is enabled through the `[providers]`
+//! settings, endpoints pick one by its [`MetadataProvider::id`].
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::config;
+
+mod acoustid;
+mod musicbrainz;
+
+pub use acoustid::AcoustId;
+pub use musicbrainz::MusicBrainz;
+
+pub type Result<T, E = ProviderError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("Provider \"{0}\" doesn't exist or isn't enabled")]
+    UnknownProvider(String),
+    #[error("Nothing was found")]
+    NotFound,
+    #[error("Provider is rate limiting requests, try again later")]
+    RateLimited,
+    #[error("Request to provider failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Provider returned an unexpected response: {0}")]
+    InvalidResponse(String),
+}
+
+/// What to look for, at least one field has to be given.
+#[derive(Debug, Clone, Default, Deserialize, TS)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Title of a track, only used if no album is given.
+    pub title: Option<String>,
+}
+
+impl SearchQuery {
+    pub fn is_empty(&self) -> bool {
+        [&self.artist, &self.album, &self.title]
+            .into_iter()
+            .all(|field| field.as_deref().is_none_or(|value| value.trim().is_empty()))
+    }
+}
+
+/// A release matching a [`SearchQuery`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseSummary {
+    /// Id of the release within the provider.
+    pub id: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub date: Option<String>,
+    pub track_count: Option<u32>,
+    /// How well the release matches the query from 0 to 100, if the provider ranks results.
+    pub score: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Release {
+    pub id: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub date: Option<String>,
+    pub tracks: Vec<ReleaseTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseTrack {
+    pub disc_number: u32,
+    pub track_number: u32,
+    pub title: String,
+    pub artist: Option<String>,
+    pub length_ms: Option<u32>,
+    /// Id of the recording within the provider, if it tells recordings apart from tracks.
+    pub recording_id: Option<String>,
+}
+
+/// A recording that might be the song, found by its acoustic fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentifyCandidate {
+    /// Id of the recording on MusicBrainz.
+    pub recording_id: String,
+    /// How well the fingerprint matches from 0 to 100.
+    pub score: u8,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub length_ms: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CoverArt {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+#[async_trait]
+pub trait MetadataProvider: 'static + Send + Sync + Debug {
+    /// Identifies the provider in requests and settings, e.g. `musicbrainz`.
+    fn id(&self) -> &'static str;
+
+    /// Name of the provider shown in the UI.
+    fn name(&self) -> &'static str;
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<ReleaseSummary>>;
+
+    async fn release(&self, id: &str) -> Result<Release>;
+
+    /// Returns the front cover of the release, if the provider has one.
+    async fn cover_art(&self, release_id: &str) -> Result<Option<CoverArt>>;
+}
+
+/// The providers enabled in the settings.
+#[derive(Debug, Default)]
+pub struct ProviderRegistry {
+    providers: BTreeMap<&'static str, Arc<dyn MetadataProvider>>,
+    acoustid: Option<Arc<AcoustId>>,
+}
+
+impl ProviderRegistry {
+    pub fn new(settings: &config::Providers) -> Self {
+        let mut registry = Self::default();
+
+        if settings.musicbrainz.enabled {
+            registry.register(MusicBrainz::new(&settings.musicbrainz));
+        }
+
+        registry.acoustid = AcoustId::from_settings(&settings.acoustid).map(Arc::new);
+
+        registry
+    }
+
+    pub fn register<P: MetadataProvider>(&mut self, provider: P) {
+        self.providers.insert(provider.id(), Arc::new(provider));
+    }
+
+    pub fn get(&self, id: &str) -> Result<Arc<dyn MetadataProvider>> {
+        self.providers
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ProviderError::UnknownProvider(id.to_string()))
+    }
+
+    pub fn providers(&self) -> impl Iterator<Item = &Arc<dyn MetadataProvider>> {
+        self.providers.values()
+    }
+
+    /// Returns the AcoustID client, if songs can be identified by their fingerprint.
+    pub fn acoustid(&self) -> Option<Arc<AcoustId>> {
+        self.acoustid.clone()
+    }
+}
+
+/// Spaces out requests so a provider receives at most one request per interval.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until the next request may be sent.
+    pub async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        let now = Instant::now();
+
+        if *next > now {
+            tokio::time::sleep(*next - now).await;
+        }
+
+        *next = Instant::now().max(*next) + self.interval;
+    }
+}
+
+/// Joins the names of an artist credit the way they're displayed, e.g. `Artist feat. Guest`.
+fn join_credit<'a>(credit: impl IntoIterator<Item = (&'a str, &'a str)>) -> Option<String> {
+    let credit = credit
+        .into_iter()
+        .map(|(name, join_phrase)| format!("{name}{join_phrase}"))
+        .collect::<String>();
+
+    (!credit.is_empty()).then_some(credit)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(Duration::from_millis(50));
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.wait().await;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_registry_only_contains_enabled_providers() {
+        let mut settings = config::Providers::default();
+        assert!(ProviderRegistry::new(&settings).get("musicbrainz").is_ok());
+
+        settings.musicbrainz.enabled = false;
+        assert!(matches!(
+            ProviderRegistry::new(&settings).get("musicbrainz"),
+            Err(ProviderError::UnknownProvider(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_search_query() {
+        assert!(SearchQuery::default().is_empty());
+        assert!(
+            SearchQuery {
+                artist: Some(String::from(" ")),
+                ..Default::default()
+            }
+            .is_empty()
+        );
+        assert!(
+            !SearchQuery {
+                title: Some(String::from("Song")),
+                ..Default::default()
+            }
+            .is_empty()
+        );
+    }
+}
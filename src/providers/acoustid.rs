@@ -0,0 +1,295 @@
+//! Recordings matching an acoustic fingerprint, looked up on [AcoustID](https://acoustid.org/webservice)
+//! along with their MusicBrainz metadata.
+
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::{StatusCode, Url, header};
+use serde::Deserialize;
+
+use crate::{APP_NAME, APP_VERSION, config, fingerprint::Fingerprint};
+
+use super::*;
+
+const API_URL: &str = "https://api.acoustid.org/v2/lookup";
+
+/// AcoustID allows three requests per second from every client.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(334);
+
+/// Most candidates returned for a single fingerprint.
+const CANDIDATE_LIMIT: usize = 10;
+
+#[derive(Debug)]
+pub struct AcoustId {
+    client: reqwest::Client,
+    api_key: String,
+    limiter: RateLimiter,
+}
+
+impl AcoustId {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            limiter: RateLimiter::new(REQUEST_INTERVAL),
+        }
+    }
+
+    /// Creates the client if it's enabled and has an API key.
+    pub fn from_settings(settings: &config::AcoustId) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+
+        match &settings.api_key {
+            Some(api_key) if !api_key.trim().is_empty() => Some(Self::new(api_key.clone())),
+            _ => {
+                tracing::warn!("AcoustID is enabled without an API key, lookups are disabled");
+                None
+            }
+        }
+    }
+
+    /// Returns the recordings matching the fingerprint, best matches first.
+    pub async fn lookup(&self, fingerprint: &Fingerprint) -> Result<Vec<IdentifyCandidate>> {
+        self.limiter.wait().await;
+
+        let mut url =
+            Url::parse(API_URL).map_err(|err| ProviderError::InvalidResponse(err.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("format", "json")
+            .append_pair("client", &self.api_key)
+            .append_pair("meta", "recordings releasegroups")
+            .append_pair("duration", &fingerprint.duration_secs.to_string())
+            .append_pair("fingerprint", &fingerprint.fingerprint);
+
+        let response = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, format!("{APP_NAME}/{APP_VERSION}"))
+            .send()
+            .await?;
+
+        match response.status() {
+            // Errors such as an invalid API key are described in the body.
+            status if status.is_success() || status == StatusCode::BAD_REQUEST => {}
+            StatusCode::SERVICE_UNAVAILABLE | StatusCode::TOO_MANY_REQUESTS => {
+                return Err(ProviderError::RateLimited);
+            }
+            status => {
+                return Err(ProviderError::InvalidResponse(format!(
+                    "Unexpected status {status}"
+                )));
+            }
+        }
+
+        let body = response.text().await?;
+        let response: LookupResponse = serde_json::from_str(&body)
+            .map_err(|err| ProviderError::InvalidResponse(err.to_string()))?;
+
+        response.into_candidates()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    status: String,
+    #[serde(default)]
+    error: Option<LookupError>,
+    #[serde(default)]
+    results: Vec<LookupResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResult {
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+    title: Option<String>,
+    /// Length in seconds.
+    duration: Option<f64>,
+    #[serde(default)]
+    artists: Vec<RecordingArtist>,
+    #[serde(default)]
+    releasegroups: Vec<ReleaseGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordingArtist {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroup {
+    title: String,
+}
+
+impl LookupResponse {
+    /// Every titled recording of the results, keeping the best score of recordings matched by
+    /// more than one result.
+    fn into_candidates(self) -> Result<Vec<IdentifyCandidate>> {
+        if self.status != "ok" {
+            return Err(ProviderError::InvalidResponse(
+                self.error.map(|error| error.message).unwrap_or(self.status),
+            ));
+        }
+
+        let mut candidates: HashMap<String, IdentifyCandidate> = HashMap::new();
+
+        for result in self.results {
+            let score = (result.score.clamp(0.0, 1.0) * 100.0).round() as u8;
+
+            for recording in result.recordings {
+                let Some(title) = recording.title else {
+                    continue;
+                };
+
+                if candidates
+                    .get(&recording.id)
+                    .is_some_and(|candidate| candidate.score >= score)
+                {
+                    continue;
+                }
+
+                let artist = join_credit(
+                    recording
+                        .artists
+                        .iter()
+                        .map(|artist| (artist.name.as_str(), artist.joinphrase.as_str())),
+                );
+
+                candidates.insert(
+                    recording.id.clone(),
+                    IdentifyCandidate {
+                        recording_id: recording.id,
+                        score,
+                        title,
+                        artist,
+                        album: recording
+                            .releasegroups
+                            .into_iter()
+                            .next()
+                            .map(|group| group.title),
+                        length_ms: recording
+                            .duration
+                            .map(|duration| (duration * 1000.0) as u32),
+                    },
+                );
+            }
+        }
+
+        let mut candidates = candidates.into_values().collect::<Vec<_>>();
+        candidates.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.recording_id.cmp(&b.recording_id))
+        });
+        candidates.truncate(CANDIDATE_LIMIT);
+
+        Ok(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_lookup() {
+        let response: LookupResponse = serde_json::from_str(
+            r#"{
+                "status": "ok",
+                "results": [
+                    {
+                        "id": "a",
+                        "score": 0.5,
+                        "recordings": [
+                            { "id": "r1", "title": "Song", "duration": 181.4 },
+                            { "id": "r2" }
+                        ]
+                    },
+                    {
+                        "id": "b",
+                        "score": 0.97,
+                        "recordings": [
+                            {
+                                "id": "r1",
+                                "title": "Song",
+                                "artists": [
+                                    { "name": "Artist", "joinphrase": " feat. " },
+                                    { "name": "Guest" }
+                                ],
+                                "releasegroups": [{ "id": "g", "title": "Album" }]
+                            },
+                            { "id": "r3", "title": "Other Song" }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            response.into_candidates().unwrap(),
+            [
+                IdentifyCandidate {
+                    recording_id: String::from("r1"),
+                    score: 97,
+                    title: String::from("Song"),
+                    artist: Some(String::from("Artist feat. Guest")),
+                    album: Some(String::from("Album")),
+                    length_ms: None,
+                },
+                IdentifyCandidate {
+                    recording_id: String::from("r3"),
+                    score: 97,
+                    title: String::from("Other Song"),
+                    artist: None,
+                    album: None,
+                    length_ms: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_lookup_error() {
+        let response: LookupResponse = serde_json::from_str(
+            r#"{ "status": "error", "error": { "code": 4, "message": "invalid API key" } }"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            response.into_candidates(),
+            Err(ProviderError::InvalidResponse(message)) if message == "invalid API key"
+        ));
+    }
+
+    #[test]
+    fn test_client_requires_api_key() {
+        let mut settings = config::AcoustId {
+            enabled: true,
+            api_key: None,
+        };
+        assert!(AcoustId::from_settings(&settings).is_none());
+
+        settings.api_key = Some(String::from("key"));
+        assert!(AcoustId::from_settings(&settings).is_some());
+
+        settings.enabled = false;
+        assert!(AcoustId::from_settings(&settings).is_none());
+    }
+}
@@ -0,0 +1,505 @@
+//! [MusicBrainz](https://musicbrainz.org/doc/MusicBrainz_API) releases along with cover art from
+//! the [Cover Art Archive](https://coverartarchive.org/).
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{StatusCode, Url, header};
+use serde::Deserialize;
+
+use crate::{APP_NAME, APP_VERSION, config};
+
+use super::*;
+
+const API_URL: &str = "https://musicbrainz.org/ws/2/";
+const COVER_ART_URL: &str = "https://coverartarchive.org/";
+
+/// MusicBrainz allows a single request per second from every client.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+const SEARCH_LIMIT: u32 = 10;
+
+#[derive(Debug)]
+pub struct MusicBrainz {
+    client: reqwest::Client,
+    user_agent: String,
+    limiter: RateLimiter,
+}
+
+impl MusicBrainz {
+    pub fn new(settings: &config::MusicBrainz) -> Self {
+        // MusicBrainz asks clients to identify themselves with a way to contact them.
+        let user_agent = match &settings.contact {
+            Some(contact) => format!("{APP_NAME}/{APP_VERSION} ( {contact} )"),
+            None => format!("{APP_NAME}/{APP_VERSION}"),
+        };
+
+        Self {
+            client: reqwest::Client::new(),
+            user_agent,
+            limiter: RateLimiter::new(REQUEST_INTERVAL),
+        }
+    }
+
+    async fn get(&self, url: Url) -> Result<reqwest::Response> {
+        self.limiter.wait().await;
+
+        let response = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, &self.user_agent)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST => Err(ProviderError::NotFound),
+            StatusCode::SERVICE_UNAVAILABLE | StatusCode::TOO_MANY_REQUESTS => {
+                Err(ProviderError::RateLimited)
+            }
+            status => Err(ProviderError::InvalidResponse(format!(
+                "Unexpected status {status}"
+            ))),
+        }
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T> {
+        let mut url = api_url(path)?;
+        url.query_pairs_mut()
+            .extend_pairs(query)
+            .append_pair("fmt", "json");
+
+        let body = self.get(url).await?.text().await?;
+
+        serde_json::from_str(&body).map_err(|err| ProviderError::InvalidResponse(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for MusicBrainz {
+    fn id(&self) -> &'static str {
+        "musicbrainz"
+    }
+
+    fn name(&self) -> &'static str {
+        "MusicBrainz"
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<ReleaseSummary>> {
+        let limit = SEARCH_LIMIT.to_string();
+        let search = lucene_query(query);
+
+        if query.album.is_none() && query.title.is_some() {
+            let response: RecordingSearch = self
+                .get_json(
+                    "recording",
+                    &[("query", search.as_str()), ("limit", &limit)],
+                )
+                .await?;
+
+            Ok(response.into_summaries())
+        } else {
+            let response: ReleaseSearch = self
+                .get_json("release", &[("query", search.as_str()), ("limit", &limit)])
+                .await?;
+
+            Ok(response.into_summaries())
+        }
+    }
+
+    async fn release(&self, id: &str) -> Result<Release> {
+        let response: ReleaseResponse = self
+            .get_json(
+                &format!("release/{id}"),
+                &[("inc", "recordings+artist-credits")],
+            )
+            .await?;
+
+        Ok(response.into())
+    }
+
+    async fn cover_art(&self, release_id: &str) -> Result<Option<CoverArt>> {
+        let url = Url::parse(COVER_ART_URL)
+            .and_then(|url| url.join(&format!("release/{release_id}/front")))
+            .map_err(|err| ProviderError::InvalidResponse(err.to_string()))?;
+
+        let response = match self.get(url).await {
+            Ok(response) => response,
+            Err(ProviderError::NotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mime_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("image/jpeg")
+            .to_string();
+
+        Ok(Some(CoverArt {
+            mime_type,
+            data: response.bytes().await?.to_vec(),
+        }))
+    }
+}
+
+fn api_url(path: &str) -> Result<Url> {
+    Url::parse(API_URL)
+        .and_then(|url| url.join(path))
+        .map_err(|err| ProviderError::InvalidResponse(err.to_string()))
+}
+
+/// Builds a [Lucene](https://musicbrainz.org/doc/MusicBrainz_API/Search) query matching every
+/// given field as a phrase.
+fn lucene_query(query: &SearchQuery) -> String {
+    let title_field = if query.album.is_none() {
+        "recording"
+    } else {
+        // Release searches can't match track titles.
+        ""
+    };
+
+    [
+        ("artist", &query.artist),
+        ("release", &query.album),
+        (title_field, &query.title),
+    ]
+    .into_iter()
+    .filter(|(field, _)| !field.is_empty())
+    .filter_map(|(field, value)| {
+        let value = value.as_deref()?.trim();
+        (!value.is_empty()).then(|| {
+            format!(
+                "{field}:\"{}\"",
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+    })
+    .collect::<Vec<_>>()
+    .join(" AND ")
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+fn credit(credits: &[ArtistCredit]) -> Option<String> {
+    join_credit(
+        credits
+            .iter()
+            .map(|credit| (credit.name.as_str(), credit.joinphrase.as_str())),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseSearch {
+    releases: Vec<SearchedRelease>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SearchedRelease {
+    id: String,
+    title: String,
+    score: Option<u8>,
+    date: Option<String>,
+    track_count: Option<u32>,
+    #[serde(default)]
+    artist_credit: Vec<ArtistCredit>,
+}
+
+impl ReleaseSearch {
+    fn into_summaries(self) -> Vec<ReleaseSummary> {
+        self.releases
+            .into_iter()
+            .map(|release| ReleaseSummary {
+                artist: credit(&release.artist_credit),
+                id: release.id,
+                title: release.title,
+                date: release.date,
+                track_count: release.track_count,
+                score: release.score,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordingSearch {
+    recordings: Vec<SearchedRecording>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SearchedRecording {
+    score: Option<u8>,
+    #[serde(default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    releases: Vec<RecordingRelease>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RecordingRelease {
+    id: String,
+    title: String,
+    date: Option<String>,
+    track_count: Option<u32>,
+}
+
+impl RecordingSearch {
+    /// Lists every release the matching recordings appear on, keeping the first match of each.
+    fn into_summaries(self) -> Vec<ReleaseSummary> {
+        let mut summaries: Vec<ReleaseSummary> = Vec::new();
+
+        for recording in self.recordings {
+            let artist = credit(&recording.artist_credit);
+
+            for release in recording.releases {
+                if summaries.iter().any(|summary| summary.id == release.id) {
+                    continue;
+                }
+
+                summaries.push(ReleaseSummary {
+                    id: release.id,
+                    title: release.title,
+                    artist: artist.clone(),
+                    date: release.date,
+                    track_count: release.track_count,
+                    score: recording.score,
+                });
+            }
+        }
+
+        summaries
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ReleaseResponse {
+    id: String,
+    title: String,
+    date: Option<String>,
+    #[serde(default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Medium {
+    position: u32,
+    #[serde(default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Track {
+    position: u32,
+    title: String,
+    length: Option<u32>,
+    #[serde(default)]
+    artist_credit: Vec<ArtistCredit>,
+    recording: Option<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+}
+
+impl From<ReleaseResponse> for Release {
+    fn from(release: ReleaseResponse) -> Self {
+        Self {
+            artist: credit(&release.artist_credit),
+            tracks: release
+                .media
+                .into_iter()
+                .flat_map(|medium| {
+                    medium.tracks.into_iter().map(move |track| ReleaseTrack {
+                        disc_number: medium.position,
+                        track_number: track.position,
+                        artist: credit(&track.artist_credit),
+                        title: track.title,
+                        length_ms: track.length,
+                        recording_id: track.recording.map(|recording| recording.id),
+                    })
+                })
+                .collect(),
+            id: release.id,
+            title: release.title,
+            date: release.date,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_lucene_query() {
+        let query = SearchQuery {
+            artist: Some(String::from("Sigur Rós")),
+            album: Some(String::from("( )")),
+            title: Some(String::from("Untitled")),
+        };
+        assert_eq!(
+            lucene_query(&query),
+            r#"artist:"Sigur Rós" AND release:"( )""#
+        );
+
+        let query = SearchQuery {
+            artist: None,
+            album: None,
+            title: Some(String::from(r#"The "Quoted" \ Song"#)),
+        };
+        assert_eq!(
+            lucene_query(&query),
+            r#"recording:"The \"Quoted\" \\ Song""#
+        );
+    }
+
+    #[test]
+    fn test_parse_release_search() {
+        let response: ReleaseSearch = serde_json::from_str(
+            r#"{
+                "created": "2026-10-15T12:00:00.000Z",
+                "count": 1,
+                "releases": [{
+                    "id": "b84ee12a-09ef-421b-82de-0441a926375b",
+                    "score": 100,
+                    "title": "Some Album",
+                    "date": "1973-03-01",
+                    "track-count": 10,
+                    "artist-credit": [
+                        { "name": "Artist", "joinphrase": " feat. " },
+                        { "name": "Guest" }
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            response.into_summaries(),
+            [ReleaseSummary {
+                id: String::from("b84ee12a-09ef-421b-82de-0441a926375b"),
+                title: String::from("Some Album"),
+                artist: Some(String::from("Artist feat. Guest")),
+                date: Some(String::from("1973-03-01")),
+                track_count: Some(10),
+                score: Some(100),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_recording_search() {
+        let response: RecordingSearch = serde_json::from_str(
+            r#"{
+                "recordings": [
+                    {
+                        "id": "recording-1",
+                        "score": 98,
+                        "title": "Song",
+                        "artist-credit": [{ "name": "Artist" }],
+                        "releases": [
+                            { "id": "release-1", "title": "Album" },
+                            { "id": "release-2", "title": "Best Of", "date": "2001" }
+                        ]
+                    },
+                    {
+                        "id": "recording-2",
+                        "score": 80,
+                        "title": "Song (live)",
+                        "releases": [{ "id": "release-2", "title": "Best Of" }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let summaries = response.into_summaries();
+
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|summary| (summary.id.as_str(), summary.score))
+                .collect::<Vec<_>>(),
+            [("release-1", Some(98)), ("release-2", Some(98))]
+        );
+        assert_eq!(summaries[1].date.as_deref(), Some("2001"));
+    }
+
+    #[test]
+    fn test_parse_release() {
+        let response: ReleaseResponse = serde_json::from_str(
+            r#"{
+                "id": "release-1",
+                "title": "Album",
+                "artist-credit": [{ "name": "Artist" }],
+                "media": [
+                    {
+                        "position": 1,
+                        "tracks": [{
+                            "position": 1,
+                            "number": "A1",
+                            "title": "First",
+                            "length": 240200,
+                            "recording": { "id": "recording-1" }
+                        }]
+                    },
+                    {
+                        "position": 2,
+                        "tracks": [{
+                            "position": 1,
+                            "number": "B1",
+                            "title": "Second",
+                            "length": null,
+                            "artist-credit": [{ "name": "Guest" }]
+                        }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let release = Release::from(response);
+
+        assert_eq!(release.artist.as_deref(), Some("Artist"));
+        assert_eq!(
+            release.tracks,
+            [
+                ReleaseTrack {
+                    disc_number: 1,
+                    track_number: 1,
+                    title: String::from("First"),
+                    artist: None,
+                    length_ms: Some(240_200),
+                    recording_id: Some(String::from("recording-1")),
+                },
+                ReleaseTrack {
+                    disc_number: 2,
+                    track_number: 1,
+                    title: String::from("Second"),
+                    artist: Some(String::from("Guest")),
+                    length_ms: None,
+                    recording_id: None,
+                },
+            ]
+        );
+    }
+}
@@ -1,72 +1,350 @@
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError, RwLock};
 
-use axum::{extract::FromRef, response::sse::Event};
-use tokio::sync::broadcast::Sender;
+use axum::extract::FromRef;
 
-use crate::state::job::{Job, JobRegistry};
+use crate::state::job::{Job, JobParameters, JobRegistry};
 
-use super::{config::Settings, jobs::ScanSongs};
+use super::{
+    config::Settings,
+    events::EventBus,
+    jobs::{
+        AlbumHygiene, AnalyzeLoudness, BackfillAudioProperties, BundlePlaylist, CheckIntegrity,
+        IdentifySongs, InferMetadata, MergeGenres, PruneMetadataHistory, RebuildIndexes, ScanSongs,
+    },
+    providers::ProviderRegistry,
+};
 
+mod cover_art_cache;
+mod directory_cache;
+mod directory_sizes;
+pub mod disk_space;
+mod file_types;
 mod fs;
 pub mod job;
+mod maintenance;
+mod watcher;
 
+pub use cover_art_cache::*;
+pub use directory_cache::*;
+pub use directory_sizes::*;
+pub use file_types::*;
 pub use fs::*;
+pub use maintenance::*;
+pub use watcher::*;
 
 pub type JobManager = Arc<job::manager::JobManager>;
 pub type Pool = sqlx::SqlitePool;
 pub type FileOperationManager = Arc<OperationManager>;
+pub type SharedDirectoryWatcher = Arc<DirectoryWatcher>;
+pub type SharedCoverArtCache = Arc<CoverArtCache>;
+pub type SharedDirectoryCache = Arc<DirectoryCache>;
+pub type SharedDirectorySizes = Arc<DirectorySizes>;
+pub type SharedProviderRegistry = Arc<ProviderRegistry>;
+pub type SharedSongFileTypes = Arc<SongFileTypes>;
+pub type SharedMaintenance = Arc<Maintenance>;
+/// Permits for transcoding streamed songs, one per song transcoded at the same time.
+pub type TranscodePermits = Arc<tokio::sync::Semaphore>;
+/// The settings, which can be changed while the server is running.
+pub type SharedSettings = Arc<RwLock<Settings>>;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub settings: Settings,
+    pub settings: SharedSettings,
     pub job_manager: JobManager,
-    pub event_sender: Sender<Event>,
+    pub events: EventBus,
     pub file_operation_manager: FileOperationManager,
+    pub directory_watcher: SharedDirectoryWatcher,
+    pub cover_art_cache: SharedCoverArtCache,
+    pub directory_cache: SharedDirectoryCache,
+    pub directory_sizes: SharedDirectorySizes,
+    pub providers: SharedProviderRegistry,
+    pub song_file_types: SharedSongFileTypes,
+    pub maintenance: SharedMaintenance,
+    pub transcodes: TranscodePermits,
     pub pool: Pool,
 }
 
 impl AppState {
     pub fn new(db: Pool, settings: Settings) -> Self {
-        let (tx, _) = tokio::sync::broadcast::channel(1024);
+        let shared_settings = Arc::new(RwLock::new(settings.clone()));
+        let events = EventBus::new(db.clone(), settings.events.retention_days);
 
-        let file_operation_manager = OperationManager::new();
+        let file_operation_manager = Arc::new(OperationManager::new());
         let mut rx = file_operation_manager.events();
 
-        let tx_clone = tx.clone();
+        let bus = events.clone();
         tokio::spawn(async move {
             while let Ok(item) = rx.recv().await {
-                let _ = tx_clone.send(Event::from(super::events::FileOperationManagerEvent::from(
-                    item,
-                )));
+                bus.publish(super::events::FileOperationManagerEvent::from(item));
             }
         });
 
-        let job_manager = job::manager::JobManager::new(setup_jobs(&db));
+        let cover_art_cache = Arc::new(CoverArtCache::new(
+            super::paths::cover_art_cache_dir(),
+            settings.cache.cover_art_size_limit_mb * 1024 * 1024,
+        ));
+
+        let providers = Arc::new(ProviderRegistry::new(&settings.providers));
+
+        // The settings are validated when loaded, whatever doesn't parse here was set in code.
+        let song_file_types = Arc::new(SongFileTypes::new(
+            super::config::parse_file_types(&settings.scan.file_types)
+                .expect("Invalid song file types"),
+        ));
+
+        let job_manager = Arc::new(job::manager::JobManager::with_workers(
+            setup_jobs(
+                &db,
+                &settings,
+                &shared_settings,
+                &cover_art_cache,
+                &providers,
+                &song_file_types,
+            ),
+            settings.jobs.workers,
+        ));
         let mut rx = job_manager.events();
-        let tx_clone = tx.clone();
+        let bus = events.clone();
+        tokio::spawn(async move {
+            while let Ok(item) = rx.recv().await {
+                bus.publish(super::events::JobManagerEvent::from(item));
+            }
+        });
+
+        let directory_watcher = Arc::new(
+            DirectoryWatcher::new(
+                db.clone(),
+                shared_settings.clone(),
+                cover_art_cache.clone(),
+                song_file_types.clone(),
+            )
+            .expect("Failed to create directory watcher"),
+        );
+
+        let mut rx = directory_watcher.events();
+        let bus = events.clone();
         tokio::spawn(async move {
             while let Ok(item) = rx.recv().await {
-                let _ = tx_clone.send(Event::from(super::events::JobManagerEvent::from(item)));
+                bus.publish(super::events::DirectoryWatcherEvent::from(item));
+            }
+        });
+
+        let directory_cache = SharedDirectoryCache::default();
+
+        let watcher = directory_watcher.clone();
+        let cache = directory_cache.clone();
+        let pool = db.clone();
+        tokio::spawn(async move {
+            if let Err(err) = cache.reload(&pool).await {
+                tracing::error!("Failed to load the directory cache: {err}");
+            }
+
+            let directories = match pool.acquire().await {
+                Ok(mut connection) => super::db::directories::get_directories(&mut connection)
+                    .await
+                    .unwrap_or_default(),
+                Err(err) => {
+                    tracing::error!("Failed to load directories to watch: {err}");
+                    return;
+                }
+            };
+
+            for directory in directories {
+                if let Err(err) = watcher.watch(std::path::Path::new(&directory.path)).await {
+                    tracing::warn!("Failed to watch \"{}\": {err}", directory.path);
+                }
             }
         });
 
+        let maintenance = Maintenance::new(
+            db.clone(),
+            job_manager.clone(),
+            file_operation_manager.clone(),
+            events.clone(),
+        );
+
+        job::scheduler::spawn(db.clone(), job_manager.clone());
+        disk_space::spawn(db.clone(), shared_settings.clone(), events.clone());
+
+        if settings.jobs.check_integrity_on_startup {
+            let manager = job_manager.clone();
+            tokio::spawn(async move {
+                if let Err(err) = manager
+                    .queue("check-integrity", JobParameters::default(), true, false)
+                    .await
+                {
+                    tracing::error!("Failed to queue the integrity check: {err}");
+                }
+            });
+        }
+
         Self {
             pool: db,
-            settings,
-            event_sender: tx,
-            job_manager: Arc::new(job_manager),
-            file_operation_manager: Arc::new(file_operation_manager),
+            settings: shared_settings,
+            events,
+            job_manager,
+            file_operation_manager,
+            directory_watcher,
+            cover_art_cache,
+            directory_cache,
+            directory_sizes: SharedDirectorySizes::default(),
+            providers,
+            song_file_types,
+            maintenance,
+            transcodes: Arc::new(tokio::sync::Semaphore::new(
+                settings.streaming.max_transcodes,
+            )),
         }
     }
+    /// Cancels the running jobs and file operations, waiting up to
+    /// [`Jobs::shutdown_timeout_secs`](crate::config::Jobs::shutdown_timeout_secs) for them to
+    /// stop. Returns whether everything stopped in time.
+    pub async fn shutdown(&self) -> bool {
+        let timeout = std::time::Duration::from_secs(
+            self.settings
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .jobs
+                .shutdown_timeout_secs,
+        );
+
+        let (jobs, operations) = tokio::join!(
+            self.job_manager.shutdown(timeout),
+            self.file_operation_manager.shutdown(timeout)
+        );
+
+        jobs && operations
+    }
 }
 
-fn setup_jobs(pool: &sqlx::Pool<sqlx::Sqlite>) -> JobRegistry {
+fn setup_jobs(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &Settings,
+    shared_settings: &SharedSettings,
+    cover_art_cache: &SharedCoverArtCache,
+    providers: &SharedProviderRegistry,
+    song_file_types: &SharedSongFileTypes,
+) -> JobRegistry {
     let mut registry = JobRegistry::default();
 
     registry
         .register_job(
             "scan-songs",
-            Job::new(ScanSongs::job_info(), ScanSongs::new(pool.clone())),
+            Job::new(
+                ScanSongs::job_info(),
+                ScanSongs::new(
+                    pool.clone(),
+                    cover_art_cache.clone(),
+                    song_file_types.clone(),
+                    shared_settings.clone(),
+                ),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "album-hygiene",
+            Job::new(AlbumHygiene::job_info(), AlbumHygiene::new(pool.clone())),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "analyze-loudness",
+            Job::new(
+                AnalyzeLoudness::job_info(),
+                AnalyzeLoudness::new(pool.clone()),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "backfill-audio-properties",
+            Job::new(
+                BackfillAudioProperties::job_info(),
+                BackfillAudioProperties::new(pool.clone()),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "bundle-playlist",
+            Job::new(
+                BundlePlaylist::job_info(),
+                BundlePlaylist::new(
+                    pool.clone(),
+                    settings.bundles.clone(),
+                    super::paths::bundles_dir(),
+                ),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "check-integrity",
+            Job::new(
+                CheckIntegrity::job_info(),
+                CheckIntegrity::new(pool.clone()),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "identify-songs",
+            Job::new(
+                IdentifySongs::job_info(),
+                IdentifySongs::new(pool.clone(), providers.acoustid()),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "infer-metadata",
+            Job::new(
+                InferMetadata::job_info(),
+                InferMetadata::new(pool.clone(), shared_settings.clone()),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "merge-genres",
+            Job::new(MergeGenres::job_info(), MergeGenres::new(pool.clone())),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "prune-metadata-history",
+            Job::new(
+                PruneMetadataHistory::job_info(),
+                PruneMetadataHistory::new(
+                    shared_settings.clone(),
+                    super::paths::metadata_history_dir(),
+                ),
+            ),
+        )
+        .expect("Failed to register job");
+
+    registry
+        .register_job(
+            "rebuild-indexes",
+            Job::new(
+                RebuildIndexes::job_info(),
+                RebuildIndexes::new(
+                    pool.clone(),
+                    cover_art_cache.clone(),
+                    super::paths::bundles_dir(),
+                    settings.bundles.retention(),
+                ),
+            ),
         )
         .expect("Failed to register job");
 
@@ -79,9 +357,9 @@ impl FromRef<AppState> for JobManager {
     }
 }
 
-impl FromRef<AppState> for Sender<Event> {
+impl FromRef<AppState> for EventBus {
     fn from_ref(state: &AppState) -> Self {
-        state.event_sender.clone()
+        state.events.clone()
     }
 }
 
@@ -91,14 +369,73 @@ impl FromRef<AppState> for FileOperationManager {
     }
 }
 
+impl FromRef<AppState> for SharedDirectoryWatcher {
+    fn from_ref(state: &AppState) -> Self {
+        state.directory_watcher.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedCoverArtCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.cover_art_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedDirectoryCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.directory_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for TranscodePermits {
+    fn from_ref(state: &AppState) -> Self {
+        state.transcodes.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedDirectorySizes {
+    fn from_ref(state: &AppState) -> Self {
+        state.directory_sizes.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedProviderRegistry {
+    fn from_ref(state: &AppState) -> Self {
+        state.providers.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedSongFileTypes {
+    fn from_ref(state: &AppState) -> Self {
+        state.song_file_types.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedMaintenance {
+    fn from_ref(state: &AppState) -> Self {
+        state.maintenance.clone()
+    }
+}
+
 impl FromRef<AppState> for Pool {
     fn from_ref(state: &AppState) -> Self {
         state.pool.clone()
     }
 }
 
-impl FromRef<AppState> for Settings {
+impl FromRef<AppState> for SharedSettings {
     fn from_ref(state: &AppState) -> Self {
         state.settings.clone()
     }
 }
+
+/// The current settings, later changes don't affect the returned copy.
+impl FromRef<AppState> for Settings {
+    fn from_ref(state: &AppState) -> Self {
+        state
+            .settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
@@ -0,0 +1,390 @@
+//! Disk cache for album cover art that has already been converted to the requested format.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+
+use tokio::task::spawn_blocking;
+
+#[derive(Debug)]
+pub struct CoverArtCache {
+    directory: PathBuf,
+    /// The total size in bytes the cache is trimmed down to after an entry is written.
+    max_size: AtomicU64,
+    /// Total size of the entries in bytes, `None` until the entries are counted by the next write.
+    ///
+    /// It's only an estimate, entries written while the cache is being trimmed are counted again
+    /// the next time it's trimmed.
+    size: Mutex<Option<u64>>,
+    /// Locks held while an entry is being created, so the same entry is only created once.
+    pending: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl CoverArtCache {
+    pub fn new(directory: PathBuf, max_size: u64) -> Self {
+        Self {
+            directory,
+            max_size: AtomicU64::new(max_size),
+            size: Mutex::default(),
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Returns the cached entry of the album, calling `create` to fill it on a miss.
+    ///
+    /// `variant` identifies the cover type and format, e.g. `front-0.jpg`. Failing to read or
+    /// write the cache is logged and treated as a miss.
+    pub async fn get_or_create<F, Fut, E>(
+        &self,
+        album: &str,
+        variant: &str,
+        create: F,
+    ) -> Result<Option<Vec<u8>>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<Vec<u8>>, E>>,
+    {
+        let path = self.album_directory(album).join(variant);
+        let lock = self
+            .pending
+            .lock()
+            .expect("Cover art cache lock poisoned")
+            .entry(path.clone())
+            .or_default()
+            .clone();
+
+        let guard = lock.lock().await;
+        let result = match tokio::fs::read(&path).await {
+            Ok(data) => {
+                if let Err(err) = touch(&path) {
+                    tracing::warn!("Failed to update cover art cache entry {path:?}: {err}");
+                }
+
+                Ok(Some(data))
+            }
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to read cover art cache entry {path:?}: {err}");
+                }
+
+                let data = create().await;
+                if let Ok(Some(data)) = &data
+                    && let Err(err) = self.write(&path, data).await
+                {
+                    tracing::warn!("Failed to write cover art cache entry {path:?}: {err}");
+                }
+
+                data
+            }
+        };
+        drop(guard);
+
+        let mut pending = self.pending.lock().expect("Cover art cache lock poisoned");
+        if Arc::strong_count(&lock) == 2 {
+            pending.remove(&path);
+        }
+
+        result
+    }
+
+    /// Removes every cached entry of the album.
+    ///
+    /// Entries of the album being created are waited for, so they can't be written back after
+    /// they were removed.
+    pub async fn invalidate_album(&self, album: &str) {
+        let directory = self.album_directory(album);
+
+        let mut locks = self
+            .pending
+            .lock()
+            .expect("Cover art cache lock poisoned")
+            .iter()
+            .filter(|(path, _)| path.starts_with(&directory))
+            .map(|(path, lock)| (path.clone(), lock.clone()))
+            .collect::<Vec<_>>();
+        // Locked in the same order by every invalidation of the album.
+        locks.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut guards = Vec::with_capacity(locks.len());
+        for (_, lock) in &locks {
+            guards.push(lock.lock().await);
+        }
+
+        match tokio::fs::remove_dir_all(&directory).await {
+            Ok(()) => tracing::debug!("Invalidated cover art cache of \"{album}\""),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                tracing::warn!("Failed to invalidate cover art cache of \"{album}\": {err}")
+            }
+        }
+
+        // The entries are counted again instead of walking the album to know what was removed.
+        *self.size.lock().expect("Cover art cache lock poisoned") = None;
+        drop(guards);
+
+        let mut pending = self.pending.lock().expect("Cover art cache lock poisoned");
+        for (path, lock) in locks {
+            if Arc::strong_count(&lock) == 2 {
+                pending.remove(&path);
+            }
+        }
+    }
+
+    /// Changes the size the cache is trimmed down to, applied the next time an entry is written.
+    pub fn set_max_size(&self, max_size: u64) {
+        self.max_size.store(max_size, Ordering::Relaxed);
+    }
+
+    /// Removes every cached entry, they are recreated the next time they are requested.
+    pub async fn clear(&self) -> std::io::Result<()> {
+        match tokio::fs::remove_dir_all(&self.directory).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+        *self.size.lock().expect("Cover art cache lock poisoned") = Some(0);
+        tracing::debug!("Cleared cover art cache");
+
+        Ok(())
+    }
+
+    fn album_directory(&self, album: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        album.hash(&mut hasher);
+
+        self.directory.join(format!("{:016x}", hasher.finish()))
+    }
+
+    async fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let temporary = path.with_extension("tmp");
+        tokio::fs::write(&temporary, data).await?;
+        tokio::fs::rename(&temporary, path).await?;
+
+        let max_size = self.max_size.load(Ordering::Relaxed);
+        let over_limit = {
+            let mut size = self.size.lock().expect("Cover art cache lock poisoned");
+            if let Some(size) = size.as_mut() {
+                *size += data.len() as u64;
+            }
+
+            size.is_none_or(|size| size > max_size)
+        };
+
+        // Entries are only walked when they have to be, to count them or to find the least
+        // recently used ones.
+        if over_limit {
+            let directory = self.directory.clone();
+            let size = spawn_blocking(move || evict(&directory, max_size))
+                .await
+                .map_err(std::io::Error::other)??;
+
+            *self.size.lock().expect("Cover art cache lock poisoned") = Some(size);
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks the entry as recently used.
+fn touch(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Removes the least recently used entries until the cache is at most `max_size` bytes, returning
+/// the size of the entries left.
+///
+/// Entries and albums removed while the cache is walked, by an invalidation, are skipped.
+fn evict(directory: &Path, max_size: u64) -> std::io::Result<u64> {
+    let mut entries = Vec::new();
+
+    for album in std::fs::read_dir(directory)? {
+        let album = album?;
+        if !album.file_type()?.is_dir() {
+            continue;
+        }
+
+        let album_entries = match std::fs::read_dir(album.path()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        for entry in album_entries {
+            let entry = entry?;
+            if let Ok(metadata) = entry.metadata()
+                && metadata.is_file()
+            {
+                entries.push((
+                    entry.path(),
+                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    metadata.len(),
+                ));
+            }
+        }
+    }
+
+    let mut size = entries.iter().map(|(_, _, len)| len).sum::<u64>();
+    if size <= max_size {
+        return Ok(size);
+    }
+
+    entries.sort_by_key(|(_, modified, _)| *modified);
+
+    for (path, _, len) in entries {
+        if size <= max_size {
+            break;
+        }
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        size -= len;
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use test_log::test;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_entry_is_created_once() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Arc::new(CoverArtCache::new(directory.path().to_path_buf(), u64::MAX));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let requests = (0..8).map(|_| {
+            let cache = cache.clone();
+            let calls = calls.clone();
+
+            tokio::spawn(async move {
+                cache
+                    .get_or_create("Album", "front-0.jpg", || async {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        Ok::<_, ()>(Some(vec![1, 2, 3]))
+                    })
+                    .await
+            })
+        });
+
+        for request in requests.collect::<Vec<_>>() {
+            assert_eq!(request.await.unwrap(), Ok(Some(vec![1, 2, 3])));
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        cache.invalidate_album("Album").await;
+        cache
+            .get_or_create("Album", "front-0.jpg", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>(Some(vec![1, 2, 3]))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_least_recently_used_entries_are_evicted() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = CoverArtCache::new(directory.path().to_path_buf(), 8);
+
+        for album in ["First", "Second", "Third"] {
+            cache
+                .get_or_create(album, "front-0.jpg", || async {
+                    Ok::<_, ()>(Some(vec![0; 4]))
+                })
+                .await
+                .unwrap();
+
+            // Modification times aren't always precise enough to tell entries apart otherwise.
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        assert!(!cache.album_directory("First").join("front-0.jpg").exists());
+        assert!(cache.album_directory("Second").join("front-0.jpg").exists());
+        assert!(cache.album_directory("Third").join("front-0.jpg").exists());
+    }
+
+    #[test(tokio::test)]
+    async fn test_invalidation_waits_for_pending_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Arc::new(CoverArtCache::new(directory.path().to_path_buf(), u64::MAX));
+
+        let request = tokio::spawn({
+            let cache = cache.clone();
+
+            async move {
+                cache
+                    .get_or_create("Album", "front-0.jpg", || async {
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        Ok::<_, ()>(Some(vec![1, 2, 3]))
+                    })
+                    .await
+            }
+        });
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        cache.invalidate_album("Album").await;
+
+        // The stale entry was written before the album was invalidated, not after.
+        assert_eq!(request.await.unwrap(), Ok(Some(vec![1, 2, 3])));
+        assert!(!cache.album_directory("Album").join("front-0.jpg").exists());
+        assert!(cache.pending.lock().unwrap().is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_size_is_counted_after_invalidation() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = CoverArtCache::new(directory.path().to_path_buf(), 8);
+        let create = || async { Ok::<_, ()>(Some(vec![0; 4])) };
+
+        cache
+            .get_or_create("First", "front-0.jpg", create)
+            .await
+            .unwrap();
+        assert_eq!(*cache.size.lock().unwrap(), Some(4));
+        cache
+            .get_or_create("Second", "front-0.jpg", create)
+            .await
+            .unwrap();
+        assert_eq!(*cache.size.lock().unwrap(), Some(8));
+
+        cache.invalidate_album("First").await;
+        assert_eq!(*cache.size.lock().unwrap(), None);
+
+        cache
+            .get_or_create("Third", "front-0.jpg", create)
+            .await
+            .unwrap();
+        assert_eq!(*cache.size.lock().unwrap(), Some(8));
+        assert!(cache.album_directory("Second").join("front-0.jpg").exists());
+        assert!(cache.album_directory("Third").join("front-0.jpg").exists());
+    }
+}
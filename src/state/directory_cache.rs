@@ -0,0 +1,49 @@
+//! The library directories kept in memory, so responses can include a song's directory without
+//! querying it for every song.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use tokio::sync::Mutex;
+
+use crate::db::{DatabaseError, Directory, directories};
+
+use super::Pool;
+
+#[derive(Debug, Default)]
+pub struct DirectoryCache {
+    directories: RwLock<Arc<HashMap<String, Directory>>>,
+    /// Held while reloading, so a slower reload can't replace the result of a later one.
+    reloading: Mutex<()>,
+}
+
+impl DirectoryCache {
+    /// Returns the directories by name, later changes don't affect the returned map.
+    pub fn get(&self) -> Arc<HashMap<String, Directory>> {
+        self.directories
+            .read()
+            .expect("Directory cache lock poisoned")
+            .clone()
+    }
+
+    /// Reads the directories from the database again, which has to be done whenever they change.
+    pub async fn reload(&self, pool: &Pool) -> Result<(), DatabaseError> {
+        let _reloading = self.reloading.lock().await;
+
+        let mut connection = pool.acquire().await?;
+        let directories = directories::get_directories(&mut connection)
+            .await?
+            .into_iter()
+            .map(|directory| (directory.name.clone(), directory))
+            .collect();
+
+        *self
+            .directories
+            .write()
+            .expect("Directory cache lock poisoned") = Arc::new(directories);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Sizes of the library directories, computed in the background and kept in memory since walking
+//! a large library can take a long time, especially on a network drive.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+};
+
+use fs_extra::dir::get_size;
+use time::{Duration, OffsetDateTime};
+use tokio::task::spawn_blocking;
+
+/// How long a size is used for before it's computed again.
+const MAX_AGE: Duration = Duration::minutes(10);
+
+/// The size of a directory as of when it was computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectorySize {
+    pub bytes: u64,
+    pub computed_at: OffsetDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct DirectorySizes {
+    sizes: RwLock<HashMap<PathBuf, DirectorySize>>,
+    /// Directories whose size is being computed in the background, so each is only walked by one
+    /// background task at a time.
+    computing: Mutex<HashSet<PathBuf>>,
+}
+
+impl DirectorySizes {
+    /// Returns the last computed size of the directory, computing it again in the background if
+    /// there's none yet or it's outdated.
+    pub fn get(self: &Arc<Self>, path: &Path) -> Option<DirectorySize> {
+        let size = self.cached(path);
+
+        let outdated =
+            size.is_none_or(|size| OffsetDateTime::now_utc() - size.computed_at > MAX_AGE);
+        if outdated
+            && self
+                .computing
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.to_path_buf())
+        {
+            let sizes = self.clone();
+            let path = path.to_path_buf();
+            tokio::spawn(async move {
+                sizes.refresh(&path).await;
+                sizes
+                    .computing
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(&path);
+            });
+        }
+
+        size
+    }
+
+    /// Computes the size of the directory now, `None` if it can't be read.
+    pub async fn refresh(&self, path: &Path) -> Option<DirectorySize> {
+        let directory = path.to_path_buf();
+        let bytes = match spawn_blocking(move || get_size(directory)).await {
+            Ok(Ok(bytes)) => bytes,
+            Ok(Err(err)) => {
+                tracing::warn!(
+                    "Failed to compute the size of \"{}\": {err}",
+                    path.display()
+                );
+                return None;
+            }
+            Err(err) => {
+                tracing::error!("Failed to join directory size task: {err}");
+                return None;
+            }
+        };
+
+        let size = DirectorySize {
+            bytes,
+            computed_at: OffsetDateTime::now_utc(),
+        };
+
+        self.sizes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.to_path_buf(), size);
+
+        Some(size)
+    }
+
+    fn cached(&self, path: &Path) -> Option<DirectorySize> {
+        self.sizes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .copied()
+    }
+
+    /// Forgets the size of a directory that was removed from the library.
+    pub fn remove(&self, path: &Path) {
+        self.sizes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_directory_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("song.mp3"), [0; 100]).unwrap();
+
+        let sizes = Arc::new(DirectorySizes::default());
+        let size = sizes.refresh(dir.path()).await.unwrap();
+        assert_eq!(size.bytes, 100);
+        assert_eq!(sizes.get(dir.path()), Some(size));
+
+        // Outdated sizes are still returned while they're computed again.
+        std::fs::write(dir.path().join("other.mp3"), [0; 50]).unwrap();
+        sizes
+            .sizes
+            .write()
+            .unwrap()
+            .get_mut(dir.path())
+            .unwrap()
+            .computed_at -= MAX_AGE * 2;
+        assert_eq!(sizes.get(dir.path()).unwrap().bytes, 100);
+
+        sizes.remove(dir.path());
+        assert_eq!(sizes.refresh(dir.path()).await.unwrap().bytes, 150);
+    }
+}
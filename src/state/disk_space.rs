@@ -0,0 +1,216 @@
+//! Warns when the disks library directories are stored on run low on free space.
+//!
+//! A disk is only reported again after its free space went back above the thresholds with some
+//! margin, so hovering around a threshold doesn't send a warning on every check.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::PoisonError,
+    time::Duration,
+};
+
+use serde::Serialize;
+use sysinfo::{Disk, Disks};
+
+use crate::{
+    config::Storage,
+    db::directories,
+    events::{AppEvent, AppEventKind, EventBus},
+};
+
+use super::{Pool, SharedSettings};
+
+/// How much the free space has to exceed the thresholds by before a low disk counts as
+/// recovered.
+const RECOVERY_FACTOR: f64 = 1.1;
+
+/// How long the webhook gets to accept a warning.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Body posted to the webhook when a directory's disk runs low on space.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LowSpaceWarning {
+    pub directory: String,
+    pub path: String,
+    pub free_space: u64,
+    pub total_space: u64,
+    pub message: String,
+}
+
+/// Returns the disk the path is stored on, which is the one with the longest mount point
+/// containing it.
+pub fn find_disk<'d>(disks: &'d Disks, path: &Path) -> Option<&'d Disk> {
+    disks
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+}
+
+/// Whether a disk is low on space after a check, given whether it was on the previous one.
+pub fn is_still_low_space(
+    storage: &Storage,
+    was_low: bool,
+    free_space: u64,
+    total_space: u64,
+) -> bool {
+    if was_low {
+        storage.is_below(free_space, total_space, RECOVERY_FACTOR)
+    } else {
+        storage.is_low_space(free_space, total_space)
+    }
+}
+
+/// Spawns the task checking the free space of the library disks for as long as the app runs.
+pub fn spawn(pool: Pool, settings: SharedSettings, events: EventBus) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut low = HashSet::new();
+
+        loop {
+            let storage = settings
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .storage
+                .clone();
+
+            let warnings = match check_directories(&pool, &storage, &mut low).await {
+                Ok(warnings) => warnings,
+                Err(err) => {
+                    tracing::error!("Failed to check the free space of the library: {err}");
+                    Vec::new()
+                }
+            };
+
+            for warning in warnings {
+                tracing::warn!("{}", warning.message);
+                events.publish(AppEvent::new(AppEventKind::Warning, &warning.message));
+
+                if let Some(url) = &storage.webhook_url {
+                    send_webhook(&client, url, &warning).await;
+                }
+            }
+
+            let minutes = u64::from(storage.check_interval_minutes.max(1));
+            tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+        }
+    });
+}
+
+/// Checks every directory, returning warnings for the ones whose disk just ran low on space.
+///
+/// `low` holds the paths of the directories that were low on space on the previous check.
+async fn check_directories(
+    pool: &Pool,
+    storage: &Storage,
+    low: &mut HashSet<PathBuf>,
+) -> Result<Vec<LowSpaceWarning>, crate::db::DatabaseError> {
+    let mut connection = pool.acquire().await?;
+    let directories = directories::get_directories(&mut connection).await?;
+    drop(connection);
+
+    let disks = Disks::new_with_refreshed_list();
+    let mut warnings = Vec::new();
+
+    low.retain(|path| {
+        directories
+            .iter()
+            .any(|directory| Path::new(&directory.path) == path)
+    });
+
+    for directory in directories {
+        let path = PathBuf::from(&directory.path);
+        let Some(disk) = find_disk(&disks, &path) else {
+            continue;
+        };
+
+        let was_low = low.contains(&path);
+        let is_low =
+            is_still_low_space(storage, was_low, disk.available_space(), disk.total_space());
+
+        match (was_low, is_low) {
+            (false, true) => {
+                let name = directory
+                    .display_name
+                    .unwrap_or_else(|| directory.name.clone());
+                warnings.push(LowSpaceWarning {
+                    message: format!(
+                        "\"{name}\" is low on space, {} MB free of {} MB",
+                        disk.available_space() / 1024 / 1024,
+                        disk.total_space() / 1024 / 1024
+                    ),
+                    directory: directory.name,
+                    path: directory.path,
+                    free_space: disk.available_space(),
+                    total_space: disk.total_space(),
+                });
+                low.insert(path);
+            }
+            (true, false) => {
+                tracing::info!("\"{}\" has enough free space again", directory.path);
+                low.remove(&path);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(warnings)
+}
+
+async fn send_webhook(client: &reqwest::Client, url: &str, warning: &LowSpaceWarning) {
+    let result = client
+        .post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .json(warning)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status);
+
+    if let Err(err) = result {
+        tracing::warn!("Failed to send the low space warning to the webhook: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_low_space_hysteresis() {
+        let storage = Storage {
+            low_space_mb: 100,
+            low_space_percent: 0,
+            ..Default::default()
+        };
+
+        assert!(!is_still_low_space(&storage, false, 101 * MB, 1000 * MB));
+        assert!(is_still_low_space(&storage, false, 99 * MB, 1000 * MB));
+
+        // A disk that was low needs some margin above the threshold to recover.
+        assert!(is_still_low_space(&storage, true, 101 * MB, 1000 * MB));
+        assert!(!is_still_low_space(&storage, true, 111 * MB, 1000 * MB));
+    }
+
+    #[test]
+    fn test_low_space_thresholds() {
+        let storage = Storage {
+            low_space_mb: 10,
+            low_space_percent: 5,
+            ..Default::default()
+        };
+
+        assert!(storage.is_low_space(40 * MB, 1000 * MB));
+        assert!(storage.is_low_space(5 * MB, 10 * MB));
+        assert!(!storage.is_low_space(60 * MB, 1000 * MB));
+
+        let disabled = Storage {
+            low_space_mb: 0,
+            low_space_percent: 0,
+            ..Default::default()
+        };
+        assert!(!disabled.is_low_space(0, 1000 * MB));
+    }
+}
@@ -0,0 +1,34 @@
+//! Extensions of the files treated as songs, which can change while the server is running.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, RwLock},
+};
+
+#[derive(Debug)]
+pub struct SongFileTypes {
+    file_types: RwLock<Arc<BTreeSet<String>>>,
+}
+
+impl SongFileTypes {
+    pub fn new(file_types: BTreeSet<String>) -> Self {
+        Self {
+            file_types: RwLock::new(Arc::new(file_types)),
+        }
+    }
+
+    /// Returns the current extensions, later changes don't affect the returned set.
+    pub fn get(&self) -> Arc<BTreeSet<String>> {
+        self.file_types
+            .read()
+            .expect("Song file types lock poisoned")
+            .clone()
+    }
+
+    pub fn set(&self, file_types: BTreeSet<String>) {
+        *self
+            .file_types
+            .write()
+            .expect("Song file types lock poisoned") = Arc::new(file_types);
+    }
+}
@@ -1,18 +1,20 @@
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     path::PathBuf,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-    },
+    sync::Arc,
+    time::Duration,
 };
 
 use serde::Serialize;
 use time::OffsetDateTime;
 use tokio::sync::{Mutex, broadcast, mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
 use ts_rs::TS;
 
-use crate::fs::{Operation, OperationError, OperationEvent};
+use crate::fs::{Operation, OperationError, OperationEvent, OperationKind, PauseGate};
+
+/// How often shutting down checks whether the operations have stopped.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 type Result<T, E = OperationManagerError> = std::result::Result<T, E>;
 type OperationResult = std::result::Result<(), OperationError>;
@@ -20,7 +22,7 @@ type OperationResult = std::result::Result<(), OperationError>;
 type QueueItem = (
     i128,
     Operation,
-    Arc<AtomicBool>,
+    CancellationToken,
     mpsc::Sender<OperationEvent>,
     oneshot::Sender<OperationResult>,
 );
@@ -57,42 +59,134 @@ impl OperationHandle {
 
 #[derive(Clone, Debug, serde::Serialize, TS)]
 #[serde(rename_all = "camelCase", tag = "kind")]
-#[ts(export, export_to = "bindings.ts", rename = "FileOperationState")]
+#[ts(rename = "FileOperationState")]
 pub enum OperationState {
     Move {
         paths: HashMap<PathBuf, PathBuf>,
         status: OperationStatus,
+        file_count: usize,
+        total_bytes: u64,
         #[serde(skip)]
-        stop_flag: Arc<AtomicBool>,
+        token: CancellationToken,
     },
     Copy {
         paths: HashMap<PathBuf, PathBuf>,
         status: OperationStatus,
+        file_count: usize,
+        total_bytes: u64,
         #[serde(skip)]
-        stop_flag: Arc<AtomicBool>,
+        token: CancellationToken,
     },
     Delete {
         paths: HashSet<PathBuf>,
+        /// Whether the paths are deleted for good instead of moved to the trash.
+        permanent: bool,
         status: OperationStatus,
+        file_count: usize,
+        total_bytes: u64,
         #[serde(skip)]
-        stop_flag: Arc<AtomicBool>,
+        token: CancellationToken,
+    },
+    Restore {
+        /// The trash entry being restored.
+        id: String,
+        status: OperationStatus,
+        file_count: usize,
+        total_bytes: u64,
+        #[serde(skip)]
+        token: CancellationToken,
     },
 }
 
 impl OperationState {
+    /// Creates the state of a queued operation, counting the files and bytes it will touch.
+    pub fn new(operation: &Operation) -> Self {
+        let (file_count, total_bytes) = operation.totals();
+
+        match operation {
+            Operation::Move { paths, .. } => OperationState::Move {
+                paths: paths.clone(),
+                status: OperationStatus::Pending,
+                file_count,
+                total_bytes,
+                token: CancellationToken::new(),
+            },
+            Operation::Copy { paths, .. } => OperationState::Copy {
+                paths: paths.clone(),
+                status: OperationStatus::Pending,
+                file_count,
+                total_bytes,
+                token: CancellationToken::new(),
+            },
+            Operation::Delete { paths, trash, .. } => OperationState::Delete {
+                paths: paths.clone(),
+                permanent: trash.is_none(),
+                status: OperationStatus::Pending,
+                file_count,
+                total_bytes,
+                token: CancellationToken::new(),
+            },
+            Operation::Restore { id, .. } => OperationState::Restore {
+                id: id.clone(),
+                status: OperationStatus::Pending,
+                file_count,
+                total_bytes,
+                token: CancellationToken::new(),
+            },
+        }
+    }
+
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            OperationState::Move { .. } => OperationKind::Move,
+            OperationState::Copy { .. } => OperationKind::Copy,
+            OperationState::Delete { .. } => OperationKind::Delete,
+            OperationState::Restore { .. } => OperationKind::Restore,
+        }
+    }
+
+    /// Returns the number of files and bytes the operation was queued with.
+    pub fn totals(&self) -> (usize, u64) {
+        match self {
+            OperationState::Move {
+                file_count,
+                total_bytes,
+                ..
+            }
+            | OperationState::Copy {
+                file_count,
+                total_bytes,
+                ..
+            }
+            | OperationState::Delete {
+                file_count,
+                total_bytes,
+                ..
+            }
+            | OperationState::Restore {
+                file_count,
+                total_bytes,
+                ..
+            } => (*file_count, *total_bytes),
+        }
+    }
+
     pub fn status(&self) -> &OperationStatus {
         match self {
             OperationState::Move { status, .. } => status,
             OperationState::Copy { status, .. } => status,
             OperationState::Delete { status, .. } => status,
+            OperationState::Restore { status, .. } => status,
         }
     }
 
-    pub fn stop_flag(&self) -> &Arc<AtomicBool> {
+    /// Returns the token the running operation checks between files and copied buffers.
+    pub fn token(&self) -> &CancellationToken {
         match self {
-            OperationState::Move { stop_flag, .. } => stop_flag,
-            OperationState::Copy { stop_flag, .. } => stop_flag,
-            OperationState::Delete { stop_flag, .. } => stop_flag,
+            OperationState::Move { token, .. } => token,
+            OperationState::Copy { token, .. } => token,
+            OperationState::Delete { token, .. } => token,
+            OperationState::Restore { token, .. } => token,
         }
     }
 
@@ -112,41 +206,27 @@ impl OperationState {
                 status: previous_status,
                 ..
             } => *previous_status = status,
+
+            OperationState::Restore {
+                status: previous_status,
+                ..
+            } => *previous_status = status,
         }
     }
 
+    /// Stops the operation when `stop` is set, kept for callers of the old stop flag.
+    ///
+    /// A cancelled token can't be reset, so clearing the flag doesn't resume the operation.
     pub fn set_stop_flag(&self, stop: bool) {
-        let flag = self.stop_flag();
-
-        flag.store(stop, Ordering::SeqCst);
-    }
-}
-
-impl From<&Operation> for OperationState {
-    fn from(op: &Operation) -> Self {
-        match op {
-            Operation::Move { paths, .. } => OperationState::Move {
-                paths: paths.clone(),
-                status: OperationStatus::Pending,
-                stop_flag: Default::default(),
-            },
-            Operation::Copy { paths, .. } => OperationState::Copy {
-                paths: paths.clone(),
-                status: OperationStatus::Pending,
-                stop_flag: Default::default(),
-            },
-            Operation::Delete { paths, .. } => OperationState::Delete {
-                paths: paths.clone(),
-                status: OperationStatus::Pending,
-                stop_flag: Default::default(),
-            },
+        if stop {
+            self.token().cancel();
         }
     }
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, serde::Serialize, TS)]
 #[serde(rename_all = "camelCase")]
-#[ts(export, export_to = "bindings.ts", rename ="FileOperationStatus")]
+#[ts(rename = "FileOperationStatus")]
 pub enum OperationStatus {
     #[default]
     Pending,
@@ -158,6 +238,7 @@ pub struct OperationManager {
     queue: tokio::sync::mpsc::Sender<QueueItem>,
     events: broadcast::Sender<OperationManagerEvent>,
     state: Arc<Mutex<BTreeMap<i128, OperationState>>>,
+    pause: PauseGate,
 }
 
 impl OperationManager {
@@ -167,10 +248,13 @@ impl OperationManager {
         let state: Arc<Mutex<BTreeMap<i128, OperationState>>> =
             Arc::new(Mutex::new(BTreeMap::new()));
 
+        let pause = PauseGate::default();
+
         let state_clone = state.clone();
         let events_clone = events.clone();
+        let pause_clone = pause.clone();
         tokio::spawn(async move {
-            while let Some((id, operation, flag, operation_tx, result)) = rx.recv().await {
+            while let Some((id, operation, token, operation_tx, result)) = rx.recv().await {
                 let (tx, rx) = std::sync::mpsc::channel::<OperationEvent>();
                 let (bridged_tx, mut bridged_rx) = mpsc::channel(256);
 
@@ -192,11 +276,20 @@ impl OperationManager {
                     while let Some(item) = bridged_rx.recv().await {
                         match item {
                             OperationEvent::Started => {
-                                send_event(&events, OperationManagerEvent::Started { source: id });
-
                                 let mut state = state.lock().await;
                                 if let Some(op) = state.get_mut(&id) {
                                     op.set_status(OperationStatus::InProgress);
+
+                                    let (file_count, total_bytes) = op.totals();
+                                    send_event(
+                                        &events,
+                                        OperationManagerEvent::Started {
+                                            source: id,
+                                            operation: op.kind(),
+                                            file_count,
+                                            total_bytes,
+                                        },
+                                    );
                                 }
                             }
                             OperationEvent::Completed => {
@@ -222,6 +315,9 @@ impl OperationManager {
                                 total_bytes,
                                 file_count,
                                 file_index,
+                                operation_copied_bytes,
+                                operation_total_bytes,
+                                eta_seconds,
                             } => {
                                 send_event(
                                     &events,
@@ -229,8 +325,11 @@ impl OperationManager {
                                         source: id,
                                         copied_bytes,
                                         total_bytes,
-                                        file_count,
                                         file_index,
+                                        file_count,
+                                        operation_copied_bytes,
+                                        operation_total_bytes,
+                                        eta_seconds,
                                     },
                                 );
                             }
@@ -274,9 +373,12 @@ impl OperationManager {
                     }
                 });
 
-                let operation = tokio::task::spawn_blocking(move || operation.execute(&tx, &flag))
-                    .await
-                    .expect("Failed to execute operation");
+                let pause = pause_clone.clone();
+                let operation = tokio::task::spawn_blocking(move || {
+                    operation.execute_pausable(&tx, &token, &pause)
+                })
+                .await
+                .expect("Failed to execute operation");
 
                 if let Err(e) = &operation {
                     tracing::error!("Failed to execute operation: {e}");
@@ -288,6 +390,10 @@ impl OperationManager {
                         .expect("Failed to send event");
                 }
 
+                // Failed operations don't send an event that removes them, and shutting down waits
+                // for every operation to be removed.
+                state_clone.lock().await.remove(&id);
+
                 let _ = result.send(operation);
             }
         });
@@ -296,33 +402,64 @@ impl OperationManager {
             queue: tx,
             events,
             state,
+            pause,
         }
     }
 
     pub async fn queue_operation(&self, operation: Operation) -> Result<OperationHandle> {
         let id = OffsetDateTime::now_utc().unix_timestamp_nanos();
-        let operation_state = OperationState::from(&operation);
-        let flag = operation_state.stop_flag().clone();
+        let operation_state = OperationState::new(&operation);
+        let token = operation_state.token().clone();
         let (events_tx, events) = mpsc::channel(256);
         let (result_tx, result) = oneshot::channel();
 
         self.state.lock().await.insert(id, operation_state);
         self.queue
-            .send((id, operation, flag, events_tx, result_tx))
+            .send((id, operation, token, events_tx, result_tx))
             .await?;
 
         Ok(OperationHandle { id, events, result })
     }
 
     pub async fn stop_operation(&self, id: i128) -> Result<()> {
-        let mut state = self.state.lock().await;
-        let state = state.get_mut(&id).ok_or(OperationManagerError::NotFound)?;
+        let state = self.state.lock().await;
+        let state = state.get(&id).ok_or(OperationManagerError::NotFound)?;
 
-        state.set_stop_flag(true);
+        state.token().cancel();
 
         Ok(())
     }
 
+    /// Cancels every operation, the running one stops in the middle of the file it's on and
+    /// removes the partial copy of it. Returns whether every operation stopped within the timeout.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        for state in self.state.lock().await.values() {
+            state.token().cancel();
+        }
+
+        tokio::time::timeout(timeout, async {
+            while !self.state.lock().await.is_empty() {
+                tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    /// Holds the running operation once it's done with the file it's on, queued operations wait
+    /// until the manager is resumed.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
     pub fn events(&self) -> broadcast::Receiver<OperationManagerEvent> {
         self.events.subscribe()
     }
@@ -343,6 +480,9 @@ pub enum OperationManagerEvent {
     },
     Started {
         source: i128,
+        operation: OperationKind,
+        file_count: usize,
+        total_bytes: u64,
     },
     Completed {
         source: i128,
@@ -375,6 +515,10 @@ pub enum OperationManagerEvent {
         total_bytes: u64,
         file_index: usize,
         file_count: usize,
+        /// Bytes done over the whole operation, see [`OperationEvent::Progress`].
+        operation_copied_bytes: u64,
+        operation_total_bytes: u64,
+        eta_seconds: Option<u64>,
     },
 }
 
@@ -382,7 +526,7 @@ impl OperationManagerEvent {
     pub fn source(&self) -> i128 {
         match self {
             OperationManagerEvent::Failed { source, .. } => *source,
-            OperationManagerEvent::Started { source } => *source,
+            OperationManagerEvent::Started { source, .. } => *source,
             OperationManagerEvent::Completed { source } => *source,
             OperationManagerEvent::Cancelled { source } => *source,
             OperationManagerEvent::Progress { source, .. } => *source,
@@ -442,6 +586,7 @@ mod tests {
                 paths,
                 overwrite: true,
                 delete_empty_directories_after: true,
+                verify: false,
             })
             .await
             .expect("Failed to add operation");
@@ -459,6 +604,101 @@ mod tests {
         );
     }
 
+    #[test(tokio::test)]
+    async fn test_stopping_large_copy() {
+        const FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+        let temp = tempdir().expect("Failed to create temp dir");
+        let src_file = temp.path().join("large.flac");
+        let dst_file = temp.path().join("copy.flac");
+        fs::File::create(&src_file)
+            .and_then(|file| file.set_len(FILE_SIZE))
+            .expect("Failed to create source file");
+
+        let manager = OperationManager::new();
+        let mut handle = manager
+            .queue_operation(Operation::Copy {
+                paths: HashMap::from([(src_file.clone(), dst_file.clone())]),
+                overwrite: false,
+                verify: false,
+            })
+            .await
+            .expect("Failed to add operation");
+
+        while let Some(event) = handle.events().recv().await {
+            if let OperationEvent::Progress { .. } = event {
+                break;
+            }
+        }
+
+        let stopped_at = std::time::Instant::now();
+        manager
+            .stop_operation(handle.id())
+            .await
+            .expect("Failed to stop operation");
+
+        handle
+            .result()
+            .await
+            .expect("Operation result should be sent")
+            .expect("Cancelled copy shouldn't fail");
+
+        assert!(stopped_at.elapsed() < std::time::Duration::from_secs(1));
+        assert!(!dst_file.exists(), "partial copy should be removed");
+
+        let mut cancelled = false;
+        while let Some(event) = handle.events().recv().await {
+            cancelled |= matches!(event, OperationEvent::Cancelled);
+        }
+        assert!(cancelled);
+    }
+
+    #[test]
+    fn test_operation_state_shape() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let src_file = temp.path().join("file.txt");
+        fs::write(&src_file, "hello").expect("Failed to write file");
+
+        let state = OperationState::new(&Operation::Copy {
+            paths: HashMap::from([(src_file.clone(), temp.path().join("dst"))]),
+            overwrite: false,
+            verify: false,
+        });
+
+        assert_eq!(state.kind(), OperationKind::Copy);
+        assert_eq!(
+            serde_json::to_value(&state).expect("Failed to serialize state"),
+            serde_json::json!({
+                "kind": "copy",
+                "paths": { (src_file.to_string_lossy()): temp.path().join("dst") },
+                "status": "pending",
+                "file_count": 1,
+                "total_bytes": 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_started_event_shape() {
+        let event = OperationManagerEvent::Started {
+            source: 1,
+            operation: OperationKind::Move,
+            file_count: 12,
+            total_bytes: 4096,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&event).expect("Failed to serialize event"),
+            serde_json::json!({
+                "kind": "started",
+                "source": 1,
+                "operation": "move",
+                "file_count": 12,
+                "total_bytes": 4096,
+            })
+        );
+    }
+
     #[test(tokio::test)]
     async fn test_fail() {
         let temp = tempdir().expect("Failed to create temp dir");
@@ -489,6 +729,7 @@ mod tests {
                 paths,
                 overwrite: true,
                 delete_empty_directories_after: true,
+                verify: false,
             })
             .await
             .expect("Failed to add operation");
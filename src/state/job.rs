@@ -1,14 +1,21 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use ts_rs::TS;
 
-use crate::jobs::{JobEvent, JobHandle};
+use crate::{
+    genres::GenreMerge,
+    jobs::{JobEvent, JobHandle},
+};
 
 pub mod manager;
+pub mod scheduler;
 
 type Result<T, E = JobRegistryError> = std::result::Result<T, E>;
 
@@ -28,6 +35,20 @@ pub struct JobInfo {
     pub name: String,
     pub description: String,
     pub steps: BTreeMap<u8, String>,
+    /// Whether the job can be queued with [`JobParameters::dry_run`].
+    pub supports_dry_run: bool,
+    /// Whether the job can be queued with [`JobParameters::steps`].
+    pub supports_step_selection: bool,
+    /// Whether the job can be queued with [`JobParameters::album`] and
+    /// [`JobParameters::directory`].
+    pub supports_filters: bool,
+    /// Whether the job can be queued with [`JobParameters::playlist`].
+    pub supports_playlist: bool,
+    /// Whether the job can be queued with [`JobParameters::genre_merge`].
+    pub supports_genre_merge: bool,
+    /// Whether the job must not run alongside other exclusive jobs, e.g. because they write the
+    /// same rows.
+    pub exclusive: bool,
 }
 
 impl JobInfo {
@@ -40,8 +61,103 @@ impl JobInfo {
             name: name.into(),
             description: description.into(),
             steps,
+            supports_dry_run: false,
+            supports_step_selection: false,
+            supports_filters: false,
+            supports_playlist: false,
+            supports_genre_merge: false,
+            exclusive: false,
         }
     }
+
+    pub fn with_dry_run(mut self) -> Self {
+        self.supports_dry_run = true;
+        self
+    }
+
+    pub fn with_step_selection(mut self) -> Self {
+        self.supports_step_selection = true;
+        self
+    }
+
+    pub fn with_filters(mut self) -> Self {
+        self.supports_filters = true;
+        self
+    }
+
+    pub fn with_playlist(mut self) -> Self {
+        self.supports_playlist = true;
+        self
+    }
+
+    pub fn with_genre_merge(mut self) -> Self {
+        self.supports_genre_merge = true;
+        self
+    }
+
+    pub fn exclusive(mut self) -> Self {
+        self.exclusive = true;
+        self
+    }
+}
+
+/// Parameters every job is queued with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase", default)]
+pub struct JobParameters {
+    /// Only plan the changes, without touching any files or the database.
+    ///
+    /// Dry runs return what they would have changed as the artifact of the report.
+    pub dry_run: bool,
+    /// Only run these steps of the job, every step runs when not set.
+    ///
+    /// Accepts a comma separated list in query strings, e.g. `?steps=1,3`.
+    #[serde(deserialize_with = "deserialize_steps")]
+    pub steps: Option<BTreeSet<u8>>,
+    /// Only process the songs of this album.
+    pub album: Option<String>,
+    /// Only process the songs of the directory with this name.
+    pub directory: Option<String>,
+    /// Process the songs of the playlist with this id.
+    pub playlist: Option<String>,
+    /// The genres to merge.
+    pub genre_merge: Option<GenreMerge>,
+}
+
+impl JobParameters {
+    /// Returns whether the step should run with these parameters.
+    pub fn runs_step(&self, step: u8) -> bool {
+        self.steps
+            .as_ref()
+            .is_none_or(|steps| steps.contains(&step))
+    }
+
+    /// Whether the job is limited to some of the songs.
+    pub fn is_filtered(&self) -> bool {
+        self.album.is_some() || self.directory.is_some()
+    }
+}
+
+fn deserialize_steps<'de, D>(deserializer: D) -> Result<Option<BTreeSet<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Steps {
+        List(BTreeSet<u8>),
+        Text(String),
+    }
+
+    match Option::<Steps>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Steps::List(steps)) => Ok(Some(steps)),
+        Some(Steps::Text(text)) => text
+            .split(',')
+            .map(|step| step.trim().parse::<u8>().map_err(serde::de::Error::custom))
+            .collect::<Result<_, _>>()
+            .map(Some),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -96,31 +212,51 @@ impl JobRegistry {
 }
 
 #[derive(Debug, Clone, Serialize, TS)]
-#[ts(export, export_to = "bindings.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct JobState {
     pub job_id: JobId,
     pub status: JobStatus,
     pub current_step: u8,
     pub values: BTreeMap<u8, String>,
+    /// The most recent progress reported by the job, if it reported any.
+    pub progress: Option<JobProgress>,
+    pub parameters: JobParameters,
     #[serde(skip)]
     pub token: CancellationToken,
 }
 
 impl JobState {
-    pub fn new(job_id: JobId) -> Self {
+    pub fn new(job_id: JobId, parameters: JobParameters) -> Self {
         Self {
             job_id,
             status: JobStatus::Pending,
             current_step: 1,
             values: BTreeMap::new(),
+            progress: None,
+            parameters,
             token: CancellationToken::new(),
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, TS)]
-#[ts(export, export_to = "bindings.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub current: u64,
+    pub total: u64,
+    pub step: u8,
+}
+
+impl JobProgress {
+    /// Whole percentage of the step that's done, `None` if the total is unknown.
+    pub fn percentage(&self) -> Option<u8> {
+        (self.total > 0).then(|| {
+            (u128::from(self.current.min(self.total)) * 100 / u128::from(self.total)) as u8
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, TS)]
 #[serde(rename_all = "camelCase")]
 pub enum JobStatus {
     Pending,
@@ -128,7 +264,6 @@ pub enum JobStatus {
 }
 
 #[derive(Debug, Clone, Serialize, Default, TS)]
-#[ts(export, export_to = "bindings.ts")]
 #[serde(rename_all = "camelCase")]
 pub struct JobExecutionReport {
     #[ts(type = "Date")]
@@ -141,4 +276,50 @@ pub struct JobExecutionReport {
     #[serde(with = "time::serde::rfc3339::option")]
     pub cancelled_at: Option<OffsetDateTime>,
     pub completed_successfully: bool,
+    pub dry_run: bool,
+    /// The directory the run was limited to, see [`JobParameters::directory`].
+    pub directory: Option<String>,
+    /// Whether the run saved an artifact, see [`crate::paths::job_artifact_path`].
+    pub has_artifact: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::Query, http::Uri};
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn test_parsing_selected_steps() {
+        let parse = |uri: &'static str| {
+            Query::<JobParameters>::try_from_uri(&Uri::from_static(uri)).map(|query| query.0)
+        };
+
+        assert_eq!(parse("/queue").unwrap(), JobParameters::default());
+        assert_eq!(
+            parse("/queue?steps=1,3").unwrap().steps,
+            Some(BTreeSet::from([1, 3]))
+        );
+        assert!(parse("/queue?steps=one").is_err());
+
+        let parameters = serde_json::from_str::<JobParameters>(r#"{"steps":[2]}"#).unwrap();
+        assert!(parameters.runs_step(2));
+        assert!(!parameters.runs_step(1));
+        assert!(JobParameters::default().runs_step(1));
+    }
+
+    #[test]
+    fn test_progress_percentage() {
+        let progress = |current, total| JobProgress {
+            current,
+            total,
+            step: 1,
+        };
+
+        assert_eq!(progress(1, 3).percentage(), Some(33));
+        assert_eq!(progress(5, 3).percentage(), Some(100));
+        assert_eq!(progress(u64::MAX - 1, u64::MAX).percentage(), Some(99));
+        assert_eq!(progress(1, 0).percentage(), None);
+    }
 }
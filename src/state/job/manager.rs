@@ -1,13 +1,23 @@
 use std::{
+    any::Any,
     collections::{HashMap, VecDeque},
-    sync::Arc,
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
 
+use color_eyre::eyre::eyre;
+use futures::FutureExt;
 use serde::Serialize;
 use time::OffsetDateTime;
 use tokio::sync::{Mutex, MutexGuard, Notify, broadcast};
 use tokio_util::sync::CancellationToken;
 
+use crate::{jobs::JobArtifact, paths::job_artifact_path};
+
 use super::*;
 
 pub type JobStates = BTreeMap<JobStateId, JobState>;
@@ -24,12 +34,15 @@ type Result<T, E = JobManagerError> = std::result::Result<T, E>;
 pub enum JobManagerEvent {
     Started {
         source: JobStateId,
+        job_id: JobId,
     },
     Completed {
         source: JobStateId,
+        job_id: JobId,
     },
     Cancelled {
         source: JobStateId,
+        job_id: JobId,
     },
     Warning {
         source: JobStateId,
@@ -37,6 +50,7 @@ pub enum JobManagerEvent {
     },
     Failed {
         source: JobStateId,
+        job_id: JobId,
         message: String,
     },
     StepCompleted {
@@ -80,6 +94,18 @@ pub enum JobManagerError {
     StateNotFound,
     #[error("Job report not found")]
     ReportNotFound,
+    #[error("Job doesn't support dry runs")]
+    DryRunUnsupported,
+    #[error("Job doesn't support selecting steps")]
+    StepSelectionUnsupported,
+    #[error("Job has no step {0}")]
+    UnknownStep(u8),
+    #[error("Job doesn't support filtering songs")]
+    FiltersUnsupported,
+    #[error("Job doesn't support selecting a playlist")]
+    PlaylistUnsupported,
+    #[error("Job doesn't support merging genres")]
+    GenreMergeUnsupported,
 }
 
 #[derive(Debug)]
@@ -92,7 +118,16 @@ pub struct JobManager {
 }
 
 impl JobManager {
+    /// Creates a manager running one job at a time.
     pub fn new(registry: JobRegistry) -> Self {
+        Self::with_workers(registry, 1)
+    }
+
+    /// Creates a manager running up to `workers` jobs at the same time.
+    ///
+    /// Jobs marked [`JobInfo::exclusive`] never run alongside each other, and a job queued as
+    /// unique doesn't start while another run of the same job is still going.
+    pub fn with_workers(registry: JobRegistry, workers: usize) -> Self {
         let (events, _) = broadcast::channel(1024 * 8);
         let states: Arc<Mutex<JobStates>> = Arc::new(Mutex::new(BTreeMap::new()));
         let reports: Arc<Mutex<JobReports>> = Arc::new(Mutex::new(
@@ -104,6 +139,7 @@ impl JobManager {
         ));
 
         let queue = Arc::new(Queue::new());
+        let workers = workers.max(1);
 
         let events_clone = events.clone();
         let state_clone = states.clone();
@@ -112,174 +148,15 @@ impl JobManager {
 
         tokio::spawn(async move {
             loop {
-                if let Some((
-                    state_id,
-                    QueueItem {
-                        job,
-                        report_id,
-                        cancel_token,
-                        job_events,
-                        ..
-                    },
-                )) = {
-                    let mut order = queued.order.lock().await;
-                    let mut queued = queued.items.lock().await;
-
-                    let entry = order.pop_front().and_then(|id| queued.remove_entry(&id));
-                    drop(queued);
-
-                    if entry.is_some() {
-                        let new_order = order.clone();
-                        drop(order);
-
-                        Self::send_event(
-                            &events_clone,
-                            JobManagerEvent::OrderUpdated {
-                                queue: new_order.into(),
-                            },
-                        );
-                    }
-
-                    entry
-                } {
-                    let (tx, mut rx) = mpsc::channel::<JobEvent>(256);
-                    let manager_events = events_clone.clone();
-                    let state = state_clone.clone();
-                    let job_token = cancel_token.child_token();
-                    tokio::spawn(async move {
-                        while let Some(event) = rx.recv().await
-                            && !job_token.is_cancelled()
-                        {
-                            let _ = job_events.send(event.clone()).await;
-
-                            match event {
-                                JobEvent::Progress {
-                                    current,
-                                    total,
-                                    step,
-                                } => {
-                                    Self::send_event(
-                                        &manager_events,
-                                        JobManagerEvent::Progress {
-                                            source: state_id,
-                                            current,
-                                            total,
-                                            step,
-                                        },
-                                    );
-                                }
-                                JobEvent::StepCompleted { step, value } => {
-                                    let mut state = state.lock().await;
-                                    if let Some(state) = state.get_mut(&state_id) {
-                                        state.current_step = step + 1;
-                                        state
-                                            .values
-                                            .insert(step, value.clone().unwrap_or_default());
-
-                                        Self::send_event(
-                                            &manager_events,
-                                            JobManagerEvent::StateUpdated {
-                                                source: state_id,
-                                                state: state.clone(),
-                                            },
-                                        );
-                                    }
-
-                                    drop(state);
-
-                                    Self::send_event(
-                                        &manager_events,
-                                        JobManagerEvent::StepCompleted {
-                                            source: state_id,
-                                            step,
-                                            value,
-                                        },
-                                    );
-                                }
-                                JobEvent::Warning { message } => {
-                                    Self::send_event(
-                                        &manager_events,
-                                        JobManagerEvent::Warning {
-                                            source: state_id,
-                                            message,
-                                        },
-                                    );
-                                }
-                            }
-                        }
-
-                        drop(job_events);
-                    });
-
-                    let mut states = state_clone.lock().await;
-                    let state = states.get_mut(&state_id).unwrap();
-                    state.status = JobStatus::InProgress;
-
-                    drop(states);
-
-                    Self::send_event(&events_clone, JobManagerEvent::Started { source: state_id });
-
-                    let mut reports = reports_clone.lock().await;
-                    let report = Self::report(&mut reports, &report_id);
-                    report.started_at.replace(OffsetDateTime::now_utc());
-
-                    Self::send_event(
-                        &events_clone,
-                        JobManagerEvent::ReportUpdated {
-                            job_id: report_id.clone(),
-                            report: report.clone(),
-                        },
-                    );
-
-                    drop(reports);
-
-                    let result = job.execute(cancel_token.child_token(), tx).await;
-
-                    let mut reports = reports_clone.lock().await;
-                    let report = Self::report(&mut reports, &report_id);
-
-                    if result.is_ok() && !cancel_token.is_cancelled() {
-                        report.completed_at.replace(OffsetDateTime::now_utc());
-                        report.completed_successfully = true;
-
-                        Self::send_event(
-                            &events_clone,
-                            JobManagerEvent::Completed { source: state_id },
-                        );
-                    } else if let Err(err) = result.as_ref() {
-                        tracing::error!("Job failed: {err}");
-
-                        report.completed_at.replace(OffsetDateTime::now_utc());
-                        report.completed_successfully = false;
-
-                        Self::send_event(
-                            &events_clone,
-                            JobManagerEvent::Failed {
-                                source: state_id,
-                                message: err.to_string(),
-                            },
-                        );
-                    } else {
-                        report.cancelled_at.replace(OffsetDateTime::now_utc());
-                        report.completed_successfully = false;
-
-                        Self::send_event(
-                            &events_clone,
-                            JobManagerEvent::Cancelled { source: state_id },
-                        );
-                    }
-
-                    Self::send_event(
-                        &events_clone,
-                        JobManagerEvent::ReportUpdated {
-                            job_id: report_id,
-                            report: report.clone(),
-                        },
-                    );
-
-                    drop(reports);
-
-                    Self::remove_state(state_clone.lock().await, &events_clone, state_id).await;
+                if let Some((state_id, item)) = queued.next_item(workers, &events_clone).await {
+                    tokio::spawn(Self::execute(
+                        state_id,
+                        item,
+                        events_clone.clone(),
+                        state_clone.clone(),
+                        reports_clone.clone(),
+                        queued.clone(),
+                    ));
                 } else {
                     queued.notify.notified().await;
                 }
@@ -298,10 +175,45 @@ impl JobManager {
     pub async fn queue(
         &self,
         job_id: impl Into<JobId>,
+        parameters: JobParameters,
         unique: bool,
         high_priority: bool,
     ) -> Result<JobHandler> {
         let job_id = job_id.into();
+        let job = self
+            .registry
+            .jobs()
+            .get(&job_id)
+            .ok_or(JobRegistryError::NotFound)?;
+
+        if parameters.dry_run && !job.info().supports_dry_run {
+            return Err(JobManagerError::DryRunUnsupported);
+        }
+
+        if let Some(steps) = &parameters.steps {
+            if !job.info().supports_step_selection {
+                return Err(JobManagerError::StepSelectionUnsupported);
+            }
+
+            if let Some(step) = steps
+                .iter()
+                .find(|step| !job.info().steps.contains_key(step))
+            {
+                return Err(JobManagerError::UnknownStep(*step));
+            }
+        }
+
+        if parameters.is_filtered() && !job.info().supports_filters {
+            return Err(JobManagerError::FiltersUnsupported);
+        }
+
+        if parameters.playlist.is_some() && !job.info().supports_playlist {
+            return Err(JobManagerError::PlaylistUnsupported);
+        }
+
+        if parameters.genre_merge.is_some() && !job.info().supports_genre_merge {
+            return Err(JobManagerError::GenreMergeUnsupported);
+        }
 
         if unique
             && self
@@ -319,7 +231,7 @@ impl JobManager {
 
         let id = JobStateId::new_v4();
         let (tx, rx) = mpsc::channel(256);
-        let state = JobState::new(job_id.clone());
+        let state = JobState::new(job_id.clone(), parameters.clone());
         let cancel_token = state.token.child_token();
 
         Self::add_state(self.states.lock().await, &self.events, id, state).await;
@@ -330,26 +242,17 @@ impl JobManager {
                 QueueItem {
                     unique,
                     cancel_token,
+                    exclusive: job.info().exclusive,
                     job_events: tx,
                     report_id: job_id.clone(),
-                    job: self
-                        .registry
-                        .jobs()
-                        .get(&job_id)
-                        .map(|job| job.handle())
-                        .ok_or(JobRegistryError::NotFound)?,
+                    parameters,
+                    job: job.handle(),
                 },
                 high_priority,
+                &self.events,
             )
             .await;
 
-        Self::send_event(
-            &self.events,
-            JobManagerEvent::OrderUpdated {
-                queue: self.queue.order.lock().await.clone().into(),
-            },
-        );
-
         tracing::debug!("Job queued: {job_id}");
 
         Ok(JobHandler {
@@ -368,12 +271,14 @@ impl JobManager {
                 tracing::debug!("Stopped job: {state_id}");
 
                 let mut reports = self.reports.lock().await;
-                let report = Self::report(&mut reports, &state.job_id);
-                report.cancelled_at.replace(OffsetDateTime::now_utc());
-                report.completed_successfully = false;
+                if self.queue.owns_report(&state.job_id, state_id).await {
+                    let report = Self::report(&mut reports, &state.job_id);
+                    report.cancelled_at.replace(OffsetDateTime::now_utc());
+                    report.completed_successfully = false;
+                }
             } else {
                 Self::remove_state(states, &self.events, state_id).await;
-                self.queue.remove_item(state_id, false).await;
+                self.queue.remove_item(state_id, &self.events).await;
             }
 
             Ok(())
@@ -382,6 +287,57 @@ impl JobManager {
         }
     }
 
+    /// Cancels the running jobs and keeps the queued ones from starting, then waits for the
+    /// running jobs to stop. Returns whether they stopped within the timeout.
+    ///
+    /// The reports of the cancelled jobs record the cancellation right away, in case they don't
+    /// stop in time.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.pause();
+
+        let running = self.queue.running_ids().await;
+        let states = self.states.lock().await;
+        let mut reports = self.reports.lock().await;
+
+        for state_id in running {
+            let Some(state) = states.get(&state_id) else {
+                continue;
+            };
+
+            state.token.cancel();
+            tracing::debug!("Stopped job for shutdown: {state_id}");
+
+            if self.queue.owns_report(&state.job_id, state_id).await {
+                let report = Self::report(&mut reports, &state.job_id);
+                report.cancelled_at.replace(OffsetDateTime::now_utc());
+                report.completed_successfully = false;
+            }
+        }
+
+        drop(reports);
+        drop(states);
+
+        tokio::time::timeout(timeout, self.queue.wait_until_idle())
+            .await
+            .is_ok()
+    }
+
+    /// Stops jobs from being taken off the queue, the ones that are running carry on until
+    /// they're done.
+    pub fn pause(&self) {
+        self.queue.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Lets the queued jobs start again, in the order they were queued in.
+    pub fn resume(&self) {
+        self.queue.paused.store(false, Ordering::SeqCst);
+        self.queue.notify.notify_one();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.queue.paused.load(Ordering::SeqCst)
+    }
+
     pub fn events(&self) -> broadcast::Receiver<JobManagerEvent> {
         self.events.subscribe()
     }
@@ -402,6 +358,247 @@ impl JobManager {
         self.states.lock().await.clone()
     }
 
+    /// Returns whether the job is queued or running.
+    pub async fn is_active(&self, job_id: &str) -> bool {
+        self.states
+            .lock()
+            .await
+            .values()
+            .any(|state| state.job_id == job_id)
+    }
+
+    /// Runs a job taken off the queue, keeping its state and the report of the job up to date.
+    async fn execute(
+        state_id: JobStateId,
+        item: QueueItem,
+        events: broadcast::Sender<JobManagerEvent>,
+        states: Arc<Mutex<JobStates>>,
+        reports: Arc<Mutex<JobReports>>,
+        queue: Arc<Queue>,
+    ) {
+        let QueueItem {
+            job,
+            parameters,
+            report_id,
+            cancel_token,
+            job_events,
+            ..
+        } = item;
+
+        let (tx, mut rx) = mpsc::channel::<JobEvent>(256);
+        let manager_events = events.clone();
+        let state = states.clone();
+        let job_token = cancel_token.child_token();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await
+                && !job_token.is_cancelled()
+            {
+                let _ = job_events.send(event.clone()).await;
+
+                match event {
+                    JobEvent::Progress {
+                        current,
+                        total,
+                        step,
+                    } => {
+                        let progress = JobProgress {
+                            current,
+                            total,
+                            step,
+                        };
+
+                        let mut state = state.lock().await;
+                        if let Some(state) = state.get_mut(&state_id) {
+                            let previous = state.progress.replace(progress);
+
+                            // Clients only need the state again once the shown
+                            // percentage changes, not on every single tick.
+                            if previous.is_none_or(|previous| {
+                                previous.step != step
+                                    || previous.percentage() != progress.percentage()
+                            }) {
+                                Self::send_event(
+                                    &manager_events,
+                                    JobManagerEvent::StateUpdated {
+                                        source: state_id,
+                                        state: state.clone(),
+                                    },
+                                );
+                            }
+                        }
+
+                        drop(state);
+
+                        Self::send_event(
+                            &manager_events,
+                            JobManagerEvent::Progress {
+                                source: state_id,
+                                current,
+                                total,
+                                step,
+                            },
+                        );
+                    }
+                    JobEvent::StepCompleted { step, value } => {
+                        let mut state = state.lock().await;
+                        if let Some(state) = state.get_mut(&state_id) {
+                            state.current_step = step + 1;
+                            state.values.insert(step, value.clone().unwrap_or_default());
+
+                            Self::send_event(
+                                &manager_events,
+                                JobManagerEvent::StateUpdated {
+                                    source: state_id,
+                                    state: state.clone(),
+                                },
+                            );
+                        }
+
+                        drop(state);
+
+                        Self::send_event(
+                            &manager_events,
+                            JobManagerEvent::StepCompleted {
+                                source: state_id,
+                                step,
+                                value,
+                            },
+                        );
+                    }
+                    JobEvent::Warning { message } => {
+                        Self::send_event(
+                            &manager_events,
+                            JobManagerEvent::Warning {
+                                source: state_id,
+                                message,
+                            },
+                        );
+                    }
+                }
+            }
+
+            drop(job_events);
+        });
+
+        let mut states_guard = states.lock().await;
+        let state = states_guard.get_mut(&state_id).unwrap();
+        state.status = JobStatus::InProgress;
+
+        drop(states_guard);
+
+        Self::send_event(
+            &events,
+            JobManagerEvent::Started {
+                source: state_id,
+                job_id: report_id.clone(),
+            },
+        );
+
+        let mut reports_guard = reports.lock().await;
+
+        // Another run of the same job can be dispatched while this one is waiting for the lock,
+        // the report and artifact belong to whichever run was dispatched last.
+        if queue.owns_report(&report_id, state_id).await {
+            let report = Self::report(&mut reports_guard, &report_id);
+            report.started_at.replace(OffsetDateTime::now_utc());
+            report.dry_run = parameters.dry_run;
+            report.directory = parameters.directory.clone();
+            report.has_artifact = false;
+
+            Self::send_event(
+                &events,
+                JobManagerEvent::ReportUpdated {
+                    job_id: report_id.clone(),
+                    report: report.clone(),
+                },
+            );
+
+            Self::remove_artifact(&report_id).await;
+        }
+
+        drop(reports_guard);
+
+        // A panicking job still has to give up its state and its place in the queue, otherwise
+        // it would keep running forever as far as every other job and client can tell.
+        let result = AssertUnwindSafe(job.execute(parameters, cancel_token.child_token(), tx))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|panic| Err(eyre!("Job panicked: {}", panic_message(&*panic))));
+
+        let completed = result.is_ok() && !cancel_token.is_cancelled();
+
+        match &result {
+            _ if completed => {
+                Self::send_event(
+                    &events,
+                    JobManagerEvent::Completed {
+                        source: state_id,
+                        job_id: report_id.clone(),
+                    },
+                );
+            }
+            Err(err) => {
+                tracing::error!("Job failed: {err}");
+
+                Self::send_event(
+                    &events,
+                    JobManagerEvent::Failed {
+                        source: state_id,
+                        job_id: report_id.clone(),
+                        message: err.to_string(),
+                    },
+                );
+            }
+            Ok(_) => {
+                Self::send_event(
+                    &events,
+                    JobManagerEvent::Cancelled {
+                        source: state_id,
+                        job_id: report_id.clone(),
+                    },
+                );
+            }
+        }
+
+        let mut reports_guard = reports.lock().await;
+
+        // Runs can finish out of order, a run that finishes after a later run of the same job
+        // started must not overwrite the report of the later one.
+        if queue.owns_report(&report_id, state_id).await {
+            let has_artifact = match &result {
+                Ok(Some(artifact)) if completed => Self::save_artifact(&report_id, artifact).await,
+                _ => false,
+            };
+
+            let report = Self::report(&mut reports_guard, &report_id);
+
+            if completed {
+                report.completed_at.replace(OffsetDateTime::now_utc());
+                report.completed_successfully = true;
+                report.has_artifact = has_artifact;
+            } else if result.is_err() {
+                report.completed_at.replace(OffsetDateTime::now_utc());
+                report.completed_successfully = false;
+            } else {
+                report.cancelled_at.replace(OffsetDateTime::now_utc());
+                report.completed_successfully = false;
+            }
+
+            Self::send_event(
+                &events,
+                JobManagerEvent::ReportUpdated {
+                    job_id: report_id,
+                    report: report.clone(),
+                },
+            );
+        }
+
+        drop(reports_guard);
+
+        Self::remove_state(states.lock().await, &events, state_id).await;
+        queue.finish(state_id).await;
+    }
+
     async fn add_state<'l>(
         mut states: MutexGuard<'l, JobStates>,
         events: &broadcast::Sender<JobManagerEvent>,
@@ -423,6 +620,34 @@ impl JobManager {
         Self::send_event(events, JobManagerEvent::StateRemoved { source: id });
     }
 
+    /// Saves the artifact of a job run, returning whether it was saved.
+    async fn save_artifact(job_id: &JobId, artifact: &JobArtifact) -> bool {
+        let path = job_artifact_path(job_id);
+        let result = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            tokio::fs::write(&path, serde_json::to_vec_pretty(artifact)?).await
+        }
+        .await;
+
+        if let Err(err) = &result {
+            tracing::error!("Failed to save artifact of \"{job_id}\": {err}");
+        }
+
+        result.is_ok()
+    }
+
+    /// Removes the artifact of the previous run, so it can't be mistaken for the current one.
+    async fn remove_artifact(job_id: &JobId) {
+        match tokio::fs::remove_file(job_artifact_path(job_id)).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => tracing::warn!("Failed to remove artifact of \"{job_id}\": {err}"),
+        }
+    }
+
     fn report<'r>(reports: &'r mut JobReports, job_id: &JobId) -> &'r mut JobExecutionReport {
         reports
             .get_mut(job_id)
@@ -436,6 +661,15 @@ impl JobManager {
     }
 }
 
+/// Returns the message a panic was started with.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
 #[derive(Debug)]
 pub struct JobHandler {
     state_id: JobStateId,
@@ -460,17 +694,47 @@ impl JobHandler {
 #[derive(Debug)]
 struct QueueItem {
     job: Arc<dyn JobHandle>,
+    parameters: JobParameters,
     report_id: JobId,
     cancel_token: CancellationToken,
     job_events: mpsc::Sender<JobEvent>,
     unique: bool,
+    exclusive: bool,
+}
+
+#[derive(Debug)]
+struct RunningJob {
+    job_id: JobId,
+    exclusive: bool,
+}
+
+/// Jobs taken off the queue that haven't finished yet.
+#[derive(Debug, Default)]
+struct Running {
+    jobs: HashMap<JobStateId, RunningJob>,
+    /// The run of every job that was dispatched last, which owns the report of the job.
+    latest: HashMap<JobId, JobStateId>,
+}
+
+impl Running {
+    fn can_start(&self, item: &QueueItem) -> bool {
+        !self.jobs.values().any(|running| {
+            (item.exclusive && running.exclusive)
+                || (item.unique && running.job_id == item.report_id)
+        })
+    }
 }
 
 #[derive(Debug)]
 struct Queue {
     order: Mutex<VecDeque<JobStateId>>,
     items: Mutex<HashMap<JobStateId, QueueItem>>,
+    running: Mutex<Running>,
     notify: Notify,
+    /// Wakes up whoever waits for the running jobs to finish, see [`Queue::wait_until_idle`].
+    finished: Notify,
+    /// Keeps every job in the queue while set, see [`JobManager::pause`].
+    paused: AtomicBool,
 }
 
 impl Queue {
@@ -478,53 +742,137 @@ impl Queue {
         Self {
             items: Mutex::new(HashMap::new()),
             order: Mutex::new(VecDeque::new()),
+            running: Mutex::new(Running::default()),
             notify: Notify::new(),
+            finished: Notify::new(),
+            paused: AtomicBool::new(false),
         }
     }
 
     /// Adds an item to the queue
-    async fn add_item(&self, id: JobStateId, item: QueueItem, high_priority: bool) {
+    async fn add_item(
+        &self,
+        id: JobStateId,
+        item: QueueItem,
+        high_priority: bool,
+        events: &broadcast::Sender<JobManagerEvent>,
+    ) {
         tracing::debug!("Locking Queue");
-        let mut queue = self.items.lock().await;
-        queue.insert(id, item);
-        drop(queue);
-
         let mut order = self.order.lock().await;
+        self.items.lock().await.insert(id, item);
+
         if high_priority {
             order.push_front(id);
-            drop(order);
         } else {
             order.push_back(id);
-            drop(order);
         }
 
-        self.notify.notify_waiters();
+        // Sending the order while it's locked keeps clients from seeing the updates out of order.
+        Self::send_order(&order, events);
+        drop(order);
+
+        self.notify.notify_one();
     }
 
     /// Removes an item from the queue
-    async fn remove_item(&self, state_id: JobStateId, notify: bool) {
+    async fn remove_item(&self, state_id: JobStateId, events: &broadcast::Sender<JobManagerEvent>) {
         let mut order = self.order.lock().await;
         let mut queue = self.items.lock().await;
 
         order.retain(|id| *id != state_id);
         queue.remove(&state_id);
-
         drop(queue);
-        drop(order);
 
-        if notify {
-            self.notify.notify_waiters();
+        Self::send_order(&order, events);
+    }
+
+    /// Takes the first item off the queue that's allowed to run next to the running jobs.
+    async fn next_item(
+        &self,
+        workers: usize,
+        events: &broadcast::Sender<JobManagerEvent>,
+    ) -> Option<(JobStateId, QueueItem)> {
+        let mut order = self.order.lock().await;
+        let mut items = self.items.lock().await;
+        let mut running = self.running.lock().await;
+
+        if self.paused.load(Ordering::SeqCst) || running.jobs.len() >= workers {
+            return None;
         }
+
+        let position = order
+            .iter()
+            .position(|id| items.get(id).is_some_and(|item| running.can_start(item)))?;
+        let id = order.remove(position)?;
+        let item = items.remove(&id)?;
+
+        running.jobs.insert(
+            id,
+            RunningJob {
+                job_id: item.report_id.clone(),
+                exclusive: item.exclusive,
+            },
+        );
+        running.latest.insert(item.report_id.clone(), id);
+
+        Self::send_order(&order, events);
+
+        Some((id, item))
+    }
+
+    /// Returns whether the run is the last dispatched run of the job.
+    async fn owns_report(&self, job_id: &JobId, state_id: JobStateId) -> bool {
+        self.running.lock().await.latest.get(job_id) == Some(&state_id)
+    }
+
+    /// Marks a run as finished, letting the next items in the queue start.
+    async fn finish(&self, state_id: JobStateId) {
+        self.running.lock().await.jobs.remove(&state_id);
+        self.notify.notify_one();
+        self.finished.notify_waiters();
+    }
+
+    async fn running_ids(&self) -> Vec<JobStateId> {
+        self.running.lock().await.jobs.keys().copied().collect()
+    }
+
+    /// Waits until no job is running anymore.
+    async fn wait_until_idle(&self) {
+        loop {
+            let finished = self.finished.notified();
+            tokio::pin!(finished);
+
+            // Registers the waiter before checking, so a run finishing in between isn't missed.
+            finished.as_mut().enable();
+
+            if self.running.lock().await.jobs.is_empty() {
+                return;
+            }
+
+            finished.await;
+        }
+    }
+
+    fn send_order(order: &VecDeque<JobStateId>, events: &broadcast::Sender<JobManagerEvent>) {
+        JobManager::send_event(
+            events,
+            JobManagerEvent::OrderUpdated {
+                queue: order.iter().copied().collect(),
+            },
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
 
     use super::*;
     use async_trait::async_trait;
-    use color_eyre::eyre::Result;
+    use color_eyre::eyre::{Result, eyre};
     use test_log::test;
     use tokio::time::sleep;
     use tokio_util::sync::CancellationToken;
@@ -536,9 +884,10 @@ mod tests {
     impl JobHandle for TestJob {
         async fn execute(
             &self,
+            _parameters: JobParameters,
             token: CancellationToken,
             tx: mpsc::Sender<JobEvent>,
-        ) -> Result<()> {
+        ) -> Result<Option<JobArtifact>> {
             let mut index = 0;
 
             loop {
@@ -557,9 +906,98 @@ mod tests {
                 }
             }
 
-            Ok(())
+            Ok(None)
+        }
+    }
+
+    /// Finishes right away.
+    #[derive(Debug)]
+    struct FastJob {}
+
+    #[async_trait]
+    impl JobHandle for FastJob {
+        async fn execute(
+            &self,
+            _parameters: JobParameters,
+            _token: CancellationToken,
+            _tx: mpsc::Sender<JobEvent>,
+        ) -> Result<Option<JobArtifact>> {
+            sleep(Duration::from_millis(10)).await;
+            Ok(None)
+        }
+    }
+
+    /// Fails slowly on the first run and succeeds right away on every run after it.
+    #[derive(Debug, Default)]
+    struct FlakyJob {
+        runs: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl JobHandle for FlakyJob {
+        async fn execute(
+            &self,
+            _parameters: JobParameters,
+            _token: CancellationToken,
+            _tx: mpsc::Sender<JobEvent>,
+        ) -> Result<Option<JobArtifact>> {
+            if self.runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                sleep(Duration::from_millis(300)).await;
+                return Err(eyre!("First run failed"));
+            }
+
+            Ok(None)
         }
     }
+
+    /// Panics on the first run and succeeds on every run after it.
+    #[derive(Debug, Default)]
+    struct PanickingJob {
+        runs: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl JobHandle for PanickingJob {
+        async fn execute(
+            &self,
+            _parameters: JobParameters,
+            _token: CancellationToken,
+            _tx: mpsc::Sender<JobEvent>,
+        ) -> Result<Option<JobArtifact>> {
+            if self.runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("Something went horribly wrong");
+            }
+
+            Ok(None)
+        }
+    }
+
+    fn concurrent_registry() -> JobRegistry {
+        let mut registry = registry();
+        let info = |name: &str| JobInfo::new(name, "Test", BTreeMap::new());
+
+        for (id, job) in [
+            ("fast", Job::new(info("Fast"), FastJob {})),
+            ("flaky", Job::new(info("Flaky"), FlakyJob::default())),
+            (
+                "panicking",
+                Job::new(info("Panicking").exclusive(), PanickingJob::default()),
+            ),
+            ("exclusive-a", Job::new(info("A").exclusive(), TestJob {})),
+            ("exclusive-b", Job::new(info("B").exclusive(), TestJob {})),
+        ] {
+            registry
+                .register_job(id, job)
+                .expect("Failed to register job");
+        }
+
+        registry
+    }
+
+    async fn status(manager: &JobManager, id: JobStateId) -> Option<JobStatus> {
+        manager.states().await.get(&id).map(|state| state.status)
+    }
+
     fn registry() -> JobRegistry {
         let mut registry = JobRegistry::new();
         registry
@@ -584,7 +1022,9 @@ mod tests {
         let manager = JobManager::new(registry());
 
         for _ in 0..100 {
-            let _ = manager.queue("test".to_string(), false, false).await?;
+            let _ = manager
+                .queue("test".to_string(), JobParameters::default(), false, false)
+                .await?;
         }
 
         Ok(())
@@ -594,8 +1034,102 @@ mod tests {
     async fn test_failing_adding_duplicate_jobs() -> Result<()> {
         let manager = JobManager::new(registry());
 
-        let _ = manager.queue("test", true, true).await;
-        assert!(manager.queue("test", true, true).await.is_err());
+        let _ = manager
+            .queue("test", JobParameters::default(), true, true)
+            .await;
+        assert!(
+            manager
+                .queue("test", JobParameters::default(), true, true)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_rejecting_unsupported_dry_runs() -> Result<()> {
+        let manager = JobManager::new(registry());
+
+        assert!(matches!(
+            manager
+                .queue(
+                    "test",
+                    JobParameters {
+                        dry_run: true,
+                        ..Default::default()
+                    },
+                    false,
+                    false
+                )
+                .await,
+            Err(JobManagerError::DryRunUnsupported)
+        ));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_rejecting_unsupported_step_selection() -> Result<()> {
+        let manager = JobManager::new(registry());
+
+        assert!(matches!(
+            manager
+                .queue(
+                    "test",
+                    JobParameters {
+                        steps: Some(BTreeSet::from([1])),
+                        ..Default::default()
+                    },
+                    false,
+                    false
+                )
+                .await,
+            Err(JobManagerError::StepSelectionUnsupported)
+        ));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_rejecting_unsupported_filters() -> Result<()> {
+        let manager = JobManager::new(registry());
+
+        assert!(matches!(
+            manager
+                .queue(
+                    "test",
+                    JobParameters {
+                        album: Some(String::from("Album")),
+                        ..Default::default()
+                    },
+                    false,
+                    false
+                )
+                .await,
+            Err(JobManagerError::FiltersUnsupported)
+        ));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_states_reflect_latest_progress() -> Result<()> {
+        let manager = JobManager::new(registry());
+        let job = manager
+            .queue("test", JobParameters::default(), false, false)
+            .await?;
+
+        sleep(Duration::from_millis(550)).await;
+
+        let progress = manager.states().await[&job.id()]
+            .progress
+            .expect("Progress should have been recorded");
+
+        assert!(progress.current >= 3);
+        assert_eq!(progress.total, u64::MAX);
+
+        manager.cancel_job(job.id()).await?;
 
         Ok(())
     }
@@ -603,7 +1137,9 @@ mod tests {
     #[test(tokio::test)]
     async fn test_cancelling_jobs() -> Result<()> {
         let manager = JobManager::new(registry());
-        let mut job = manager.queue("test", true, true).await?;
+        let mut job = manager
+            .queue("test", JobParameters::default(), true, true)
+            .await?;
         let id = job.state_id;
         log::debug!("{job:#?}");
 
@@ -631,4 +1167,215 @@ mod tests {
 
         Ok(())
     }
+
+    #[test(tokio::test)]
+    async fn test_fast_jobs_run_beside_slow_job() -> Result<()> {
+        let manager = JobManager::with_workers(concurrent_registry(), 2);
+        let mut events = manager.events();
+
+        let slow = manager
+            .queue("test", JobParameters::default(), true, false)
+            .await?;
+        for _ in 0..3 {
+            manager
+                .queue("fast", JobParameters::default(), false, false)
+                .await?;
+        }
+
+        let mut completed = 0;
+        let mut last_order = None;
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while completed < 3 {
+                match events.recv().await.expect("Events should be received") {
+                    JobManagerEvent::Completed { .. } => completed += 1,
+                    JobManagerEvent::OrderUpdated { queue } => last_order = Some(queue),
+                    _ => {}
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(last_order, Some(Vec::new()));
+        assert_eq!(
+            status(&manager, slow.id()).await,
+            Some(JobStatus::InProgress)
+        );
+        assert!(manager.reports().await["fast"].completed_successfully);
+
+        manager.cancel_job(slow.id()).await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_paused_queue_keeps_jobs_until_resumed() -> Result<()> {
+        let manager = JobManager::with_workers(concurrent_registry(), 2);
+        manager.pause();
+
+        let first = manager
+            .queue("fast", JobParameters::default(), false, false)
+            .await?;
+        let second = manager
+            .queue("flaky", JobParameters::default(), false, false)
+            .await?;
+
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(status(&manager, first.id()).await, Some(JobStatus::Pending));
+        assert_eq!(manager.queue_order().await, vec![first.id(), second.id()]);
+
+        let mut events = manager.events();
+        manager.resume();
+
+        let started = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                if let JobManagerEvent::Started { source, .. } =
+                    events.recv().await.expect("Events should be received")
+                {
+                    break source;
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(started, first.id());
+        assert!(!manager.is_paused());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_exclusive_jobs_never_overlap() -> Result<()> {
+        let manager = JobManager::with_workers(concurrent_registry(), 3);
+
+        let first = manager
+            .queue("exclusive-a", JobParameters::default(), false, false)
+            .await?;
+        let second = manager
+            .queue("exclusive-b", JobParameters::default(), false, false)
+            .await?;
+        let fast = manager
+            .queue("fast", JobParameters::default(), false, false)
+            .await?;
+
+        sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(
+            status(&manager, first.id()).await,
+            Some(JobStatus::InProgress)
+        );
+        assert_eq!(
+            status(&manager, second.id()).await,
+            Some(JobStatus::Pending)
+        );
+        assert_eq!(status(&manager, fast.id()).await, None);
+
+        manager.cancel_job(first.id()).await?;
+        sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(status(&manager, first.id()).await, None);
+        assert_eq!(
+            status(&manager, second.id()).await,
+            Some(JobStatus::InProgress)
+        );
+
+        manager.cancel_job(second.id()).await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_reports_ignore_runs_finishing_out_of_order() -> Result<()> {
+        let manager = JobManager::with_workers(concurrent_registry(), 2);
+
+        let first = manager
+            .queue("flaky", JobParameters::default(), false, false)
+            .await?;
+        sleep(Duration::from_millis(50)).await;
+        let second = manager
+            .queue("flaky", JobParameters::default(), false, false)
+            .await?;
+
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            status(&manager, first.id()).await,
+            Some(JobStatus::InProgress)
+        );
+        assert_eq!(status(&manager, second.id()).await, None);
+        assert!(manager.reports().await["flaky"].completed_successfully);
+
+        sleep(Duration::from_millis(400)).await;
+        assert_eq!(status(&manager, first.id()).await, None);
+
+        let reports = manager.reports().await;
+        assert!(reports["flaky"].completed_successfully);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_panicking_job_is_reported_as_failed() -> Result<()> {
+        let manager = JobManager::new(concurrent_registry());
+        let mut events = manager.events();
+
+        let panicked = manager
+            .queue("panicking", JobParameters::default(), true, false)
+            .await?;
+        sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(status(&manager, panicked.id()).await, None);
+        assert!(!manager.reports().await["panicking"].completed_successfully);
+
+        let mut failure = None;
+        while let Ok(event) = events.try_recv() {
+            if let JobManagerEvent::Failed { message, .. } = event {
+                failure = Some(message);
+            }
+        }
+        assert_eq!(
+            failure.as_deref(),
+            Some("Job panicked: Something went horribly wrong")
+        );
+
+        // Neither the queue nor the exclusive slot stay taken by the run that panicked.
+        let next = manager
+            .queue("panicking", JobParameters::default(), true, false)
+            .await?;
+        sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(status(&manager, next.id()).await, None);
+        assert!(manager.reports().await["panicking"].completed_successfully);
+        assert!(manager.queue_order().await.is_empty());
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_shutdown_cancels_running_jobs() -> Result<()> {
+        let manager = JobManager::new(registry());
+
+        let running = manager
+            .queue("test", JobParameters::default(), false, false)
+            .await?;
+        let queued = manager
+            .queue("test", JobParameters::default(), false, false)
+            .await?;
+        sleep(Duration::from_millis(200)).await;
+
+        assert!(manager.shutdown(Duration::from_secs(5)).await);
+        assert_eq!(status(&manager, running.id()).await, None);
+
+        let report = &manager.reports().await["test"];
+        assert!(report.cancelled_at.is_some());
+        assert!(report.completed_at.is_none());
+        assert!(!report.completed_successfully);
+
+        // The queued run never starts once the manager is shut down.
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            status(&manager, queued.id()).await,
+            Some(JobStatus::Pending)
+        );
+
+        Ok(())
+    }
 }
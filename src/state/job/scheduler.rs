@@ -0,0 +1,192 @@
+//! Queues jobs on the schedules saved through `/api/jobs/schedules`.
+//!
+//! Scheduled jobs are queued as unique jobs and skipped while an instance is already queued or
+//! running, so a run that outlasts its interval doesn't pile up duplicates.
+
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tokio::time::MissedTickBehavior;
+
+use crate::{
+    db::{DatabaseError, schedules},
+    state::{JobManager, Pool},
+};
+
+use super::{JobId, JobParameters, manager::JobManagerError};
+
+/// How often the schedules are checked for jobs that are due.
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Spawns the task queueing scheduled jobs for as long as the app runs.
+pub fn spawn(pool: Pool, manager: JobManager) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(TICK_INTERVAL);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            ticks.tick().await;
+
+            if let Err(err) = queue_due_jobs(&pool, &manager, OffsetDateTime::now_utc()).await {
+                tracing::error!("Failed to queue scheduled jobs: {err}");
+            }
+        }
+    });
+}
+
+/// Queues every job whose schedule is due at `now`, returning the ids of the queued jobs.
+pub async fn queue_due_jobs(
+    pool: &Pool,
+    manager: &JobManager,
+    now: OffsetDateTime,
+) -> Result<Vec<JobId>, DatabaseError> {
+    let mut connection = pool.acquire().await?;
+    let mut queued = Vec::new();
+
+    for schedule in schedules::get_schedules(&mut connection).await? {
+        if schedule
+            .next_run_at
+            .is_none_or(|next_run_at| next_run_at > now)
+        {
+            continue;
+        }
+
+        // Skipped runs still count, otherwise the schedule would fire again as soon as the
+        // instance that's already running finishes.
+        schedules::mark_schedule_run(&mut connection, &schedule.job_id, now).await?;
+
+        if manager.is_active(&schedule.job_id).await {
+            tracing::info!(
+                "Skipping scheduled run of \"{}\", it's already queued or running",
+                schedule.job_id
+            );
+            continue;
+        }
+
+        match manager
+            .queue(
+                schedule.job_id.clone(),
+                JobParameters::default(),
+                true,
+                false,
+            )
+            .await
+        {
+            Ok(_) => {
+                tracing::info!("Queued scheduled run of \"{}\"", schedule.job_id);
+                queued.push(schedule.job_id);
+            }
+            Err(JobManagerError::AlreadyQueued) => {
+                tracing::info!(
+                    "Skipping scheduled run of \"{}\", it's already queued",
+                    schedule.job_id
+                );
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to queue scheduled run of \"{}\": {err}",
+                    schedule.job_id
+                );
+            }
+        }
+    }
+
+    Ok(queued)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    use test_log::test;
+
+    use super::*;
+    use crate::{
+        db::{NewJobSchedule, ScheduleTrigger, test_utils::pool_with_songs},
+        jobs::{JobArtifact, JobEvent, JobHandle},
+        state::job::{Job, JobInfo, JobRegistry, manager},
+    };
+
+    /// Runs until it's cancelled, like a scan outlasting its interval.
+    #[derive(Debug)]
+    struct EndlessJob;
+
+    #[async_trait]
+    impl JobHandle for EndlessJob {
+        async fn execute(
+            &self,
+            _parameters: JobParameters,
+            token: CancellationToken,
+            _tx: mpsc::Sender<JobEvent>,
+        ) -> color_eyre::Result<Option<JobArtifact>> {
+            token.cancelled().await;
+            Ok(None)
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_skipping_overlapping_runs() {
+        let pool = pool_with_songs(&[]).await;
+        let mut registry = JobRegistry::new();
+        registry
+            .register_job(
+                "endless",
+                Job::new(
+                    JobInfo::new("Endless", "Never finishes", BTreeMap::new()),
+                    EndlessJob,
+                ),
+            )
+            .unwrap();
+        let manager = Arc::new(manager::JobManager::new(registry));
+
+        let mut connection = pool.acquire().await.unwrap();
+        schedules::set_schedule(
+            &mut connection,
+            "endless",
+            &NewJobSchedule {
+                trigger: ScheduleTrigger::Interval { seconds: 60 },
+                enabled: true,
+            },
+        )
+        .await
+        .unwrap();
+        drop(connection);
+
+        let now = OffsetDateTime::now_utc();
+
+        assert!(
+            queue_due_jobs(&pool, &manager, now)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            queue_due_jobs(&pool, &manager, now + Duration::from_secs(120))
+                .await
+                .unwrap(),
+            ["endless"]
+        );
+        assert!(
+            queue_due_jobs(&pool, &manager, now + Duration::from_secs(240))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(manager.states().await.len(), 1);
+
+        let mut connection = pool.acquire().await.unwrap();
+        let schedule = schedules::get_schedule(&mut connection, "endless")
+            .await
+            .unwrap();
+        assert!(schedule.last_run_at.is_some());
+        assert!(
+            schedule
+                .next_run_at
+                .is_some_and(|next_run_at| next_run_at > now + Duration::from_secs(240))
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Maintenance mode, in which the server stops touching the library, e.g. while it's backed up.
+//!
+//! The job queue and file operations are paused while it's on and requests that would change
+//! files are turned away, see [`crate::api::maintenance`]. It's stored in the database, so a
+//! restart doesn't end it.
+
+use std::sync::Arc;
+
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::{
+    db::{DatabaseError, MaintenanceMode, maintenance},
+    events::{AppEvent, AppEventKind, EventBus},
+};
+
+use super::{FileOperationManager, JobManager, Pool};
+
+#[derive(Debug)]
+pub struct Maintenance {
+    pool: Pool,
+    job_manager: JobManager,
+    file_operation_manager: FileOperationManager,
+    events: EventBus,
+    /// Locked while maintenance is started or ended, so toggling it quickly applies every change
+    /// in order and readers never see it half applied.
+    mode: Arc<Mutex<Option<MaintenanceMode>>>,
+}
+
+impl Maintenance {
+    /// Creates the maintenance mode and spawns the task restoring it from the database.
+    ///
+    /// Jobs and file operations are paused and the mode is locked until it's restored, so
+    /// nothing slips through between the start of the server and reading the database.
+    pub fn new(
+        pool: Pool,
+        job_manager: JobManager,
+        file_operation_manager: FileOperationManager,
+        events: EventBus,
+    ) -> Arc<Self> {
+        let mode = Arc::new(Mutex::new(None));
+        let guard = mode
+            .clone()
+            .try_lock_owned()
+            .expect("Maintenance mode shouldn't be locked yet");
+
+        job_manager.pause();
+        file_operation_manager.pause();
+
+        let maintenance = Arc::new(Self {
+            pool,
+            job_manager,
+            file_operation_manager,
+            events,
+            mode,
+        });
+
+        let restoring = maintenance.clone();
+        tokio::spawn(async move {
+            if let Err(err) = restoring.restore(guard).await {
+                tracing::error!("Failed to restore maintenance mode: {err}");
+                restoring.resume();
+            }
+        });
+
+        maintenance
+    }
+
+    /// Returns the maintenance the server is in, waiting for it to be started or ended first if
+    /// that's underway.
+    pub async fn mode(&self) -> Option<MaintenanceMode> {
+        self.mode.lock().await.clone()
+    }
+
+    /// Puts the server in maintenance, returns the current one if it already is.
+    pub async fn start(&self, reason: Option<String>) -> Result<MaintenanceMode, DatabaseError> {
+        let mut mode = self.mode.lock().await;
+        if let Some(mode) = mode.as_ref() {
+            return Ok(mode.clone());
+        }
+
+        let started = MaintenanceMode {
+            started_at: OffsetDateTime::now_utc(),
+            reason,
+        };
+
+        let mut connection = self.pool.acquire().await?;
+        maintenance::set_maintenance(&mut connection, &started).await?;
+        drop(connection);
+
+        self.pause();
+        *mode = Some(started.clone());
+
+        self.events.publish(AppEvent::new(
+            AppEventKind::MaintenanceStarted,
+            describe(&started),
+        ));
+
+        Ok(started)
+    }
+
+    /// Takes the server out of maintenance, see [`Maintenance::resume`].
+    pub async fn end(&self) -> Result<(), DatabaseError> {
+        let mut mode = self.mode.lock().await;
+        if mode.is_none() {
+            return Ok(());
+        }
+
+        let mut connection = self.pool.acquire().await?;
+        maintenance::clear_maintenance(&mut connection).await?;
+        drop(connection);
+
+        self.resume();
+        *mode = None;
+
+        self.events.publish(AppEvent::new(
+            AppEventKind::MaintenanceEnded,
+            "Maintenance ended",
+        ));
+
+        Ok(())
+    }
+
+    /// Keeps everything paused if the server was in maintenance when it stopped, resumes it
+    /// otherwise.
+    async fn restore(
+        &self,
+        mut mode: OwnedMutexGuard<Option<MaintenanceMode>>,
+    ) -> Result<(), DatabaseError> {
+        let mut connection = self.pool.acquire().await?;
+        let Some(restored) = maintenance::get_maintenance(&mut connection).await? else {
+            self.resume();
+            return Ok(());
+        };
+
+        tracing::info!("{}", describe(&restored));
+        self.events.publish(AppEvent::new(
+            AppEventKind::MaintenanceStarted,
+            describe(&restored),
+        ));
+        *mode = Some(restored);
+
+        Ok(())
+    }
+
+    fn pause(&self) {
+        self.job_manager.pause();
+        self.file_operation_manager.pause();
+    }
+
+    /// Lets the file operations that were paused halfway finish before queued jobs can start.
+    fn resume(&self) {
+        self.file_operation_manager.resume();
+        self.job_manager.resume();
+    }
+}
+
+fn describe(mode: &MaintenanceMode) -> String {
+    match &mode.reason {
+        Some(reason) => format!("In maintenance: {reason}"),
+        None => String::from("In maintenance"),
+    }
+}
@@ -0,0 +1,419 @@
+//! Watches registered directories and applies song changes as they happen.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    sync::PoisonError,
+    time::Duration,
+};
+
+use color_eyre::eyre;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::{
+    sync::{Mutex, broadcast, mpsc},
+    task::spawn_blocking,
+};
+use ts_rs::TS;
+
+use crate::{
+    db::{
+        self,
+        directories::find_directory_from_sub_path,
+        scan_errors::get_failed_paths,
+        songs::{SongInsert, SongUpdate},
+    },
+    jobs::{IGNORE_FILE_NAMES, SongRead, is_song_file, save_read_outcomes, song_walker},
+};
+
+use super::{Pool, SharedCoverArtCache, SharedSettings, SharedSongFileTypes};
+
+/// How long to wait for the file system to settle before applying changes.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+type Result<T, E = DirectoryWatcherError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DirectoryWatcherError {
+    #[error("Failed to watch directory: {0}")]
+    Notify(#[from] notify::Error),
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum DirectoryWatcherEvent {
+    SongsChanged {
+        added: usize,
+        updated: usize,
+        /// Songs whose file was removed, they're marked as missing rather than deleted.
+        removed: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct DirectoryWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    events: broadcast::Sender<DirectoryWatcherEvent>,
+}
+
+impl DirectoryWatcher {
+    pub fn new(
+        pool: Pool,
+        settings: SharedSettings,
+        cover_art_cache: SharedCoverArtCache,
+        song_file_types: SharedSongFileTypes,
+    ) -> Result<Self> {
+        let (events, _) = broadcast::channel(256);
+        let (tx, rx) = mpsc::unbounded_channel::<PathBuf>();
+
+        let watcher =
+            notify::recommended_watcher(
+                move |result: notify::Result<notify::Event>| match result {
+                    Ok(event) => {
+                        if matches!(
+                            event.kind,
+                            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                        ) {
+                            for path in event.paths {
+                                let _ = tx.send(path);
+                            }
+                        }
+                    }
+                    Err(err) => tracing::warn!("Directory watcher error: {err}"),
+                },
+            )?;
+
+        tokio::spawn(Self::process(
+            pool,
+            settings,
+            cover_art_cache,
+            song_file_types,
+            rx,
+            events.clone(),
+        ));
+
+        Ok(Self {
+            watcher: Mutex::new(watcher),
+            events,
+        })
+    }
+
+    pub async fn watch(&self, path: &Path) -> Result<()> {
+        tracing::debug!("Watching directory: {path:?}");
+        self.watcher
+            .lock()
+            .await
+            .watch(path, RecursiveMode::Recursive)?;
+
+        Ok(())
+    }
+
+    pub async fn unwatch(&self, path: &Path) -> Result<()> {
+        tracing::debug!("Stopped watching directory: {path:?}");
+        self.watcher.lock().await.unwatch(path)?;
+
+        Ok(())
+    }
+
+    pub fn events(&self) -> broadcast::Receiver<DirectoryWatcherEvent> {
+        self.events.subscribe()
+    }
+
+    /// Collects changed paths until nothing has changed for [`DEBOUNCE`], then applies them.
+    async fn process(
+        pool: Pool,
+        settings: SharedSettings,
+        cover_art_cache: SharedCoverArtCache,
+        song_file_types: SharedSongFileTypes,
+        mut rx: mpsc::UnboundedReceiver<PathBuf>,
+        events: broadcast::Sender<DirectoryWatcherEvent>,
+    ) {
+        while let Some(path) = rx.recv().await {
+            let mut paths = HashSet::from([path]);
+
+            while let Ok(Some(path)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                paths.insert(path);
+            }
+
+            let read_audio_properties = settings
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .scan
+                .read_audio_properties;
+            let mut changed_albums = HashSet::new();
+            let result = apply_changes(
+                &pool,
+                paths,
+                &song_file_types.get(),
+                read_audio_properties,
+                &mut changed_albums,
+            )
+            .await;
+
+            for album in changed_albums {
+                cover_art_cache.invalidate_album(&album).await;
+            }
+
+            let event = match result {
+                Ok((0, 0, 0)) => continue,
+                Ok((added, updated, removed)) => {
+                    tracing::info!(
+                        "Directory watcher applied changes: {added} added, {updated} updated, {removed} removed"
+                    );
+
+                    DirectoryWatcherEvent::SongsChanged {
+                        added,
+                        updated,
+                        removed,
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to apply directory changes: {err}");
+                    DirectoryWatcherEvent::Failed {
+                        error: err.to_string(),
+                    }
+                }
+            };
+
+            if events.send(event).is_err() {
+                tracing::debug!("No listeners for directory watcher event");
+            }
+        }
+    }
+}
+
+/// Applies the changed paths to the database, returns the amount of added, updated and removed
+/// songs.
+///
+/// Files are read like the scan job reads them, failed reads are saved with the scan errors.
+/// Albums of updated and removed songs are collected into `changed_albums`.
+async fn apply_changes(
+    pool: &Pool,
+    paths: HashSet<PathBuf>,
+    file_types: &BTreeSet<String>,
+    read_audio_properties: bool,
+    changed_albums: &mut HashSet<String>,
+) -> eyre::Result<(usize, usize, u64)> {
+    let mut connection = pool.acquire().await?;
+    let directories = db::directories::get_directories(&mut connection)
+        .await?
+        .into_iter()
+        .map(|directory| (directory.name, directory.path))
+        .collect::<Vec<_>>();
+    let failed_paths = get_failed_paths(&mut connection).await?;
+
+    let paths = spawn_blocking(move || {
+        paths
+            .into_iter()
+            .flat_map(|path| {
+                if path.is_dir() {
+                    song_walker(&path)
+                        .build()
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+                        .map(|entry| entry.into_path())
+                        .collect()
+                } else {
+                    vec![path]
+                }
+            })
+            .collect::<HashSet<_>>()
+    })
+    .await?;
+
+    let mut removed_paths = Vec::new();
+    let mut song_paths = Vec::new();
+
+    for path in paths {
+        let path_str = path.to_string_lossy().to_string();
+        let Some((_, directory)) = find_directory_from_sub_path(&directories, &path_str) else {
+            continue;
+        };
+
+        if !path.exists() {
+            removed_paths.push(path_str);
+            continue;
+        }
+
+        if !is_song_file(&path, file_types) || is_ignored(Path::new(directory), &path) {
+            continue;
+        }
+
+        // Tracks of a cue sheet get their tags from the sheet, the scan job keeps them in sync.
+        if db::songs::is_cue_backed(&mut connection, &path_str).await? {
+            continue;
+        }
+
+        song_paths.push(path);
+    }
+
+    drop(connection);
+
+    // The files are read before the transaction starts, so other writers aren't blocked meanwhile.
+    let reads = spawn_blocking(move || {
+        song_paths
+            .into_iter()
+            .map(|path| SongRead::read(path, read_audio_properties))
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    let now = OffsetDateTime::now_utc();
+    let mut transaction = pool.begin().await?;
+    let mut removed = 0;
+
+    for path in &removed_paths {
+        changed_albums.extend(db::songs::get_albums_by_path(&mut transaction, path).await?);
+        // Removed files are only marked as missing, like the scan job does, the scan deletes them
+        // once the grace period is over.
+        removed += db::songs::mark_songs_missing_by_path(&mut transaction, path, now).await?;
+    }
+
+    let mut new_songs = Vec::new();
+    let mut song_updates = Vec::new();
+    let mut failed_reads = Vec::new();
+    let mut recovered_paths = Vec::new();
+
+    for mut read in reads {
+        let path = read.path.to_string_lossy().to_string();
+        let is_song = read.is_song();
+
+        match read.error.take() {
+            Some(error) => {
+                tracing::warn!("Failed to read \"{path}\": {}", error.error);
+                failed_reads.push(error);
+            }
+            None if failed_paths.contains_key(&path) => recovered_paths.push(path.clone()),
+            None => {}
+        }
+
+        if !is_song {
+            continue;
+        }
+
+        match db::songs::get_song_id_by_path(&mut transaction, &path).await? {
+            Some(id) => {
+                let song = read.updated_song();
+                changed_albums.extend(db::songs::get_song(&mut transaction, &id).await?.album);
+                changed_albums.extend(song.album.clone());
+
+                song_updates.push(SongUpdate {
+                    id,
+                    song,
+                    properties: read.properties,
+                });
+            }
+            None => new_songs.push(SongInsert {
+                song: read.new_song(),
+                range: None,
+                properties: read.properties,
+            }),
+        }
+    }
+
+    let added = db::songs::add_songs(&mut transaction, &new_songs).await?;
+    let updated = db::songs::update_songs(&mut transaction, &song_updates).await?;
+    let updated_ids = song_updates
+        .into_iter()
+        .map(|update| update.id)
+        .collect::<Vec<_>>();
+    db::songs::restore_missing_songs(&mut transaction, &updated_ids).await?;
+    save_read_outcomes(&mut transaction, &failed_reads, &recovered_paths, now).await;
+
+    transaction.commit().await?;
+
+    Ok((added as usize, updated as usize, removed))
+}
+
+/// Checks the ignore files between `root` and the path, matching the rules the scan job uses.
+fn is_ignored(root: &Path, path: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        .take_while(|directory| directory.starts_with(root))
+        .flat_map(|directory| IGNORE_FILE_NAMES.map(|name| directory.join(name)))
+        .filter(|file| file.is_file())
+        .any(|file| {
+            let (ignore, _) = ignore::gitignore::Gitignore::new(file);
+            ignore.matched_path_or_any_parents(path, false).is_ignore()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{query, query_as, query_scalar};
+    use tempfile::tempdir;
+    use test_log::test;
+
+    use super::*;
+    use crate::db::test_utils::pool_with_songs;
+
+    #[test(tokio::test)]
+    async fn test_apply_changes() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let root = temp.path();
+        let nested = root.join("nested");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::copy("data/goose.flac", nested.join("goose.flac")).unwrap();
+        std::fs::write(nested.join("broken.flac"), "Not a song").unwrap();
+
+        let pool = pool_with_songs(&[]).await;
+        for (name, path) in [("outer", root), ("inner", nested.as_path())] {
+            query("INSERT INTO directories (name, path) VALUES (?, ?)")
+                .bind(name)
+                .bind(path.to_string_lossy())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let file_types = BTreeSet::from(["flac".to_string(), "mp3".to_string()]);
+        let paths = HashSet::from([nested.join("goose.flac"), nested.join("broken.flac")]);
+        let changes = apply_changes(&pool, paths, &file_types, false, &mut HashSet::new())
+            .await
+            .unwrap();
+        assert_eq!(changes, (1, 0, 0));
+
+        // The song belongs to the most nested directory, and its properties are left for later
+        // like the scan does when it doesn't read them.
+        let (directory_id, properties_read) =
+            query_as::<_, (String, bool)>("SELECT directory_id, properties_read FROM songs")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(directory_id, "inner");
+        assert!(!properties_read);
+
+        let failed = query_scalar::<_, String>("SELECT path FROM scan_errors")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            failed,
+            [nested.join("broken.flac").to_string_lossy().to_string()]
+        );
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let temp = tempdir().expect("Failed to create temp dir");
+        let root = temp.path();
+        let nested = root.join("album");
+
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.join(".muusik-ignore"), "skipped/\n").unwrap();
+        std::fs::write(nested.join(".muusikignore"), "*.wav\n").unwrap();
+
+        assert!(is_ignored(root, &root.join("skipped").join("song.mp3")));
+        assert!(is_ignored(root, &nested.join("song.wav")));
+        assert!(!is_ignored(root, &nested.join("song.flac")));
+        assert!(!is_ignored(root, &root.join("song.wav")));
+    }
+}
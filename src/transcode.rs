@@ -0,0 +1,131 @@
+//! Transcoding songs with ffmpeg while they're streamed, for clients that can't play the format
+//! they're stored in, like browsers with WMA.
+
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    process::Stdio,
+    task::{Context, Poll},
+};
+
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use tokio::{
+    process::{Child, ChildStdout, Command},
+    sync::OwnedSemaphorePermit,
+};
+use tokio_util::io::ReaderStream;
+use ts_rs::TS;
+
+/// Bitrates in kbit/s songs can be transcoded with.
+pub const BITRATES_KBPS: std::ops::RangeInclusive<u32> = 32..=320;
+
+/// Format songs are transcoded to, each is written in a container that can be streamed.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscodeFormat {
+    Mp3,
+    Opus,
+    Aac,
+}
+
+impl TranscodeFormat {
+    /// Name of the ffmpeg encoder writing the format.
+    fn encoder(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "libmp3lame",
+            Self::Opus => "libopus",
+            Self::Aac => "aac",
+        }
+    }
+
+    /// Name of the ffmpeg muxer, MP4 files can't be written to a pipe so AAC is sent as ADTS.
+    fn muxer(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Opus => "ogg",
+            Self::Aac => "adts",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Opus => "audio/ogg",
+            Self::Aac => "audio/aac",
+        }
+    }
+}
+
+/// The audio of a song as ffmpeg transcodes it.
+///
+/// ffmpeg is killed once the stream is dropped, which happens as soon as the client disconnects,
+/// and the permit it was started with is released along with it.
+pub struct Transcode {
+    output: ReaderStream<ChildStdout>,
+    _ffmpeg: Child,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Transcode {
+    /// Starts transcoding the part of the file between `start_ms` and `end_ms`, until the end of
+    /// the file if there's no end.
+    pub fn spawn(
+        ffmpeg: &str,
+        path: &Path,
+        (start_ms, end_ms): (u32, Option<u32>),
+        format: TranscodeFormat,
+        bitrate_kbps: u32,
+        permit: OwnedSemaphorePermit,
+    ) -> io::Result<Self> {
+        let mut command = Command::new(ffmpeg);
+        command.args(["-nostdin", "-hide_banner", "-loglevel", "error"]);
+
+        if start_ms > 0 {
+            command.args(["-ss", &seconds(start_ms)]);
+        }
+
+        if let Some(end_ms) = end_ms {
+            command.args(["-t", &seconds(end_ms.saturating_sub(start_ms))]);
+        }
+
+        let mut child = command
+            .arg("-i")
+            .arg(path)
+            .args(["-map", "0:a", "-map_metadata", "-1"])
+            .args(["-c:a", format.encoder()])
+            .args(["-b:a", &format!("{bitrate_kbps}k")])
+            .args(["-f", format.muxer(), "pipe:1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .expect("ffmpeg was spawned with a piped stdout");
+
+        Ok(Self {
+            output: ReaderStream::new(stdout),
+            _ffmpeg: child,
+            _permit: permit,
+        })
+    }
+}
+
+impl Stream for Transcode {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.output.poll_next_unpin(cx)
+    }
+}
+
+/// Formats milliseconds as the seconds ffmpeg expects.
+fn seconds(ms: u32) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
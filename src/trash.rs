@@ -0,0 +1,235 @@
+//! Deleted files kept around so they can be restored.
+//!
+//! Every deletion gets a directory of its own in the trash, named after when it happened. The
+//! deleted files are kept under `files/` at their original path, and `manifest.json` lists where
+//! they came from.
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use ts_rs::TS;
+
+use crate::config::Trash;
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const FILES_DIR_NAME: &str = "files";
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrashError {
+    #[error("Trash entry not found: {0}")]
+    NotFound(String),
+    #[error("Failed to access the trash: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid trash manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+}
+
+type Result<T, E = TrashError> = std::result::Result<T, E>;
+
+/// The files removed by a single deletion.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+    pub id: String,
+    #[ts(type = "Date")]
+    #[serde(with = "time::serde::rfc3339")]
+    pub deleted_at: OffsetDateTime,
+    /// Bytes the deleted files take up.
+    pub size: u64,
+    pub paths: Vec<TrashedPath>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedPath {
+    /// Where the file or directory was deleted from.
+    pub original: PathBuf,
+    /// Where it's kept, relative to the directory of the entry.
+    pub trashed: PathBuf,
+}
+
+impl TrashEntry {
+    pub fn new(deleted_at: OffsetDateTime) -> Self {
+        Self {
+            id: deleted_at.unix_timestamp_nanos().to_string(),
+            deleted_at,
+            size: 0,
+            paths: Vec::new(),
+        }
+    }
+
+    /// The directory the entry is kept in.
+    pub fn dir(&self, root: &Path) -> PathBuf {
+        root.join(&self.id)
+    }
+
+    /// Returns where a deleted path is kept, relative to the directory of the entry.
+    ///
+    /// Only the normal components of the path are kept, so it can't point outside of the entry.
+    pub fn trashed_path(original: &Path) -> PathBuf {
+        let relative = original
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .collect::<PathBuf>();
+
+        Path::new(FILES_DIR_NAME).join(relative)
+    }
+
+    /// Writes the manifest, replacing the previous one so it's never left half written.
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let dir = self.dir(root);
+        let manifest = dir.join(MANIFEST_FILE_NAME);
+        let partial = dir.join(format!("{MANIFEST_FILE_NAME}.partial"));
+
+        fs::create_dir_all(&dir)?;
+        fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&partial, &manifest)?;
+
+        Ok(())
+    }
+}
+
+/// Reads the entry with the id, ids are timestamps so anything else can't be one.
+pub fn read_entry(root: &Path, id: &str) -> Result<TrashEntry> {
+    if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(TrashError::NotFound(id.to_string()));
+    }
+
+    match fs::read(root.join(id).join(MANIFEST_FILE_NAME)) {
+        Ok(manifest) => Ok(serde_json::from_slice(&manifest)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Err(TrashError::NotFound(id.to_string()))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Returns every entry of the trash, oldest first.
+///
+/// Directories without a readable manifest are skipped, since there'd be no way to restore them.
+pub fn list_entries(root: &Path) -> Result<Vec<TrashEntry>> {
+    let mut entries = Vec::new();
+
+    let dirs = match fs::read_dir(root) {
+        Ok(dirs) => dirs,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(entries),
+        Err(err) => return Err(err.into()),
+    };
+
+    for dir in dirs {
+        let id = dir?.file_name().to_string_lossy().to_string();
+
+        match read_entry(root, &id) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => tracing::warn!("Skipping trash entry \"{id}\": {err}"),
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.deleted_at);
+
+    Ok(entries)
+}
+
+/// Permanently deletes the entry along with its files.
+pub fn purge_entry(root: &Path, id: &str) -> Result<TrashEntry> {
+    let entry = read_entry(root, id)?;
+    fs::remove_dir_all(entry.dir(root))?;
+
+    Ok(entry)
+}
+
+/// Purges the entries older than the maximum age, and then the oldest ones until the trash fits
+/// in the maximum size. Returns the purged entries.
+pub fn purge_expired(
+    root: &Path,
+    settings: &Trash,
+    now: OffsetDateTime,
+) -> Result<Vec<TrashEntry>> {
+    let mut entries = list_entries(root)?;
+    let mut size = entries.iter().map(|entry| entry.size).sum::<u64>();
+    let mut purged = Vec::new();
+
+    let expires_before = settings.max_age().map(|max_age| now - max_age);
+
+    while let Some(oldest) = entries.first() {
+        let expired = expires_before.is_some_and(|before| oldest.deleted_at < before);
+        let too_large = settings.max_size().is_some_and(|max_size| size > max_size);
+
+        if !expired && !too_large {
+            break;
+        }
+
+        let entry = entries.remove(0);
+        fs::remove_dir_all(entry.dir(root))?;
+        size = size.saturating_sub(entry.size);
+        purged.push(entry);
+    }
+
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn add_entry(root: &Path, deleted_at: OffsetDateTime, size: u64) -> TrashEntry {
+        let entry = TrashEntry {
+            size,
+            ..TrashEntry::new(deleted_at)
+        };
+        entry.save(root).unwrap();
+        entry
+    }
+
+    #[test]
+    fn test_trashed_path_stays_inside_entry() {
+        assert_eq!(
+            TrashEntry::trashed_path(Path::new("/music/../album/01.flac")),
+            Path::new("files/music/album/01.flac")
+        );
+    }
+
+    #[test]
+    fn test_read_entry() {
+        let root = tempfile::tempdir().unwrap();
+        let entry = add_entry(root.path(), OffsetDateTime::UNIX_EPOCH, 5);
+
+        assert_eq!(read_entry(root.path(), &entry.id).unwrap(), entry);
+        assert!(matches!(
+            read_entry(root.path(), "../trash"),
+            Err(TrashError::NotFound(_))
+        ));
+        assert!(matches!(
+            read_entry(root.path(), "1"),
+            Err(TrashError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_purging_expired_entries() {
+        let root = tempfile::tempdir().unwrap();
+        let now = OffsetDateTime::now_utc();
+        let settings = Trash {
+            max_size_mb: 1,
+            max_age_days: 7,
+        };
+
+        let expired = add_entry(root.path(), now - Duration::days(8), 10);
+        let oldest = add_entry(root.path(), now - Duration::days(2), 1024 * 1024);
+        let newest = add_entry(root.path(), now - Duration::days(1), 1024);
+
+        let purged = purge_expired(root.path(), &settings, now).unwrap();
+
+        assert_eq!(purged, [expired, oldest]);
+        assert_eq!(list_entries(root.path()).unwrap(), [newest]);
+    }
+}
@@ -0,0 +1,2500 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Method, StatusCode},
+};
+use serde_json::json;
+
+use common::{FixtureTags, TestApp, encode_segment};
+
+#[tokio::test]
+async fn test_library_flow() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.add_fixture(
+        "flip.mp3",
+        "flip.mp3",
+        FixtureTags {
+            title: "Flip",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 2,
+        },
+    );
+
+    // Adding a directory queues a scan of it.
+    let directory = app
+        .post(
+            "/api/directories/",
+            json!({ "path": app.library(), "displayName": "Library" }),
+        )
+        .await;
+    assert_eq!(directory["displayName"], "Library");
+    let library = directory["path"].as_str().unwrap();
+
+    let report = app.wait_for_job("scan-songs").await;
+    assert_eq!(report["completedSuccessfully"], true);
+
+    let page = app.get("/api/songs/?sortBy=title").await;
+    let songs = page["items"].as_array().unwrap();
+    assert_eq!(
+        songs
+            .iter()
+            .map(|song| song["title"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        ["Flip", "Goose"]
+    );
+    assert!(songs.iter().all(|song| song["album"] == "Fixture Album"));
+
+    let id = songs[1]["id"].as_str().unwrap();
+
+    app.put(
+        &format!("/api/songs/{id}"),
+        json!({
+            "title": "Renamed Goose",
+            "artist": "Fixture Artist",
+            "album": "Fixture Album",
+            "trackNumber": "1",
+        }),
+    )
+    .await;
+    app.post(&format!("/api/songs/{id}/refresh"), json!({}))
+        .await;
+
+    let song = app.get(&format!("/api/songs/{id}")).await;
+    assert_eq!(song["title"], "Renamed Goose");
+
+    let previews = app
+        .get(&format!(
+            "/api/albums/{}/organize",
+            encode_segment("Fixture Album")
+        ))
+        .await;
+    let previews = previews.as_array().unwrap();
+    assert_eq!(previews.len(), 2);
+    assert!(previews.iter().all(|preview| {
+        preview["collides"] == false && preview["newPath"].as_str().unwrap().starts_with(library)
+    }));
+    assert!(previews.iter().any(|preview| {
+        preview["newPath"]
+            .as_str()
+            .unwrap()
+            .contains("Renamed Goose")
+    }));
+}
+
+#[tokio::test]
+async fn test_albums_by_id() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Back/Forth",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let albums = app.get("/api/albums/").await;
+    let albums = albums.as_array().unwrap();
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0]["title"], "Back/Forth");
+    let id = albums[0]["id"].as_str().unwrap();
+
+    let album = app.get(&format!("/api/albums/by-id/{id}")).await;
+    assert_eq!(album["title"], "Back/Forth");
+    assert_eq!(album["tracks"].as_array().unwrap().len(), 2);
+
+    let previews = app.get(&format!("/api/albums/by-id/{id}/organize")).await;
+    assert_eq!(previews.as_array().unwrap().len(), 2);
+    assert!(
+        app.get(&format!("/api/albums/by-id/{id}/cover-art"))
+            .await
+            .is_array()
+    );
+
+    // Albums of different artists sharing a title get ids of their own.
+    let songs = app.get("/api/songs/?sortBy=title").await["items"].clone();
+    for (song, album_artist) in songs.as_array().unwrap().iter().zip(["First", "Second"]) {
+        app.put(
+            &format!("/api/songs/{}", song["id"].as_str().unwrap()),
+            json!({
+                "title": song["title"],
+                "artist": "Fixture Artist",
+                "album": "Back/Forth",
+                "albumArtist": album_artist,
+                "trackNumber": "1",
+            }),
+        )
+        .await;
+    }
+
+    let albums = app.get("/api/albums/").await;
+    let albums = albums.as_array().unwrap();
+    assert_eq!(albums.len(), 2);
+    assert_ne!(albums[0]["id"], albums[1]["id"]);
+    for album in albums {
+        let found = app
+            .get(&format!(
+                "/api/albums/by-id/{}",
+                album["id"].as_str().unwrap()
+            ))
+            .await;
+        assert_eq!(found["artist"], album["artist"]);
+        assert_eq!(found["tracks"].as_array().unwrap().len(), 1);
+    }
+
+    for uri in [
+        "/api/albums/by-id/missing",
+        "/api/albums/by-id/missing/organize",
+        "/api/albums/by-id/missing/cover-art/front.jpg",
+    ] {
+        let (status, _) = app.request(Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_routes_and_ids() {
+    let app = TestApp::new().await;
+
+    let (status, _) = app.request(Method::GET, "/api/songs/missing", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app
+        .request(Method::POST, "/api/jobs/missing/queue", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/api/directories/",
+            Some(json!({ "path": "/" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_lyrics_flow() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let page = app.get("/api/songs/").await;
+    let id = page["items"][0]["id"].as_str().unwrap();
+    assert!(page["items"][0].get("lyrics").is_none());
+
+    let lyrics_uri = format!("/api/songs/{id}/lyrics");
+    assert_eq!(
+        app.get(&lyrics_uri).await,
+        json!({ "lyrics": null, "synchronized": false })
+    );
+
+    let lyrics = "[00:01.00]Honk; honk\n[00:02.50]  Honk again\n";
+    let saved = app.put(&lyrics_uri, json!({ "lyrics": lyrics })).await;
+    assert_eq!(saved, json!({ "lyrics": lyrics, "synchronized": true }));
+    assert_eq!(app.get(&lyrics_uri).await, saved);
+
+    // A rescan reads the same lyrics back from the file.
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    app.wait_for_job("scan-songs").await;
+    assert_eq!(app.get(&lyrics_uri).await["lyrics"], lyrics);
+
+    let removed = app.put(&lyrics_uri, json!({ "lyrics": "  " })).await;
+    assert_eq!(removed["lyrics"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn test_song_ratings() {
+    let app = TestApp::new().await;
+
+    for (sample, title) in [("goose.flac", "Goose"), ("bumm.m4a", "Bumm")] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track: 1,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let page = app.get("/api/songs/?sortBy=title").await;
+    let bumm = page["items"][0]["id"].as_str().unwrap();
+    let goose = page["items"][1]["id"].as_str().unwrap();
+    assert_eq!(page["items"][0]["rating"], serde_json::Value::Null);
+
+    let rated = app
+        .put(
+            &format!("/api/songs/{goose}/rating"),
+            json!({ "rating": 80 }),
+        )
+        .await;
+    assert_eq!(rated["rating"], 80);
+
+    // MP4 tags have nowhere to store a rating, it's only kept in the library.
+    let rated = app
+        .put(
+            &format!("/api/songs/{bumm}/rating"),
+            json!({ "rating": 40 }),
+        )
+        .await;
+    assert_eq!(rated["rating"], 40);
+
+    let (status, _) = app
+        .request(
+            Method::PUT,
+            &format!("/api/songs/{goose}/rating"),
+            Some(json!({ "rating": 101 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let page = app.get("/api/songs/?sortBy=rating&order=desc").await;
+    assert_eq!(page["items"][0]["title"], "Goose");
+    assert_eq!(page["items"][1]["title"], "Bumm");
+    let page = app.get("/api/songs/?minRating=60").await;
+    assert_eq!(page["total"], 1);
+
+    // A rescan reads the rating back from the tag, without dropping the one only in the library.
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    app.wait_for_job("scan-songs").await;
+    let metadata = app
+        .post(&format!("/api/songs/{goose}/refresh"), json!({}))
+        .await;
+    assert_eq!(metadata["rating"], "80");
+    assert_eq!(app.get(&format!("/api/songs/{goose}")).await["rating"], 80);
+    assert_eq!(app.get(&format!("/api/songs/{bumm}")).await["rating"], 40);
+
+    let removed = app
+        .put(
+            &format!("/api/songs/{goose}/rating"),
+            json!({ "rating": null }),
+        )
+        .await;
+    assert_eq!(removed["rating"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn test_file_info_reports_health() {
+    let app = TestApp::new().await;
+
+    let path = app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let page = app.get("/api/songs/").await;
+    let id = page["items"][0]["id"].as_str().unwrap();
+
+    let info = app
+        .get(&format!("/api/songs/{id}/file-info?probe=true"))
+        .await;
+    assert_eq!(info["readable"], true);
+    assert_eq!(info["writable"], true);
+    assert!(info["recentlyModified"].is_boolean());
+    assert_eq!(info["capabilities"]["tagType"], info["tagType"]);
+    assert_eq!(info["capabilities"]["pictures"], true);
+    assert!(info["capabilities"]["fields"].is_array());
+    assert_eq!(info["warnings"], json!([]));
+
+    let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(&path, permissions).unwrap();
+
+    let info = app.get(&format!("/api/songs/{id}/file-info")).await;
+    assert_eq!(info["writable"], false);
+
+    let (status, _) = app
+        .request(
+            Method::PUT,
+            &format!("/api/songs/{id}"),
+            Some(json!({ "title": "Renamed Goose" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_configurable_file_types() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+
+    let titles = || async {
+        app.get("/api/songs/?sortBy=title").await["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|song| song["title"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let (status, _) = app
+        .request(
+            Method::PUT,
+            "/api/settings/file-types",
+            Some(json!({ "fileTypes": ["mp3", ".flac"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    app.put("/api/settings/file-types", json!({ "fileTypes": ["mp3"] }))
+        .await;
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+    assert_eq!(titles().await, ["Flip"]);
+
+    // Enabling an extension picks its files up on the next scan.
+    let file_types = app
+        .put(
+            "/api/settings/file-types",
+            json!({ "fileTypes": ["FLAC", "mp3"] }),
+        )
+        .await;
+    assert_eq!(file_types, json!({ "fileTypes": ["flac", "mp3"] }));
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    app.wait_for_job("scan-songs").await;
+    assert_eq!(titles().await, ["Flip", "Goose"]);
+
+    // Disabling one keeps the songs that were already scanned.
+    app.put("/api/settings/file-types", json!({ "fileTypes": ["flac"] }))
+        .await;
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    app.wait_for_job("scan-songs").await;
+    assert_eq!(titles().await, ["Flip", "Goose"]);
+    assert_eq!(
+        app.get("/api/settings/file-types").await,
+        json!({ "fileTypes": ["flac"] })
+    );
+}
+
+#[tokio::test]
+async fn test_settings_flow() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let preview_uri = format!(
+        "/api/albums/{}/organize?templateName=flat",
+        encode_segment("Fixture Album")
+    );
+    let (status, _) = app.request(Method::GET, &preview_uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app
+        .request(
+            Method::PATCH,
+            "/api/settings",
+            Some(json!({ "server": { "port": "not a port" } })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Templates apply right away, the port only after a restart.
+    let update = app
+        .expect_ok(
+            Method::PATCH,
+            "/api/settings",
+            Some(json!({
+                "server": { "port": 9999 },
+                "organize": { "templates": { "flat": "{{title}}" } },
+                "providers": { "acoustid": { "api_key": "secret" } },
+            })),
+        )
+        .await;
+    assert_eq!(
+        update["requires_restart"],
+        json!(["providers.acoustid.api_key", "server.port"])
+    );
+    assert_eq!(update["settings"]["server"]["port"], 9999);
+
+    let previews = app.get(&preview_uri).await;
+    assert!(
+        previews[0]["newPath"]
+            .as_str()
+            .unwrap()
+            .ends_with("Goose.flac")
+    );
+
+    let settings = app.get("/api/settings").await;
+    assert_eq!(settings["organize"]["templates"]["flat"], "{{title}}");
+    assert!(settings["providers"]["acoustid"].get("api_key").is_none());
+}
+
+#[tokio::test]
+async fn test_library_export_and_import() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    for format in ["json", "csv"] {
+        let (status, export) = app
+            .request_bytes(
+                Method::GET,
+                &format!("/api/library/export?format={format}"),
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Importing into the same library matches every song by its path.
+        for (on_conflict, counter) in [("skip", "songsSkipped"), ("overwrite", "songsUpdated")] {
+            let (status, summary) = app
+                .request_bytes(
+                    Method::POST,
+                    &format!("/api/library/import?format={format}&onConflict={on_conflict}"),
+                    export.clone(),
+                )
+                .await;
+            assert_eq!(status, StatusCode::OK);
+
+            let summary: serde_json::Value = serde_json::from_slice(&summary).unwrap();
+            assert_eq!(summary["directoriesMatched"], 1);
+            assert_eq!(summary["songsAdded"], 0);
+            assert_eq!(summary[counter], 1);
+        }
+    }
+
+    let (status, _) = app
+        .request_bytes(
+            Method::POST,
+            "/api/library/import",
+            "{\"record\":\"song\",\"id\":\"song\"}\n",
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_browse_filesystem() {
+    let app = TestApp::new().await;
+    let library = app.library();
+
+    for folder in ["Album", "Empty"] {
+        std::fs::create_dir(library.join(folder)).unwrap();
+    }
+    app.add_fixture(
+        "goose.flac",
+        "Album/goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    std::fs::write(library.join("notes.txt"), "honk").unwrap();
+
+    let uri = format!("/api/directories/filesystem/{}", library.display());
+    let names = |page: &serde_json::Value| {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let page = app.get(&uri).await;
+    assert_eq!(page["total"], 3);
+    assert_eq!(names(&page), ["Album", "Empty", "notes.txt"]);
+    assert_eq!(page["items"][0]["containsAudio"], true);
+    assert_eq!(page["items"][1]["containsAudio"], false);
+    assert_eq!(page["items"][2]["isDir"], false);
+    assert_eq!(page["items"][2]["size"], 4);
+
+    assert_eq!(
+        names(&app.get(&format!("{uri}?filter=EMP")).await),
+        ["Empty"]
+    );
+
+    let page = app.get(&format!("{uri}?offset=1&limit=1")).await;
+    assert_eq!(page["total"], 3);
+    assert_eq!(names(&page), ["Empty"]);
+
+    // Only the way to an allowed directory is listed outside of it.
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "browse": { "allowed_paths": [library.join("Album")] } })),
+    )
+    .await;
+    assert_eq!(names(&app.get(&uri).await), ["Album"]);
+    assert_eq!(
+        app.get(&format!("{uri}/Album")).await["items"][0]["name"],
+        "goose.flac"
+    );
+
+    let (status, _) = app
+        .request(Method::GET, &format!("{uri}/Empty"), None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_missing_songs_are_kept_until_purged() {
+    let app = TestApp::new().await;
+    let library = app.library();
+
+    std::fs::create_dir(library.join("Album")).unwrap();
+    app.add_fixture(
+        "goose.flac",
+        "Album/goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": library }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let song = app.get("/api/songs/").await["items"][0].clone();
+    let id = song["id"].as_str().unwrap();
+    let rescan = || async {
+        app.post("/api/jobs/scan-songs/queue", json!({})).await;
+        app.wait_for_job("scan-songs").await;
+    };
+
+    // The folder vanishes like a share that's no longer mounted.
+    let unmounted = library.with_file_name("unmounted");
+    std::fs::rename(library.join("Album"), &unmounted).unwrap();
+    rescan().await;
+
+    assert_eq!(app.get("/api/songs/").await["total"], 0);
+    let page = app.get("/api/songs/?includeMissing=true").await;
+    assert_eq!(page["items"][0]["id"], id);
+    assert!(page["items"][0]["missingSince"].is_string());
+
+    std::fs::rename(&unmounted, library.join("Album")).unwrap();
+    rescan().await;
+
+    let restored = app.get(&format!("/api/songs/{id}")).await;
+    assert!(restored["missingSince"].is_null());
+    assert_eq!(restored["addedAt"], song["addedAt"]);
+    assert_eq!(app.get("/api/songs/").await["total"], 1);
+
+    std::fs::rename(library.join("Album"), &unmounted).unwrap();
+    rescan().await;
+
+    let purged = app
+        .expect_ok(Method::DELETE, "/api/songs/missing", None)
+        .await;
+    assert_eq!(purged["songIds"], json!([id]));
+
+    let (status, _) = app
+        .request(Method::GET, &format!("/api/songs/{id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_scanning_thousands_of_songs() {
+    const SONGS: usize = 3000;
+
+    let app = TestApp::new().await;
+    let library = app.library();
+
+    let mut paths = Vec::with_capacity(SONGS);
+    for index in 0..SONGS {
+        let directory = library.join(format!("Album {}", index / 100));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join(format!("{index:04}.wav"));
+        std::fs::copy("data/flip.wav", &path).unwrap();
+        paths.push(path);
+    }
+
+    app.post("/api/directories/", json!({ "path": library }))
+        .await;
+    let report = app.wait_for_job("scan-songs").await;
+    assert_eq!(report["completedSuccessfully"], true);
+    assert_eq!(app.get("/api/songs/").await["total"], SONGS);
+
+    for path in paths.iter().step_by(3) {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    let report = app.wait_for_job("scan-songs").await;
+    assert_eq!(report["completedSuccessfully"], true);
+    assert_eq!(app.get("/api/songs/").await["total"], SONGS - SONGS / 3);
+    assert_eq!(
+        app.get("/api/songs/?includeMissing=true").await["total"],
+        SONGS
+    );
+}
+
+#[tokio::test]
+async fn test_scan_limited_to_directory() {
+    let app = TestApp::new().await;
+    let tags = |title| FixtureTags {
+        title,
+        artist: "Fixture Artist",
+        album: "Fixture Album",
+        track: 1,
+    };
+
+    // Changing the file types doesn't touch the files, so the directory watcher stays out of it.
+    app.put("/api/settings/file-types", json!({ "fileTypes": ["flac"] }))
+        .await;
+
+    let mut names = Vec::new();
+    for (folder, flip) in [("First", "First Flip"), ("Second", "Second Flip")] {
+        std::fs::create_dir(app.library().join(folder)).unwrap();
+        app.add_fixture("goose.flac", &format!("{folder}/goose.flac"), tags(folder));
+        app.add_fixture("flip.mp3", &format!("{folder}/flip.mp3"), tags(flip));
+
+        let directory = app
+            .post(
+                "/api/directories/",
+                json!({ "path": app.library().join(folder) }),
+            )
+            .await;
+        let name = directory["name"].as_str().unwrap().to_string();
+
+        // Only the directory that was just added is scanned.
+        let report = app.wait_for_job("scan-songs").await;
+        assert_eq!(report["directory"], name);
+        names.push(name);
+    }
+
+    let titles = || async {
+        app.get("/api/songs/?sortBy=title").await["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|song| song["title"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles().await, ["First", "Second"]);
+
+    app.put(
+        "/api/settings/file-types",
+        json!({ "fileTypes": ["flac", "mp3"] }),
+    )
+    .await;
+    app.post(
+        "/api/jobs/scan-songs/queue",
+        json!({ "directory": names[0] }),
+    )
+    .await;
+    let report = app.wait_for_job("scan-songs").await;
+    assert_eq!(report["directory"], names[0]);
+    assert_eq!(titles().await, ["First", "First Flip", "Second"]);
+
+    app.post(&format!("/api/directories/{}/scan", names[1]), json!({}))
+        .await;
+    app.wait_for_job("scan-songs").await;
+    assert_eq!(
+        titles().await,
+        ["First", "First Flip", "Second", "Second Flip"]
+    );
+
+    let (status, _) = app
+        .request(Method::POST, "/api/directories/missing/scan", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_directories_report_low_space() {
+    let app = TestApp::new().await;
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let low_space = || async { app.get("/api/directories/").await[0]["lowSpace"].clone() };
+
+    // No disk is ever completely free, nor below a disabled threshold.
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "storage": { "low_space_mb": 0, "low_space_percent": 100 } })),
+    )
+    .await;
+    assert_eq!(low_space().await, true);
+
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "storage": { "low_space_mb": 0, "low_space_percent": 0 } })),
+    )
+    .await;
+    assert_eq!(low_space().await, false);
+}
+
+#[tokio::test]
+async fn test_preview_template() {
+    let app = TestApp::new().await;
+
+    let preview = app
+        .post(
+            "/api/organize/preview-template",
+            json!({
+                "template": "{{artist}}/{{title}}",
+                "metadata": { "title": "Goose", "artist": "AC/DC" },
+                "fileName": "goose.flac",
+            }),
+        )
+        .await;
+    assert!(preview["error"].is_null());
+    assert_eq!(preview["song"]["path"], "ACDC/Goose.flac");
+    assert_eq!(preview["samples"].as_array().unwrap().len(), 3);
+    assert!(
+        preview["samples"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|sample| sample["path"].is_string())
+    );
+
+    let preview = app
+        .post(
+            "/api/organize/preview-template",
+            json!({ "template": "{{artist}}/\n{{title" }),
+        )
+        .await;
+    assert!(preview["error"]["message"].is_string());
+    assert!(preview["error"]["line"].is_number());
+    assert!(preview["song"].is_null());
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/api/organize/preview-template",
+            Some(json!({ "template": "{{title}}", "songId": "missing" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_backfilling_audio_properties() {
+    let app = TestApp::new().await;
+
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "scan": { "read_audio_properties": false } })),
+    )
+    .await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    // The total is left out until every track had its properties read.
+    let album_uri = format!("/api/albums/{}", encode_segment("Fixture Album"));
+    let album = app.get(&album_uri).await;
+    assert_eq!(album["tracks"][0]["propertiesRead"], false);
+    assert_eq!(album["durationPending"], true);
+    assert!(album["durationMs"].is_null());
+
+    app.post("/api/jobs/backfill-audio-properties/queue", json!({}))
+        .await;
+    let report = app.wait_for_job("backfill-audio-properties").await;
+    assert_eq!(report["completedSuccessfully"], true);
+
+    let album = app.get(&album_uri).await;
+    assert_eq!(album["tracks"][0]["propertiesRead"], true);
+    assert_eq!(album["durationPending"], false);
+    assert!(
+        album["durationMs"]
+            .as_u64()
+            .is_some_and(|duration| duration > 0)
+    );
+}
+
+#[tokio::test]
+async fn test_deleted_files_are_trashed_until_purged() {
+    let app = TestApp::new().await;
+    let library = app.library();
+
+    std::fs::create_dir(library.join("Album")).unwrap();
+    for (name, track) in [("one.flac", 1), ("two.flac", 2)] {
+        app.add_fixture(
+            "goose.flac",
+            &format!("Album/{name}"),
+            FixtureTags {
+                title: name,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": library }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let path = library.join("Album/one.flac").canonicalize().unwrap();
+    let songs = app.get("/api/songs/").await["items"].clone();
+    let id_of = |title: &str| {
+        songs
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|song| song["title"] == title)
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+
+    app.expect_ok(
+        Method::DELETE,
+        &format!("/api/songs/{}?delete_file=true", id_of("one.flac")),
+        None,
+    )
+    .await;
+    assert!(!path.exists());
+
+    let trash = app.get("/api/fs/trash").await;
+    let entry = trash
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["paths"][0]["original"] == path.to_string_lossy().as_ref())
+        .expect("The deleted file is in the trash")
+        .clone();
+    let id = entry["id"].as_str().unwrap();
+
+    let restored = app
+        .post(&format!("/api/fs/trash/{id}/restore"), json!({}))
+        .await;
+    assert_eq!(restored["id"], id);
+    assert!(path.is_file());
+
+    let (status, _) = app
+        .request(Method::POST, &format!("/api/fs/trash/{id}/restore"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Deleting for good skips the trash.
+    let two = library.join("Album/two.flac").canonicalize().unwrap();
+    app.expect_ok(
+        Method::DELETE,
+        &format!(
+            "/api/songs/{}?delete_file=true&permanent=true",
+            id_of("two.flac")
+        ),
+        None,
+    )
+    .await;
+    assert!(!two.exists());
+    assert!(
+        app.get("/api/fs/trash")
+            .await
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|entry| entry["paths"][0]["original"] != two.to_string_lossy().as_ref())
+    );
+}
+
+#[tokio::test]
+async fn test_long_polling_events() {
+    let app = TestApp::new().await;
+    let change_settings = |days: u32| {
+        app.expect_ok(
+            Method::PATCH,
+            "/api/settings",
+            Some(json!({ "events": { "retention_days": days } })),
+        )
+    };
+
+    // Without a `since_id` only events published from now on are returned.
+    assert_eq!(app.get("/api/events/poll?timeout=0").await, json!([]));
+
+    // A parked request returns as soon as something gets published.
+    let (polled, _) = tokio::join!(app.get("/api/events/poll?timeout=5"), async {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        change_settings(10).await
+    });
+    let event = &polled[0];
+    assert_eq!(event["event"], "app-event");
+    assert_eq!(event["data"]["kind"], "settingsChanged");
+
+    // Events missed in between requests are returned right away.
+    let since_id = event["id"].as_u64().unwrap();
+    change_settings(20).await;
+    let missed = app
+        .get(&format!("/api/events/poll?since_id={since_id}&timeout=5"))
+        .await;
+    assert_eq!(missed[0]["id"], since_id + 1);
+    assert_eq!(missed[0]["data"]["message"], polled[0]["data"]["message"]);
+
+    let last_id = missed.as_array().unwrap().last().unwrap()["id"].clone();
+    assert_eq!(
+        app.get(&format!("/api/events/poll?since_id={last_id}&timeout=0"))
+            .await,
+        json!([])
+    );
+}
+
+#[tokio::test]
+async fn test_songs_include_directory_and_album() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    let directory = app
+        .post(
+            "/api/directories/",
+            json!({ "path": app.library(), "displayName": "NAS" }),
+        )
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let song = app.get("/api/songs/").await["items"][0].clone();
+    assert!(song.get("included").is_none());
+
+    let page = app.get("/api/songs/?include=directory,album").await;
+    let expanded = &page["items"][0];
+    assert_eq!(expanded["id"], song["id"]);
+    assert_eq!(expanded["album"], "Fixture Album");
+    assert_eq!(expanded["included"]["directory"]["name"], directory["name"]);
+    assert_eq!(expanded["included"]["directory"]["displayName"], "NAS");
+    assert_eq!(
+        expanded["included"]["album"]["coverArtUrl"],
+        format!("/api/albums/{}/cover-art", encode_segment("Fixture Album"))
+    );
+
+    let id = song["id"].as_str().unwrap();
+    let single = app.get(&format!("/api/songs/{id}?include=directory")).await;
+    assert_eq!(single["included"]["directory"]["path"], directory["path"]);
+    assert!(single["included"].get("album").is_none());
+
+    let (status, _) = app
+        .request(Method::GET, "/api/songs/?include=playlists", None)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_downloading_songs_and_albums() {
+    let app = TestApp::new().await;
+    let library = app.library();
+
+    std::fs::create_dir_all(library.join("Album/Disc 1")).unwrap();
+    std::fs::create_dir_all(library.join("Album/Disc 2")).unwrap();
+    for (disc, track) in [(1, 1), (2, 2)] {
+        app.add_fixture(
+            "goose.flac",
+            &format!("Album/Disc {disc}/01.flac"),
+            FixtureTags {
+                title: "Goose",
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": library }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let song = app.get("/api/songs/").await["items"][0].clone();
+    let id = song["id"].as_str().unwrap();
+    let path = std::path::PathBuf::from(song["path"].as_str().unwrap());
+
+    let (status, bytes) = app
+        .request_bytes(
+            Method::GET,
+            &format!("/api/songs/{id}/download"),
+            Body::empty(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bytes, std::fs::read(&path).unwrap());
+
+    let (status, bytes) = app
+        .request_bytes(
+            Method::GET,
+            &format!("/api/albums/{}/download", encode_segment("Fixture Album")),
+            Body::empty(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut names = zip.file_names().map(String::from).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["Disc 1/01.flac", "Disc 2/01.flac"]);
+    assert_eq!(
+        zip.by_name("Disc 1/01.flac").unwrap().size(),
+        std::fs::metadata(library.join("Album/Disc 1/01.flac"))
+            .unwrap()
+            .len()
+    );
+
+    // A file that's gone can't be downloaded, and the song is marked missing.
+    std::fs::remove_file(&path).unwrap();
+    let (status, _) = app
+        .request(Method::GET, &format!("/api/songs/{id}/download"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(app.get(&format!("/api/songs/{id}")).await["missingSince"].is_string());
+}
+
+#[tokio::test]
+async fn test_schema_version_mismatch_conflicts() {
+    let app = TestApp::new().await;
+
+    let version = app.get("/api/info").await["schemaVersion"]
+        .as_u64()
+        .expect("Info should include the schema version");
+
+    let (status, _) = app
+        .request_with_headers(
+            Method::GET,
+            "/api/songs",
+            &[("x-schema-version", &version.to_string())],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .request_with_headers(
+            Method::GET,
+            "/api/songs",
+            &[("x-schema-version", &(version + 1).to_string())],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = app
+        .request_with_headers(
+            Method::GET,
+            "/api/songs",
+            &[("x-schema-version", "latest")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Clients that don't send a version aren't checked.
+    let (status, _) = app.request(Method::GET, "/api/songs", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_external_cover_art_files() {
+    let app = TestApp::new().await;
+    let library = app.library();
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Sidecar Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": library }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let id = app.get("/api/songs/").await["items"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let album = format!("/api/albums/{}/cover-art", encode_segment("Sidecar Album"));
+
+    let (status, _) = app
+        .request_bytes(Method::GET, &format!("{album}/front.png"), Body::empty())
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .request(Method::POST, &format!("{album}/extract"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .request(Method::GET, &format!("{album}/palette"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    std::fs::copy("data/cover.png", library.join("Folder.PNG")).unwrap();
+
+    let palette = app.get(&format!("{album}/palette")).await;
+    let colors = palette["colors"].as_array().unwrap();
+    assert!((1..=6).contains(&colors.len()));
+    assert!(
+        colors
+            .iter()
+            .all(|color| color.as_str().unwrap().len() == 7)
+    );
+    assert!(["#000000", "#ffffff"].contains(&palette["foreground"].as_str().unwrap()));
+
+    for uri in [
+        format!("/api/songs/{id}/cover-art/front.png"),
+        format!("{album}/front.png"),
+    ] {
+        let (status, _) = app.request_bytes(Method::GET, &uri, Body::empty()).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+    }
+    let (status, _) = app
+        .request_bytes(
+            Method::GET,
+            &format!("/api/songs/{id}/cover-art/back.png"),
+            Body::empty(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let embedded = app.post(&format!("{album}/embed"), json!(null)).await;
+    assert_eq!(embedded["songIds"], json!([id]));
+    assert_eq!(
+        app.get(&format!("/api/songs/{id}/cover-art")).await[0]["cover_type"],
+        "Front"
+    );
+
+    std::fs::remove_file(library.join("Folder.PNG")).unwrap();
+    let extracted = app.post(&format!("{album}/extract"), json!(null)).await;
+    let cover = library.join("cover.jpg");
+    assert_eq!(extracted["paths"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        image::guess_format(&std::fs::read(&cover).unwrap()).unwrap(),
+        image::ImageFormat::Jpeg
+    );
+
+    let (status, _) = app
+        .request(Method::POST, &format!("{album}/extract"), None)
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    app.post(&format!("{album}/extract?overwrite=true"), json!(null))
+        .await;
+}
+
+#[tokio::test]
+async fn test_maintenance_mode() {
+    let mut app = TestApp::new().await;
+    assert_eq!(edit_unknown_song(&app).await, StatusCode::NOT_FOUND);
+
+    let started = app
+        .post(
+            "/api/admin/maintenance",
+            json!({ "enabled": true, "reason": "Backup" }),
+        )
+        .await;
+    assert_eq!(started["reason"], "Backup");
+
+    // Starting it again keeps the maintenance that's already going.
+    let again = app
+        .post("/api/admin/maintenance", json!({ "enabled": true }))
+        .await;
+    assert_eq!(again, started);
+
+    let (status, message) = app
+        .request_bytes(Method::PUT, "/api/songs/unknown", Body::empty())
+        .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(message, "Server is in maintenance: Backup");
+    app.get("/api/songs").await;
+
+    app.restart();
+    assert_eq!(app.get("/api/info").await["maintenance"], started);
+    assert_eq!(
+        edit_unknown_song(&app).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    let ended = app
+        .post("/api/admin/maintenance", json!({ "enabled": false }))
+        .await;
+    assert!(ended.is_null());
+    assert!(app.get("/api/info").await["maintenance"].is_null());
+
+    assert_eq!(edit_unknown_song(&app).await, StatusCode::NOT_FOUND);
+}
+
+async fn edit_unknown_song(app: &TestApp) -> StatusCode {
+    app.request(Method::PUT, "/api/songs/unknown", Some(json!({})))
+        .await
+        .0
+}
+
+#[tokio::test]
+async fn test_play_counts() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let song = app.get("/api/songs/").await["items"][0].clone();
+    let uri = format!("/api/songs/{}/played", song["id"].as_str().unwrap());
+    assert_eq!(song["playCount"], 0);
+
+    let played = app.expect_ok(Method::POST, &uri, None).await;
+    assert_eq!(played["playCount"], 1);
+    assert!(played["lastPlayedAt"].is_string());
+
+    let skipped = app.post(&uri, json!({ "positionMs": 0 })).await;
+    assert_eq!(skipped["playCount"], 1);
+    assert_eq!(skipped["skipCount"], 1);
+
+    // Reports arriving together are all counted.
+    futures::future::join_all((0..5).map(|_| app.expect_ok(Method::POST, &uri, None))).await;
+    let song = app
+        .get(&format!("/api/songs/{}", song["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(song["playCount"], 6);
+
+    let page = app
+        .get("/api/songs/?sortBy=playCount&order=desc&minPlayCount=6")
+        .await;
+    assert_eq!(page["total"], 1);
+    let page = app.get("/api/songs/?minPlayCount=7").await;
+    assert_eq!(page["total"], 0);
+
+    let home = app.get("/api/home").await;
+    assert_eq!(home["mostPlayed"][0]["id"], song["id"]);
+
+    let stats = app.get("/api/library/stats").await;
+    assert_eq!(stats["totalPlays"], 6);
+    assert_eq!(stats["totalSkips"], 1);
+    assert_eq!(stats["topArtists"][0]["name"], "Fixture Artist");
+    assert_eq!(stats["topAlbums"][0]["title"], "Fixture Album");
+    assert_eq!(stats["topAlbums"][0]["playCount"], 6);
+
+    let stats = app
+        .get(&format!(
+            "/api/library/stats?from={}",
+            encode_segment("2999-01-01T00:00:00Z")
+        ))
+        .await;
+    assert_eq!(stats["totalPlays"], 0);
+    assert_eq!(stats["topArtists"], json!([]));
+
+    let (status, _) = app
+        .request(Method::POST, "/api/songs/unknown/played", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_song_playback_preferences() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let song = app.get("/api/songs/").await["items"][0].clone();
+    let uri = format!("/api/songs/{}", song["id"].as_str().unwrap());
+    assert_eq!(song["excludeFromShuffle"], false);
+
+    let song = app
+        .expect_ok(
+            Method::PATCH,
+            &uri,
+            Some(json!({ "excludeFromShuffle": true, "startOffsetMs": 250 })),
+        )
+        .await;
+    assert_eq!(song["excludeFromShuffle"], true);
+    assert_eq!(song["startOffsetMs"], 250);
+    assert_eq!(song["endOffsetMs"], serde_json::Value::Null);
+
+    let page = app.get("/api/songs/?shuffleable=true").await;
+    assert_eq!(page["total"], 0);
+    let page = app.get("/api/songs/?shuffleable=false").await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(app.get("/api/home").await["random"], json!([]));
+
+    let (status, response) = app
+        .request(Method::PATCH, &uri, Some(json!({ "endOffsetMs": 100 })))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{response}");
+
+    let response = app.response(Method::GET, &format!("{uri}/stream")).await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["x-start-offset-ms"], "250");
+    assert!(response.headers().get("x-end-offset-ms").is_none());
+
+    // Transcoding needs ffmpeg, which isn't set up by default.
+    let (status, response) = app
+        .request(Method::GET, &format!("{uri}/stream?format=mp3"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED, "{response}");
+}
+
+#[tokio::test]
+async fn test_inbox_identify_and_apply() {
+    let app = TestApp::new().await;
+
+    let directory = app
+        .post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let inbox = app.library().with_file_name("inbox");
+    let folder = inbox.join("rip");
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::copy("data/goose.flac", folder.join("01.flac")).unwrap();
+
+    let (status, response) = app
+        .request(
+            Method::POST,
+            "/api/inbox/identify",
+            Some(json!({ "path": folder, "lookup": false })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{response}");
+
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "inbox": { "path": inbox } })),
+    )
+    .await;
+
+    let (status, response) = app
+        .request(
+            Method::POST,
+            "/api/inbox/identify",
+            Some(json!({ "path": app.library(), "lookup": false })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{response}");
+
+    // Without a lookup the folder is only read, so it can be tagged by hand.
+    let identification = app
+        .post(
+            "/api/inbox/identify",
+            json!({ "path": folder, "lookup": false }),
+        )
+        .await;
+    assert_eq!(identification["candidates"], json!([]));
+    let track = &identification["tracks"][0];
+    assert!(track["durationMs"].as_u64().is_some());
+
+    let release = app
+        .post(
+            "/api/inbox/apply",
+            json!({
+                "path": folder,
+                "tracks": [{
+                    "path": track["path"],
+                    "title": "Goose",
+                    "artist": "Inbox Artist",
+                    "album": "Inbox Album",
+                    "albumArtist": null,
+                    "discNumber": 1,
+                    "trackNumber": 1,
+                    "year": "2024",
+                }],
+                "organize": { "directoryId": directory["name"] },
+            }),
+        )
+        .await;
+    let moved = release["moved"][0].as_str().unwrap();
+    assert!(moved.starts_with(app.library().to_str().unwrap()));
+    assert!(!folder.join("01.flac").exists());
+
+    app.wait_for_job("scan-songs").await;
+    let page = app.get("/api/songs/").await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["title"], "Goose");
+    assert_eq!(page["items"][0]["album"], "Inbox Album");
+    assert_eq!(page["items"][0]["path"], moved);
+    assert_eq!(page["items"][0]["yearNum"], 2024);
+
+    let page = app.get("/api/songs/?yearMin=2020&yearMax=2029").await;
+    assert_eq!(page["total"], 1);
+    let page = app.get("/api/songs/?yearMax=2000").await;
+    assert_eq!(page["total"], 0);
+
+    // The same recording again is set aside instead of being imported.
+    let song_id = app.get("/api/songs/").await["items"][0]["id"].clone();
+    let again = inbox.join("again");
+    std::fs::create_dir_all(&again).unwrap();
+    std::fs::copy("data/goose.flac", again.join("01.flac")).unwrap();
+    let proposal = |path: &serde_json::Value, folder: &std::path::Path| {
+        json!({
+            "path": folder,
+            "tracks": [{
+                "path": path,
+                "title": "goose",
+                "artist": "Inbox Artist",
+                "album": "Inbox Album",
+                "albumArtist": null,
+                "discNumber": 1,
+                "trackNumber": 2,
+                "year": "2024",
+            }],
+            "organize": { "directoryId": directory["name"] },
+        })
+    };
+
+    let identification = app
+        .post(
+            "/api/inbox/identify",
+            json!({ "path": again, "lookup": false }),
+        )
+        .await;
+    let (status, response) = app
+        .request(
+            Method::POST,
+            "/api/inbox/apply",
+            Some(proposal(&identification["tracks"][0]["path"], &again)),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{response}");
+    assert_eq!(response["duplicates"][0]["duplicateOf"], song_id);
+    let set_aside = inbox.join("duplicates/again/01.flac");
+    assert!(set_aside.exists());
+    assert!(!again.join("01.flac").exists());
+    assert_eq!(app.get("/api/songs/duplicates").await, json!([]));
+
+    let set_aside_folder = inbox.join("duplicates/again");
+    let identification = app
+        .post(
+            "/api/inbox/identify",
+            json!({ "path": set_aside_folder, "lookup": false }),
+        )
+        .await;
+    app.post(
+        "/api/inbox/apply?allow_duplicate=true",
+        proposal(&identification["tracks"][0]["path"], &set_aside_folder),
+    )
+    .await;
+    app.wait_for_job("scan-songs").await;
+
+    let duplicates = app.get("/api/songs/duplicates").await;
+    assert_eq!(duplicates.as_array().unwrap().len(), 1);
+    assert_eq!(duplicates[0][0]["id"], song_id);
+    assert_eq!(duplicates[0].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_smart_playlists() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.add_fixture(
+        "flip.mp3",
+        "flip.mp3",
+        FixtureTags {
+            title: "Flip",
+            artist: "Other Artist",
+            album: "Fixture Album",
+            track: 2,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let (status, response) = app
+        .request(
+            Method::POST,
+            "/api/smart-playlists/",
+            Some(json!({
+                "name": "Broken",
+                "filter": { "type": "rule", "field": "playCount", "op": "contains", "value": 1 },
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{response}");
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/api/smart-playlists/",
+            Some(json!({
+                "name": "Broken",
+                "filter": { "type": "rule", "field": "path", "op": "isSet" },
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let playlist = app
+        .post(
+            "/api/smart-playlists/",
+            json!({
+                "name": "Recent and unplayed",
+                "filter": {
+                    "type": "all",
+                    "filters": [
+                        { "type": "rule", "field": "addedAt", "op": "inLastDays", "value": 30 },
+                        { "type": "rule", "field": "playCount", "op": "is", "value": 0 },
+                        { "type": "rule", "field": "artist", "op": "contains", "value": "fixture" },
+                    ],
+                },
+            }),
+        )
+        .await;
+    let uri = format!("/api/smart-playlists/{}", playlist["id"].as_str().unwrap());
+
+    let page = app.get(&format!("{uri}/songs")).await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["title"], "Goose");
+
+    // Evaluated on every request, so the playlist follows changes to the library.
+    let id = page["items"][0]["id"].as_str().unwrap();
+    app.post(&format!("/api/songs/{id}/played"), json!({}))
+        .await;
+    let page = app.get(&format!("{uri}/songs")).await;
+    assert_eq!(page["total"], 0);
+
+    app.expect_ok(
+        Method::PUT,
+        &uri,
+        Some(json!({
+            "name": "Not recently played",
+            "filter": { "type": "rule", "field": "lastPlayedAt", "op": "notInLastDays", "value": 1 },
+        })),
+    )
+    .await;
+    let page = app.get(&format!("{uri}/songs")).await;
+    assert_eq!(page["items"][0]["title"], "Flip");
+    assert_eq!(
+        app.get("/api/smart-playlists/").await[0]["name"],
+        "Not recently played"
+    );
+
+    app.expect_ok(Method::DELETE, &uri, None).await;
+    let (status, _) = app.request(Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_api_tokens() {
+    let app = TestApp::new().await;
+
+    // Without tokens the API stays open.
+    app.get("/api/songs/").await;
+    let login = app
+        .post("/api/auth/login", json!({ "token": "anything" }))
+        .await;
+    assert_eq!(login["name"], serde_json::Value::Null);
+
+    let update = app
+        .expect_ok(
+            Method::PATCH,
+            "/api/settings",
+            Some(json!({ "auth": { "tokens": { "phone": "phone-token" } } })),
+        )
+        .await;
+    assert!(update["settings"]["auth"].get("tokens").is_none());
+
+    let (status, _) = app.request(Method::GET, "/api/songs/", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = app
+        .request_with_headers(
+            Method::GET,
+            "/api/songs/",
+            &[("authorization", "Bearer wrong-token")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, response) = app
+        .request_with_headers(
+            Method::GET,
+            "/api/songs/",
+            &[("authorization", "Bearer phone-token")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/api/auth/login",
+            Some(json!({ "token": "wrong-token" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let login = app
+        .post("/api/auth/login", json!({ "token": "phone-token" }))
+        .await;
+    assert_eq!(login["name"], "phone");
+
+    // The event stream takes the token in the query, as an `EventSource` can't set headers.
+    let response = app.response(Method::GET, "/api/events").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app
+        .response(Method::GET, "/api/events?token=phone-token")
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The UI stays reachable so it can ask for a token.
+    let response = app.response(Method::GET, "/").await;
+    assert_ne!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+/// Waits until every song's path exists and passes `organized`, failing if it takes too long.
+async fn wait_for_song_paths(app: &TestApp, organized: impl Fn(&str) -> bool) -> Vec<String> {
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        loop {
+            let page = app.get("/api/songs/").await;
+            let paths = page["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|song| song["path"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+
+            if paths
+                .iter()
+                .all(|path| organized(path) && std::path::Path::new(path).exists())
+            {
+                return paths;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Songs weren't organized in time")
+}
+
+#[tokio::test]
+async fn test_organize_outlives_request() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let uri = format!("/api/albums/{}/organize", encode_segment("Fixture Album"));
+
+    // The files are moved after the response, which only acknowledges the operation.
+    let (status, started) = app.request(Method::POST, &uri, None).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{started}");
+    assert_eq!(started["album"], "Fixture Album");
+    assert!(started["operationId"].is_number());
+
+    let library = app.library();
+    let paths = wait_for_song_paths(&app, |path| path.contains("Fixture Album")).await;
+    assert_eq!(paths.len(), 2);
+
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "organize": { "templates": { "flat": "{{title}}" } } })),
+    )
+    .await;
+
+    // Disconnect once the first file was moved, the paths are still updated afterwards.
+    let flat = |path: &str| {
+        std::path::Path::new(path)
+            .parent()
+            .is_some_and(|parent| parent == library)
+    };
+    let request = app.request(
+        Method::POST,
+        &format!("{uri}?templateName=flat&wait=true"),
+        None,
+    );
+    tokio::select! {
+        _ = request => {}
+        _ = async {
+            while !library.join("Goose.flac").exists() && !library.join("Flip.mp3").exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        } => {}
+    }
+
+    let mut paths = wait_for_song_paths(&app, flat).await;
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            library.join("Flip.mp3").to_string_lossy(),
+            library.join("Goose.flac").to_string_lossy(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_subsonic_api() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let ping = app.get("/rest/ping.view?f=json").await;
+    assert_eq!(ping["subsonic-response"]["status"], "ok");
+    assert_eq!(ping["subsonic-response"]["version"], "1.16.1");
+
+    let (status, body) = app
+        .request_bytes(Method::GET, "/rest/ping", Body::empty())
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(String::from_utf8_lossy(&body).contains(r#"<subsonic-response "#));
+
+    let artists = app.get("/rest/getArtists?f=json").await;
+    let index = &artists["subsonic-response"]["artists"]["index"][0];
+    assert_eq!(index["name"], "F");
+    assert_eq!(index["artist"][0]["name"], "Fixture Artist");
+    assert_eq!(index["artist"][0]["albumCount"], 1);
+
+    let artist_id = index["artist"][0]["id"].as_str().unwrap();
+    let artist = app
+        .get(&format!("/rest/getArtist.view?f=json&id={artist_id}"))
+        .await;
+    assert_eq!(
+        artist["subsonic-response"]["artist"]["album"][0]["name"],
+        "Fixture Album"
+    );
+
+    let list = app
+        .get("/rest/getAlbumList2?f=json&type=alphabeticalByName")
+        .await;
+    let album = &list["subsonic-response"]["albumList2"]["album"][0];
+    assert_eq!(album["name"], "Fixture Album");
+    assert_eq!(album["songCount"], 2);
+    assert!(album.get("song").is_none());
+
+    let album_id = album["id"].as_str().unwrap();
+    let album = app
+        .get(&format!("/rest/getAlbum?f=json&id={album_id}"))
+        .await;
+    let songs = album["subsonic-response"]["album"]["song"]
+        .as_array()
+        .unwrap();
+    assert_eq!(
+        songs
+            .iter()
+            .map(|song| song["title"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        ["Goose", "Flip"]
+    );
+    assert_eq!(songs[0]["albumId"], album_id);
+    assert_eq!(songs[0]["track"], 1);
+    assert_eq!(songs[0]["suffix"], "flac");
+
+    let song_id = songs[1]["id"].as_str().unwrap();
+    assert!(song_id.starts_with("tr-"));
+    let song = app
+        .get(&format!("/rest/getSong.view?f=json&id={song_id}"))
+        .await;
+    assert_eq!(song["subsonic-response"]["song"]["title"], "Flip");
+
+    let search = app.get("/rest/search3?f=json&query=goo").await;
+    let result = &search["subsonic-response"]["searchResult3"];
+    assert_eq!(result["song"].as_array().unwrap().len(), 1);
+    assert_eq!(result["song"][0]["title"], "Goose");
+    assert!(result["album"].as_array().unwrap().is_empty());
+
+    // An empty query lists everything, that's how clients sync the library.
+    let search = app.get("/rest/search3?f=json&query=%22%22").await;
+    let result = &search["subsonic-response"]["searchResult3"];
+    assert_eq!(result["song"].as_array().unwrap().len(), 2);
+    assert_eq!(result["album"].as_array().unwrap().len(), 1);
+
+    let (status, audio) = app
+        .request_bytes(
+            Method::GET,
+            &format!("/rest/stream?id={song_id}"),
+            Body::empty(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!audio.is_empty());
+
+    app.get(&format!("/rest/scrobble?f=json&id={song_id}"))
+        .await;
+    let song = app
+        .get(&format!("/rest/getSong.view?f=json&id={song_id}"))
+        .await;
+    assert_eq!(song["subsonic-response"]["song"]["playCount"], 1);
+
+    // Failures are reported in the envelope.
+    let missing = app.get("/rest/getSong?f=json&id=tr-missing").await;
+    assert_eq!(missing["subsonic-response"]["status"], "failed");
+    assert_eq!(missing["subsonic-response"]["error"]["code"], 70);
+    let missing = app.get("/rest/getAlbum?f=json").await;
+    assert_eq!(missing["subsonic-response"]["error"]["code"], 10);
+
+    // Once tokens are configured clients log in with the name and the token.
+    app.expect_ok(
+        Method::PATCH,
+        "/api/settings",
+        Some(json!({ "auth": { "tokens": { "phone": "phone-token" } } })),
+    )
+    .await;
+
+    let ping = app.get("/rest/ping.view?f=json&u=phone&p=wrong").await;
+    assert_eq!(ping["subsonic-response"]["status"], "failed");
+    assert_eq!(ping["subsonic-response"]["error"]["code"], 40);
+
+    let ping = app
+        .get("/rest/ping.view?f=json&u=phone&p=phone-token")
+        .await;
+    assert_eq!(ping["subsonic-response"]["status"], "ok");
+}
+
+#[tokio::test]
+async fn test_scan_errors() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    let corrupt = app.library().join("corrupt.flac");
+    std::fs::write(&corrupt, b"fLaC, or so it claims").unwrap();
+
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let page = app.get("/api/songs/").await;
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+
+    let errors = app.get("/api/library/scan-errors").await;
+    let errors = errors.as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("corrupt.flac")
+    );
+    assert_eq!(errors[0]["category"], "unreadable");
+    assert_eq!(errors[0]["attempts"], 1);
+
+    // The file isn't read again until it's modified.
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    app.wait_for_job("scan-songs").await;
+    let errors = app.get("/api/library/scan-errors").await;
+    assert_eq!(errors[0]["attempts"], 1);
+
+    // Retrying forgets the failure, so the next scan reads the file again.
+    let id = errors[0]["id"].as_i64().unwrap();
+    app.post(&format!("/api/library/scan-errors/{id}/retry"), json!({}))
+        .await;
+    assert_eq!(app.get("/api/library/scan-errors").await, json!([]));
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/library/scan-errors/{id}/retry"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+    app.wait_for_job("scan-songs").await;
+    let errors = app.get("/api/library/scan-errors").await;
+    assert_eq!(errors[0]["attempts"], 1);
+    assert_ne!(errors[0]["id"].as_i64().unwrap(), id);
+}
+
+#[tokio::test]
+async fn test_metadata_history() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let page = app.get("/api/songs/").await;
+    let id = page["items"][0]["id"].as_str().unwrap();
+
+    app.put(
+        &format!("/api/songs/{id}"),
+        json!({
+            "title": "Renamed Goose",
+            "artist": "Fixture Artist",
+            "album": "Fixture Album",
+            "trackNumber": "1",
+        }),
+    )
+    .await;
+
+    let history = app
+        .post(&format!("/api/songs/{id}/metadata/history"), json!({}))
+        .await;
+    let history = history.as_object().unwrap();
+    assert_eq!(history.len(), 1);
+    let (timestamp, snapshot) = history.iter().next().unwrap();
+    assert_eq!(snapshot["title"], "Goose");
+
+    // Only the fields that changed since the snapshot are returned.
+    let diff = app
+        .get(&format!(
+            "/api/songs/{id}/metadata/history/{}/diff",
+            encode_segment(timestamp)
+        ))
+        .await;
+    let diff = diff.as_array().unwrap();
+    assert!(diff.contains(&json!({
+        "key": "title",
+        "snapshot": "Goose",
+        "current": "Renamed Goose",
+    })));
+    assert!(!diff.iter().any(|change| change["key"] == "artist"));
+
+    let (status, _) = app
+        .request(
+            Method::DELETE,
+            &format!("/api/songs/{id}/metadata/history"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/songs/{id}/metadata/history"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_preview_inferred_metadata() {
+    let app = TestApp::new().await;
+
+    app.add_fixture(
+        "goose.flac",
+        "1999 - goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let page = app.get("/api/songs/").await;
+    let id = page["items"][0]["id"].as_str().unwrap();
+
+    // Only the year is missing, the title is already tagged.
+    let inferred = app
+        .get(&format!(
+            "/api/songs/{id}/infer?pattern={}",
+            encode_segment("{year} - {title}")
+        ))
+        .await;
+    assert_eq!(inferred["pattern"], "{year} - {title}");
+    assert_eq!(inferred["ambiguous"], false);
+    assert_eq!(inferred["fields"], json!({ "year": "1999" }));
+
+    let (status, _) = app
+        .request(
+            Method::GET,
+            &format!(
+                "/api/songs/{id}/infer?pattern={}",
+                encode_segment("{year}{title}")
+            ),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_directory_sizes() {
+    let app = TestApp::new().await;
+
+    let fixture = app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    let directory = app
+        .post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    // The size of a new directory is only computed in the background.
+    assert_eq!(directory["pathSize"], serde_json::Value::Null);
+    assert_eq!(directory["sizeComputedAt"], serde_json::Value::Null);
+
+    let directories = app.get("/api/directories/?refresh=true").await;
+    assert_eq!(
+        directories[0]["pathSize"],
+        std::fs::metadata(fixture).unwrap().len()
+    );
+    assert!(directories[0]["sizeComputedAt"].is_string());
+}
+
+#[tokio::test]
+async fn test_moving_directory() {
+    let app = TestApp::new().await;
+
+    let fixture = app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    let directory = app
+        .post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let moved = app.library().with_file_name("moved");
+    std::fs::create_dir(&moved).unwrap();
+    let uri = format!(
+        "/api/directories/{}?verify=true",
+        directory["name"].as_str().unwrap()
+    );
+
+    // Nothing was copied to the new path yet, so the songs would be lost.
+    let (status, _) = app
+        .request(Method::PUT, &uri, Some(json!({ "newPath": moved })))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    std::fs::copy(&fixture, moved.join("goose.flac")).unwrap();
+    let updated = app
+        .put(&uri, json!({ "newPath": moved, "displayName": "Moved" }))
+        .await;
+    assert_eq!(updated["path"], moved.to_string_lossy().as_ref());
+    assert_eq!(updated["displayName"], "Moved");
+
+    let song = app.get("/api/songs/").await["items"][0].clone();
+    assert_eq!(
+        song["path"],
+        moved.join("goose.flac").to_string_lossy().as_ref()
+    );
+}
+
+#[tokio::test]
+async fn test_library_integrity() {
+    let app = TestApp::new().await;
+
+    let fixture = app.add_fixture(
+        "goose.flac",
+        "goose.flac",
+        FixtureTags {
+            title: "Goose",
+            artist: "Fixture Artist",
+            album: "Fixture Album",
+            track: 1,
+        },
+    );
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    std::fs::remove_file(&fixture).unwrap();
+    app.post("/api/jobs/check-integrity/queue", json!({})).await;
+    app.wait_for_job("check-integrity").await;
+
+    let report = app.get("/api/library/integrity").await;
+    assert_eq!(report["orphanedSongs"], json!([]));
+    assert_eq!(report["missingFiles"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        report["missingFiles"][0]["path"],
+        fixture.to_string_lossy().as_ref()
+    );
+
+    // Missing files need a decision, so they're left in the report.
+    let repair = app.post("/api/library/integrity/repair", json!({})).await;
+    assert_eq!(repair["deletedSongs"], json!([]));
+    assert_eq!(repair["report"]["missingFiles"], report["missingFiles"]);
+}
+
+#[tokio::test]
+async fn test_search() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let results = app.get("/api/search?q=goo").await;
+    assert_eq!(results["songs"].as_array().unwrap().len(), 1);
+    assert_eq!(results["songs"][0]["title"], "Goose");
+    assert!(results["songs"][0]["score"].as_f64().unwrap() > 0.0);
+
+    let results = app.get("/api/search?q=fixture%20alb&limit=1").await;
+    assert_eq!(results["songs"].as_array().unwrap().len(), 1);
+    assert_eq!(results["albums"][0]["title"], "Fixture Album");
+    assert_eq!(results["albums"][0]["trackCount"], 2);
+    assert_eq!(results["artists"], json!([]));
+
+    let results = app.get("/api/search?q=FIXTURE%20art").await;
+    assert_eq!(results["artists"][0]["name"], "Fixture Artist");
+    assert_eq!(results["artists"][0]["trackCount"], 2);
+
+    let (status, response) = app.request(Method::GET, "/api/search?q=%20", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{response}");
+}
+
+#[tokio::test]
+async fn test_bulk_editing_songs() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let songs = app.get("/api/songs/?sortBy=title").await["items"].clone();
+    let song_ids = [&songs[1]["id"], &json!("missing"), &songs[0]["id"]];
+    let results = app
+        .put(
+            "/api/songs/metadata/bulk",
+            json!({ "songIds": song_ids, "changes": { "genre": "Rock" } }),
+        )
+        .await;
+
+    let results = results.as_array().unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| &result["songId"])
+            .collect::<Vec<_>>(),
+        song_ids
+    );
+    assert_eq!(results[0]["status"], "updated");
+    assert_eq!(results[1]["status"], "songNotFound");
+    assert_eq!(results[2]["status"], "updated");
+}
+
+#[tokio::test]
+async fn test_merging_genres() {
+    let app = TestApp::new().await;
+
+    for (sample, title, track) in [("goose.flac", "Goose", 1), ("flip.mp3", "Flip", 2)] {
+        app.add_fixture(
+            sample,
+            sample,
+            FixtureTags {
+                title,
+                artist: "Fixture Artist",
+                album: "Fixture Album",
+                track,
+            },
+        );
+    }
+    app.post("/api/directories/", json!({ "path": app.library() }))
+        .await;
+    app.wait_for_job("scan-songs").await;
+
+    let songs = app.get("/api/songs/?sortBy=title").await["items"].clone();
+    for (song, genre) in [(&songs[0], "hip hop; Rock"), (&songs[1], "Hip-Hop")] {
+        app.put(
+            "/api/songs/metadata/bulk",
+            json!({ "songIds": [song["id"]], "changes": { "genre": genre } }),
+        )
+        .await;
+    }
+
+    let genres = app.get("/api/genres/").await;
+    assert_eq!(genres.as_array().unwrap().len(), 3);
+    assert_eq!(genres[0]["name"], "hip hop");
+
+    let merge = json!({ "sources": ["Hip-Hop", "HIP HOP"], "target": "Hip Hop" });
+    let mut dry_run = merge.clone();
+    dry_run["dryRun"] = json!(true);
+    let result = app.post("/api/genres/merge", dry_run).await;
+    assert_eq!(result["songCount"], 2);
+    assert_eq!(result["job"], serde_json::Value::Null);
+    assert_eq!(app.get("/api/genres/").await, genres);
+
+    let mut write_tags = merge.clone();
+    write_tags["writeTags"] = json!(true);
+    let result = app.post("/api/genres/merge", write_tags).await;
+    assert_eq!(result["songCount"], 2);
+    assert!(result["job"].is_string());
+    app.wait_for_job("merge-genres").await;
+
+    let genres = app.get("/api/genres/").await;
+    assert_eq!(genres[0]["name"], "Hip Hop");
+    assert_eq!(genres[0]["songCount"], 2);
+    assert_eq!(genres[1]["name"], "Rock");
+
+    let (status, response) = app
+        .request(
+            Method::POST,
+            "/api/genres/merge",
+            Some(json!({ "sources": ["Rock"], "target": " " })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{response}");
+}
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Returns the next text message of the socket, read as JSON.
+async fn next_json(socket: &mut WebSocket) -> serde_json::Value {
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    loop {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(10), socket.next())
+            .await
+            .expect("No message in time")
+            .expect("WebSocket closed")
+            .expect("Failed to read message");
+
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_events() {
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let app = TestApp::new().await;
+    let address = app.serve().await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{address}/api/ws"))
+        .await
+        .unwrap();
+
+    socket
+        .send(Message::text(
+            json!({ "subscribe": { "jobs": ["scan-songs"] } }).to_string(),
+        ))
+        .await
+        .unwrap();
+
+    // Messages are handled in order, so the subscription is in place once this is answered.
+    socket.send(Message::text("hello")).await.unwrap();
+    assert!(next_json(&mut socket).await["error"].is_string());
+
+    app.post("/api/jobs/album-hygiene/queue", json!({})).await;
+    app.wait_for_job("album-hygiene").await;
+    app.post("/api/jobs/scan-songs/queue", json!({})).await;
+
+    loop {
+        let event = next_json(&mut socket).await;
+        assert_eq!(event["event"], "job-event");
+
+        let data = &event["data"];
+        let job_id = data
+            .get("jobId")
+            .or_else(|| data.get("state")?.get("jobId"));
+        assert_ne!(job_id, Some(&json!("album-hygiene")));
+
+        if data["kind"] == "completed" {
+            assert_eq!(data["jobId"], "scan-songs");
+            break;
+        }
+    }
+}
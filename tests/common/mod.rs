@@ -0,0 +1,301 @@
+//! Harness for exercising the HTTP API end-to-end.
+//!
+//! Every [`TestApp`] runs the full router against its own migrated database and library
+//! directory, both stored in a temporary directory that's removed once the app is dropped. The
+//! data, cache and config directories of the app are shared by every app of the test binary, and
+//! point to another temporary directory so tests never touch the ones of the machine.
+
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes, to_bytes},
+    extract::ConnectInfo,
+    http::{Method, Request, Response, StatusCode, header},
+};
+use lofty::{
+    config::WriteOptions,
+    file::{AudioFile, TaggedFileExt},
+    tag::{Accessor, Tag},
+};
+use serde_json::Value;
+use sqlx::{
+    SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+use muusik::{APP_DIRECTORIES, AppState, routes, run_migrations};
+
+/// The largest response body the harness reads.
+const BODY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// How long to wait for queued jobs before failing the test.
+const JOB_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Holds the data, cache and config directories of the app, see [`init_app_directories`].
+static APP_ROOT: OnceLock<TempDir> = OnceLock::new();
+
+/// Points the app directories to [`APP_ROOT`] and creates them, before [`APP_DIRECTORIES`] is
+/// first read since it only reads the environment once.
+fn init_app_directories() {
+    APP_ROOT.get_or_init(|| {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        // SAFETY: Tests start by creating a `TestApp`, which waits for this to finish, so no other
+        // thread reads the environment meanwhile.
+        unsafe {
+            std::env::set_var("MUUSIK_DATA_DIR", root.path().join("data"));
+            std::env::set_var("MUUSIK_CACHE_DIR", root.path().join("cache"));
+            std::env::set_var("MUUSIK_CONFIG_DIR", root.path().join("config"));
+        }
+
+        for dir in APP_DIRECTORIES.iter() {
+            std::fs::create_dir_all(dir).expect("Failed to create app directory");
+        }
+
+        root
+    });
+}
+
+/// Tags written to a fixture, so tests don't depend on the tags the sample files ship with.
+pub struct FixtureTags<'a> {
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub album: &'a str,
+    pub track: u32,
+}
+
+pub struct TestApp {
+    router: Router,
+    pool: SqlitePool,
+    /// Holds the database and the library, see [`TestApp::library`].
+    root: TempDir,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        init_app_directories();
+
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::create_dir(root.path().join("library")).expect("Failed to create library");
+
+        // Every connection to an in-memory database opens a database of its own, while the app
+        // reads and writes through a pool, so the database is a file that goes away with `root`.
+        let pool = SqlitePoolOptions::new()
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .filename(root.path().join("test.db"))
+                    .create_if_missing(true),
+            )
+            .await
+            .expect("Failed to connect to database");
+
+        run_migrations(&pool, true, None)
+            .await
+            .expect("Failed to run migrations");
+
+        let router = routes(AppState::new(pool.clone(), Default::default()));
+
+        Self { router, pool, root }
+    }
+
+    /// Starts the app over on the same database and library, like a restart of the server.
+    pub fn restart(&mut self) {
+        self.router = routes(AppState::new(self.pool.clone(), Default::default()));
+    }
+
+    /// The directory tests add their fixtures to.
+    pub fn library(&self) -> PathBuf {
+        self.root.path().join("library")
+    }
+
+    /// Copies a sample file from `data/` into the library with the given tags.
+    pub fn add_fixture(&self, sample: &str, name: &str, tags: FixtureTags) -> PathBuf {
+        let path = self.library().join(name);
+        std::fs::copy(Path::new("data").join(sample), &path).expect("Failed to copy fixture");
+
+        let mut file = lofty::read_from_path(&path).expect("Failed to read fixture");
+        let mut tag = Tag::new(file.primary_tag_type());
+        tag.set_title(tags.title.to_string());
+        tag.set_artist(tags.artist.to_string());
+        tag.set_album(tags.album.to_string());
+        tag.set_track(tags.track);
+
+        file.clear();
+        file.insert_tag(tag);
+        file.save_to_path(&path, WriteOptions::default())
+            .expect("Failed to write fixture tags");
+
+        path
+    }
+
+    /// Sends a request the way a client on the same machine would, returning the status and the
+    /// body parsed as JSON, or `Value::Null` if it isn't JSON.
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        self.request_with_headers(method, uri, &[], body).await
+    }
+
+    /// Like [`Self::request`], sending `headers` along with the request.
+    pub async fn request_with_headers(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let (status, bytes) = match body {
+            Some(body) => {
+                let mut headers = headers.to_vec();
+                headers.push((header::CONTENT_TYPE.as_str(), "application/json"));
+                self.send(method, uri, &headers, body.to_string()).await
+            }
+            None => self.send(method, uri, headers, Body::empty()).await,
+        };
+
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    /// Sends a request with a body that isn't JSON, returning the status and the raw body.
+    pub async fn request_bytes(
+        &self,
+        method: Method,
+        uri: &str,
+        body: impl Into<Body>,
+    ) -> (StatusCode, Bytes) {
+        self.send(method, uri, &[], body).await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: impl Into<Body>,
+    ) -> (StatusCode, Bytes) {
+        let response = self.respond(method, uri, headers, body).await;
+
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), BODY_LIMIT)
+            .await
+            .expect("Failed to read body");
+
+        (status, bytes)
+    }
+
+    /// Serves the app on a local port, for clients that need a real connection like WebSockets.
+    pub async fn serve(&self) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind test server");
+        let address = listener
+            .local_addr()
+            .expect("Failed to get the address of the test server");
+
+        let router = self.router.clone();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .expect("Failed to serve test server");
+        });
+
+        address
+    }
+
+    /// Sends a request without a body, returning the whole response so its headers can be checked.
+    pub async fn response(&self, method: Method, uri: &str) -> Response<Body> {
+        self.respond(method, uri, &[], Body::empty()).await
+    }
+
+    async fn respond(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: impl Into<Body>,
+    ) -> Response<Body> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        self.router
+            .clone()
+            .oneshot(request.body(body.into()).expect("Failed to build request"))
+            .await
+            .expect("Router can't fail")
+    }
+
+    /// Sends a request, failing the test unless it succeeds.
+    pub async fn expect_ok(&self, method: Method, uri: &str, body: Option<Value>) -> Value {
+        let (status, response) = self.request(method.clone(), uri, body).await;
+        assert!(
+            status.is_success(),
+            "{method} {uri} returned {status}: {response}"
+        );
+
+        response
+    }
+
+    pub async fn get(&self, uri: &str) -> Value {
+        self.expect_ok(Method::GET, uri, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> Value {
+        self.expect_ok(Method::POST, uri, Some(body)).await
+    }
+
+    pub async fn put(&self, uri: &str, body: Value) -> Value {
+        self.expect_ok(Method::PUT, uri, Some(body)).await
+    }
+
+    /// Waits until nothing is queued or running and the job has completed at least once.
+    pub async fn wait_for_job(&self, job_id: &str) -> Value {
+        tokio::time::timeout(JOB_TIMEOUT, async {
+            loop {
+                let states = self.get("/api/jobs/state").await;
+                let report = self.get("/api/jobs/reports").await[job_id].clone();
+
+                if states.as_object().is_some_and(|states| states.is_empty())
+                    && !report["completedAt"].is_null()
+                {
+                    return report;
+                }
+
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("\"{job_id}\" didn't complete in time"))
+    }
+}
+
+/// Encodes a value for use as a single path segment.
+pub fn encode_segment(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}